@@ -0,0 +1,125 @@
+//! Selects the memory layout (`memory/<chip>.x`) of the enabled chip feature
+//! and embeds the build info (see `src/build_info.rs`). Checks the public key
+//! of signed updates (see `src/crypto/signed.rs`), links the CryptoCell
+//! library and generates its bindings (see `src/crypto/hw.rs`).
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Generate the bindings of the CryptoCell library from its headers into
+/// `cc310.rs` in `out`.
+#[cfg(feature = "cryptocell")]
+fn cryptocell_bindings(out: &std::path::Path) {
+    let include = env::var("NRF_CC310_INCLUDE_DIR").expect(
+        "the cryptocell feature needs NRF_CC310_INCLUDE_DIR, the directory of the \
+         headers of the library (nRF5 SDK, external/nrf_cc310/include)",
+    );
+    bindgen::Builder::default()
+        .header("src/crypto/cc310.h")
+        .clang_args([
+            format!("-I{include}"),
+            "--target=thumbv7em-none-eabihf".to_string(),
+            "-ffreestanding".to_string(),
+        ])
+        .use_core()
+        .ctypes_prefix("core::ffi")
+        .default_enum_style(bindgen::EnumVariation::Consts)
+        .prepend_enum_name(false)
+        .allowlist_function(
+            "SaSi_LibInit|CRYS_HASH|SaSi_Aes(Init|SetKey|Finish|Free)|CRYS_ECPKI_GetEcDomain\
+             |_DX_ECPKI_BuildPublKey|CRYS_ECPKI_BuildPrivKey|CRYS_ECDH_SVDP_DH|CRYS_ECEDW_Verify",
+        )
+        .allowlist_type(
+            "SaSiAesUserKeyData_t|CRYS_ECPKI_BUILD_TempData_t|CRYS_ECDH_TempData_t\
+             |CRYS_ECEDW_TempBuff_t",
+        )
+        .generate()
+        .expect("couldn't generate the CryptoCell bindings")
+        .write_to_file(out.join("cc310.rs"))
+        .unwrap();
+    println!("cargo:rerun-if-changed=src/crypto/cc310.h");
+}
+
+/// Output of a git command, `None` if git or the repository isn't available.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn main() {
+    let chip = if env::var_os("CARGO_FEATURE_CHIP_NRF52832").is_some() {
+        "nrf52832"
+    } else if env::var_os("CARGO_FEATURE_CHIP_NRF52833").is_some() {
+        "nrf52833"
+    } else {
+        "nrf52840"
+    };
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::copy(format!("memory/{chip}.x"), out.join("memory.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=memory");
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let hash = match git(&["rev-parse", "--short=8", "HEAD"]) {
+        Some(hash) if git(&["status", "--porcelain"]).is_some_and(|s| !s.is_empty()) => {
+            format!("{hash}-dirty")
+        }
+        Some(hash) => hash,
+        None => "unknown".to_string(),
+    };
+    // Reproducible builds set the time of the commit instead.
+    let timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        });
+    println!("cargo:rustc-env=GIT_HASH={hash}");
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={timestamp}");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    // A malformed key would leave the firmware rejecting every update.
+    if let Ok(key) = env::var("CONFIG_SIGNING_KEY") {
+        assert!(
+            key.len() == 64 && key.bytes().all(|b| b.is_ascii_hexdigit()),
+            "CONFIG_SIGNING_KEY has to be an Ed25519 public key of 64 hex digits"
+        );
+    }
+    println!("cargo:rerun-if-env-changed=CONFIG_SIGNING_KEY");
+
+    // The keys of the mesh network, shared by all its nodes.
+    for name in ["CONFIG_MESH_NET_KEY", "CONFIG_MESH_APP_KEY"] {
+        if let Ok(key) = env::var(name) {
+            assert!(
+                key.len() == 32 && key.bytes().all(|b| b.is_ascii_hexdigit()),
+                "{name} has to be an AES-128 key of 32 hex digits"
+            );
+        }
+        println!("cargo:rerun-if-env-changed={name}");
+    }
+
+    if env::var_os("CARGO_FEATURE_CRYPTOCELL").is_some() {
+        let dir = env::var("NRF_CC310_LIB_DIR").expect(
+            "the cryptocell feature needs NRF_CC310_LIB_DIR, the directory of \
+             libnrf_cc310_0.9.13.a (nRF5 SDK, external/nrf_cc310/lib/cortex-m4/hard-float/no-interrupts)",
+        );
+        println!("cargo:rustc-link-search={dir}");
+        println!("cargo:rustc-link-lib=static=nrf_cc310_0.9.13");
+    }
+    #[cfg(feature = "cryptocell")]
+    cryptocell_bindings(&out);
+    println!("cargo:rerun-if-env-changed=NRF_CC310_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=NRF_CC310_INCLUDE_DIR");
+}
@@ -0,0 +1,224 @@
+//! Advertising payloads that always fit.
+//!
+//! Encoding AD structures straight into the 31 bytes of legacy advertising
+//! data fails as soon as a long device name meets manufacturer data. [`plan`]
+//! takes the structures in priority order instead: each one is placed while
+//! it fits and dropped otherwise, a [`Ad::Name`] only needs its minimum
+//! length and is shortened to the space left. The [`Plan`] tells what was
+//! dropped or shortened.
+
+use heapless::Vec;
+
+/// Length of legacy advertising or scan response data.
+pub const ADV_LEN: usize = 31;
+
+/// Most AD structures in one payload.
+pub const MAX_ADS: usize = 8;
+
+/// AD types.
+pub const AD_FLAGS: u8 = 0x01;
+pub const AD_SERVICE_UUIDS_16: u8 = 0x03;
+pub const AD_SHORTENED_NAME: u8 = 0x08;
+pub const AD_COMPLETE_NAME: u8 = 0x09;
+pub const AD_SERVICE_DATA_16: u8 = 0x16;
+pub const AD_MANUFACTURER_DATA: u8 = 0xFF;
+
+/// An AD structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ad<'a> {
+    Flags(u8),
+    /// Complete list of 16 bit service UUIDs, little endian.
+    ServiceUuids16(&'a [[u8; 2]]),
+    ServiceData16 {
+        uuid: [u8; 2],
+        data: &'a [u8],
+    },
+    Manufacturer {
+        company_identifier: u16,
+        payload: &'a [u8],
+    },
+    /// The local name, cut at a character boundary to what fits, but to no
+    /// less than `min_len` bytes. A cut name is sent as the shortened name.
+    Name {
+        name: &'a str,
+        min_len: usize,
+    },
+}
+
+impl Ad<'_> {
+    /// AD type, of the complete name for a name.
+    pub fn ad_type(&self) -> u8 {
+        match self {
+            Self::Flags(_) => AD_FLAGS,
+            Self::ServiceUuids16(_) => AD_SERVICE_UUIDS_16,
+            Self::ServiceData16 { .. } => AD_SERVICE_DATA_16,
+            Self::Manufacturer { .. } => AD_MANUFACTURER_DATA,
+            Self::Name { .. } => AD_COMPLETE_NAME,
+        }
+    }
+
+    /// Length of the data, of the whole name for a name.
+    fn data_len(&self) -> usize {
+        match self {
+            Self::Flags(_) => 1,
+            Self::ServiceUuids16(uuids) => 2 * uuids.len(),
+            Self::ServiceData16 { data, .. } => 2 + data.len(),
+            Self::Manufacturer { payload, .. } => 2 + payload.len(),
+            Self::Name { name, .. } => name.len(),
+        }
+    }
+
+    /// Length of the data the structure can't do without.
+    fn min_data_len(&self) -> usize {
+        match self {
+            Self::Name { name, min_len } => cut(name, *min_len).len(),
+            _ => self.data_len(),
+        }
+    }
+}
+
+/// The longest prefix of `name` up to `len` bytes that ends at a character
+/// boundary.
+fn cut(name: &str, len: usize) -> &str {
+    let mut len = len.min(name.len());
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    &name[..len]
+}
+
+/// Planned payload.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Plan {
+    pub payload: Vec<u8, ADV_LEN>,
+    /// Types of the structures that didn't fit, in priority order.
+    pub dropped: Vec<u8, MAX_ADS>,
+    /// Whether the name was shortened.
+    pub shortened: bool,
+}
+
+impl Plan {
+    /// Whether everything fit as it was.
+    pub fn is_complete(&self) -> bool {
+        self.dropped.is_empty() && !self.shortened
+    }
+}
+
+/// Plan the payload of `ads`, the most important first. Structures beyond
+/// [`MAX_ADS`] are dropped.
+pub fn plan(ads: &[Ad]) -> Plan {
+    let mut plan = Plan::default();
+    let mut placed = [false; MAX_ADS];
+    let mut left = ADV_LEN;
+    for (i, ad) in ads.iter().enumerate() {
+        let need = 2 + ad.min_data_len();
+        if i < MAX_ADS && need <= left {
+            placed[i] = true;
+            left -= need;
+        } else {
+            let _ = plan.dropped.push(ad.ad_type());
+        }
+    }
+    for (ad, _) in ads.iter().zip(placed).filter(|(_, placed)| *placed) {
+        let payload = &mut plan.payload;
+        match *ad {
+            Ad::Flags(flags) => put(payload, AD_FLAGS, &[&[flags]]),
+            Ad::ServiceUuids16(uuids) => put(payload, AD_SERVICE_UUIDS_16, &[uuids.as_flattened()]),
+            Ad::ServiceData16 { uuid, data } => put(payload, AD_SERVICE_DATA_16, &[&uuid, data]),
+            Ad::Manufacturer {
+                company_identifier,
+                payload: data,
+            } => put(
+                payload,
+                AD_MANUFACTURER_DATA,
+                &[&company_identifier.to_le_bytes(), data],
+            ),
+            Ad::Name { name, .. } => {
+                // Takes the space left after all placed structures got their
+                // minimum.
+                let shortened = cut(name, ad.min_data_len() + left);
+                left -= shortened.len() - ad.min_data_len();
+                let ad_type = if shortened.len() < name.len() {
+                    plan.shortened = true;
+                    AD_SHORTENED_NAME
+                } else {
+                    AD_COMPLETE_NAME
+                };
+                put(payload, ad_type, &[shortened.as_bytes()]);
+            }
+        }
+    }
+    plan
+}
+
+/// Append an AD structure of `parts`, which was planned to fit.
+fn put(payload: &mut Vec<u8, ADV_LEN>, ad_type: u8, parts: &[&[u8]]) {
+    let len: usize = parts.iter().map(|part| part.len()).sum();
+    let _ = payload.extend_from_slice(&[len as u8 + 1, ad_type]);
+    for part in parts {
+        let _ = payload.extend_from_slice(part);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans() {
+        let flags = Ad::Flags(0x06);
+        let uuids = Ad::ServiceUuids16(&[[0x0f, 0x18]]);
+        let plan = plan(&[
+            flags,
+            uuids,
+            Ad::Name {
+                name: "Tracker",
+                min_len: 4,
+            },
+        ]);
+        assert!(plan.is_complete());
+        assert_eq!(
+            plan.payload.as_slice(),
+            b"\x02\x01\x06\x03\x03\x0f\x18\x08\x09Tracker"
+        );
+
+        // 3 + 4 + 20 bytes leave 2 bytes of the name.
+        let payload = [0; 16];
+        let manufacturer = Ad::Manufacturer {
+            company_identifier: 0xFFFF,
+            payload: &payload,
+        };
+        let name = Ad::Name {
+            name: "Trackeräbc",
+            min_len: 2,
+        };
+        let plan = super::plan(&[flags, uuids, manufacturer, name]);
+        assert!(plan.dropped.is_empty() && plan.shortened);
+        assert_eq!(plan.payload.len(), ADV_LEN);
+        assert_eq!(&plan.payload[ADV_LEN - 4..], b"\x03\x08Tr");
+
+        // Too little room for the minimum drops the name, a character isn't
+        // split.
+        let name = Ad::Name {
+            name: "Trackeräbc",
+            min_len: 3,
+        };
+        let plan = super::plan(&[flags, uuids, manufacturer, name]);
+        assert_eq!(plan.dropped.as_slice(), &[AD_COMPLETE_NAME]);
+        assert_eq!(plan.payload.len(), ADV_LEN - 4);
+        assert_eq!(cut("Trackerä", 8), "Tracker");
+
+        // Less important structures make room for more important ones.
+        let service_data = Ad::ServiceData16 {
+            uuid: [0xAA, 0xFE],
+            data: &[0; 6],
+        };
+        let name = Ad::Name {
+            name: "Tag",
+            min_len: 3,
+        };
+        let plan = super::plan(&[manufacturer, name, service_data]);
+        assert_eq!(plan.dropped.as_slice(), &[AD_SERVICE_DATA_16]);
+        assert_eq!(&plan.payload[20..], b"\x04\x09Tag");
+    }
+}
@@ -0,0 +1,63 @@
+//! Alert levels of the Proximity profile.
+//!
+//! The Immediate Alert and Link Loss services share the Alert Level
+//! characteristic (GATT specification supplement, section 3.9). A phone
+//! writes it to make the device alert right away, or to choose how it
+//! alerts when the link is lost. A central disconnecting on purpose doesn't
+//! lose the link, [`is_link_loss`] tells the two apart by the reason.
+
+/// Alert Level characteristic value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum AlertLevel {
+    #[default]
+    None = 0,
+    Mild = 1,
+    High = 2,
+}
+
+impl TryFrom<u8> for AlertLevel {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Mild),
+            2 => Ok(Self::High),
+            _ => Err(value),
+        }
+    }
+}
+
+/// HCI disconnect reasons of a central or host terminating on purpose.
+const REMOTE_USER_TERMINATED: u8 = 0x13;
+const REMOTE_LOW_RESOURCES: u8 = 0x14;
+const REMOTE_POWER_OFF: u8 = 0x15;
+const LOCAL_HOST_TERMINATED: u8 = 0x16;
+
+/// Whether the HCI disconnect `reason` means the link was lost, e.g. a
+/// supervision timeout, rather than terminated on purpose.
+pub fn is_link_loss(reason: u8) -> bool {
+    !matches!(
+        reason,
+        REMOTE_USER_TERMINATED | REMOTE_LOW_RESOURCES | REMOTE_POWER_OFF | LOCAL_HOST_TERMINATED
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_and_link_loss() {
+        assert_eq!(AlertLevel::try_from(2), Ok(AlertLevel::High));
+        assert_eq!(AlertLevel::try_from(3), Err(3));
+        assert!(AlertLevel::Mild > AlertLevel::None);
+
+        // Connection timeout
+        assert!(is_link_loss(0x08));
+        assert!(!is_link_loss(0x13));
+        assert!(!is_link_loss(0x16));
+    }
+}
@@ -0,0 +1,154 @@
+//! Alert Notification Service values, for the client on the device.
+//!
+//! A phone running the Alert Notification Service (0x1811) notifies a New
+//! Alert (GATT specification supplement, section 3.155) for every incoming
+//! call, message or e-mail, once the client enabled the categories it wants
+//! through the control point, see [`control_point`]. [`NewAlert::parse`]
+//! decodes the notification: the category, the number of new alerts and
+//! the start of the text, e.g. the caller or sender.
+
+/// Longest text of a New Alert.
+pub const TEXT_LEN: usize = 18;
+
+/// Category ID selecting all categories in a control point command.
+pub const ALL_CATEGORIES: u8 = 0xFF;
+
+/// Alert categories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Category {
+    Simple = 0,
+    Email = 1,
+    News = 2,
+    Call = 3,
+    MissedCall = 4,
+    Sms = 5,
+    VoiceMail = 6,
+    Schedule = 7,
+    HighPriority = 8,
+    InstantMessage = 9,
+}
+
+impl Category {
+    /// Category of an ID, `None` for reserved and service specific IDs.
+    pub fn from_id(id: u8) -> Option<Self> {
+        Some(match id {
+            0 => Self::Simple,
+            1 => Self::Email,
+            2 => Self::News,
+            3 => Self::Call,
+            4 => Self::MissedCall,
+            5 => Self::Sms,
+            6 => Self::VoiceMail,
+            7 => Self::Schedule,
+            8 => Self::HighPriority,
+            9 => Self::InstantMessage,
+            _ => return None,
+        })
+    }
+
+    /// Short label for a display.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Simple => "Alert",
+            Self::Email => "E-mail",
+            Self::News => "News",
+            Self::Call => "Call",
+            Self::MissedCall => "Missed call",
+            Self::Sms => "SMS",
+            Self::VoiceMail => "Voice mail",
+            Self::Schedule => "Schedule",
+            Self::HighPriority => "Important",
+            Self::InstantMessage => "Message",
+        }
+    }
+}
+
+/// Commands of the Alert Notification Control Point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Command {
+    EnableNewAlerts = 0,
+    EnableUnreadStatus = 1,
+    DisableNewAlerts = 2,
+    DisableUnreadStatus = 3,
+    NotifyNewAlertsNow = 4,
+    NotifyUnreadStatusNow = 5,
+}
+
+/// Control point value of `command` for the category `category_id`, e.g.
+/// [`ALL_CATEGORIES`].
+pub fn control_point(command: Command, category_id: u8) -> [u8; 2] {
+    [command as u8, category_id]
+}
+
+/// A New Alert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NewAlert {
+    /// Category, service specific ones as [`Category::Simple`].
+    pub category: Category,
+    /// Number of new alerts in the category.
+    pub count: u8,
+    text: [u8; TEXT_LEN],
+    text_len: u8,
+}
+
+impl NewAlert {
+    /// Decode a New Alert notification, `None` if it's too short. Text that
+    /// isn't UTF-8 is cut before the first invalid byte.
+    pub fn parse(value: &[u8]) -> Option<Self> {
+        let [category, count, text @ ..] = value else {
+            return None;
+        };
+        let text = &text[..text.len().min(TEXT_LEN)];
+        let valid = match core::str::from_utf8(text) {
+            Ok(text) => text.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        let mut alert = Self {
+            category: Category::from_id(*category).unwrap_or(Category::Simple),
+            count: *count,
+            text: [0; TEXT_LEN],
+            text_len: valid as u8,
+        };
+        alert.text[..valid].copy_from_slice(&text[..valid]);
+        Some(alert)
+    }
+
+    /// Text of the alert, e.g. the caller or sender.
+    pub fn text(&self) -> &str {
+        // Checked in parse.
+        core::str::from_utf8(&self.text[..self.text_len as usize]).unwrap_or("")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_alerts() {
+        let alert = NewAlert::parse(b"\x03\x01Alice").unwrap();
+        assert_eq!(alert.category, Category::Call);
+        assert_eq!(alert.count, 1);
+        assert_eq!(alert.text(), "Alice");
+
+        // Cut to the text length, an incomplete character is dropped.
+        let alert = NewAlert::parse("\x05\x02Grüße aus München".as_bytes()).unwrap();
+        assert_eq!(alert.text(), "Grüße aus Münch");
+        let alert = NewAlert::parse("\x05\x02abcdefghijklmnopqü".as_bytes()).unwrap();
+        assert_eq!(alert.text(), "abcdefghijklmnopq");
+
+        let alert = NewAlert::parse(b"\xfb\x00").unwrap();
+        assert_eq!((alert.category, alert.text()), (Category::Simple, ""));
+        assert_eq!(NewAlert::parse(b"\x03"), None);
+
+        assert_eq!(
+            control_point(Command::EnableNewAlerts, ALL_CATEGORIES),
+            [0x00, 0xFF]
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! ANT-like broadcast channels with 8 byte data pages.
+//!
+//! A channel is identified by a [`ChannelId`] and sends one [`Page`] every
+//! channel period (in 1/32768 s, as in ANT). A [`Profile`] produces the
+//! pages, [`HeartRate`] and [`BikeSpeed`] follow the layouts of the ANT+
+//! heart rate and bike speed device profiles. [`payload`] appends the
+//! channel ID to the page like an ANT extended message, so a receiver can
+//! tell the transmitters apart.
+//!
+//! This is for experiments: the radio framing is not ANT's.
+
+/// Length of a data page.
+pub const PAGE_LEN: usize = 8;
+
+/// A data page, the first byte is the page number.
+pub type Page = [u8; PAGE_LEN];
+
+/// Length of [`payload`].
+pub const PAYLOAD_LEN: usize = PAGE_LEN + 4;
+
+/// Channel period unit, 1/32768 s.
+pub const PERIOD_HZ: u32 = 32_768;
+
+/// Device type of a heart rate monitor.
+pub const DEVICE_TYPE_HEART_RATE: u8 = 120;
+
+/// Channel period of a heart rate monitor, about 4 Hz.
+pub const PERIOD_HEART_RATE: u16 = 8070;
+
+/// Device type of a bike speed sensor.
+pub const DEVICE_TYPE_BIKE_SPEED: u8 = 123;
+
+/// Channel period of a bike speed sensor, about 4 Hz.
+pub const PERIOD_BIKE_SPEED: u16 = 8118;
+
+/// Messages after which the toggle bit of the page number flips.
+const TOGGLE_MESSAGES: u32 = 4;
+
+/// Identifies the transmitter of a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelId {
+    pub device_number: u16,
+    pub device_type: u8,
+    pub transmission_type: u8,
+}
+
+/// A broadcast channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelConfig {
+    pub id: ChannelId,
+    /// Time between two pages in 1/32768 s.
+    pub period: u16,
+    /// RF channel, MHz above 2400.
+    pub rf_channel: u8,
+}
+
+impl ChannelConfig {
+    /// Time between two pages in µs.
+    pub fn period_us(&self) -> u32 {
+        (self.period as u64 * 1_000_000 / PERIOD_HZ as u64) as u32
+    }
+}
+
+/// Page followed by the channel ID:
+/// `page (8) | device number (2, little endian) | device type | transmission type`
+pub fn payload(page: &Page, id: &ChannelId) -> [u8; PAYLOAD_LEN] {
+    let mut payload = [0; PAYLOAD_LEN];
+    payload[..PAGE_LEN].copy_from_slice(page);
+    payload[8..10].copy_from_slice(&id.device_number.to_le_bytes());
+    payload[10] = id.device_type;
+    payload[11] = id.transmission_type;
+    payload
+}
+
+/// Produces the page of every message of a channel.
+pub trait Profile {
+    fn next_page(&mut self) -> Page;
+}
+
+/// Heart rate monitor, page 4 (previous heart beat) with the toggle bit.
+#[derive(Debug, Default)]
+pub struct HeartRate {
+    /// Time of the last heart beat in 1/1024 s.
+    event_time: u16,
+    previous_event_time: u16,
+    /// Heart beats, wrapping.
+    count: u8,
+    bpm: u8,
+    messages: u32,
+}
+
+impl HeartRate {
+    pub const fn new() -> Self {
+        Self {
+            event_time: 0,
+            previous_event_time: 0,
+            count: 0,
+            bpm: 0,
+            messages: 0,
+        }
+    }
+
+    /// Record a heart beat at `event_time` (1/1024 s, wrapping) with the
+    /// computed heart rate.
+    pub fn beat(&mut self, event_time: u16, bpm: u8) {
+        self.previous_event_time = self.event_time;
+        self.event_time = event_time;
+        self.count = self.count.wrapping_add(1);
+        self.bpm = bpm;
+    }
+}
+
+impl Profile for HeartRate {
+    fn next_page(&mut self) -> Page {
+        let toggle = ((self.messages / TOGGLE_MESSAGES) % 2) as u8;
+        self.messages = self.messages.wrapping_add(1);
+        let previous = self.previous_event_time.to_le_bytes();
+        let event = self.event_time.to_le_bytes();
+        [
+            4 | toggle << 7,
+            0xff,
+            previous[0],
+            previous[1],
+            event[0],
+            event[1],
+            self.count,
+            self.bpm,
+        ]
+    }
+}
+
+/// Bike speed sensor, page 0.
+#[derive(Debug, Default)]
+pub struct BikeSpeed {
+    /// Time of the last wheel revolution in 1/1024 s.
+    event_time: u16,
+    /// Wheel revolutions, wrapping.
+    revolutions: u16,
+    messages: u32,
+}
+
+impl BikeSpeed {
+    pub const fn new() -> Self {
+        Self {
+            event_time: 0,
+            revolutions: 0,
+            messages: 0,
+        }
+    }
+
+    /// Record a wheel revolution at `event_time` (1/1024 s, wrapping).
+    pub fn revolution(&mut self, event_time: u16) {
+        self.event_time = event_time;
+        self.revolutions = self.revolutions.wrapping_add(1);
+    }
+}
+
+impl Profile for BikeSpeed {
+    fn next_page(&mut self) -> Page {
+        let toggle = ((self.messages / TOGGLE_MESSAGES) % 2) as u8;
+        self.messages = self.messages.wrapping_add(1);
+        let event = self.event_time.to_le_bytes();
+        let revolutions = self.revolutions.to_le_bytes();
+        [
+            toggle << 7,
+            0xff,
+            0xff,
+            0xff,
+            event[0],
+            event[1],
+            revolutions[0],
+            revolutions[1],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_pages() {
+        let mut hr = HeartRate::new();
+        hr.beat(1024, 60);
+        hr.beat(2048, 61);
+        let pages: [Page; 5] = core::array::from_fn(|_| hr.next_page());
+        assert_eq!(pages[0], [0x04, 0xff, 0x00, 0x04, 0x00, 0x08, 2, 61]);
+        assert_eq!(pages[3][0], 0x04);
+        assert_eq!(pages[4][0], 0x84);
+
+        let id = ChannelId {
+            device_number: 0x1234,
+            device_type: DEVICE_TYPE_HEART_RATE,
+            transmission_type: 1,
+        };
+        assert_eq!(payload(&pages[0], &id)[8..], [0x34, 0x12, 120, 1]);
+
+        let config = ChannelConfig {
+            id,
+            period: PERIOD_HEART_RATE,
+            rf_channel: 57,
+        };
+        assert_eq!(config.period_us(), 246_276);
+    }
+}
@@ -0,0 +1,245 @@
+//! Barometric pressure: sensor compensation and altitude fusion.
+//!
+//! [`Bmp280Calibration`] and [`Dps310Coefficients`] turn the raw readings
+//! of the sensors into temperature and pressure, following their
+//! datasheets. The pressure altitude is precise over minutes but drifts
+//! with the weather, the GNSS altitude is noisy but doesn't drift: an
+//! [`AltitudeFilter`] combines both.
+
+use libm::powf;
+
+/// Standard pressure at sea level in Pa.
+pub const SEA_LEVEL_PA: f32 = 101_325.0;
+
+/// Altitude in m of `pressure_pa` in the international standard atmosphere.
+pub fn pressure_altitude(pressure_pa: f32) -> f32 {
+    44_330.0 * (1.0 - powf(pressure_pa / SEA_LEVEL_PA, 1.0 / 5.255))
+}
+
+/// A compensated measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Pressure {
+    pub pascal: f32,
+    pub celsius: f32,
+}
+
+/// Trimming parameters of a BMP280, registers 0x88 to 0x9F.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bmp280Calibration {
+    t1: u16,
+    t2: i16,
+    t3: i16,
+    p1: u16,
+    p: [i16; 8],
+}
+
+impl Bmp280Calibration {
+    /// Parse the little endian trimming registers.
+    pub fn from_bytes(bytes: &[u8; 24]) -> Self {
+        let word = |i: usize| [bytes[2 * i], bytes[2 * i + 1]];
+        Self {
+            t1: u16::from_le_bytes(word(0)),
+            t2: i16::from_le_bytes(word(1)),
+            t3: i16::from_le_bytes(word(2)),
+            p1: u16::from_le_bytes(word(3)),
+            p: core::array::from_fn(|i| i16::from_le_bytes(word(4 + i))),
+        }
+    }
+
+    /// Compensate 20 bit raw readings, after the floating point formulas of
+    /// the datasheet (section 8.1). `None` for an invalid calibration.
+    pub fn compensate(&self, raw_temperature: i32, raw_pressure: i32) -> Option<Pressure> {
+        let (t1, t2, t3) = (self.t1 as f32, self.t2 as f32, self.t3 as f32);
+        let adc_t = raw_temperature as f32;
+        let var1 = (adc_t / 16384.0 - t1 / 1024.0) * t2;
+        let var2 = (adc_t / 131072.0 - t1 / 8192.0) * (adc_t / 131072.0 - t1 / 8192.0) * t3;
+        let t_fine = var1 + var2;
+
+        let [p2, p3, p4, p5, p6, p7, p8, p9] = self.p.map(|p| p as f32);
+        let mut var1 = t_fine / 2.0 - 64000.0;
+        let mut var2 = var1 * var1 * p6 / 32768.0;
+        var2 += var1 * p5 * 2.0;
+        var2 = var2 / 4.0 + p4 * 65536.0;
+        var1 = (p3 * var1 * var1 / 524288.0 + p2 * var1) / 524288.0;
+        var1 = (1.0 + var1 / 32768.0) * self.p1 as f32;
+        if var1 == 0.0 {
+            return None;
+        }
+        let mut p = 1_048_576.0 - raw_pressure as f32;
+        p = (p - var2 / 4096.0) * 6250.0 / var1;
+        let var1 = p9 * p * p / 2_147_483_648.0;
+        let var2 = p * p8 / 32768.0;
+        Some(Pressure {
+            pascal: p + (var1 + var2 + p7) / 16.0,
+            celsius: t_fine / 5120.0,
+        })
+    }
+}
+
+/// Sign extend the lowest `bits` of `value`.
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+/// Calibration coefficients of a DPS310, registers 0x10 to 0x21.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dps310Coefficients {
+    c0: f32,
+    c1: f32,
+    c00: f32,
+    c10: f32,
+    c01: f32,
+    c11: f32,
+    c20: f32,
+    c21: f32,
+    c30: f32,
+}
+
+impl Dps310Coefficients {
+    /// Scale factor of the raw values at single oversampling.
+    pub const SCALE_1X: f32 = 524_288.0;
+
+    /// Parse the packed big endian coefficient registers.
+    pub fn from_bytes(b: &[u8; 18]) -> Self {
+        let b: [u32; 18] = b.map(u32::from);
+        let word = |i: usize| sign_extend(b[i] << 8 | b[i + 1], 16) as f32;
+        Self {
+            c0: sign_extend(b[0] << 4 | b[1] >> 4, 12) as f32,
+            c1: sign_extend((b[1] & 0x0F) << 8 | b[2], 12) as f32,
+            c00: sign_extend(b[3] << 12 | b[4] << 4 | b[5] >> 4, 20) as f32,
+            c10: sign_extend((b[5] & 0x0F) << 16 | b[6] << 8 | b[7], 20) as f32,
+            c01: word(8),
+            c11: word(10),
+            c20: word(12),
+            c21: word(14),
+            c30: word(16),
+        }
+    }
+
+    /// Compensate 24 bit raw readings at single oversampling (datasheet
+    /// section 4.9).
+    pub fn compensate(&self, raw_temperature: i32, raw_pressure: i32) -> Pressure {
+        let t = raw_temperature as f32 / Self::SCALE_1X;
+        let p = raw_pressure as f32 / Self::SCALE_1X;
+        Pressure {
+            pascal: self.c00
+                + p * (self.c10 + p * (self.c20 + p * self.c30))
+                + t * self.c01
+                + t * p * (self.c11 + p * self.c21),
+            celsius: self.c0 * 0.5 + self.c1 * t,
+        }
+    }
+}
+
+/// Weight of a GNSS altitude in the estimate of the pressure altitude offset.
+const GNSS_WEIGHT: f32 = 0.02;
+
+/// Complementary filter of pressure and GNSS altitude.
+///
+/// The pressure altitude follows every change, its offset to the true
+/// altitude is slowly pulled towards the GNSS altitude, which averages the
+/// GNSS noise over about a minute at 1 Hz.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AltitudeFilter {
+    pressure_altitude: Option<f32>,
+    offset: Option<f32>,
+    gnss_altitude: Option<f32>,
+}
+
+impl AltitudeFilter {
+    pub const fn new() -> Self {
+        Self {
+            pressure_altitude: None,
+            offset: None,
+            gnss_altitude: None,
+        }
+    }
+
+    /// Add a pressure altitude.
+    pub fn update_pressure(&mut self, altitude: f32) {
+        self.pressure_altitude = Some(altitude);
+    }
+
+    /// Add a GNSS altitude.
+    pub fn update_gnss(&mut self, altitude: f32) {
+        self.gnss_altitude = Some(altitude);
+        if let Some(pressure_altitude) = self.pressure_altitude {
+            let error = altitude - pressure_altitude;
+            self.offset = Some(match self.offset {
+                Some(offset) => offset + GNSS_WEIGHT * (error - offset),
+                None => error,
+            });
+        }
+    }
+
+    /// Fused altitude in m, the pressure or GNSS altitude alone if the
+    /// other one is missing.
+    pub fn altitude(&self) -> Option<f32> {
+        match (self.pressure_altitude, self.offset) {
+            (Some(altitude), Some(offset)) => Some(altitude + offset),
+            (Some(altitude), None) if self.gnss_altitude.is_none() => Some(altitude),
+            _ => self.gnss_altitude,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bmp280_datasheet_example() {
+        // Trimming values and readings of the datasheet, section 3.12.
+        let words: [i32; 12] = [
+            27504, 26435, -1000, 36477, -10685, 3024, 2855, 140, -7, 15500, -14600, 6000,
+        ];
+        let mut bytes = [0; 24];
+        for (i, w) in words.iter().enumerate() {
+            bytes[2 * i..2 * i + 2].copy_from_slice(&(*w as u16).to_le_bytes());
+        }
+        let pressure = Bmp280Calibration::from_bytes(&bytes)
+            .compensate(519_888, 415_148)
+            .unwrap();
+        assert!((pressure.celsius - 25.08).abs() < 0.01, "{pressure:?}");
+        assert!((pressure.pascal - 100_653.27).abs() < 1.0, "{pressure:?}");
+    }
+
+    #[test]
+    fn dps310_coefficients() {
+        // c0 = -2 (0xFFE), c1 = 3, c00 = 100000, c10 = -1, c01 = -256.
+        let mut bytes = [0; 18];
+        bytes[..3].copy_from_slice(&[0xFF, 0xE0, 0x03]);
+        bytes[3..8].copy_from_slice(&[0x18, 0x6A, 0x0F, 0xFF, 0xFF]);
+        bytes[8..10].copy_from_slice(&[0xFF, 0x00]);
+        let c = Dps310Coefficients::from_bytes(&bytes);
+        assert_eq!(
+            (c.c0, c.c1, c.c00, c.c10, c.c01),
+            (-2.0, 3.0, 100_000.0, -1.0, -256.0)
+        );
+        let scale = Dps310Coefficients::SCALE_1X as i32;
+        let pressure = c.compensate(scale, scale);
+        assert_eq!(pressure.celsius, 2.0);
+        assert_eq!(pressure.pascal, 100_000.0 - 1.0 - 256.0);
+    }
+
+    #[test]
+    fn altitude() {
+        assert!(pressure_altitude(SEA_LEVEL_PA).abs() < 0.01);
+        assert!((pressure_altitude(89_875.0) - 1000.0).abs() < 2.0);
+
+        let mut filter = AltitudeFilter::new();
+        assert_eq!(filter.altitude(), None);
+        filter.update_pressure(120.0);
+        assert_eq!(filter.altitude(), Some(120.0));
+        // The first GNSS altitude sets the offset, later ones move it slowly.
+        filter.update_gnss(100.0);
+        assert_eq!(filter.altitude(), Some(100.0));
+        filter.update_gnss(150.0);
+        assert!((filter.altitude().unwrap() - 101.0).abs() < 1e-3);
+        // Climbing is followed by the pressure altitude immediately.
+        filter.update_pressure(130.0);
+        assert!((filter.altitude().unwrap() - 111.0).abs() < 1e-3);
+    }
+}
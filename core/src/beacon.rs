@@ -0,0 +1,98 @@
+//! Payloads of the common beacon formats.
+//!
+//! - [`ibeacon`], manufacturer specific data of Apple's iBeacon
+//! - [`eddystone_url`], an Eddystone-URL frame with the URL compressed
+
+use heapless::Vec;
+
+/// Company identifier of iBeacon manufacturer specific data.
+pub const IBEACON_COMPANY_ID: u16 = 0x004C;
+
+/// Length of the iBeacon payload after the company identifier.
+pub const IBEACON_LEN: usize = 23;
+
+/// Longest Eddystone-URL frame.
+pub const EDDYSTONE_URL_LEN: usize = 20;
+
+/// Eddystone frame type of URL.
+const URL_FRAME_TYPE: u8 = 0x10;
+
+/// URL scheme prefixes by code.
+const SCHEMES: [&str; 4] = ["http://www.", "https://www.", "http://", "https://"];
+
+/// Text expanded from the codes 0x00 to 0x0D, with slash first so it is
+/// matched before the shorter one.
+const EXPANSIONS: [&str; 14] = [
+    ".com/", ".org/", ".edu/", ".net/", ".info/", ".biz/", ".gov/", ".com", ".org", ".edu", ".net",
+    ".info", ".biz", ".gov",
+];
+
+/// iBeacon payload of `uuid` (big endian, as written) with `major` and
+/// `minor`, `tx_power` is the RSSI at 1 m in dBm.
+pub fn ibeacon(uuid: &[u8; 16], major: u16, minor: u16, tx_power: i8) -> [u8; IBEACON_LEN] {
+    let mut payload = [0; IBEACON_LEN];
+    payload[0] = 0x02;
+    payload[1] = 0x15;
+    payload[2..18].copy_from_slice(uuid);
+    payload[18..20].copy_from_slice(&major.to_be_bytes());
+    payload[20..22].copy_from_slice(&minor.to_be_bytes());
+    payload[22] = tx_power as u8;
+    payload
+}
+
+/// Eddystone-URL frame of `url`, `tx_power` is the RSSI at 0 m in dBm.
+///
+/// `None` if the scheme isn't http(s), the URL isn't ASCII or it is too long
+/// even compressed.
+pub fn eddystone_url(url: &str, tx_power: i8) -> Option<Vec<u8, EDDYSTONE_URL_LEN>> {
+    if !url.is_ascii() {
+        return None;
+    }
+    // Longer prefixes first, `http://www.` also starts with `http://`.
+    let (scheme, prefix) = SCHEMES
+        .iter()
+        .enumerate()
+        .find(|(_, prefix)| url.starts_with(*prefix))?;
+    let mut frame = Vec::new();
+    frame
+        .extend_from_slice(&[URL_FRAME_TYPE, tx_power as u8, scheme as u8])
+        .ok()?;
+    let mut rest = &url[prefix.len()..];
+    while !rest.is_empty() {
+        if let Some((code, expansion)) = EXPANSIONS
+            .iter()
+            .enumerate()
+            .find(|(_, expansion)| rest.starts_with(*expansion))
+        {
+            frame.push(code as u8).ok()?;
+            rest = &rest[expansion.len()..];
+        } else {
+            frame.push(rest.as_bytes()[0]).ok()?;
+            rest = &rest[1..];
+        }
+    }
+    Some(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn beacon_payloads() {
+        let payload = ibeacon(&[0x11; 16], 1, 0x0203, -59);
+        assert_eq!(payload[..2], [0x02, 0x15]);
+        assert_eq!(payload[18..], [0, 1, 2, 3, 0xC5]);
+
+        let frame = eddystone_url("https://www.example.com/", -20).unwrap();
+        assert_eq!(frame[..3], [0x10, 0xEC, 0x01]);
+        assert_eq!(frame[3..], *b"example\x00");
+        assert_eq!(
+            eddystone_url("http://a.info", 0).unwrap()[2..],
+            [2, b'a', 0x0B]
+        );
+        assert_eq!(eddystone_url("ftp://example.com", 0), None);
+        assert_eq!(eddystone_url("https://bücher.de", 0), None);
+        assert_eq!(eddystone_url("https://a-very-long-host-name.com/", 0), None);
+    }
+}
@@ -0,0 +1,184 @@
+//! Framing of the CASIC binary protocol of the L76K GNSS module.
+//!
+//! A frame is `0xBA 0xCE | length (2) | class | id | payload | checksum (4)`,
+//! little endian, where the payload length is a multiple of 4 and the
+//! checksum is `id << 24 + class << 16 + length` plus the payload as 32 bit
+//! words, wrapping. Assistance data (ephemeris, time and position aiding)
+//! is sent to the module in this format.
+
+/// Start of a frame.
+pub const SYNC: [u8; 2] = [0xBA, 0xCE];
+
+/// Bytes of a frame besides the payload.
+pub const OVERHEAD: usize = 10;
+
+/// Errors of [`FrameAssembler::push`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameError {
+    /// The payload length isn't a multiple of 4 or the frame doesn't fit
+    /// into the buffer.
+    InvalidLength(u16),
+    /// The checksum doesn't match, the frame was dropped.
+    Checksum,
+}
+
+/// Checksum of a frame with the given class, id and payload.
+pub fn checksum(class: u8, id: u8, payload: &[u8]) -> u32 {
+    let header = (id as u32) << 24 | (class as u32) << 16 | payload.len() as u32;
+    payload.chunks(4).fold(header, |sum, word| {
+        let mut bytes = [0; 4];
+        bytes[..word.len()].copy_from_slice(word);
+        sum.wrapping_add(u32::from_le_bytes(bytes))
+    })
+}
+
+/// Encode a frame into `out`, returning its length.
+pub fn encode(class: u8, id: u8, payload: &[u8], out: &mut [u8]) -> Result<usize, FrameError> {
+    let len = payload.len() + OVERHEAD;
+    if !payload.len().is_multiple_of(4) || payload.len() > u16::MAX as usize || out.len() < len {
+        return Err(FrameError::InvalidLength(payload.len() as u16));
+    }
+    out[..2].copy_from_slice(&SYNC);
+    out[2..4].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+    out[4] = class;
+    out[5] = id;
+    out[6..6 + payload.len()].copy_from_slice(payload);
+    out[len - 4..len].copy_from_slice(&checksum(class, id, payload).to_le_bytes());
+    Ok(len)
+}
+
+/// Finds complete frames in a byte stream received in chunks of any size.
+///
+/// Bytes outside of frames are skipped, frames of up to `N` bytes are
+/// returned once their checksum was verified.
+pub struct FrameAssembler<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for FrameAssembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FrameAssembler<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Drop a partially received frame.
+    pub fn reset(&mut self) {
+        self.len = 0;
+    }
+
+    /// Whether a frame is partially received.
+    pub fn in_frame(&self) -> bool {
+        self.len > 0
+    }
+
+    /// Add a byte, returning the frame it completes.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], FrameError>> {
+        if self.len < SYNC.len() && byte != SYNC[self.len] {
+            // Resynchronize, the byte may start a new frame.
+            self.len = 0;
+            if byte != SYNC[0] {
+                return None;
+            }
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len < 4 {
+            return None;
+        }
+        let payload_len = u16::from_le_bytes([self.buf[2], self.buf[3]]);
+        let frame_len = payload_len as usize + OVERHEAD;
+        if !payload_len.is_multiple_of(4) || frame_len > N {
+            self.len = 0;
+            return Some(Err(FrameError::InvalidLength(payload_len)));
+        }
+        if self.len < frame_len {
+            return None;
+        }
+        self.len = 0;
+        let frame = &self.buf[..frame_len];
+        let payload = &frame[6..frame_len - 4];
+        let expected = u32::from_le_bytes(frame[frame_len - 4..].try_into().unwrap());
+        if checksum(frame[4], frame[5], payload) == expected {
+            Some(Ok(frame))
+        } else {
+            Some(Err(FrameError::Checksum))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames<const N: usize>(
+        assembler: &mut FrameAssembler<N>,
+        data: &[u8],
+    ) -> std::vec::Vec<Result<std::vec::Vec<u8>, FrameError>> {
+        data.iter()
+            .filter_map(|&b| assembler.push(b).map(|r| r.map(|f| f.to_vec())))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_in_chunks() {
+        let mut frame = [0; 32];
+        let len = encode(0x0B, 0x01, &[1, 2, 3, 4, 5, 6, 7, 8], &mut frame).unwrap();
+        assert_eq!(len, 18);
+        assert_eq!(frame[..6], [0xBA, 0xCE, 8, 0, 0x0B, 0x01]);
+
+        let mut assembler = FrameAssembler::<64>::new();
+        let mut stream = vec![0x00, 0xBA, 0x42];
+        stream.extend_from_slice(&frame[..len]);
+        let (first, second) = stream.split_at(7);
+        assert!(frames(&mut assembler, first).is_empty());
+        assert!(assembler.in_frame());
+        assert_eq!(
+            frames(&mut assembler, second),
+            vec![Ok(frame[..len].to_vec())]
+        );
+        assert!(!assembler.in_frame());
+    }
+
+    #[test]
+    fn checksum_wraps() {
+        let payload = [0xFF; 8];
+        assert_eq!(
+            checksum(0x08, 0x07, &payload),
+            (0x0708_0008u32)
+                .wrapping_add(0xFFFF_FFFF)
+                .wrapping_add(0xFFFF_FFFF)
+        );
+    }
+
+    #[test]
+    fn corrupted_and_oversized_frames() {
+        let mut frame = [0; 16];
+        let len = encode(0x0B, 0x01, &[9; 4], &mut frame).unwrap();
+        frame[7] ^= 1;
+        let mut assembler = FrameAssembler::<16>::new();
+        assert_eq!(
+            frames(&mut assembler, &frame[..len]),
+            vec![Err(FrameError::Checksum)]
+        );
+
+        let oversized = [0xBA, 0xCE, 8, 0];
+        assert_eq!(
+            frames(&mut assembler, &oversized),
+            vec![Err(FrameError::InvalidLength(8))]
+        );
+        assert_eq!(
+            encode(0, 0, &[0; 3], &mut frame),
+            Err(FrameError::InvalidLength(3))
+        );
+    }
+}
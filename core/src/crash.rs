@@ -0,0 +1,141 @@
+//! Crash dump record written to flash by the HardFault handler.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::crc::crc32;
+
+/// Marks a written record ("CRSH").
+pub const MAGIC: u32 = 0x4853_5243;
+
+/// Words of the stack above the exception frame in a dump.
+pub const STACK_WORDS: usize = 32;
+
+/// Bytes of a basic exception frame: r0-r3, r12, lr, pc and xPSR.
+const BASIC_FRAME_LEN: u32 = 0x20;
+
+/// Bytes of an extended exception frame, which adds s0-s15 and FPSCR.
+const EXTENDED_FRAME_LEN: u32 = 0x68;
+
+/// Stack pointer before an exception whose frame was stacked at `frame`,
+/// from the EXC_RETURN value the handler was entered with and the stacked
+/// xPSR.
+///
+/// Bit 4 of EXC_RETURN is clear if the FPU registers were stacked too, bit 9
+/// of the stacked xPSR is set if a word of padding aligned the frame to 8
+/// bytes.
+pub fn stack_pointer(frame: u32, exc_return: u32, xpsr: u32) -> u32 {
+    let len = if exc_return & (1 << 4) == 0 {
+        EXTENDED_FRAME_LEN
+    } else {
+        BASIC_FRAME_LEN
+    };
+    let padding = if xpsr & (1 << 9) != 0 { 4 } else { 0 };
+    frame + len + padding
+}
+
+/// The state of the CPU when it faulted (little endian).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct CrashDump {
+    pub magic: u32,
+    /// Stacked exception frame: r0, r1, r2, r3, r12, lr, pc, xPSR.
+    pub frame: [u32; 8],
+    /// Configurable, HardFault, MemManage address and BusFault address
+    /// status registers.
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32,
+    /// Stack pointer before the exception.
+    pub sp: u32,
+    /// Stack contents starting at `sp`, zero beyond the top of the stack.
+    pub stack: [u32; STACK_WORDS],
+    /// CRC-32 of the fields above.
+    pub crc: u32,
+}
+
+impl CrashDump {
+    /// Bytes of a record.
+    pub const LEN: usize = core::mem::size_of::<Self>();
+
+    /// A record of `frame` and the fault status registers, without stack.
+    pub fn new(frame: [u32; 8], cfsr: u32, hfsr: u32, mmfar: u32, bfar: u32, sp: u32) -> Self {
+        Self {
+            magic: MAGIC,
+            frame,
+            cfsr,
+            hfsr,
+            mmfar,
+            bfar,
+            sp,
+            ..Self::zeroed()
+        }
+    }
+
+    /// Program counter of the faulting instruction.
+    pub fn pc(&self) -> u32 {
+        self.frame[6]
+    }
+
+    /// Link register at the fault.
+    pub fn lr(&self) -> u32 {
+        self.frame[5]
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+
+    fn checksum(&self) -> u32 {
+        crc32(&self.as_bytes()[..Self::LEN - 4])
+    }
+
+    /// Compute the CRC once all fields are filled in.
+    pub fn seal(&mut self) {
+        self.crc = self.checksum();
+    }
+
+    /// A sealed record, not erased flash.
+    pub fn is_valid(&self) -> bool {
+        self.magic == MAGIC && self.crc == self.checksum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_dump_is_valid() {
+        let mut frame = [0; 8];
+        frame[5] = 0x2_7101;
+        frame[6] = 0x2_8000;
+        let mut dump = CrashDump::new(frame, 0x100, 0x4000_0000, 0, 0, 0x2003_FF00);
+        dump.stack[0] = 0xDEAD_BEEF;
+        assert!(!dump.is_valid());
+        dump.seal();
+        assert!(dump.is_valid());
+        assert_eq!((dump.pc(), dump.lr()), (0x2_8000, 0x2_7101));
+        assert_eq!(CrashDump::LEN, 188);
+        assert_eq!(dump.as_bytes()[..4], *b"CRSH");
+        // Erased flash is not a dump.
+        assert!(!bytemuck::pod_read_unaligned::<CrashDump>(&[0xFF; CrashDump::LEN]).is_valid());
+    }
+
+    #[test]
+    fn stack_pointer_before_the_exception() {
+        // Thread mode on the main stack, without and with the FPU registers.
+        assert_eq!(stack_pointer(0x2000_1000, 0xFFFF_FFF9, 0), 0x2000_1020);
+        assert_eq!(stack_pointer(0x2000_1000, 0xFFFF_FFE9, 0), 0x2000_1068);
+        // Aligned with a word of padding.
+        assert_eq!(
+            stack_pointer(0x2000_1000, 0xFFFF_FFF9, 0x0100_0200),
+            0x2000_1024
+        );
+        assert_eq!(
+            stack_pointer(0x2000_1000, 0xFFFF_FFED, 0x0100_0200),
+            0x2000_106C
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Editing text and numbers with a single button.
+//!
+//! [`Input::Next`] (a short press) steps through the choices,
+//! [`Input::Select`] (a long press) takes the current one and
+//! [`Input::Cancel`] (a double press) discards the edit:
+//! - [`NumberEditor`] edits the decimal digits from left to right, every
+//!   short press increments the digit under the cursor, a long press moves
+//!   to the next one and confirms after the last.
+//! - [`TextEditor`] appends characters picked from [`CHARSET`], followed by
+//!   the [`Choice::Delete`] and [`Choice::Done`] entries.
+
+use heapless::{String, Vec};
+
+/// Characters offered by the [`TextEditor`].
+pub const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789 -_.";
+
+/// Most digits of a [`NumberEditor`], enough for any `u32`.
+pub const MAX_DIGITS: usize = 10;
+
+/// A button input to an editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Input {
+    Next,
+    Select,
+    Cancel,
+}
+
+/// State of an editor after an [`Input`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Outcome {
+    Editing,
+    /// The value was confirmed.
+    Done,
+    Cancelled,
+}
+
+/// Edits a number in `min..=max` digit by digit.
+#[derive(Debug, Clone)]
+pub struct NumberEditor {
+    digits: Vec<u8, MAX_DIGITS>,
+    cursor: usize,
+    min: u32,
+    max: u32,
+}
+
+impl NumberEditor {
+    /// Start editing `value` with as many digits as `max` has.
+    pub fn new(value: u32, min: u32, max: u32) -> Self {
+        let len = max.checked_ilog10().unwrap_or(0) as usize + 1;
+        let mut digits = Vec::new();
+        let mut rest = value.clamp(min, max);
+        for _ in 0..len {
+            let _ = digits.push((rest % 10) as u8);
+            rest /= 10;
+        }
+        digits.reverse();
+        Self {
+            digits,
+            cursor: 0,
+            min,
+            max,
+        }
+    }
+
+    /// The digits, most significant first.
+    pub fn digits(&self) -> &[u8] {
+        &self.digits
+    }
+
+    /// Index of the digit being edited.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The edited value, limited to the range.
+    pub fn value(&self) -> u32 {
+        let value = self
+            .digits
+            .iter()
+            .fold(0u64, |value, d| value * 10 + u64::from(*d));
+        value.clamp(self.min.into(), self.max.into()) as u32
+    }
+
+    pub fn input(&mut self, input: Input) -> Outcome {
+        match input {
+            Input::Next => {
+                let digit = &mut self.digits[self.cursor];
+                *digit = (*digit + 1) % 10;
+                Outcome::Editing
+            }
+            Input::Select if self.cursor + 1 < self.digits.len() => {
+                self.cursor += 1;
+                Outcome::Editing
+            }
+            Input::Select => Outcome::Done,
+            Input::Cancel => Outcome::Cancelled,
+        }
+    }
+}
+
+/// An entry of the [`TextEditor`] picker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Choice {
+    Char(char),
+    /// Remove the last character.
+    Delete,
+    /// Confirm the text.
+    Done,
+}
+
+/// Edits an ASCII text of up to `N` characters from [`CHARSET`].
+#[derive(Debug, Clone)]
+pub struct TextEditor<const N: usize> {
+    text: String<N>,
+    choice: usize,
+}
+
+impl<const N: usize> TextEditor<N> {
+    /// Start editing `text`, the picker on [`Choice::Done`] to keep it as is.
+    /// Characters that can't be picked are dropped.
+    pub fn new(text: &str) -> Self {
+        let mut editor = Self {
+            text: String::new(),
+            choice: CHARSET.len() + 1,
+        };
+        for c in text
+            .chars()
+            .filter(|c| c.is_ascii() && CHARSET.contains(&(*c as u8)))
+        {
+            if editor.text.push(c).is_err() {
+                break;
+            }
+        }
+        editor
+    }
+
+    /// The text so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The entry the picker is on.
+    pub fn choice(&self) -> Choice {
+        match CHARSET.get(self.choice) {
+            Some(c) => Choice::Char(*c as char),
+            None if self.choice == CHARSET.len() => Choice::Delete,
+            None => Choice::Done,
+        }
+    }
+
+    pub fn input(&mut self, input: Input) -> Outcome {
+        match input {
+            Input::Next => {
+                self.choice = (self.choice + 1) % (CHARSET.len() + 2);
+                Outcome::Editing
+            }
+            Input::Select => match self.choice() {
+                Choice::Char(c) => {
+                    // Full: the picker stays, only Delete and Done help.
+                    let _ = self.text.push(c);
+                    Outcome::Editing
+                }
+                Choice::Delete => {
+                    self.text.pop();
+                    Outcome::Editing
+                }
+                Choice::Done => Outcome::Done,
+            },
+            Input::Cancel => Outcome::Cancelled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_editor() {
+        let mut editor = NumberEditor::new(250, 20, 10_240);
+        assert_eq!(editor.digits(), &[0, 0, 2, 5, 0]);
+        assert_eq!(editor.input(Input::Select), Outcome::Editing);
+        editor.input(Input::Next);
+        assert_eq!(editor.value(), 1250);
+        editor.input(Input::Select);
+        editor.input(Input::Select);
+        for _ in 0..5 {
+            editor.input(Input::Next);
+        }
+        assert_eq!(editor.value(), 1200);
+        assert_eq!(editor.input(Input::Select), Outcome::Editing);
+        assert_eq!(editor.cursor(), 4);
+        assert_eq!(editor.input(Input::Select), Outcome::Done);
+
+        // Limited to the range while a digit is out of it.
+        let mut editor = NumberEditor::new(250, 20, 10_240);
+        editor.input(Input::Next);
+        assert_eq!(editor.value(), 10_240);
+        let mut editor = NumberEditor::new(9, 0, 9);
+        editor.input(Input::Next);
+        assert_eq!(editor.value(), 0);
+        assert_eq!(editor.input(Input::Cancel), Outcome::Cancelled);
+        assert_eq!(NumberEditor::new(0, 100, 999).value(), 100);
+    }
+
+    #[test]
+    fn text_editor() {
+        let mut editor = TextEditor::<4>::new("Tag#1");
+        assert_eq!(editor.text(), "Tag1");
+        assert_eq!(editor.choice(), Choice::Done);
+        editor.input(Input::Next);
+        assert_eq!(editor.choice(), Choice::Char('A'));
+        // Full, the character is ignored.
+        editor.input(Input::Select);
+        assert_eq!(editor.text(), "Tag1");
+        for _ in 0..CHARSET.len() {
+            editor.input(Input::Next);
+        }
+        assert_eq!(editor.choice(), Choice::Delete);
+        editor.input(Input::Select);
+        editor.input(Input::Select);
+        assert_eq!(editor.text(), "Ta");
+        editor.input(Input::Next);
+        assert_eq!(editor.input(Input::Select), Outcome::Done);
+        assert_eq!(editor.input(Input::Cancel), Outcome::Cancelled);
+    }
+}
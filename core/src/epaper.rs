@@ -0,0 +1,216 @@
+//! Frame buffer and commands of the SSD1681 e-paper controller.
+//!
+//! The SSD1681 drives 200x200 panels like the 1.54" modules. Its RAM has one
+//! bit per pixel, row by row, the leftmost pixel in the most significant bit,
+//! a set bit is white. There are two RAMs: the new image in
+//! [`WRITE_BLACK_RAM`] and the base image in [`WRITE_RED_RAM`], written with
+//! every full refresh, which a partial refresh compares against to only
+//! drive the changed pixels.
+//!
+//! Partial refreshes are quick and don't flash, but leave ghosting behind,
+//! so [`RefreshPolicy`] falls back to a full refresh after a number of them.
+
+use crate::oled::Rotation;
+
+/// Width and height of the panel in pixels.
+pub const SIZE: usize = 200;
+
+/// Bytes of the frame buffer.
+pub const BUFFER_LEN: usize = SIZE * SIZE / 8;
+
+/// Partial refreshes between two full refreshes by default.
+pub const DEFAULT_MAX_PARTIAL: u8 = 10;
+
+/// Software reset, needs a wait for BUSY.
+pub const SW_RESET: u8 = 0x12;
+
+/// Write the new image.
+pub const WRITE_BLACK_RAM: u8 = 0x24;
+
+/// Write the previous image, the base of a partial refresh.
+pub const WRITE_RED_RAM: u8 = 0x26;
+
+/// Set the X address counter.
+pub const SET_RAM_X_COUNTER: u8 = 0x4E;
+
+/// Set the Y address counter.
+pub const SET_RAM_Y_COUNTER: u8 = 0x4F;
+
+/// Select the display update sequence.
+pub const UPDATE_CONTROL_2: u8 = 0x22;
+
+/// Run the selected display update sequence, needs a wait for BUSY.
+pub const MASTER_ACTIVATION: u8 = 0x20;
+
+/// Enter deep sleep, left only by a hardware reset.
+pub const DEEP_SLEEP: u8 = 0x10;
+
+/// Commands after the software reset, each with its data: driver output
+/// for 200 gate lines, data entry mode X and Y increasing, the RAM window
+/// of the whole panel, border waveform and the internal temperature sensor.
+pub const INIT_COMMANDS: [(u8, &[u8]); 6] = [
+    (0x01, &[(SIZE - 1) as u8, 0x00, 0x00]),
+    (0x11, &[0x03]),
+    (0x44, &[0x00, (SIZE / 8 - 1) as u8]),
+    (0x45, &[0x00, 0x00, (SIZE - 1) as u8, 0x00]),
+    (0x3C, &[0x05]),
+    (0x18, &[0x80]),
+];
+
+/// Kind of a display update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Refresh {
+    /// Flashes the panel and clears the ghosting, about 2 s.
+    Full,
+    /// Only drives the changed pixels, about 0.3 s.
+    Partial,
+}
+
+impl Refresh {
+    /// Data of [`UPDATE_CONTROL_2`]: clock and analog on, load the
+    /// temperature and the waveform, display, analog and clock off.
+    pub const fn update_sequence(self) -> u8 {
+        match self {
+            Self::Full => 0xF7,
+            Self::Partial => 0xFF,
+        }
+    }
+}
+
+/// Chooses between full and partial refreshes.
+#[derive(Debug, Clone)]
+pub struct RefreshPolicy {
+    max_partial: u8,
+    partial: Option<u8>,
+}
+
+impl RefreshPolicy {
+    /// A full refresh after `max_partial` partial ones, 0 for full refreshes
+    /// only. The first refresh is always a full one.
+    pub const fn new(max_partial: u8) -> Self {
+        Self {
+            max_partial,
+            partial: None,
+        }
+    }
+
+    /// Force a full refresh next, e.g. after a wake up from deep sleep.
+    pub fn reset(&mut self) {
+        self.partial = None;
+    }
+
+    /// Kind of the next refresh.
+    pub fn choose(&mut self) -> Refresh {
+        match self.partial {
+            Some(count) if count < self.max_partial => {
+                self.partial = Some(count + 1);
+                Refresh::Partial
+            }
+            _ => {
+                self.partial = Some(0);
+                Refresh::Full
+            }
+        }
+    }
+}
+
+impl Default for RefreshPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PARTIAL)
+    }
+}
+
+/// Frame buffer in the RAM layout of the SSD1681.
+#[derive(Debug, Clone)]
+pub struct FrameBuffer {
+    buf: [u8; BUFFER_LEN],
+    rotation: Rotation,
+}
+
+impl FrameBuffer {
+    /// A white frame.
+    pub const fn new(rotation: Rotation) -> Self {
+        Self {
+            buf: [0xFF; BUFFER_LEN],
+            rotation,
+        }
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Rotate what is drawn from now on.
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+    }
+
+    /// Fill the frame with white.
+    pub fn clear(&mut self) {
+        self.buf = [0xFF; BUFFER_LEN];
+    }
+
+    /// Set the pixel at `x`, `y` of the rotated content to black if `on`,
+    /// ignored outside.
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        if x >= SIZE || y >= SIZE {
+            return;
+        }
+        let last = SIZE - 1;
+        let (x, y) = match self.rotation {
+            Rotation::Rotate0 => (x, y),
+            Rotation::Rotate90 => (last - y, x),
+            Rotation::Rotate180 => (last - x, last - y),
+            Rotation::Rotate270 => (y, last - x),
+        };
+        let byte = &mut self.buf[y * SIZE / 8 + x / 8];
+        let bit = 0x80 >> (x % 8);
+        if on {
+            *byte &= !bit;
+        } else {
+            *byte |= bit;
+        }
+    }
+
+    /// The whole frame, to be written after [`WRITE_BLACK_RAM`].
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_layout_and_refresh_policy() {
+        let mut frame = FrameBuffer::new(Rotation::Rotate0);
+        frame.set_pixel(0, 0, true);
+        frame.set_pixel(9, 1, true);
+        frame.set_pixel(SIZE, 0, true);
+        assert_eq!(frame.bytes()[0], 0x7F);
+        assert_eq!(frame.bytes()[SIZE / 8 + 1], 0xBF);
+        frame.set_rotation(Rotation::Rotate90);
+        // Top left of the content is the top right of the panel.
+        frame.set_pixel(0, 0, true);
+        assert_eq!(frame.bytes()[SIZE / 8 - 1], 0xFE);
+        frame.set_rotation(Rotation::Rotate0);
+        frame.set_pixel(0, 0, false);
+        assert_eq!(frame.bytes()[0], 0xFF);
+        frame.clear();
+        assert!(frame.bytes().iter().all(|b| *b == 0xFF));
+
+        let mut policy = RefreshPolicy::new(2);
+        assert_eq!(policy.choose(), Refresh::Full);
+        assert_eq!(policy.choose(), Refresh::Partial);
+        assert_eq!(policy.choose(), Refresh::Partial);
+        assert_eq!(policy.choose(), Refresh::Full);
+        assert_eq!(policy.choose(), Refresh::Partial);
+        policy.reset();
+        assert_eq!(policy.choose(), Refresh::Full);
+        let mut full_only = RefreshPolicy::new(0);
+        assert_eq!(full_only.choose(), Refresh::Full);
+        assert_eq!(full_only.choose(), Refresh::Full);
+    }
+}
@@ -0,0 +1,287 @@
+//! Slots of a shared epoch for time-synchronized beaconing.
+//!
+//! Every unit of a group owns one slot of a repeating epoch and advertises
+//! only in it, so the units don't collide and a receiver knows whom it
+//! should have heard in an epoch, see [`Roster`]. Epochs are counted in UTC
+//! µs, taken from the GNSS pulse per second where available, or learned
+//! from the [`SlotBeacon`]s of a unit that has it, see [`BleSync`].
+
+use core::fmt::{self, Write};
+
+/// Largest number of units, a [`Roster`] is a bit mask.
+pub const MAX_UNITS: u8 = 32;
+
+/// Length of an encoded [`SlotBeacon`].
+pub const SLOT_BEACON_LEN: usize = 7;
+
+/// First byte of an encoded [`SlotBeacon`].
+const MAGIC: u8 = 0xE5;
+
+/// BLE sync older than this is not used anymore.
+pub const BLE_HOLDOVER_US: u64 = 60 * 1_000_000;
+
+/// Division of the epoch into the slots of the units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Schedule {
+    /// Length of an epoch.
+    pub epoch_us: u32,
+    /// Number of units, one slot each.
+    pub units: u8,
+    /// Time at the end of every slot without advertising, for the clock
+    /// error between the units.
+    pub guard_us: u32,
+}
+
+impl Schedule {
+    /// Schedule of `units` slots per epoch, at most [`MAX_UNITS`].
+    pub const fn new(epoch_us: u32, units: u8, guard_us: u32) -> Self {
+        assert!(units > 0 && units <= MAX_UNITS);
+        Self {
+            epoch_us,
+            units,
+            guard_us,
+        }
+    }
+
+    /// Length of a slot.
+    pub const fn slot_us(&self) -> u32 {
+        self.epoch_us / self.units as u32
+    }
+
+    /// Time a unit advertises at the start of its slot.
+    pub const fn active_us(&self) -> u32 {
+        self.slot_us().saturating_sub(self.guard_us)
+    }
+
+    /// Epoch at UTC `utc_us`.
+    pub fn epoch(&self, utc_us: i64) -> u32 {
+        utc_us.div_euclid(self.epoch_us as i64) as u32
+    }
+
+    /// UTC at which the slot of `unit` in `epoch` starts.
+    pub fn slot_start(&self, epoch: u32, unit: u8) -> i64 {
+        epoch as i64 * self.epoch_us as i64 + unit as i64 * self.slot_us() as i64
+    }
+
+    /// Epoch and start of the first slot of `unit` starting at or after
+    /// `utc_us`.
+    pub fn next_slot(&self, utc_us: i64, unit: u8) -> (u32, i64) {
+        let epoch = self.epoch(utc_us);
+        let start = self.slot_start(epoch, unit);
+        if start >= utc_us {
+            (epoch, start)
+        } else {
+            (
+                epoch.wrapping_add(1),
+                self.slot_start(epoch.wrapping_add(1), unit),
+            )
+        }
+    }
+}
+
+/// Where the time of a unit comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Source {
+    /// The GNSS pulse per second.
+    Gnss = 1,
+    /// The beacons of a unit with [`Source::Gnss`].
+    Ble = 2,
+}
+
+/// Content of the beacon a unit sends in its slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SlotBeacon {
+    pub unit: u8,
+    pub epoch: u32,
+    pub source: Source,
+}
+
+impl SlotBeacon {
+    /// Magic, unit, source and epoch (little endian).
+    pub fn encode(&self) -> [u8; SLOT_BEACON_LEN] {
+        let mut buf = [0; SLOT_BEACON_LEN];
+        buf[0] = MAGIC;
+        buf[1] = self.unit;
+        buf[2] = self.source as u8;
+        buf[3..].copy_from_slice(&self.epoch.to_le_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let buf: &[u8; SLOT_BEACON_LEN] = buf.try_into().ok()?;
+        if buf[0] != MAGIC {
+            return None;
+        }
+        let source = match buf[2] {
+            1 => Source::Gnss,
+            2 => Source::Ble,
+            _ => return None,
+        };
+        Some(Self {
+            unit: buf[1],
+            epoch: u32::from_le_bytes(buf[3..].try_into().ok()?),
+            source,
+        })
+    }
+}
+
+/// UTC learned from the slot beacons of a unit with [`Source::Gnss`].
+///
+/// Only beacons of such units are taken, so errors don't add up along a
+/// chain of units. A unit sends several advertising events in its slot,
+/// the earliest one received in an epoch is closest to the slot start.
+/// The accuracy is that of the first advertising event, a few ms with the
+/// random advertising delay, so the guard time has to cover it.
+#[derive(Debug, Default)]
+pub struct BleSync {
+    /// UTC minus local time.
+    offset_us: Option<i64>,
+    epoch: u32,
+    last_us: u64,
+}
+
+impl BleSync {
+    pub const fn new() -> Self {
+        Self {
+            offset_us: None,
+            epoch: 0,
+            last_us: 0,
+        }
+    }
+
+    /// `beacon` was received at `local_us`. Returns whether it was taken.
+    pub fn observe(&mut self, schedule: &Schedule, beacon: &SlotBeacon, local_us: u64) -> bool {
+        if beacon.source != Source::Gnss || beacon.unit >= schedule.units {
+            return false;
+        }
+        let offset = schedule.slot_start(beacon.epoch, beacon.unit) - local_us as i64;
+        self.offset_us = match self.offset_us {
+            // A later advertising event of the same slot.
+            Some(current) if beacon.epoch == self.epoch => Some(current.max(offset)),
+            _ => Some(offset),
+        };
+        self.epoch = beacon.epoch;
+        self.last_us = local_us;
+        true
+    }
+
+    /// UTC in µs at `local_us`, `None` without recent beacons.
+    pub fn utc_us(&self, local_us: u64) -> Option<i64> {
+        if local_us.saturating_sub(self.last_us) > BLE_HOLDOVER_US {
+            return None;
+        }
+        Some(local_us as i64 + self.offset_us?)
+    }
+}
+
+/// The units a receiver heard in the current epoch.
+#[derive(Debug)]
+pub struct Roster {
+    units: u8,
+    epoch: Option<u32>,
+    seen: u32,
+}
+
+impl Roster {
+    pub const fn new(schedule: &Schedule) -> Self {
+        Self {
+            units: schedule.units,
+            epoch: None,
+            seen: 0,
+        }
+    }
+
+    fn all(&self) -> u32 {
+        u32::MAX >> (32 - self.units as u32)
+    }
+
+    /// `unit` was heard in `epoch`. Returns the previous epoch and the
+    /// units missing in it (bit n for unit n) once a later epoch starts.
+    ///
+    /// Epochs in which no unit was heard at all are not reported.
+    pub fn observe(&mut self, unit: u8, epoch: u32) -> Option<(u32, u32)> {
+        if unit >= self.units {
+            return None;
+        }
+        let bit = 1 << unit;
+        match self.epoch {
+            Some(current) if epoch == current => {
+                self.seen |= bit;
+                None
+            }
+            Some(current) if epoch > current => {
+                let missing = self.all() & !self.seen;
+                self.epoch = Some(epoch);
+                self.seen = bit;
+                Some((current, missing))
+            }
+            // A late beacon of an earlier epoch.
+            Some(_) => None,
+            None => {
+                self.epoch = Some(epoch);
+                self.seen = bit;
+                None
+            }
+        }
+    }
+}
+
+/// Write the units `missing` in `epoch` as one line of JSON.
+pub fn write_missing(out: &mut impl Write, epoch: u32, missing: u32) -> fmt::Result {
+    write!(
+        out,
+        "{{\"type\":\"missing\",\"epoch\":{},\"units\":[",
+        epoch
+    )?;
+    let mut first = true;
+    for unit in (0..MAX_UNITS).filter(|unit| missing & 1 << unit != 0) {
+        if !first {
+            out.write_char(',')?;
+        }
+        write!(out, "{}", unit)?;
+        first = false;
+    }
+    out.write_str("]}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_sync_and_roster() {
+        let schedule = Schedule::new(1_000_000, 4, 50_000);
+        assert_eq!(schedule.slot_us(), 250_000);
+        assert_eq!(schedule.next_slot(3_100_000, 2), (3, 3_500_000));
+        assert_eq!(schedule.next_slot(3_600_000, 2), (4, 4_500_000));
+
+        let beacon = SlotBeacon {
+            unit: 1,
+            epoch: 7,
+            source: Source::Gnss,
+        };
+        assert_eq!(SlotBeacon::decode(&beacon.encode()), Some(beacon));
+        let mut sync = BleSync::new();
+        assert!(sync.observe(&schedule, &beacon, 1_003_000));
+        // A later advertising event of the same slot doesn't move the clock.
+        assert!(sync.observe(&schedule, &beacon, 1_025_000));
+        assert_eq!(sync.utc_us(1_003_000), Some(7_250_000));
+        let relayed = SlotBeacon {
+            source: Source::Ble,
+            ..beacon
+        };
+        assert!(!sync.observe(&schedule, &relayed, 1_010_000));
+
+        let mut roster = Roster::new(&schedule);
+        assert_eq!(roster.observe(0, 7), None);
+        assert_eq!(roster.observe(2, 7), None);
+        assert_eq!(roster.observe(1, 8), Some((7, 0b1010)));
+        let mut line = String::new();
+        write_missing(&mut line, 7, 0b1010).unwrap();
+        assert_eq!(line, "{\"type\":\"missing\",\"epoch\":7,\"units\":[1,3]}\n");
+    }
+}
@@ -0,0 +1,269 @@
+//! Frequency hopping point-to-point link with acknowledgements.
+//!
+//! Both ends derive the same [`HopTable`] from a shared key, a permutation
+//! of the 37 BLE data channel frequencies, and follow it by the hop index
+//! carried in every [`Frame`]. The [`Sender`] retransmits a data frame
+//! until it is acknowledged or [`MAX_ATTEMPTS`] are used up, the
+//! [`Receiver`] drops duplicates by their sequence number (stop and wait).
+//!
+//! The hop table hides nothing, the key only keeps two links apart.
+
+use heapless::Vec;
+
+use crate::crc::crc32;
+
+/// Number of channels, the BLE data channels.
+pub const CHANNELS: usize = 37;
+
+/// Longest payload of a data frame.
+pub const MAX_PAYLOAD: usize = 32;
+
+/// Length of the frame header.
+pub const HEADER_LEN: usize = 4;
+
+/// Longest encoded frame.
+pub const MAX_FRAME_LEN: usize = HEADER_LEN + MAX_PAYLOAD;
+
+/// Transmissions of a data frame before it is dropped.
+pub const MAX_ATTEMPTS: u8 = 8;
+
+/// Order in which the channels are used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopTable {
+    /// Frequencies in MHz above 2400.
+    channels: [u8; CHANNELS],
+}
+
+impl HopTable {
+    /// Shuffle the channels with a generator seeded from `key`.
+    pub fn new(key: &[u8; 16]) -> Self {
+        let mut channels: [u8; CHANNELS] = core::array::from_fn(|i| data_channel_mhz(i as u8));
+        let mut state = (crc32(&key[..8]) as u64) << 32 | crc32(&key[8..]) as u64;
+        for i in (1..CHANNELS).rev() {
+            let j = (splitmix64(&mut state) % (i as u64 + 1)) as usize;
+            channels.swap(i, j);
+        }
+        Self { channels }
+    }
+
+    /// Frequency in MHz above 2400 of hop `index`.
+    pub fn frequency(&self, index: u16) -> u8 {
+        self.channels[index as usize % CHANNELS]
+    }
+}
+
+/// Frequency of BLE data channel `channel`, skipping advertising channel 38.
+fn data_channel_mhz(channel: u8) -> u8 {
+    if channel <= 10 {
+        4 + 2 * channel
+    } else {
+        28 + 2 * (channel - 11)
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Frame types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Kind {
+    Data = 1,
+    Ack = 2,
+}
+
+/// A frame of the link, `kind | sequence number | hop index (2, little endian) | payload`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub kind: Kind,
+    pub seq: u8,
+    /// Hop index the frame was sent on.
+    pub hop: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    /// Encode into `buf`, returning the length.
+    pub fn encode(&self, buf: &mut [u8; MAX_FRAME_LEN]) -> usize {
+        let len = HEADER_LEN + self.payload.len().min(MAX_PAYLOAD);
+        buf[0] = self.kind as u8;
+        buf[1] = self.seq;
+        buf[2..4].copy_from_slice(&self.hop.to_le_bytes());
+        buf[HEADER_LEN..len].copy_from_slice(&self.payload[..len - HEADER_LEN]);
+        len
+    }
+
+    pub fn decode(data: &'a [u8]) -> Option<Self> {
+        if data.len() < HEADER_LEN || data.len() > MAX_FRAME_LEN {
+            return None;
+        }
+        let kind = match data[0] {
+            1 => Kind::Data,
+            2 => Kind::Ack,
+            _ => return None,
+        };
+        Some(Self {
+            kind,
+            seq: data[1],
+            hop: u16::from_le_bytes([data[2], data[3]]),
+            payload: &data[HEADER_LEN..],
+        })
+    }
+}
+
+/// What became of a payload given to the [`Sender`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Outcome {
+    Delivered { attempts: u8 },
+    Dropped,
+}
+
+/// Transmitting end, one payload in flight.
+#[derive(Debug, Default)]
+pub struct Sender {
+    seq: u8,
+    pending: Option<Vec<u8, MAX_PAYLOAD>>,
+    attempts: u8,
+}
+
+impl Sender {
+    pub const fn new() -> Self {
+        Self {
+            seq: 0,
+            pending: None,
+            attempts: 0,
+        }
+    }
+
+    /// Whether a new payload can be loaded.
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_none()
+    }
+
+    /// Send `payload` next, cut to [`MAX_PAYLOAD`].
+    pub fn load(&mut self, payload: &[u8]) {
+        let payload = &payload[..payload.len().min(MAX_PAYLOAD)];
+        self.seq = self.seq.wrapping_add(1);
+        self.pending = Vec::from_slice(payload).ok();
+        self.attempts = 0;
+    }
+
+    /// Encode the next transmission of the pending payload on hop `hop`,
+    /// `None` when idle.
+    pub fn transmit(&mut self, hop: u16, buf: &mut [u8; MAX_FRAME_LEN]) -> Option<usize> {
+        let payload = self.pending.as_ref()?;
+        self.attempts += 1;
+        let frame = Frame {
+            kind: Kind::Data,
+            seq: self.seq,
+            hop,
+            payload,
+        };
+        Some(frame.encode(buf))
+    }
+
+    /// Take the answer to the last transmission, `None` for no answer.
+    ///
+    /// Returns the outcome once the payload was acknowledged or dropped.
+    pub fn answer(&mut self, ack: Option<&Frame>) -> Option<Outcome> {
+        self.pending.as_ref()?;
+        let outcome = match ack {
+            Some(ack) if ack.kind == Kind::Ack && ack.seq == self.seq => Outcome::Delivered {
+                attempts: self.attempts,
+            },
+            _ if self.attempts >= MAX_ATTEMPTS => Outcome::Dropped,
+            _ => return None,
+        };
+        self.pending = None;
+        Some(outcome)
+    }
+}
+
+/// Receiving end.
+#[derive(Debug, Default)]
+pub struct Receiver {
+    last_seq: Option<u8>,
+}
+
+impl Receiver {
+    pub const fn new() -> Self {
+        Self { last_seq: None }
+    }
+
+    /// Take a data frame, returning whether it is new (not a
+    /// retransmission of an acknowledged one). Every data frame is
+    /// acknowledged, the ack may have been lost.
+    pub fn receive(&mut self, frame: &Frame) -> bool {
+        let new = self.last_seq != Some(frame.seq);
+        self.last_seq = Some(frame.seq);
+        new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hop_table_is_keyed_permutation() {
+        let table = HopTable::new(&[1; 16]);
+        let mut seen = [false; 81];
+        for hop in 0..CHANNELS as u16 {
+            let mhz = table.frequency(hop);
+            assert!((4..=80).contains(&mhz) && mhz != 26 && mhz.is_multiple_of(2));
+            assert!(!seen[mhz as usize]);
+            seen[mhz as usize] = true;
+        }
+        assert_eq!(table.frequency(CHANNELS as u16), table.frequency(0));
+        assert_eq!(table, HopTable::new(&[1; 16]));
+        assert_ne!(table, HopTable::new(&[2; 16]));
+    }
+
+    #[test]
+    fn retransmits_until_acknowledged() {
+        let mut buf = [0; MAX_FRAME_LEN];
+        let mut sender = Sender::new();
+        let mut receiver = Receiver::new();
+        assert_eq!(sender.transmit(0, &mut buf), None);
+        sender.load(b"fix");
+
+        // The ack of the first transmission is lost.
+        let len = sender.transmit(0, &mut buf).unwrap();
+        let data = Frame::decode(&buf[..len]).unwrap();
+        assert_eq!((data.kind, data.payload), (Kind::Data, &b"fix"[..]));
+        assert!(receiver.receive(&data));
+        assert_eq!(sender.answer(None), None);
+
+        let len = sender.transmit(1, &mut buf).unwrap();
+        let data = Frame::decode(&buf[..len]).unwrap();
+        assert_eq!(data.hop, 1);
+        assert!(!receiver.receive(&data));
+        let ack = Frame {
+            kind: Kind::Ack,
+            seq: data.seq,
+            hop: data.hop,
+            payload: &[],
+        };
+        assert_eq!(
+            sender.answer(Some(&ack)),
+            Some(Outcome::Delivered { attempts: 2 })
+        );
+        assert!(sender.is_idle());
+
+        sender.load(b"next");
+        for hop in 0..MAX_ATTEMPTS as u16 {
+            sender.transmit(hop, &mut buf).unwrap();
+            // A stale ack doesn't count.
+            let outcome = sender.answer(Some(&ack));
+            assert_eq!(outcome.is_some(), hop + 1 == MAX_ATTEMPTS as u16);
+        }
+        assert!(sender.is_idle());
+        assert_eq!(Frame::decode(&[3, 0, 0, 0]), None);
+    }
+}
@@ -0,0 +1,127 @@
+//! Advertisement formats of item-finder networks.
+//!
+//! - Offline finding (the format of OpenHaystack): the device advertises a
+//!   28 byte P-224 public key, split between the random static address and
+//!   Apple manufacturer specific data. Finder devices nearby encrypt their
+//!   location with the key and upload it, only the owner of the private
+//!   key can decrypt the reports.
+//! - Exposure notification: a rolling proximity identifier and encrypted
+//!   metadata as service data of the 0xFD6F service, rotating every
+//!   [`EN_INTERVAL_SECS`]. The AES operations are done by the firmware.
+
+/// Length of an offline finding public key (P-224 x coordinate).
+pub const OFFLINE_FINDING_KEY_LEN: usize = 28;
+
+/// Apple company identifier.
+pub const APPLE_COMPANY_ID: u16 = 0x004C;
+
+/// Offline finding type and length after the company identifier.
+const OFFLINE_FINDING_TYPE: [u8; 2] = [0x12, 0x19];
+
+/// Length of the offline finding manufacturer specific payload.
+pub const OFFLINE_FINDING_PAYLOAD_LEN: usize = 27;
+
+/// 16 bit UUID of the exposure notification service, little endian.
+pub const EN_UUID: [u8; 2] = [0x6F, 0xFD];
+
+/// Seconds of an exposure notification interval, the identifier rotates
+/// with every interval.
+pub const EN_INTERVAL_SECS: i64 = 600;
+
+/// Length of a rolling proximity identifier.
+pub const RPI_LEN: usize = 16;
+
+/// Length of the associated encrypted metadata.
+pub const AEM_LEN: usize = 4;
+
+/// Exposure notification version 1.0 in the metadata.
+const EN_VERSION: u8 = 0b0100_0000;
+
+/// Address and advertised payload of an offline finding key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OfflineFinding {
+    /// Random static address, least significant byte first.
+    pub address: [u8; 6],
+    /// Payload of the manufacturer specific data with [`APPLE_COMPANY_ID`].
+    pub payload: [u8; OFFLINE_FINDING_PAYLOAD_LEN],
+}
+
+impl OfflineFinding {
+    /// Advertisement of the public `key`.
+    ///
+    /// `status` is the battery state in the top two bits (0: full to
+    /// 3: critical).
+    pub fn new(key: &[u8; OFFLINE_FINDING_KEY_LEN], status: u8) -> Self {
+        let mut address = [0; 6];
+        address.copy_from_slice(&key[..6]);
+        // A random static address has the two most significant bits set.
+        address[0] |= 0xC0;
+        address.reverse();
+
+        let mut payload = [0; OFFLINE_FINDING_PAYLOAD_LEN];
+        payload[..2].copy_from_slice(&OFFLINE_FINDING_TYPE);
+        payload[2] = status;
+        payload[3..25].copy_from_slice(&key[6..]);
+        // The bits overwritten in the address.
+        payload[25] = key[0] >> 6;
+        payload[26] = 0; // hint
+        Self { address, payload }
+    }
+}
+
+/// Exposure notification interval number of the Unix time `secs`.
+pub fn en_interval(secs: i64) -> u32 {
+    (secs / EN_INTERVAL_SECS) as u32
+}
+
+/// Plaintext of the rolling proximity identifier of `interval`, to be
+/// encrypted with the RPI key.
+pub fn rpi_block(interval: u32) -> [u8; RPI_LEN] {
+    let mut block = [0; RPI_LEN];
+    block[..6].copy_from_slice(b"EN-RPI");
+    block[12..].copy_from_slice(&interval.to_le_bytes());
+    block
+}
+
+/// Plaintext metadata: version and TX power in dBm.
+pub fn en_metadata(tx_power: i8) -> [u8; AEM_LEN] {
+    [EN_VERSION, tx_power as u8, 0, 0]
+}
+
+/// Service data of the exposure notification service (after the UUID).
+pub fn en_service_data(rpi: &[u8; RPI_LEN], aem: &[u8; AEM_LEN]) -> [u8; RPI_LEN + AEM_LEN] {
+    let mut data = [0; RPI_LEN + AEM_LEN];
+    data[..RPI_LEN].copy_from_slice(rpi);
+    data[RPI_LEN..].copy_from_slice(aem);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offline_finding_splits_the_key() {
+        let mut key = [0; OFFLINE_FINDING_KEY_LEN];
+        key.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        key[0] = 0x5A;
+        let adv = OfflineFinding::new(&key, 0x40);
+        assert_eq!(adv.address, [5, 4, 3, 2, 1, 0xDA]);
+        assert_eq!(adv.payload[..3], [0x12, 0x19, 0x40]);
+        assert_eq!(adv.payload[3..25], key[6..]);
+        assert_eq!(adv.payload[25], 0b01);
+        // Manufacturer specific data fills a legacy advertisement.
+        assert_eq!(2 + 2 + adv.payload.len(), 31);
+    }
+
+    #[test]
+    fn exposure_notification_interval() {
+        // 2020-04-10T00:00:00Z, the example of the specification.
+        assert_eq!(en_interval(1_586_476_800), 2_644_128);
+        let block = rpi_block(2_644_128);
+        assert_eq!(&block[..6], b"EN-RPI");
+        assert_eq!(block[6..12], [0; 6]);
+        assert_eq!(block[12..], 2_644_128u32.to_le_bytes());
+        assert_eq!(en_metadata(-8), [0x40, 0xF8, 0, 0]);
+    }
+}
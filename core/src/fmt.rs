@@ -0,0 +1,92 @@
+//! Fixed-size text of values shown on the OLED and in read-only GATT
+//! characteristics.
+//!
+//! Every function returns a `heapless::String` with the capacity of the
+//! longest value, so nothing is truncated:
+//! - [`mac`], e.g. `FF:E4:05:1A:8F:FF`
+//! - [`version`], e.g. `1.2.3`
+//! - [`coordinates`], e.g. `53.36134N 6.50562W`, see [`format`]
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::nav::format;
+
+/// Longest [`mac`] text.
+pub const MAC_LEN: usize = 17;
+
+/// Longest [`version`] text.
+pub const VERSION_LEN: usize = 17;
+
+/// Longest [`coordinates`] text, `90.00000S 180.00000W`.
+pub const COORDINATES_LEN: usize = 20;
+
+/// BLE address, given least significant byte first (as on air), written most
+/// significant byte first.
+pub fn mac(address: &[u8; 6]) -> String<MAC_LEN> {
+    let mut text = String::new();
+    for (i, byte) in address.iter().rev().enumerate() {
+        let separator = if i == 0 { "" } else { ":" };
+        // The capacity fits all six bytes.
+        let _ = write!(text, "{}{:02X}", separator, byte);
+    }
+    text
+}
+
+/// Parse a BLE address written as by [`mac`], returning it least
+/// significant byte first.
+pub fn parse_mac(text: &str) -> Option<[u8; 6]> {
+    let mut address = [0; 6];
+    let mut bytes = text.split(':');
+    for byte in address.iter_mut().rev() {
+        let hex = bytes.next()?;
+        // `from_str_radix` would accept a sign.
+        if hex.len() != 2 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        *byte = u8::from_str_radix(hex, 16).ok()?;
+    }
+    bytes.next().is_none().then_some(address)
+}
+
+/// Version as `major.minor.patch`.
+pub fn version(major: u16, minor: u16, patch: u16) -> String<VERSION_LEN> {
+    let mut text = String::new();
+    let _ = write!(text, "{}.{}.{}", major, minor, patch);
+    text
+}
+
+/// Position in decimal degrees, empty if a coordinate is out of range.
+pub fn coordinates(latitude: f64, longitude: f64) -> String<COORDINATES_LEN> {
+    let mut text = String::new();
+    if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) {
+        let _ = format::decimal_degrees(&mut text, latitude, longitude);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_size_text() {
+        assert_eq!(
+            mac(&[0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff]),
+            "FF:E4:05:1A:8F:FF"
+        );
+        assert_eq!(
+            parse_mac("FF:E4:05:1a:8f:FF"),
+            Some([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff])
+        );
+        assert_eq!(parse_mac("FF:E4:05:1A:8F"), None);
+        assert_eq!(parse_mac("FF:E4:05:1A:8F:FF:00"), None);
+        assert_eq!(parse_mac("FF:E4:05:1A:8F:+F"), None);
+        assert_eq!(version(0, 1, 0), "0.1.0");
+        assert_eq!(version(u16::MAX, u16::MAX, u16::MAX).len(), VERSION_LEN);
+        assert_eq!(coordinates(53.361_337, -6.505_62), "53.36134N 6.50562W");
+        assert_eq!(coordinates(-90.0, -180.0).len(), COORDINATES_LEN);
+        assert_eq!(coordinates(f64::NAN, 0.0), "");
+    }
+}
@@ -0,0 +1,182 @@
+//! Decoding of the advertisements received by the `ble_gateway` and their
+//! output as newline delimited JSON records.
+
+use core::fmt::{self, Write};
+
+use crate::fmt::mac;
+
+/// AD type of service data with a 16 bit UUID.
+const AD_SERVICE_DATA_16: u8 = 0x16;
+
+/// AD type of manufacturer specific data.
+const AD_MANUFACTURER_DATA: u8 = 0xFF;
+
+/// 16 bit UUID of the Eddystone service, little endian.
+const EDDYSTONE_UUID: [u8; 2] = [0xAA, 0xFE];
+
+/// Eddystone frame types.
+const FRAME_TYPE_TLM: u8 = 0x20;
+const FRAME_TYPE_EID: u8 = 0x30;
+
+/// Length of an Eddystone-TLM frame, see [`metrics`](crate::metrics).
+const TLM_LEN: usize = 14;
+
+/// Length of an Eddystone-EID frame: type, TX power, exponent and EID.
+const EID_FRAME_LEN: usize = 11;
+
+/// Length of the plaintext of an encrypted beacon of `ble_beacon`.
+pub const BEACON_PLAINTEXT_LEN: usize = 8;
+
+/// The AD structures of advertising data as `(type, data)`, up to the
+/// first malformed one.
+pub fn ad_structures(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    core::iter::from_fn(move || {
+        let (&len, rest) = data.split_first()?;
+        let len = len as usize;
+        if len == 0 || len > rest.len() {
+            return None;
+        }
+        let (structure, rest) = rest.split_at(len);
+        data = rest;
+        Some((structure[0], &structure[1..]))
+    })
+}
+
+/// The Eddystone frame in advertising data.
+pub fn eddystone_frame(data: &[u8]) -> Option<&[u8]> {
+    ad_structures(data)
+        .find(|(ty, value)| *ty == AD_SERVICE_DATA_16 && value.starts_with(&EDDYSTONE_UUID))
+        .map(|(_, value)| &value[EDDYSTONE_UUID.len()..])
+}
+
+/// The manufacturer specific data of `company_id` in advertising data,
+/// without the company identifier.
+pub fn manufacturer_data(data: &[u8], company_id: u16) -> Option<&[u8]> {
+    ad_structures(data)
+        .find(|(ty, value)| {
+            *ty == AD_MANUFACTURER_DATA && value.starts_with(&company_id.to_le_bytes())
+        })
+        .map(|(_, value)| &value[2..])
+}
+
+/// A decoded advertisement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Record {
+    /// Eddystone-TLM frame.
+    Tlm {
+        battery_mv: u16,
+        advertisements: u32,
+        uptime_secs: u32,
+    },
+    /// Eddystone-EID frame, the identifier is resolved by the receiver.
+    Eid { exponent: u8, eid: [u8; 8] },
+    /// Decrypted beacon of `ble_beacon`.
+    Beacon { count: u32, elapsed_ms: u32 },
+    /// Slot beacon of a time-synchronized unit, see [`crate::epoch`].
+    Slot { unit: u8, epoch: u32 },
+}
+
+impl Record {
+    /// Decode an Eddystone-TLM or -EID frame.
+    pub fn from_eddystone(frame: &[u8]) -> Option<Self> {
+        let be32 =
+            |i: usize| u32::from_be_bytes([frame[i], frame[i + 1], frame[i + 2], frame[i + 3]]);
+        match (frame.first()?, frame.len()) {
+            (&FRAME_TYPE_TLM, TLM_LEN) => Some(Record::Tlm {
+                battery_mv: u16::from_be_bytes([frame[2], frame[3]]),
+                advertisements: be32(6),
+                uptime_secs: be32(10) / 10,
+            }),
+            (&FRAME_TYPE_EID, EID_FRAME_LEN) => Some(Record::Eid {
+                exponent: frame[2],
+                eid: frame[3..].try_into().ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Decode the plaintext of an encrypted beacon.
+    pub fn from_beacon(plaintext: &[u8]) -> Option<Self> {
+        let plaintext: &[u8; BEACON_PLAINTEXT_LEN] = plaintext.try_into().ok()?;
+        let (count, elapsed_ms) = plaintext.split_at(4);
+        Some(Record::Beacon {
+            count: u32::from_be_bytes(count.try_into().ok()?),
+            elapsed_ms: u32::from_be_bytes(elapsed_ms.try_into().ok()?),
+        })
+    }
+
+    /// Write the record received from `address` (least significant byte
+    /// first) as one line of JSON.
+    pub fn write_line(&self, out: &mut impl Write, address: &[u8; 6], rssi: i8) -> fmt::Result {
+        write!(out, "{{\"addr\":\"{}\",\"rssi\":{},", mac(address), rssi)?;
+        match self {
+            Record::Tlm {
+                battery_mv,
+                advertisements,
+                uptime_secs,
+            } => write!(
+                out,
+                "\"type\":\"tlm\",\"battery_mv\":{},\"advertisements\":{},\"uptime_s\":{}",
+                battery_mv, advertisements, uptime_secs
+            )?,
+            Record::Eid { exponent, eid } => {
+                write!(out, "\"type\":\"eid\",\"exponent\":{},\"eid\":\"", exponent)?;
+                for byte in eid {
+                    write!(out, "{:02x}", byte)?;
+                }
+                out.write_char('"')?;
+            }
+            Record::Beacon { count, elapsed_ms } => write!(
+                out,
+                "\"type\":\"beacon\",\"count\":{},\"elapsed_ms\":{}",
+                count, elapsed_ms
+            )?,
+            Record::Slot { unit, epoch } => write!(
+                out,
+                "\"type\":\"slot\",\"unit\":{},\"epoch\":{}",
+                unit, epoch
+            )?,
+        }
+        out.write_str("}\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Snapshot;
+
+    #[test]
+    fn decode_and_write_records() {
+        let tlm = Snapshot {
+            uptime_secs: 3600,
+            advertisements: 7,
+            battery_mv: 3700,
+            ..Default::default()
+        }
+        .tlm_frame();
+        let mut data = vec![2, 0x01, 0x06, 3 + TLM_LEN as u8, 0x16, 0xAA, 0xFE];
+        data.extend_from_slice(&tlm);
+        let record = Record::from_eddystone(eddystone_frame(&data).unwrap()).unwrap();
+        let mut line = String::new();
+        record
+            .write_line(&mut line, &[0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff], -60)
+            .unwrap();
+        assert_eq!(
+            line,
+            "{\"addr\":\"FF:E4:05:1A:8F:FF\",\"rssi\":-60,\"type\":\"tlm\",\
+             \"battery_mv\":3700,\"advertisements\":7,\"uptime_s\":3600}\n"
+        );
+
+        let data = [5, 0xFF, 0xFF, 0xFF, 1, 2, 3, 0x01];
+        assert_eq!(manufacturer_data(&data, 0xFFFF), Some(&[1, 2][..]));
+        assert_eq!(
+            Record::from_beacon(&[0, 0, 0, 1, 0, 0, 1, 0]),
+            Some(Record::Beacon {
+                count: 1,
+                elapsed_ms: 256
+            })
+        );
+    }
+}
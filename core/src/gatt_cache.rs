@@ -0,0 +1,49 @@
+//! GATT caching across firmware updates.
+//!
+//! Clients cache the attribute handles of a server, bonded ones across
+//! connections. After an update that changed the services they would use
+//! stale handles, unless the server indicates Service Changed with the
+//! affected handle range (Core specification, Vol 3, Part G, 7.1) or they
+//! see a different Database Hash (7.3).
+//!
+//! The firmware can't walk the attribute table the GATT server macro
+//! builds, so the hash covers a layout revision, bumped with every change
+//! of the services, instead of the AES-CMAC over the attributes of the
+//! specification. Clients only compare it.
+
+use crate::sha256::sha256;
+
+/// Length of the Database Hash value.
+pub const DATABASE_HASH_LEN: usize = 16;
+
+/// Service Changed value of the handles `start` to `end`.
+pub const fn service_changed(start: u16, end: u16) -> [u8; 4] {
+    let [s0, s1] = start.to_le_bytes();
+    let [e0, e1] = end.to_le_bytes();
+    [s0, s1, e0, e1]
+}
+
+/// Service Changed value of all handles.
+pub const ALL_HANDLES: [u8; 4] = service_changed(0x0001, 0xFFFF);
+
+/// Database Hash of the layout `revision`.
+pub fn database_hash(revision: u16) -> [u8; DATABASE_HASH_LEN] {
+    let [r0, r1] = revision.to_le_bytes();
+    let digest = sha256(&[b'G', b'A', b'T', b'T', r0, r1]);
+    let mut hash = [0; DATABASE_HASH_LEN];
+    hash.copy_from_slice(&digest[..DATABASE_HASH_LEN]);
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        assert_eq!(ALL_HANDLES, [0x01, 0x00, 0xFF, 0xFF]);
+        assert_eq!(service_changed(0x0010, 0x0123), [0x10, 0x00, 0x23, 0x01]);
+        assert_eq!(database_hash(1), database_hash(1));
+        assert_ne!(database_hash(1), database_hash(2));
+    }
+}
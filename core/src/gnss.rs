@@ -0,0 +1,282 @@
+//! NMEA and PCAS handling of the L76K GNSS module.
+//!
+//! Builds the PCAS configuration commands, splits the bytes received from the
+//! module into sentences and describes a position [`Fix`].
+
+use core::fmt::Write;
+
+/// Maximum length of a PCAS command including checksum and line ending.
+const COMMAND_MAX_LEN: usize = 32;
+
+/// Bytes of the NMEA sentences the module sends per update with its default
+/// output, generously with the GSV sentences of several constellations.
+pub const NMEA_BYTES_PER_UPDATE: u32 = 800;
+
+/// PCAS01 code of a baud rate.
+pub fn baud_code(baud: u32) -> Option<u8> {
+    match baud {
+        9600 => Some(1),
+        19200 => Some(2),
+        38400 => Some(3),
+        57600 => Some(4),
+        115200 => Some(5),
+        _ => None,
+    }
+}
+
+/// Whether `baud` carries the NMEA output at `update_rate_hz`, with 10 bits
+/// per byte on the wire.
+pub fn fits_link(baud: u32, update_rate_hz: u8) -> bool {
+    u32::from(update_rate_hz) * NMEA_BYTES_PER_UPDATE <= baud / 10
+}
+
+/// Position fix reported by the GNSS module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Fix {
+    /// Latitude in degrees, positive north.
+    pub latitude: f64,
+    /// Longitude in degrees, positive east.
+    pub longitude: f64,
+    /// Altitude above mean sea level in meters.
+    pub altitude: Option<f32>,
+    /// Number of satellites used for the fix.
+    pub satellites: u8,
+    /// Horizontal dilution of precision.
+    pub hdop: Option<f32>,
+}
+
+/// NMEA checksum: XOR of all characters between `$` and `*`.
+pub fn nmea_checksum(body: &[u8]) -> u8 {
+    body.iter().fold(0, |acc, b| acc ^ b)
+}
+
+/// A PCAS command with checksum and line ending.
+pub struct Command {
+    buf: [u8; COMMAND_MAX_LEN],
+    len: usize,
+}
+
+impl Command {
+    /// Build `$<body>*<checksum>\r\n`.
+    fn new(args: core::fmt::Arguments) -> Self {
+        let mut cmd = Self {
+            buf: [0; COMMAND_MAX_LEN],
+            len: 0,
+        };
+        // The commands built in this module always fit into the buffer.
+        let _ = cmd.write_str("$");
+        let _ = cmd.write_fmt(args);
+        let checksum = nmea_checksum(&cmd.buf[1..cmd.len]);
+        let _ = write!(cmd, "*{:02X}\r\n", checksum);
+        cmd
+    }
+
+    /// Set the baud rate of the module (PCAS01).
+    pub fn set_baud(baud: u32) -> Option<Self> {
+        baud_code(baud).map(|code| Self::new(format_args!("PCAS01,{}", code)))
+    }
+
+    /// Set the interval between position updates (PCAS02).
+    pub fn set_update_rate(update_rate_hz: u8) -> Self {
+        Self::new(format_args!(
+            "PCAS02,{}",
+            1000 / u16::from(update_rate_hz.max(1))
+        ))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Write for Command {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        if end > COMMAND_MAX_LEN {
+            return Err(core::fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// A line didn't fit into the provided buffer and was discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TooLong;
+
+/// Fixed size ring buffer of received bytes.
+pub struct RingBuffer<const N: usize> {
+    buf: [u8; N],
+    /// Index of the oldest byte.
+    head: usize,
+    /// Number of buffered bytes.
+    len: usize,
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Number of buffered bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Largest contiguous free region, to be filled by DMA.
+    pub fn writable(&mut self) -> &mut [u8] {
+        if self.len == 0 {
+            self.head = 0;
+        }
+        let tail = (self.head + self.len) % N;
+        let end = if tail >= self.head && self.len < N {
+            N
+        } else {
+            self.head
+        };
+        &mut self.buf[tail..end]
+    }
+
+    /// Mark `n` bytes of the region returned by [`Self::writable`] as filled.
+    pub fn commit(&mut self, n: usize) {
+        self.len = (self.len + n).min(N);
+    }
+
+    /// Append bytes, dropping the ones that don't fit.
+    pub fn extend(&mut self, data: &[u8]) -> usize {
+        let mut written = 0;
+        while written < data.len() {
+            let free = self.writable();
+            let n = free.len().min(data.len() - written);
+            if n == 0 {
+                break;
+            }
+            free[..n].copy_from_slice(&data[written..written + n]);
+            self.commit(n);
+            written += n;
+        }
+        written
+    }
+
+    fn get(&self, i: usize) -> u8 {
+        self.buf[(self.head + i) % N]
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.head = (self.head + n) % N;
+        self.len -= n;
+    }
+
+    /// Take the next `\n` terminated line out of the buffer and copy it
+    /// (without the trailing `\r\n`) into `line`.
+    ///
+    /// Returns `None` if no complete line is buffered.
+    pub fn pop_line(&mut self, line: &mut [u8]) -> Option<Result<usize, TooLong>> {
+        let newline = (0..self.len).find(|&i| self.get(i) == b'\n')?;
+        let mut content = newline;
+        if content > 0 && self.get(content - 1) == b'\r' {
+            content -= 1;
+        }
+        let result = if content > line.len() {
+            Err(TooLong)
+        } else {
+            for (i, b) in line[..content].iter_mut().enumerate() {
+                *b = self.get(i);
+            }
+            Ok(content)
+        };
+        self.consume(newline + 1);
+        Some(result)
+    }
+
+    /// Drop everything, e.g. if the buffer filled up without a line ending.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_of_sentence_body() {
+        let body = b"GPGGA,092750.000,5321.6802,N,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,";
+        assert_eq!(nmea_checksum(body), 0x76);
+    }
+
+    #[test]
+    fn pcas_commands() {
+        assert_eq!(
+            Command::set_baud(115200).unwrap().as_bytes(),
+            b"$PCAS01,5*19\r\n"
+        );
+        assert!(Command::set_baud(4800).is_none());
+        assert_eq!(
+            Command::set_update_rate(1).as_bytes(),
+            b"$PCAS02,1000*2E\r\n"
+        );
+        assert_eq!(
+            Command::set_update_rate(10).as_bytes(),
+            b"$PCAS02,100*1E\r\n"
+        );
+        // 0 Hz is treated as 1 Hz instead of dividing by zero.
+        assert_eq!(
+            Command::set_update_rate(0).as_bytes(),
+            b"$PCAS02,1000*2E\r\n"
+        );
+    }
+
+    #[test]
+    fn update_rates_fit_the_baud_rate() {
+        assert!(fits_link(9600, 1));
+        assert!(!fits_link(9600, 2));
+        assert!(fits_link(38400, 4));
+        assert!(!fits_link(57600, 10));
+        assert!(fits_link(115200, 10));
+    }
+
+    #[test]
+    fn lines_are_split_across_the_wrap_around() {
+        let mut ring = RingBuffer::<16>::new();
+        let mut line = [0; 16];
+        assert_eq!(ring.extend(b"$GPTXT,1*00\r\n$G"), 15);
+        assert_eq!(ring.pop_line(&mut line), Some(Ok(11)));
+        assert_eq!(&line[..11], b"$GPTXT,1*00");
+        assert_eq!(ring.pop_line(&mut line), None);
+
+        // The second line wraps around the end of the buffer.
+        assert_eq!(ring.extend(b"PZDA*00\n"), 8);
+        assert_eq!(ring.pop_line(&mut line), Some(Ok(9)));
+        assert_eq!(&line[..9], b"$GPZDA*00");
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn overlong_lines_are_discarded() {
+        let mut ring = RingBuffer::<16>::new();
+        let mut line = [0; 4];
+        ring.extend(b"$GPGGA\n$A\n");
+        assert_eq!(ring.pop_line(&mut line), Some(Err(TooLong)));
+        assert_eq!(ring.pop_line(&mut line), Some(Ok(2)));
+        assert_eq!(ring.extend(&[0; 32]), 16);
+    }
+}
@@ -0,0 +1,137 @@
+//! GPX export of the track log.
+//!
+//! A session is written as one track of one segment: [`write_header`],
+//! [`write_point`] for every point as decoded by
+//! [`track::codec`](crate::track::codec), then [`write_footer`]. Every
+//! part is short enough for a BLE notification, [`write`] does it all at
+//! once.
+
+use core::fmt::{self, Write};
+
+use crate::track::Point;
+
+/// Longest [`write_point`] text.
+pub const POINT_MAX_LEN: usize = 160;
+
+/// Write the start of the document and of the track named `name`.
+pub fn write_header(out: &mut impl Write, name: &str) -> fmt::Result {
+    out.write_str(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"nrf52-radio-rs\" \
+         xmlns=\"http://www.topografix.com/GPX/1/1\">\n<trk><name>",
+    )?;
+    for c in name.chars() {
+        match c {
+            '&' => out.write_str("&amp;")?,
+            '<' => out.write_str("&lt;")?,
+            '>' => out.write_str("&gt;")?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_str("</name><trkseg>\n")
+}
+
+/// Write `point` as a track point, without the time if it is unknown.
+pub fn write_point(out: &mut impl Write, point: &Point) -> fmt::Result {
+    write!(out, "<trkpt lat=\"")?;
+    write_e7(out, point.latitude_e7)?;
+    write!(out, "\" lon=\"")?;
+    write_e7(out, point.longitude_e7)?;
+    out.write_str("\">")?;
+    if point.altitude_m != i16::MIN {
+        write!(out, "<ele>{}</ele>", point.altitude_m)?;
+    }
+    if point.utc != 0 {
+        out.write_str("<time>")?;
+        write_utc(out, point.utc)?;
+        out.write_str("</time>")?;
+    }
+    writeln!(out, "<sat>{}</sat></trkpt>", point.satellites)
+}
+
+/// Write the end of the track and of the document.
+pub fn write_footer(out: &mut impl Write) -> fmt::Result {
+    out.write_str("</trkseg></trk>\n</gpx>\n")
+}
+
+/// Write the session `name` with `points`.
+pub fn write(
+    out: &mut impl Write,
+    name: &str,
+    points: impl IntoIterator<Item = Point>,
+) -> fmt::Result {
+    write_header(out, name)?;
+    for point in points {
+        write_point(out, &point)?;
+    }
+    write_footer(out)
+}
+
+/// Degrees from 1e-7 degrees without rounding.
+fn write_e7(out: &mut impl Write, value: i32) -> fmt::Result {
+    let sign = if value < 0 { "-" } else { "" };
+    let value = value.unsigned_abs();
+    write!(
+        out,
+        "{}{}.{:07}",
+        sign,
+        value / 10_000_000,
+        value % 10_000_000
+    )
+}
+
+/// ISO 8601 time of the Unix timestamp `utc`.
+fn write_utc(out: &mut impl Write, utc: u32) -> fmt::Result {
+    let days = (utc / 86_400) as i32;
+    let seconds = utc % 86_400;
+    // Civil from days, after Howard Hinnant.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i32;
+    write!(
+        out,
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpx_document() {
+        let point = Point {
+            utc: 1_709_210_096,
+            latitude_e7: 473_769_000,
+            longitude_e7: -8_541_705,
+            altitude_m: 408,
+            satellites: 9,
+            reserved: 0,
+        };
+        let mut text = String::new();
+        write_point(&mut text, &point).unwrap();
+        assert_eq!(
+            text,
+            "<trkpt lat=\"47.3769000\" lon=\"-0.8541705\"><ele>408</ele>\
+             <time>2024-02-29T12:34:56Z</time><sat>9</sat></trkpt>\n"
+        );
+        assert!(text.len() <= POINT_MAX_LEN);
+
+        let mut text = String::new();
+        write(&mut text, "a<b", [point]).unwrap();
+        assert!(text.contains("<name>a&lt;b</name>"));
+        assert!(text.ends_with("</gpx>\n"));
+    }
+}
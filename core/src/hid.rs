@@ -0,0 +1,72 @@
+//! Keys of a USB HID keyboard with the US layout.
+//!
+//! [`key`] gives the modifier byte and the usage ID of the keyboard/keypad
+//! page for a character, to type text as a boot keyboard report.
+
+/// Left shift in the modifier byte of a keyboard report.
+pub const LEFT_SHIFT: u8 = 0x02;
+
+/// Keys of the punctuation characters from usage ID 0x2C on, unshifted and
+/// shifted.
+const PUNCTUATION: [(char, char); 13] = [
+    (' ', ' '),
+    ('-', '_'),
+    ('=', '+'),
+    ('[', '{'),
+    (']', '}'),
+    ('\\', '|'),
+    // Non-US # and ~, not on a US keyboard.
+    ('\0', '\0'),
+    (';', ':'),
+    ('\'', '"'),
+    ('`', '~'),
+    (',', '<'),
+    ('.', '>'),
+    ('/', '?'),
+];
+
+/// Characters typed with shift on the digit keys 1 to 0.
+const SHIFTED_DIGITS: &str = "!@#$%^&*()";
+
+/// Modifier and usage ID of the key typing `c`, `None` if there is none.
+pub fn key(c: char) -> Option<(u8, u8)> {
+    match c {
+        'a'..='z' => Some((0, 0x04 + (c as u8 - b'a'))),
+        'A'..='Z' => Some((LEFT_SHIFT, 0x04 + (c as u8 - b'A'))),
+        '1'..='9' => Some((0, 0x1E + (c as u8 - b'1'))),
+        '0' => Some((0, 0x27)),
+        '\n' => Some((0, 0x28)),
+        '\t' => Some((0, 0x2B)),
+        '\0' => None,
+        _ => {
+            if let Some(i) = SHIFTED_DIGITS.find(c) {
+                return Some((LEFT_SHIFT, 0x1E + i as u8));
+            }
+            let (i, (plain, _)) = PUNCTUATION
+                .iter()
+                .enumerate()
+                .find(|(_, (plain, shifted))| c == *plain || c == *shifted)?;
+            let modifier = if c == *plain { 0 } else { LEFT_SHIFT };
+            Some((modifier, 0x2C + i as u8))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn us_layout() {
+        assert_eq!(key('a'), Some((0, 0x04)));
+        assert_eq!(key('Z'), Some((LEFT_SHIFT, 0x1D)));
+        assert_eq!(key('0'), Some((0, 0x27)));
+        assert_eq!(key(')'), Some((LEFT_SHIFT, 0x27)));
+        assert_eq!(key(' '), Some((0, 0x2C)));
+        assert_eq!(key('-'), Some((0, 0x2D)));
+        assert_eq!(key('.'), Some((0, 0x37)));
+        assert_eq!(key(','), Some((0, 0x36)));
+        assert_eq!(key('?'), Some((LEFT_SHIFT, 0x38)));
+        assert_eq!(key('°'), None);
+    }
+}
@@ -0,0 +1,165 @@
+//! Heart Rate Service values and a synthetic heart beat source.
+//!
+//! [`measurement`] encodes the Heart Rate Measurement characteristic (GATT
+//! specification supplement, section 3.116). [`Waveform`] produces beats of
+//! a plausible heart: a rate ramping between rest and light exercise,
+//! modulated by the breathing (respiratory sinus arrhythmia) and some
+//! beat-to-beat jitter. It is deterministic for a seed, so phone apps can
+//! be tested against the same recording.
+
+use core::f32::consts::PI;
+
+use heapless::Vec;
+use libm::{cosf, roundf, sinf};
+
+/// RR intervals in one measurement, as many as fit into the default ATT MTU.
+pub const MAX_RR: usize = 4;
+
+/// Longest measurement: flags, 16 bit rate and the RR intervals.
+pub const MEASUREMENT_LEN: usize = 3 + 2 * MAX_RR;
+
+/// Heart rate value format: 16 bit.
+const FLAG_RATE_U16: u8 = 1 << 0;
+
+/// Sensor contact supported and detected.
+const FLAG_CONTACT: u8 = 1 << 1 | 1 << 2;
+
+/// RR intervals present.
+const FLAG_RR: u8 = 1 << 4;
+
+/// Body sensor location: chest.
+pub const LOCATION_CHEST: u8 = 1;
+
+/// Heart Rate Measurement with the rate in bpm and up to [`MAX_RR`] RR
+/// intervals in 1/1024 s, the oldest first. Further intervals are dropped.
+pub fn measurement(bpm: u16, rr: &[u16]) -> Vec<u8, MEASUREMENT_LEN> {
+    let mut value = Vec::new();
+    let mut flags = FLAG_CONTACT;
+    if !rr.is_empty() {
+        flags |= FLAG_RR;
+    }
+    match u8::try_from(bpm) {
+        Ok(bpm) => {
+            let _ = value.extend_from_slice(&[flags, bpm]);
+        }
+        Err(_) => {
+            let _ = value.push(flags | FLAG_RATE_U16);
+            let _ = value.extend_from_slice(&bpm.to_le_bytes());
+        }
+    }
+    for rr in rr.iter().take(MAX_RR) {
+        let _ = value.extend_from_slice(&rr.to_le_bytes());
+    }
+    value
+}
+
+/// Rate at rest in bpm.
+const REST_BPM: f32 = 62.0;
+
+/// Rise of the rate at the top of the exercise ramp.
+const EXERCISE_BPM: f32 = 38.0;
+
+/// Period of the exercise ramp in s.
+const EXERCISE_PERIOD_S: f32 = 300.0;
+
+/// Swing of the rate with the breathing.
+const BREATHING_BPM: f32 = 4.0;
+
+/// Period of a breath in s.
+const BREATHING_PERIOD_S: f32 = 4.0;
+
+/// Largest beat-to-beat jitter.
+const JITTER_BPM: f32 = 1.5;
+
+/// A heart beat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Beat {
+    /// Instantaneous rate.
+    pub bpm: u16,
+    /// Time since the previous beat in 1/1024 s.
+    pub rr: u16,
+}
+
+impl Beat {
+    /// Time since the previous beat in ms.
+    pub fn rr_ms(&self) -> u64 {
+        self.rr as u64 * 1000 / 1024
+    }
+}
+
+/// Synthetic heart beats.
+#[derive(Debug, Clone)]
+pub struct Waveform {
+    /// Time of the last beat in s.
+    time_s: f32,
+    /// State of the xorshift jitter.
+    state: u32,
+}
+
+impl Waveform {
+    /// Beats with the jitter seeded by `seed`, starting at rest.
+    pub const fn new(seed: u32) -> Self {
+        Self {
+            time_s: 0.0,
+            // Xorshift sticks at zero.
+            state: if seed == 0 { 0x2545_f491 } else { seed },
+        }
+    }
+
+    /// Jitter in -1..1.
+    fn jitter(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 23) as f32 - 1.0
+    }
+
+    /// The next beat.
+    pub fn next_beat(&mut self) -> Beat {
+        let t = self.time_s;
+        let exercise = (1.0 - cosf(2.0 * PI * t / EXERCISE_PERIOD_S)) / 2.0;
+        let breathing = sinf(2.0 * PI * t / BREATHING_PERIOD_S);
+        let bpm = REST_BPM
+            + EXERCISE_BPM * exercise
+            + BREATHING_BPM * breathing
+            + JITTER_BPM * self.jitter();
+        let rr = roundf(60.0 * 1024.0 / bpm) as u16;
+        // Keep the time bounded, the waveform repeats with the ramp anyway.
+        self.time_s = (t + rr as f32 / 1024.0) % EXERCISE_PERIOD_S;
+        Beat {
+            // As measured from the interval.
+            bpm: roundf(60.0 * 1024.0 / rr as f32) as u16,
+            rr,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measurements_and_beats() {
+        assert_eq!(measurement(72, &[]).as_slice(), &[0x06, 72]);
+        assert_eq!(
+            measurement(72, &[853, 1024]).as_slice(),
+            &[0x16, 72, 0x55, 0x03, 0x00, 0x04]
+        );
+        assert_eq!(measurement(300, &[]).as_slice(), &[0x07, 0x2c, 0x01]);
+        assert_eq!(measurement(300, &[1; 6]).len(), MEASUREMENT_LEN);
+
+        let mut waveform = Waveform::new(1);
+        let mut elapsed = 0;
+        let (mut min, mut max) = (u16::MAX, 0);
+        while elapsed < 300 * 1024 {
+            let beat = waveform.next_beat();
+            min = min.min(beat.bpm);
+            max = max.max(beat.bpm);
+            elapsed += beat.rr as u32;
+        }
+        assert!((55..=65).contains(&min), "{min}");
+        assert!((95..=106).contains(&max), "{max}");
+        assert_eq!(Waveform::new(1).next_beat(), Waveform::new(1).next_beat());
+    }
+}
@@ -0,0 +1,174 @@
+//! Image metadata of the bootloaders the firmware runs under.
+//!
+//! MCUboot puts an [`ImageHeader`] in front of the image and keeps the
+//! swap state in a trailer at the end of the slot: after a test swap the
+//! image runs unconfirmed and is reverted on the next reset, unless the
+//! image OK flag is written, see [`Trailer`]. The Adafruit bootloader has
+//! no headers and no revert, its settings page only marks a valid
+//! application, see [`AdafruitBank`].
+
+use bytemuck::{Pod, Zeroable};
+
+/// Magic of an MCUboot image header.
+pub const IMAGE_MAGIC: u32 = 0x96f3_b83d;
+
+/// Magic at the end of an MCUboot slot with a swap trailer.
+pub const TRAILER_MAGIC: [u8; 16] = [
+    0x77, 0xc2, 0x95, 0xf3, 0x60, 0xd2, 0xef, 0x7f, 0x35, 0x52, 0x50, 0x0f, 0x2c, 0xb6, 0x79, 0x80,
+];
+
+/// Bytes of the end of a slot read as [`Trailer`], with 8 byte flash
+/// write alignment.
+pub const TRAILER_LEN: usize = 40;
+
+/// Offset of the image OK flag in the [`TRAILER_LEN`] bytes.
+pub const IMAGE_OK_OFFSET: usize = 16;
+
+/// Value of a set trailer flag, unset ones are erased (0xFF).
+pub const FLAG_SET: u8 = 0x01;
+
+/// Version of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct ImageVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub revision: u16,
+    pub build: u32,
+}
+
+impl core::fmt::Display for ImageVersion {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.revision)?;
+        if self.build != 0 {
+            write!(f, ".{}", self.build)?;
+        }
+        Ok(())
+    }
+}
+
+/// Header of an MCUboot image (little endian).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct ImageHeader {
+    pub magic: u32,
+    pub load_addr: u32,
+    /// Bytes of the header, the vector table follows.
+    pub hdr_size: u16,
+    pub protect_tlv_size: u16,
+    pub img_size: u32,
+    pub flags: u32,
+    pub version: ImageVersion,
+    pub pad: u32,
+}
+
+impl ImageHeader {
+    pub const LEN: usize = core::mem::size_of::<Self>();
+
+    /// The header in `bytes`, if they hold one.
+    pub fn parse(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        let header: Self = bytemuck::pod_read_unaligned(bytes);
+        (header.magic == IMAGE_MAGIC).then_some(header)
+    }
+}
+
+/// Whether the running image stays after the next reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ImageStatus {
+    /// The image stays.
+    Confirmed,
+    /// A test image, reverted on the next reset unless confirmed.
+    Pending,
+    /// The bootloader considers the image invalid or its state is unknown.
+    Unknown,
+}
+
+/// Swap state at the end of an MCUboot slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Trailer {
+    /// The trailer was written by a swap.
+    pub magic: bool,
+    pub image_ok: bool,
+    pub copy_done: bool,
+}
+
+impl Trailer {
+    /// The trailer in the last [`TRAILER_LEN`] bytes of a slot.
+    pub fn parse(bytes: &[u8; TRAILER_LEN]) -> Self {
+        Self {
+            magic: bytes[TRAILER_LEN - 16..] == TRAILER_MAGIC,
+            image_ok: bytes[IMAGE_OK_OFFSET] == FLAG_SET,
+            copy_done: bytes[8] == FLAG_SET,
+        }
+    }
+
+    /// Status of the image in the primary slot. Without a swap trailer the
+    /// image was flashed directly and stays.
+    pub fn status(&self) -> ImageStatus {
+        if !self.magic || self.image_ok {
+            ImageStatus::Confirmed
+        } else {
+            ImageStatus::Pending
+        }
+    }
+}
+
+/// State of the application bank in the Adafruit bootloader settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AdafruitBank {
+    ValidApp,
+    /// Erased or invalidated, e.g. by an interrupted update.
+    Invalid(u8),
+}
+
+impl AdafruitBank {
+    /// The bank code in the first byte of the settings page.
+    pub fn parse(code: u8) -> Self {
+        match code {
+            0x01 => Self::ValidApp,
+            code => Self::Invalid(code),
+        }
+    }
+
+    pub fn status(&self) -> ImageStatus {
+        match self {
+            Self::ValidApp => ImageStatus::Confirmed,
+            Self::Invalid(_) => ImageStatus::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_and_trailer() {
+        let mut bytes = [0u8; ImageHeader::LEN];
+        bytes[..4].copy_from_slice(&IMAGE_MAGIC.to_le_bytes());
+        bytes[8..10].copy_from_slice(&0x200u16.to_le_bytes());
+        bytes[20..24].copy_from_slice(&[1, 4, 2, 0]);
+        let header = ImageHeader::parse(&bytes).unwrap();
+        assert_eq!(header.hdr_size, 0x200);
+        assert_eq!(std::format!("{}", header.version), "1.4.2");
+        assert_eq!(ImageHeader::parse(&[0xFF; ImageHeader::LEN]), None);
+
+        let mut trailer = [0xFF; TRAILER_LEN];
+        assert_eq!(Trailer::parse(&trailer).status(), ImageStatus::Confirmed);
+        trailer[TRAILER_LEN - 16..].copy_from_slice(&TRAILER_MAGIC);
+        trailer[8] = FLAG_SET;
+        let swapped = Trailer::parse(&trailer);
+        assert!(swapped.copy_done);
+        assert_eq!(swapped.status(), ImageStatus::Pending);
+        trailer[IMAGE_OK_OFFSET] = FLAG_SET;
+        assert_eq!(Trailer::parse(&trailer).status(), ImageStatus::Confirmed);
+
+        assert_eq!(AdafruitBank::parse(0x01).status(), ImageStatus::Confirmed);
+        assert_eq!(AdafruitBank::parse(0xFE), AdafruitBank::Invalid(0xFE));
+    }
+}
@@ -0,0 +1,164 @@
+//! Layout of the device keys in the UICR customer registers.
+//!
+//! The [`CUSTOMER_LEN`] bytes hold a slot per [`KeyId`], the key followed
+//! by its CRC-32. UICR bits are only cleared by writes, so a slot is
+//! written once: an erased slot (all 0xFF) is [`SlotState::Empty`], a
+//! written one with a matching CRC [`SlotState::Valid`]. The last word
+//! holds [`LOCK_MAGIC`] once provisioning is done.
+
+use crate::crc::crc32;
+use crate::finder::OFFLINE_FINDING_KEY_LEN;
+
+/// Bytes of the customer registers (32 words).
+pub const CUSTOMER_LEN: usize = 128;
+
+/// Offset of the provisioning lock word.
+pub const LOCK_OFFSET: usize = CUSTOMER_LEN - 4;
+
+/// Value of the lock word of a provisioned device ("LOCK").
+pub const LOCK_MAGIC: u32 = 0x4B43_4F4C;
+
+/// Bytes of the CRC after the key.
+const CRC_LEN: usize = 4;
+
+/// The keys of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyId {
+    /// AES-128 identity key of the Eddystone-EID.
+    EidIdentity,
+    /// AES-128 key of the encrypted beacons.
+    Beacon,
+    /// P-256 private key for ECDH.
+    DeviceSecret,
+    /// P-224 public key advertised by the item-finder tag, see
+    /// [`OfflineFinding`](crate::finder::OfflineFinding). Not secret.
+    Finder,
+}
+
+impl KeyId {
+    pub const ALL: [KeyId; 4] = [
+        KeyId::EidIdentity,
+        KeyId::Beacon,
+        KeyId::DeviceSecret,
+        KeyId::Finder,
+    ];
+
+    /// Name, e.g. for the shell.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::EidIdentity => "eid",
+            Self::Beacon => "beacon",
+            Self::DeviceSecret => "device",
+            Self::Finder => "finder",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|id| id.name() == name)
+    }
+
+    /// Bytes of the key.
+    pub const fn key_len(self) -> usize {
+        match self {
+            Self::EidIdentity | Self::Beacon => 16,
+            Self::DeviceSecret => 32,
+            Self::Finder => OFFLINE_FINDING_KEY_LEN,
+        }
+    }
+
+    /// Offset of the slot in the customer registers, word aligned.
+    pub const fn offset(self) -> usize {
+        match self {
+            Self::EidIdentity => 0,
+            Self::Beacon => 20,
+            Self::DeviceSecret => 40,
+            Self::Finder => 76,
+        }
+    }
+
+    /// Bytes of the slot, key and CRC.
+    pub const fn slot_len(self) -> usize {
+        self.key_len() + CRC_LEN
+    }
+}
+
+/// State of a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SlotState {
+    Empty,
+    Valid,
+    /// Written, but the CRC doesn't match, e.g. after an interrupted write.
+    Corrupt,
+}
+
+/// State of the slot of `id` in the `customer` registers.
+pub fn slot_state(customer: &[u8; CUSTOMER_LEN], id: KeyId) -> SlotState {
+    let slot = &customer[id.offset()..id.offset() + id.slot_len()];
+    if slot.iter().all(|b| *b == 0xFF) {
+        return SlotState::Empty;
+    }
+    let (key, crc) = slot.split_at(id.key_len());
+    if crc32(key).to_le_bytes() == crc {
+        SlotState::Valid
+    } else {
+        SlotState::Corrupt
+    }
+}
+
+/// The key of `id` in the `customer` registers, if its slot is valid.
+pub fn key(customer: &[u8; CUSTOMER_LEN], id: KeyId) -> Option<&[u8]> {
+    (slot_state(customer, id) == SlotState::Valid)
+        .then(|| &customer[id.offset()..id.offset() + id.key_len()])
+}
+
+/// Whether provisioning was locked.
+pub fn is_locked(customer: &[u8; CUSTOMER_LEN]) -> bool {
+    customer[LOCK_OFFSET..] == LOCK_MAGIC.to_le_bytes()
+}
+
+/// The slot contents for `key`, `None` if it has the wrong length.
+pub fn encode_slot(id: KeyId, key: &[u8]) -> Option<heapless::Vec<u8, 36>> {
+    if key.len() != id.key_len() {
+        return None;
+    }
+    let mut slot = heapless::Vec::from_slice(key).ok()?;
+    slot.extend_from_slice(&crc32(key).to_le_bytes()).ok()?;
+    Some(slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots() {
+        let mut customer = [0xFF; CUSTOMER_LEN];
+        for id in KeyId::ALL {
+            assert_eq!(id.offset() % 4, 0);
+            assert!(id.offset() + id.slot_len() <= LOCK_OFFSET);
+            assert_eq!(slot_state(&customer, id), SlotState::Empty);
+            assert_eq!(KeyId::from_name(id.name()), Some(id));
+        }
+        assert_eq!(KeyId::Beacon.offset(), KeyId::EidIdentity.slot_len());
+        assert_eq!(
+            KeyId::Finder.offset(),
+            KeyId::DeviceSecret.offset() + KeyId::DeviceSecret.slot_len()
+        );
+
+        let slot = encode_slot(KeyId::Beacon, &[0x42; 16]).unwrap();
+        let offset = KeyId::Beacon.offset();
+        customer[offset..offset + slot.len()].copy_from_slice(&slot);
+        assert_eq!(key(&customer, KeyId::Beacon), Some(&[0x42; 16][..]));
+        assert_eq!(key(&customer, KeyId::EidIdentity), None);
+
+        customer[offset] = 0x00;
+        assert_eq!(slot_state(&customer, KeyId::Beacon), SlotState::Corrupt);
+        assert_eq!(encode_slot(KeyId::DeviceSecret, &[0; 16]), None);
+
+        assert!(!is_locked(&customer));
+        customer[LOCK_OFFSET..].copy_from_slice(&LOCK_MAGIC.to_le_bytes());
+        assert!(is_locked(&customer));
+    }
+}
@@ -0,0 +1,53 @@
+//! Platform independent logic of the nrf52-radio-rs firmware.
+//!
+//! Parsing, encoding and math that doesn't touch peripherals lives here, so
+//! it builds for the host and is tested with plain `cargo test-host` instead
+//! of on the target. The firmware re-exports these items from its own
+//! modules, e.g. [`gnss::Fix`] as `nrf52_radio_rs::gnss::Fix`.
+#![cfg_attr(not(test), no_std)]
+
+pub mod adv;
+pub mod alert;
+pub mod ans;
+pub mod ant;
+pub mod baro;
+pub mod beacon;
+pub mod casic;
+pub mod crash;
+pub mod crc;
+pub mod editor;
+pub mod epaper;
+pub mod epoch;
+pub mod fhss;
+pub mod finder;
+pub mod fmt;
+pub mod gateway;
+pub mod gatt_cache;
+pub mod gnss;
+pub mod gpx;
+pub mod hid;
+pub mod hrs;
+pub mod image;
+pub mod keystore;
+pub mod mesh;
+pub mod metrics;
+pub mod nav;
+pub mod ndef;
+pub mod oled;
+pub mod pcap;
+pub mod policy;
+pub mod pps;
+pub mod presentation;
+pub mod provision;
+pub mod qr;
+pub mod range;
+pub mod ranging;
+pub mod retained;
+pub mod rssi;
+pub mod sha256;
+pub mod signed;
+pub mod smp;
+pub mod telemetry;
+pub mod thermal;
+pub mod track;
+pub mod wear;
@@ -0,0 +1,387 @@
+//! Bluetooth Mesh PDU layouts and lower transport segmentation.
+//!
+//! Network PDUs travel in advertisements with the [`MESH_MESSAGE`] AD type
+//! (the advertising bearer). Upper transport access PDUs longer than
+//! [`MAX_UNSEGMENTED_LEN`] are split into segments of [`SEGMENT_LEN`] bytes
+//! by [`Segments`] and put back together by [`Reassembler`]. The nonces of
+//! the network and application layer encryption are built here, the AES
+//! operations are done by the firmware.
+//!
+//! [`sensor_status`] builds the access message a sensor node sends.
+
+use heapless::Vec;
+
+/// AD type of a network PDU on the advertising bearer.
+pub const MESH_MESSAGE: u8 = 0x2A;
+
+/// Longest network PDU on the advertising bearer.
+pub const MAX_NETWORK_PDU_LEN: usize = 29;
+
+/// Longest upper transport PDU sent unsegmented.
+pub const MAX_UNSEGMENTED_LEN: usize = 15;
+
+/// Bytes of upper transport PDU per segment of an access message.
+pub const SEGMENT_LEN: usize = 12;
+
+/// Most segments of a message.
+pub const MAX_SEGMENTS: usize = 32;
+
+/// Errors of the PDU encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PduError {
+    /// The PDU is longer than the layer allows or the buffer holds.
+    TooLong,
+    /// The PDU is too short or a field is out of range.
+    Malformed,
+}
+
+/// Network nonce of a PDU, `ctl_ttl` is the second byte of the network
+/// header.
+pub fn network_nonce(ctl_ttl: u8, seq: u32, src: u16, iv_index: u32) -> [u8; 13] {
+    let mut nonce = [0; 13];
+    nonce[0] = 0x00;
+    nonce[1] = ctl_ttl;
+    nonce[2..5].copy_from_slice(&seq.to_be_bytes()[1..]);
+    nonce[5..7].copy_from_slice(&src.to_be_bytes());
+    nonce[9..].copy_from_slice(&iv_index.to_be_bytes());
+    nonce
+}
+
+/// Application nonce of an access message, `szmic` selects the 8 byte
+/// TransMIC of segmented messages.
+pub fn application_nonce(szmic: bool, seq: u32, src: u16, dst: u16, iv_index: u32) -> [u8; 13] {
+    let mut nonce = [0; 13];
+    nonce[0] = 0x01;
+    nonce[1] = (szmic as u8) << 7;
+    nonce[2..5].copy_from_slice(&seq.to_be_bytes()[1..]);
+    nonce[5..7].copy_from_slice(&src.to_be_bytes());
+    nonce[7..9].copy_from_slice(&dst.to_be_bytes());
+    nonce[9..].copy_from_slice(&iv_index.to_be_bytes());
+    nonce
+}
+
+/// Header of a segmented access message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SegmentHeader {
+    /// Encrypted with an application key (not the device key).
+    pub akf: bool,
+    /// Application key identifier.
+    pub aid: u8,
+    /// 8 byte TransMIC.
+    pub szmic: bool,
+    /// Lowest 13 bits of the sequence number of the first segment.
+    pub seq_zero: u16,
+    /// Segment offset.
+    pub seg_o: u8,
+    /// Last segment number.
+    pub seg_n: u8,
+}
+
+impl SegmentHeader {
+    /// Bytes of the header.
+    pub const LEN: usize = 4;
+
+    /// Parse a lower transport PDU, returning the header and the segment,
+    /// `None` if it isn't a segmented access message.
+    pub fn parse(pdu: &[u8]) -> Option<(Self, &[u8])> {
+        let (header, segment) = pdu.split_at_checked(Self::LEN)?;
+        if header[0] & 0x80 == 0 || segment.is_empty() {
+            return None;
+        }
+        let bits = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        let header = Self {
+            akf: header[0] & 0x40 != 0,
+            aid: header[0] & 0x3F,
+            szmic: bits & 1 << 23 != 0,
+            seq_zero: (bits >> 10) as u16 & 0x1FFF,
+            seg_o: (bits >> 5) as u8 & 0x1F,
+            seg_n: bits as u8 & 0x1F,
+        };
+        (header.seg_o <= header.seg_n).then_some((header, segment))
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let bits = (self.szmic as u32) << 23
+            | (self.seq_zero as u32 & 0x1FFF) << 10
+            | (self.seg_o as u32 & 0x1F) << 5
+            | self.seg_n as u32 & 0x1F;
+        let b = bits.to_be_bytes();
+        [
+            0x80 | (self.akf as u8) << 6 | self.aid & 0x3F,
+            b[1],
+            b[2],
+            b[3],
+        ]
+    }
+}
+
+/// Header of an unsegmented access message.
+pub fn unsegmented_header(akf: bool, aid: u8) -> u8 {
+    (akf as u8) << 6 | aid & 0x3F
+}
+
+/// Iterator over the segments of an upper transport PDU: the header and
+/// the part of the PDU of each segment.
+pub struct Segments<'a> {
+    header: SegmentHeader,
+    chunks: core::slice::Chunks<'a, u8>,
+}
+
+impl<'a> Segments<'a> {
+    /// Split `pdu`, sent with the sequence number `seq` for the first
+    /// segment.
+    pub fn new(pdu: &'a [u8], akf: bool, aid: u8, szmic: bool, seq: u32) -> Result<Self, PduError> {
+        if pdu.is_empty() || pdu.len() > MAX_SEGMENTS * SEGMENT_LEN {
+            return Err(PduError::TooLong);
+        }
+        Ok(Self {
+            header: SegmentHeader {
+                akf,
+                aid,
+                szmic,
+                seq_zero: seq as u16 & 0x1FFF,
+                seg_o: 0,
+                seg_n: pdu.len().div_ceil(SEGMENT_LEN) as u8 - 1,
+            },
+            chunks: pdu.chunks(SEGMENT_LEN),
+        })
+    }
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = ([u8; SegmentHeader::LEN], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = self.chunks.next()?;
+        let header = self.header.to_bytes();
+        self.header.seg_o += 1;
+        Some((header, chunk))
+    }
+}
+
+/// Reassembles the segments of one message at a time, of up to `N` bytes.
+///
+/// A segment of another message (source or SeqZero) drops the partially
+/// received one.
+pub struct Reassembler<const N: usize> {
+    buf: [u8; N],
+    src: u16,
+    header: Option<SegmentHeader>,
+    /// Bit per received segment, the block acknowledgement.
+    received: u32,
+    len: usize,
+}
+
+impl<const N: usize> Default for Reassembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Reassembler<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            src: 0,
+            header: None,
+            received: 0,
+            len: 0,
+        }
+    }
+
+    /// Add a segment from `src`, returning the upper transport PDU once all
+    /// segments arrived.
+    pub fn push(
+        &mut self,
+        src: u16,
+        header: SegmentHeader,
+        segment: &[u8],
+    ) -> Result<Option<&[u8]>, PduError> {
+        let same_message = self.header.is_some_and(|h| {
+            self.src == src
+                && h.seq_zero == header.seq_zero
+                && h.seg_n == header.seg_n
+                && h.akf == header.akf
+                && h.aid == header.aid
+        });
+        if !same_message {
+            self.src = src;
+            self.header = Some(header);
+            self.received = 0;
+            self.len = 0;
+        }
+        let last = header.seg_o == header.seg_n;
+        let offset = header.seg_o as usize * SEGMENT_LEN;
+        if segment.len() > SEGMENT_LEN || !last && segment.len() != SEGMENT_LEN {
+            return Err(PduError::Malformed);
+        }
+        self.buf
+            .get_mut(offset..offset + segment.len())
+            .ok_or(PduError::TooLong)?
+            .copy_from_slice(segment);
+        self.received |= 1 << header.seg_o;
+        if last {
+            self.len = offset + segment.len();
+        }
+        let all = ((1u64 << (header.seg_n + 1)) - 1) as u32;
+        if self.received == all {
+            self.header = None;
+            Ok(Some(&self.buf[..self.len]))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Block acknowledgement of the message being received.
+    pub fn block_ack(&self) -> u32 {
+        self.received
+    }
+}
+
+/// Bytes of a network or application key.
+pub const KEY_LEN: usize = 16;
+
+/// A key given as 32 hex digits, `None` if malformed.
+pub fn decode_key(hex: &str) -> Option<[u8; KEY_LEN]> {
+    if hex.len() != 2 * KEY_LEN {
+        return None;
+    }
+    let mut key = [0; KEY_LEN];
+    let nibble = |c: u8| char::from(c).to_digit(16);
+    for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        *byte = (nibble(digits[0])? << 4 | nibble(digits[1])?) as u8;
+    }
+    Some(key)
+}
+
+/// Opcode of the Sensor Status message of the Sensor Server model.
+pub const SENSOR_STATUS: u8 = 0x52;
+
+/// Property of the die temperature: sint16 in 0.01 °C.
+pub const PRESENT_DEVICE_OPERATING_TEMPERATURE: u16 = 0x0054;
+
+/// Longest raw value of a property in [`sensor_status`].
+pub const MAX_SENSOR_VALUE_LEN: usize = 16;
+
+/// Longest access payload of [`sensor_status`].
+pub const MAX_SENSOR_STATUS_LEN: usize = 1 + 3 + MAX_SENSOR_VALUE_LEN;
+
+/// Access payload of a Sensor Status message with the raw `value` of one
+/// `property`. The marshalled sensor data has the short format A for
+/// properties below 0x800, else format B (Mesh Model specification,
+/// 4.2.14).
+pub fn sensor_status(
+    property: u16,
+    value: &[u8],
+) -> Result<Vec<u8, MAX_SENSOR_STATUS_LEN>, PduError> {
+    if value.is_empty() || value.len() > MAX_SENSOR_VALUE_LEN {
+        return Err(PduError::Malformed);
+    }
+    let length = value.len() as u16 - 1;
+    let mut pdu = Vec::new();
+    let _ = pdu.push(SENSOR_STATUS);
+    if property < 0x800 {
+        let _ = pdu.extend_from_slice(&(length << 1 | property << 5).to_le_bytes());
+    } else {
+        let _ = pdu.push(0x01 | (length as u8) << 1);
+        let _ = pdu.extend_from_slice(&property.to_le_bytes());
+    }
+    let _ = pdu.extend_from_slice(value);
+    Ok(pdu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_header_round_trip() {
+        let header = SegmentHeader {
+            akf: true,
+            aid: 0x26,
+            szmic: false,
+            seq_zero: 0x1234 & 0x1FFF,
+            seg_o: 2,
+            seg_n: 3,
+        };
+        let mut pdu = [0; 8];
+        pdu[..4].copy_from_slice(&header.to_bytes());
+        assert_eq!(pdu[0], 0xE6);
+        assert_eq!(SegmentHeader::parse(&pdu), Some((header, &pdu[4..])));
+        // Unsegmented
+        pdu[0] = unsegmented_header(true, 0x26);
+        assert_eq!(SegmentHeader::parse(&pdu), None);
+    }
+
+    #[test]
+    fn segments_are_reassembled_in_any_order() {
+        let pdu: std::vec::Vec<u8> = (0..30).collect();
+        let segments: std::vec::Vec<_> = Segments::new(&pdu, true, 5, false, 0x2_0001)
+            .unwrap()
+            .collect();
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[2].1.len(), 6);
+
+        let mut reassembler = Reassembler::<64>::new();
+        let mut parse = |i: usize| {
+            let mut raw = std::vec::Vec::from(segments[i].0);
+            raw.extend_from_slice(segments[i].1);
+            let (header, segment) = SegmentHeader::parse(&raw).unwrap();
+            assert_eq!(header.seq_zero, 1);
+            reassembler
+                .push(0x0100, header, segment)
+                .unwrap()
+                .map(|p| p.to_vec())
+        };
+        assert_eq!(parse(2), None);
+        assert_eq!(parse(0), None);
+        assert_eq!(parse(1), Some(pdu.clone()));
+    }
+
+    #[test]
+    fn keys_are_decoded() {
+        assert_eq!(
+            decode_key("7dd7364cd842ad18c17c2b820c84c3d6"),
+            Some([
+                0x7D, 0xD7, 0x36, 0x4C, 0xD8, 0x42, 0xAD, 0x18, 0xC1, 0x7C, 0x2B, 0x82, 0x0C, 0x84,
+                0xC3, 0xD6
+            ])
+        );
+        assert_eq!(decode_key("7dd7"), None);
+        assert_eq!(decode_key(&"g0".repeat(KEY_LEN)), None);
+    }
+
+    #[test]
+    fn sensor_status_is_marshalled() {
+        let celsius = 2350i16.to_le_bytes();
+        assert_eq!(
+            sensor_status(PRESENT_DEVICE_OPERATING_TEMPERATURE, &celsius).unwrap(),
+            [SENSOR_STATUS, 0x82, 0x0A, 0x2E, 0x09]
+        );
+        assert_eq!(
+            sensor_status(0x0800, &[7]).unwrap(),
+            [SENSOR_STATUS, 0x01, 0x00, 0x08, 7]
+        );
+        assert_eq!(sensor_status(0x0054, &[]), Err(PduError::Malformed));
+        assert_eq!(
+            sensor_status(0x0054, &[0; MAX_SENSOR_VALUE_LEN + 1]),
+            Err(PduError::Malformed)
+        );
+    }
+
+    #[test]
+    fn nonces() {
+        assert_eq!(
+            network_nonce(0x03, 0x000001, 0x1201, 0x1234_5678),
+            [0, 3, 0, 0, 1, 0x12, 0x01, 0, 0, 0x12, 0x34, 0x56, 0x78]
+        );
+        assert_eq!(
+            application_nonce(true, 0x07080B, 0x1234, 0x9736, 0x1234_5677),
+            [
+                1, 0x80, 7, 8, 0x0B, 0x12, 0x34, 0x97, 0x36, 0x12, 0x34, 0x56, 0x77
+            ]
+        );
+    }
+}
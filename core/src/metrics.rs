@@ -0,0 +1,69 @@
+//! Snapshot of the firmware counters and the Eddystone-TLM frame.
+
+use bytemuck::{Pod, Zeroable};
+
+/// Length of an unencrypted Eddystone-TLM frame.
+pub const TLM_LEN: usize = 14;
+
+/// Eddystone frame type of TLM.
+const TLM_FRAME_TYPE: u8 = 0x20;
+
+/// Temperature of a TLM frame without sensor (-128 °C).
+const TLM_NO_TEMPERATURE: [u8; 2] = [0x80, 0x00];
+
+/// Counters since boot as read over GATT (little endian).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Pod, Zeroable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct Snapshot {
+    pub uptime_secs: u32,
+    /// Advertising sets started.
+    pub advertisements: u32,
+    /// Connections established.
+    pub connections: u32,
+    /// Position fixes received.
+    pub fixes: u32,
+    /// Last battery voltage, 0 if unknown.
+    pub battery_mv: u16,
+    pub reserved: u16,
+}
+
+impl Snapshot {
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+
+    /// Unencrypted Eddystone-TLM frame (without the service UUID), the
+    /// advertising count is [`advertisements`](Self::advertisements).
+    pub fn tlm_frame(&self) -> [u8; TLM_LEN] {
+        let mut frame = [0; TLM_LEN];
+        frame[0] = TLM_FRAME_TYPE;
+        frame[2..4].copy_from_slice(&self.battery_mv.to_be_bytes());
+        frame[4..6].copy_from_slice(&TLM_NO_TEMPERATURE);
+        frame[6..10].copy_from_slice(&self.advertisements.to_be_bytes());
+        // Time since boot in 0.1 s.
+        let deciseconds = self.uptime_secs.saturating_mul(10);
+        frame[10..].copy_from_slice(&deciseconds.to_be_bytes());
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tlm_frame_layout() {
+        let snapshot = Snapshot {
+            uptime_secs: 3600,
+            advertisements: 0x0102_0304,
+            battery_mv: 3700,
+            ..Default::default()
+        };
+        assert_eq!(snapshot.as_bytes().len(), 20);
+        assert_eq!(
+            snapshot.tlm_frame(),
+            [0x20, 0, 0x0E, 0x74, 0x80, 0, 1, 2, 3, 4, 0, 0, 0x8C, 0xA0]
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Navigation: filtering of the speed and course over ground.
+//!
+//! The speed reported by a GNSS module jitters by about half a knot while
+//! the device doesn't move, and the course is meaningless then. A
+//! [`MotionFilter`] low-pass filters both and decides with some hysteresis
+//! whether the device is [`Moving`](MotionState::Moving) or
+//! [`Stationary`](MotionState::Stationary).
+//! Coordinates are rendered as text by [`format`], distance and bearing to
+//! a [`Waypoint`] are computed on a sphere, the heading by [`compass`].
+
+use libm::{atan2, cos, sin, sqrt};
+
+pub mod compass;
+pub mod format;
+
+/// Default speed below which the device is considered stationary, in knots.
+pub const DEFAULT_STATIONARY_KNOTS: f32 = 0.8;
+
+/// Weight of a new measurement in the low-pass filter.
+const ALPHA: f32 = 0.3;
+
+/// The filtered speed has to exceed the stationary threshold by this
+/// factor to count as moving again.
+const HYSTERESIS: f32 = 1.5;
+
+/// Whether the device moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MotionState {
+    Moving,
+    Stationary,
+}
+
+/// Low-pass filter of speed and course with stationary detection.
+#[derive(Debug, Clone, Copy)]
+pub struct MotionFilter {
+    stationary_knots: f32,
+    speed_knots: Option<f32>,
+    course: Option<f32>,
+    state: MotionState,
+}
+
+impl MotionFilter {
+    /// Filter considering speeds below `stationary_knots` as stationary.
+    pub const fn new(stationary_knots: f32) -> Self {
+        Self {
+            stationary_knots,
+            speed_knots: None,
+            course: None,
+            state: MotionState::Stationary,
+        }
+    }
+
+    /// Change the stationary threshold, e.g. after a settings change.
+    pub fn set_stationary_knots(&mut self, stationary_knots: f32) {
+        self.stationary_knots = stationary_knots;
+    }
+
+    /// Add a measurement, returning the new state.
+    ///
+    /// The course is only tracked while moving.
+    pub fn update(&mut self, speed_knots: f32, course: Option<f32>) -> MotionState {
+        let speed = match self.speed_knots {
+            Some(filtered) => filtered + ALPHA * (speed_knots - filtered),
+            None => speed_knots,
+        };
+        self.speed_knots = Some(speed);
+        self.state = match self.state {
+            MotionState::Moving if speed < self.stationary_knots => MotionState::Stationary,
+            MotionState::Stationary if speed >= self.stationary_knots * HYSTERESIS => {
+                MotionState::Moving
+            }
+            state => state,
+        };
+        self.course = match (self.state, self.course, course) {
+            (MotionState::Stationary, ..) => None,
+            (MotionState::Moving, Some(filtered), Some(course)) => {
+                // Shortest way around the circle, e.g. 350° to 10° is +20°.
+                let diff = (course - filtered + 540.0) % 360.0 - 180.0;
+                Some((filtered + ALPHA * diff + 360.0) % 360.0)
+            }
+            (MotionState::Moving, filtered, None) => filtered,
+            (MotionState::Moving, None, course) => course,
+        };
+        self.state
+    }
+
+    pub fn state(&self) -> MotionState {
+        self.state
+    }
+
+    /// Filtered speed over ground in knots, 0 while stationary.
+    pub fn speed_knots(&self) -> Option<f32> {
+        match self.state {
+            MotionState::Moving => self.speed_knots,
+            MotionState::Stationary => self.speed_knots.map(|_| 0.0),
+        }
+    }
+
+    /// Filtered course over ground in degrees from true north, `None`
+    /// while stationary.
+    pub fn course(&self) -> Option<f32> {
+        self.course
+    }
+}
+
+impl Default for MotionFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_STATIONARY_KNOTS)
+    }
+}
+
+/// Mean radius of the earth in m.
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// A navigation target in degrees (WGS 84).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Waypoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Waypoint {
+    /// A waypoint at a valid position.
+    pub fn new(latitude: f64, longitude: f64) -> Option<Self> {
+        ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)).then_some(
+            Self {
+                latitude,
+                longitude,
+            },
+        )
+    }
+
+    /// Decode latitude and longitude in 1e-7 degrees (little endian i32),
+    /// the layout of the telemetry samples.
+    pub fn from_bytes(bytes: &[u8; 8]) -> Option<Self> {
+        let latitude_e7 = i32::from_le_bytes(bytes[..4].try_into().unwrap());
+        let longitude_e7 = i32::from_le_bytes(bytes[4..].try_into().unwrap());
+        Self::new(latitude_e7 as f64 * 1e-7, longitude_e7 as f64 * 1e-7)
+    }
+
+    /// Encode as by [`Waypoint::from_bytes`].
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&((self.latitude * 1e7) as i32).to_le_bytes());
+        bytes[4..].copy_from_slice(&((self.longitude * 1e7) as i32).to_le_bytes());
+        bytes
+    }
+
+    /// Great circle distance in m and initial bearing in degrees from true
+    /// north from the given position to the waypoint.
+    pub fn course_from(&self, latitude: f64, longitude: f64) -> (f64, f64) {
+        let (phi1, phi2) = (latitude.to_radians(), self.latitude.to_radians());
+        let delta_phi = phi2 - phi1;
+        let delta_lambda = (self.longitude - longitude).to_radians();
+
+        // Haversine formula, well conditioned for short distances.
+        let h = sin(delta_phi / 2.0) * sin(delta_phi / 2.0)
+            + cos(phi1) * cos(phi2) * sin(delta_lambda / 2.0) * sin(delta_lambda / 2.0);
+        let distance = 2.0 * EARTH_RADIUS_M * atan2(sqrt(h), sqrt(1.0 - h));
+
+        let y = sin(delta_lambda) * cos(phi2);
+        let x = cos(phi1) * sin(phi2) - sin(phi1) * cos(phi2) * cos(delta_lambda);
+        let bearing = (atan2(y, x).to_degrees() + 360.0) % 360.0;
+        (distance, bearing)
+    }
+}
+
+/// Bearing relative to a heading, both in degrees, from 0 to 360 clockwise.
+pub fn relative_bearing(bearing: f32, heading: f32) -> f32 {
+    (bearing - heading + 360.0) % 360.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_is_stationary() {
+        let mut filter = MotionFilter::default();
+        for speed in [0.5, 0.1, 0.9, 0.3, 0.6, 1.0, 0.2] {
+            assert_eq!(filter.update(speed, Some(123.0)), MotionState::Stationary);
+        }
+        assert_eq!(filter.speed_knots(), Some(0.0));
+        assert_eq!(filter.course(), None);
+    }
+
+    #[test]
+    fn moving_with_hysteresis() {
+        let mut filter = MotionFilter::new(1.0);
+        assert_eq!(filter.update(5.0, Some(350.0)), MotionState::Moving);
+        assert_eq!(filter.course(), Some(350.0));
+        filter.update(5.0, Some(10.0));
+        let course = filter.course().unwrap();
+        assert!((course - 356.0).abs() < 1e-3, "{course}");
+
+        // Slowing down below the threshold but above the hysteresis band
+        // for moving off again.
+        while filter.update(0.9, None) == MotionState::Moving {}
+        for _ in 0..10 {
+            assert_eq!(filter.update(1.2, None), MotionState::Stationary);
+        }
+        assert_eq!(filter.update(10.0, None), MotionState::Moving);
+    }
+
+    #[test]
+    fn course_to_waypoint() {
+        let north = Waypoint::new(1.0, 0.0).unwrap();
+        let (distance, bearing) = north.course_from(0.0, 0.0);
+        assert!((distance - 111_195.0).abs() < 1.0, "{distance}");
+        assert!(bearing.abs() < 1e-9);
+
+        // Paris to London, about 344 km to the north-west.
+        let london = Waypoint::new(51.5074, -0.1278).unwrap();
+        let (distance, bearing) = london.course_from(48.8566, 2.3522);
+        assert!((distance - 343_500.0).abs() < 1000.0, "{distance}");
+        assert!((bearing - 330.0).abs() < 1.0, "{bearing}");
+
+        assert_eq!(relative_bearing(10.0, 350.0), 20.0);
+        assert_eq!(relative_bearing(350.0, 10.0), 340.0);
+    }
+
+    #[test]
+    fn waypoint_bytes() {
+        let waypoint = Waypoint::new(53.3613366, -6.50562).unwrap();
+        let decoded = Waypoint::from_bytes(&waypoint.to_bytes()).unwrap();
+        assert!((decoded.latitude - waypoint.latitude).abs() < 1e-7);
+        assert!((decoded.longitude - waypoint.longitude).abs() < 1e-7);
+
+        let mut invalid = [0; 8];
+        invalid[..4].copy_from_slice(&910_000_000i32.to_le_bytes());
+        assert_eq!(Waypoint::from_bytes(&invalid), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Tilt-compensated compass heading from a magnetometer and an accelerometer.
+//!
+//! Vectors are in the body frame of the device: x forward, y left, z up, so
+//! an accelerometer lying flat reads +1 g on z. The magnetometer is corrected
+//! for hard iron (offset) and soft iron (per axis scale) distortion by a
+//! [`Calibration`] captured while turning the device in all directions.
+
+use libm::{atan2f, sqrtf};
+
+/// A vector in the body frame of the device.
+pub type Vector = [f32; 3];
+
+/// Minimum range of every axis during a calibration, in raw counts.
+const MIN_SPAN: i16 = 100;
+
+/// Extremes of the magnetometer axes, defining offset and scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Calibration {
+    pub min: [i16; 3],
+    pub max: [i16; 3],
+}
+
+impl Calibration {
+    /// No offset, equal scale on all axes.
+    pub const UNCALIBRATED: Self = Self {
+        min: [-1; 3],
+        max: [1; 3],
+    };
+
+    /// Correct a raw magnetometer reading.
+    pub fn apply(&self, raw: [i16; 3]) -> Vector {
+        let radius = |i: usize| (self.max[i] as f32 - self.min[i] as f32).max(1.0) / 2.0;
+        let mean_radius = (radius(0) + radius(1) + radius(2)) / 3.0;
+        core::array::from_fn(|i| {
+            let offset = (self.max[i] as f32 + self.min[i] as f32) / 2.0;
+            (raw[i] as f32 - offset) * mean_radius / radius(i)
+        })
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::UNCALIBRATED
+    }
+}
+
+/// Collects the extremes of raw readings for a [`Calibration`].
+#[derive(Debug, Clone, Copy)]
+pub struct CalibrationCapture {
+    min: [i16; 3],
+    max: [i16; 3],
+}
+
+impl Default for CalibrationCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CalibrationCapture {
+    pub const fn new() -> Self {
+        Self {
+            min: [i16::MAX; 3],
+            max: [i16::MIN; 3],
+        }
+    }
+
+    pub fn add(&mut self, raw: [i16; 3]) {
+        for (i, &value) in raw.iter().enumerate() {
+            self.min[i] = self.min[i].min(value);
+            self.max[i] = self.max[i].max(value);
+        }
+    }
+
+    /// The calibration, `None` if an axis wasn't turned through enough.
+    pub fn finish(&self) -> Option<Calibration> {
+        (0..3)
+            .all(|i| self.max[i].saturating_sub(self.min[i]) >= MIN_SPAN)
+            .then_some(Calibration {
+                min: self.min,
+                max: self.max,
+            })
+    }
+}
+
+fn cross(a: Vector, b: Vector) -> Vector {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Heading of the x axis in degrees clockwise from magnetic north.
+///
+/// `acceleration` is the accelerometer reading at rest (pointing up) in any
+/// unit, `[0.0, 0.0, 1.0]` for a device held level.
+pub fn heading(magnetic: Vector, acceleration: Vector) -> f32 {
+    let norm = sqrtf(acceleration.iter().map(|a| a * a).sum());
+    let down = acceleration.map(|a| -a / norm);
+    // East is perpendicular to both the field and gravity, north completes
+    // the horizontal plane.
+    let east = cross(down, magnetic);
+    let north = cross(east, down);
+    (atan2f(east[0], north[0]).to_degrees() + 360.0) % 360.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVEL: Vector = [0.0, 0.0, 1.0];
+
+    fn assert_heading(actual: f32, expected: f32) {
+        let diff = (actual - expected + 540.0) % 360.0 - 180.0;
+        assert!(diff.abs() < 0.5, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn level_headings() {
+        // Field pointing north and down (northern hemisphere).
+        assert_heading(heading([20.0, 0.0, -40.0], LEVEL), 0.0);
+        assert_heading(heading([0.0, 20.0, -40.0], LEVEL), 90.0);
+        assert_heading(heading([-20.0, 0.0, -40.0], LEVEL), 180.0);
+        assert_heading(heading([0.0, -20.0, -40.0], LEVEL), 270.0);
+    }
+
+    #[test]
+    fn tilt_is_compensated() {
+        // Facing east, pitched nose up by 30° around the y (left) axis.
+        let (s, c) = (0.5f32, 0.75f32.sqrt());
+        let rotate = |v: Vector| [c * v[0] - s * v[2], v[1], s * v[0] + c * v[2]];
+        let magnetic = rotate([0.0, 20.0, -40.0]);
+        let acceleration = rotate(LEVEL);
+        assert_heading(heading(magnetic, acceleration), 90.0);
+        // Without compensation the vertical field component leaks in.
+        let naive = atan2f(magnetic[1], magnetic[0]).to_degrees();
+        assert!((naive - 90.0).abs() > 5.0, "{naive}");
+    }
+
+    #[test]
+    fn calibration_removes_offset_and_scale() {
+        let mut capture = CalibrationCapture::new();
+        capture.add([100, -50, 0]);
+        assert_eq!(capture.finish(), None);
+        capture.add([300, 350, 400]);
+        let calibration = capture.finish().unwrap();
+        assert_eq!(calibration.min, [100, -50, 0]);
+        // Centered, and every axis scaled to the mean radius of 166.7.
+        let corrected = calibration.apply([300, 150, 200]);
+        assert!((corrected[0] - 166.67).abs() < 0.1, "{corrected:?}");
+        assert!(corrected[1].abs() < 1e-3);
+        assert!(corrected[2].abs() < 1e-3);
+        assert_eq!(
+            Calibration::UNCALIBRATED.apply([5, -7, 9]),
+            [5.0, -7.0, 9.0]
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Rendering of coordinates as text, without allocation.
+//!
+//! Every function writes to a [`core::fmt::Write`], e.g. a
+//! `heapless::String`, so the OLED pages and the text exports share the same
+//! notation:
+//! - [`decimal_degrees`]: `53.36134N 6.50562W`
+//! - [`dms`]: `53°21'40.8"N 6°30'20.2"W`
+//! - [`utm`]: `29U 665982 5915367`
+//! - [`mgrs`]: `29U PV 65982 15367`
+//!
+//! UTM and MGRS are only defined between 80°S and 84°N, the polar regions
+//! use UPS, which isn't supported: the functions fail with [`fmt::Error`].
+
+use core::fmt::{self, Write};
+
+use libm::{cos, floor, sin, sqrt, tan};
+
+/// Semi-major axis of the WGS 84 ellipsoid in m.
+const WGS84_A: f64 = 6_378_137.0;
+
+/// Flattening of the WGS 84 ellipsoid.
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+
+/// Scale factor on the central meridian of a UTM zone.
+const UTM_K0: f64 = 0.9996;
+
+/// Easting of the central meridian of a UTM zone in m.
+const FALSE_EASTING: f64 = 500_000.0;
+
+/// Northing of the equator on the southern hemisphere in m.
+const FALSE_NORTHING_SOUTH: f64 = 10_000_000.0;
+
+/// Latitude bands of 8° from 80°S, X spans 12°.
+const BANDS: &[u8; 20] = b"CDEFGHJKLMNPQRSTUVWX";
+
+/// MGRS column letters of the 100 km squares, by zone modulo 3.
+const MGRS_COLUMNS: [&[u8; 8]; 3] = [b"STUVWXYZ", b"ABCDEFGH", b"JKLMNPQR"];
+
+/// MGRS row letters of the 100 km squares, offset by 5 in even zones.
+const MGRS_ROWS: &[u8; 20] = b"ABCDEFGHJKLMNPQRSTUV";
+
+/// Absolute value and hemisphere letter of a coordinate.
+fn hemisphere(value: f64, positive: char, negative: char) -> (f64, char) {
+    if value >= 0.0 {
+        (value, positive)
+    } else {
+        (-value, negative)
+    }
+}
+
+/// Decimal degrees with 5 decimals (about 1 m), e.g. `53.36134N 6.50562W`.
+pub fn decimal_degrees<W: Write>(out: &mut W, latitude: f64, longitude: f64) -> fmt::Result {
+    let (lat, ns) = hemisphere(latitude, 'N', 'S');
+    let (lon, ew) = hemisphere(longitude, 'E', 'W');
+    write!(out, "{:.5}{} {:.5}{}", lat, ns, lon, ew)
+}
+
+/// Write a single axis as degrees, minutes and seconds with one decimal.
+fn dms_axis<W: Write>(out: &mut W, value: f64, hemisphere: char) -> fmt::Result {
+    // Round to tenths of a second first, so 59.96" doesn't become 60.0".
+    let tenths = (value * 36_000.0 + 0.5) as u64;
+    let degrees = tenths / 36_000;
+    let minutes = tenths / 600 % 60;
+    let seconds = tenths % 600;
+    write!(
+        out,
+        "{}°{:02}'{:02}.{}\"{}",
+        degrees,
+        minutes,
+        seconds / 10,
+        seconds % 10,
+        hemisphere
+    )
+}
+
+/// Degrees, minutes and seconds, e.g. `53°21'40.8"N 6°30'20.2"W`.
+pub fn dms<W: Write>(out: &mut W, latitude: f64, longitude: f64) -> fmt::Result {
+    let (lat, ns) = hemisphere(latitude, 'N', 'S');
+    let (lon, ew) = hemisphere(longitude, 'E', 'W');
+    dms_axis(out, lat, ns)?;
+    out.write_char(' ')?;
+    dms_axis(out, lon, ew)
+}
+
+/// A position in the Universal Transverse Mercator grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Utm {
+    /// Zone of 6° longitude, 1 to 60.
+    pub zone: u8,
+    /// Latitude band letter, `C` to `X`.
+    pub band: char,
+    /// Easting in m.
+    pub easting: f64,
+    /// Northing in m, from the equator on the northern hemisphere, from
+    /// 10000 km south of it on the southern one.
+    pub northing: f64,
+}
+
+/// UTM zone of a position, with the exceptions around Norway and Svalbard.
+fn utm_zone(latitude: f64, longitude: f64) -> u8 {
+    if (56.0..64.0).contains(&latitude) && (3.0..12.0).contains(&longitude) {
+        return 32;
+    }
+    if latitude >= 72.0 && (0.0..42.0).contains(&longitude) {
+        return match longitude {
+            l if l < 9.0 => 31,
+            l if l < 21.0 => 33,
+            l if l < 33.0 => 35,
+            _ => 37,
+        };
+    }
+    (floor((longitude + 180.0) / 6.0) as i32).clamp(0, 59) as u8 + 1
+}
+
+impl Utm {
+    /// Project a WGS 84 position, `None` in the polar regions.
+    pub fn from_lat_lon(latitude: f64, longitude: f64) -> Option<Self> {
+        if !(-80.0..=84.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return None;
+        }
+        let zone = utm_zone(latitude, longitude);
+        let band = BANDS[((floor((latitude + 80.0) / 8.0)) as usize).min(BANDS.len() - 1)] as char;
+
+        // Transverse Mercator projection after Snyder, "Map Projections: A
+        // Working Manual" (1987), equations 8-9 to 8-13, accurate to
+        // millimetres within a zone.
+        let e2 = WGS84_F * (2.0 - WGS84_F);
+        let e4 = e2 * e2;
+        let e6 = e4 * e2;
+        let ep2 = e2 / (1.0 - e2);
+        let phi = latitude.to_radians();
+        let central_meridian = (zone as f64 - 1.0) * 6.0 - 180.0 + 3.0;
+        let (sin_phi, cos_phi, tan_phi) = (sin(phi), cos(phi), tan(phi));
+
+        let n = WGS84_A / sqrt(1.0 - e2 * sin_phi * sin_phi);
+        let t = tan_phi * tan_phi;
+        let c = ep2 * cos_phi * cos_phi;
+        let a = cos_phi * (longitude - central_meridian).to_radians();
+        let m = WGS84_A
+            * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * phi
+                - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * sin(2.0 * phi)
+                + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * sin(4.0 * phi)
+                - (35.0 * e6 / 3072.0) * sin(6.0 * phi));
+
+        let (a2, a3) = (a * a, a * a * a);
+        let (a4, a5, a6) = (a3 * a, a3 * a2, a3 * a3);
+        let easting = FALSE_EASTING
+            + UTM_K0
+                * n
+                * (a + (1.0 - t + c) * a3 / 6.0
+                    + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a5 / 120.0);
+        let mut northing = UTM_K0
+            * (m + n
+                * tan_phi
+                * (a2 / 2.0
+                    + (5.0 - t + 9.0 * c + 4.0 * c * c) * a4 / 24.0
+                    + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a6 / 720.0));
+        if latitude < 0.0 {
+            northing += FALSE_NORTHING_SOUTH;
+        }
+        Some(Self {
+            zone,
+            band,
+            easting,
+            northing,
+        })
+    }
+}
+
+/// UTM zone, band, easting and northing in m, e.g. `29U 665982 5915367`.
+pub fn utm<W: Write>(out: &mut W, latitude: f64, longitude: f64) -> fmt::Result {
+    let utm = Utm::from_lat_lon(latitude, longitude).ok_or(fmt::Error)?;
+    write!(
+        out,
+        "{}{} {} {}",
+        utm.zone, utm.band, utm.easting as u32, utm.northing as u32
+    )
+}
+
+/// MGRS grid reference with `digits` (1 to 5) digits per axis, i.e. 5 for
+/// 1 m and 3 for 100 m resolution, e.g. `29U PV 65982 15367`.
+pub fn mgrs<W: Write>(out: &mut W, latitude: f64, longitude: f64, digits: u8) -> fmt::Result {
+    if !(1..=5).contains(&digits) {
+        return Err(fmt::Error);
+    }
+    let utm = Utm::from_lat_lon(latitude, longitude).ok_or(fmt::Error)?;
+    let (easting, northing) = (utm.easting as u32, utm.northing as u32);
+    let column = MGRS_COLUMNS[utm.zone as usize % 3][(easting / 100_000) as usize - 1];
+    let row_offset = if utm.zone % 2 == 0 { 5 } else { 0 };
+    let row = MGRS_ROWS[(northing / 100_000 + row_offset) as usize % MGRS_ROWS.len()];
+    let divisor = 10u32.pow(5 - digits as u32);
+    write!(
+        out,
+        "{}{} {}{} {:0width$} {:0width$}",
+        utm.zone,
+        utm.band,
+        column as char,
+        row as char,
+        easting % 100_000 / divisor,
+        northing % 100_000 / divisor,
+        width = digits as usize
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(f: impl FnOnce(&mut std::string::String) -> fmt::Result) -> std::string::String {
+        let mut out = std::string::String::new();
+        f(&mut out).unwrap();
+        out
+    }
+
+    /// The CN Tower in Toronto, the example of the Wikipedia UTM article.
+    const CN_TOWER: (f64, f64) = (43.642567, -79.387139);
+
+    #[test]
+    fn degrees() {
+        assert_eq!(
+            render(|o| decimal_degrees(o, 53.361337, -6.50562)),
+            "53.36134N 6.50562W"
+        );
+        assert_eq!(
+            render(|o| dms(o, CN_TOWER.0, CN_TOWER.1)),
+            "43°38'33.2\"N 79°23'13.7\"W"
+        );
+        assert_eq!(
+            render(|o| dms(o, -0.999999, 0.0)),
+            "1°00'00.0\"S 0°00'00.0\"E"
+        );
+    }
+
+    #[test]
+    fn utm_and_mgrs() {
+        let (lat, lon) = CN_TOWER;
+        assert_eq!(render(|o| utm(o, lat, lon)), "17T 630084 4833438");
+        assert_eq!(render(|o| mgrs(o, lat, lon, 5)), "17T PJ 30084 33438");
+        assert_eq!(render(|o| mgrs(o, lat, lon, 3)), "17T PJ 300 334");
+
+        // On the equator and the central meridian of zone 31.
+        let origin = Utm::from_lat_lon(0.0, 3.0).unwrap();
+        assert_eq!((origin.zone, origin.band), (31, 'N'));
+        assert!((origin.easting - FALSE_EASTING).abs() < 1e-6);
+        assert!(origin.northing.abs() < 1e-6);
+        let south = Utm::from_lat_lon(-33.9, 18.4).unwrap();
+        assert_eq!((south.zone, south.band), (34, 'H'));
+        assert!(south.northing > 6_000_000.0);
+
+        assert_eq!(utm_zone(60.0, 5.0), 32);
+        assert_eq!(utm_zone(78.0, 15.0), 33);
+        assert_eq!(utm_zone(0.0, 180.0), 60);
+        assert!(utm(&mut std::string::String::new(), 85.0, 0.0).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! NDEF messages on an emulated NFC Forum Type 2 Tag.
+//!
+//! [`le_oob_record`] encodes a Bluetooth LE out-of-band record (MIME type
+//! `application/vnd.bluetooth.le.oob`) with the device address, role, name
+//! and optionally the LE Secure Connections OOB values. Phones reading it
+//! offer to pair with the device. [`Type2Tag`] holds the memory of a tag
+//! with such a message, as returned by the READ command.
+
+/// MIME type of a Bluetooth LE OOB record.
+pub const LE_OOB_TYPE: &[u8] = b"application/vnd.bluetooth.le.oob";
+
+/// Bytes of a Type 2 Tag block, returned by a READ command.
+pub const BLOCK_LEN: usize = 16;
+
+/// Bytes of a Type 2 Tag page, addressed by the READ command.
+pub const PAGE_LEN: usize = 4;
+
+/// Bytes before the data area: UID, lock bytes and capability container.
+const HEADER_LEN: usize = 16;
+
+/// Record header flags: message begin, message end, short record.
+const MB_ME_SR: u8 = 0xD0;
+
+/// Type name format of a MIME type record.
+const TNF_MIME: u8 = 0x02;
+
+/// AD types of the OOB record payload.
+const AD_LOCAL_NAME: u8 = 0x09;
+const AD_LE_ADDRESS: u8 = 0x1B;
+const AD_LE_ROLE: u8 = 0x1C;
+const AD_SC_CONFIRM: u8 = 0x22;
+const AD_SC_RANDOM: u8 = 0x23;
+
+/// LE role: peripheral only.
+const ROLE_PERIPHERAL: u8 = 0x00;
+
+/// TLV tags of the data area.
+const TLV_NDEF: u8 = 0x03;
+const TLV_TERMINATOR: u8 = 0xFE;
+
+/// Errors of encoding an NDEF message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NdefError {
+    /// The message doesn't fit into the buffer or the tag.
+    TooLong,
+}
+
+/// LE Secure Connections OOB values of the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OobData {
+    /// Random value `r`.
+    pub random: [u8; 16],
+    /// Confirmation value `f4(PKx, PKx, r, 0)`.
+    pub confirm: [u8; 16],
+}
+
+/// Appends bytes to a buffer, failing once it's full.
+struct Cursor<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl Cursor<'_> {
+    fn put(&mut self, bytes: &[u8]) -> Result<(), NdefError> {
+        let end = self.len + bytes.len();
+        self.out
+            .get_mut(self.len..end)
+            .ok_or(NdefError::TooLong)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn ad(&mut self, ad_type: u8, data: &[u8]) -> Result<(), NdefError> {
+        let len = u8::try_from(data.len() + 1).map_err(|_| NdefError::TooLong)?;
+        self.put(&[len, ad_type])?;
+        self.put(data)
+    }
+}
+
+/// Encode an NDEF message of a single LE OOB record into `out`, returning
+/// its length.
+///
+/// `address` is least significant byte first, as on air.
+pub fn le_oob_record(
+    out: &mut [u8],
+    address: [u8; 6],
+    random_address: bool,
+    name: &str,
+    oob: Option<&OobData>,
+) -> Result<usize, NdefError> {
+    let header_len = 3 + LE_OOB_TYPE.len();
+    let mut payload = Cursor {
+        out: out.get_mut(header_len..).ok_or(NdefError::TooLong)?,
+        len: 0,
+    };
+    let mut le_address = [0; 7];
+    le_address[..6].copy_from_slice(&address);
+    le_address[6] = random_address as u8;
+    payload.ad(AD_LE_ADDRESS, &le_address)?;
+    payload.ad(AD_LE_ROLE, &[ROLE_PERIPHERAL])?;
+    if let Some(oob) = oob {
+        payload.ad(AD_SC_CONFIRM, &oob.confirm)?;
+        payload.ad(AD_SC_RANDOM, &oob.random)?;
+    }
+    payload.ad(AD_LOCAL_NAME, name.as_bytes())?;
+    let payload_len = payload.len;
+
+    out[0] = MB_ME_SR | TNF_MIME;
+    out[1] = LE_OOB_TYPE.len() as u8;
+    out[2] = u8::try_from(payload_len).map_err(|_| NdefError::TooLong)?;
+    out[3..header_len].copy_from_slice(LE_OOB_TYPE);
+    Ok(header_len + payload_len)
+}
+
+/// Memory of a read-only Type 2 Tag of `N` bytes holding an NDEF message.
+pub struct Type2Tag<const N: usize> {
+    memory: [u8; N],
+}
+
+impl<const N: usize> Type2Tag<N> {
+    /// Tag with the 7 byte `uid` (the NFCID1 of the anticollision) and
+    /// the NDEF `message`.
+    pub fn new(uid: [u8; 7], message: &[u8]) -> Result<Self, NdefError> {
+        const {
+            assert!(N > HEADER_LEN && N.is_multiple_of(8) && N - HEADER_LEN <= 255 * 8);
+        }
+        let mut memory = [0; N];
+        let bcc0 = 0x88 ^ uid[0] ^ uid[1] ^ uid[2];
+        let bcc1 = uid[3] ^ uid[4] ^ uid[5] ^ uid[6];
+        memory[..3].copy_from_slice(&uid[..3]);
+        memory[3] = bcc0;
+        memory[4..8].copy_from_slice(&uid[3..]);
+        memory[8] = bcc1;
+        memory[9] = 0x48;
+        // Lock bytes: all static pages locked.
+        memory[10..12].copy_from_slice(&[0xFF, 0xFF]);
+        // Capability container: NDEF, version 1.0, data area size, read-only.
+        memory[12..16].copy_from_slice(&[0xE1, 0x10, ((N - HEADER_LEN) / 8) as u8, 0x0F]);
+
+        let len = u8::try_from(message.len())
+            .ok()
+            .filter(|&len| len < 0xFF)
+            .ok_or(NdefError::TooLong)?;
+        let mut data = Cursor {
+            out: &mut memory[HEADER_LEN..],
+            len: 0,
+        };
+        data.put(&[TLV_NDEF, len])?;
+        data.put(message)?;
+        data.put(&[TLV_TERMINATOR])?;
+        Ok(Self { memory })
+    }
+
+    /// Response to a READ command: 4 pages from `page`, rolling over to
+    /// page 0 at the end of the memory.
+    pub fn read(&self, page: u8) -> [u8; BLOCK_LEN] {
+        let mut block = [0; BLOCK_LEN];
+        let start = page as usize * PAGE_LEN;
+        for (i, byte) in block.iter_mut().enumerate() {
+            *byte = self.memory[(start + i) % N];
+        }
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: [u8; 6] = [0x01, 0x02, 0x03, 0x04, 0x05, 0xC6];
+
+    #[test]
+    fn oob_record() {
+        let mut out = [0; 128];
+        let len = le_oob_record(&mut out, ADDRESS, true, "TB", None).unwrap();
+        let payload = [
+            8, 0x1B, 0x01, 0x02, 0x03, 0x04, 0x05, 0xC6, 0x01, // address
+            2, 0x1C, 0x00, // role
+            3, 0x09, b'T', b'B', // name
+        ];
+        assert_eq!(out[..3], [0xD2, 32, payload.len() as u8]);
+        assert_eq!(&out[3..35], LE_OOB_TYPE);
+        assert_eq!(out[35..len], payload);
+
+        let oob = OobData {
+            random: [0xAA; 16],
+            confirm: [0xCC; 16],
+        };
+        let with_oob = le_oob_record(&mut out, ADDRESS, true, "TB", Some(&oob)).unwrap();
+        assert_eq!(with_oob, len + 2 * 18);
+        assert_eq!(out[2] as usize, with_oob - 35);
+        assert!(le_oob_record(&mut out[..40], ADDRESS, true, "TB", None).is_err());
+    }
+
+    #[test]
+    fn tag_memory() {
+        let uid = [0x5F, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+        let message = [0xD2, 1, 1, b'x', 0];
+        let tag = Type2Tag::<64>::new(uid, &message).unwrap();
+        let header = tag.read(0);
+        assert_eq!(header[3], 0x88 ^ 0x5F ^ 0x01 ^ 0x02);
+        assert_eq!(header[8], 0x03 ^ 0x04 ^ 0x05 ^ 0x06);
+        assert_eq!(header[12..], [0xE1, 0x10, 6, 0x0F]);
+        assert_eq!(tag.read(4)[..8], [0x03, 5, 0xD2, 1, 1, b'x', 0, 0xFE]);
+        // Reading past the end rolls over.
+        assert_eq!(tag.read(15)[4..], header[..12]);
+        assert!(Type2Tag::<32>::new(uid, &[0; 16]).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Frame buffer and commands of monochrome OLED controllers.
+//!
+//! The SSD1306 and the SH1106 share their command set for the basics and
+//! the memory layout: every page of 8 rows is a row of bytes, one per
+//! column, the top row in the least significant bit. Both are written page
+//! by page with [`page_address`] followed by the bytes of
+//! [`FrameBuffer::page`]. The SH1106 has 132 columns, 128 wide panels start
+//! at column 2.
+//!
+//! Panels smaller than the controller are wired to the middle columns, see
+//! [`PanelSize::column_offset`]. The [`FrameBuffer`] rotates in software,
+//! so quarter turns are possible on any panel.
+
+use heapless::Vec;
+
+/// Bytes of the largest frame buffer, 128x64.
+pub const BUFFER_LEN: usize = 128 * 64 / 8;
+
+/// Longest [`init_commands`] sequence.
+pub const INIT_LEN: usize = 24;
+
+/// Display controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Controller {
+    Ssd1306,
+    Sh1106,
+}
+
+impl Controller {
+    pub const ALL: [Self; 2] = [Self::Ssd1306, Self::Sh1106];
+}
+
+/// Size of the panel in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PanelSize {
+    W128H64,
+    W128H32,
+    W64H48,
+    W64H32,
+}
+
+impl PanelSize {
+    pub const ALL: [Self; 4] = [Self::W128H64, Self::W128H32, Self::W64H48, Self::W64H32];
+
+    pub const fn width(self) -> usize {
+        match self {
+            Self::W128H64 | Self::W128H32 => 128,
+            Self::W64H48 | Self::W64H32 => 64,
+        }
+    }
+
+    pub const fn height(self) -> usize {
+        match self {
+            Self::W128H64 => 64,
+            Self::W128H32 | Self::W64H32 => 32,
+            Self::W64H48 => 48,
+        }
+    }
+
+    pub const fn pages(self) -> usize {
+        self.height() / 8
+    }
+
+    /// First column of the panel on an SSD1306.
+    pub const fn column_offset(self) -> u8 {
+        match self {
+            Self::W128H64 | Self::W128H32 => 0,
+            Self::W64H48 | Self::W64H32 => 32,
+        }
+    }
+
+    /// COM pins hardware configuration, sequential on the 128x32 panels.
+    const fn com_pins(self) -> u8 {
+        match self {
+            Self::W128H32 => 0x02,
+            _ => 0x12,
+        }
+    }
+}
+
+/// Rotation of the content, clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Rotation {
+    #[default]
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    /// `None` unless `degrees` is a multiple of 90 below 360.
+    pub const fn from_degrees(degrees: u16) -> Option<Self> {
+        match degrees {
+            0 => Some(Self::Rotate0),
+            90 => Some(Self::Rotate90),
+            180 => Some(Self::Rotate180),
+            270 => Some(Self::Rotate270),
+            _ => None,
+        }
+    }
+
+    /// Whether width and height are swapped.
+    pub const fn is_portrait(self) -> bool {
+        matches!(self, Self::Rotate90 | Self::Rotate270)
+    }
+}
+
+/// Command turning the display on or off.
+pub const fn power_command(on: bool) -> u8 {
+    if on { 0xAF } else { 0xAE }
+}
+
+/// Commands setting the contrast.
+pub const fn contrast_commands(contrast: u8) -> [u8; 2] {
+    [0x81, contrast]
+}
+
+/// Commands initializing `controller` for `size`, leaving the display off.
+pub fn init_commands(controller: Controller, size: PanelSize) -> Vec<u8, INIT_LEN> {
+    let multiplex = size.height() as u8 - 1;
+    let com_pins = size.com_pins();
+    // Off, oscillator, multiplex ratio, no offset, start line 0, charge
+    // pump or DC-DC converter (and page addressing on the SSD1306), segment
+    // remap, COM scan descending, COM pins, contrast, pre-charge period,
+    // VCOM deselect level, show the RAM, not inverted.
+    let commands: &[u8] = match controller {
+        Controller::Ssd1306 => &[
+            0xAE, 0xD5, 0x80, 0xA8, multiplex, 0xD3, 0x00, 0x40, 0x8D, 0x14, 0x20, 0x02, 0xA1,
+            0xC8, 0xDA, com_pins, 0x81, 0xCF, 0xD9, 0xF1, 0xDB, 0x40, 0xA4, 0xA6,
+        ],
+        Controller::Sh1106 => &[
+            0xAE, 0xD5, 0x80, 0xA8, multiplex, 0xD3, 0x00, 0x40, 0xAD, 0x8B, 0xA1, 0xC8, 0xDA,
+            com_pins, 0x81, 0xCF, 0xD9, 0x22, 0xDB, 0x35, 0xA4, 0xA6,
+        ],
+    };
+    // Both fit INIT_LEN.
+    Vec::from_slice(commands).unwrap_or_default()
+}
+
+/// Commands addressing the first column of `page`.
+pub const fn page_address(controller: Controller, size: PanelSize, page: u8) -> [u8; 3] {
+    let column = size.column_offset()
+        + match controller {
+            Controller::Ssd1306 => 0,
+            Controller::Sh1106 => 2,
+        };
+    [0xB0 | page, column & 0x0F, 0x10 | column >> 4]
+}
+
+/// Frame buffer of a panel in the page layout of the controllers.
+#[derive(Debug, Clone)]
+pub struct FrameBuffer {
+    buf: [u8; BUFFER_LEN],
+    size: PanelSize,
+    rotation: Rotation,
+}
+
+impl FrameBuffer {
+    pub const fn new(size: PanelSize, rotation: Rotation) -> Self {
+        Self {
+            buf: [0; BUFFER_LEN],
+            size,
+            rotation,
+        }
+    }
+
+    pub fn size(&self) -> PanelSize {
+        self.size
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Rotate what is drawn from now on.
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+    }
+
+    /// Width and height of the content after rotation.
+    pub fn dimensions(&self) -> (usize, usize) {
+        let (width, height) = (self.size.width(), self.size.height());
+        if self.rotation.is_portrait() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.buf = [0; BUFFER_LEN];
+    }
+
+    /// Set the pixel at `x`, `y` of the rotated content, ignored outside.
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        let (width, height) = self.dimensions();
+        if x >= width || y >= height {
+            return;
+        }
+        let (w, h) = (self.size.width(), self.size.height());
+        let (x, y) = match self.rotation {
+            Rotation::Rotate0 => (x, y),
+            Rotation::Rotate90 => (w - 1 - y, x),
+            Rotation::Rotate180 => (w - 1 - x, h - 1 - y),
+            Rotation::Rotate270 => (y, h - 1 - x),
+        };
+        let byte = &mut self.buf[y / 8 * w + x];
+        let bit = 1 << (y % 8);
+        if on {
+            *byte |= bit;
+        } else {
+            *byte &= !bit;
+        }
+    }
+
+    /// The bytes of `page`, one per column.
+    pub fn page(&self, page: usize) -> &[u8] {
+        let width = self.size.width();
+        &self.buf[page * width..(page + 1) * width]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotated_pixels_and_commands() {
+        let mut frame = FrameBuffer::new(PanelSize::W64H48, Rotation::Rotate90);
+        assert_eq!(frame.dimensions(), (48, 64));
+        // Top left of the content is the top right of the panel.
+        frame.set_pixel(0, 0, true);
+        assert_eq!(frame.page(0)[63], 0x01);
+        frame.set_pixel(47, 63, true);
+        assert_eq!(frame.page(5)[0], 0x80);
+        frame.set_pixel(48, 0, true);
+        frame.set_rotation(Rotation::Rotate180);
+        frame.set_pixel(0, 0, false);
+        assert_eq!(frame.page(5)[63], 0x00);
+        assert_eq!(frame.page(0)[63], 0x01);
+
+        assert_eq!(
+            page_address(Controller::Sh1106, PanelSize::W128H64, 3),
+            [0xB3, 0x02, 0x10]
+        );
+        assert_eq!(
+            page_address(Controller::Ssd1306, PanelSize::W64H32, 0),
+            [0xB0, 0x00, 0x12]
+        );
+        for controller in Controller::ALL {
+            for size in PanelSize::ALL {
+                let commands = init_commands(controller, size);
+                assert!(!commands.is_empty());
+                assert!(commands.contains(&(size.height() as u8 - 1)));
+            }
+        }
+        assert_eq!(Rotation::from_degrees(45), None);
+    }
+}
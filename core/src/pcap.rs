@@ -0,0 +1,158 @@
+//! PCAP records of BLE link layer packets, as read by Wireshark.
+//!
+//! The stream starts with [`global_header`], followed by one [`Record`] per
+//! packet of link type `LINKTYPE_BLUETOOTH_LE_LL_WITH_PHDR`: a pseudo header
+//! with RF channel, RSSI and CRC status, then the packet from the access
+//! address to the CRC. The RADIO checks the CRC but doesn't store it, it is
+//! computed again with [`ble_crc24`].
+
+/// `LINKTYPE_BLUETOOTH_LE_LL_WITH_PHDR`
+pub const LINKTYPE_BLE_LL_WITH_PHDR: u32 = 256;
+
+/// Access address of the advertising channels.
+pub const ADV_ACCESS_ADDRESS: u32 = 0x8e89_bed6;
+
+/// CRC initial value of the advertising channels.
+pub const ADV_CRC_INIT: u32 = 0x55_5555;
+
+/// Longest PDU (header and payload) of a legacy advertising packet.
+pub const MAX_PDU_LEN: usize = 2 + 37;
+
+/// Length of the record header, pseudo header, access address and CRC.
+pub const RECORD_OVERHEAD: usize = 16 + 10 + 4 + 3;
+
+/// Longest encoded [`Record`].
+pub const MAX_RECORD_LEN: usize = RECORD_OVERHEAD + MAX_PDU_LEN;
+
+/// Pseudo header flags.
+const FLAG_DEWHITENED: u16 = 0x0001;
+const FLAG_SIGNAL_VALID: u16 = 0x0002;
+const FLAG_CRC_CHECKED: u16 = 0x0400;
+const FLAG_CRC_VALID: u16 = 0x0800;
+
+/// Header of the capture, little endian, microsecond timestamps.
+pub fn global_header() -> [u8; 24] {
+    let mut header = [0; 24];
+    header[0..4].copy_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+    header[4..6].copy_from_slice(&2u16.to_le_bytes());
+    header[6..8].copy_from_slice(&4u16.to_le_bytes());
+    // Time zone and accuracy stay 0.
+    header[16..20].copy_from_slice(&(MAX_RECORD_LEN as u32).to_le_bytes());
+    header[20..24].copy_from_slice(&LINKTYPE_BLE_LL_WITH_PHDR.to_le_bytes());
+    header
+}
+
+/// CRC of the link layer over `pdu`, in the order of the air (the first
+/// byte is sent first).
+pub fn ble_crc24(init: u32, pdu: &[u8]) -> [u8; 3] {
+    // The LFSR shifts right, so it starts with the bit reversed init value.
+    let mut state = init.reverse_bits() >> 8;
+    for &byte in pdu {
+        let mut byte = byte;
+        for _ in 0..8 {
+            let bit = (state ^ byte as u32) & 1;
+            byte >>= 1;
+            state >>= 1;
+            if bit != 0 {
+                state |= 1 << 23;
+                state ^= 0x5a_6000;
+            }
+        }
+    }
+    let crc = state.to_le_bytes();
+    [crc[0], crc[1], crc[2]]
+}
+
+/// RF channel (0 to 39 by frequency) of BLE channel `channel`.
+pub fn rf_channel(channel: u8) -> u8 {
+    match channel {
+        37 => 0,
+        38 => 12,
+        39 => 39,
+        0..=10 => channel + 1,
+        _ => channel + 2,
+    }
+}
+
+/// A captured advertising channel packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// Time of the access address in µs since the capture started.
+    pub timestamp_us: u64,
+    /// BLE channel index, 37 to 39.
+    pub channel: u8,
+    pub rssi: i8,
+    pub crc_ok: bool,
+    /// Header and payload, at most [`MAX_PDU_LEN`].
+    pub pdu: &'a [u8],
+}
+
+impl Record<'_> {
+    /// Encode into `buf`, returning the length.
+    pub fn encode(&self, buf: &mut [u8; MAX_RECORD_LEN]) -> usize {
+        let pdu = &self.pdu[..self.pdu.len().min(MAX_PDU_LEN)];
+        let packet_len = (10 + 4 + pdu.len() + 3) as u32;
+        let secs = (self.timestamp_us / 1_000_000) as u32;
+        let micros = (self.timestamp_us % 1_000_000) as u32;
+        buf[0..4].copy_from_slice(&secs.to_le_bytes());
+        buf[4..8].copy_from_slice(&micros.to_le_bytes());
+        buf[8..12].copy_from_slice(&packet_len.to_le_bytes());
+        buf[12..16].copy_from_slice(&packet_len.to_le_bytes());
+
+        let mut flags = FLAG_DEWHITENED | FLAG_SIGNAL_VALID | FLAG_CRC_CHECKED;
+        if self.crc_ok {
+            flags |= FLAG_CRC_VALID;
+        }
+        buf[16] = rf_channel(self.channel);
+        buf[17] = self.rssi as u8;
+        // Noise power and access address offenses.
+        buf[18] = 0;
+        buf[19] = 0;
+        buf[20..24].copy_from_slice(&ADV_ACCESS_ADDRESS.to_le_bytes());
+        buf[24..26].copy_from_slice(&flags.to_le_bytes());
+
+        buf[26..30].copy_from_slice(&ADV_ACCESS_ADDRESS.to_le_bytes());
+        let end = 30 + pdu.len();
+        buf[30..end].copy_from_slice(pdu);
+        buf[end..end + 3].copy_from_slice(&ble_crc24(ADV_CRC_INIT, pdu));
+        end + 3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_records() {
+        // The CRC over a PDU followed by its CRC leaves no remainder.
+        let pdu = [0x40, 0x06, 0xd6, 0xbe, 0x89, 0x8e, 0x00, 0x11];
+        let crc = ble_crc24(ADV_CRC_INIT, &pdu);
+        let mut with_crc = pdu.to_vec();
+        with_crc.extend_from_slice(&crc);
+        assert_eq!(ble_crc24(ADV_CRC_INIT, &with_crc), [0; 3]);
+        assert_ne!(ble_crc24(0, &pdu), crc);
+
+        assert_eq!(global_header()[..4], [0xd4, 0xc3, 0xb2, 0xa1]);
+        assert_eq!(
+            (rf_channel(37), rf_channel(10), rf_channel(11)),
+            (0, 11, 13)
+        );
+
+        let record = Record {
+            timestamp_us: 3_000_042,
+            channel: 38,
+            rssi: -60,
+            crc_ok: true,
+            pdu: &pdu,
+        };
+        let mut buf = [0; MAX_RECORD_LEN];
+        let len = record.encode(&mut buf);
+        assert_eq!(len, RECORD_OVERHEAD + pdu.len());
+        assert_eq!(buf[..8], [3, 0, 0, 0, 42, 0, 0, 0]);
+        assert_eq!(buf[8] as usize, len - 16);
+        assert_eq!((buf[16], buf[17] as i8), (12, -60));
+        assert_eq!(buf[24..26], [0x03, 0x0c]);
+        assert_eq!(buf[len - 3..len], crc);
+    }
+}
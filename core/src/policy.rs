@@ -0,0 +1,130 @@
+//! Battery-aware behavior levels.
+//!
+//! [`BatteryPolicy`] maps the battery state of charge to a [`PowerLevel`]
+//! the subsystems adapt to: below [`REDUCED_PERCENT`] the GNSS module and
+//! the advertising save power, below [`CRITICAL_PERCENT`] the GNSS module
+//! and the display are stopped, below [`EMPTY_PERCENT`] the device shuts
+//! down. A level is only left again [`HYSTERESIS_PERCENT`] above its
+//! threshold, so a voltage wobbling around it doesn't toggle the
+//! subsystems. On external power the level is always
+//! [`Normal`](PowerLevel::Normal).
+
+/// State of charge below which power is saved.
+pub const REDUCED_PERCENT: u8 = 20;
+
+/// State of charge below which only the radio keeps running.
+pub const CRITICAL_PERCENT: u8 = 10;
+
+/// State of charge below which the device shuts down.
+pub const EMPTY_PERCENT: u8 = 5;
+
+/// Margin above a threshold for returning to the better level.
+pub const HYSTERESIS_PERCENT: u8 = 3;
+
+/// How much the subsystems have to save, ordered from none to all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerLevel {
+    #[default]
+    Normal,
+    /// Reduced GNSS duty cycle and advertising rate.
+    Reduced,
+    /// GNSS and display off.
+    Critical,
+    /// State persisted, then ship mode.
+    Empty,
+}
+
+impl PowerLevel {
+    /// Level of a state of charge, without hysteresis.
+    pub const fn of(percent: u8) -> Self {
+        if percent < EMPTY_PERCENT {
+            Self::Empty
+        } else if percent < CRITICAL_PERCENT {
+            Self::Critical
+        } else if percent < REDUCED_PERCENT {
+            Self::Reduced
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+/// Tracks the [`PowerLevel`] from battery updates.
+#[derive(Debug, Default)]
+pub struct BatteryPolicy {
+    level: PowerLevel,
+    percent: Option<u8>,
+    external_power: bool,
+}
+
+impl BatteryPolicy {
+    pub const fn new() -> Self {
+        Self {
+            level: PowerLevel::Normal,
+            percent: None,
+            external_power: false,
+        }
+    }
+
+    pub fn level(&self) -> PowerLevel {
+        self.level
+    }
+
+    /// A new state of charge estimate. Returns the level if it changed.
+    pub fn battery(&mut self, percent: u8) -> Option<PowerLevel> {
+        self.percent = Some(percent);
+        self.evaluate()
+    }
+
+    /// USB power was (dis)connected. Returns the level if it changed.
+    pub fn external_power(&mut self, present: bool) -> Option<PowerLevel> {
+        self.external_power = present;
+        self.evaluate()
+    }
+
+    fn evaluate(&mut self) -> Option<PowerLevel> {
+        let level = match self.percent {
+            _ if self.external_power => PowerLevel::Normal,
+            None => PowerLevel::Normal,
+            Some(percent) => {
+                let level = PowerLevel::of(percent);
+                if level < self.level {
+                    // Better only with the margin, possibly by several levels.
+                    PowerLevel::of(percent.saturating_sub(HYSTERESIS_PERCENT)).min(self.level)
+                } else {
+                    level
+                }
+            }
+        };
+        (level != self.level).then(|| {
+            self.level = level;
+            level
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_with_hysteresis() {
+        let mut policy = BatteryPolicy::new();
+        assert_eq!(policy.battery(80), None);
+        assert_eq!(policy.battery(19), Some(PowerLevel::Reduced));
+        assert_eq!(policy.battery(20), None);
+        assert_eq!(policy.battery(22), None);
+        assert_eq!(policy.battery(23), Some(PowerLevel::Normal));
+        assert_eq!(policy.battery(9), Some(PowerLevel::Critical));
+        assert_eq!(policy.battery(12), None);
+        assert_eq!(policy.battery(13), Some(PowerLevel::Reduced));
+        assert_eq!(policy.battery(4), Some(PowerLevel::Empty));
+
+        // External power overrides the state of charge until it's removed.
+        assert_eq!(policy.external_power(true), Some(PowerLevel::Normal));
+        assert_eq!(policy.battery(4), None);
+        assert_eq!(policy.external_power(false), Some(PowerLevel::Empty));
+        assert_eq!(policy.level(), PowerLevel::Empty);
+    }
+}
@@ -0,0 +1,132 @@
+//! UTC from the pulse per second of a GNSS module.
+//!
+//! The local clock runs from boot in µs. A [`PpsClock`] takes the local
+//! time of every pulse and the UTC second the following NMEA time sentence
+//! names for it, and maps local times to UTC from the last such pair. The
+//! rate difference of the local clock, some ppm of the 32 kHz crystal, is
+//! measured between pulses and corrected for, so the mapping holds between
+//! pulses and for a while after they stop.
+
+/// A pulse older than this when the time sentence arrives belongs to an
+/// earlier second.
+const MAX_SENTENCE_DELAY_US: u64 = 1_000_000;
+
+/// Mapping older than this is not used anymore.
+pub const HOLDOVER_US: u64 = 3600 * 1_000_000;
+
+/// Rate differences beyond this are measurement errors, e.g. a missed pulse.
+const MAX_DRIFT_PPB: i64 = 500_000;
+
+/// Weight of a new rate measurement, 1/2^n.
+const DRIFT_SMOOTHING_SHIFT: u32 = 3;
+
+/// A local time at which the UTC time is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Anchor {
+    pub local_us: u64,
+    /// µs since the Unix epoch.
+    pub utc_us: i64,
+}
+
+/// Maps the local clock to UTC.
+#[derive(Debug, Default)]
+pub struct PpsClock {
+    anchor: Option<Anchor>,
+    last_pulse: Option<u64>,
+    /// Rate of the local clock relative to UTC in parts per billion,
+    /// positive when it runs fast.
+    drift_ppb: i64,
+}
+
+impl PpsClock {
+    pub const fn new() -> Self {
+        Self {
+            anchor: None,
+            last_pulse: None,
+            drift_ppb: 0,
+        }
+    }
+
+    /// A pulse arrived at `local_us`.
+    pub fn pulse(&mut self, local_us: u64) {
+        self.last_pulse = Some(local_us);
+    }
+
+    /// A time sentence for the second `utc_secs` (since the Unix epoch)
+    /// arrived at `local_us`. Returns whether it was matched to a pulse.
+    pub fn utc_second(&mut self, utc_secs: i64, local_us: u64) -> bool {
+        let Some(pulse) = self.last_pulse else {
+            return false;
+        };
+        if local_us.saturating_sub(pulse) > MAX_SENTENCE_DELAY_US {
+            return false;
+        }
+        let anchor = Anchor {
+            local_us: pulse,
+            utc_us: utc_secs * 1_000_000,
+        };
+        if let Some(previous) = self.anchor {
+            let local = (anchor.local_us - previous.local_us) as i64;
+            let utc = anchor.utc_us - previous.utc_us;
+            if utc > 0 {
+                let ppb = (local - utc) * 1_000_000_000 / utc;
+                if ppb.abs() <= MAX_DRIFT_PPB {
+                    self.drift_ppb += (ppb - self.drift_ppb) >> DRIFT_SMOOTHING_SHIFT;
+                }
+            }
+        }
+        self.anchor = Some(anchor);
+        true
+    }
+
+    /// The last pulse matched to a UTC second.
+    pub fn anchor(&self) -> Option<Anchor> {
+        self.anchor
+    }
+
+    /// Measured rate of the local clock in parts per billion.
+    pub fn drift_ppb(&self) -> i64 {
+        self.drift_ppb
+    }
+
+    /// UTC in µs since the Unix epoch at `local_us`, `None` without a pulse
+    /// in the last [`HOLDOVER_US`].
+    pub fn utc_us(&self, local_us: u64) -> Option<i64> {
+        let anchor = self.anchor?;
+        let elapsed = local_us as i64 - anchor.local_us as i64;
+        if elapsed.unsigned_abs() > HOLDOVER_US {
+            return None;
+        }
+        let correction = elapsed * self.drift_ppb / 1_000_000_000;
+        Some(anchor.utc_us + elapsed - correction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disciplines_local_clock() {
+        let mut clock = PpsClock::new();
+        assert!(!clock.utc_second(1_700_000_000, 500_000));
+        assert_eq!(clock.utc_us(0), None);
+
+        // The local clock runs 100 ppm fast, the sentence follows the pulse.
+        let mut local = 1_000_000;
+        for second in 0..40 {
+            clock.pulse(local);
+            assert!(clock.utc_second(1_700_000_000 + second, local + 300_000));
+            local += 1_000_100;
+        }
+        assert!((clock.drift_ppb() - 100_000).abs() < 1_000);
+        let anchor = clock.anchor().unwrap();
+        let half_second = clock.utc_us(anchor.local_us + 500_050).unwrap();
+        assert!((half_second - anchor.utc_us - 500_000).abs() <= 1);
+
+        // A sentence long after the pulse isn't matched.
+        assert!(!clock.utc_second(1_700_000_100, local + 5_000_000));
+        assert_eq!(clock.utc_us(anchor.local_us + HOLDOVER_US + 1), None);
+    }
+}
@@ -0,0 +1,103 @@
+//! Characteristic Presentation Format descriptor values.
+//!
+//! A generic BLE browser shows a vendor characteristic as raw bytes unless
+//! a Characteristic Presentation Format descriptor (Core specification,
+//! Vol 3, Part G, 3.3.3.5) tells it the value format, the unit and the
+//! decimal exponent. A [`Format`] is declared once, e.g. [`DEGREES_E7`],
+//! and its [`Format::to_bytes`] is the descriptor value.
+
+/// Length of the descriptor value.
+pub const LEN: usize = 7;
+
+/// Value formats.
+pub const FORMAT_BOOLEAN: u8 = 0x01;
+pub const FORMAT_UINT8: u8 = 0x04;
+pub const FORMAT_SINT8: u8 = 0x0C;
+pub const FORMAT_UINT16: u8 = 0x06;
+pub const FORMAT_UINT32: u8 = 0x08;
+pub const FORMAT_SINT16: u8 = 0x0E;
+pub const FORMAT_SINT32: u8 = 0x10;
+pub const FORMAT_UTF8: u8 = 0x19;
+pub const FORMAT_STRUCT: u8 = 0x1B;
+
+/// Units of the Bluetooth SIG assigned numbers.
+pub const UNIT_UNITLESS: u16 = 0x2700;
+pub const UNIT_METRE: u16 = 0x2701;
+pub const UNIT_SECOND: u16 = 0x2703;
+pub const UNIT_PASCAL: u16 = 0x2724;
+pub const UNIT_VOLT: u16 = 0x2728;
+pub const UNIT_DEGREE_CELSIUS: u16 = 0x272F;
+pub const UNIT_DEGREE: u16 = 0x2763;
+pub const UNIT_BEATS_PER_MINUTE: u16 = 0x27A7;
+pub const UNIT_PERCENTAGE: u16 = 0x27AD;
+pub const UNIT_DECIBEL: u16 = 0x27C3;
+
+/// Name space of the units and descriptions: Bluetooth SIG.
+const NAMESPACE_SIG: u8 = 0x01;
+
+/// Description of the name space: unknown.
+const DESCRIPTION_UNKNOWN: u16 = 0x0000;
+
+/// Format of a characteristic value: the value times 10 to the `exponent`
+/// is in `unit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    pub format: u8,
+    pub exponent: i8,
+    pub unit: u16,
+}
+
+impl Format {
+    pub const fn new(format: u8, exponent: i8, unit: u16) -> Self {
+        Self {
+            format,
+            exponent,
+            unit,
+        }
+    }
+
+    /// The descriptor value.
+    pub const fn to_bytes(self) -> [u8; LEN] {
+        let unit = self.unit.to_le_bytes();
+        let description = DESCRIPTION_UNKNOWN.to_le_bytes();
+        [
+            self.format,
+            self.exponent as u8,
+            unit[0],
+            unit[1],
+            NAMESPACE_SIG,
+            description[0],
+            description[1],
+        ]
+    }
+}
+
+/// Latitude or longitude in 1e-7 degrees.
+pub const DEGREES_E7: Format = Format::new(FORMAT_SINT32, -7, UNIT_DEGREE);
+
+/// Signal or TX power in dBm.
+pub const DBM: Format = Format::new(FORMAT_SINT8, 0, UNIT_DECIBEL);
+
+/// Percentage, e.g. a battery level.
+pub const PERCENT: Format = Format::new(FORMAT_UINT8, 0, UNIT_PERCENTAGE);
+
+/// UTF-8 text.
+pub const TEXT: Format = Format::new(FORMAT_UTF8, 0, UNIT_UNITLESS);
+
+/// Vendor specific structure, see the user description.
+pub const OPAQUE: Format = Format::new(FORMAT_STRUCT, 0, UNIT_UNITLESS);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptor_values() {
+        assert_eq!(
+            DEGREES_E7.to_bytes(),
+            [0x10, 0xF9, 0x63, 0x27, 0x01, 0x00, 0x00]
+        );
+        assert_eq!(DBM.to_bytes(), [0x0C, 0x00, 0xC3, 0x27, 0x01, 0x00, 0x00]);
+        assert_eq!(PERCENT.to_bytes()[..4], [0x04, 0x00, 0xAD, 0x27]);
+    }
+}
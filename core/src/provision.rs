@@ -0,0 +1,154 @@
+//! Line protocol of the factory provisioning.
+//!
+//! Every line is a request, answered with `OK` or `ERR <reason>`:
+//! - `name <text>`: the device name,
+//! - `key <eid|beacon|device|finder> <hex>`: a key for the keystore,
+//! - `cal <min x> <min y> <min z> <max x> <max y> <max z>`: the raw
+//!   magnetometer range of the compass calibration,
+//! - `set <setting> <value>`: any other setting,
+//! - `status`: the provisioned keys and settings,
+//! - `lock`: end provisioning for good,
+//! - `debug-lock confirm`: lock the debugger out for good, spelled out to
+//!   rule out typos.
+
+use heapless::Vec;
+
+use crate::keystore::KeyId;
+
+/// Longest key in bytes.
+pub const MAX_KEY_LEN: usize = 32;
+
+/// A parsed request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request<'a> {
+    Name(&'a str),
+    Key(KeyId, Vec<u8, MAX_KEY_LEN>),
+    Calibration { min: [i16; 3], max: [i16; 3] },
+    Set(&'a str, u32),
+    Status,
+    Lock,
+    DebugLock,
+}
+
+/// Reasons a line isn't a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    UnknownCommand,
+    UnknownKey,
+    /// Missing or extra arguments.
+    Usage,
+    /// Not a number in range.
+    Number,
+    /// Not an even number of hex digits, or too long.
+    Hex,
+}
+
+impl ParseError {
+    /// Reason in the `ERR` response.
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::UnknownCommand => "unknown command",
+            Self::UnknownKey => "unknown key",
+            Self::Usage => "usage",
+            Self::Number => "invalid number",
+            Self::Hex => "invalid hex",
+        }
+    }
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8, MAX_KEY_LEN>, ParseError> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(ParseError::Hex);
+    }
+    let nibble = |c: u8| char::from(c).to_digit(16).ok_or(ParseError::Hex);
+    let mut bytes = Vec::new();
+    for digits in hex.as_bytes().chunks_exact(2) {
+        let byte = (nibble(digits[0])? << 4 | nibble(digits[1])?) as u8;
+        bytes.push(byte).map_err(|_| ParseError::Hex)?;
+    }
+    Ok(bytes)
+}
+
+/// Parse a line without its line ending.
+pub fn parse(line: &str) -> Result<Request<'_>, ParseError> {
+    let line = line.trim();
+    let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+    let args = args.trim();
+    let mut words = args.split_whitespace();
+    let request = match command {
+        "name" if !args.is_empty() => return Ok(Request::Name(args)),
+        "key" => {
+            let id = words.next().ok_or(ParseError::Usage)?;
+            let id = KeyId::from_name(id).ok_or(ParseError::UnknownKey)?;
+            Request::Key(id, decode_hex(words.next().ok_or(ParseError::Usage)?)?)
+        }
+        "cal" => {
+            let mut values = [0i16; 6];
+            for value in &mut values {
+                let word = words.next().ok_or(ParseError::Usage)?;
+                *value = word.parse().map_err(|_| ParseError::Number)?;
+            }
+            let [x0, y0, z0, x1, y1, z1] = values;
+            Request::Calibration {
+                min: [x0, y0, z0],
+                max: [x1, y1, z1],
+            }
+        }
+        "set" => {
+            let name = words.next().ok_or(ParseError::Usage)?;
+            let value = words.next().ok_or(ParseError::Usage)?;
+            Request::Set(name, value.parse().map_err(|_| ParseError::Number)?)
+        }
+        "status" => Request::Status,
+        "lock" => Request::Lock,
+        "debug-lock" if words.next() == Some("confirm") => Request::DebugLock,
+        "debug-lock" => return Err(ParseError::Usage),
+        "name" => return Err(ParseError::Usage),
+        _ => return Err(ParseError::UnknownCommand),
+    };
+    match words.next() {
+        Some(_) => Err(ParseError::Usage),
+        None => Ok(request),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        assert_eq!(parse("name Tracker 7\r"), Ok(Request::Name("Tracker 7")));
+        assert_eq!(
+            parse("key beacon 00ff10"),
+            Ok(Request::Key(
+                KeyId::Beacon,
+                Vec::from_slice(&[0x00, 0xFF, 0x10]).unwrap()
+            ))
+        );
+        assert_eq!(
+            parse("cal -310 -280 -400 295 330 250"),
+            Ok(Request::Calibration {
+                min: [-310, -280, -400],
+                max: [295, 330, 250]
+            })
+        );
+        assert_eq!(
+            parse("set thermal_limit_c 65"),
+            Ok(Request::Set("thermal_limit_c", 65))
+        );
+        assert_eq!(parse("lock"), Ok(Request::Lock));
+        assert_eq!(parse("debug-lock confirm"), Ok(Request::DebugLock));
+
+        assert_eq!(parse("name"), Err(ParseError::Usage));
+        assert_eq!(parse("key other 00"), Err(ParseError::UnknownKey));
+        assert_eq!(parse("key eid 0g"), Err(ParseError::Hex));
+        assert_eq!(parse("key eid 000"), Err(ParseError::Hex));
+        assert_eq!(parse("cal 1 2 3"), Err(ParseError::Usage));
+        assert_eq!(parse("cal 1 2 3 4 5 99999"), Err(ParseError::Number));
+        assert_eq!(parse("lock now"), Err(ParseError::Usage));
+        assert_eq!(parse("debug-lock"), Err(ParseError::Usage));
+        assert_eq!(parse("erase"), Err(ParseError::UnknownCommand));
+    }
+}
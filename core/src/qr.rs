@@ -0,0 +1,491 @@
+//! QR codes of versions 1 to 3 without allocation.
+//!
+//! Enough for a BLE address or a short pairing URL: [`QrCode::encode`]
+//! takes up to 53 bytes in byte mode and picks the smallest version that
+//! fits, at error correction level L or M, both a single Reed-Solomon block
+//! in these versions. The mask is chosen by the penalty rules of ISO/IEC
+//! 18004, so the result matches other encoders module for module.
+
+/// Modules per side of a version 3 symbol.
+pub const MAX_SIZE: usize = 29;
+
+/// Highest supported version.
+pub const MAX_VERSION: u8 = 3;
+
+/// Error correction level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Ecc {
+    /// Recovers about 7% of the codewords.
+    Low,
+    /// Recovers about 15% of the codewords.
+    Medium,
+}
+
+impl Ecc {
+    /// Bits of the level in the format information.
+    const fn format_bits(self) -> u32 {
+        match self {
+            Self::Low => 1,
+            Self::Medium => 0,
+        }
+    }
+
+    /// Data and error correction codewords of `version`.
+    const fn codewords(self, version: u8) -> (usize, usize) {
+        match (self, version) {
+            (Self::Low, 1) => (19, 7),
+            (Self::Medium, 1) => (16, 10),
+            (Self::Low, 2) => (34, 10),
+            (Self::Medium, 2) => (28, 16),
+            (Self::Low, _) => (55, 15),
+            (Self::Medium, _) => (44, 26),
+        }
+    }
+
+    /// Longest data in byte mode for `version`: the mode and the 8 bit
+    /// length take 12 bits.
+    pub const fn capacity(self, version: u8) -> usize {
+        (self.codewords(version).0 * 8 - 12) / 8
+    }
+}
+
+/// Errors of [`QrCode::encode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QrError {
+    /// The data doesn't fit version 3 at the level.
+    TooLong,
+}
+
+/// Longest codeword sequence, of version 3.
+const MAX_CODEWORDS: usize = 70;
+
+/// Penalty weights of the mask evaluation.
+const PENALTY_N1: i32 = 3;
+const PENALTY_N2: i32 = 3;
+const PENALTY_N3: i32 = 40;
+const PENALTY_N4: i32 = 10;
+
+/// A QR code symbol, dark modules are set bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QrCode {
+    version: u8,
+    size: usize,
+    /// One row per `y`, bit `x` of the row.
+    modules: [u32; MAX_SIZE],
+    /// Modules of the finder, timing and alignment patterns and the format
+    /// information, which aren't masked.
+    function: [u32; MAX_SIZE],
+}
+
+impl QrCode {
+    /// Encode `data` in byte mode into the smallest version it fits.
+    pub fn encode(data: &[u8], ecc: Ecc) -> Result<Self, QrError> {
+        let version = (1..=MAX_VERSION)
+            .find(|v| data.len() <= ecc.capacity(*v))
+            .ok_or(QrError::TooLong)?;
+        let (data_len, ecc_len) = ecc.codewords(version);
+
+        let mut codewords = [0; MAX_CODEWORDS];
+        let mut bits = BitWriter::new(&mut codewords[..data_len]);
+        bits.push(0b0100, 4);
+        bits.push(data.len() as u32, 8);
+        for byte in data {
+            bits.push((*byte).into(), 8);
+        }
+        // Terminator, then up to the byte boundary.
+        let terminator = (data_len * 8 - bits.len).min(4);
+        bits.push(0, terminator);
+        bits.push(0, (8 - bits.len % 8) % 8);
+        let written = bits.len / 8;
+        let mut pad = [0xEC, 0x11].into_iter().cycle();
+        for byte in &mut codewords[written..data_len] {
+            *byte = pad.next().unwrap_or_default();
+        }
+        let (message, parity) = codewords.split_at_mut(data_len);
+        reed_solomon(message, &mut parity[..ecc_len]);
+
+        let size = 17 + 4 * version as usize;
+        let mut qr = Self {
+            version,
+            size,
+            modules: [0; MAX_SIZE],
+            function: [0; MAX_SIZE],
+        };
+        qr.draw_function_patterns();
+        qr.draw_codewords(&codewords[..data_len + ecc_len]);
+
+        let mut best = (i32::MAX, 0);
+        for mask in 0..8 {
+            qr.apply_mask(mask);
+            qr.draw_format(ecc, mask);
+            let penalty = qr.penalty();
+            if penalty < best.0 {
+                best = (penalty, mask);
+            }
+            // XOR again to undo it.
+            qr.apply_mask(mask);
+        }
+        qr.apply_mask(best.1);
+        qr.draw_format(ecc, best.1);
+        Ok(qr)
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Modules per side, without the quiet zone of 4 modules around it.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the module at column `x` and row `y` is dark, `false`
+    /// outside.
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[y] & 1 << x != 0
+    }
+
+    fn set(&mut self, x: usize, y: usize, dark: bool) {
+        if dark {
+            self.modules[y] |= 1 << x;
+        } else {
+            self.modules[y] &= !(1 << x);
+        }
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.set(x, y, dark);
+        self.function[y] |= 1 << x;
+    }
+
+    fn is_function(&self, x: usize, y: usize) -> bool {
+        self.function[y] & 1 << x != 0
+    }
+
+    fn draw_function_patterns(&mut self) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            self.draw_pattern(x, y, 4, |distance| distance != 2 && distance != 4);
+        }
+        // The only alignment pattern not overlapping a finder.
+        if self.version > 1 {
+            let at = size - 7;
+            self.draw_pattern(at, at, 2, |distance| distance != 1);
+        }
+        // Reserve the format information.
+        self.draw_format(Ecc::Low, 0);
+    }
+
+    /// Draw the square pattern of `radius` centered at `x`, `y`, dark by
+    /// Chebyshev distance from the center, clipped at the edges.
+    fn draw_pattern(&mut self, x: usize, y: usize, radius: i32, dark: impl Fn(i32) -> bool) {
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let (mx, my) = (x as i32 + dx, y as i32 + dy);
+                if (0..self.size as i32).contains(&mx) && (0..self.size as i32).contains(&my) {
+                    self.set_function(mx as usize, my as usize, dark(dx.abs().max(dy.abs())));
+                }
+            }
+        }
+    }
+
+    /// Draw both copies of the format information and the dark module.
+    fn draw_format(&mut self, ecc: Ecc, mask: u8) {
+        let data = ecc.format_bits() << 3 | u32::from(mask);
+        let mut remainder = data;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = (data << 10 | remainder) ^ 0x5412;
+        let bit = |i: usize| bits >> i & 1 != 0;
+
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        let size = self.size;
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Place the codewords in the zigzag order, two columns at a time from
+    /// the bottom right, skipping the vertical timing pattern.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size - 1;
+        loop {
+            if right == 6 {
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vertical in 0..size {
+                let y = if upward {
+                    size - 1 - vertical
+                } else {
+                    vertical
+                };
+                for x in [right, right - 1] {
+                    if !self.is_function(x, y) && i < codewords.len() * 8 {
+                        self.set(x, y, codewords[i / 8] >> (7 - i % 8) & 1 != 0);
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    /// XOR the data modules with the pattern of `mask`.
+    fn apply_mask(&mut self, mask: u8) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if invert && !self.is_function(x, y) {
+                    self.modules[y] ^= 1 << x;
+                }
+            }
+        }
+    }
+
+    /// Penalty of the current modules, lower is better.
+    fn penalty(&self) -> i32 {
+        let size = self.size;
+        let mut penalty = 0;
+        // Runs of the same color and finder-like patterns in rows and columns.
+        for transposed in [false, true] {
+            for a in 0..size {
+                let mut runs = RunHistory::new(size);
+                for b in 0..size {
+                    let dark = if transposed {
+                        self.get(a, b)
+                    } else {
+                        self.get(b, a)
+                    };
+                    penalty += runs.push(dark);
+                }
+                penalty += runs.finish();
+            }
+        }
+        // 2x2 blocks of the same color.
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let dark = self.get(x, y);
+                if dark == self.get(x + 1, y)
+                    && dark == self.get(x, y + 1)
+                    && dark == self.get(x + 1, y + 1)
+                {
+                    penalty += PENALTY_N2;
+                }
+            }
+        }
+        // Balance of dark and light modules, per 5% away from half.
+        let dark: u32 = self.modules[..size]
+            .iter()
+            .map(|row| row.count_ones())
+            .sum();
+        let total = (size * size) as i32;
+        let k = ((dark as i32 * 20 - total * 10).abs() + total - 1) / total - 1;
+        penalty + k * PENALTY_N4
+    }
+}
+
+/// Lengths of the last runs of a row or column for the penalty, the
+/// surrounding light area counted as part of the first and last runs.
+struct RunHistory {
+    size: i32,
+    history: [i32; 7],
+    dark: bool,
+    len: i32,
+}
+
+impl RunHistory {
+    fn new(size: usize) -> Self {
+        Self {
+            size: size as i32,
+            history: [0; 7],
+            dark: false,
+            len: 0,
+        }
+    }
+
+    /// Add a module, returns its penalty.
+    fn push(&mut self, dark: bool) -> i32 {
+        if dark == self.dark {
+            self.len += 1;
+            return match self.len {
+                5 => PENALTY_N1,
+                len if len > 5 => 1,
+                _ => 0,
+            };
+        }
+        self.add(self.len);
+        let penalty = if self.dark {
+            0
+        } else {
+            self.finder_patterns() * PENALTY_N3
+        };
+        self.dark = dark;
+        self.len = 1;
+        penalty
+    }
+
+    /// End of the row or column, returns the penalty of the last runs.
+    fn finish(&mut self) -> i32 {
+        if self.dark {
+            self.add(self.len);
+            self.len = 0;
+        }
+        self.add(self.len + self.size);
+        self.finder_patterns() * PENALTY_N3
+    }
+
+    fn add(&mut self, mut len: i32) {
+        if self.history[0] == 0 {
+            len += self.size;
+        }
+        self.history.copy_within(0..6, 1);
+        self.history[0] = len;
+    }
+
+    /// Dark-light-dark runs of 1:1:3:1:1 with 4 light modules on one side.
+    fn finder_patterns(&self) -> i32 {
+        let h = &self.history;
+        let n = h[1];
+        let core = n > 0 && h[2] == n && h[3] == n * 3 && h[4] == n && h[5] == n;
+        i32::from(core && h[0] >= n * 4 && h[6] >= n)
+            + i32::from(core && h[6] >= n * 4 && h[0] >= n)
+    }
+}
+
+/// Writes bits most significant first.
+struct BitWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> BitWriter<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Append the `count` low bits of `value`.
+    fn push(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            if value >> i & 1 != 0 {
+                self.buf[self.len / 8] |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+/// Multiply in GF(256) with the QR code polynomial 0x11D.
+fn gf_mul(a: u8, b: u8) -> u8 {
+    let mut product: u8 = 0;
+    for i in (0..8).rev() {
+        product = (product << 1) ^ ((product >> 7) * 0x1D);
+        product ^= ((b >> i) & 1) * a;
+    }
+    product
+}
+
+/// Reed-Solomon error correction codewords of `message` into `parity`.
+fn reed_solomon(message: &[u8], parity: &mut [u8]) {
+    // Generator polynomial, highest coefficient (1) dropped.
+    let degree = parity.len();
+    let mut generator = [0u8; MAX_CODEWORDS];
+    let generator = &mut generator[..degree];
+    generator[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            generator[j] = gf_mul(generator[j], root);
+            if j + 1 < degree {
+                generator[j] ^= generator[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+
+    parity.fill(0);
+    for byte in message {
+        let factor = byte ^ parity[0];
+        parity.copy_within(1.., 0);
+        parity[degree - 1] = 0;
+        for (p, g) in parity.iter_mut().zip(generator.iter()) {
+            *p ^= gf_mul(*g, factor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rows of a symbol as text, `#` dark.
+    fn rows(qr: &QrCode) -> Vec<String> {
+        (0..qr.size())
+            .map(|y| {
+                (0..qr.size())
+                    .map(|x| if qr.get(x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn versions_and_capacity() {
+        assert_eq!(Ecc::Low.capacity(1), 17);
+        assert_eq!(Ecc::Medium.capacity(3), 42);
+        let qr = QrCode::encode(b"", Ecc::Medium).unwrap();
+        assert_eq!((qr.version(), qr.size()), (1, 21));
+        let qr = QrCode::encode(&[b'x'; 18], Ecc::Low).unwrap();
+        assert_eq!((qr.version(), qr.size()), (2, 25));
+        let qr = QrCode::encode(&[b'x'; 53], Ecc::Low).unwrap();
+        assert_eq!((qr.version(), qr.size()), (3, 29));
+        assert_eq!(
+            QrCode::encode(&[b'x'; 43], Ecc::Medium),
+            Err(QrError::TooLong)
+        );
+        // Finder patterns in three corners, the dark module above the
+        // bottom left one.
+        let rows = rows(&qr);
+        assert_eq!(&rows[0][..8], "#######.");
+        assert_eq!(&rows[0][21..], ".#######");
+        assert_eq!(&rows[28][..8], "#######.");
+        assert!(qr.get(8, 21));
+        // Alignment pattern.
+        assert_eq!(&rows[21][20..25], "#...#");
+        assert_eq!(&rows[22][20..25], "#.#.#");
+    }
+}
@@ -0,0 +1,151 @@
+//! Frames and packet error rate of the two-board range test.
+//!
+//! The transmitter advertises a [`Frame`] with an incrementing sequence
+//! number, the receiver feeds every frame it hears into a [`PerCounter`].
+//! A frame is advertised several times until the next one replaces it, it
+//! counts as received if any copy arrives. Frames missing between two
+//! received sequence numbers count as lost.
+
+/// Marks a range test frame, "RT".
+const MAGIC: [u8; 2] = *b"RT";
+
+/// Length of an encoded [`Frame`].
+pub const FRAME_LEN: usize = 7;
+
+/// A gap in the sequence numbers larger than this is a restart of the
+/// transmitter, not lost frames.
+const MAX_GAP: u32 = 10_000;
+
+/// Payload of the range test advertisements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame {
+    pub seq: u32,
+    /// Configured TX power of the transmitter in dBm.
+    pub tx_power: i8,
+}
+
+impl Frame {
+    /// `magic (2) | sequence number (4, little endian) | TX power (1)`
+    pub fn encode(&self) -> [u8; FRAME_LEN] {
+        let mut frame = [0; FRAME_LEN];
+        frame[..2].copy_from_slice(&MAGIC);
+        frame[2..6].copy_from_slice(&self.seq.to_le_bytes());
+        frame[6] = self.tx_power as u8;
+        frame
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let data: &[u8; FRAME_LEN] = data.try_into().ok()?;
+        (data[..2] == MAGIC).then(|| Self {
+            seq: u32::from_le_bytes([data[2], data[3], data[4], data[5]]),
+            tx_power: data[6] as i8,
+        })
+    }
+}
+
+/// Statistics of one measurement window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Window {
+    /// Distinct frames received.
+    pub received: u32,
+    /// Frames sent by the transmitter.
+    pub expected: u32,
+    /// Mean, minimum and maximum RSSI in dBm, `None` without frames.
+    pub rssi: Option<(i8, i8, i8)>,
+}
+
+impl Window {
+    /// Packet error rate in per mille, `None` without frames.
+    pub fn per_permille(&self) -> Option<u16> {
+        let lost = self.expected.checked_sub(self.received)?;
+        (self.expected > 0).then(|| (lost as u64 * 1000 / self.expected as u64) as u16)
+    }
+}
+
+/// Counts received and expected frames.
+#[derive(Debug, Default)]
+pub struct PerCounter {
+    last_seq: Option<u32>,
+    received: u32,
+    expected: u32,
+    rssi_sum: i32,
+    rssi_min: i8,
+    rssi_max: i8,
+}
+
+impl PerCounter {
+    pub const fn new() -> Self {
+        Self {
+            last_seq: None,
+            received: 0,
+            expected: 0,
+            rssi_sum: 0,
+            rssi_min: i8::MAX,
+            rssi_max: i8::MIN,
+        }
+    }
+
+    /// Record a received frame, repeated copies are ignored.
+    pub fn record(&mut self, frame: &Frame, rssi: i8) {
+        let gap = self.last_seq.map(|last| frame.seq.wrapping_sub(last));
+        match gap {
+            Some(0) => return,
+            Some(gap) if gap <= MAX_GAP => self.expected += gap,
+            // First frame, or the transmitter restarted.
+            _ => self.expected += 1,
+        }
+        self.last_seq = Some(frame.seq);
+        self.received += 1;
+        self.rssi_sum += rssi as i32;
+        self.rssi_min = self.rssi_min.min(rssi);
+        self.rssi_max = self.rssi_max.max(rssi);
+    }
+
+    /// Statistics since the last call, the sequence continues.
+    pub fn take_window(&mut self) -> Window {
+        let window = Window {
+            received: self.received,
+            expected: self.expected,
+            rssi: (self.received > 0).then(|| {
+                let mean = self.rssi_sum / self.received as i32;
+                (mean as i8, self.rssi_min, self.rssi_max)
+            }),
+        };
+        *self = Self {
+            last_seq: self.last_seq,
+            ..Self::new()
+        };
+        window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_lost_frames() {
+        let frame = |seq| Frame { seq, tx_power: 0 };
+        assert_eq!(Frame::decode(&frame(7).encode()), Some(frame(7)));
+        assert_eq!(Frame::decode(&[0; FRAME_LEN]), None);
+
+        let mut counter = PerCounter::new();
+        for (seq, rssi) in [(10, -60), (10, -61), (11, -70), (14, -50)] {
+            counter.record(&frame(seq), rssi);
+        }
+        let window = counter.take_window();
+        assert_eq!(window.received, 3);
+        assert_eq!(window.expected, 5);
+        assert_eq!(window.rssi, Some((-60, -70, -50)));
+        assert_eq!(window.per_permille(), Some(400));
+
+        // Continues from 14, then the transmitter restarts.
+        counter.record(&frame(16), -60);
+        counter.record(&frame(0), -60);
+        let window = counter.take_window();
+        assert_eq!((window.received, window.expected), (2, 3));
+        assert_eq!(counter.take_window().per_permille(), None);
+    }
+}
@@ -0,0 +1,159 @@
+//! Distance from the round trip time of raw radio packets.
+//!
+//! The initiator sends a [`Poll`], the responder echoes it after the fixed
+//! turnaround of the RADIO shortcuts. TIMER0 of the initiator captures the
+//! address events of both packets, the difference is the round trip time in
+//! ticks of 16 MHz. One tick is 9.37 m of distance, so single exchanges are
+//! far too coarse, but the jitter of many exchanges averages to a usable
+//! estimate. The constant part (packet length, turnaround, delays of the
+//! radios) is removed by [calibrating](Estimator::calibrate) at a known
+//! distance.
+
+use heapless::Vec;
+
+/// Marks a ranging poll, "RG".
+const MAGIC: [u8; 2] = *b"RG";
+
+/// Length of an encoded [`Poll`].
+pub const POLL_LEN: usize = 4;
+
+/// Frequency of the capturing timer.
+pub const TICK_HZ: u32 = 16_000_000;
+
+/// One way distance of one tick of round trip time in 1/100 cm,
+/// `c / TICK_HZ / 2`.
+const CENTI_CM_PER_TICK: i64 = 93_685;
+
+/// Round trip times are averaged in 1/16 ticks.
+const SUBTICKS: i64 = 16;
+
+/// Packet sent by the initiator and echoed by the responder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Poll {
+    pub seq: u16,
+}
+
+impl Poll {
+    /// `magic (2) | sequence number (2, little endian)`
+    pub fn encode(&self) -> [u8; POLL_LEN] {
+        let mut poll = [0; POLL_LEN];
+        poll[..2].copy_from_slice(&MAGIC);
+        poll[2..].copy_from_slice(&self.seq.to_le_bytes());
+        poll
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let data: &[u8; POLL_LEN] = data.try_into().ok()?;
+        (data[..2] == MAGIC).then(|| Self {
+            seq: u16::from_le_bytes([data[2], data[3]]),
+        })
+    }
+}
+
+/// Distance estimated from a batch of round trip times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Estimate {
+    /// Distance in cm, negative values are noise close to the calibration
+    /// point.
+    pub distance_cm: i32,
+    /// Round trip times the estimate is based on.
+    pub samples: u16,
+    /// Averaged round trip time in 1/16 ticks, see
+    /// [`Estimator::calibrate`].
+    pub rtt_subticks: i64,
+}
+
+/// Collects round trip times and turns them into [`Estimate`]s.
+#[derive(Debug)]
+pub struct Estimator<const N: usize> {
+    samples: Vec<u32, N>,
+    /// Round trip time of zero distance in 1/16 ticks.
+    offset: i64,
+}
+
+impl<const N: usize> Estimator<N> {
+    /// An estimator with the zero distance round trip time of a previous
+    /// calibration.
+    pub const fn new(offset_subticks: i64) -> Self {
+        Self {
+            samples: Vec::new(),
+            offset: offset_subticks,
+        }
+    }
+
+    /// Zero distance round trip time in 1/16 ticks, e.g. to store it.
+    pub fn offset_subticks(&self) -> i64 {
+        self.offset
+    }
+
+    /// Add a round trip time in ticks, returning whether the batch is full.
+    pub fn push(&mut self, rtt_ticks: u32) -> bool {
+        let _ = self.samples.push(rtt_ticks);
+        self.samples.is_full()
+    }
+
+    /// Estimate the distance from the collected round trip times and start
+    /// a new batch, `None` without any.
+    ///
+    /// The fastest and slowest quarter are dropped (multipath, missed
+    /// captures), the rest is averaged.
+    pub fn estimate(&mut self) -> Option<Estimate> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        self.samples.sort_unstable();
+        let trim = self.samples.len() / 4;
+        let kept = &self.samples[trim..self.samples.len() - trim];
+        let sum: i64 = kept.iter().map(|&rtt| rtt as i64).sum();
+        let rtt_subticks = sum * SUBTICKS / kept.len() as i64;
+        let distance = (rtt_subticks - self.offset) * CENTI_CM_PER_TICK / SUBTICKS / 100;
+        let estimate = Estimate {
+            distance_cm: distance.clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+            samples: self.samples.len() as u16,
+            rtt_subticks,
+        };
+        self.samples.clear();
+        Some(estimate)
+    }
+
+    /// Take `estimate`, measured at `known_cm`, as the reference for the
+    /// following estimates.
+    pub fn calibrate(&mut self, estimate: &Estimate, known_cm: u32) {
+        self.offset = estimate.rtt_subticks - known_cm as i64 * 100 * SUBTICKS / CENTI_CM_PER_TICK;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_distance() {
+        assert_eq!(
+            Poll::decode(&Poll { seq: 513 }.encode()),
+            Some(Poll { seq: 513 })
+        );
+        assert_eq!(Poll::decode(&[0; POLL_LEN]), None);
+
+        let mut estimator = Estimator::<8>::new(0);
+        assert_eq!(estimator.estimate(), None);
+        // Outliers at both ends are dropped.
+        for rtt in [2000, 2000, 2001, 2001, 2000, 2001, 1000, 9000] {
+            estimator.push(rtt);
+        }
+        let estimate = estimator.estimate().unwrap();
+        assert_eq!(estimate.samples, 8);
+        assert_eq!(estimate.rtt_subticks, 2000 * 16 + 8);
+
+        // Calibrated with the boards side by side, half a tick more is
+        // 4.68 m further.
+        estimator.calibrate(&estimate, 0);
+        for rtt in [2000, 2001, 2001, 2001] {
+            assert!(!estimator.push(rtt));
+        }
+        let estimate = estimator.estimate().unwrap();
+        assert_eq!(estimate.distance_cm, 468);
+    }
+}
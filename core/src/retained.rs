@@ -0,0 +1,153 @@
+//! State kept in retained RAM across resets.
+//!
+//! The record lives in a RAM region the startup code doesn't initialize,
+//! so after a watchdog or soft reset, or a wake from System OFF with RAM
+//! retention, it still holds what the previous run stored. Power-on leaves
+//! random contents, which the magic and CRC reject.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::crc::crc32;
+
+/// Marks a sealed record ("RETN").
+pub const MAGIC: u32 = 0x4E54_4552;
+
+/// [`RetainedState::flags`]: [`RetainedState::boot_utc`] is known.
+pub const CLOCK_VALID: u32 = 1 << 0;
+
+/// [`RetainedState::flags`]: the last fix is known.
+pub const FIX_VALID: u32 = 1 << 1;
+
+/// State of the previous run (little endian).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct RetainedState {
+    pub magic: u32,
+    /// [`CLOCK_VALID`] and [`FIX_VALID`].
+    pub flags: u32,
+    /// UTC seconds since the Unix epoch at the boot of the run.
+    pub boot_utc: i64,
+    /// Seconds since the boot of the run when the record was last sealed.
+    pub uptime_secs: u32,
+    /// Resets with preserved state since the last cold boot.
+    pub warm_boots: u32,
+    /// Last fix in 1e-7 degrees.
+    pub latitude_e7: i32,
+    pub longitude_e7: i32,
+    /// Counters since the last cold boot.
+    pub advertisements: u32,
+    pub connections: u32,
+    pub fixes: u32,
+    /// CRC-32 of the fields above.
+    pub crc: u32,
+}
+
+impl RetainedState {
+    /// Bytes of a record.
+    pub const LEN: usize = core::mem::size_of::<Self>();
+
+    /// An empty record, as after a cold boot.
+    pub fn new() -> Self {
+        Self {
+            magic: MAGIC,
+            ..Self::zeroed()
+        }
+    }
+
+    fn checksum(&self) -> u32 {
+        crc32(&bytemuck::bytes_of(self)[..Self::LEN - 4])
+    }
+
+    /// Compute the CRC once all fields are filled in.
+    pub fn seal(&mut self) {
+        self.crc = self.checksum();
+    }
+
+    /// A sealed record, not random RAM contents.
+    pub fn is_valid(&self) -> bool {
+        self.magic == MAGIC && self.crc == self.checksum()
+    }
+
+    /// The UTC time the previous run reached, if its clock was known.
+    pub fn utc_at_reset(&self) -> Option<i64> {
+        (self.flags & CLOCK_VALID != 0).then(|| self.boot_utc + i64::from(self.uptime_secs))
+    }
+
+    /// The last fix as latitude and longitude in degrees.
+    pub fn last_fix(&self) -> Option<(f64, f64)> {
+        (self.flags & FIX_VALID != 0).then(|| {
+            (
+                f64::from(self.latitude_e7) / 1e7,
+                f64::from(self.longitude_e7) / 1e7,
+            )
+        })
+    }
+
+    pub fn set_fix(&mut self, latitude: f64, longitude: f64) {
+        self.latitude_e7 = libm::round(latitude * 1e7) as i32;
+        self.longitude_e7 = libm::round(longitude * 1e7) as i32;
+        self.flags |= FIX_VALID;
+    }
+
+    /// The UTC time was `utc` at `uptime_secs` into the run.
+    pub fn set_clock(&mut self, utc: i64, uptime_secs: u32) {
+        self.boot_utc = utc - i64::from(uptime_secs);
+        self.flags |= CLOCK_VALID;
+    }
+
+    /// The record for the next run after a reset: the clock continues where
+    /// this one stopped if `clock_kept`, which a wake from System OFF
+    /// doesn't.
+    pub fn resume(&self, clock_kept: bool) -> Self {
+        let mut next = *self;
+        next.warm_boots = self.warm_boots.wrapping_add(1);
+        next.uptime_secs = 0;
+        match self.utc_at_reset() {
+            Some(utc) if clock_kept => next.boot_utc = utc,
+            _ => next.flags &= !CLOCK_VALID,
+        }
+        next.seal();
+        next
+    }
+}
+
+impl Default for RetainedState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_state_resumes() {
+        let mut state = RetainedState::new();
+        assert!(!state.is_valid());
+        state.set_clock(1_700_000_100, 100);
+        state.set_fix(52.520_008_1, -13.404_954_2);
+        state.uptime_secs = 160;
+        state.fixes = 7;
+        state.seal();
+        assert!(state.is_valid());
+        assert_eq!(RetainedState::LEN, 48);
+        assert_eq!(state.utc_at_reset(), Some(1_700_000_160));
+        let (lat, lon) = state.last_fix().unwrap();
+        assert!((lat - 52.520_008_1).abs() < 1e-7 && (lon + 13.404_954_2).abs() < 1e-7);
+
+        let warm = state.resume(true);
+        assert!(warm.is_valid());
+        assert_eq!((warm.warm_boots, warm.fixes), (1, 7));
+        assert_eq!(warm.boot_utc, 1_700_000_160);
+        let woken = state.resume(false);
+        assert_eq!(woken.utc_at_reset(), None);
+        assert!(woken.last_fix().is_some());
+
+        // Random RAM contents are not a record.
+        state.fixes = 8;
+        assert!(!state.is_valid());
+        assert!(!bytemuck::pod_read_unaligned::<RetainedState>(&[0xA5; 48]).is_valid());
+    }
+}
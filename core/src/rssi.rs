@@ -0,0 +1,97 @@
+//! Proximity of a connected central from the RSSI of the connection.
+//!
+//! A single RSSI reading swings by 10 dB and more with the orientation of
+//! the phone and the body in between. [`Proximity`] smooths the readings and
+//! only reports the central as near once the smoothed RSSI reaches the
+//! threshold, and as far again [`HYSTERESIS_DB`] below it, so the reported
+//! state doesn't toggle at the edge.
+
+/// Drop below the threshold from which a near central is far again.
+pub const HYSTERESIS_DB: i16 = 6;
+
+/// Weight of a new reading in the smoothed RSSI: 1/4.
+const SMOOTHING_SHIFT: u32 = 2;
+
+/// Smoothed RSSI with a near/far state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Proximity {
+    /// Smoothed RSSI in 1/16 dBm, `None` before the first reading.
+    smoothed: Option<i16>,
+    near: bool,
+}
+
+impl Proximity {
+    pub const fn new() -> Self {
+        Self {
+            smoothed: None,
+            near: false,
+        }
+    }
+
+    /// Smoothed RSSI in dBm.
+    pub fn rssi(&self) -> Option<i8> {
+        self.smoothed.map(|smoothed| (smoothed >> 4) as i8)
+    }
+
+    pub fn is_near(&self) -> bool {
+        self.near
+    }
+
+    /// Add a reading in dBm. Returns `true` if the central came near, i.e.
+    /// the smoothed RSSI reached `threshold_dbm`.
+    pub fn update(&mut self, rssi: i8, threshold_dbm: i8) -> bool {
+        let reading = (rssi as i16) << 4;
+        let smoothed = match self.smoothed {
+            Some(smoothed) => smoothed + ((reading - smoothed) >> SMOOTHING_SHIFT),
+            None => reading,
+        };
+        self.smoothed = Some(smoothed);
+        let threshold = (threshold_dbm as i16) << 4;
+        let was_near = self.near;
+        if smoothed >= threshold {
+            self.near = true;
+        } else if smoothed < threshold - (HYSTERESIS_DB << 4) {
+            self.near = false;
+        }
+        self.near && !was_near
+    }
+
+    /// Forget the readings, e.g. when the central disconnected.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approach_with_hysteresis() {
+        let mut proximity = Proximity::new();
+        assert!(!proximity.update(-80, -55));
+        assert_eq!(proximity.rssi(), Some(-80));
+
+        // A single strong reading doesn't make it near.
+        assert!(!proximity.update(-40, -55));
+        assert!(!proximity.is_near());
+
+        let approached = (0..10).filter(|_| proximity.update(-45, -55)).count();
+        assert_eq!(approached, 1);
+        assert!(proximity.is_near());
+
+        // Just below the threshold stays near, well below is far.
+        for _ in 0..20 {
+            assert!(!proximity.update(-58, -55));
+        }
+        assert!(proximity.is_near());
+        for _ in 0..20 {
+            proximity.update(-70, -55);
+        }
+        assert!(!proximity.is_near());
+
+        proximity.reset();
+        assert_eq!(proximity.rssi(), None);
+    }
+}
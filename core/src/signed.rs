@@ -0,0 +1,149 @@
+//! Envelope of signed updates written over BLE.
+//!
+//! A signed blob is a [`HEADER_LEN`] byte header (magic `SG`, the [`Kind`],
+//! a reserved zero byte and the sequence number), the payload and an
+//! Ed25519 signature of header and payload. The kind in the signed header
+//! keeps a signature of one kind of update from being replayed as another.
+//! The sequence number grows with every update of a kind, the device takes
+//! only updates newer than the last one, see [`Signed::check_sequence`].
+//! Settings blobs and DFU manifests use it.
+
+/// Magic at the start of a signed blob.
+pub const MAGIC: [u8; 2] = *b"SG";
+
+/// Bytes of the header in front of the payload.
+pub const HEADER_LEN: usize = 8;
+
+/// Bytes of an Ed25519 signature.
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Bytes of an Ed25519 public key.
+pub const KEY_LEN: usize = 32;
+
+/// What a blob updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Kind {
+    Settings = 1,
+    DfuManifest = 2,
+}
+
+/// Reasons a signed blob is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SignedError {
+    /// No signed envelope, or it is truncated.
+    Unsigned,
+    /// Signed for another kind of update.
+    Kind,
+    /// The signature doesn't match the payload and the public key.
+    Signature,
+    /// Not newer than the last update taken, e.g. an old blob sent again.
+    Replayed,
+    /// The firmware was built without a public key.
+    NoKey,
+}
+
+/// Length of a signed blob with `payload_len` bytes of payload.
+pub const fn signed_len(payload_len: usize) -> usize {
+    HEADER_LEN + payload_len + SIGNATURE_LEN
+}
+
+/// A blob split into its parts, not verified yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signed<'a> {
+    /// Header and payload, the signed bytes.
+    pub message: &'a [u8],
+    pub payload: &'a [u8],
+    pub signature: &'a [u8; SIGNATURE_LEN],
+    /// Sequence number from the header.
+    pub sequence: u32,
+}
+
+impl<'a> Signed<'a> {
+    /// Split a blob signed for `kind`.
+    pub fn parse(blob: &'a [u8], kind: Kind) -> Result<Self, SignedError> {
+        if blob.len() < signed_len(0) || blob[..2] != MAGIC || blob[3] != 0 {
+            return Err(SignedError::Unsigned);
+        }
+        if blob[2] != kind as u8 {
+            return Err(SignedError::Kind);
+        }
+        let (message, signature) = blob.split_at(blob.len() - SIGNATURE_LEN);
+        Ok(Self {
+            message,
+            payload: &message[HEADER_LEN..],
+            signature: signature.try_into().unwrap(),
+            sequence: u32::from_le_bytes(blob[4..HEADER_LEN].try_into().unwrap()),
+        })
+    }
+
+    /// Take the blob only if it is newer than the update with sequence
+    /// number `last`.
+    pub fn check_sequence(&self, last: u32) -> Result<(), SignedError> {
+        if self.sequence <= last {
+            return Err(SignedError::Replayed);
+        }
+        Ok(())
+    }
+}
+
+/// The header of a blob of `kind` with sequence number `sequence`, to sign
+/// together with the payload.
+pub fn header(kind: Kind, sequence: u32) -> [u8; HEADER_LEN] {
+    let [s0, s1, s2, s3] = sequence.to_le_bytes();
+    [MAGIC[0], MAGIC[1], kind as u8, 0, s0, s1, s2, s3]
+}
+
+/// Decode a public key given as 64 hex digits.
+pub fn decode_key(hex: &str) -> Option<[u8; KEY_LEN]> {
+    if hex.len() != 2 * KEY_LEN {
+        return None;
+    }
+    let mut key = [0; KEY_LEN];
+    let nibble = |c: u8| char::from(c).to_digit(16);
+    for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        *byte = (nibble(digits[0])? << 4 | nibble(digits[1])?) as u8;
+    }
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope() {
+        let mut blob = std::vec::Vec::new();
+        blob.extend_from_slice(&header(Kind::Settings, 7));
+        blob.extend_from_slice(b"payload");
+        blob.extend_from_slice(&[0xA5; SIGNATURE_LEN]);
+        assert_eq!(blob.len(), signed_len(7));
+        let signed = Signed::parse(&blob, Kind::Settings).unwrap();
+        assert_eq!(signed.payload, b"payload");
+        assert_eq!(signed.message, &blob[..HEADER_LEN + 7]);
+        assert_eq!(signed.signature, &[0xA5; SIGNATURE_LEN]);
+        assert_eq!(signed.sequence, 7);
+        assert_eq!(signed.check_sequence(6), Ok(()));
+        assert_eq!(signed.check_sequence(7), Err(SignedError::Replayed));
+
+        assert_eq!(
+            Signed::parse(&blob, Kind::DfuManifest),
+            Err(SignedError::Kind)
+        );
+        assert_eq!(
+            Signed::parse(&blob[..SIGNATURE_LEN], Kind::Settings),
+            Err(SignedError::Unsigned)
+        );
+        assert_eq!(
+            Signed::parse(&[0; 80], Kind::Settings),
+            Err(SignedError::Unsigned)
+        );
+
+        let key = decode_key(&"0f".repeat(KEY_LEN)).unwrap();
+        assert_eq!(key, [0x0F; KEY_LEN]);
+        assert_eq!(decode_key("0f"), None);
+        assert_eq!(decode_key(&"+f".repeat(KEY_LEN)), None);
+    }
+}
@@ -0,0 +1,276 @@
+//! Simple Management Protocol (SMP), the transport of mcumgr.
+//!
+//! Every message is a [`Header`] followed by a CBOR map, see [`cbor`].
+//! Over BLE a message may span several writes or notifications, the
+//! [`Assembler`] collects the writes of a request until the length in the
+//! header is complete. Commands are addressed by a group and an id within
+//! the group, failures are reported with an [`Rc`] by [`encode_rc`].
+
+pub mod cbor;
+
+/// Bytes of the message header.
+pub const HEADER_LEN: usize = 8;
+
+/// Operations of a message.
+pub mod op {
+    pub const READ: u8 = 0;
+    pub const READ_RSP: u8 = 1;
+    pub const WRITE: u8 = 2;
+    pub const WRITE_RSP: u8 = 3;
+}
+
+/// Management groups and their command ids.
+pub mod group {
+    pub const OS: u16 = 0;
+    pub const IMAGE: u16 = 1;
+    pub const STATS: u16 = 2;
+}
+
+/// Commands of the OS group.
+pub mod os {
+    pub const ECHO: u8 = 0;
+    pub const RESET: u8 = 5;
+    pub const MCUMGR_PARAMS: u8 = 6;
+}
+
+/// Commands of the image group.
+pub mod image {
+    pub const STATE: u8 = 0;
+}
+
+/// Commands of the stats group.
+pub mod stats {
+    pub const READ: u8 = 0;
+    pub const LIST: u8 = 1;
+}
+
+/// Result codes of a failed command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Rc {
+    Unknown = 1,
+    NoMemory = 2,
+    Invalid = 3,
+    NoEntry = 5,
+    MessageSize = 7,
+    NotSupported = 8,
+}
+
+impl From<cbor::CborError> for Rc {
+    fn from(err: cbor::CborError) -> Self {
+        match err {
+            cbor::CborError::BufferFull => Rc::MessageSize,
+            cbor::CborError::Truncated | cbor::CborError::Malformed | cbor::CborError::Depth => {
+                Rc::Invalid
+            }
+        }
+    }
+}
+
+/// Header of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Header {
+    /// One of [`op`].
+    pub op: u8,
+    /// Protocol version, 0 (SMP v1) or 1 (SMP v2).
+    pub version: u8,
+    pub flags: u8,
+    /// Length of the CBOR payload.
+    pub len: u16,
+    pub group: u16,
+    /// Sequence number, echoed in the response.
+    pub seq: u8,
+    /// Command id within the group.
+    pub id: u8,
+}
+
+impl Header {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; HEADER_LEN] = bytes.get(..HEADER_LEN)?.try_into().ok()?;
+        Some(Self {
+            op: bytes[0] & 0x07,
+            version: bytes[0] >> 3 & 0x03,
+            flags: bytes[1],
+            len: u16::from_be_bytes([bytes[2], bytes[3]]),
+            group: u16::from_be_bytes([bytes[4], bytes[5]]),
+            seq: bytes[6],
+            id: bytes[7],
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let len = self.len.to_be_bytes();
+        let group = self.group.to_be_bytes();
+        [
+            (self.version & 0x03) << 3 | self.op & 0x07,
+            self.flags,
+            len[0],
+            len[1],
+            group[0],
+            group[1],
+            self.seq,
+            self.id,
+        ]
+    }
+
+    /// Header of the response with a payload of `len` bytes.
+    pub fn response(&self, len: u16) -> Self {
+        Self {
+            op: if self.op == op::WRITE {
+                op::WRITE_RSP
+            } else {
+                op::READ_RSP
+            },
+            flags: 0,
+            len,
+            ..*self
+        }
+    }
+}
+
+/// Write the error response payload to the request `header`:
+/// `{"rc": rc}` in SMP v1, `{"err": {"group": group, "rc": rc}}` in v2.
+pub fn encode_rc(out: &mut [u8], header: &Header, rc: Rc) -> Result<usize, cbor::CborError> {
+    let mut encoder = cbor::Encoder::new(out);
+    encoder.map(1)?;
+    if header.version > 0 {
+        encoder
+            .text("err")?
+            .map(2)?
+            .text("group")?
+            .uint(header.group as u64)?;
+    }
+    encoder.text("rc")?.uint(rc as u64)?;
+    Ok(encoder.len())
+}
+
+/// Collects the fragments of a request of up to `N` bytes.
+pub struct Assembler<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for Assembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Assembler<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Add a fragment, returning the header and payload once the request
+    /// is complete. A request longer than `N` is dropped with
+    /// [`Rc::MessageSize`], bytes after the end of a request are ignored.
+    pub fn push(&mut self, fragment: &[u8]) -> Result<Option<(Header, &[u8])>, Rc> {
+        let Some(buf) = self.buf.get_mut(self.len..self.len + fragment.len()) else {
+            self.len = 0;
+            return Err(Rc::MessageSize);
+        };
+        buf.copy_from_slice(fragment);
+        self.len += fragment.len();
+        let Some(header) = Header::parse(&self.buf[..self.len]) else {
+            return Ok(None);
+        };
+        let end = HEADER_LEN + header.len as usize;
+        if end > N {
+            self.len = 0;
+            return Err(Rc::MessageSize);
+        }
+        if self.len < end {
+            return Ok(None);
+        }
+        self.len = 0;
+        Ok(Some((header, &self.buf[HEADER_LEN..end])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cbor::{Decoder, Encoder, Item, map_get};
+    use super::*;
+
+    #[test]
+    fn echo_request_is_assembled_from_fragments() {
+        // mcumgr echo "hi": {"d": "hi"}
+        let request = [
+            0x0A, 0, 0, 6, 0, 0, 0x42, 0, 0xA1, 0x61, b'd', 0x62, b'h', b'i',
+        ];
+        let mut assembler = Assembler::<64>::new();
+        assert_eq!(assembler.push(&request[..5]), Ok(None));
+        let (header, payload) = assembler.push(&request[5..]).unwrap().unwrap();
+        assert_eq!(header.op, op::WRITE);
+        assert_eq!(header.version, 1);
+        assert_eq!(
+            (header.group, header.id, header.seq),
+            (group::OS, os::ECHO, 0x42)
+        );
+        assert_eq!(map_get(payload, "d"), Ok(Some(Item::Text("hi"))));
+        assert_eq!(map_get(payload, "x"), Ok(None));
+
+        let response = header.response(4).to_bytes();
+        assert_eq!(response, [0x0B, 0, 0, 4, 0, 0, 0x42, 0]);
+    }
+
+    #[test]
+    fn cbor_round_trip() {
+        let mut buf = [0; 64];
+        let mut encoder = Encoder::new(&mut buf);
+        encoder
+            .map(3)
+            .and_then(|e| e.text("fields")?.map(1)?.text("errors")?.uint(300))
+            .and_then(|e| e.text("neg")?.int(-500))
+            .and_then(|e| e.text("ok")?.array(2)?.bool(true)?.bytes(&[1, 2]))
+            .unwrap();
+        let len = encoder.len();
+        let payload = &buf[..len];
+        assert_eq!(&payload[..3], &[0xA3, 0x66, b'f']);
+        // The nested map is skipped while looking for later keys.
+        assert_eq!(
+            map_get(payload, "neg").unwrap().and_then(|i| i.as_int()),
+            Some(-500)
+        );
+        assert_eq!(map_get(payload, "ok"), Ok(Some(Item::Array(2))));
+
+        let mut decoder = Decoder::new(payload);
+        decoder.item().unwrap();
+        decoder.skip().unwrap();
+        assert_eq!(decoder.item(), Ok(Item::Map(1)));
+
+        let mut header = Header::parse(&[0, 0, 0, 0, 0, 2, 0, 0]).unwrap();
+        let mut rc = [0; 5];
+        assert_eq!(encode_rc(&mut rc, &header, Rc::NotSupported), Ok(5));
+        assert_eq!(rc, [0xA1, 0x62, b'r', b'c', 8]);
+        assert_eq!(
+            encode_rc(&mut rc[..4], &header, Rc::NotSupported),
+            Err(cbor::CborError::BufferFull)
+        );
+        header.version = 1;
+        let mut err = [0; 32];
+        let len = encode_rc(&mut err, &header, Rc::NoEntry).unwrap();
+        assert_eq!(map_get(&err[..len], "err"), Ok(Some(Item::Map(2))));
+    }
+
+    #[test]
+    fn nested_tags_are_limited() {
+        // {"d": 6(6(...(0)))}
+        let mut payload = std::vec![0xA1, 0x61, b'd'];
+        payload.extend([0xC6; 1000]);
+        payload.push(0);
+        assert_eq!(map_get(&payload, "d"), Err(cbor::CborError::Depth));
+        assert_eq!(
+            Decoder::new(&payload[3..]).skip(),
+            Err(cbor::CborError::Depth)
+        );
+
+        let tagged = [0xC6, 0xC6, 0];
+        assert_eq!(Decoder::new(&tagged).item(), Ok(Item::Other));
+    }
+}
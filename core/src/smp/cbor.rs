@@ -0,0 +1,251 @@
+//! Minimal CBOR encoding and decoding for SMP payloads.
+//!
+//! Only definite lengths are supported, which is what the mcumgr clients
+//! send. Floats and other simple values are decoded but not interpreted.
+
+/// Errors of the CBOR encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CborError {
+    /// The output buffer is full.
+    BufferFull,
+    /// The input ended in the middle of an item.
+    Truncated,
+    /// The input isn't valid CBOR or uses indefinite lengths.
+    Malformed,
+    /// Tags are nested deeper than [`MAX_TAG_DEPTH`].
+    Depth,
+}
+
+/// Tags nested at most around a value, they are skipped recursively.
+pub const MAX_TAG_DEPTH: u8 = 8;
+
+const UINT: u8 = 0;
+const NINT: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+const SIMPLE: u8 = 7;
+
+/// Writes CBOR items into a buffer.
+pub struct Encoder<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Bytes written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> Result<&mut Self, CborError> {
+        self.buf
+            .get_mut(self.len..self.len + bytes.len())
+            .ok_or(CborError::BufferFull)?
+            .copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(self)
+    }
+
+    fn head(&mut self, major: u8, arg: u64) -> Result<&mut Self, CborError> {
+        let major = major << 5;
+        match arg {
+            0..24 => self.put(&[major | arg as u8]),
+            24..0x100 => self.put(&[major | 24, arg as u8]),
+            0x100..0x1_0000 => self.put(&[major | 25])?.put(&(arg as u16).to_be_bytes()),
+            0x1_0000..0x1_0000_0000 => self.put(&[major | 26])?.put(&(arg as u32).to_be_bytes()),
+            _ => self.put(&[major | 27])?.put(&arg.to_be_bytes()),
+        }
+    }
+
+    pub fn uint(&mut self, value: u64) -> Result<&mut Self, CborError> {
+        self.head(UINT, value)
+    }
+
+    pub fn int(&mut self, value: i64) -> Result<&mut Self, CborError> {
+        if value < 0 {
+            self.head(NINT, !value as u64)
+        } else {
+            self.head(UINT, value as u64)
+        }
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> Result<&mut Self, CborError> {
+        self.head(BYTES, value.len() as u64)?.put(value)
+    }
+
+    pub fn text(&mut self, value: &str) -> Result<&mut Self, CborError> {
+        self.head(TEXT, value.len() as u64)?.put(value.as_bytes())
+    }
+
+    pub fn bool(&mut self, value: bool) -> Result<&mut Self, CborError> {
+        self.put(&[SIMPLE << 5 | if value { 21 } else { 20 }])
+    }
+
+    /// Start an array of `len` items.
+    pub fn array(&mut self, len: usize) -> Result<&mut Self, CborError> {
+        self.head(ARRAY, len as u64)
+    }
+
+    /// Start a map of `len` key and value pairs.
+    pub fn map(&mut self, len: usize) -> Result<&mut Self, CborError> {
+        self.head(MAP, len as u64)
+    }
+}
+
+/// A decoded data item. Arrays and maps only carry their length, the
+/// items follow.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Item<'a> {
+    Uint(u64),
+    /// Negative integer, `-1 - n`.
+    Nint(u64),
+    Bytes(&'a [u8]),
+    Text(&'a str),
+    Array(usize),
+    Map(usize),
+    Bool(bool),
+    Null,
+    /// Tags, floats and other simple values.
+    Other,
+}
+
+impl Item<'_> {
+    /// The value as signed integer, if it is an integer in range.
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            Item::Uint(n) => i64::try_from(n).ok(),
+            Item::Nint(n) => i64::try_from(n).ok().map(|n| -1 - n),
+            _ => None,
+        }
+    }
+}
+
+/// Reads CBOR items from a buffer.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Tags around the item being decoded.
+    depth: u8,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CborError> {
+        let end = self.pos.checked_add(n).ok_or(CborError::Malformed)?;
+        let bytes = self.data.get(self.pos..end).ok_or(CborError::Truncated)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn arg(&mut self, info: u8) -> Result<u64, CborError> {
+        let n = match info {
+            0..24 => return Ok(info as u64),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(CborError::Malformed),
+        };
+        let mut be = [0; 8];
+        be[8 - n..].copy_from_slice(self.take(n)?);
+        Ok(u64::from_be_bytes(be))
+    }
+
+    fn length(&mut self, info: u8) -> Result<usize, CborError> {
+        usize::try_from(self.arg(info)?).map_err(|_| CborError::Malformed)
+    }
+
+    /// Decode the next item.
+    pub fn item(&mut self) -> Result<Item<'a>, CborError> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1F);
+        Ok(match major {
+            UINT => Item::Uint(self.arg(info)?),
+            NINT => Item::Nint(self.arg(info)?),
+            BYTES => {
+                let len = self.length(info)?;
+                Item::Bytes(self.take(len)?)
+            }
+            TEXT => {
+                let len = self.length(info)?;
+                let text = self.take(len)?;
+                Item::Text(core::str::from_utf8(text).map_err(|_| CborError::Malformed)?)
+            }
+            ARRAY => Item::Array(self.length(info)?),
+            MAP => Item::Map(self.length(info)?),
+            TAG => {
+                self.arg(info)?;
+                if self.depth >= MAX_TAG_DEPTH {
+                    return Err(CborError::Depth);
+                }
+                self.depth += 1;
+                let skipped = self.skip();
+                self.depth -= 1;
+                skipped?;
+                Item::Other
+            }
+            _ => match info {
+                20 => Item::Bool(false),
+                21 => Item::Bool(true),
+                22 => Item::Null,
+                _ => {
+                    self.arg(info)?;
+                    Item::Other
+                }
+            },
+        })
+    }
+
+    /// Skip the next item, including the items of arrays and maps.
+    pub fn skip(&mut self) -> Result<(), CborError> {
+        let mut pending = 1usize;
+        while pending > 0 {
+            pending -= 1;
+            let items = match self.item()? {
+                Item::Array(len) => Some(len),
+                Item::Map(len) => len.checked_mul(2),
+                _ => continue,
+            };
+            pending = items
+                .and_then(|n| pending.checked_add(n))
+                .ok_or(CborError::Malformed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Look up the value of the text `key` in the map `data`, `None` if the
+/// key is missing. Nested arrays and maps are returned as their length only.
+pub fn map_get<'a>(data: &'a [u8], key: &str) -> Result<Option<Item<'a>>, CborError> {
+    let mut decoder = Decoder::new(data);
+    let Item::Map(len) = decoder.item()? else {
+        return Err(CborError::Malformed);
+    };
+    for _ in 0..len {
+        match decoder.item()? {
+            Item::Text(k) if k == key => return decoder.item().map(Some),
+            Item::Array(_) | Item::Map(_) => return Err(CborError::Malformed),
+            _ => decoder.skip()?,
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,150 @@
+//! Telemetry samples and their flash records.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::crc::crc32;
+use crate::gnss::Fix;
+
+/// Value of the `sent` word of a record not sent yet (erased flash).
+pub const UNSENT: u32 = 0xFFFF_FFFF;
+
+/// Speed of 1 knot in cm/s.
+pub const KNOT_CM_S: f32 = 51.44;
+
+/// A telemetry sample as notified to the central (little endian).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct Sample {
+    /// Sequence number, incremented for every sample (also across reboots).
+    pub seq: u32,
+    /// Seconds since boot when the sample was taken.
+    pub uptime_secs: u32,
+    /// Latitude in 1e-7 degrees, `i32::MIN` without fix.
+    pub latitude_e7: i32,
+    /// Longitude in 1e-7 degrees, `i32::MIN` without fix.
+    pub longitude_e7: i32,
+    /// Speed over ground in cm/s.
+    pub speed_cm_s: u16,
+    /// Battery state of charge in percent, 0xFF if unknown.
+    pub battery_percent: u8,
+    pub reserved: u8,
+}
+
+impl Default for Sample {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sample {
+    /// A sample without fix and battery state of charge.
+    pub const fn new() -> Self {
+        Self {
+            seq: 0,
+            uptime_secs: 0,
+            latitude_e7: i32::MIN,
+            longitude_e7: i32::MIN,
+            speed_cm_s: 0,
+            battery_percent: 0xFF,
+            reserved: 0,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+
+    /// Take the position of `fix`, or mark the position unknown.
+    pub fn set_fix(&mut self, fix: Option<&Fix>) {
+        (self.latitude_e7, self.longitude_e7) = match fix {
+            Some(fix) => ((fix.latitude * 1e7) as i32, (fix.longitude * 1e7) as i32),
+            None => (i32::MIN, i32::MIN),
+        };
+    }
+
+    /// Set the speed over ground as reported by RMC sentences.
+    pub fn set_speed_knots(&mut self, speed_knots: f32) {
+        self.speed_cm_s = (speed_knots * KNOT_CM_S) as u16;
+    }
+}
+
+/// A sample in flash.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct Record {
+    pub sample: Sample,
+    pub crc: u32,
+    /// [`UNSENT`] until the sample was acknowledged, then cleared to 0
+    /// without erasing.
+    pub sent: u32,
+}
+
+impl Record {
+    pub fn new(sample: Sample) -> Self {
+        Self {
+            sample,
+            crc: crc32(sample.as_bytes()),
+            sent: UNSENT,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.crc == crc32(self.sample.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_layout() {
+        let sample = Sample {
+            seq: 0x0403_0201,
+            speed_cm_s: 0x0201,
+            battery_percent: 80,
+            ..Sample::new()
+        };
+        let bytes = sample.as_bytes();
+        assert_eq!(bytes.len(), 20);
+        assert_eq!(bytes[..4], [1, 2, 3, 4]);
+        assert_eq!(bytes[8..12], i32::MIN.to_le_bytes());
+        assert_eq!(bytes[16..], [1, 2, 80, 0]);
+        assert_eq!(*bytemuck::from_bytes::<Sample>(bytes), sample);
+    }
+
+    #[test]
+    fn position_and_speed() {
+        let mut sample = Sample::new();
+        let fix = Fix {
+            latitude: 53.361_337,
+            longitude: -6.505_62,
+            altitude: None,
+            satellites: 8,
+            hdop: None,
+        };
+        sample.set_fix(Some(&fix));
+        assert_eq!(sample.latitude_e7, 533_613_370);
+        assert_eq!(sample.longitude_e7, -65_056_200);
+        sample.set_fix(None);
+        assert_eq!(sample.latitude_e7, i32::MIN);
+
+        sample.set_speed_knots(10.0);
+        assert_eq!(sample.speed_cm_s, 514);
+        // Saturates instead of wrapping.
+        sample.set_speed_knots(-1.0);
+        assert_eq!(sample.speed_cm_s, 0);
+    }
+
+    #[test]
+    fn record_detects_corruption() {
+        let mut record = Record::new(Sample::new());
+        assert!(record.is_valid());
+        assert_eq!(record.sent, UNSENT);
+        record.sample.seq = 1;
+        assert!(!record.is_valid());
+        // Erased flash is not a valid record.
+        assert!(!bytemuck::cast::<_, Record>([0xFFu8; 28]).is_valid());
+    }
+}
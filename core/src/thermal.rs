@@ -0,0 +1,80 @@
+//! Throttling of the radio TX power on a hot die.
+//!
+//! A [`Throttle`] takes die temperature readings and steps the TX power
+//! down through [`TX_POWER_STEPS_DBM`] by one step per reading while the die
+//! is at or above the limit, and back up by one step per reading once it's
+//! [`HYSTERESIS_C`] below it.
+
+/// TX power levels from the nominal one down, in dBm.
+pub const TX_POWER_STEPS_DBM: [i8; 5] = [0, -4, -8, -12, -20];
+
+/// Degrees below the limit at which the TX power is raised again.
+pub const HYSTERESIS_C: i32 = 5;
+
+/// Current TX power step.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    step: usize,
+}
+
+impl Throttle {
+    pub const fn new() -> Self {
+        Self { step: 0 }
+    }
+
+    /// Whether the TX power is below the nominal one.
+    pub fn throttled(&self) -> bool {
+        self.step > 0
+    }
+
+    pub fn tx_power_dbm(&self) -> i8 {
+        TX_POWER_STEPS_DBM[self.step]
+    }
+
+    /// A die temperature reading in 0.25 °C, the limit in °C (0: no
+    /// throttling). Returns the TX power if it changed.
+    pub fn update(&mut self, quarter_c: i32, limit_c: u16) -> Option<i8> {
+        let celsius = quarter_c.div_euclid(4);
+        let limit = i32::from(limit_c);
+        let step = if limit_c == 0 {
+            0
+        } else if celsius >= limit {
+            (self.step + 1).min(TX_POWER_STEPS_DBM.len() - 1)
+        } else if celsius <= limit - HYSTERESIS_C {
+            self.step.saturating_sub(1)
+        } else {
+            self.step
+        };
+        (step != self.step).then(|| {
+            self.step = step;
+            self.tx_power_dbm()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_down_and_recovers() {
+        let mut throttle = Throttle::new();
+        assert_eq!(throttle.update(60 * 4, 70), None);
+        assert_eq!(throttle.update(70 * 4, 70), Some(-4));
+        assert_eq!(throttle.update(72 * 4, 70), Some(-8));
+        assert!(throttle.throttled());
+        // Between the limit and the hysteresis the power is kept.
+        assert_eq!(throttle.update(66 * 4, 70), None);
+        assert_eq!(throttle.update(65 * 4, 70), Some(-4));
+        assert_eq!(throttle.update(50 * 4, 70), Some(0));
+        assert_eq!(throttle.update(50 * 4, 70), None);
+
+        for _ in 0..10 {
+            throttle.update(90 * 4, 70);
+        }
+        assert_eq!(throttle.tx_power_dbm(), -20);
+        // Disabling the limit restores the nominal power at once.
+        assert_eq!(throttle.update(90 * 4, 0), Some(0));
+        assert!(!throttle.throttled());
+    }
+}
@@ -0,0 +1,284 @@
+//! Sessions of the track log and their flash layout.
+//!
+//! The log is a ring of flash pages, every session takes whole pages in a
+//! row. Each page starts with a [`PageHeader`] naming its session. The first
+//! page of a session continues with a [`SessionHeader`] and a [`Summary`]
+//! slot that stays erased until the session is stopped. The rest of the
+//! pages holds the [`Point`]s compressed by [`codec`], up to erased flash.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::crc::crc32;
+use crate::gnss::Fix;
+use crate::nav::Waypoint;
+
+pub mod codec;
+
+/// Longest session name in bytes.
+pub const NAME_LEN: usize = 16;
+
+/// Part of the pages in percent up to which the log is filled, the oldest
+/// sessions are deleted beyond.
+pub const QUOTA_PERCENT: u32 = 90;
+
+/// Marks a page of the track log.
+const PAGE_MAGIC: u32 = 0x5452_4B4C; // "TRKL"
+
+/// Offset of the [`SessionHeader`] in the first page of a session.
+pub const SESSION_HEADER_OFFSET: u32 = size_of::<PageHeader>() as u32;
+
+/// Offset of the [`Summary`] in the first page of a session.
+pub const SUMMARY_OFFSET: u32 = SESSION_HEADER_OFFSET + size_of::<SessionHeader>() as u32;
+
+/// Offset of the first [`Point`] in page `index` of a session.
+pub const fn points_offset(index: u32) -> u32 {
+    if index == 0 {
+        SUMMARY_OFFSET + size_of::<Summary>() as u32
+    } else {
+        size_of::<PageHeader>() as u32
+    }
+}
+
+/// Pages the log fills before deleting the oldest sessions.
+pub const fn quota_pages(pages: u32) -> u32 {
+    pages * QUOTA_PERCENT / 100
+}
+
+/// CRC of a record up to its trailing `crc` word.
+fn checksum<T: Pod>(record: &T) -> u32 {
+    let bytes = bytemuck::bytes_of(record);
+    crc32(&bytes[..bytes.len() - size_of::<u32>()])
+}
+
+/// Start of every page of the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct PageHeader {
+    magic: u32,
+    /// ID of the session, counting up.
+    pub session: u32,
+    /// Number of the page within the session.
+    pub index: u32,
+    crc: u32,
+}
+
+impl PageHeader {
+    pub fn new(session: u32, index: u32) -> Self {
+        let mut header = Self {
+            magic: PAGE_MAGIC,
+            session,
+            index,
+            crc: 0,
+        };
+        header.crc = checksum(&header);
+        header
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.magic == PAGE_MAGIC && self.crc == checksum(self)
+    }
+}
+
+/// Name and start of a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct SessionHeader {
+    /// UTF-8, zero padded.
+    pub name: [u8; NAME_LEN],
+    /// Seconds since the Unix epoch, 0 if unknown.
+    pub start_utc: u32,
+    crc: u32,
+}
+
+impl SessionHeader {
+    /// Header of a session named `name`, cut to [`NAME_LEN`] bytes.
+    pub fn new(name: &str, start_utc: u32) -> Self {
+        let mut header = Self {
+            name: [0; NAME_LEN],
+            start_utc,
+            crc: 0,
+        };
+        let mut len = name.len().min(NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        header.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        header.crc = checksum(&header);
+        header
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.crc == checksum(self)
+    }
+}
+
+/// Name of a session as stored in a [`SessionHeader`].
+pub fn name_str(name: &[u8; NAME_LEN]) -> &str {
+    let len = name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+    core::str::from_utf8(&name[..len]).unwrap_or("?")
+}
+
+/// Metadata of a stopped session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct Summary {
+    pub fixes: u32,
+    pub distance_m: u32,
+    /// Seconds since the Unix epoch, 0 if unknown.
+    pub end_utc: u32,
+    crc: u32,
+}
+
+impl Summary {
+    pub fn new(stats: &Stats, end_utc: u32) -> Self {
+        let mut summary = Self {
+            fixes: stats.fixes,
+            distance_m: stats.distance_m(),
+            end_utc,
+            crc: 0,
+        };
+        summary.crc = checksum(&summary);
+        summary
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.crc == checksum(self)
+    }
+}
+
+/// A fix in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct Point {
+    /// Seconds since the Unix epoch, 0 if unknown.
+    pub utc: u32,
+    /// Latitude in 1e-7 degrees.
+    pub latitude_e7: i32,
+    /// Longitude in 1e-7 degrees.
+    pub longitude_e7: i32,
+    /// Altitude above mean sea level in m, `i16::MIN` if unknown.
+    pub altitude_m: i16,
+    pub satellites: u8,
+    pub reserved: u8,
+}
+
+impl Point {
+    pub fn new(fix: &Fix, utc: u32) -> Self {
+        Self {
+            utc,
+            latitude_e7: (fix.latitude * 1e7) as i32,
+            longitude_e7: (fix.longitude * 1e7) as i32,
+            altitude_m: fix.altitude.map_or(i16::MIN, |a| a as i16),
+            satellites: fix.satellites,
+            reserved: 0,
+        }
+    }
+}
+
+/// Fix count and distance of a session so far.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub fixes: u32,
+    distance_m: f64,
+    last: Option<Waypoint>,
+}
+
+impl Stats {
+    pub fn add(&mut self, point: &Point) {
+        let Some(position) = Waypoint::new(
+            point.latitude_e7 as f64 * 1e-7,
+            point.longitude_e7 as f64 * 1e-7,
+        ) else {
+            return;
+        };
+        if let Some(last) = self.last {
+            self.distance_m += last.course_from(position.latitude, position.longitude).0;
+        }
+        self.last = Some(position);
+        self.fixes += 1;
+    }
+
+    pub fn distance_m(&self) -> u32 {
+        self.distance_m as u32
+    }
+}
+
+/// A session as listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SessionInfo {
+    pub id: u32,
+    pub name: [u8; NAME_LEN],
+    /// Seconds since the Unix epoch, 0 if unknown.
+    pub start_utc: u32,
+    /// Seconds since the Unix epoch, 0 if unknown or still recording.
+    pub end_utc: u32,
+    pub fixes: u32,
+    pub distance_m: u32,
+    /// Page of the [`SessionHeader`] in the ring.
+    pub first_page: u32,
+    pub pages: u32,
+    pub recording: bool,
+}
+
+impl SessionInfo {
+    /// Length of [`SessionInfo::to_bytes`].
+    pub const LEN: usize = 40;
+
+    /// ID, start, end, fixes and distance (little endian u32), pages
+    /// (little endian u16), flags (bit 0: recording), a reserved byte and
+    /// the name.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        let words = [
+            self.id,
+            self.start_utc,
+            self.end_utc,
+            self.fixes,
+            self.distance_m,
+        ];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes[20..22].copy_from_slice(&(self.pages as u16).to_le_bytes());
+        bytes[22] = self.recording as u8;
+        bytes[24..].copy_from_slice(&self.name);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_and_stats() {
+        assert!(PageHeader::new(3, 1).is_valid());
+        let mut header: PageHeader = bytemuck::cast([0xFFu8; 16]);
+        assert!(!header.is_valid());
+        header = PageHeader::new(3, 1);
+        header.index = 2;
+        assert!(!header.is_valid());
+
+        let session = SessionHeader::new("morning ride to the lake", 1_700_000_000);
+        assert!(session.is_valid());
+        assert_eq!(name_str(&session.name), "morning ride to ");
+        assert_eq!(points_offset(0) % 4, 0);
+
+        let fix = |latitude| Fix {
+            latitude,
+            longitude: 8.0,
+            altitude: Some(420.0),
+            satellites: 7,
+            hdop: None,
+        };
+        let mut stats = Stats::default();
+        stats.add(&Point::new(&fix(47.0), 10));
+        stats.add(&Point::new(&fix(47.01), 20));
+        assert_eq!(stats.fixes, 2);
+        // 0.01 degrees of latitude are about 1112 m.
+        assert!((1100..1125).contains(&stats.distance_m()));
+        assert!(Summary::new(&stats, 20).is_valid());
+    }
+}
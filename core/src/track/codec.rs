@@ -0,0 +1,218 @@
+//! Compression of the points of a session.
+//!
+//! A point is stored as the difference to the one before, every field a
+//! zig-zag varint, so a fix a few seconds and meters from the last one takes
+//! 5 to 8 bytes instead of the 16 of a [`Point`]. A keyframe with the
+//! absolute values starts every page and follows every
+//! [`KEYFRAME_INTERVAL`] points, so a page decodes on its own and a bad
+//! byte spoils at most the points up to the next keyframe.
+//!
+//! A record starts with a tag byte:
+//! - [`PADDING`]: fills the rest of a flash word, skipped
+//! - `0x01`: keyframe, varints of the UTC, latitude, longitude, altitude and
+//!   satellites
+//! - `0x80 | zigzag(Δsatellites)`: delta, varints of the difference in UTC,
+//!   latitude, longitude and altitude
+//! - `0xFF`: erased flash after the last record
+//!
+//! A varint of erased flash doesn't end, so a record torn by a reset
+//! doesn't decode.
+
+use heapless::Vec;
+
+use super::Point;
+
+/// Longest encoded record, a keyframe far from the equator.
+pub const MAX_RECORD_LEN: usize = 21;
+
+/// Points between two keyframes.
+pub const KEYFRAME_INTERVAL: u16 = 60;
+
+/// Fills the rest of a flash word after the last record written.
+pub const PADDING: u8 = 0x00;
+
+const KEYFRAME: u8 = 0x01;
+const DELTA: u8 = 0x80;
+const ERASED: u8 = 0xFF;
+
+/// Longest varint of a `u32`.
+const MAX_VARINT_LEN: usize = 5;
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn unzigzag(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+fn push_varint(buf: &mut Vec<u8, MAX_RECORD_LEN>, mut value: u32) {
+    while value >= 0x80 {
+        // Records are at most MAX_RECORD_LEN long.
+        let _ = buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    let _ = buf.push(value as u8);
+}
+
+/// Encodes the points of a session into records.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Encoder {
+    last: Option<Point>,
+    since_keyframe: u16,
+}
+
+impl Encoder {
+    pub const fn new() -> Self {
+        Self {
+            last: None,
+            since_keyframe: 0,
+        }
+    }
+
+    /// Encode the next point as a keyframe, e.g. at the start of a page.
+    pub fn keyframe(&mut self) {
+        self.last = None;
+    }
+
+    /// The record of `point`.
+    pub fn encode(&mut self, point: &Point) -> Vec<u8, MAX_RECORD_LEN> {
+        let mut buf = Vec::new();
+        let satellites = self
+            .last
+            .map(|last| zigzag(point.satellites as i32 - last.satellites as i32));
+        match (self.last, satellites) {
+            (Some(last), Some(satellites))
+                if self.since_keyframe < KEYFRAME_INTERVAL && satellites < 0x7F =>
+            {
+                let _ = buf.push(DELTA | satellites as u8);
+                push_varint(&mut buf, zigzag(point.utc.wrapping_sub(last.utc) as i32));
+                push_varint(
+                    &mut buf,
+                    zigzag(point.latitude_e7.wrapping_sub(last.latitude_e7)),
+                );
+                push_varint(
+                    &mut buf,
+                    zigzag(point.longitude_e7.wrapping_sub(last.longitude_e7)),
+                );
+                push_varint(
+                    &mut buf,
+                    zigzag(point.altitude_m as i32 - last.altitude_m as i32),
+                );
+                self.since_keyframe += 1;
+            }
+            _ => {
+                let _ = buf.push(KEYFRAME);
+                push_varint(&mut buf, point.utc);
+                push_varint(&mut buf, zigzag(point.latitude_e7));
+                push_varint(&mut buf, zigzag(point.longitude_e7));
+                push_varint(&mut buf, zigzag(point.altitude_m as i32));
+                push_varint(&mut buf, point.satellites as u32);
+                self.since_keyframe = 0;
+            }
+        }
+        self.last = Some(*point);
+        buf
+    }
+}
+
+/// Decodes the records of a page into points.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Decoder {
+    last: Option<Point>,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// The next point from `bytes`, `None` at erased flash, at the end of
+    /// `bytes` or at a record that doesn't decode.
+    pub fn next_point(&mut self, bytes: &mut impl Iterator<Item = u8>) -> Option<Point> {
+        loop {
+            let tag = bytes.next()?;
+            let point = match tag {
+                PADDING => continue,
+                KEYFRAME => Point {
+                    utc: varint(bytes)?,
+                    latitude_e7: unzigzag(varint(bytes)?),
+                    longitude_e7: unzigzag(varint(bytes)?),
+                    altitude_m: unzigzag(varint(bytes)?) as i16,
+                    satellites: varint(bytes)? as u8,
+                    reserved: 0,
+                },
+                ERASED => return None,
+                _ if tag & DELTA != 0 => {
+                    let last = self.last?;
+                    Point {
+                        utc: last.utc.wrapping_add(unzigzag(varint(bytes)?) as u32),
+                        latitude_e7: last.latitude_e7.wrapping_add(unzigzag(varint(bytes)?)),
+                        longitude_e7: last.longitude_e7.wrapping_add(unzigzag(varint(bytes)?)),
+                        altitude_m: (last.altitude_m as i32 + unzigzag(varint(bytes)?)) as i16,
+                        satellites: (last.satellites as i32 + unzigzag((tag & !DELTA) as u32))
+                            as u8,
+                        reserved: 0,
+                    }
+                }
+                _ => return None,
+            };
+            self.last = Some(point);
+            return Some(point);
+        }
+    }
+}
+
+fn varint(bytes: &mut impl Iterator<Item = u8>) -> Option<u32> {
+    let mut value = 0u32;
+    for i in 0..MAX_VARINT_LEN {
+        let byte = bytes.next()?;
+        value |= ((byte & 0x7F) as u32).checked_shl(7 * i as u32)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// The points in the records of a page.
+pub fn decode(bytes: impl IntoIterator<Item = u8>) -> impl Iterator<Item = Point> {
+    let mut bytes = bytes.into_iter();
+    let mut decoder = Decoder::new();
+    core::iter::from_fn(move || decoder.next_point(&mut bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_and_torn_record() {
+        let mut points = std::vec::Vec::new();
+        for i in 0..100 {
+            points.push(Point {
+                utc: 1_700_000_000 + 5 * i,
+                latitude_e7: 470_000_000 + 450 * i as i32,
+                longitude_e7: -80_000_000 - 600 * i as i32,
+                altitude_m: 420 - (i % 7) as i16,
+                satellites: 7 + (i % 3) as u8,
+                reserved: 0,
+            });
+        }
+        let mut encoder = Encoder::new();
+        let mut bytes = std::vec::Vec::new();
+        for point in &points {
+            bytes.extend_from_slice(&encoder.encode(point));
+        }
+        // 2 keyframes, the deltas take 8 bytes at most.
+        assert!(bytes.len() <= 2 * MAX_RECORD_LEN + 98 * 8);
+        bytes.extend_from_slice(&[PADDING; 3]);
+        assert!(decode(bytes.iter().copied()).eq(points.iter().copied()));
+
+        // The last record is torn, erased flash follows.
+        let len = bytes.len() - 3;
+        bytes.truncate(len - 2);
+        bytes.extend_from_slice(&[0xFF; 8]);
+        assert!(decode(bytes.iter().copied()).eq(points[..99].iter().copied()));
+    }
+}
@@ -0,0 +1,158 @@
+//! Erase counters of the flash pages.
+//!
+//! A [`WearTable`] counts the erases of every data page in RAM. The counts
+//! persist in a log of one word per [`Record`], appended for the pages
+//! erased since; the last record of a page holds its count. A full log is
+//! compacted: erased and rewritten with one record per page.
+
+/// Length of a [`Record`] in flash.
+pub const RECORD_LEN: u32 = 4;
+
+/// Largest count, the 24 bits of a record.
+pub const MAX_ERASES: u32 = 0xFF_FFFF;
+
+/// Value of an erased word, ends the log.
+const ERASED: u32 = 0xFFFF_FFFF;
+
+/// Erase count of a page in the log: the page in the top byte, the count in
+/// the lower 24 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Record {
+    pub page: u8,
+    pub erases: u32,
+}
+
+impl Record {
+    pub fn encode(&self) -> u32 {
+        (self.page as u32) << 24 | self.erases.min(MAX_ERASES)
+    }
+
+    /// `None` for an erased word.
+    pub fn decode(word: u32) -> Option<Self> {
+        (word != ERASED).then_some(Self {
+            page: (word >> 24) as u8,
+            erases: word & MAX_ERASES,
+        })
+    }
+}
+
+/// Erase counts of `N` pages, at most 128.
+#[derive(Debug, Clone)]
+pub struct WearTable<const N: usize> {
+    erases: [u32; N],
+    /// Pages counted since the last [`take_dirty`](Self::take_dirty).
+    dirty: u128,
+}
+
+impl<const N: usize> Default for WearTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> WearTable<N> {
+    pub const fn new() -> Self {
+        assert!(N <= 128);
+        Self {
+            erases: [0; N],
+            dirty: 0,
+        }
+    }
+
+    /// Take the counts of a log, returns the number of records up to its end.
+    pub fn load(&mut self, log: impl IntoIterator<Item = u32>) -> u32 {
+        let mut len = 0;
+        for record in log.into_iter().map_while(Record::decode) {
+            if let Some(erases) = self.erases.get_mut(record.page as usize) {
+                *erases = record.erases;
+            }
+            len += 1;
+        }
+        len
+    }
+
+    /// Count an erase of `page`.
+    pub fn count(&mut self, page: usize) {
+        if let Some(erases) = self.erases.get_mut(page) {
+            *erases = (*erases + 1).min(MAX_ERASES);
+            self.dirty |= 1 << page;
+        }
+    }
+
+    pub fn erases(&self) -> &[u32; N] {
+        &self.erases
+    }
+
+    /// The records of the pages counted since the last call.
+    pub fn take_dirty(&mut self) -> impl Iterator<Item = Record> + use<N> {
+        let dirty = core::mem::take(&mut self.dirty);
+        let erases = self.erases;
+        (0..N)
+            .filter(move |page| dirty & 1 << page != 0)
+            .map(move |page| Record {
+                page: page as u8,
+                erases: erases[page],
+            })
+    }
+
+    /// Whether pages were counted since the last [`take_dirty`](Self::take_dirty).
+    pub fn is_dirty(&self) -> bool {
+        self.dirty != 0
+    }
+
+    /// The records of a compacted log, one per page erased at all.
+    pub fn records(&self) -> impl Iterator<Item = Record> + '_ {
+        self.erases
+            .iter()
+            .enumerate()
+            .filter(|(_, erases)| **erases > 0)
+            .map(|(page, erases)| Record {
+                page: page as u8,
+                erases: *erases,
+            })
+    }
+
+    /// Page erased most often and its count.
+    pub fn most_worn(&self) -> Option<(usize, u32)> {
+        self.erases
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by_key(|(_, erases)| *erases)
+            .filter(|(_, erases)| *erases > 0)
+    }
+
+    /// Erases of all pages.
+    pub fn total(&self) -> u32 {
+        self.erases.iter().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_persist_in_the_log() {
+        let mut table = WearTable::<8>::new();
+        table.count(3);
+        table.count(3);
+        table.count(5);
+        table.count(9);
+        let log: Vec<u32> = table.take_dirty().map(|r| r.encode()).collect();
+        assert_eq!(log, [0x0300_0002, 0x0500_0001]);
+        assert!(!table.is_dirty());
+        table.count(3);
+        let mut log = log;
+        log.extend(table.take_dirty().map(|r| r.encode()));
+        log.push(0xFFFF_FFFF);
+
+        let mut loaded = WearTable::<8>::new();
+        assert_eq!(loaded.load(log.iter().copied()), 3);
+        assert_eq!(loaded.erases()[3], 3);
+        assert_eq!(loaded.most_worn(), Some((3, 3)));
+        assert_eq!(loaded.total(), 4);
+        assert_eq!(loaded.records().count(), 2);
+    }
+}
@@ -0,0 +1,18 @@
+MEMORY
+{
+  /* Space for the S132 SoftDevice the Adafruit bootloader expects */
+  FLASH (rx)     : ORIGIN = 0x26000, LENGTH = 0x5E000 - 0x26000
+  /* 0x5E000: application data (see src/bsp/flash.rs), 0x74000: bootloader */
+
+  RAM (rwx) :  ORIGIN = 0x20000000 , LENGTH = 0x20010000 - 0x100 - 0x20000000
+  /* Not initialized at startup, kept across resets (see src/retained.rs) */
+  RETAINED (rw) : ORIGIN = 0x20010000 - 0x100, LENGTH = 0x100
+}
+
+SECTIONS
+{
+  .retained (NOLOAD) : ALIGN(8)
+  {
+    KEEP(*(.retained .retained.*));
+  } > RETAINED
+}
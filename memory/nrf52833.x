@@ -0,0 +1,20 @@
+MEMORY
+{
+  /* Same layout as on the nRF52840: space for the S140 SoftDevice the
+   * Adafruit bootloader expects, application data below the bootloader.
+   */
+  FLASH (rx)     : ORIGIN = 0x27000, LENGTH = 0x5E000 - 0x27000
+  /* 0x5E000: application data (see src/bsp/flash.rs), 0x74000: bootloader */
+
+  RAM (rwx) :  ORIGIN = 0x20000000 , LENGTH = 0x20020000 - 0x100 - 0x20000000
+  /* Not initialized at startup, kept across resets (see src/retained.rs) */
+  RETAINED (rw) : ORIGIN = 0x20020000 - 0x100, LENGTH = 0x100
+}
+
+SECTIONS
+{
+  .retained (NOLOAD) : ALIGN(8)
+  {
+    KEEP(*(.retained .retained.*));
+  } > RETAINED
+}
@@ -0,0 +1,29 @@
+MEMORY
+{
+  /* Need to leave space for the SoftDevice 
+    These values are confirmed working for S140 7.3.0
+  */
+  FLASH (rx)     : ORIGIN = 0x27000, LENGTH = 0xAE000 - 0x27000
+  /* 0xAE000: application data (see src/bsp/flash.rs), 0xF4000: bootloader */
+
+  /* SRAM required by Softdevice depend on
+   * - Attribute Table Size (Number of Services and Characteristics)
+   * - Vendor UUID count
+   * - Max ATT MTU
+   * - Concurrent connection peripheral + central + secure links
+   * - Event Len, HVN queue, Write CMD queue
+   *
+   *  TODO: How to set SRAM optimally for this specific board?
+   */ 
+  RAM (rwx) :  ORIGIN = 0x20000000 , LENGTH = 0x20040000 - 0x100 - 0x20000000
+  /* Not initialized at startup, kept across resets (see src/retained.rs) */
+  RETAINED (rw) : ORIGIN = 0x20040000 - 0x100, LENGTH = 0x100
+}
+
+SECTIONS
+{
+  .retained (NOLOAD) : ALIGN(8)
+  {
+    KEEP(*(.retained .retained.*));
+  } > RETAINED
+}
@@ -0,0 +1,107 @@
+//! "Find my keys" alerts of the Proximity profile.
+//!
+//! A phone [raises](raise) an alert over the Immediate Alert Service to
+//! find the device, or sets the level of the alert the device raises by
+//! itself when the link is lost over the Link Loss Service. [`run`] plays an
+//! alert on the buzzer and flashes the status LED for [`ALERT_DURATION`],
+//! until it's raised again or cleared with [`AlertLevel::None`].
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+pub use nrf52_radio_core::alert::{AlertLevel, is_link_loss};
+
+use crate::bsp::buzzer::{self, Melody};
+use crate::bsp::led::{self, LedPattern};
+use crate::log;
+
+/// How long an alert lasts unless cleared.
+pub const ALERT_DURATION: Duration = Duration::from_secs(30);
+
+/// Interval in which the melody of a high alert is repeated.
+const REPEAT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Level of the alert raised when the link is lost.
+static LINK_LOSS_LEVEL: AtomicU8 = AtomicU8::new(AlertLevel::None as u8);
+
+/// Alert to raise, waiting for [`run`].
+static ALERT: Signal<CriticalSectionRawMutex, AlertLevel> = Signal::new();
+
+/// Raise an alert, or clear it with [`AlertLevel::None`].
+pub fn raise(level: AlertLevel) {
+    ALERT.signal(level);
+}
+
+/// Level of the alert raised when the link is lost.
+pub fn link_loss_level() -> AlertLevel {
+    AlertLevel::try_from(LINK_LOSS_LEVEL.load(Ordering::Relaxed)).unwrap_or_default()
+}
+
+/// Set the level of the alert raised when the link is lost.
+pub fn set_link_loss_level(level: AlertLevel) {
+    LINK_LOSS_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// A central disconnected for the HCI disconnect `reason`, raise the link
+/// loss alert if the link was lost.
+pub fn disconnected(reason: u8) {
+    let level = link_loss_level();
+    if is_link_loss(reason) && level != AlertLevel::None {
+        log!(
+            Ble,
+            warn,
+            "[alert] link lost (reason {:#x}), alerting",
+            reason
+        );
+        raise(level);
+    }
+}
+
+/// Play the alerts forever.
+pub async fn run() -> ! {
+    let mut level = ALERT.wait().await;
+    // Pattern restored when the alert ends.
+    let mut previous = LedPattern::Heartbeat;
+    loop {
+        if level == AlertLevel::None {
+            level = ALERT.wait().await;
+            continue;
+        }
+        log!(Ble, info, "[alert] {:?} alert", level);
+        match led::pattern() {
+            // Raised again while alerting.
+            LedPattern::Alert => {}
+            pattern => previous = pattern,
+        }
+        led::set_pattern(LedPattern::Alert);
+        let end = Instant::now() + ALERT_DURATION;
+        let melody = match level {
+            AlertLevel::High => Melody::AlertHigh,
+            _ => Melody::AlertMild,
+        };
+        buzzer::play(melody);
+        let next = loop {
+            let repeat = match level {
+                // Only a high alert keeps sounding.
+                AlertLevel::High => (Instant::now() + REPEAT_INTERVAL).min(end),
+                _ => end,
+            };
+            match select(Timer::at(repeat), ALERT.wait()).await {
+                Either::First(_) if Instant::now() >= end => break AlertLevel::None,
+                Either::First(_) => buzzer::play(melody),
+                Either::Second(next) => break next,
+            }
+        };
+        if next == AlertLevel::None {
+            buzzer::play(Melody::Silence);
+            // Unless the pattern changed meanwhile, e.g. for a recovery.
+            if led::pattern() == LedPattern::Alert {
+                led::set_pattern(previous);
+            }
+        }
+        level = next;
+    }
+}
@@ -0,0 +1,39 @@
+//! Altitude from a barometer, fused with the GNSS altitude.
+//!
+//! [`Altimeter`] takes the pressure from [`Event::SensorSampled`] and the
+//! GNSS altitude from [`Event::FixAcquired`] and publishes the result of an
+//! [`AltitudeFilter`] with every pressure sample as
+//! [`Event::AltitudeUpdated`]: changes of the altitude show up within a
+//! sample interval, without the noise of the GNSS.
+
+use defmt::warn;
+use nrf52_radio_core::baro::{AltitudeFilter, pressure_altitude};
+
+use crate::events::{self, Event};
+use crate::sensors::Sample;
+
+/// Publish the fused altitude, needs a barometer registered with the
+/// [sensor scheduler](crate::sensors::Scheduler).
+pub async fn run_altimeter() {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[altimeter] no event bus subscriber available");
+        return;
+    };
+    let mut filter = AltitudeFilter::new();
+    loop {
+        match events.next_message_pure().await {
+            Event::FixAcquired(fix) => {
+                if let Some(altitude) = fix.altitude {
+                    filter.update_gnss(altitude);
+                }
+            }
+            Event::SensorSampled(Sample::Pressure(pressure)) => {
+                filter.update_pressure(pressure_altitude(pressure.pascal));
+                if let Some(altitude) = filter.altitude() {
+                    events::publish(Event::AltitudeUpdated(altitude));
+                }
+            }
+            _ => {}
+        }
+    }
+}
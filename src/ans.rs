@@ -0,0 +1,81 @@
+//! Alert Notification Service client, phone notifications on the display.
+//!
+//! [`run`] looks for the Alert Notification Service on the connected
+//! central with a GATT client, subscribes to its New Alert characteristic
+//! and enables the alerts of all categories. Every new alert is published
+//! as [`Event::PhoneNotification`], the [UI](crate::ui::Notifications)
+//! shows it and the [buzzer](crate::bsp::buzzer) chirps. Phones without the
+//! service, e.g. iPhones, are left alone.
+
+use defmt::{info, warn};
+use embassy_futures::select::{Either, select};
+pub use nrf52_radio_core::ans::{
+    ALL_CATEGORIES, Category, Command, NewAlert, TEXT_LEN, control_point,
+};
+use trouble_host::prelude::*;
+
+use crate::events::{self, Event};
+
+/// Alert Notification Service.
+const SERVICE: u16 = 0x1811;
+
+/// New Alert characteristic.
+const NEW_ALERT: u16 = 0x2A46;
+
+/// Alert Notification Control Point characteristic.
+const CONTROL_POINT: u16 = 0x2A44;
+
+/// Services the client keeps track of.
+const MAX_SERVICES: usize = 4;
+
+/// Publish the new alerts of the central on `conn` until it disconnects.
+/// Returns at once if it has no Alert Notification Service.
+pub async fn run<C: Controller, P: PacketPool>(
+    stack: &Stack<'_, C, P>,
+    conn: &Connection<'_, P>,
+) -> Result<(), BleHostError<C::Error>> {
+    let client = GattClient::<C, P, MAX_SERVICES>::new(stack, conn).await?;
+    match select(client.task(), subscribe(&client)).await {
+        Either::First(result) => result,
+        Either::Second(result) => result,
+    }
+}
+
+/// Discover the service, subscribe and publish the alerts.
+async fn subscribe<C: Controller, P: PacketPool>(
+    client: &GattClient<'_, C, P, MAX_SERVICES>,
+) -> Result<(), BleHostError<C::Error>> {
+    let services = client.services_by_uuid(&Uuid::new_short(SERVICE)).await?;
+    let Some(service) = services.first() else {
+        info!("[ans] no Alert Notification Service on the central");
+        return Ok(());
+    };
+    let new_alert: Characteristic<u8> = client
+        .characteristic_by_uuid(service, &Uuid::new_short(NEW_ALERT))
+        .await?;
+    let control: Characteristic<u8> = client
+        .characteristic_by_uuid(service, &Uuid::new_short(CONTROL_POINT))
+        .await?;
+    let mut listener = client.subscribe(&new_alert, false).await?;
+    for command in [Command::EnableNewAlerts, Command::NotifyNewAlertsNow] {
+        client
+            .write_characteristic(&control, &control_point(command, ALL_CATEGORIES))
+            .await?;
+    }
+    info!("[ans] subscribed to new alerts");
+    loop {
+        let notification = listener.next().await;
+        match NewAlert::parse(notification.as_ref()) {
+            Some(alert) => {
+                info!(
+                    "[ans] {} ({}): {}",
+                    alert.category.label(),
+                    alert.count,
+                    alert.text()
+                );
+                events::publish(Event::PhoneNotification(alert));
+            }
+            None => warn!("[ans] invalid new alert"),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! ANT-like broadcast channels on the raw radio, for experiments.
+//!
+//! An [`AntChannel`] sends the next page of its [`Profile`] once per channel
+//! period in a short [timeslot](crate::bsp::ble::timeslot), next to BLE.
+//! The crate has no Enhanced ShockBurst stack, so the packets use the ESB
+//! framing of a transmitter without acknowledgements (dynamic payload
+//! length, 9 bit packet control field, 2 byte CRC) on the 1 Mbit nRF mode.
+//! A receiver has to be set up for [`BASE_ADDRESS`] and [`PREFIX`] on the
+//! RF channel of the [`ChannelConfig`]. Pages and channel IDs live in
+//! [`nrf52_radio_core::ant`].
+
+use core::cell::{RefCell, UnsafeCell};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use embassy_nrf::pac::{self, radio::vals};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::Duration;
+pub use nrf52_radio_core::ant::{
+    BikeSpeed, ChannelConfig, ChannelId, DEVICE_TYPE_BIKE_SPEED, DEVICE_TYPE_HEART_RATE, HeartRate,
+    PAGE_LEN, PAYLOAD_LEN, PERIOD_BIKE_SPEED, PERIOD_HEART_RATE, Page, Profile, payload,
+};
+
+use crate::bsp::ble::timeslot::{Action, Request, TimeslotHandler};
+
+/// Base address of the ESB pipe 0.
+pub const BASE_ADDRESS: u32 = 0xe7e7_e7e7;
+
+/// Address prefix of the ESB pipe 0.
+pub const PREFIX: u8 = 0xe7;
+
+/// Length of the slots, one packet each.
+const SLOT: Duration = Duration::from_micros(1000);
+
+/// The packet is cut off this long before the slot ends.
+const SLOT_MARGIN: Duration = Duration::from_micros(200);
+
+/// Length field, packet control field and payload of an ESB packet.
+struct PacketBuffer(UnsafeCell<[u8; 2 + PAYLOAD_LEN]>);
+
+// SAFETY: only accessed from the timeslot callback, which isn't reentrant.
+unsafe impl Sync for PacketBuffer {}
+
+static PACKET: PacketBuffer = PacketBuffer(UnsafeCell::new([0; 2 + PAYLOAD_LEN]));
+
+/// A broadcast channel sending the pages of `P`.
+pub struct AntChannel<P> {
+    config: ChannelConfig,
+    profile: Mutex<CriticalSectionRawMutex, RefCell<P>>,
+    /// ESB packet ID, incremented for every packet.
+    pid: AtomicU8,
+}
+
+impl<P: Profile + Send> AntChannel<P> {
+    pub const fn new(config: ChannelConfig, profile: P) -> Self {
+        Self {
+            config,
+            profile: Mutex::new(RefCell::new(profile)),
+            pid: AtomicU8::new(0),
+        }
+    }
+
+    /// First request of the session.
+    pub fn first_request(&self) -> Request {
+        Request::Earliest {
+            length: SLOT,
+            timeout: self.period(),
+        }
+    }
+
+    /// Update the profile, e.g. record a heart beat.
+    pub fn update<R>(&self, f: impl FnOnce(&mut P) -> R) -> R {
+        self.profile.lock(|profile| f(&mut profile.borrow_mut()))
+    }
+
+    fn period(&self) -> Duration {
+        Duration::from_micros(self.config.period_us() as u64)
+    }
+
+    /// Next slot one channel period after the start of this one.
+    fn next(&self) -> Action {
+        stop();
+        Action::Request(Request::Normal {
+            length: SLOT,
+            distance: self.period(),
+        })
+    }
+}
+
+/// Disable the RADIO and the slot guard, so the slot can end.
+fn stop() {
+    let radio = pac::RADIO;
+    radio.shorts().write(|_| {});
+    radio.intenclr().write(|w| w.set_disabled(true));
+    if radio.state().read().state() != vals::State::DISABLED {
+        radio.events_disabled().write_value(0);
+        radio.tasks_disable().write_value(1);
+        while radio.events_disabled().read() == 0 {}
+    }
+    radio.events_disabled().write_value(0);
+    let timer = pac::TIMER0;
+    timer.intenclr().write(|w| w.set_compare(0, true));
+    timer.events_compare(0).write_value(0);
+}
+
+impl<P: Profile + Send> TimeslotHandler for AntChannel<P> {
+    fn start(&self) -> Action {
+        let page = self.update(|profile| profile.next_page());
+        let pid = self.pid.fetch_add(1, Ordering::Relaxed) & 0b11;
+        // SAFETY: the RADIO is disabled, it doesn't access the buffer.
+        let packet = unsafe { &mut *PACKET.0.get() };
+        packet[0] = PAYLOAD_LEN as u8;
+        // PID and the no acknowledgement bit.
+        packet[1] = pid << 1 | 1;
+        packet[2..].copy_from_slice(&payload(&page, &self.config.id));
+
+        let radio = pac::RADIO;
+        radio.mode().write(|w| w.set_mode(vals::Mode::NRF_1MBIT));
+        radio
+            .frequency()
+            .write(|w| w.set_frequency(self.config.rf_channel));
+        radio
+            .txpower()
+            .write(|w| w.set_txpower(vals::Txpower::_0_DBM));
+        radio.pcnf0().write(|w| {
+            w.set_lflen(6);
+            w.set_s1len(3);
+        });
+        radio.pcnf1().write(|w| {
+            w.set_maxlen(32);
+            w.set_balen(4);
+            w.set_endian(vals::Endian::BIG);
+        });
+        radio.base0().write_value(BASE_ADDRESS);
+        radio.prefix0().write(|w| w.set_ap(0, PREFIX));
+        radio.txaddress().write(|w| w.set_txaddress(0));
+        radio.crccnf().write(|w| w.set_len(vals::Len::TWO));
+        radio.crcinit().write(|w| w.set_crcinit(0xffff));
+        radio.crcpoly().write(|w| w.set_crcpoly(0x1_1021));
+        radio.packetptr().write_value(packet.as_ptr() as u32);
+        radio.shorts().write(|w| {
+            w.set_ready_start(true);
+            w.set_end_disable(true);
+        });
+        radio.events_disabled().write_value(0);
+        radio.intenset().write(|w| w.set_disabled(true));
+        radio.tasks_txen().write_value(1);
+
+        // TIMER0 runs at 1 MHz from the start of the slot.
+        let timer = pac::TIMER0;
+        timer
+            .cc(0)
+            .write_value((SLOT.as_micros() - SLOT_MARGIN.as_micros()) as u32);
+        timer.events_compare(0).write_value(0);
+        timer.intenset().write(|w| w.set_compare(0, true));
+        Action::None
+    }
+
+    fn timer0(&self) -> Action {
+        self.next()
+    }
+
+    fn radio(&self) -> Action {
+        if pac::RADIO.events_disabled().read() == 0 {
+            return Action::None;
+        }
+        self.next()
+    }
+}
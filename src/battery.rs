@@ -0,0 +1,221 @@
+//! Battery state of charge estimation.
+//!
+//! There is no fuel gauge, so the state of charge is estimated from the
+//! battery voltage measured with the SAADC: the voltage is smoothed with an
+//! IIR filter, compensated for the sag caused by the current load (higher
+//! while the radio is transmitting) and mapped to a state of charge with an
+//! open circuit voltage (OCV) table of a typical LiPo cell.
+//!
+//! [`BatteryMonitor`] publishes [`Event::BatteryUpdated`] whenever the
+//! estimate changes and [`Event::BatteryLow`] when it drops below
+//! [`LOW_PERCENT`]. The charger status is detected by [`charger`].
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use defmt::info;
+use embassy_nrf::saadc::Saadc;
+use embassy_time::{Duration, Ticker};
+
+use crate::events::{self, Event};
+use crate::metrics;
+use crate::supervisor::Subsystem;
+
+pub mod charger;
+
+pub use charger::{ChargeState, ChargerMonitor};
+
+/// Open circuit voltage in mV and the corresponding state of charge in percent
+/// of a typical LiPo cell, ordered by voltage.
+const OCV_TABLE: [(u16, u8); 11] = [
+    (3300, 0),
+    (3500, 5),
+    (3600, 10),
+    (3680, 20),
+    (3740, 30),
+    (3790, 40),
+    (3830, 50),
+    (3880, 60),
+    (3950, 70),
+    (4040, 85),
+    (4150, 100),
+];
+
+/// State of charge below which the battery is low.
+pub const LOW_PERCENT: u8 = 15;
+
+/// The battery is no longer low once the state of charge is above this.
+const LOW_CLEAR_PERCENT: u8 = 20;
+
+/// Interval in which the battery voltage is measured.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Weight of a new sample in the IIR filter, in 1/256.
+const FILTER_ALPHA: u32 = 32;
+
+/// Set while the radio is transmitting a lot, e.g. while a central is connected.
+static RADIO_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Tell the estimator whether the radio is active, which increases the load.
+pub fn set_radio_active(active: bool) {
+    RADIO_ACTIVE.store(active, Ordering::Relaxed);
+}
+
+/// Map an open circuit voltage to a state of charge in percent.
+pub fn ocv_to_percent(millivolts: u16) -> u8 {
+    let (first, last) = (OCV_TABLE[0], OCV_TABLE[OCV_TABLE.len() - 1]);
+    if millivolts <= first.0 {
+        return first.1;
+    }
+    if millivolts >= last.0 {
+        return last.1;
+    }
+    let upper = OCV_TABLE
+        .iter()
+        .position(|&(mv, _)| mv > millivolts)
+        .unwrap_or(OCV_TABLE.len() - 1);
+    let (v0, p0) = OCV_TABLE[upper - 1];
+    let (v1, p1) = OCV_TABLE[upper];
+    let span = (millivolts - v0) as u32 * (p1 - p0) as u32 / (v1 - v0) as u32;
+    p0 + span as u8
+}
+
+/// Electrical model of the battery and the load of the device.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct BatteryModel {
+    /// Internal resistance of the cell (including protection circuit) in mΩ.
+    pub internal_resistance_mohm: u16,
+    /// Average current of the device without radio activity in mA.
+    pub base_current_ma: u16,
+    /// Additional average current while the radio is active in mA.
+    pub radio_current_ma: u16,
+    /// Ratio of the voltage divider between battery and ADC input.
+    pub divider: u16,
+}
+
+impl Default for BatteryModel {
+    /// Adafruit Feather nRF52840: 100k/100k divider on VBAT.
+    fn default() -> Self {
+        Self {
+            internal_resistance_mohm: 250,
+            base_current_ma: 30,
+            radio_current_ma: 10,
+            divider: 2,
+        }
+    }
+}
+
+/// State of charge estimator.
+pub struct SocEstimator {
+    model: BatteryModel,
+    /// Filtered battery voltage in 1/256 mV.
+    filtered: Option<u32>,
+}
+
+impl SocEstimator {
+    pub fn new(model: BatteryModel) -> Self {
+        Self {
+            model,
+            filtered: None,
+        }
+    }
+
+    /// Voltage drop across the internal resistance caused by the load in mV.
+    fn load_drop_mv(&self, radio_active: bool) -> u32 {
+        let mut current = self.model.base_current_ma as u32;
+        if radio_active {
+            current += self.model.radio_current_ma as u32;
+        }
+        current * self.model.internal_resistance_mohm as u32 / 1000
+    }
+
+    /// Add a measured battery voltage (under load).
+    pub fn add_sample(&mut self, millivolts: u16, radio_active: bool) {
+        let ocv = (millivolts as u32 + self.load_drop_mv(radio_active)) << 8;
+        self.filtered = Some(match self.filtered {
+            None => ocv,
+            Some(prev) => (prev * (256 - FILTER_ALPHA) + ocv * FILTER_ALPHA) / 256,
+        });
+    }
+
+    /// Estimated open circuit voltage in mV.
+    pub fn millivolts(&self) -> Option<u16> {
+        self.filtered.map(|v| (v >> 8) as u16)
+    }
+
+    /// Estimated state of charge in percent.
+    pub fn percent(&self) -> Option<u8> {
+        self.millivolts().map(ocv_to_percent)
+    }
+}
+
+/// Convert a 12 bit SAADC sample (internal 0.6 V reference, gain 1/6)
+/// to the battery voltage in mV.
+pub fn sample_to_millivolts(sample: i16, divider: u16) -> u16 {
+    (sample.max(0) as u32 * 3600 * divider as u32 / 4096) as u16
+}
+
+/// Subsystem measuring the battery and publishing the state of charge.
+pub struct BatteryMonitor<'a, 'd> {
+    saadc: &'a mut Saadc<'d, 1>,
+    estimator: SocEstimator,
+    divider: u16,
+    low: bool,
+    last_percent: Option<u8>,
+}
+
+impl<'a, 'd> BatteryMonitor<'a, 'd> {
+    /// The SAADC has to be configured with a single channel on the battery
+    /// voltage divider and 12 bit resolution.
+    pub fn new(saadc: &'a mut Saadc<'d, 1>, model: BatteryModel) -> Self {
+        Self {
+            saadc,
+            estimator: SocEstimator::new(model),
+            divider: model.divider,
+            low: false,
+            last_percent: None,
+        }
+    }
+
+    fn publish(&mut self) {
+        let (Some(percent), Some(millivolts)) =
+            (self.estimator.percent(), self.estimator.millivolts())
+        else {
+            return;
+        };
+        if self.last_percent != Some(percent) {
+            self.last_percent = Some(percent);
+            info!("[battery] {} mV, {}%", millivolts, percent);
+            metrics::set_battery_millivolts(millivolts);
+            events::publish(Event::BatteryUpdated {
+                percent,
+                millivolts,
+            });
+        }
+        if !self.low && percent < LOW_PERCENT {
+            self.low = true;
+            events::publish(Event::BatteryLow { millivolts });
+        } else if self.low && percent > LOW_CLEAR_PERCENT {
+            self.low = false;
+        }
+    }
+}
+
+impl Subsystem for BatteryMonitor<'_, '_> {
+    type Error = core::convert::Infallible;
+
+    const NAME: &'static str = "battery";
+
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        self.saadc.calibrate().await;
+        let mut ticker = Ticker::every(SAMPLE_INTERVAL);
+        loop {
+            let mut buf = [0i16; 1];
+            self.saadc.sample(&mut buf).await;
+            let millivolts = sample_to_millivolts(buf[0], self.divider);
+            self.estimator
+                .add_sample(millivolts, RADIO_ACTIVE.load(Ordering::Relaxed));
+            self.publish();
+            ticker.next().await;
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Charger status detection.
+//!
+//! The LiPo charger signals charging on an open drain status pin (active
+//! low). Whether USB power is present is read from the VBUS detector of the
+//! POWER peripheral, which distinguishes "charged" from "discharging" while
+//! the status pin is released.
+
+use defmt::info;
+use embassy_futures::select::select;
+use embassy_nrf::gpio::Input;
+use embassy_nrf::pac;
+use embassy_time::{Duration, Timer};
+
+use crate::events::{self, Event};
+use crate::supervisor::Subsystem;
+
+/// Interval in which VBUS is polled, it doesn't change the status pin when
+/// no battery is connected.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Charging state of the battery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ChargeState {
+    /// USB power present, the battery is being charged.
+    Charging,
+    /// USB power present, charging finished.
+    Charged,
+    /// Running from the battery.
+    Discharging,
+}
+
+impl ChargeState {
+    /// Value of the Battery Power State characteristic (0x2A1A).
+    ///
+    /// Two bits each for battery present, discharging, charging and level.
+    pub fn power_state(self, critical: bool) -> u8 {
+        const PRESENT: u8 = 0b11;
+        let (discharging, charging) = match self {
+            ChargeState::Charging => (0b10, 0b11),
+            ChargeState::Charged => (0b10, 0b10),
+            ChargeState::Discharging => (0b11, 0b10),
+        };
+        let level = if critical { 0b11 } else { 0b10 };
+        PRESENT | discharging << 2 | charging << 4 | level << 6
+    }
+}
+
+/// Whether USB power is present.
+fn vbus_present() -> bool {
+    pac::POWER.usbregstatus().read().vbusdetect()
+}
+
+/// Subsystem publishing [`Event::ChargeStateChanged`].
+pub struct ChargerMonitor<'a, 'd> {
+    status: &'a mut Input<'d>,
+    state: Option<ChargeState>,
+}
+
+impl<'a, 'd> ChargerMonitor<'a, 'd> {
+    /// `status` is the active low status output of the charger, with pull-up.
+    pub fn new(status: &'a mut Input<'d>) -> Self {
+        Self {
+            status,
+            state: None,
+        }
+    }
+
+    /// Read the current charge state.
+    pub fn read(&self) -> ChargeState {
+        match (vbus_present(), self.status.is_low()) {
+            (true, true) => ChargeState::Charging,
+            (true, false) => ChargeState::Charged,
+            (false, _) => ChargeState::Discharging,
+        }
+    }
+}
+
+impl Subsystem for ChargerMonitor<'_, '_> {
+    type Error = core::convert::Infallible;
+
+    const NAME: &'static str = "charger";
+
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        loop {
+            let state = self.read();
+            if self.state != Some(state) {
+                info!("[charger] {:?}", state);
+                self.state = Some(state);
+                events::publish(Event::ChargeStateChanged(state));
+            }
+            select(self.status.wait_for_any_edge(), Timer::after(POLL_INTERVAL)).await;
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Simulated heart rate monitor on an ANT-like broadcast channel.
+//!
+//! Sends heart rate pages with a slowly varying rate next to the BLE
+//! controller, see [`ant`](nrf52_radio_rs::ant).
+
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Instant, Timer};
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    ant::{
+        AntChannel, ChannelConfig, ChannelId, DEVICE_TYPE_HEART_RATE, HeartRate, PERIOD_HEART_RATE,
+    },
+    bsp::ble::timeslot::Timeslot,
+};
+
+/// RF channel of ANT+, 2457 MHz.
+const RF_CHANNEL: u8 = 57;
+
+static CHANNEL: AntChannel<HeartRate> = AntChannel::new(
+    ChannelConfig {
+        id: ChannelId {
+            device_number: 0x5242,
+            device_type: DEVICE_TYPE_HEART_RATE,
+            transmission_type: 1,
+        },
+        period: PERIOD_HEART_RATE,
+        rf_channel: RF_CHANNEL,
+    },
+    HeartRate::new(),
+);
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Record simulated heart beats.
+#[embassy_executor::task]
+async fn heart_task() {
+    loop {
+        let now = Instant::now();
+        let bpm = simulated_bpm(now);
+        // Event times in 1/1024 s, wrapping.
+        let event_time = (now.as_millis() * 1024 / 1000) as u16;
+        CHANNEL.update(|hr| hr.beat(event_time, bpm));
+        Timer::after(Duration::from_millis(60_000 / bpm as u64)).await;
+    }
+}
+
+/// Heart rate swinging between 60 and 90 bpm over two minutes.
+fn simulated_bpm(now: Instant) -> u8 {
+    let phase = (now.as_secs() % 120) as u8;
+    60 + if phase < 60 {
+        phase / 2
+    } else {
+        (120 - phase) / 2
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    let board = Board::default();
+    let (_sdc, mpsl) = board
+        .ble
+        .peripheral_links(0)
+        .init(board.timer0, board.rng)
+        .unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+
+    spawner.must_spawn(heart_task());
+
+    let session = Timeslot::open(mpsl, &CHANNEL).unwrap();
+    info!("[ant] broadcasting heart rate pages");
+    session.keep_requesting(CHANNEL.first_request()).await
+}
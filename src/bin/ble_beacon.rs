@@ -1,17 +1,33 @@
 #![no_std]
 #![no_main]
 
-use defmt::info;
+use defmt::{info, warn};
 use embassy_executor::Spawner;
 use embassy_futures::join::join;
 use embassy_time::{Duration, Instant, Timer};
+use heapless::Vec;
 use nrf_sdc::SoftdeviceController;
-use nrf52_radio_rs::{self as _, Board};
+use nrf52_radio_rs::{
+    self as _, Board,
+    bsp::{
+        ble::{
+            AdvTiming, BeaconResources,
+            adv::{self, ADV_LEN, Ad},
+        },
+        rng::Rng,
+    },
+    crypto::{BEACON_OVERHEAD, BEACON_SALT_LEN, BeaconCipher},
+    keystore::{self, KeyId},
+};
+use rand_core::RngCore;
 use trouble_host::prelude::*;
 
 // Arbitrary company ID
 const COMPANY_ID: u16 = 0xFFFF;
 
+/// Length of the encrypted manufacturer specific payload.
+const PAYLOAD_LEN: usize = 8 + BEACON_OVERHEAD;
+
 fn make_adv_payload(start: Instant, update_count: u32) -> [u8; 8] {
     let mut data = [0u8; 8];
     let elapsed_ms = Instant::now().duration_since(start).as_millis() as u32;
@@ -20,46 +36,66 @@ fn make_adv_payload(start: Instant, update_count: u32) -> [u8; 8] {
     data
 }
 
+/// Advertising data with the encrypted `payload`, which goes before the name.
+fn adv_payload(payload: &[u8]) -> Vec<u8, ADV_LEN> {
+    adv::payload(&[
+        Ad::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+        Ad::Manufacturer {
+            company_identifier: COMPANY_ID,
+            payload,
+        },
+        Ad::Name {
+            name: "TB",
+            min_len: 2,
+        },
+    ])
+    .payload
+}
+
 #[embassy_executor::task]
-async fn beacon(sdc: SoftdeviceController<'static>) {
+async fn beacon(sdc: SoftdeviceController<'static>, mut rng: Rng) {
+    // The key shared with the receivers, the beacon stays silent without it.
+    let key = match keystore::handle(KeyId::Beacon).and_then(|key| key.aes()) {
+        Ok(key) => key,
+        Err(e) => {
+            warn!("No provisioned beacon key ({:?}), not advertising", e);
+            return;
+        }
+    };
     let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff]);
     info!("Our address = {:?}", address);
 
-    let mut resources: HostResources<DefaultPacketPool, 0, 0, 27> = HostResources::new();
-    let stack = trouble_host::new(sdc, &mut resources).set_random_address(address);
+    let mut resources = BeaconResources::new();
+    let stack = resources.stack(sdc, address);
     let Host {
         mut peripheral,
         mut runner,
         ..
     } = stack.build();
 
-    let mut adv_data = [0; 64];
     let mut update_count = 0u32;
     let start = Instant::now();
-    let len = AdStructure::encode_slice(
-        &[
-            AdStructure::CompleteLocalName(b"Trouble Beacon"),
-            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
-            AdStructure::ManufacturerSpecificData {
-                company_identifier: COMPANY_ID,
-                payload: &make_adv_payload(start, update_count),
-            },
-        ],
-        &mut adv_data[..],
-    )
-    .unwrap();
+    // Random salt and start value of the counter, so nonces don't repeat
+    // after a reboot.
+    let mut salt = [0; BEACON_SALT_LEN];
+    rng.fill_bytes(&mut salt);
+    let mut cipher = BeaconCipher::new(key, COMPANY_ID, salt, rng.next_u32());
+    let mut payload = [0u8; PAYLOAD_LEN];
+    cipher
+        .encrypt(&make_adv_payload(start, update_count), &mut payload)
+        .unwrap();
+    let mut adv_data = adv_payload(&payload);
 
     info!("Starting advertising");
     let _ = join(runner.run(), async {
+        let params = AdvTiming::BEACON.randomize(&mut rng);
         loop {
-            let mut params = AdvertisementParameters::default();
-            params.interval_min = Duration::from_millis(25);
-            params.interval_max = Duration::from_millis(150);
+            AdvTiming::BEACON.restart_delay(&mut rng).await;
             let _advertiser = peripheral
                 .advertise(
                     &params,
                     Advertisement::NonconnectableNonscannableUndirected {
-                        adv_data: &adv_data[..len],
+                        adv_data: &adv_data,
                     },
                 )
                 .await
@@ -67,23 +103,15 @@ async fn beacon(sdc: SoftdeviceController<'static>) {
             loop {
                 Timer::after(Duration::from_millis(10)).await;
                 update_count = update_count.wrapping_add(1);
+                cipher
+                    .encrypt(&make_adv_payload(start, update_count), &mut payload)
+                    .unwrap();
 
-                let len = AdStructure::encode_slice(
-                    &[
-                        AdStructure::CompleteLocalName(b"Trouble Beacon"),
-                        AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
-                        AdStructure::ManufacturerSpecificData {
-                            company_identifier: COMPANY_ID,
-                            payload: &make_adv_payload(start, update_count),
-                        },
-                    ],
-                    &mut adv_data[..],
-                )
-                .unwrap();
+                adv_data = adv_payload(&payload);
 
                 peripheral
                     .update_adv_data(Advertisement::NonconnectableNonscannableUndirected {
-                        adv_data: &adv_data[..len],
+                        adv_data: &adv_data,
                     })
                     .await
                     .unwrap();
@@ -100,8 +128,10 @@ async fn beacon(sdc: SoftdeviceController<'static>) {
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     info!("Starting BLE beacon...");
+    #[cfg(feature = "cryptocell")]
+    nrf52_radio_rs::crypto::hw::init();
     let b = Board::default();
     let (sdc, _mpsl) = b.ble.init(b.timer0, b.rng).unwrap();
     info!("Initialized BLE.");
-    spawner.spawn(beacon(sdc)).unwrap();
+    spawner.spawn(beacon(sdc, b.rng)).unwrap();
 }
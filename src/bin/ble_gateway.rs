@@ -0,0 +1,228 @@
+//! Observer to UART gateway.
+//!
+//! Scans for the beacons of this crate: the encrypted beacons of
+//! `ble_beacon`, the Eddystone-TLM and -EID frames of `sensor_reading` and
+//! the slot beacons of `slotted_beacon`. Every decoded advertisement is
+//! written as one line of JSON to UARTE1 (115200 baud, TX of the console
+//! pins of the `Board`), so a host reading the serial port becomes a
+//! receiver without a BLE stack of its own. See [`Record::write_line`] for
+//! the format. After every epoch of the slot beacons, the units missed in
+//! it are written as a line of their own, see [`write_missing`]. The
+//! encrypted beacons are only decoded with a provisioned beacon key.
+
+#![no_std]
+#![no_main]
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use bt_hci::param::LeAdvReportsIter;
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_nrf::{
+    bind_interrupts, peripherals,
+    uarte::{self, Baudrate, Config, UarteTx},
+};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    channel::Channel,
+};
+use embassy_time::{Duration, Timer};
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    bsp::ble::ObserverResources,
+    crypto::{Aes, BEACON_OVERHEAD, BeaconCipher},
+    epoch::{Roster, Schedule, SlotBeacon, write_missing},
+    gateway::{Record, eddystone_frame, manufacturer_data},
+    keystore::{self, KeyId},
+};
+use trouble_host::prelude::*;
+
+/// Company ID of the encrypted beacons, as in `ble_beacon`.
+const COMPANY_ID: u16 = 0xFFFF;
+
+/// Slots of the time-synchronized units, as in `slotted_beacon`.
+const SCHEDULE: Schedule = Schedule::new(1_000_000, 10, 30_000);
+
+/// Longest line written.
+const LINE_LEN: usize = 128;
+
+/// Lines buffered for the UART.
+const LINE_QUEUE: usize = 16;
+
+/// Interval in which the number of dropped lines is logged.
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Lines waiting to be written to the UART.
+static LINES: Channel<CriticalSectionRawMutex, heapless::String<LINE_LEN>, LINE_QUEUE> =
+    Channel::new();
+
+/// Lines dropped because the UART couldn't keep up.
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Decodes the advertising reports of the scanner.
+struct Gateway {
+    /// Cipher with the key shared with `ble_beacon`, if provisioned.
+    cipher: Option<BeaconCipher<Aes>>,
+    roster: Mutex<CriticalSectionRawMutex, RefCell<Roster>>,
+}
+
+impl Gateway {
+    /// Decode the advertising data of a report.
+    fn decode(&self, data: &[u8]) -> Option<Record> {
+        if let Some(frame) = eddystone_frame(data) {
+            return Record::from_eddystone(frame);
+        }
+        let payload = manufacturer_data(data, COMPANY_ID)?;
+        if let Some(beacon) = SlotBeacon::decode(payload) {
+            self.slot(&beacon);
+            return Some(Record::Slot {
+                unit: beacon.unit,
+                epoch: beacon.epoch,
+            });
+        }
+        let mut buf = [0; 31];
+        let buf = buf.get_mut(..payload.len())?;
+        buf.copy_from_slice(payload);
+        if buf.len() < BEACON_OVERHEAD {
+            return None;
+        }
+        Record::from_beacon(self.cipher.as_ref()?.decrypt(buf).ok()?)
+    }
+
+    /// Track the units of the epochs, write the missing ones of the last.
+    fn slot(&self, beacon: &SlotBeacon) {
+        let Some((epoch, missing)) = self
+            .roster
+            .lock(|roster| roster.borrow_mut().observe(beacon.unit, beacon.epoch))
+        else {
+            return;
+        };
+        if missing == 0 {
+            return;
+        }
+        let mut line = heapless::String::new();
+        if write_missing(&mut line, epoch, missing).is_err() || LINES.try_send(line).is_err() {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl EventHandler for Gateway {
+    fn on_adv_reports(&self, reports: LeAdvReportsIter) {
+        for report in reports.flatten() {
+            let Some(record) = self.decode(report.data) else {
+                continue;
+            };
+            let mut line = heapless::String::new();
+            if record
+                .write_line(&mut line, &report.addr.into_inner(), report.rssi)
+                .is_err()
+                || LINES.try_send(line).is_err()
+            {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Write the decoded records to the UART.
+#[embassy_executor::task]
+async fn uart_task(mut tx: UarteTx<'static>) {
+    loop {
+        let line = LINES.receive().await;
+        if let Err(e) = tx.write(line.as_bytes()).await {
+            warn!("[gateway] UART write failed: {:?}", e);
+        }
+    }
+}
+
+/// Log the number of dropped lines periodically.
+#[embassy_executor::task]
+async fn report_task() {
+    loop {
+        Timer::after(REPORT_INTERVAL).await;
+        let dropped = DROPPED.load(Ordering::Relaxed);
+        if dropped > 0 {
+            warn!("[gateway] {} lines dropped", dropped);
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    bind_interrupts!(struct Irqs {
+        UARTE1 => uarte::InterruptHandler<peripherals::UARTE1>;
+    });
+
+    #[cfg(feature = "cryptocell")]
+    nrf52_radio_rs::crypto::hw::init();
+    let board = Board::default();
+    let (sdc, mpsl) = board
+        .ble
+        .peripheral_links(0)
+        .init(board.timer0, board.rng)
+        .unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+
+    let Some((_, console_tx)) = board.console else {
+        panic!("[main] no console UART on this board");
+    };
+    let tx = UarteTx::new(board.uarte1, Irqs, console_tx, {
+        let mut c = Config::default();
+        c.baudrate = Baudrate::BAUD115200;
+        c
+    });
+    spawner.must_spawn(uart_task(tx));
+    spawner.must_spawn(report_task());
+
+    let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xfc]);
+    info!("Our address = {:?}", address);
+
+    let mut resources = ObserverResources::new();
+    let stack = resources.stack(sdc, address);
+    let Host {
+        central,
+        mut runner,
+        ..
+    } = stack.build();
+    let cipher = match keystore::handle(KeyId::Beacon).and_then(|key| key.aes()) {
+        Ok(key) => Some(BeaconCipher::new(key, COMPANY_ID, [0; 4], 0)),
+        Err(e) => {
+            warn!("No provisioned beacon key ({:?}), not decrypting", e);
+            None
+        }
+    };
+    let gateway = Gateway {
+        cipher,
+        roster: Mutex::new(RefCell::new(Roster::new(&SCHEDULE))),
+    };
+    let mut scanner = Scanner::new(central);
+    let _ = join(runner.run_with_handler(&gateway), async {
+        // Active scanning, the TLM and EID frames are in scan responses.
+        let config = ScanConfig {
+            active: true,
+            ..Default::default()
+        };
+        match scanner.scan(&config).await {
+            Ok(_session) => {
+                info!("[gateway] scanning");
+                core::future::pending::<()>().await
+            }
+            Err(e) => {
+                let e = defmt::Debug2Format(&e);
+                panic!("[gateway] couldn't start scanning: {:?}", e);
+            }
+        }
+    })
+    .await;
+    panic!("[main] BLE runner terminated");
+}
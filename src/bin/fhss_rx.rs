@@ -0,0 +1,52 @@
+//! Receiving side of the frequency hopping link.
+//!
+//! Logs the uptime sent by `fhss_tx`, see [`fhss`](nrf52_radio_rs::fhss).
+
+#![no_std]
+#![no_main]
+
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    bsp::ble::timeslot::Timeslot,
+    fhss::{self, LINK_KEY, Receiver},
+};
+
+static RECEIVER: Receiver = Receiver::new();
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Keep the timeslot session going.
+#[embassy_executor::task]
+async fn timeslot_task(session: Timeslot) {
+    session
+        .keep_requesting(RECEIVER.first_request(&LINK_KEY))
+        .await
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    let board = Board::default();
+    let (_sdc, mpsl) = board
+        .ble
+        .peripheral_links(0)
+        .init(board.timer0, board.rng)
+        .unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+    let session = Timeslot::open(mpsl, &RECEIVER).unwrap();
+    spawner.must_spawn(timeslot_task(session));
+
+    loop {
+        let payload = fhss::receive().await;
+        match <[u8; 4]>::try_from(payload.as_slice()) {
+            Ok(bytes) => info!("[fhss] transmitter up {} s", u32::from_le_bytes(bytes)),
+            Err(_) => warn!("[fhss] unexpected payload {=[u8]:x}", payload),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Transmitting side of the frequency hopping link.
+//!
+//! Sends the uptime to `fhss_rx` every second and logs how many attempts
+//! each payload needed, see [`fhss`](nrf52_radio_rs::fhss).
+
+#![no_std]
+#![no_main]
+
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Instant, Timer};
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    bsp::ble::timeslot::Timeslot,
+    fhss::{self, LINK_KEY, Outcome, Transmitter},
+};
+
+/// Interval of the telemetry.
+const SEND_INTERVAL: Duration = Duration::from_secs(1);
+
+static TRANSMITTER: Transmitter = Transmitter::new();
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Keep the timeslot session going.
+#[embassy_executor::task]
+async fn timeslot_task(session: Timeslot) {
+    session
+        .keep_requesting(TRANSMITTER.first_request(&LINK_KEY))
+        .await
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    let board = Board::default();
+    let (_sdc, mpsl) = board
+        .ble
+        .peripheral_links(0)
+        .init(board.timer0, board.rng)
+        .unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+    let session = Timeslot::open(mpsl, &TRANSMITTER).unwrap();
+    spawner.must_spawn(timeslot_task(session));
+
+    loop {
+        let uptime = Instant::now().as_secs() as u32;
+        match fhss::send(&uptime.to_le_bytes()).await {
+            Outcome::Delivered { attempts } => {
+                info!(
+                    "[fhss] uptime {} delivered after {} attempts",
+                    uptime, attempts
+                )
+            }
+            Outcome::Dropped => warn!("[fhss] uptime {} dropped", uptime),
+        }
+        Timer::after(SEND_INTERVAL).await;
+    }
+}
@@ -0,0 +1,85 @@
+//! Item-finder tag.
+//!
+//! Advertises a public key in the offline finding format, so finder devices
+//! of the community network nearby report the location of the tag,
+//! encrypted for the owner of the private key (e.g. with OpenHaystack).
+//! The public key is [provisioned](nrf52_radio_rs::keystore) per tag, the
+//! tag doesn't advertise without one. Build with `--features item-finder`.
+
+#![no_std]
+#![no_main]
+
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_time::Duration;
+use nrf_sdc::SoftdeviceController;
+use nrf52_radio_rs::{
+    self as _, Board,
+    bsp::{
+        ble::{
+            BeaconResources,
+            adv::{self, Ad},
+        },
+        rng::Rng,
+    },
+    crypto::finder::{APPLE_COMPANY_ID, OfflineFinding},
+    keystore,
+};
+use trouble_host::prelude::*;
+
+/// Advertising interval of item-finder tags.
+const INTERVAL: Duration = Duration::from_secs(2);
+
+#[embassy_executor::task]
+async fn tag(sdc: SoftdeviceController<'static>, mut rng: Rng) {
+    let public_key = match keystore::finder_public_key() {
+        Ok(key) => key,
+        Err(e) => {
+            warn!("No provisioned public key ({:?}), not advertising", e);
+            return;
+        }
+    };
+    let finding = OfflineFinding::new(&public_key, 0);
+    let address = Address::random(finding.address);
+    info!("Our address = {:?}", address);
+
+    let mut resources = BeaconResources::new();
+    let stack = resources.stack(sdc, address);
+    let Host {
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+
+    let adv_data = adv::payload(&[Ad::Manufacturer {
+        company_identifier: APPLE_COMPANY_ID,
+        payload: &finding.payload,
+    }])
+    .payload;
+
+    let mut params = AdvertisementParameters::default();
+    params.interval_min = INTERVAL;
+    params.interval_max = INTERVAL + Duration::from_millis(rng.below(100) as u64);
+    let _ = join(runner.run(), async {
+        let _advertiser = peripheral
+            .advertise(
+                &params,
+                Advertisement::NonconnectableNonscannableUndirected {
+                    adv_data: &adv_data,
+                },
+            )
+            .await
+            .unwrap();
+        info!("Advertising offline finding key");
+        core::future::pending::<()>().await
+    })
+    .await;
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let b = Board::default();
+    let (sdc, _mpsl) = b.ble.init(b.timer0, b.rng).unwrap();
+    spawner.spawn(tag(sdc, b.rng)).unwrap();
+}
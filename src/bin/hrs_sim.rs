@@ -0,0 +1,129 @@
+//! Simulated heart rate monitor over the BLE Heart Rate Service.
+//!
+//! Notifies a synthetic heart rate with RR intervals once a second, see
+//! [`hrs_sim`](nrf52_radio_rs::hrs_sim), for testing phone apps without a
+//! chest strap. The beats repeat for every boot, the measurements go
+//! through the [notification queue](nrf52_radio_rs::bsp::ble::notify).
+
+#![no_std]
+#![no_main]
+
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_futures::select::{Either, select};
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    bsp::ble::{PeripheralResources, notify},
+    gatt::HeartRateService,
+    hrs_sim,
+};
+use trouble_host::prelude::*;
+
+/// Static random address of the simulator.
+const ADDRESS: [u8; 6] = [0x48, 0x52, 0x53, 0x05, 0xe4, 0xfc];
+
+/// Seed of the beat-to-beat jitter.
+const SEED: u32 = 0x4852_5331;
+
+#[gatt_server]
+struct Server {
+    heart_rate_service: HeartRateService,
+}
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Beat and produce the measurements.
+#[embassy_executor::task]
+async fn heart_task() {
+    hrs_sim::run(SEED).await
+}
+
+/// Notify the measurements until disconnected.
+async fn serve(server: &Server<'_>, conn: &GattConnection<'_, '_, DefaultPacketPool>) {
+    let service = &server.heart_rate_service;
+    loop {
+        match select(conn.next(), hrs_sim::next_measurement()).await {
+            Either::First(GattConnectionEvent::Disconnected { reason }) => {
+                info!("[gatt] disconnected: {:?}", reason);
+                return;
+            }
+            Either::First(GattConnectionEvent::Gatt { event }) => match event.accept() {
+                Ok(reply) => reply.send().await,
+                Err(e) => warn!("[gatt] error sending response: {:?}", e),
+            },
+            Either::First(_) => {}
+            Either::Second(value) => {
+                if let Err(e) = notify::send(&service.measurement, conn, &value).await {
+                    warn!("[gatt] error notifying heart rate: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Advertise and serve one central after the other.
+async fn run<C: Controller>(
+    peripheral: &mut Peripheral<'_, C, DefaultPacketPool>,
+    server: &Server<'_>,
+) -> Result<(), BleHostError<C::Error>> {
+    let mut adv_data = [0; 31];
+    let len = AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            // Heart Rate Service
+            AdStructure::ServiceUuids16(&[[0x0d, 0x18]]),
+            AdStructure::CompleteLocalName(b"HRS Sim"),
+        ],
+        &mut adv_data[..],
+    )?;
+    loop {
+        let advertiser = peripheral
+            .advertise(
+                &Default::default(),
+                Advertisement::ConnectableScannableUndirected {
+                    adv_data: &adv_data[..len],
+                    scan_data: &[],
+                },
+            )
+            .await?;
+        info!("[hrs_sim] advertising");
+        let conn = advertiser.accept().await?.with_attribute_server(server)?;
+        info!("[hrs_sim] connection established");
+        serve(server, &conn).await;
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    let board = Board::default();
+    let (sdc, mpsl) = board.ble.init(board.timer0, board.rng).unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+    spawner.must_spawn(heart_task());
+
+    let mut resources = PeripheralResources::new();
+    let stack = resources.stack(sdc, Address::random(ADDRESS));
+    let Host {
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+    let server = Server::new_with_config(GapConfig::Peripheral(PeripheralConfig {
+        name: "HRS Sim",
+        appearance: &appearance::heart_rate_sensor::HEART_RATE_BELT,
+    }))
+    .unwrap();
+    let _ = join(runner.run(), async {
+        if let Err(e) = run(&mut peripheral, &server).await {
+            let e = defmt::Debug2Format(&e);
+            panic!("[hrs_sim] error: {:?}", e);
+        }
+    })
+    .await;
+    panic!("[main] BLE runner terminated");
+}
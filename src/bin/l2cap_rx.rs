@@ -0,0 +1,121 @@
+//! Receiving side (peripheral) of the L2CAP throughput test.
+//!
+//! Advertises, accepts a connection and an L2CAP connection oriented channel
+//! from `l2cap_tx`, counts the received bytes and answers echo requests.
+
+#![no_std]
+#![no_main]
+
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_time::Duration;
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    bsp::ble::L2capResources,
+    throughput::{FrameHeader, FrameKind, HEADER_LEN, PSM, RX_ADDRESS, ThroughputMeter},
+};
+use trouble_host::prelude::*;
+
+/// Maximum SDU size of the L2CAP channel.
+const SDU_MAX: usize = 251;
+
+/// Interval in which the throughput is reported.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Accept connections and channels and measure the incoming data.
+async fn receive<C: Controller>(
+    stack: &Stack<'_, C, DefaultPacketPool>,
+    peripheral: &mut Peripheral<'_, C, DefaultPacketPool>,
+) -> Result<(), BleHostError<C::Error>> {
+    let mut adv_data = [0; 31];
+    let len = AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::CompleteLocalName(b"L2CAP RX"),
+        ],
+        &mut adv_data[..],
+    )?;
+    let config = L2capChannelConfig {
+        mtu: Some(SDU_MAX as u16),
+        ..Default::default()
+    };
+    loop {
+        let advertiser = peripheral
+            .advertise(
+                &Default::default(),
+                Advertisement::ConnectableScannableUndirected {
+                    adv_data: &adv_data[..len],
+                    scan_data: &[],
+                },
+            )
+            .await?;
+        info!("[l2cap_rx] advertising");
+        let conn = advertiser.accept().await?;
+        info!("[l2cap_rx] connection established");
+
+        let mut channel = L2capChannel::accept(stack, &conn, &[PSM], &config).await?;
+        info!("[l2cap_rx] channel accepted");
+
+        let mut meter = ThroughputMeter::new(REPORT_INTERVAL);
+        let mut rx = [0u8; SDU_MAX];
+        let mut echo = [0u8; HEADER_LEN];
+        loop {
+            let len = match channel.receive(stack, &mut rx).await {
+                Ok(len) => len,
+                Err(e) => {
+                    warn!("[l2cap_rx] channel closed: {:?}", e);
+                    break;
+                }
+            };
+            match FrameHeader::decode(&rx[..len]) {
+                Ok(header) => {
+                    meter.record_frame(&header, len);
+                    if header.kind == FrameKind::EchoRequest {
+                        let reply = FrameHeader {
+                            kind: FrameKind::Echo,
+                            ..header
+                        };
+                        let echo_len = reply.encode(&mut echo).unwrap_or(0);
+                        channel.send(stack, &echo[..echo_len]).await?;
+                    }
+                }
+                Err(e) => warn!("[l2cap_rx] invalid frame: {:?}", e),
+            }
+            meter.report_if_due();
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    let board = Board::default();
+    let (sdc, mpsl) = board.ble.init(board.timer0, board.rng).unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+
+    let address: Address = Address::random(RX_ADDRESS);
+    info!("Our address = {:?}", address);
+
+    let mut resources = L2capResources::new();
+    let stack = resources.stack(sdc, address);
+    let Host {
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+    let _ = join(runner.run(), async {
+        if let Err(e) = receive(&stack, &mut peripheral).await {
+            let e = defmt::Debug2Format(&e);
+            panic!("[l2cap_rx] error: {:?}", e);
+        }
+    })
+    .await;
+    panic!("[main] BLE runner terminated");
+}
@@ -0,0 +1,128 @@
+//! Sending side (central) of the L2CAP throughput test.
+//!
+//! Connects to `l2cap_rx`, opens an L2CAP connection oriented channel and
+//! sends frames as fast as possible. Every [`ECHO_EVERY`]th frame requests an
+//! echo to measure the round trip latency.
+
+#![no_std]
+#![no_main]
+
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_time::Duration;
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    bsp::ble::L2capResources,
+    throughput::{
+        FrameHeader, FrameKind, HEADER_LEN, PSM, RX_ADDRESS, ThroughputMeter, timestamp_us,
+    },
+};
+use trouble_host::prelude::*;
+
+/// SDU size used for the data frames. Tune together with the packet pool.
+const SDU_LEN: usize = 251;
+
+/// Every n-th frame requests an echo from the receiver.
+const ECHO_EVERY: u32 = 50;
+
+/// Interval in which the throughput is reported.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Connect to the receiver and send frames until the channel breaks.
+async fn transmit<C: Controller>(
+    stack: &Stack<'_, C, DefaultPacketPool>,
+    central: &mut Central<'_, C, DefaultPacketPool>,
+) -> Result<(), BleHostError<C::Error>> {
+    let target = Address::random(RX_ADDRESS);
+    let connect_config = ConnectConfig {
+        connect_params: Default::default(),
+        scan_config: ScanConfig {
+            filter_accept_list: &[(target.kind, &target.addr)],
+            ..Default::default()
+        },
+    };
+    let channel_config = L2capChannelConfig {
+        mtu: Some(SDU_LEN as u16),
+        ..Default::default()
+    };
+    loop {
+        info!("[l2cap_tx] connecting to {:?}", target);
+        let conn = central.connect(&connect_config).await?;
+        info!("[l2cap_tx] connected, opening channel");
+        let mut channel = L2capChannel::create(stack, &conn, PSM, &channel_config).await?;
+
+        let mut meter = ThroughputMeter::new(REPORT_INTERVAL);
+        let mut tx = [0u8; SDU_LEN];
+        let mut echo = [0u8; HEADER_LEN];
+        let mut seq = 0u32;
+        loop {
+            let kind = if seq % ECHO_EVERY == 0 {
+                FrameKind::EchoRequest
+            } else {
+                FrameKind::Data
+            };
+            let header = FrameHeader {
+                kind,
+                seq,
+                timestamp_us: timestamp_us(),
+            };
+            let len = header.encode(&mut tx).unwrap_or(0);
+            if let Err(e) = channel.send(stack, &tx[..len]).await {
+                warn!("[l2cap_tx] channel closed: {:?}", e);
+                break;
+            }
+            meter.record_frame(&header, len);
+
+            if kind == FrameKind::EchoRequest {
+                let len = channel.receive(stack, &mut echo).await?;
+                match FrameHeader::decode(&echo[..len]) {
+                    Ok(reply) if reply.kind == FrameKind::Echo && reply.seq == seq => {
+                        meter.record_echo(&reply)
+                    }
+                    Ok(reply) => warn!("[l2cap_tx] unexpected reply: {:?}", reply),
+                    Err(e) => warn!("[l2cap_tx] invalid reply: {:?}", e),
+                }
+            }
+            seq = seq.wrapping_add(1);
+            meter.report_if_due();
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    let board = Board::default();
+    let (sdc, mpsl) = board
+        .ble
+        .with_central()
+        .init(board.timer0, board.rng)
+        .unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+
+    let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xfd]);
+    info!("Our address = {:?}", address);
+
+    let mut resources = L2capResources::new();
+    let stack = resources.stack(sdc, address);
+    let Host {
+        mut central,
+        mut runner,
+        ..
+    } = stack.build();
+    let _ = join(runner.run(), async {
+        if let Err(e) = transmit(&stack, &mut central).await {
+            let e = defmt::Debug2Format(&e);
+            panic!("[l2cap_tx] error: {:?}", e);
+        }
+    })
+    .await;
+    panic!("[main] BLE runner terminated");
+}
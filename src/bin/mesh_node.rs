@@ -0,0 +1,246 @@
+//! Sensor node of an existing Bluetooth Mesh network.
+//!
+//! Publishes the die temperature in a Sensor Status message to
+//! [`PUBLISH_ADDRESS`] every [`PUBLISH_INTERVAL`] over the advertising
+//! bearer and logs the access messages it receives, see
+//! [`mesh`](nrf52_radio_rs::mesh). Relaying and acknowledgements are left
+//! to the other nodes.
+//!
+//! The network and application keys, shared by all nodes of the network,
+//! are baked in at build time from the `CONFIG_MESH_NET_KEY` and
+//! `CONFIG_MESH_APP_KEY` environment variables (32 hex digits, checked by
+//! `build.rs`). The unicast address and the IV index are the
+//! `mesh_address` and `mesh_iv_index` [settings](nrf52_radio_rs::settings)
+//! of the node. Sequence numbers are reserved in blocks of [`SEQ_BLOCK`]
+//! in the `mesh_seq` setting, so none is used twice across reboots.
+
+#![no_std]
+#![no_main]
+
+use bt_hci::param::LeAdvReportsIter;
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_futures::select::{Either, select};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Duration, Ticker, Timer};
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    bsp::{
+        ble::BeaconResources,
+        flash::{self, Partition},
+        thermal,
+    },
+    crypto::Aes,
+    mesh::{self, AppKey, MeshError, Network, NetworkPdu, Node},
+    settings::{self, Store},
+    supervisor::supervise,
+};
+use trouble_host::prelude::*;
+
+/// Network key as hex digits, `None` if the firmware was built without one.
+const NET_KEY: Option<&str> = option_env!("CONFIG_MESH_NET_KEY");
+
+/// Application key as hex digits, `None` if the firmware was built without
+/// one.
+const APP_KEY: Option<&str> = option_env!("CONFIG_MESH_APP_KEY");
+
+/// Group the sensor readings are published to.
+const PUBLISH_ADDRESS: u16 = 0xC000;
+
+/// Interval of the sensor readings.
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// TTL of the published messages.
+const TTL: u8 = 5;
+
+/// Network PDUs of the longest message published.
+const MAX_PDUS: usize = 2;
+
+/// Sequence numbers reserved in the settings at once.
+const SEQ_BLOCK: u32 = 1024;
+
+/// Highest sequence number, the network has to move to the next IV index
+/// before it runs out.
+const SEQ_MAX: u32 = 0xFF_FFFF;
+
+/// Advertising interval of the network PDUs.
+const ADV_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Time every network PDU is advertised for, a few transmissions.
+const PDU_DWELL: Duration = Duration::from_millis(100);
+
+/// Network PDUs received, waiting to be decrypted.
+static RECEIVED: Channel<CriticalSectionRawMutex, NetworkPdu, 8> = Channel::new();
+
+/// Queues the network PDUs of the advertising reports.
+struct Bearer;
+
+impl EventHandler for Bearer {
+    fn on_adv_reports(&self, reports: LeAdvReportsIter) {
+        for report in reports.flatten() {
+            for pdu in mesh::network_pdus(report.data) {
+                let Ok(pdu) = NetworkPdu::from_slice(pdu) else {
+                    continue;
+                };
+                if RECEIVED.try_send(pdu).is_err() {
+                    warn!("[mesh] receive queue full, PDU dropped");
+                }
+            }
+        }
+    }
+}
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Persist changed settings.
+#[embassy_executor::task]
+async fn settings_task(mut store: Store<Partition>) {
+    supervise(&mut store).await
+}
+
+/// Reserve the next block of sequence numbers once half of the current one
+/// is used, the block end written to flash well before it's reached.
+fn reserve_seq(node: &Node<Aes>, reserved: &mut u32) {
+    if node.seq().saturating_add(SEQ_BLOCK / 2) >= *reserved {
+        *reserved = node.seq().saturating_add(SEQ_BLOCK);
+        settings::update(|s| s.mesh_seq = *reserved);
+    }
+}
+
+/// Publish the die temperature.
+async fn publish<C: Controller, P: PacketPool>(
+    node: &mut Node<Aes>,
+    peripheral: &mut Peripheral<'_, C, P>,
+    params: &AdvertisementParameters,
+) {
+    if node.seq().saturating_add(MAX_PDUS as u32) > SEQ_MAX {
+        warn!("[mesh] sequence numbers used up, waiting for the next IV index");
+        return;
+    }
+    // 0.25 °C to 0.01 °C
+    let centi_c = (thermal::die_temperature() * 25) as i16;
+    let access = mesh::sensor_status(
+        mesh::PRESENT_DEVICE_OPERATING_TEMPERATURE,
+        &centi_c.to_le_bytes(),
+    )
+    .unwrap();
+    let pdus = match node.send::<MAX_PDUS>(PUBLISH_ADDRESS, &access) {
+        Ok(pdus) => pdus,
+        Err(e) => {
+            warn!("[mesh] couldn't encrypt the sensor status: {:?}", e);
+            return;
+        }
+    };
+    for pdu in &pdus {
+        let mut adv_data = [0; 31];
+        let len = mesh::adv_data(pdu, &mut adv_data).unwrap();
+        let advertisement = Advertisement::NonconnectableNonscannableUndirected {
+            adv_data: &adv_data[..len],
+        };
+        match peripheral.advertise(params, advertisement).await {
+            Ok(_advertiser) => Timer::after(PDU_DWELL).await,
+            Err(e) => warn!("[mesh] advertising failed: {:?}", defmt::Debug2Format(&e)),
+        }
+    }
+    info!("[mesh] published {} centi-°C", centi_c);
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    #[cfg(feature = "cryptocell")]
+    nrf52_radio_rs::crypto::hw::init();
+    let board = Board::default();
+    let (sdc, mpsl) = board
+        .ble
+        .peripheral_links(0)
+        .init(board.timer0, board.rng)
+        .unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+    let shared_flash = flash::init(mpsl, board.nvmc);
+    let mut store = Store::new(flash::SETTINGS.partition(shared_flash));
+    if store.load().await.is_err() {
+        warn!("[main] couldn't read settings");
+    }
+    spawner.must_spawn(settings_task(store));
+
+    let (Some(net_key), Some(app_key)) = (
+        NET_KEY.and_then(mesh::decode_key),
+        APP_KEY.and_then(mesh::decode_key),
+    ) else {
+        panic!("[main] built without the mesh keys");
+    };
+    let settings = settings::get();
+    let Some(address) = u16::try_from(settings.mesh_address)
+        .ok()
+        .filter(|address| *address != 0)
+    else {
+        panic!("[main] no mesh address provisioned");
+    };
+    let network = Network::<Aes>::new(net_key, settings.mesh_iv_index).unwrap();
+    let app_key = AppKey::new::<Aes>(app_key).unwrap();
+    let mut node = Node::new(network, app_key, address, settings.mesh_seq, TTL);
+    let mut reserved = settings.mesh_seq;
+    reserve_seq(&node, &mut reserved);
+    info!(
+        "[main] mesh node {:04x}, IV index {}, sequence number {}",
+        address,
+        settings.mesh_iv_index,
+        node.seq()
+    );
+
+    let [lo, hi] = address.to_le_bytes();
+    let address: Address = Address::random([lo, hi, 0x1a, 0x05, 0xe6, 0xff]);
+    info!("Our address = {:?}", address);
+
+    let mut resources = BeaconResources::new();
+    let stack = resources.stack(sdc, address);
+    let Host {
+        central,
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+    let mut scanner = Scanner::new(central);
+    let _ = join(runner.run_with_handler(&Bearer), async {
+        let _session = match scanner.scan(&ScanConfig::default()).await {
+            Ok(session) => Some(session),
+            Err(e) => {
+                warn!(
+                    "[mesh] couldn't start scanning: {:?}",
+                    defmt::Debug2Format(&e)
+                );
+                None
+            }
+        };
+        let mut params = AdvertisementParameters::default();
+        params.interval_min = ADV_INTERVAL;
+        params.interval_max = ADV_INTERVAL;
+        let mut ticker = Ticker::every(PUBLISH_INTERVAL);
+        loop {
+            match select(ticker.next(), RECEIVED.receive()).await {
+                Either::First(()) => {
+                    reserve_seq(&node, &mut reserved);
+                    publish(&mut node, &mut peripheral, &params).await;
+                }
+                Either::Second(mut pdu) => match node.receive(&mut pdu) {
+                    Ok(Some(message)) => info!(
+                        "[mesh] from {:04x} to {:04x}: {=[u8]:02x}",
+                        message.header.src, message.header.dst, message.access
+                    ),
+                    Ok(None) => {}
+                    // Other networks and keys share the bearer.
+                    Err(MeshError::OtherNetwork | MeshError::OtherKey) => {}
+                    Err(e) => warn!("[mesh] dropped a PDU: {:?}", e),
+                },
+            }
+        }
+    })
+    .await;
+    panic!("[main] BLE runner terminated");
+}
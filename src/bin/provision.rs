@@ -0,0 +1,159 @@
+//! Factory provisioning over USB.
+//!
+//! Flashed once per device before the application firmware. The device
+//! enumerates as a USB serial port (CDC ACM) and takes the line protocol of
+//! [`provision`]: device name, keys, compass calibration and settings,
+//! stored in the keystore and the settings page. The `lock` request ends
+//! provisioning, afterwards every request is refused:
+//!
+//! ```text
+//! name Tracker 7
+//! key eid 0123456789abcdef0123456789abcdef
+//! cal -310 -280 -400 295 330 250
+//! lock
+//! debug-lock confirm
+//! ```
+
+#![no_std]
+#![no_main]
+
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_nrf::{
+    bind_interrupts, peripherals,
+    usb::{self, Driver, vbus_detect::HardwareVbusDetect},
+};
+use embassy_usb::{
+    Builder,
+    class::cdc_acm::{CdcAcmClass, State},
+    driver::EndpointError,
+};
+use heapless::String;
+use nrf52_radio_rs::{
+    Board,
+    bsp::{
+        approtect,
+        flash::{self, NvmcFlash, Partition},
+    },
+    provision,
+    settings::Store,
+    supervisor::supervise,
+};
+
+bind_interrupts!(struct Irqs {
+    USBD => usb::InterruptHandler<peripherals::USBD>;
+    CLOCK_POWER => usb::vbus_detect::InterruptHandler;
+});
+
+/// Vendor ID of pid.codes.
+const VID: u16 = 0x1209;
+
+/// Product ID, a pid.codes test PID.
+const PID: u16 = 0x0002;
+
+/// Size of a USB packet.
+const PACKET_LEN: usize = 64;
+
+/// Longest request line.
+const LINE_LEN: usize = 128;
+
+/// Longest response, the status.
+const RESPONSE_LEN: usize = 256;
+
+/// Write the settings to flash whenever they were changed.
+#[embassy_executor::task]
+async fn settings_task(mut store: Store<Partition<NvmcFlash>>) {
+    supervise(&mut store).await
+}
+
+/// Send `text` in packets.
+async fn send<'d>(
+    class: &mut CdcAcmClass<'d, Driver<'d, HardwareVbusDetect>>,
+    text: &str,
+) -> Result<(), EndpointError> {
+    for packet in text.as_bytes().chunks(PACKET_LEN) {
+        class.write_packet(packet).await?;
+    }
+    Ok(())
+}
+
+/// Serve requests until the host disconnects.
+async fn serve<'d>(
+    class: &mut CdcAcmClass<'d, Driver<'d, HardwareVbusDetect>>,
+) -> Result<(), EndpointError> {
+    let mut packet = [0; PACKET_LEN];
+    let mut line = String::<LINE_LEN>::new();
+    loop {
+        let n = class.read_packet(&mut packet).await?;
+        for &byte in &packet[..n] {
+            match byte {
+                b'\r' | b'\n' if line.is_empty() => {}
+                b'\r' | b'\n' => {
+                    let mut response = String::<RESPONSE_LEN>::new();
+                    if provision::handle(&line, &mut response).await.is_err() {
+                        response.clear();
+                        let _ = response.push_str("ERR response too long\r\n");
+                    }
+                    send(class, &response).await?;
+                    line.clear();
+                }
+                byte if byte.is_ascii() && line.push(byte as char).is_ok() => {}
+                _ => {
+                    line.clear();
+                    send(class, "ERR line too long\r\n").await?;
+                }
+            }
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let board = Board::default();
+    approtect::log();
+    // USB takes the clock interrupt, there is no MPSL.
+    let shared_flash = flash::init_nvmc(board.nvmc);
+    let mut store = Store::new(flash::SETTINGS.partition(shared_flash));
+    match store.load().await {
+        Ok(false) => info!("[provision] blank settings, first boot"),
+        Ok(true) => info!("[provision] settings stored already"),
+        Err(_) => warn!("[provision] couldn't read settings"),
+    }
+    spawner.must_spawn(settings_task(store));
+    if !provision::open() {
+        warn!("[provision] locked, flash the application firmware");
+    }
+
+    let mut config = embassy_usb::Config::new(VID, PID);
+    config.manufacturer = Some("nrf52-radio-rs");
+    config.product = Some("Provisioning");
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut state = State::new();
+    let mut builder = Builder::new(
+        Driver::new(board.usbd, Irqs, HardwareVbusDetect::new(Irqs)),
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [],
+        &mut control_buf,
+    );
+    let mut class = CdcAcmClass::new(&mut builder, &mut state, PACKET_LEN as u16);
+    let mut device = builder.build();
+
+    let requests = async {
+        loop {
+            class.wait_connection().await;
+            info!("[provision] host connected");
+            if let Err(e) = serve(&mut class).await {
+                info!("[provision] host disconnected: {:?}", e);
+            }
+        }
+    };
+    join(device.run(), requests).await;
+}
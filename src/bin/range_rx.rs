@@ -0,0 +1,131 @@
+//! Receiving side of the range test.
+//!
+//! Scans for the frames of `range_tx` and shows the packet error rate and
+//! RSSI of every [`WINDOW`] on the OLED, see
+//! [`range`](nrf52_radio_rs::range).
+
+#![no_std]
+#![no_main]
+
+use core::cell::RefCell;
+
+use bt_hci::param::LeAdvReportsIter;
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_nrf::{
+    bind_interrupts, peripherals,
+    twim::{self, Twim},
+};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Timer};
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    bsp::ble::ObserverResources,
+    gateway::manufacturer_data,
+    range::{COMPANY_ID, Frame, PerCounter, TX_ADDRESS, WINDOW},
+    ui::{self, RangePage},
+};
+use trouble_host::prelude::*;
+
+/// Scan interval and window, equal for a 100% duty cycle.
+const SCAN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Counts the frames of the transmitter.
+struct RangeCounter(Mutex<CriticalSectionRawMutex, RefCell<PerCounter>>);
+
+impl EventHandler for RangeCounter {
+    fn on_adv_reports(&self, reports: LeAdvReportsIter) {
+        for report in reports.flatten() {
+            if report.addr.into_inner() != TX_ADDRESS {
+                continue;
+            }
+            if let Some(frame) = manufacturer_data(report.data, COMPANY_ID).and_then(Frame::decode)
+            {
+                self.0.lock(|c| c.borrow_mut().record(&frame, report.rssi));
+            }
+        }
+    }
+}
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    bind_interrupts!(struct Irqs {
+        TWISPI0 => twim::InterruptHandler<peripherals::TWISPI0>;
+    });
+
+    let board = Board::default();
+    let (sdc, mpsl) = board
+        .ble
+        .peripheral_links(0)
+        .init(board.timer0, board.rng)
+        .unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+
+    let twim = Twim::new(
+        board.twispi0,
+        Irqs,
+        board.p0_06,
+        board.p0_05,
+        Default::default(),
+        &mut [],
+    );
+    let mut display = match ui::connect(twim) {
+        Ok(display) => Some(display),
+        Err(e) => {
+            warn!("[range_rx] no display: {:?}", e);
+            None
+        }
+    };
+
+    let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xfa]);
+    let mut resources = ObserverResources::new();
+    let stack = resources.stack(sdc, address);
+    let Host {
+        central,
+        mut runner,
+        ..
+    } = stack.build();
+    let counter = RangeCounter(Mutex::new(RefCell::new(PerCounter::new())));
+    let mut scanner = Scanner::new(central);
+    let _ = join(runner.run_with_handler(&counter), async {
+        // Scan continuously, so no advertisement is missed.
+        let config = ScanConfig {
+            interval: SCAN_INTERVAL,
+            window: SCAN_INTERVAL,
+            ..Default::default()
+        };
+        let _session = match scanner.scan(&config).await {
+            Ok(session) => session,
+            Err(e) => {
+                let e = defmt::Debug2Format(&e);
+                panic!("[range_rx] couldn't start scanning: {:?}", e);
+            }
+        };
+        let mut page = RangePage::new();
+        loop {
+            Timer::after(WINDOW).await;
+            let window = counter.0.lock(|c| c.borrow_mut().take_window());
+            info!(
+                "[range_rx] {:?}, PER {:?} permille",
+                window,
+                window.per_permille()
+            );
+            page.set_window(window);
+            if let Some(display) = &mut display
+                && let Err(e) = ui::render(display, &page)
+            {
+                warn!("[range_rx] display error: {:?}", e);
+            }
+        }
+    })
+    .await;
+    panic!("[main] BLE runner terminated");
+}
@@ -0,0 +1,110 @@
+//! Transmitting side of the range test.
+//!
+//! Advertises a numbered frame every [`FRAME_INTERVAL`] at a fixed TX power
+//! for `range_rx`, see [`range`](nrf52_radio_rs::range).
+
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_time::{Duration, Timer};
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    bsp::ble::BeaconResources,
+    range::{COMPANY_ID, FRAME_INTERVAL, Frame, TX_ADDRESS, TX_POWER_DBM},
+};
+use trouble_host::prelude::*;
+
+/// Advertising interval, several copies of every frame are sent.
+const ADV_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Advertising data of a frame.
+fn adv_data(frame: &Frame, buf: &mut [u8]) -> Result<usize, Error> {
+    AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::ManufacturerSpecificData {
+                company_identifier: COMPANY_ID,
+                payload: &frame.encode(),
+            },
+        ],
+        buf,
+    )
+}
+
+/// Advertise the frames forever.
+async fn transmit<C: Controller>(
+    peripheral: &mut Peripheral<'_, C, DefaultPacketPool>,
+) -> Result<(), BleHostError<C::Error>> {
+    let params = AdvertisementParameters {
+        interval_min: ADV_INTERVAL,
+        interval_max: ADV_INTERVAL,
+        // Keep in sync with TX_POWER_DBM.
+        tx_power: TxPower::ZerodBm,
+        ..Default::default()
+    };
+    let mut frame = Frame {
+        seq: 0,
+        tx_power: TX_POWER_DBM,
+    };
+    let mut buf = [0; 31];
+    let len = adv_data(&frame, &mut buf)?;
+    let _advertiser = peripheral
+        .advertise(
+            &params,
+            Advertisement::NonconnectableNonscannableUndirected {
+                adv_data: &buf[..len],
+            },
+        )
+        .await?;
+    info!("[range_tx] advertising at {} dBm", TX_POWER_DBM);
+    loop {
+        Timer::after(FRAME_INTERVAL).await;
+        frame.seq = frame.seq.wrapping_add(1);
+        let len = adv_data(&frame, &mut buf)?;
+        peripheral
+            .update_adv_data(Advertisement::NonconnectableNonscannableUndirected {
+                adv_data: &buf[..len],
+            })
+            .await?;
+        if frame.seq % 100 == 0 {
+            info!("[range_tx] frame {}", frame.seq);
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    let board = Board::default();
+    let (sdc, mpsl) = board
+        .ble
+        .peripheral_links(0)
+        .init(board.timer0, board.rng)
+        .unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+
+    let mut resources = BeaconResources::new();
+    let stack = resources.stack(sdc, Address::random(TX_ADDRESS));
+    let Host {
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+    let _ = join(runner.run(), async {
+        if let Err(e) = transmit(&mut peripheral).await {
+            let e = defmt::Debug2Format(&e);
+            panic!("[range_tx] error: {:?}", e);
+        }
+    })
+    .await;
+    panic!("[main] BLE runner terminated");
+}
@@ -0,0 +1,179 @@
+//! Measuring side of the ranging experiment.
+//!
+//! Sends polls to `ranging_responder` in timeslots between its BLE events and
+//! reports the estimated distance over GATT, see
+//! [`ranging`](nrf52_radio_rs::ranging). Writing the known distance in cm to
+//! the calibrate characteristic takes the next estimate as the reference.
+
+#![no_std]
+#![no_main]
+
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{
+    Board,
+    bsp::ble::{PeripheralResources, timeslot::Timeslot},
+    gatt::RangingService,
+    ranging::{self, Estimate, Estimator, Initiator},
+};
+use trouble_host::prelude::*;
+
+/// Static random address of the initiator.
+const ADDRESS: [u8; 6] = [0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xfc];
+
+/// Round trip times per estimate.
+const BATCH: usize = 64;
+
+static INITIATOR: Initiator = Initiator::new();
+
+/// Latest estimate, for the connected central.
+static ESTIMATE: Signal<CriticalSectionRawMutex, Estimate> = Signal::new();
+
+/// Known distance in cm written by the central.
+static CALIBRATE: Signal<CriticalSectionRawMutex, u16> = Signal::new();
+
+#[gatt_server]
+struct Server {
+    ranging_service: RangingService,
+}
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Keep the timeslot session of the initiator going.
+#[embassy_executor::task]
+async fn timeslot_task(session: Timeslot) {
+    session.keep_requesting(Initiator::first_request()).await
+}
+
+/// Turn batches of round trip times into estimates.
+#[embassy_executor::task]
+async fn estimate_task() {
+    let mut estimator = Estimator::<BATCH>::new(0);
+    loop {
+        if !estimator.push(ranging::next_rtt().await) {
+            continue;
+        }
+        let Some(mut estimate) = estimator.estimate() else {
+            continue;
+        };
+        if let Some(known_cm) = CALIBRATE.try_take() {
+            estimator.calibrate(&estimate, known_cm as u32);
+            estimate.distance_cm = known_cm as i32;
+            info!(
+                "[ranging] calibrated at {} cm, offset {} / 16 ticks",
+                known_cm,
+                estimator.offset_subticks()
+            );
+        }
+        info!(
+            "[ranging] {} cm from {} round trips",
+            estimate.distance_cm, estimate.samples
+        );
+        ESTIMATE.signal(estimate);
+    }
+}
+
+/// Notify the estimates and take calibration requests until disconnected.
+async fn serve(server: &Server<'_>, conn: &GattConnection<'_, '_, DefaultPacketPool>) {
+    let service = &server.ranging_service;
+    loop {
+        match select(conn.next(), ESTIMATE.wait()).await {
+            Either::First(GattConnectionEvent::Disconnected { reason }) => {
+                info!("[gatt] disconnected: {:?}", reason);
+                return;
+            }
+            Either::First(GattConnectionEvent::Gatt { event }) => {
+                if let GattEvent::Write(write) = &event
+                    && write.handle() == service.calibrate.handle
+                {
+                    match write.data().try_into() {
+                        Ok(bytes) => CALIBRATE.signal(u16::from_le_bytes(bytes)),
+                        Err(_) => warn!("[gatt] invalid calibration length"),
+                    }
+                }
+                match event.accept() {
+                    Ok(reply) => reply.send().await,
+                    Err(e) => warn!("[gatt] error sending response: {:?}", e),
+                }
+            }
+            Either::First(_) => {}
+            Either::Second(estimate) => {
+                let _ = server.set(&service.samples, &estimate.samples);
+                if let Err(e) = service.distance.notify(conn, &estimate.distance_cm).await {
+                    warn!("[gatt] error notifying distance: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Advertise and serve one central after the other.
+async fn run<C: Controller>(
+    peripheral: &mut Peripheral<'_, C, DefaultPacketPool>,
+    server: &Server<'_>,
+) -> Result<(), BleHostError<C::Error>> {
+    let mut adv_data = [0; 31];
+    let len = AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::CompleteLocalName(b"Ranging"),
+        ],
+        &mut adv_data[..],
+    )?;
+    loop {
+        let advertiser = peripheral
+            .advertise(
+                &Default::default(),
+                Advertisement::ConnectableScannableUndirected {
+                    adv_data: &adv_data[..len],
+                    scan_data: &[],
+                },
+            )
+            .await?;
+        info!("[ranging] advertising");
+        let conn = advertiser.accept().await?.with_attribute_server(server)?;
+        info!("[ranging] connection established");
+        serve(server, &conn).await;
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    let board = Board::default();
+    let (sdc, mpsl) = board.ble.init(board.timer0, board.rng).unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+
+    let session = Timeslot::open(mpsl, &INITIATOR).unwrap();
+    spawner.must_spawn(timeslot_task(session));
+    spawner.must_spawn(estimate_task());
+
+    let mut resources = PeripheralResources::new();
+    let stack = resources.stack(sdc, Address::random(ADDRESS));
+    let Host {
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+    let server = Server::new_with_config(GapConfig::Peripheral(PeripheralConfig {
+        name: "Ranging",
+        appearance: &appearance::sensor::GENERIC_SENSOR,
+    }))
+    .unwrap();
+    let _ = join(runner.run(), async {
+        if let Err(e) = run(&mut peripheral, &server).await {
+            let e = defmt::Debug2Format(&e);
+            panic!("[ranging] error: {:?}", e);
+        }
+    })
+    .await;
+    panic!("[main] BLE runner terminated");
+}
@@ -0,0 +1,34 @@
+//! Echoing side of the ranging experiment.
+//!
+//! Listens for the polls of `ranging_initiator` in long timeslots and echoes
+//! them, see [`ranging`](nrf52_radio_rs::ranging). The BLE controller runs
+//! without any role, only its MPSL schedules the slots.
+
+#![no_std]
+#![no_main]
+
+use embassy_executor::Spawner;
+use nrf_mpsl::MultiprotocolServiceLayer;
+use nrf52_radio_rs::{Board, bsp::ble::timeslot::Timeslot, ranging::Responder};
+
+static RESPONDER: Responder = Responder::new();
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    let board = Board::default();
+    let (_sdc, mpsl) = board
+        .ble
+        .peripheral_links(0)
+        .init(board.timer0, board.rng)
+        .unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+
+    let session = Timeslot::open(mpsl, &RESPONDER).unwrap();
+    session.keep_requesting(Responder::first_request()).await
+}
@@ -0,0 +1,243 @@
+//! Self-test for newly assembled Wio Tracker L1 boards.
+//!
+//! Runs every check once and prints a pass/fail summary via defmt and on the
+//! OLED (if it was found):
+//! - I2C: the OLED answers at 0x3C or 0x3D.
+//! - UARTE: the GNSS module sends an NMEA sentence after being enabled.
+//! - SAADC: the battery voltage is plausible for a LiPo cell, skipped on
+//!   boards without a battery input.
+//! - RADIO: the MPSL and the SoftDevice Controller initialize.
+//! - Flash: a scratch page can be erased, written and read back.
+//!
+//! The flash check uses the first page of the telemetry region, samples
+//! queued there are lost.
+
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+
+use defmt::{error, info, warn};
+use display_interface::DisplayError;
+use embassy_executor::Spawner;
+use embassy_nrf::{
+    bind_interrupts,
+    gpio::{Level, Output, OutputDrive},
+    peripherals,
+    saadc::{self, Saadc},
+    twim::{self, Twim},
+    uarte::{self, Baudrate, Config, Uarte},
+};
+use embassy_time::{Duration, with_timeout};
+use embedded_graphics::prelude::*;
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+use heapless::String;
+use nrf52_radio_rs::{
+    Board,
+    battery::{self, BatteryModel},
+    bsp::{
+        flash::{self, NvmcFlash, PAGE_SIZE, Partition, Region},
+        i2c_probe,
+    },
+    gnss::{ENABLE_GNSS_MODULE, LineReader, NMEA_MAX_LEN},
+    ui::{
+        self, Display,
+        assets::{self, LINE_HEIGHT},
+    },
+};
+
+/// Time the GNSS module gets to send its first sentence.
+const GNSS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Plausible battery voltages in mV.
+const VBAT_RANGE: core::ops::RangeInclusive<u16> = 3000..=4400;
+
+/// Page written by the flash check.
+const SCRATCH: Region = Region {
+    start: flash::TELEMETRY.start,
+    pages: 1,
+};
+
+/// Outcome of a check.
+struct Check {
+    name: &'static str,
+    passed: bool,
+}
+
+/// Wait for an NMEA sentence from the GNSS module.
+async fn check_uarte(reader: &mut LineReader<'_, '_>) -> bool {
+    let mut line = [0; NMEA_MAX_LEN];
+    let result = with_timeout(GNSS_TIMEOUT, async {
+        loop {
+            if let Ok(len) = reader.read_line(&mut line).await
+                && line[..len].starts_with(b"$")
+            {
+                return len;
+            }
+        }
+    })
+    .await;
+    match result {
+        Ok(len) => {
+            info!(
+                "[selftest] GNSS: {}",
+                str::from_utf8(&line[..len]).unwrap_or("UTF8 error")
+            );
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Measure the battery voltage.
+async fn check_saadc(saadc: &mut Saadc<'_, 1>) -> bool {
+    saadc.calibrate().await;
+    let mut buf = [0i16; 1];
+    saadc.sample(&mut buf).await;
+    let millivolts = battery::sample_to_millivolts(buf[0], BatteryModel::default().divider);
+    info!("[selftest] VBAT {} mV", millivolts);
+    VBAT_RANGE.contains(&millivolts)
+}
+
+/// Erase, write and read back the scratch page, erasing it again afterwards.
+async fn check_flash(partition: &mut Partition<NvmcFlash>) -> bool {
+    let mut pattern = [0u8; 256];
+    pattern
+        .iter_mut()
+        .enumerate()
+        .for_each(|(i, b)| *b = i as u8 ^ 0xA5);
+    let mut readback = [0u8; 256];
+    let result = async {
+        partition.erase(0, PAGE_SIZE).await?;
+        partition.write(0, &pattern).await?;
+        partition.read(0, &mut readback).await?;
+        partition.erase(0, PAGE_SIZE).await
+    };
+    match result.await {
+        Ok(()) => readback == pattern,
+        Err(e) => {
+            warn!("[selftest] flash error: {:?}", defmt::Debug2Format(&e));
+            false
+        }
+    }
+}
+
+/// Show the results on the OLED.
+fn show<D: Display>(display: &mut D, checks: &[Check]) -> Result<(), DisplayError> {
+    display.clear_buffer();
+    let passed = checks.iter().filter(|c| c.passed).count();
+    let mut line: String<24> = String::new();
+    let _ = write!(line, "SELFTEST {}/{}", passed, checks.len());
+    assets::draw_text(display, &line, Point::zero())?;
+    for (i, check) in checks.iter().enumerate() {
+        line.clear();
+        let result = if check.passed { "PASS" } else { "FAIL" };
+        let _ = write!(line, "{:<8}{}", check.name, result);
+        assets::draw_text(display, &line, Point::new(0, (i as i32 + 1) * LINE_HEIGHT))?;
+    }
+    display.flush_buffer()
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    bind_interrupts!(struct Irqs {
+        UARTE0 => uarte::InterruptHandler<peripherals::UARTE0>;
+        TWISPI0 => twim::InterruptHandler<peripherals::TWISPI0>;
+        SAADC => saadc::InterruptHandler;
+    });
+
+    let mut board = Board::default();
+
+    let mut twim = Twim::new(
+        board.twispi0,
+        Irqs,
+        board.p0_06,
+        board.p0_05,
+        Default::default(),
+        &mut [],
+    );
+    // Log everything on the bus, helps to find bad solder joints.
+    if let Err(e) = i2c_probe::scan(&mut twim) {
+        warn!("[selftest] I2C scan failed: {:?}", e);
+    }
+    let display = ui::connect(twim);
+
+    let _gnss_wakeup = Output::new(board.p1_09, Level::High, OutputDrive::Standard);
+    let mut conf = Config::default();
+    conf.baudrate = Baudrate::BAUD9600;
+    let uarte = Uarte::new(board.uarte0, board.p0_26, board.p0_27, Irqs, conf);
+    let [idle_ch0, idle_ch1] = board.ppi.alloc_n().unwrap();
+    let (mut uarte_tx, mut uarte_rx) = uarte.split_with_idle(board.timer1, idle_ch0, idle_ch1);
+    let uarte_ok = uarte_tx.write(ENABLE_GNSS_MODULE).await.is_ok()
+        && check_uarte(&mut LineReader::new(&mut uarte_rx)).await;
+
+    let saadc_ok = match board.vbat {
+        Some(vbat) => {
+            let mut saadc = Saadc::new(board.saadc, Irqs, Default::default(), [vbat]);
+            Some(check_saadc(&mut saadc).await)
+        }
+        None => {
+            info!("[selftest] no battery input, SAADC skipped");
+            None
+        }
+    };
+
+    let radio_ok = match board.ble.init(board.timer0, board.rng) {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("[selftest] BLE init failed: {:?}", e);
+            false
+        }
+    };
+
+    // Nothing is scheduled on the radio, the NVMC is programmed directly.
+    let shared_flash = flash::init_nvmc(board.nvmc);
+    let flash_ok = check_flash(&mut SCRATCH.partition(shared_flash)).await;
+
+    let checks: heapless::Vec<Check, 5> = [
+        Some(Check {
+            name: "I2C",
+            passed: display.is_ok(),
+        }),
+        Some(Check {
+            name: "UARTE",
+            passed: uarte_ok,
+        }),
+        saadc_ok.map(|passed| Check {
+            name: "SAADC",
+            passed,
+        }),
+        Some(Check {
+            name: "RADIO",
+            passed: radio_ok,
+        }),
+        Some(Check {
+            name: "FLASH",
+            passed: flash_ok,
+        }),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    for check in &checks {
+        if check.passed {
+            info!("[selftest] {=str}: PASS", check.name);
+        } else {
+            error!("[selftest] {=str}: FAIL", check.name);
+        }
+    }
+    let passed = checks.iter().filter(|c| c.passed).count();
+    info!("[selftest] {}/{} checks passed", passed, checks.len());
+
+    match display {
+        Ok(mut display) => {
+            if let Err(e) = show(&mut display, &checks) {
+                warn!("[selftest] display error: {:?}", e);
+            }
+        }
+        Err(e) => warn!("[selftest] no display: {:?}", e),
+    }
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
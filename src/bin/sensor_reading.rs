@@ -1,222 +1,729 @@
 //! Firmware for the Wio Tracker L1.
 //! Provides current time read from the L76K GNSS module
 //! as a BLE GATT service.
+//! The GNSS and BLE tasks communicate through the event bus,
+//! the GNSS and BLE status are shown on the OLED.
+//! While connected, the raw NMEA stream is passed through the
+//! Nordic UART Service for GNSS tools on a phone, which can also
+//! inject assistance data for a faster first fix.
+//! A waypoint written by the central is navigated to on the OLED,
+//! using the heading of a magnetometer on the I2C bus if there is one.
+//! A barometer on the bus refines the altitude of the notified location.
+//! Tapping a phone on the NFC antenna offers to pair with the device.
+//! With the `buzzer` feature, geofence exits, a low battery and disconnects
+//! are signalled by the buzzer.
+//! The name, advertising interval and geofence radius can be edited on the
+//! OLED with the button.
+//! mcumgr tools can read the stats of the device and reboot it over SMP.
+//! With the `usb-hid` feature a short press types the coordinates into a
+//! computer on USB.
 //! Based on an example from the `trouble` crate
 //! (examples/apps/src/ble_bas_peripheral.rs).
 
 #![no_std]
 #![no_main]
 
-use bytemuck::{Pod, Zeroable, checked::try_cast};
-use chrono::{Datelike, NaiveDateTime, Timelike};
 use defmt::{info, warn};
 use embassy_executor::Spawner;
-use embassy_futures::{join::join, select::select};
+use embassy_futures::select::{Either, Either4, select, select4};
 use embassy_nrf::{
-    bind_interrupts, peripherals,
-    uarte::{self, Baudrate, Config, Parity, Uarte, UarteRxWithIdle, UarteTx},
+    bind_interrupts,
+    gpio::{Input, Level, Output, OutputDrive, Pull},
+    nfct::{self, NfcT},
+    peripherals,
+    saadc::{self, Saadc},
+    twim::{self, Twim},
+    uarte::{self, Baudrate, Config, Parity, Uarte, UarteRx, UarteRxWithIdle, UarteTx},
 };
-use nmea::ParseResult::{self, ZDA};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
 use nrf_mpsl::MultiprotocolServiceLayer;
 use nrf_sdc::SoftdeviceController;
-use nrf52_radio_rs::Board;
+#[cfg(feature = "buzzer")]
+use nrf52_radio_rs::bsp::buzzer::buzzer_task;
+#[cfg(feature = "gnss-pps")]
+use nrf52_radio_rs::gnss::pps::PpsCapture;
+#[cfg(feature = "usb-hid")]
+use nrf52_radio_rs::usb_hid;
+use nrf52_radio_rs::{
+    Board,
+    alert::{self, AlertLevel},
+    altimeter::run_altimeter,
+    ans,
+    battery::{self, BatteryModel, BatteryMonitor, ChargeState, ChargerMonitor},
+    bsp::{
+        approtect,
+        barometer::{self, AnyBarometer},
+        ble::{
+            AdvTiming, PeripheralResources, SoftdeviceError, accept_list,
+            adv::{self, Ad},
+            hooks::{self, BleEvents},
+            link, long,
+            notify::{self, NotifyError},
+            rssi,
+        },
+        brownout,
+        button::button_task,
+        flash::{self, Partition, SharedFlash},
+        i2c::{self, I2cDevice},
+        led::led_task,
+        link_quality::{DATA_CHANNELS, LinkQuality},
+        magnetometer::{self, AnyMagnetometer},
+        neopixel::neopixel_task,
+        power::{WakePin, ship_mode},
+        rng::Rng,
+        thermal,
+    },
+    build_info,
+    compass::{self, Compass, Level},
+    crash::{self, CrashDump},
+    crypto::{
+        Aes,
+        eid::{self, EDDYSTONE_UUID, EidGenerator},
+    },
+    events::{self, ButtonPress, ConnParams, Event, Phy},
+    factory_reset::{self, FactoryReset},
+    gatt::{
+        self, BatteryService, DeviceInfoService, GenericAttributeService, ImmediateAlertService,
+        LinkLossService, LocationService, NusService, SmpService, TxPowerService,
+        generic_attribute, location, nus, presentation, proximity,
+    },
+    gnss::{Gnss, GnssConfig, Policy, assist, passthrough, run_policy},
+    image_info, log,
+    maintenance::{self, Maintenance},
+    metrics,
+    nfc::{self, DeviceTag},
+    policy,
+    recovery::{self, Backoff, Recovery, recover},
+    replay, retained,
+    sensors::{Scheduled, Scheduler},
+    settings::{self, DEVICE_NAME_LEN, Settings, Store},
+    shell::{BUILTIN_COMMANDS, Shell},
+    smp,
+    supervisor::{Subsystem, supervise},
+    telemetry,
+    track::{self, Export, NAME_LEN, Point, SessionInfo, gpx, name_str},
+    ui::{
+        self, BlePage, Carousel, DebugPage, GnssPage, Menu, Notifications, QrPage, SettingsPage,
+        StatusPage, WaypointPage,
+    },
+    waypoint::{self, Waypoint},
+};
 use trouble_host::prelude::*;
 
-/// Max number of connections
-const CONNECTIONS_MAX: usize = 1;
+/// Duty cycling of the GNSS module.
+const GNSS_POLICY: Policy = Policy::AlwaysOn;
+
+/// Interval of the barometer measurements.
+const BAROMETER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval in which the scan response alternates between the EID and the
+/// TLM frame.
+const TLM_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Interval in which the metrics are logged.
+const METRICS_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Interval in which the MTU of a connection is polled.
+const LINK_STATE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Name in the advertisements and the NFC tag.
+const ADV_NAME: &str = "Trouble Example";
+
+/// Shortest part of the name kept when the advertising data runs out of
+/// space.
+const MIN_ADV_NAME_LEN: usize = 8;
+
+/// Random static address, least significant byte first.
+// Using a fixed "random" address can be useful for testing. In real scenarios, one would
+// use e.g. the MAC 6 byte array as the address (how to get that varies by the platform).
+const ADDRESS: [u8; 6] = [0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff];
+
+/// The ephemeral identifier rotates every 2^10 s (about 17 minutes).
+const EID_EXPONENT: u8 = 10;
 
-/// Max number of L2CAP channels.
-const L2CAP_CHANNELS_MAX: usize = 2; // Signal + att
+/// Calibrated TX power at 0 m in dBm (RSSI at 1 m + 41 dB).
+const EID_TX_POWER: i8 = -18;
 
-/// PCAS message (proprietary NMEA message) to configure
-/// the receiver to start searching for satellites (GPS and BeiDou).
-const ENABLE_GNSS_MODULE: &[u8; 14] = b"$PCAS04,3*1A\r\n";
+/// Interval in which telemetry samples are taken.
+const TELEMETRY_INTERVAL: Duration = Duration::from_secs(30);
 
-// GATT Server definition
+/// Interval in which the link quality is logged and notified.
+const LINK_QUALITY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Per-channel link quality, fed by the QoS reports of the controller.
+static LINK_QUALITY: LinkQuality = LinkQuality::new();
+
+/// Revision of the layout of [`Server`], bump it whenever a service or
+/// characteristic is added, removed or changed, so centrals that cached the
+/// handles discover them again, see [`generic_attribute`].
+const GATT_REVISION: u16 = 5;
+
+/// GATT server with the shared services of [`gatt`] and the ones of this firmware.
 #[gatt_server]
 struct Server {
+    generic_attribute_service: GenericAttributeService,
     battery_service: BatteryService,
-    gnss_service: GnssService,
-}
-
-/// Battery service
-#[gatt_service(uuid = service::BATTERY)]
-struct BatteryService {
-    /// Battery Level
-    #[descriptor(uuid = descriptors::VALID_RANGE, read, value = [0, 100])]
-    #[descriptor(uuid = descriptors::MEASUREMENT_DESCRIPTION, name = "hello", read, value = "Battery Level")]
-    #[characteristic(uuid = characteristic::BATTERY_LEVEL, read, notify, value = 10)]
-    level: u8,
-    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001100000", write, read, notify)]
-    status: bool,
-}
-
-/// Current Time characteristic of BLE
-/// see GATT specification supplement (2023-12-23)
-/// section 3.71
-#[derive(Clone, Copy, Pod, Zeroable)]
-#[repr(C)]
-struct CurrentTime {
-    year: u16,
-    month: u8,
-    day: u8,
-    hours: u8,
-    minutes: u8,
-    seconds: u8,
-    day_of_week: u8,
-    fractions_256: u8,
-    adj_reason: u8,
-}
-
-impl CurrentTime {
-    pub fn from(date_time: &NaiveDateTime) -> Self {
-        let date = date_time.date();
-        let time = date_time.time();
-        CurrentTime {
-            year: date.year() as u16,
-            month: date.month() as u8,
-            day: date.day() as u8,
-            hours: time.hour() as u8,
-            minutes: time.minute() as u8,
-            seconds: time.second() as u8,
-            day_of_week: 0u8,
-            fractions_256: 0u8,
-            adj_reason: 0u8,
-        }
-    }
+    gnss_service: LocationService,
+    diagnostics_service: DiagnosticsService,
+    telemetry_service: TelemetryService,
+    nus_service: NusService,
+    assist_service: AssistService,
+    navigation_service: NavigationService,
+    track_service: TrackService,
+    smp_service: SmpService,
+    device_info_service: DeviceInfoService,
+    immediate_alert_service: ImmediateAlertService,
+    link_loss_service: LinkLossService,
+    tx_power_service: TxPowerService,
+}
 
-    pub fn to_bytes(self) -> [u8; 10] {
-        try_cast(self).expect("CurrentTime should always have a representation of size 10 bytes")
-    }
+/// Value of the diagnostics command characteristic to enter ship mode.
+const SHIP_MODE_COMMAND: u8 = 0x53;
+
+/// Value of the diagnostics command characteristic to request the factory
+/// reset, written twice within [`factory_reset::CONFIRM_WINDOW`].
+const FACTORY_RESET_COMMAND: u8 = 0x46;
+
+/// Value of the diagnostics command characteristic to reboot into the
+/// bootloader, see [`image_info::reboot_to_bootloader`].
+const BOOTLOADER_COMMAND: u8 = 0x42;
+
+/// Diagnostics service
+#[gatt_service(uuid = "408813df-5dd4-1f87-ec11-cdb001200000")]
+struct DiagnosticsService {
+    /// Packet error rate per data channel in percent (0xFF: no traffic)
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Link quality per channel")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200001", read, notify)]
+    link_quality: [u8; DATA_CHANNELS],
+    /// Device command, see [`SHIP_MODE_COMMAND`], [`FACTORY_RESET_COMMAND`]
+    /// and [`BOOTLOADER_COMMAND`]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Command")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200002", write)]
+    command: u8,
+    /// Dump of the last crash, see [`crash::CrashDump`] (empty without
+    /// crash), any write clears it
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Crash dump")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200003", read, write)]
+    crash_dump: heapless::Vec<u8, { CrashDump::LEN }>,
+    /// Runtime log level per [`log::Module`] (0: trace to 5: off), levels
+    /// above 5 are left unchanged when written
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Log levels")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200004", read, write)]
+    log_levels: [u8; log::MODULES],
+    /// Counters since boot, see [`metrics::Snapshot`]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Metrics")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200005", read)]
+    metrics: [u8; 20],
+    /// All settings with version header and checksum, see
+    /// [`settings::export`], written to clone them from another device (in
+    /// a signed envelope if the firmware has a signing key), read and
+    /// written in parts beyond one ATT MTU
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Settings")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200006", read, write)]
+    settings: heapless::Vec<u8, { settings::SIGNED_BLOB_LEN }>,
+    /// Erase count per data page from the track log up (little endian u16,
+    /// saturating), see [`maintenance::erases`]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Flash wear")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200007", read)]
+    wear: [u8; 2 * flash::DATA_PAGES],
+    /// UTF-8 device name, see [`device_name`], written to rename the
+    /// device (empty: the firmware's name)
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Device name")]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_PRESENTATION_FORMAT, read, value = presentation::TEXT.to_bytes())]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200008", read, write)]
+    name: heapless::Vec<u8, DEVICE_NAME_LEN>,
+    /// RSSI of the connection in dBm, see [`rssi::monitor`]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Connection RSSI")]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_PRESENTATION_FORMAT, read, value = presentation::DBM.to_bytes())]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200009", read, notify)]
+    rssi: i8,
+}
+
+/// Telemetry service
+#[gatt_service(uuid = "408813df-5dd4-1f87-ec11-cdb001300000")]
+struct TelemetryService {
+    /// Queued telemetry samples, oldest first, see [`telemetry::Sample`]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Telemetry sample")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001300001", notify)]
+    sample: [u8; 20],
+}
+
+/// Length of the assistance data values, fits into an ATT MTU of 247.
+const ASSIST_CHUNK_LEN: usize = 244;
+
+/// AGNSS service, injects assistance data into the GNSS module
+#[gatt_service(uuid = "408813df-5dd4-1f87-ec11-cdb001400000")]
+struct AssistService {
+    /// CASIC binary messages for the GNSS module, split into chunks of any size
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Assistance data")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001400001", write)]
+    data: heapless::Vec<u8, ASSIST_CHUNK_LEN>,
+}
+
+/// Navigation service
+#[gatt_service(uuid = "408813df-5dd4-1f87-ec11-cdb001500000")]
+struct NavigationService {
+    /// Waypoint latitude and longitude in 1e-7 degrees (little endian i32),
+    /// an invalid latitude (e.g. `i32::MIN`) clears it
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Waypoint")]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_PRESENTATION_FORMAT, read, value = presentation::OPAQUE.to_bytes())]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001500001", read, write)]
+    waypoint: [u8; 8],
+    /// Latitude of the waypoint in 1e-7 degrees, `i32::MIN` without one
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Waypoint latitude")]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_PRESENTATION_FORMAT, read, value = presentation::DEGREES_E7.to_bytes())]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001500002", read)]
+    latitude: i32,
+    /// Longitude of the waypoint in 1e-7 degrees
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Waypoint longitude")]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_PRESENTATION_FORMAT, read, value = presentation::DEGREES_E7.to_bytes())]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001500003", read)]
+    longitude: i32,
+}
+
+/// Track log service
+#[gatt_service(uuid = "408813df-5dd4-1f87-ec11-cdb001700000")]
+struct TrackService {
+    /// A session name starts a session, an empty value stops it
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Track session control")]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_PRESENTATION_FORMAT, read, value = presentation::TEXT.to_bytes())]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001700001", write)]
+    control: heapless::Vec<u8, NAME_LEN>,
+    /// The newest sessions, see [`SessionInfo::to_bytes`], read in parts
+    /// beyond one ATT MTU
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Track sessions")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001700002", read)]
+    sessions: heapless::Vec<u8, { TRACK_LISTED * SessionInfo::LEN }>,
+    /// GPX of a session, notified in parts once its ID (little endian u32)
+    /// is written, an empty notification ends it
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Track GPX")]
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001700003", write, notify)]
+    gpx: heapless::Vec<u8, GPX_PART_LEN>,
 }
 
-/// GNSS service
-#[gatt_service(uuid = service::LOCATION_AND_NAVIGATION)]
-struct GnssService {
-    #[characteristic(uuid = characteristic::CURRENT_TIME, read, notify)]
-    time: [u8; 10],
+/// Sessions listed by the track log service, as many as fit into the
+/// longest attribute value.
+const TRACK_LISTED: usize = long::MAX_VALUE_LEN / SessionInfo::LEN;
+
+/// Longest GPX part, the header with a long session name.
+const GPX_PART_LEN: usize = 244;
+
+/// Sessions to export as GPX, written to the track log service.
+static GPX_EXPORT: Signal<CriticalSectionRawMutex, u32> = Signal::new();
+
+/// Name of the device, the configured one or [`ADV_NAME`].
+fn device_name(settings: &Settings) -> &str {
+    match settings.device_name_str() {
+        "" => ADV_NAME,
+        name => name,
+    }
 }
 
 /// Run the BLE stack.
+///
+/// The GAP name and appearance come from the settings. They are fixed for a
+/// GATT server, so after a change the server is set up again before
+/// advertising restarts.
+///
+/// Returns the last error once error recovery gave up.
 pub async fn run_ble(
-    mut peri: Peripheral<'_, SoftdeviceController<'_>, DefaultPacketPool>,
-    gnss_uarte_rx: &mut UarteRxWithIdle<'_>,
-    gnss_uarte_tx: &mut UarteTx<'_>,
-) {
+    stack: &Stack<'_, SoftdeviceController<'_>, DefaultPacketPool>,
+    peri: &mut Peripheral<'_, SoftdeviceController<'_>, DefaultPacketPool>,
+    rng: &mut Rng,
+) -> Result<(), BleHostError<SoftdeviceError>> {
     info!("[adv] start advertising and GATT service");
-    let server = Server::new_with_config(GapConfig::Peripheral(PeripheralConfig {
-        name: "TrouBLE",
-        appearance: &appearance::power_device::GENERIC_POWER_DEVICE,
-    }))
-    .unwrap();
+    // Without a provisioned identity key only TLM frames are advertised.
+    let eid = match keystore::handle(KeyId::EidIdentity).and_then(|key| key.aes()) {
+        Ok(identity) => Some(EidGenerator::new(identity, EID_EXPONENT).unwrap()),
+        Err(e) => {
+            warn!("[eid] no provisioned identity key ({:?}), no EIDs", e);
+            None
+        }
+    };
+    let mut interval_ms = policy::adv_interval_ms(settings::get().adv_interval_ms);
+    let mut params = AdvTiming::CONNECTABLE
+        .with_interval(Duration::from_millis(interval_ms.into()))
+        .randomize(rng);
 
-    let _ = async {
+    let mut backoff = Backoff::default();
+    // Until bonding is supported, a central that lost the link is reconnected
+    // to, one that disconnected deliberately isn't.
+    let mut last_central = None;
+    loop {
+        let gap = settings::get();
+        let appearance = match gap.appearance {
+            0 => appearance::power_device::GENERIC_POWER_DEVICE,
+            raw => BluetoothUuid16::new(raw),
+        };
+        let server = Server::new_with_config(GapConfig::Peripheral(PeripheralConfig {
+            name: device_name(&gap),
+            appearance: &appearance,
+        }))
+        .unwrap();
+        let _ = server.set(
+            &server.generic_attribute_service.database_hash,
+            &generic_attribute::database_hash(GATT_REVISION),
+        );
+        let revision = heapless::Vec::from_slice(build_info::text().as_bytes()).unwrap();
+        let _ = server.set(&server.device_info_service.firmware_revision, &revision);
+        let _ = server.set(
+            &server.link_loss_service.alert_level,
+            &(alert::link_loss_level() as u8),
+        );
         loop {
-            match advertise("Trouble Example", &mut peri, &server).await {
+            AdvTiming::CONNECTABLE.restart_delay(rng).await;
+            // Changes of the interval, also by the battery policy, the accept
+            // list and the name take effect when advertising restarts.
+            let settings = settings::get();
+            if device_name(&settings) != device_name(&gap) || settings.appearance != gap.appearance
+            {
+                info!("[adv] GAP name or appearance changed, setting up the GATT server again");
+                break;
+            }
+            if policy::adv_interval_ms(settings.adv_interval_ms) != interval_ms {
+                interval_ms = policy::adv_interval_ms(settings.adv_interval_ms);
+                params = AdvTiming::CONNECTABLE
+                    .with_interval(Duration::from_millis(interval_ms.into()))
+                    .randomize(rng);
+            }
+            match accept_list::apply(stack, settings.accepted()).await {
+                Ok(policy) => params.filter_policy = policy,
+                Err(e) => warn!("[adv] couldn't set the accept list: {:?}", e),
+            }
+            let name = device_name(&settings);
+            events::publish(Event::AdvertisingStarted);
+            match advertise(
+                name,
+                peri,
+                &server,
+                &params,
+                eid.as_ref(),
+                last_central.take(),
+            )
+            .await
+            {
                 Ok(conn) => {
+                    backoff.reset();
+                    let peer = Address {
+                        kind: conn.raw().peer_addr_kind(),
+                        addr: conn.raw().peer_address(),
+                    };
+                    let central = adv::is_identity(&peer).then_some(peer);
+                    thermal::connected(Some(conn.raw().handle().raw()));
+                    // A controller reset disables the reports, enabled per connection.
+                    if let Err(status) = LinkQuality::enable_reports() {
+                        warn!("[link] couldn't enable QoS reports: {:?}", status);
+                    }
+                    // Reconnecting ends a link loss alert.
+                    alert::raise(AlertLevel::None);
+                    events::publish(Event::CentralConnected {
+                        address: conn.raw().peer_address().into_inner(),
+                        mtu: conn.raw().att_mtu(),
+                    });
+                    // Longer link layer packets, the central exchanges the ATT MTU.
+                    if let Err(e) = link::request_data_length(stack, conn.raw()).await {
+                        warn!("[link] data length update failed: {:?}", e);
+                    }
                     // set up tasks when the connection is established to a central, so they don't run when no one is connected.
                     let gatt = gatt_events_task(&server, &conn);
-                    let gnss = gnss_notify_task(&server, &conn, gnss_uarte_rx, gnss_uarte_tx);
-                    let _ = select(gatt, gnss).await;
+                    let notify = notify_task(&server, &conn);
+                    let link_quality = link_quality_task(&server, &conn);
+                    let link_state = link_state_task(&conn);
+                    let rssi = rssi::monitor(stack, &conn, &server.diagnostics_service.rssi);
+                    let notifications = ans_task(stack, &conn);
+                    let drain = telemetry_drain_task(&server, &conn);
+                    let nmea = nmea_passthrough_task(&server, &conn);
+                    let gpx = gpx_export_task(&server, &conn);
+                    let changes = service_changed_task(&server, &conn);
+                    let ended = select4(
+                        select4(gatt, notify, link_quality, link_state),
+                        drain,
+                        nmea,
+                        select4(gpx, changes, rssi, notifications),
+                    )
+                    .await;
+                    if let Either4::First(Either4::First(Ok(reason))) = ended
+                        && alert::is_link_loss(reason)
+                    {
+                        last_central = central;
+                    }
+                    events::publish(Event::CentralDisconnected);
+                    thermal::connected(None);
+                    LINK_QUALITY.reset();
                 }
                 Err(e) => {
-                    let e = defmt::Debug2Format(&e);
-                    panic!("[adv] error: {:?}", e);
+                    if recover("adv", &e, &mut backoff).await == Recovery::GiveUp {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Advertising and GATT server as a supervised subsystem.
+struct Advertising<'a, 'd> {
+    stack: &'a Stack<'d, SoftdeviceController<'d>, DefaultPacketPool>,
+    peripheral: Peripheral<'a, SoftdeviceController<'d>, DefaultPacketPool>,
+    rng: Rng,
+}
+
+impl Subsystem for Advertising<'_, '_> {
+    type Error = BleHostError<SoftdeviceError>;
+
+    const NAME: &'static str = "adv";
+
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        run_ble(self.stack, &mut self.peripheral, &mut self.rng).await
+    }
+}
+
+/// The BLE runner, required to run forever alongside any other BLE tasks.
+struct BleRunner<'a, 'd> {
+    runner: Runner<'a, SoftdeviceController<'d>, DefaultPacketPool>,
+}
+
+impl Subsystem for BleRunner<'_, '_> {
+    type Error = BleHostError<SoftdeviceError>;
+
+    const NAME: &'static str = "ble_runner";
+
+    /// Errors are recovered from by restarting the runner, which resets and
+    /// re-initializes the controller, also when [`recover`] requests a reset
+    /// for an error of the advertising loop.
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        let mut backoff = Backoff::default();
+        loop {
+            let run = self.runner.run_with_handler(&LINK_QUALITY);
+            match select(run, recovery::reset_requested()).await {
+                Either::First(Err(e)) => {
+                    if recover(Self::NAME, &e, &mut backoff).await == Recovery::GiveUp {
+                        return Err(e);
+                    }
                 }
+                Either::First(Ok(())) => {}
+                Either::Second(()) => info!("[ble_runner] restarting to reset the controller"),
             }
         }
     }
-    .await;
 }
 
-/// This is a background task that is required to run forever alongside any other BLE tasks.
-async fn ble_background_task(mut runner: Runner<'_, SoftdeviceController<'_>, DefaultPacketPool>) {
+/// Periodically log the per-channel packet error rates and notify them
+/// over the diagnostics service.
+async fn link_quality_task<P: PacketPool>(server: &Server<'_>, conn: &GattConnection<'_, '_, P>) {
+    loop {
+        Timer::after(LINK_QUALITY_INTERVAL).await;
+        LINK_QUALITY.log_summary();
+        let _ = notify::send(
+            &server.diagnostics_service.link_quality,
+            conn,
+            &LINK_QUALITY.error_rates(),
+        )
+        .await;
+    }
+}
+
+/// Publish the ATT MTU of the connection when it changed.
+async fn link_state_task<P: PacketPool>(conn: &GattConnection<'_, '_, P>) {
+    let mut link = link::LinkParams::new(conn.raw());
     loop {
-        if let Err(e) = runner.run().await {
-            let e = defmt::Debug2Format(&e);
-            panic!("[ble_background_task] error: {:?}", e);
+        Timer::after(LINK_STATE_INTERVAL).await;
+        if link.update_mtu(conn.raw()) {
+            link.log();
+            events::publish(Event::MtuUpdated(link.att_mtu));
         }
     }
 }
-async fn send_nmea_msg<P: PacketPool>(
-    gnss_service: &GnssService,
+
+/// Notify the telemetry samples queued while disconnected and new ones, oldest first.
+async fn telemetry_drain_task<P: PacketPool>(
+    server: &Server<'_>,
     conn: &GattConnection<'_, '_, P>,
-    parse_result: ParseResult,
 ) {
-    match parse_result {
-        // TODO: Send messages for other NMEA sentences, e.g.:
-        // GGA(gga_data) => {
-        //     let gps_fix_str = defmt::Debug2Format(&gga_data);
-        //     info!("[send_nmea_msg] received GPS fix: {}", gps_fix_str)
-        //     ...
-        // }
-        ZDA(zda_data) => {
-            let maybe_utc_dt = zda_data.utc_date_time();
-            info!(
-                "[send_nmea_msg] current UTC time: {}",
-                defmt::Debug2Format(&maybe_utc_dt)
-            );
-            if let Some(dt) = maybe_utc_dt {
-                let _ = gnss_service
-                    .time
-                    .notify(conn, &CurrentTime::from(&dt).to_bytes())
-                    .await;
+    loop {
+        while let Some(sample) = telemetry::peek().await {
+            let bytes: [u8; 20] = bytemuck::cast(sample);
+            if notify::send(&server.telemetry_service.sample, conn, &bytes)
+                .await
+                .is_err()
+            {
+                break;
             }
+            telemetry::ack().await;
         }
-        _ => {
-            warn!("[send_nmea_msg] unexpected NMEA sentence received")
+        telemetry::wait_pushed().await;
+    }
+}
+
+/// Notify the raw NMEA sentences over the Nordic UART Service, split into chunks.
+async fn nmea_passthrough_task<P: PacketPool>(
+    server: &Server<'_>,
+    conn: &GattConnection<'_, '_, P>,
+) {
+    let tx = server.nus_service.tx;
+    loop {
+        let sentence = passthrough::next_sentence().await;
+        // As long as a notification of the current ATT MTU allows.
+        let len = link::LinkParams::new(conn.raw())
+            .max_notify_len()
+            .min(nus::CHUNK_LEN);
+        for chunk in sentence.chunks(len) {
+            // Chunks are at most nus::CHUNK_LEN long, so they always fit.
+            let value = heapless::Vec::from_slice(chunk).unwrap();
+            if notify::send(&tx, conn, &value).await.is_err() {
+                break;
+            }
         }
     }
 }
 
-async fn gnss_notify_task<P: PacketPool>(
+/// Indicate Service Changed if the GATT layout changed, then idle.
+async fn service_changed_task<P: PacketPool>(
     server: &Server<'_>,
     conn: &GattConnection<'_, '_, P>,
-    gnss_uarte_rx: &mut UarteRxWithIdle<'_>,
-    gnss_uarte_tx: &mut UarteTx<'_>,
 ) {
-    // TODO: Put GNSS module into standby until it is actually needed?
-    // (when BLE connection is established.)
+    let service = &server.generic_attribute_service;
+    generic_attribute::announce_changes(service, conn, GATT_REVISION).await;
+    core::future::pending().await
+}
 
-    // TODO: Necessary to send ENABLE_GNSS_MODULE?
-    // TODO: Implement retrying of GNSS enabling?
-    if let Err(err) = gnss_uarte_tx.write(ENABLE_GNSS_MODULE).await {
-        panic!("[main] couldn't enable GNSS module: {:?} error", err);
-    };
+/// Show the notifications of the phone, then idle.
+async fn ans_task<P: PacketPool>(
+    stack: &Stack<'_, SoftdeviceController<'_>, P>,
+    conn: &GattConnection<'_, '_, P>,
+) {
+    if let Err(e) = ans::run(stack, conn.raw()).await {
+        warn!("[ans] client error: {:?}", e);
+    }
+    core::future::pending().await
+}
 
-    // NMEA 0183 messages have a max length of 82 chars:
-    let mut nmea_buf = [0u8; 82];
-    let mut buf_idx: usize = 0;
+/// Notify the GPX of the sessions requested over the track log service.
+async fn gpx_export_task<P: PacketPool>(server: &Server<'_>, conn: &GattConnection<'_, '_, P>) {
+    let characteristic = server.track_service.gpx;
     loop {
-        match gnss_uarte_rx
-            .read_until_idle(&mut nmea_buf[buf_idx..buf_idx + 1])
-            .await
-        {
-            Ok(rx_len) => {
-                // TODO: Handle case when buf_idx out of bounds.
-                let nmea_sentence_terminated =
-                    buf_idx > 0 && nmea_buf[buf_idx - 1..buf_idx + 1] == *"\r\n".as_bytes();
-                if rx_len == 0 || nmea_sentence_terminated {
-                    let parsed = nmea::parse_bytes(&nmea_buf[..buf_idx + 1]);
-                    info!(
-                        "[gnss_notify_task] received NMEA sentence: {}",
-                        str::from_utf8(&nmea_buf[..buf_idx + 1]).unwrap_or("UTF8 error"),
-                    );
-                    buf_idx = 0;
-                    if let Ok(valid_nmea) = parsed {
-                        send_nmea_msg(&server.gnss_service, conn, valid_nmea).await;
-                    }
-                } else {
-                    buf_idx += 1;
-                    continue;
+        let id = GPX_EXPORT.wait().await;
+        match track::session(id) {
+            Some(session) => match export_gpx(&characteristic, conn, &session).await {
+                Ok(()) => info!("[track] exported session {} as GPX", id),
+                Err(e) => warn!("[track] GPX export of session {} failed: {:?}", id, e),
+            },
+            None => warn!("[track] no session {} to export", id),
+        }
+        // The empty notification ends it.
+        let _ = notify::send(&characteristic, conn, &heapless::Vec::new()).await;
+    }
+}
+
+/// Notify the GPX of `session`, one part per point.
+async fn export_gpx<P: PacketPool>(
+    characteristic: &Characteristic<heapless::Vec<u8, GPX_PART_LEN>>,
+    conn: &GattConnection<'_, '_, P>,
+    session: &SessionInfo,
+) -> Result<(), NotifyError> {
+    let mut part = heapless::String::<GPX_PART_LEN>::new();
+    // The header with a name of NAME_LEN escaped characters fits.
+    let _ = gpx::write_header(&mut part, name_str(&session.name));
+    notify_gpx_part(characteristic, conn, &part).await?;
+    let mut export = Export::new(session.id);
+    let mut points = heapless::Vec::<Point, 16>::new();
+    loop {
+        points.clear();
+        if let Err(e) = track::export(&mut export, &mut points).await {
+            warn!("[track] GPX export cut short: {:?}", e);
+            break;
+        }
+        for point in &points {
+            part.clear();
+            let _ = gpx::write_point(&mut part, point);
+            notify_gpx_part(characteristic, conn, &part).await?;
+        }
+        if !points.is_full() {
+            break;
+        }
+    }
+    part.clear();
+    let _ = gpx::write_footer(&mut part);
+    notify_gpx_part(characteristic, conn, &part).await
+}
+
+/// Notify a GPX part, split into chunks as long as the ATT MTU allows.
+async fn notify_gpx_part<P: PacketPool>(
+    characteristic: &Characteristic<heapless::Vec<u8, GPX_PART_LEN>>,
+    conn: &GattConnection<'_, '_, P>,
+    part: &str,
+) -> Result<(), NotifyError> {
+    let len = link::LinkParams::new(conn.raw())
+        .max_notify_len()
+        .min(GPX_PART_LEN);
+    for chunk in part.as_bytes().chunks(len) {
+        // Chunks are at most GPX_PART_LEN long, so they always fit.
+        let value = heapless::Vec::from_slice(chunk).unwrap();
+        notify::send(characteristic, conn, &value).await?;
+    }
+    Ok(())
+}
+
+/// Notify the current time, the battery level and power state whenever they are published.
+async fn notify_task<P: PacketPool>(server: &Server<'_>, conn: &GattConnection<'_, '_, P>) {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[notify_task] no event bus subscriber available");
+        return;
+    };
+    let mut charge = None;
+    let mut critical = false;
+    let mut barometric = None;
+    loop {
+        match events.next_message_pure().await {
+            Event::FixAcquired(fix) => {
+                if let Some(value) = location::location_and_speed(&fix, barometric) {
+                    let _ = notify::send(&server.gnss_service.location, conn, &value).await;
                 }
+                continue;
             }
-            Err(e) => {
-                warn!("[gnss_notify_task] error receiving bytes: {:?} error", e);
-                buf_idx = 0;
+            Event::AltitudeUpdated(altitude) => {
+                barometric = Some(altitude);
                 continue;
             }
-        };
+            Event::TimeUpdated(dt) => {
+                let time = location::CurrentTime::from(&dt).to_bytes();
+                let _ = notify::send(&server.gnss_service.time, conn, &time).await;
+            }
+            Event::BatteryUpdated { percent, .. } => {
+                critical = percent < battery::LOW_PERCENT;
+                let _ = notify::send(&server.battery_service.level, conn, &percent).await;
+            }
+            Event::ChargeStateChanged(state) => charge = Some(state),
+            _ => continue,
+        }
+        if let Some(state) = charge {
+            let power_state = ChargeState::power_state(state, critical);
+            let _ = notify::send(&server.battery_service.power_state, conn, &power_state).await;
+        }
+    }
+}
+
+/// Notify an SMP response in chunks of the ATT MTU, then reset the device
+/// if it was requested.
+async fn notify_smp<P: PacketPool>(
+    server: &Server<'_>,
+    conn: &GattConnection<'_, '_, P>,
+    response: &smp::Response,
+) {
+    let len = (conn.raw().att_mtu() as usize - 3).min(gatt::smp::CHUNK_LEN);
+    for chunk in response.as_bytes().chunks(len) {
+        // Chunks are at most gatt::smp::CHUNK_LEN long, so they always fit.
+        let value = heapless::Vec::from_slice(chunk).unwrap();
+        if let Err(e) = notify::send(&server.smp_service.smp, conn, &value).await {
+            warn!("[smp] couldn't notify response: {:?}", e);
+            return;
+        }
+    }
+    if response.reset {
+        // Give the controller time to send the response.
+        Timer::after_millis(250).await;
+        cortex_m::peripheral::SCB::sys_reset();
     }
 }
 
@@ -224,74 +731,647 @@ async fn gnss_notify_task<P: PacketPool>(
 ///
 /// This function will handle the GATT events and process them.
 /// This is how we interact with read and write requests.
+///
+/// Returns the HCI reason of the disconnection.
 async fn gatt_events_task<P: PacketPool>(
     server: &Server<'_>,
     conn: &GattConnection<'_, '_, P>,
-) -> Result<(), Error> {
+) -> Result<u8, Error> {
     let level = server.battery_service.level;
+    let command = server.diagnostics_service.command;
+    let crash_dump = server.diagnostics_service.crash_dump;
+    let log_levels = server.diagnostics_service.log_levels;
+    let metrics_value = server.diagnostics_service.metrics;
+    let settings_blob = server.diagnostics_service.settings;
+    let wear = server.diagnostics_service.wear;
+    let name_value = server.diagnostics_service.name;
+    let nus_rx = server.nus_service.rx;
+    let assist_data = server.assist_service.data;
+    let nav_waypoint = server.navigation_service.waypoint;
+    let nav_latitude = server.navigation_service.latitude;
+    let nav_longitude = server.navigation_service.longitude;
+    let track_control = server.track_service.control;
+    let track_sessions = server.track_service.sessions;
+    let track_gpx = server.track_service.gpx;
+    let smp_char = server.smp_service.smp;
+    let immediate_alert = server.immediate_alert_service.alert_level;
+    let link_loss = server.link_loss_service.alert_level;
+    let tx_power = server.tx_power_service.level;
+    let mut injector = assist::Injector::new();
+    let mut smp_server = smp::Server::new();
+    let mut prepared = long::PreparedWrites::new();
     let reason = loop {
         match conn.next().await {
             GattConnectionEvent::Disconnected { reason } => break reason,
+            GattConnectionEvent::ConnectionParamsUpdated {
+                conn_interval,
+                peripheral_latency,
+                supervision_timeout,
+            } => {
+                events::publish(Event::ConnParamsUpdated(ConnParams {
+                    interval_us: conn_interval.as_micros() as u32,
+                    latency: peripheral_latency,
+                    timeout_ms: supervision_timeout.as_millis() as u32,
+                }));
+            }
+            GattConnectionEvent::PhyUpdated { tx_phy, rx_phy } => {
+                events::publish(Event::PhyUpdated {
+                    tx: phy(tx_phy),
+                    rx: phy(rx_phy),
+                });
+            }
+            GattConnectionEvent::DataLengthUpdated {
+                max_tx_octets,
+                max_rx_octets,
+                ..
+            } => {
+                let mut link = link::LinkParams::new(conn.raw());
+                link.update_data_length(max_tx_octets, max_rx_octets);
+                link.log();
+                events::publish(Event::DataLengthUpdated {
+                    tx_octets: max_tx_octets,
+                    rx_octets: max_rx_octets,
+                });
+            }
             GattConnectionEvent::Gatt { event } => {
+                let mut smp_response = None;
+                let mut enter_bootloader = false;
+                let mut rejected = None;
+                // Values are refreshed for the first part of a long read only,
+                // so that the parts fit together.
+                let request = event.payload().incoming();
+                let first_part = long::read_offset(&request) == 0;
+                let executed = prepared.executed(&request);
                 match &event {
                     GattEvent::Read(event) => {
                         if event.handle() == level.handle {
                             let value = server.get(&level);
-                            info!("[gatt] Read Event to Level Characteristic: {:?}", value);
+                            log!(
+                                Ble,
+                                info,
+                                "[gatt] Read Event to Level Characteristic: {:?}",
+                                value
+                            );
+                        } else if event.handle() == crash_dump.handle {
+                            let value = crash::last()
+                                .map(|dump| heapless::Vec::from_slice(dump.as_bytes()).unwrap())
+                                .unwrap_or_default();
+                            let _ = server.set(&crash_dump, &value);
+                        } else if event.handle() == log_levels.handle {
+                            let _ = server.set(&log_levels, &log::levels());
+                        } else if event.handle() == metrics_value.handle {
+                            let snapshot = metrics::snapshot();
+                            let _ = server.set(&metrics_value, &bytemuck::cast(snapshot));
+                        } else if event.handle() == settings_blob.handle && first_part {
+                            let value = heapless::Vec::from_slice(&settings::export()).unwrap();
+                            let _ = server.set(&settings_blob, &value);
+                        } else if event.handle() == wear.handle {
+                            let mut value = [0; 2 * flash::DATA_PAGES];
+                            for (bytes, erases) in
+                                value.chunks_exact_mut(2).zip(maintenance::erases())
+                            {
+                                let erases = erases.min(u16::MAX as u32) as u16;
+                                bytes.copy_from_slice(&erases.to_le_bytes());
+                            }
+                            let _ = server.set(&wear, &value);
+                        } else if event.handle() == name_value.handle {
+                            let settings = settings::get();
+                            let value = device_name(&settings).as_bytes();
+                            let _ =
+                                server.set(&name_value, &heapless::Vec::from_slice(value).unwrap());
+                        } else if event.handle() == track_sessions.handle && first_part {
+                            let sessions = track::sessions();
+                            let newest = sessions.len().saturating_sub(TRACK_LISTED);
+                            let mut value = heapless::Vec::new();
+                            for session in &sessions[newest..] {
+                                let _ = value.extend_from_slice(&session.to_bytes());
+                            }
+                            let _ = server.set(&track_sessions, &value);
+                        } else if event.handle() == tx_power.handle {
+                            let _ = server.set(&tx_power, &thermal::tx_power());
+                        } else if [
+                            nav_waypoint.handle,
+                            nav_latitude.handle,
+                            nav_longitude.handle,
+                        ]
+                        .contains(&event.handle())
+                        {
+                            let [latitude, longitude] = match waypoint::get() {
+                                Some(target) => bytemuck::cast(target.to_bytes()),
+                                None => [i32::MIN, 0],
+                            };
+                            let _ =
+                                server.set(&nav_waypoint, &bytemuck::cast([latitude, longitude]));
+                            let _ = server.set(&nav_latitude, &latitude);
+                            let _ = server.set(&nav_longitude, &longitude);
                         }
                     }
                     GattEvent::Write(event) => {
+                        generic_attribute::written(
+                            &server.generic_attribute_service,
+                            event.handle(),
+                            event.data(),
+                        );
                         if event.handle() == level.handle {
-                            info!(
+                            log!(
+                                Ble,
+                                info,
                                 "[gatt] Write Event to Level Characteristic: {:?}",
                                 event.data()
                             );
+                        } else if event.handle() == command.handle
+                            && event.data() == [SHIP_MODE_COMMAND]
+                        {
+                            info!("[gatt] ship mode requested");
+                            events::publish(Event::ShipModeRequested);
+                        } else if event.handle() == command.handle
+                            && event.data() == [FACTORY_RESET_COMMAND]
+                        {
+                            info!("[gatt] factory reset requested");
+                            factory_reset::request();
+                        } else if event.handle() == command.handle
+                            && event.data() == [BOOTLOADER_COMMAND]
+                        {
+                            info!("[gatt] bootloader requested");
+                            enter_bootloader = true;
+                        } else if event.handle() == crash_dump.handle {
+                            info!("[gatt] crash dump cleared");
+                            crash::clear();
+                        } else if event.handle() == log_levels.handle {
+                            match event.data().try_into() {
+                                Ok(levels) => log::set_levels(levels),
+                                Err(_) => warn!("[gatt] invalid log levels length"),
+                            }
+                        } else if event.handle() == settings_blob.handle {
+                            match settings::import(event.data()) {
+                                Ok(()) => info!("[gatt] settings imported"),
+                                Err(e) => warn!("[gatt] settings not imported: {:?}", e),
+                            }
+                        } else if event.handle() == name_value.handle {
+                            let mut renamed = settings::get();
+                            match core::str::from_utf8(event.data()) {
+                                Ok(new_name) if renamed.set_device_name(new_name) => {
+                                    settings::update(|s| s.device_name = renamed.device_name);
+                                    info!("[gatt] renamed to '{}'", new_name);
+                                }
+                                Ok(_) => {
+                                    warn!("[gatt] device name too long");
+                                    rejected = Some(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH);
+                                }
+                                Err(_) => {
+                                    warn!("[gatt] invalid device name");
+                                    rejected = Some(AttErrorCode::VALUE_NOT_ALLOWED);
+                                }
+                            }
+                        } else if event.handle() == track_control.handle {
+                            let result = match core::str::from_utf8(event.data()) {
+                                Ok("") => track::stop().await.map(|_| ()),
+                                Ok(name) => track::start(name).await.map(|_| ()),
+                                Err(_) => {
+                                    warn!("[gatt] invalid session name");
+                                    Ok(())
+                                }
+                            };
+                            if let Err(e) = result {
+                                warn!("[gatt] track log: {:?}", e);
+                            }
+                        } else if event.handle() == track_gpx.handle {
+                            match event.data().try_into() {
+                                Ok(id) => GPX_EXPORT.signal(u32::from_le_bytes(id)),
+                                Err(_) => warn!("[gatt] invalid session ID length"),
+                            }
+                        } else if event.handle() == nus_rx.handle
+                            && !passthrough::send_to_module(event.data())
+                        {
+                            warn!("[gatt] dropped NUS write to the GNSS module");
+                        } else if event.handle() == assist_data.handle
+                            && !injector.write(event.data()).await
+                        {
+                            warn!("[gatt] assistance data incomplete");
+                        } else if event.handle() == nav_waypoint.handle {
+                            match event.data().try_into() {
+                                Ok(bytes) => waypoint::set(Waypoint::from_bytes(bytes)),
+                                Err(_) => warn!("[gatt] invalid waypoint length"),
+                            }
+                        } else if event.handle() == smp_char.handle {
+                            smp_response = smp_server.write(event.data()).await;
+                        } else if event.handle() == immediate_alert.handle {
+                            match proximity::alert_level(event.data()) {
+                                Some(level) => alert::raise(level),
+                                None => warn!("[gatt] invalid alert level"),
+                            }
+                        } else if event.handle() == link_loss.handle {
+                            match proximity::alert_level(event.data()) {
+                                Some(level) => alert::set_link_loss_level(level),
+                                None => warn!("[gatt] invalid alert level"),
+                            }
                         }
                     }
                     _ => {}
                 };
+                let reply = match rejected {
+                    Some(code) => event.reject(code),
+                    None => event.accept(),
+                };
                 // This step is also performed at drop(), but writing it explicitly is necessary
                 // in order to ensure reply is sent.
-                match event.accept() {
+                match reply {
                     Ok(reply) => reply.send().await,
                     Err(e) => warn!("[gatt] error sending response: {:?}", e),
                 };
+                if let Some((_, value)) =
+                    executed.filter(|(handle, _)| *handle == settings_blob.handle)
+                {
+                    match settings::import(&value) {
+                        Ok(()) => info!("[gatt] settings imported"),
+                        Err(e) => warn!("[gatt] settings not imported: {:?}", e),
+                    }
+                }
+                if let Some(response) = smp_response {
+                    notify_smp(server, conn, &response).await;
+                }
+                if enter_bootloader {
+                    // Give the controller time to send the response.
+                    Timer::after_millis(250).await;
+                    image_info::reboot_to_bootloader();
+                }
             }
             _ => {} // ignore other Gatt Connection Events
         }
     };
     info!("[gatt] disconnected: {:?}", reason);
-    Ok(())
+    alert::disconnected(reason.into_inner());
+    Ok(reason.into_inner())
+}
+
+/// Map the PHY reported by the controller to the event bus representation.
+fn phy(kind: PhyKind) -> Phy {
+    match kind {
+        PhyKind::Le1M => Phy::Le1M,
+        PhyKind::Le2M => Phy::Le2M,
+        _ => Phy::LeCoded,
+    }
 }
 
 /// Create an advertiser to use to connect to a BLE Central, and wait for it to connect.
+///
+/// The scan response carries the current Eddystone-EID frame, alternating
+/// with an Eddystone-TLM frame of the [`metrics`] every [`TLM_INTERVAL`].
+/// Advertising is restarted for every change. The TLM frame isn't
+/// encrypted, its counters make the device linkable across EID rotations.
+/// Without an `eid` generator only the TLM frame is sent.
+///
+/// A central in `directed_to` is advertised to first, see
+/// [`adv::advertise_directed`].
 async fn advertise<'values, 'server, C: Controller>(
-    name: &'values str,
+    name: &str,
     peripheral: &mut Peripheral<'values, C, DefaultPacketPool>,
     server: &'server Server<'values>,
+    params: &AdvertisementParameters,
+    eid: Option<&EidGenerator<Aes>>,
+    directed_to: Option<Address>,
 ) -> Result<GattConnection<'values, 'server, DefaultPacketPool>, BleHostError<C::Error>> {
-    let mut advertiser_data = [0; 31];
-    let len = AdStructure::encode_slice(
+    if let Some(peer) = directed_to {
+        log!(Ble, debug, "[adv] directed advertising");
+        if let Some(conn) = adv::advertise_directed(peripheral, params, peer).await? {
+            let conn = conn.with_attribute_server(server)?;
+            info!("[adv] central reconnected");
+            return Ok(conn);
+        }
+    }
+    let advertiser_data = adv::payload(&[
+        Ad::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+        Ad::ServiceUuids16(&[[0x0f, 0x18]]),
+        Ad::Name {
+            name,
+            min_len: MIN_ADV_NAME_LEN,
+        },
+    ])
+    .payload;
+    let mut tlm = false;
+    loop {
+        let secs = eid::time_counter();
+        let (tlm_frame, eid_frame);
+        let frame: &[u8] = match eid {
+            Some(eid) if !tlm => match eid.frame(secs, EID_TX_POWER) {
+                Ok(frame) => {
+                    eid_frame = frame;
+                    &eid_frame
+                }
+                Err(e) => {
+                    warn!("[adv] couldn't compute EID: {:?}", e);
+                    &[]
+                }
+            },
+            _ => {
+                tlm_frame = metrics::snapshot().tlm_frame();
+                &tlm_frame
+            }
+        };
+        let scan_data = if frame.is_empty() {
+            Default::default()
+        } else {
+            adv::payload(&[Ad::ServiceData16 {
+                uuid: EDDYSTONE_UUID,
+                data: frame,
+            }])
+            .payload
+        };
+        let advertiser = peripheral
+            .advertise(
+                params,
+                Advertisement::ConnectableScannableUndirected {
+                    adv_data: &advertiser_data,
+                    scan_data: &scan_data,
+                },
+            )
+            .await?;
+        metrics::ADVERTISEMENTS.increment();
+        log!(Ble, debug, "[adv] advertising");
+        let restart = eid.map_or(TLM_INTERVAL, |eid| {
+            eid.until_rotation(secs).min(TLM_INTERVAL)
+        });
+        match select(advertiser.accept(), Timer::after(restart)).await {
+            Either::First(conn) => {
+                let conn = conn?.with_attribute_server(server)?;
+                info!("[adv] connection established");
+                return Ok(conn);
+            }
+            Either::Second(_) => tlm = !tlm,
+        }
+    }
+}
+
+/// Read the GNSS module and publish its fixes and time on the event bus.
+#[embassy_executor::task]
+async fn gnss_task(mut rx: UarteRxWithIdle<'static>, mut tx: UarteTx<'static>) {
+    supervise(&mut Gnss::new(&mut rx, &mut tx, GnssConfig::default())).await
+}
+
+/// Capture the pulse per second of the GNSS module.
+#[cfg(feature = "gnss-pps")]
+#[embassy_executor::task]
+async fn pps_task(mut capture: PpsCapture<'static>) {
+    supervise(&mut capture).await
+}
+
+/// Duty cycle the GNSS module via its wakeup pin.
+#[embassy_executor::task]
+async fn gnss_policy_task(mut wakeup: Output<'static>) {
+    run_policy(GNSS_POLICY, &mut wakeup).await
+}
+
+/// Show the menu of the status, GNSS, BLE (with the QR code of the address),
+/// settings and debug pages on the OLED, switched by a short button press,
+/// and the notifications of the phone over them.
+#[embassy_executor::task]
+async fn ui_task(i2c: I2cDevice) {
+    let mut display = match ui::connect(i2c) {
+        Ok(display) => display,
+        Err(e) => {
+            warn!("[ui] no display: {:?}", e);
+            return;
+        }
+    };
+    let mut page = Notifications::new(Menu::new(Carousel::new(
+        Carousel::new(
+            StatusPage::new(),
+            Carousel::new(GnssPage::new(), WaypointPage::new()),
+        ),
+        Carousel::new(
+            Carousel::new(BlePage::new(), QrPage::address(&ADDRESS)),
+            Carousel::new(SettingsPage::new(), DebugPage::new()),
+        ),
+    )));
+    if let Err(e) = ui::run_page(&mut display, &mut page).await {
+        warn!("[ui] display error: {:?}", e);
+    }
+}
+
+/// Sample the sensors on the I2C bus.
+#[embassy_executor::task]
+async fn sensors_task(barometer: AnyBarometer<I2cDevice>) {
+    supervise(&mut Scheduler::new((Scheduled::new(
+        barometer,
+        BAROMETER_INTERVAL,
+    ),)))
+    .await
+}
+
+/// Publish the fused altitude.
+#[embassy_executor::task]
+async fn altimeter_task() {
+    run_altimeter().await
+}
+
+/// Publish the compass heading, the board is assumed to be held level.
+#[embassy_executor::task]
+async fn compass_task(magnetometer: AnyMagnetometer<I2cDevice>) {
+    supervise(&mut Compass::new(magnetometer, Level)).await
+}
+
+/// Estimate the battery state of charge.
+#[embassy_executor::task]
+async fn battery_task(mut saadc: Saadc<'static, 1>) {
+    supervise(&mut BatteryMonitor::new(
+        &mut saadc,
+        BatteryModel::default(),
+    ))
+    .await
+}
+
+/// Detect the charger state.
+#[embassy_executor::task]
+async fn charger_task(mut status: Input<'static>) {
+    supervise(&mut ChargerMonitor::new(&mut status)).await
+}
+
+/// Type the coordinates as a USB keyboard on short presses.
+#[cfg(feature = "usb-hid")]
+#[embassy_executor::task]
+async fn usb_hid_task(usbd: embassy_nrf::Peri<'static, peripherals::USBD>) {
+    usb_hid::run(usbd).await
+}
+
+/// Enter ship mode on request or when the button is held, request the
+/// factory reset with long presses, start and stop a track log session with
+/// double presses. Long and double presses are left to the UI while it
+/// [captures](ui::input_captured) them.
+#[embassy_executor::task]
+async fn ship_mode_task(wake: WakePin) {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[ship_mode_task] no event bus subscriber available");
+        return;
+    };
+    loop {
+        match events.next_message_pure().await {
+            Event::ButtonPressed(ButtonPress::Hold) | Event::ShipModeRequested => {
+                ship_mode(wake).await
+            }
+            Event::ButtonPressed(ButtonPress::Long | ButtonPress::Double)
+                if ui::input_captured() => {}
+            Event::ButtonPressed(ButtonPress::Long) => {
+                factory_reset::request();
+            }
+            Event::ButtonPressed(ButtonPress::Double) => {
+                if let Err(e) = track::toggle().await {
+                    warn!("[track] couldn't toggle the session: {:?}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Keep the state for a warm boot in retained RAM.
+#[embassy_executor::task]
+async fn retained_task() {
+    retained::keep().await
+}
+
+/// Close the track log and block flash writes when the supply fails.
+#[embassy_executor::task]
+async fn brownout_task() {
+    brownout::watch().await
+}
+
+/// Reduce the TX power while the die is hot.
+#[embassy_executor::task]
+async fn thermal_task() {
+    thermal::run().await
+}
+
+/// Adapt the subsystems to the battery state of charge.
+#[embassy_executor::task]
+async fn policy_task() {
+    policy::run().await
+}
+
+/// Play the alerts of the Proximity profile.
+#[embassy_executor::task]
+async fn alert_task() {
+    alert::run().await
+}
+
+/// Publish geofence exits.
+#[embassy_executor::task]
+async fn geofence_task() {
+    waypoint::watch_geofence().await
+}
+
+/// Record the fixes into the track log.
+#[embassy_executor::task]
+async fn track_task() {
+    track::collect().await
+}
+
+/// Confirm a test image after the self-test.
+#[embassy_executor::task]
+async fn image_confirm_task() {
+    image_info::confirm().await
+}
+
+/// Erase all data once the factory reset was confirmed.
+#[embassy_executor::task]
+async fn factory_reset_task(flash: &'static SharedFlash) {
+    supervise(&mut FactoryReset::new(flash)).await
+}
+
+/// Persist the erase counters and erase reclaimed pages while power allows.
+#[embassy_executor::task]
+async fn maintenance_task(mut maintenance: Maintenance) {
+    supervise(&mut maintenance).await
+}
+
+/// Persist changed settings.
+#[embassy_executor::task]
+async fn settings_task(mut store: Store<Partition>) {
+    supervise(&mut store).await
+}
+
+/// Erase the crash dump once it was cleared.
+#[embassy_executor::task]
+async fn crash_task(mut store: crash::Store<Partition>) {
+    supervise(&mut store).await
+}
+
+/// Persist the sequence number of the last signed update.
+#[embassy_executor::task]
+async fn replay_task(mut store: replay::Store<Partition>) {
+    supervise(&mut store).await
+}
+
+/// What the firmware does when a central connects or disconnects.
+struct ConnectionHooks;
+
+impl BleEvents for ConnectionHooks {
+    /// Account the radio in the battery estimate and pass the NMEA stream
+    /// through while connected.
+    fn connected(&mut self, _address: [u8; 6], _mtu: u16) {
+        metrics::CONNECTIONS.increment();
+        battery::set_radio_active(true);
+        passthrough::set_enabled(true);
+    }
+
+    fn disconnected(&mut self) {
+        passthrough::set_enabled(false);
+        battery::set_radio_active(false);
+    }
+
+    fn params_updated(&mut self, params: ConnParams) {
+        log!(Ble, info, "[ble] connection parameters {:?}", params);
+    }
+}
+
+/// React to connection events, see [`ConnectionHooks`].
+#[embassy_executor::task]
+async fn ble_events_task() {
+    hooks::dispatch(&mut ConnectionHooks).await
+}
+
+/// Keep the EID time counter in sync with the GNSS time.
+#[embassy_executor::task]
+async fn eid_clock_task() {
+    eid::track_time().await
+}
+
+/// Take telemetry samples.
+#[embassy_executor::task]
+async fn telemetry_task() {
+    telemetry::collect(TELEMETRY_INTERVAL).await
+}
+
+/// Log the metrics periodically.
+#[embassy_executor::task]
+async fn metrics_task() {
+    metrics::log_summary(METRICS_INTERVAL).await
+}
+
+/// Debug console on UARTE1.
+#[embassy_executor::task]
+async fn shell_task(mut rx: UarteRx<'static>, mut tx: UarteTx<'static>) {
+    supervise(&mut Shell::new(
+        &mut rx,
+        &mut tx,
         &[
-            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
-            AdStructure::ServiceUuids16(&[[0x0f, 0x18]]),
-            AdStructure::CompleteLocalName(name.as_bytes()),
+            BUILTIN_COMMANDS,
+            settings::COMMANDS,
+            compass::COMMANDS,
+            factory_reset::COMMANDS,
+            track::COMMANDS,
+            maintenance::COMMANDS,
+            keystore::COMMANDS,
+            #[cfg(feature = "usb-hid")]
+            usb_hid::COMMANDS,
         ],
-        &mut advertiser_data[..],
-    )?;
-    let advertiser = peripheral
-        .advertise(
-            &Default::default(),
-            Advertisement::ConnectableScannableUndirected {
-                adv_data: &advertiser_data[..len],
-                scan_data: &[],
-            },
-        )
-        .await?;
-    info!("[adv] advertising");
-    let conn = advertiser.accept().await?.with_attribute_server(server)?;
-    info!("[adv] connection established");
-    Ok(conn)
+    ))
+    .await
+}
+
+/// Present the device on an NFC tag for pairing.
+#[embassy_executor::task]
+async fn nfc_task(mut nfct: NfcT<'static>, tag: DeviceTag) {
+    nfc::run_tag(&mut nfct, &tag).await
 }
 
 /// Run the multiprotocol service layer task.
@@ -307,10 +1387,40 @@ async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
 async fn main(spawner: Spawner) -> ! {
     bind_interrupts!(struct Irqs {
         UARTE0 => uarte::InterruptHandler<peripherals::UARTE0>;
+        UARTE1 => uarte::InterruptHandler<peripherals::UARTE1>;
+        TWISPI0 => twim::InterruptHandler<peripherals::TWISPI0>;
+        SAADC => saadc::InterruptHandler;
+        NFCT => nfct::InterruptHandler;
     });
 
-    let board = Board::default();
+    build_info::log();
+    retained::init();
+    #[cfg(feature = "cryptocell")]
+    nrf52_radio_rs::crypto::hw::init();
+    let mut board = Board::default();
+    approtect::log();
+    brownout::init();
     let (sdc, mpsl) = board.ble.init(board.timer0, board.rng).unwrap();
+    // Runs the flash operations as well.
+    spawner.must_spawn(mpsl_task(mpsl));
+    let shared_flash = flash::init(mpsl, board.nvmc);
+    image_info::init(shared_flash);
+    // Loads the erase counters, before the other flash users erase.
+    let maintenance = Maintenance::new(shared_flash).await;
+    let mut store = Store::new(flash::SETTINGS.partition(shared_flash));
+    if store.load().await.is_err() {
+        warn!("[main] couldn't read settings");
+    }
+    telemetry::init(flash::TELEMETRY.partition(shared_flash)).await;
+    track::init(flash::TRACK_LOG.partition(shared_flash)).await;
+    let mut crash_store = crash::Store::new(flash::CRASH_DUMP.partition(shared_flash));
+    if crash_store.load().await.is_err() {
+        warn!("[main] couldn't read crash dump");
+    }
+    let mut replay_store = replay::Store::new(flash::REPLAY.partition(shared_flash));
+    if replay_store.load().await.is_err() {
+        warn!("[main] couldn't read the last signed update");
+    }
 
     let conf = {
         let mut c = Config::default();
@@ -319,26 +1429,128 @@ async fn main(spawner: Spawner) -> ! {
         c
     };
     let uarte = Uarte::new(board.uarte0, board.p0_26, board.p0_27, Irqs, conf);
-    let (mut uarte_tx, mut uarte_rx) =
-        uarte.split_with_idle(board.timer1, board.ppi_ch0, board.ppi_ch1);
+    let [idle_ch0, idle_ch1] = board.ppi.alloc_n().unwrap();
+    let (uarte_tx, uarte_rx) = uarte.split_with_idle(board.timer1, idle_ch0, idle_ch1);
 
-    spawner.must_spawn(mpsl_task(mpsl));
+    spawner.must_spawn(thermal_task());
+    spawner.must_spawn(settings_task(store));
+    spawner.must_spawn(crash_task(crash_store));
+    spawner.must_spawn(replay_task(replay_store));
+    spawner.must_spawn(factory_reset_task(shared_flash));
+    spawner.must_spawn(maintenance_task(maintenance));
+    spawner.must_spawn(telemetry_task());
+    spawner.must_spawn(track_task());
+    spawner.must_spawn(brownout_task());
+    spawner.must_spawn(retained_task());
+    spawner.must_spawn(geofence_task());
+    spawner.must_spawn(alert_task());
+    spawner.must_spawn(policy_task());
+    spawner.must_spawn(image_confirm_task());
+    spawner.must_spawn(metrics_task());
+    spawner.must_spawn(eid_clock_task());
+    spawner.must_spawn(ble_events_task());
+    if let Some(pin) = board.led {
+        spawner.must_spawn(led_task(pin));
+    }
+    if let Some(pin) = board.neopixel {
+        spawner.must_spawn(neopixel_task(board.pwm.pwm0, pin));
+    }
+    #[cfg(feature = "buzzer")]
+    spawner.must_spawn(buzzer_task(board.pwm.pwm1, board.p1.p1_00.into()));
+    match board.user_switch {
+        Some(pin) => {
+            spawner.must_spawn(ship_mode_task(WakePin::new(&pin)));
+            spawner.must_spawn(button_task(pin));
+        }
+        None => info!("[button] no user switch"),
+    }
+    match board.vbat {
+        Some(vbat) => spawner.must_spawn(battery_task(Saadc::new(
+            board.saadc,
+            Irqs,
+            Default::default(),
+            [vbat],
+        ))),
+        None => info!("[battery] no battery voltage input"),
+    }
+    match board.charger_status {
+        Some(pin) => spawner.must_spawn(charger_task(Input::new(pin, Pull::Up))),
+        None => info!("[charger] no charger status pin"),
+    }
+    #[cfg(feature = "usb-hid")]
+    spawner.must_spawn(usb_hid_task(board.usbd));
+    spawner.must_spawn(gnss_task(uarte_rx, uarte_tx));
+    spawner.must_spawn(gnss_policy_task(Output::new(
+        board.p1_09,
+        Level::High,
+        OutputDrive::Standard,
+    )));
+    #[cfg(feature = "gnss-pps")]
+    spawner.must_spawn(pps_task(PpsCapture::new(
+        board.timer2,
+        board.gpiote.ch0,
+        board.p1.p1_01,
+        board.ppi.alloc().unwrap(),
+    )));
 
-    // Using a fixed "random" address can be useful for testing. In real scenarios, one would
-    // use e.g. the MAC 6 byte array as the address (how to get that varies by the platform).
-    let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff]);
+    match board.console {
+        Some((rx, tx)) => {
+            let console = Uarte::new(board.uarte1, rx, tx, Irqs, {
+                let mut c = Config::default();
+                c.baudrate = Baudrate::BAUD115200;
+                c
+            });
+            let (console_tx, console_rx) = console.split();
+            spawner.must_spawn(shell_task(console_rx, console_tx));
+        }
+        None => info!("[shell] no console UART"),
+    }
+
+    let twim = Twim::new(
+        board.twispi0,
+        Irqs,
+        board.p0_06,
+        board.p0_05,
+        Default::default(),
+        &mut [],
+    );
+    let i2c_bus = i2c::init(twim);
+    spawner.must_spawn(ui_task(i2c::device(i2c_bus)));
+    match magnetometer::detect(i2c::device(i2c_bus)) {
+        Ok(magnetometer) => spawner.must_spawn(compass_task(magnetometer)),
+        Err(e) => info!("[compass] no magnetometer: {:?}", e),
+    }
+    match barometer::detect(i2c::device(i2c_bus)).await {
+        Ok(barometer) => {
+            spawner.must_spawn(sensors_task(barometer));
+            spawner.must_spawn(altimeter_task());
+        }
+        Err(e) => info!("[altimeter] no barometer: {:?}", e),
+    }
+    // No OOB values until the host pairs with them, see `nfc`.
+    match nfc::device_tag(ADDRESS, true, device_name(&settings::get()), None) {
+        Ok(tag) => spawner.must_spawn(nfc_task(
+            NfcT::new(board.nfct, Irqs, &nfc::config(ADDRESS)),
+            tag,
+        )),
+        Err(e) => warn!("[nfc] couldn't encode the tag: {:?}", e),
+    }
+
+    let address: Address = Address::random(ADDRESS);
     info!("Our address = {:?}", address);
 
-    let mut resources: HostResources<DefaultPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX> =
-        HostResources::new();
-    let stack = trouble_host::new(sdc, &mut resources).set_random_address(address);
+    let mut resources = PeripheralResources::new();
+    let stack = resources.stack(sdc, address);
     let Host {
         peripheral, runner, ..
     } = stack.build();
-    let _ = join(
-        ble_background_task(runner),
-        run_ble(peripheral, &mut uarte_rx, &mut uarte_tx),
-    )
-    .await;
-    panic!("[main] ble_background_task and run_ble terminated");
+    let mut runner = BleRunner { runner };
+    let mut advertising = Advertising {
+        stack: &stack,
+        peripheral,
+        rng: board.rng,
+    };
+    match select(supervise(&mut runner), supervise(&mut advertising)).await {
+        Either::First(never) | Either::Second(never) => never,
+    }
 }
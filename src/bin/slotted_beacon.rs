@@ -0,0 +1,149 @@
+//! Time-synchronized beacon, one unit of a group sharing an epoch.
+//!
+//! Advertises in the slot of the unit every epoch, see
+//! [`epoch`](nrf52_radio_rs::epoch). The unit is provisioned with the
+//! `slot_unit` [setting](nrf52_radio_rs::settings). With the `gnss-pps`
+//! feature the time comes from the GNSS pulse per second, else, or without
+//! a fix, from the beacons of the other units, which are scanned for all
+//! the time. A group needs at least one unit with the pulse per second.
+//! `ble_gateway` reports the units it missed in an epoch.
+
+#![no_std]
+#![no_main]
+
+use bt_hci::param::LeAdvReportsIter;
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_nrf::{
+    bind_interrupts, peripherals,
+    uarte::{self, Baudrate, Config, Parity, Uarte, UarteRxWithIdle, UarteTx},
+};
+use embassy_time::Instant;
+use nrf_mpsl::MultiprotocolServiceLayer;
+#[cfg(feature = "gnss-pps")]
+use nrf52_radio_rs::gnss::pps::PpsCapture;
+use nrf52_radio_rs::{
+    Board,
+    bsp::{ble::BeaconResources, flash},
+    epoch::{self, Schedule},
+    gnss::{Gnss, GnssConfig},
+    settings::{self, Store},
+    supervisor::supervise,
+};
+use trouble_host::prelude::*;
+
+/// Company ID of the slot beacons, as in `ble_gateway`.
+const COMPANY_ID: u16 = 0xFFFF;
+
+/// Slots of the group: 1 s epochs, 10 units of 100 ms, 30 ms guard.
+const SCHEDULE: Schedule = Schedule::new(1_000_000, 10, 30_000);
+
+/// Learns the time from the slot beacons of the other units.
+struct SyncHandler;
+
+impl EventHandler for SyncHandler {
+    fn on_adv_reports(&self, reports: LeAdvReportsIter) {
+        let now = Instant::now();
+        for report in reports.flatten() {
+            epoch::observe(&SCHEDULE, COMPANY_ID, report.data, now);
+        }
+    }
+}
+
+/// Run the multiprotocol service layer task.
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) {
+    mpsl.run().await
+}
+
+/// Read the GNSS module, its ZDA sentences name the pulses.
+#[embassy_executor::task]
+async fn gnss_task(mut rx: UarteRxWithIdle<'static>, mut tx: UarteTx<'static>) {
+    supervise(&mut Gnss::new(&mut rx, &mut tx, GnssConfig::default())).await
+}
+
+/// Capture the pulse per second of the GNSS module.
+#[cfg(feature = "gnss-pps")]
+#[embassy_executor::task]
+async fn pps_task(mut capture: PpsCapture<'static>) {
+    supervise(&mut capture).await
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    bind_interrupts!(struct Irqs {
+        UARTE0 => uarte::InterruptHandler<peripherals::UARTE0>;
+    });
+
+    let mut board = Board::default();
+    let (sdc, mpsl) = board
+        .ble
+        .peripheral_links(0)
+        .init(board.timer0, board.rng)
+        .unwrap();
+    spawner.must_spawn(mpsl_task(mpsl));
+    let shared_flash = flash::init(mpsl, board.nvmc);
+    if Store::new(flash::SETTINGS.partition(shared_flash))
+        .load()
+        .await
+        .is_err()
+    {
+        warn!("[main] couldn't read settings");
+    }
+    let Some(unit) = u8::try_from(settings::get().slot_unit)
+        .ok()
+        .filter(|unit| *unit < SCHEDULE.units)
+    else {
+        panic!("[main] slot unit outside of the schedule");
+    };
+    info!("[main] slot unit {}", unit);
+
+    let conf = {
+        let mut c = Config::default();
+        c.baudrate = Baudrate::BAUD9600;
+        c.parity = Parity::EXCLUDED;
+        c
+    };
+    let uarte = Uarte::new(board.uarte0, board.p0_26, board.p0_27, Irqs, conf);
+    let [idle_ch0, idle_ch1] = board.ppi.alloc_n().unwrap();
+    let (uarte_tx, uarte_rx) = uarte.split_with_idle(board.timer1, idle_ch0, idle_ch1);
+    spawner.must_spawn(gnss_task(uarte_rx, uarte_tx));
+    #[cfg(feature = "gnss-pps")]
+    spawner.must_spawn(pps_task(PpsCapture::new(
+        board.timer2,
+        board.gpiote.ch0,
+        board.p1.p1_01,
+        board.ppi.alloc().unwrap(),
+    )));
+
+    let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe5, unit]);
+    info!("Our address = {:?}", address);
+
+    let mut resources = BeaconResources::new();
+    let stack = resources.stack(sdc, address);
+    let Host {
+        central,
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+    let mut scanner = Scanner::new(central);
+    let _ = join(runner.run_with_handler(&SyncHandler), async {
+        let _session = match scanner.scan(&ScanConfig::default()).await {
+            Ok(session) => Some(session),
+            Err(e) => {
+                warn!(
+                    "[epoch] couldn't start scanning: {:?}",
+                    defmt::Debug2Format(&e)
+                );
+                None
+            }
+        };
+        if let Err(e) = epoch::advertise_slots(&mut peripheral, &SCHEDULE, unit, COMPANY_ID).await {
+            warn!("[epoch] advertising failed: {:?}", defmt::Debug2Format(&e));
+        }
+    })
+    .await;
+    panic!("[main] BLE runner terminated");
+}
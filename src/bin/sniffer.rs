@@ -0,0 +1,103 @@
+//! BLE advertising channel sniffer streaming a PCAP capture over UART.
+//!
+//! Captures every packet on advertising [`CHANNEL`] (see
+//! [`sniffer`](nrf52_radio_rs::sniffer)) and writes a PCAP stream to UARTE1
+//! (1 Mbaud, TX of the console pins of the `Board`), e.g. for
+//! `wireshark -k -i <(stty -F /dev/ttyUSB0 1000000 raw && cat /dev/ttyUSB0)`.
+//! Records are dropped while the UART is behind, the count is logged.
+
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_nrf::{
+    bind_interrupts, peripherals,
+    uarte::{self, Baudrate, Config, UarteTx},
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Duration, Timer};
+use heapless::Vec;
+use nrf52_radio_rs::{
+    Board,
+    bsp::config::BoardConfig,
+    sniffer::{MAX_RECORD_LEN, Sniffer, global_header},
+};
+
+/// Advertising channel to listen on.
+const CHANNEL: u8 = 37;
+
+/// Records waiting for the UART.
+const RECORD_QUEUE: usize = 16;
+
+/// Interval in which dropped records are reported.
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+static RECORDS: Channel<CriticalSectionRawMutex, Vec<u8, MAX_RECORD_LEN>, RECORD_QUEUE> =
+    Channel::new();
+
+/// Records dropped because the queue was full.
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Write the PCAP header and the records to the UART.
+#[embassy_executor::task]
+async fn uart_task(mut tx: UarteTx<'static>) {
+    if let Err(e) = tx.write(&global_header()).await {
+        warn!("[sniffer] UART write failed: {:?}", e);
+    }
+    loop {
+        let record = RECORDS.receive().await;
+        if let Err(e) = tx.write(&record).await {
+            warn!("[sniffer] UART write failed: {:?}", e);
+        }
+    }
+}
+
+/// Log the records dropped.
+#[embassy_executor::task]
+async fn report_task() {
+    loop {
+        Timer::after(REPORT_INTERVAL).await;
+        let dropped = DROPPED.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            warn!("[sniffer] {} records dropped", dropped);
+        }
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) -> ! {
+    bind_interrupts!(struct Irqs {
+        UARTE1 => uarte::InterruptHandler<peripherals::UARTE1>;
+    });
+
+    // The RADIO needs the crystal, there is no MPSL to request it.
+    let mut board = Board::new(BoardConfig {
+        hf_xtal: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let Some((_, console_tx)) = board.console else {
+        panic!("[sniffer] no console UART on this board");
+    };
+    let tx = UarteTx::new(board.uarte1, Irqs, console_tx, {
+        let mut c = Config::default();
+        c.baudrate = Baudrate::BAUD1M;
+        c
+    });
+    spawner.must_spawn(uart_task(tx));
+    spawner.must_spawn(report_task());
+
+    let mut sniffer = Sniffer::new(board.timer1, board.ppi.alloc().unwrap(), CHANNEL);
+    info!("[sniffer] capturing on channel {}", CHANNEL);
+    let mut buf = [0; MAX_RECORD_LEN];
+    loop {
+        let len = sniffer.receive().await.encode(&mut buf);
+        let record = Vec::from_slice(&buf[..len]).unwrap();
+        if RECORDS.try_send(record).is_err() {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
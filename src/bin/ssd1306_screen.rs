@@ -1,6 +1,8 @@
 #![no_std]
 #![no_main]
 
+use defmt::warn;
+use display_interface::DisplayError;
 use embassy_executor::Spawner;
 use embassy_nrf::{
     bind_interrupts, peripherals,
@@ -12,8 +14,26 @@ use embedded_graphics::{
     prelude::*,
     text::{Baseline, Text},
 };
-use nrf52_radio_rs::Board;
-use ssd1306_i2c::{Builder, prelude::*};
+use nrf52_radio_rs::{
+    Board,
+    ui::{self, Display},
+};
+
+fn draw<D: Display>(display: &mut D) -> Result<(), DisplayError> {
+    display.clear_buffer();
+
+    let text_style = MonoTextStyleBuilder::new()
+        .font(&FONT_6X10)
+        .text_color(BinaryColor::On)
+        .build();
+
+    Text::with_baseline("Hey sexy!", Point::zero(), text_style, Baseline::Top).draw(display)?;
+
+    Text::with_baseline("Hello Rust!", Point::new(0, 16), text_style, Baseline::Top)
+        .draw(display)?;
+
+    display.flush_buffer()
+}
 
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) {
@@ -32,30 +52,13 @@ async fn main(_spawner: Spawner) {
         &mut [],
     );
 
-    let mut display: GraphicsMode<_> = Builder::new()
-        .with_size(DisplaySize::Display128x64)
-        .with_i2c_addr(0x3d)
-        .with_rotation(DisplayRotation::Rotate0)
-        .connect_i2c(twim)
-        .into();
-
-    display.init().unwrap();
-    display.flush().unwrap();
-    display.clear();
-
-    let text_style = MonoTextStyleBuilder::new()
-        .font(&FONT_6X10)
-        .text_color(BinaryColor::On)
-        .build();
-
-    Text::with_baseline("Hey sexy!", Point::zero(), text_style, Baseline::Top)
-        .draw(&mut display)
-        .unwrap();
-
-    Text::with_baseline("Hello Rust!", Point::new(0, 16), text_style, Baseline::Top)
-        .draw(&mut display)
-        .unwrap();
-
-    display.flush().unwrap();
+    match ui::connect(twim) {
+        Ok(mut display) => {
+            if let Err(e) = draw(&mut display) {
+                warn!("display error: {:?}", e);
+            }
+        }
+        Err(e) => warn!("no display: {:?}", e),
+    }
     loop {}
 }
@@ -0,0 +1,79 @@
+//! Access port protection (APPROTECT) against readout by a debugger.
+//!
+//! Until it is enabled, a debugger reads the whole flash, including the
+//! [keystore](crate::keystore). [`enable`] writes the UICR register, which
+//! locks the access port from the next reset on. Once locked, the only way
+//! back is an ERASEALL through the CTRL-AP, which erases the flash and the
+//! UICR with it: the firmware, the settings and the keys.
+//!
+//! Newer chip revisions lock the access port at every reset unless the UICR
+//! says otherwise, `embassy_nrf::init` opens it again per [`debug`].
+
+use defmt::{info, warn};
+use embassy_nrf::config::Debug;
+
+use super::flash;
+
+/// Address of the APPROTECT register in the UICR.
+const UICR_APPROTECT: u32 = 0x1000_1208;
+
+/// Value of the register locking the access port.
+const ENABLED: u32 = 0x00;
+
+/// Value of the register keeping the access port open on newer revisions.
+const HW_DISABLED: u32 = 0x5A;
+
+/// Protection configured in the UICR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Protection {
+    /// The access port is locked.
+    Enabled,
+    /// The access port stays open.
+    Disabled,
+    /// Erased register: open on older chip revisions, on newer ones up to
+    /// the firmware.
+    Unconfigured,
+}
+
+/// Protection configured in the UICR.
+pub fn protection() -> Protection {
+    // SAFETY: The UICR is always mapped and reading it has no side effects.
+    match unsafe { core::ptr::read_volatile(UICR_APPROTECT as *const u32) } {
+        ENABLED => Protection::Enabled,
+        HW_DISABLED => Protection::Disabled,
+        0xFFFF_FFFF => Protection::Unconfigured,
+        // Any value but the erased one enables the protection.
+        _ => Protection::Enabled,
+    }
+}
+
+/// Debug configuration of `embassy_nrf::init`, which must not try to open a
+/// locked access port again.
+pub fn debug() -> Debug {
+    match protection() {
+        Protection::Enabled => Debug::Disallowed,
+        Protection::Disabled | Protection::Unconfigured => Debug::Allowed,
+    }
+}
+
+/// Log the protection at boot.
+pub fn log() {
+    match protection() {
+        Protection::Enabled => info!("[approtect] access port locked"),
+        _ => warn!("[approtect] access port open, the flash and keys are readable"),
+    }
+}
+
+/// Lock the access port from the next reset on. Irreversible short of
+/// erasing the whole chip.
+pub fn enable() {
+    if protection() == Protection::Enabled {
+        return;
+    }
+    warn!("[approtect] ============================================");
+    warn!("[approtect] LOCKING THE ACCESS PORT AT THE NEXT RESET");
+    warn!("[approtect] a debugger can't flash or read the device anymore,");
+    warn!("[approtect] only an ERASEALL recovers it, erasing firmware and keys");
+    warn!("[approtect] ============================================");
+    flash::write_uicr(UICR_APPROTECT, &ENABLED.to_le_bytes());
+}
@@ -0,0 +1,178 @@
+//! I2C barometers: Bosch BMP280 and Infineon DPS310.
+//!
+//! Both answer at 0x76 or 0x77 (selected by a pin), [`detect`] tells them
+//! apart by their identification register and configures continuous
+//! measurements of about 1 Hz (BMP280) or 2 Hz (DPS310). The compensation
+//! formulas are in [`nrf52_radio_core::baro`].
+
+use defmt::info;
+use embassy_time::{Duration, Timer};
+use embedded_hal::i2c::I2c;
+pub use nrf52_radio_core::baro::Pressure;
+use nrf52_radio_core::baro::{Bmp280Calibration, Dps310Coefficients};
+
+use super::i2c::{read_register, read_registers, write_register};
+use super::i2c_probe::{self, ProbeError};
+
+/// Possible I2C addresses of both sensors.
+const ADDRESSES: [u8; 2] = [0x76, 0x77];
+
+const BMP280_ID_REG: u8 = 0xD0;
+const BMP280_ID: u8 = 0x58;
+const BMP280_CALIBRATION: u8 = 0x88;
+const BMP280_CTRL_MEAS: u8 = 0xF4;
+const BMP280_CONFIG: u8 = 0xF5;
+const BMP280_DATA: u8 = 0xF7;
+
+const DPS310_ID_REG: u8 = 0x0D;
+const DPS310_ID: u8 = 0x10;
+const DPS310_DATA: u8 = 0x00;
+const DPS310_PRS_CFG: u8 = 0x06;
+const DPS310_TMP_CFG: u8 = 0x07;
+const DPS310_MEAS_CFG: u8 = 0x08;
+const DPS310_COEF: u8 = 0x10;
+const DPS310_COEF_SRCE: u8 = 0x28;
+
+/// Coefficients are ready in `MEAS_CFG`.
+const DPS310_COEF_RDY: u8 = 0x80;
+
+/// Time the DPS310 may need to load its coefficients after power on.
+const DPS310_STARTUP: Duration = Duration::from_millis(40);
+
+/// Errors of the barometer drivers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum BarometerError {
+    /// No supported barometer answered.
+    NotFound,
+    /// The sensor isn't ready or its calibration is invalid.
+    NotReady,
+    /// A transfer failed.
+    Bus,
+}
+
+impl From<ProbeError> for BarometerError {
+    fn from(e: ProbeError) -> Self {
+        match e {
+            ProbeError::NotFound => Self::NotFound,
+            ProbeError::Bus => Self::Bus,
+        }
+    }
+}
+
+fn bus<E>(_: E) -> BarometerError {
+    BarometerError::Bus
+}
+
+/// A barometric pressure sensor.
+pub trait Barometer {
+    /// The latest measurement.
+    fn read(&mut self) -> Result<Pressure, BarometerError>;
+}
+
+/// Bosch BMP280.
+pub struct Bmp280<I> {
+    i2c: I,
+    address: u8,
+    calibration: Bmp280Calibration,
+}
+
+impl<I: I2c> Bmp280<I> {
+    /// Read the calibration and start normal mode: 16x pressure and 2x
+    /// temperature oversampling, IIR filter 4, 1 s standby.
+    pub fn new(mut i2c: I, address: u8) -> Result<Self, BarometerError> {
+        let mut bytes = [0; 24];
+        read_registers(&mut i2c, address, BMP280_CALIBRATION, &mut bytes).map_err(bus)?;
+        write_register(&mut i2c, address, BMP280_CONFIG, 0xA8).map_err(bus)?;
+        write_register(&mut i2c, address, BMP280_CTRL_MEAS, 0x57).map_err(bus)?;
+        Ok(Self {
+            i2c,
+            address,
+            calibration: Bmp280Calibration::from_bytes(&bytes),
+        })
+    }
+}
+
+impl<I: I2c> Barometer for Bmp280<I> {
+    fn read(&mut self) -> Result<Pressure, BarometerError> {
+        let mut d = [0u8; 6];
+        read_registers(&mut self.i2c, self.address, BMP280_DATA, &mut d).map_err(bus)?;
+        let raw = |d: &[u8]| (d[0] as i32) << 12 | (d[1] as i32) << 4 | (d[2] as i32) >> 4;
+        self.calibration
+            .compensate(raw(&d[3..]), raw(&d[..3]))
+            .ok_or(BarometerError::NotReady)
+    }
+}
+
+/// Infineon DPS310.
+pub struct Dps310<I> {
+    i2c: I,
+    address: u8,
+    coefficients: Dps310Coefficients,
+}
+
+impl<I: I2c> Dps310<I> {
+    /// Read the coefficients and start continuous measurements of pressure
+    /// and temperature at 2 Hz without oversampling.
+    pub async fn new(mut i2c: I, address: u8) -> Result<Self, BarometerError> {
+        Timer::after(DPS310_STARTUP).await;
+        let status = read_register(&mut i2c, address, DPS310_MEAS_CFG).map_err(bus)?;
+        if status & DPS310_COEF_RDY == 0 {
+            return Err(BarometerError::NotReady);
+        }
+        let mut bytes = [0; 18];
+        read_registers(&mut i2c, address, DPS310_COEF, &mut bytes).map_err(bus)?;
+        // Measure the temperature with the sensor the coefficients are for.
+        let source = read_register(&mut i2c, address, DPS310_COEF_SRCE).map_err(bus)? & 0x80;
+        write_register(&mut i2c, address, DPS310_PRS_CFG, 0x10).map_err(bus)?;
+        write_register(&mut i2c, address, DPS310_TMP_CFG, source | 0x10).map_err(bus)?;
+        write_register(&mut i2c, address, DPS310_MEAS_CFG, 0x07).map_err(bus)?;
+        Ok(Self {
+            i2c,
+            address,
+            coefficients: Dps310Coefficients::from_bytes(&bytes),
+        })
+    }
+}
+
+impl<I: I2c> Barometer for Dps310<I> {
+    fn read(&mut self) -> Result<Pressure, BarometerError> {
+        let mut d = [0u8; 6];
+        read_registers(&mut self.i2c, self.address, DPS310_DATA, &mut d).map_err(bus)?;
+        // 24 bit two's complement, most significant byte first.
+        let raw = |d: &[u8]| i32::from_be_bytes([d[0], d[1], d[2], 0]) >> 8;
+        Ok(self.coefficients.compensate(raw(&d[3..]), raw(&d[..3])))
+    }
+}
+
+/// One of the supported barometers.
+pub enum AnyBarometer<I> {
+    Bmp280(Bmp280<I>),
+    Dps310(Dps310<I>),
+}
+
+impl<I: I2c> Barometer for AnyBarometer<I> {
+    fn read(&mut self) -> Result<Pressure, BarometerError> {
+        match self {
+            Self::Bmp280(b) => b.read(),
+            Self::Dps310(b) => b.read(),
+        }
+    }
+}
+
+/// Find and configure a supported barometer on the bus.
+pub async fn detect<I: I2c>(mut i2c: I) -> Result<AnyBarometer<I>, BarometerError> {
+    for address in ADDRESSES {
+        if !i2c_probe::probe(&mut i2c, address)? {
+            continue;
+        }
+        if read_register(&mut i2c, address, BMP280_ID_REG).map_err(bus)? == BMP280_ID {
+            info!("[baro] BMP280 at {:#04x}", address);
+            return Bmp280::new(i2c, address).map(AnyBarometer::Bmp280);
+        }
+        if read_register(&mut i2c, address, DPS310_ID_REG).map_err(bus)? == DPS310_ID {
+            info!("[baro] DPS310 at {:#04x}", address);
+            return Dps310::new(i2c, address).await.map(AnyBarometer::Dps310);
+        }
+    }
+    Err(BarometerError::NotFound)
+}
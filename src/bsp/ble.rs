@@ -1,35 +1,98 @@
 //! nRF Softdevice Controller Configuration for Bluetooth Peripheral
 //! Copied and adapted from the `microbit-bsp` crate.
-//! Used with `trouble-host` crate.
+//! Used with `trouble-host` crate, whose host resources are
+//! configured through [`Resources`].
+//! The supported roles are selected with the `ble-*` cargo features.
 
-use embassy_nrf::mode::Async;
 use embassy_nrf::peripherals;
-use embassy_nrf::{Peri, bind_interrupts, rng};
+use embassy_nrf::{Peri, bind_interrupts};
 use nrf_sdc::{self as sdc, mpsl};
 pub use nrf_sdc::{
     Error as SoftdeviceError, SoftdeviceController, mpsl::MultiprotocolServiceLayer,
 };
 use static_cell::StaticCell;
+use trouble_host::prelude::{Address, Controller, DefaultPacketPool, HostResources, Stack};
+
+use super::ppi::BleChannels;
+use super::rng::Rng;
+
+pub mod accept_list;
+pub mod adv;
+pub mod hooks;
+pub mod link;
+pub mod long;
+pub mod notify;
+pub mod rotation;
+pub mod rssi;
+pub mod timeslot;
+
+pub use adv::AdvTiming;
+pub use rotation::AdvRotation;
 
 /// Default memory allocation for softdevice controller in bytes.
-const SDC_MEMORY_SIZE: usize = 1432; // bytes
+/// Large enough for one peripheral and one central link.
+pub const SDC_MEMORY_SIZE: usize = 4096; // bytes
+
+/// Roles and features of the Softdevice Controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct SdcConfig {
+    /// Number of simultaneous links as peripheral, 0 for non-connectable advertising only.
+    pub peripheral_links: u8,
+    /// Number of simultaneous links as central, 0 disables scanning.
+    pub central_links: u8,
+    /// Extended advertising (long advertising data, advertising on coded PHY).
+    pub extended_adv: bool,
+    /// LE Coded PHY (long range).
+    pub coded_phy: bool,
+    /// Periodic advertising.
+    pub periodic_adv: bool,
+}
+
+impl Default for SdcConfig {
+    /// One peripheral link if the `ble-peripheral` feature is enabled.
+    fn default() -> Self {
+        Self {
+            peripheral_links: cfg!(feature = "ble-peripheral") as u8,
+            central_links: 0,
+            extended_adv: false,
+            coded_phy: false,
+            periodic_adv: false,
+        }
+    }
+}
+
+/// Errors initializing the BLE stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum BleInitError {
+    /// The MPSL or the Softdevice Controller rejected the configuration.
+    Softdevice(SoftdeviceError),
+    /// The buffer passed to [`BleControllerBuilder::init_with_memory`] is too small.
+    MemoryTooSmall { required: usize, available: usize },
+}
+
+impl From<SoftdeviceError> for BleInitError {
+    fn from(e: SoftdeviceError) -> Self {
+        Self::Softdevice(e)
+    }
+}
 
 /// Softdevice Bluetooth Controller Builder.
 pub struct BleControllerBuilder<'d> {
     /// Softdevice Controller peripherals
     sdc_peripherals: sdc::Peripherals<'d>,
-    /// Softdevice Controller memory
-    sdc_mem: sdc::Mem<SDC_MEMORY_SIZE>,
     // Required peripherals for the Multiprotocol Service Layer (MPSL)
     rtc0: Peri<'static, peripherals::RTC0>,
     temp: Peri<'static, peripherals::TEMP>,
     ppi_ch19: Peri<'static, peripherals::PPI_CH19>,
     ppi_ch30: Peri<'static, peripherals::PPI_CH30>,
     ppi_ch31: Peri<'static, peripherals::PPI_CH31>,
+    /// Low frequency clock configuration, see [`BoardConfig`](super::config::BoardConfig)
+    lf_clock: mpsl::raw::mpsl_clock_lfclk_cfg_t,
+    /// Roles and features of the Softdevice Controller
+    config: SdcConfig,
 }
 
 bind_interrupts!(struct Irqs {
-    RNG => rng::InterruptHandler<peripherals::RNG>;
     EGU0_SWI0 => nrf_sdc::mpsl::LowPrioInterruptHandler;
     CLOCK_POWER => nrf_sdc::mpsl::ClockInterruptHandler;
     RADIO => nrf_sdc::mpsl::HighPrioInterruptHandler;
@@ -41,51 +104,77 @@ impl<'d> BleControllerBuilder<'d>
 where
     'd: 'static,
 {
-    /// Low frequency clock configuration
-    const LF_CLOCK_CONFIG: mpsl::raw::mpsl_clock_lfclk_cfg_t = mpsl::raw::mpsl_clock_lfclk_cfg_t {
-        source: mpsl::raw::MPSL_CLOCK_LF_SRC_RC as u8,
-        rc_ctiv: mpsl::raw::MPSL_RECOMMENDED_RC_CTIV as u8,
-        rc_temp_ctiv: mpsl::raw::MPSL_RECOMMENDED_RC_TEMP_CTIV as u8,
-        accuracy_ppm: mpsl::raw::MPSL_DEFAULT_CLOCK_ACCURACY_PPM as u16,
-        skip_wait_lfclk_started: mpsl::raw::MPSL_DEFAULT_SKIP_WAIT_LFCLK_STARTED != 0,
-    };
     /// Create a new instance of the Softdevice Controller BLE builder
     pub(crate) fn new(
         rtc0: Peri<'static, peripherals::RTC0>,
         temp: Peri<'static, peripherals::TEMP>,
-        ppi_ch17: Peri<'static, peripherals::PPI_CH17>,
-        ppi_ch18: Peri<'static, peripherals::PPI_CH18>,
-        ppi_ch19: Peri<'static, peripherals::PPI_CH19>,
-        ppi_ch20: Peri<'static, peripherals::PPI_CH20>,
-        ppi_ch21: Peri<'static, peripherals::PPI_CH21>,
-        ppi_ch22: Peri<'static, peripherals::PPI_CH22>,
-        ppi_ch23: Peri<'static, peripherals::PPI_CH23>,
-        ppi_ch24: Peri<'static, peripherals::PPI_CH24>,
-        ppi_ch25: Peri<'static, peripherals::PPI_CH25>,
-        ppi_ch26: Peri<'static, peripherals::PPI_CH26>,
-        ppi_ch27: Peri<'static, peripherals::PPI_CH27>,
-        ppi_ch28: Peri<'static, peripherals::PPI_CH28>,
-        ppi_ch29: Peri<'static, peripherals::PPI_CH29>,
-        ppi_ch30: Peri<'static, peripherals::PPI_CH30>,
-        ppi_ch31: Peri<'static, peripherals::PPI_CH31>,
+        ppi: BleChannels,
+        lf_clock: mpsl::raw::mpsl_clock_lfclk_cfg_t,
     ) -> Self {
         // Softdevice Controller peripherals
         let sdc_peripherals = sdc::Peripherals::new(
-            ppi_ch17, ppi_ch18, ppi_ch20, ppi_ch21, ppi_ch22, ppi_ch23, ppi_ch24, ppi_ch25,
-            ppi_ch26, ppi_ch27, ppi_ch28, ppi_ch29,
+            ppi.ppi_ch17,
+            ppi.ppi_ch18,
+            ppi.ppi_ch20,
+            ppi.ppi_ch21,
+            ppi.ppi_ch22,
+            ppi.ppi_ch23,
+            ppi.ppi_ch24,
+            ppi.ppi_ch25,
+            ppi.ppi_ch26,
+            ppi.ppi_ch27,
+            ppi.ppi_ch28,
+            ppi.ppi_ch29,
         );
 
-        let sdc_mem = sdc::Mem::<SDC_MEMORY_SIZE>::new();
         Self {
             sdc_peripherals,
-            sdc_mem,
             rtc0,
             temp,
-            ppi_ch19,
-            ppi_ch30,
-            ppi_ch31,
+            ppi_ch19: ppi.ppi_ch19,
+            ppi_ch30: ppi.ppi_ch30,
+            ppi_ch31: ppi.ppi_ch31,
+            lf_clock,
+            config: SdcConfig::default(),
         }
     }
+
+    /// Additionally support the central role, i.e. scanning and
+    /// initiating a connection to a peripheral.
+    pub fn with_central(self) -> Self {
+        self.central_links(1)
+    }
+
+    /// Number of simultaneous links as peripheral, 0 for a broadcaster.
+    pub fn peripheral_links(mut self, links: u8) -> Self {
+        self.config.peripheral_links = links;
+        self
+    }
+
+    /// Number of simultaneous links as central.
+    pub fn central_links(mut self, links: u8) -> Self {
+        self.config.central_links = links;
+        self
+    }
+
+    /// Support extended advertising.
+    pub fn with_extended_advertising(mut self) -> Self {
+        self.config.extended_adv = true;
+        self
+    }
+
+    /// Support the LE Coded PHY.
+    pub fn with_coded_phy(mut self) -> Self {
+        self.config.coded_phy = true;
+        self
+    }
+
+    /// Support periodic advertising, implies extended advertising.
+    pub fn with_periodic_advertising(mut self) -> Self {
+        self.config.extended_adv = true;
+        self.config.periodic_adv = true;
+        self
+    }
     // TODO: Adapt example:
     /// Initialize the nRF `Softdevice Controller` (sdc) and the `Multiprotocol Service Layer` (mpsl).
     ///
@@ -125,14 +214,52 @@ where
     pub fn init(
         self,
         timer0: Peri<'static, peripherals::TIMER0>,
-        rng: Peri<'static, peripherals::RNG>,
+        rng: Rng,
     ) -> Result<
         (
             SoftdeviceController<'d>,
             &'static MultiprotocolServiceLayer<'d>,
         ),
-        nrf_sdc::Error,
+        BleInitError,
     > {
+        let mem = {
+            static SDC_MEM: StaticCell<sdc::Mem<SDC_MEMORY_SIZE>> = StaticCell::new();
+            SDC_MEM.init(sdc::Mem::new())
+        };
+        self.init_with_memory(timer0, rng, mem)
+    }
+
+    /// Like [`init`](Self::init), with a caller provided buffer for the
+    /// Softdevice Controller, for configurations needing more than the
+    /// default [`SDC_MEMORY_SIZE`] bytes.
+    pub fn init_with_memory<const N: usize>(
+        self,
+        timer0: Peri<'static, peripherals::TIMER0>,
+        rng: Rng,
+        mem: &'d mut sdc::Mem<N>,
+    ) -> Result<
+        (
+            SoftdeviceController<'d>,
+            &'static MultiprotocolServiceLayer<'d>,
+        ),
+        BleInitError,
+    > {
+        // Fail before starting the MPSL if the buffer is too small.
+        let required = sdc_builder(&self.config)?.required_memory()?;
+        if required > N {
+            return Err(BleInitError::MemoryTooSmall {
+                required,
+                available: N,
+            });
+        }
+        crate::log!(
+            Ble,
+            info,
+            "[ble] controller memory: {} of {} bytes",
+            required,
+            N
+        );
+
         let mpsl = {
             let p = mpsl::Peripherals::new(
                 self.rtc0,
@@ -142,35 +269,131 @@ where
                 self.ppi_ch30,
                 self.ppi_ch31,
             );
-            mpsl::MultiprotocolServiceLayer::new(p, Irqs, Self::LF_CLOCK_CONFIG)
+            mpsl::MultiprotocolServiceLayer::new(p, Irqs, self.lf_clock)
         }?;
         let sdc_rng = {
-            static SDC_RNG: StaticCell<rng::Rng<'static, Async>> = StaticCell::new();
-            SDC_RNG.init(rng::Rng::new(rng, Irqs))
-        };
-        let mem = {
-            static SDC_MEM: StaticCell<sdc::Mem<SDC_MEMORY_SIZE>> = StaticCell::new();
-            SDC_MEM.init(self.sdc_mem)
+            static SDC_RNG: StaticCell<Rng> = StaticCell::new();
+            SDC_RNG.init(rng)
         };
         let mpsl = {
             static MPSL: StaticCell<MultiprotocolServiceLayer> = StaticCell::new();
             MPSL.init(mpsl)
         };
-        let sdc = build_sdc(self.sdc_peripherals, sdc_rng, mpsl, mem)?;
+        let sdc = sdc_builder(&self.config)?.build(self.sdc_peripherals, sdc_rng, mpsl, mem)?;
         Ok((sdc, mpsl))
     }
 }
 
-/// Build the Softdevice Controller layer to pass to trouble-host
-fn build_sdc<'d, const N: usize>(
-    p: nrf_sdc::Peripherals<'d>,
-    rng: &'d mut rng::Rng<Async>,
-    mpsl: &'d MultiprotocolServiceLayer,
-    mem: &'d mut sdc::Mem<N>,
-) -> Result<nrf_sdc::SoftdeviceController<'d>, nrf_sdc::Error> {
-    sdc::Builder::new()?
-        .support_adv()?
-        .support_peripheral()?
-        .peripheral_count(1)?
-        .build(p, rng, mpsl, mem)
+/// Configure the Softdevice Controller layer to pass to trouble-host
+#[cfg_attr(
+    not(any(feature = "ble-peripheral", feature = "ble-central")),
+    allow(unused_variables)
+)]
+fn sdc_builder(config: &SdcConfig) -> Result<sdc::Builder, nrf_sdc::Error> {
+    let mut builder = sdc::Builder::new()?;
+    #[cfg(feature = "ble-broadcaster")]
+    {
+        builder = builder.support_adv()?;
+    }
+    #[cfg(feature = "ble-peripheral")]
+    if config.peripheral_links > 0 {
+        builder = builder
+            .support_peripheral()?
+            .peripheral_count(config.peripheral_links)?;
+    }
+    #[cfg(feature = "ble-observer")]
+    {
+        builder = builder.support_scan()?;
+    }
+    #[cfg(feature = "ble-central")]
+    if config.central_links > 0 {
+        builder = builder
+            .support_central()?
+            .central_count(config.central_links)?;
+    }
+    if config.extended_adv {
+        builder = builder.support_ext_adv()?;
+    }
+    if config.coded_phy {
+        builder = builder.support_le_coded_phy()?;
+    }
+    if config.periodic_adv {
+        builder = builder.support_le_periodic_adv()?;
+    }
+    Ok(builder)
+}
+
+/// Host resources of the `trouble-host` stack, sized by the maximum number of
+/// connections `C`, L2CAP channels `L` and advertising sets `A`. The host
+/// keeps the state of `A` advertising sets, one unless given. The beacons
+/// keep the 27 they always used.
+///
+/// The RAM cost of a configuration is dominated by
+/// - one connection slot (state, ATT and signalling queues) per connection,
+/// - one channel slot (state and credit based flow control) per L2CAP channel,
+///   where every connection needs two channels (ATT + signalling) and every
+///   connection oriented channel one additional one,
+/// - the packet pool, which is shared by all connections.
+///
+/// The exact size in bytes is available as [`Resources::RAM_BYTES`] and is
+/// logged by [`Resources::log_ram_usage`]. Use the aliases below for the
+/// configurations used by the binaries of this crate.
+pub struct Resources<const C: usize, const L: usize, const A: usize = 1> {
+    inner: HostResources<DefaultPacketPool, C, L, A>,
+}
+
+/// Resources of a non-connectable beacon: no connections, no channels.
+#[cfg(feature = "ble-broadcaster")]
+pub type BeaconResources = Resources<0, 0, 27>;
+
+/// Resources of a scanner: no connections, no channels.
+#[cfg(feature = "ble-observer")]
+pub type ObserverResources = Resources<0, 0>;
+
+/// Resources of a GATT peripheral with a single central connected.
+#[cfg(feature = "ble-peripheral")]
+pub type PeripheralResources = Resources<1, 2>;
+
+/// Resources of a single connection using one L2CAP connection oriented channel.
+#[cfg(any(feature = "ble-peripheral", feature = "ble-central"))]
+pub type L2capResources = Resources<1, 3>;
+
+impl<const C: usize, const L: usize, const A: usize> Default for Resources<C, L, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const C: usize, const L: usize, const A: usize> Resources<C, L, A> {
+    /// RAM used by the host resources in bytes.
+    pub const RAM_BYTES: usize = core::mem::size_of::<HostResources<DefaultPacketPool, C, L, A>>();
+
+    pub fn new() -> Self {
+        Self {
+            inner: HostResources::new(),
+        }
+    }
+
+    /// Log the RAM usage of this configuration.
+    pub fn log_ram_usage(&self) {
+        crate::log!(
+            Ble,
+            info,
+            "[ble] host resources for {} connections, {} channels: {} bytes",
+            C,
+            L,
+            Self::RAM_BYTES
+        );
+    }
+
+    /// Create the `trouble-host` stack on top of `controller` using the
+    /// given static random address.
+    pub fn stack<Ctrl: Controller>(
+        &mut self,
+        controller: Ctrl,
+        address: Address,
+    ) -> Stack<'_, Ctrl, DefaultPacketPool> {
+        self.log_ram_usage();
+        trouble_host::new(controller, &mut self.inner).set_random_address(address)
+    }
 }
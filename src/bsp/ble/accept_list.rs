@@ -0,0 +1,40 @@
+//! Filter accept list of the controller.
+//!
+//! With addresses on the list, advertising only answers connection and scan
+//! requests from them. A central using a resolvable private address can't
+//! be listed without bonding, only public and random static addresses work.
+
+use bt_hci::cmd::le::{LeAddDeviceToFilterAcceptList, LeClearFilterAcceptList};
+use bt_hci::param::AdvFilterPolicy;
+use nrf_sdc::SoftdeviceController;
+use trouble_host::prelude::*;
+
+use super::SoftdeviceError;
+
+/// Replace the filter accept list with `addresses` (least significant byte
+/// first), returning the advertising filter policy to use with it.
+///
+/// Fails while advertising, the list is in use then.
+pub async fn apply<'a, P: PacketPool>(
+    stack: &Stack<'_, SoftdeviceController<'_>, P>,
+    addresses: impl Iterator<Item = &'a [u8; 6]>,
+) -> Result<AdvFilterPolicy, BleHostError<SoftdeviceError>> {
+    stack.command(LeClearFilterAcceptList::new()).await?;
+    let mut policy = AdvFilterPolicy::Unfiltered;
+    for address in addresses {
+        // The two most significant bits of a random static address are set.
+        let kind = if address[5] >> 6 == 0b11 {
+            AddrKind::RANDOM
+        } else {
+            AddrKind::PUBLIC
+        };
+        stack
+            .command(LeAddDeviceToFilterAcceptList::new(
+                kind,
+                BdAddr::new(*address),
+            ))
+            .await?;
+        policy = AdvFilterPolicy::FilterConnAndScan;
+    }
+    Ok(policy)
+}
@@ -0,0 +1,148 @@
+//! Randomized advertising timing, directed advertising and payloads.
+//!
+//! Many devices advertising with the same fixed intervals, and restarting
+//! advertising at the same time (e.g. after a power cut), keep colliding on
+//! the advertising channels. [`AdvTiming`] shifts the intervals by a random
+//! amount once per boot and delays every restart by a random jitter.
+//!
+//! A central that was connected before reconnects faster with
+//! [`advertise_directed`], which only it can answer.
+//!
+//! [`payload`] plans advertising data that never overflows, see
+//! [`nrf52_radio_core::adv`].
+
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Timer};
+pub use nrf52_radio_core::adv::{ADV_LEN, Ad, Plan, plan};
+use trouble_host::prelude::*;
+
+use crate::bsp::rng::Rng;
+
+/// Advertising intervals and restart jitter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct AdvTiming {
+    /// Minimum advertising interval before randomization.
+    pub interval_min: Duration,
+    /// Maximum advertising interval before randomization.
+    pub interval_max: Duration,
+    /// Both intervals are increased by a random amount up to this, per boot.
+    pub spread: Duration,
+    /// Every restart is delayed by a random amount up to this.
+    pub restart_jitter: Duration,
+}
+
+impl AdvTiming {
+    /// Fast beaconing.
+    pub const BEACON: Self = Self {
+        interval_min: Duration::from_millis(25),
+        interval_max: Duration::from_millis(150),
+        spread: Duration::from_millis(20),
+        restart_jitter: Duration::from_millis(50),
+    };
+
+    /// Connectable advertising, intervals as recommended for fast discovery.
+    pub const CONNECTABLE: Self = Self {
+        interval_min: Duration::from_millis(100),
+        interval_max: Duration::from_millis(150),
+        spread: Duration::from_millis(30),
+        restart_jitter: Duration::from_millis(200),
+    };
+
+    /// The same timing with the minimum interval `interval_min`, the maximum
+    /// interval as far above it as before.
+    pub fn with_interval(self, interval_min: Duration) -> Self {
+        Self {
+            interval_min,
+            interval_max: interval_min + (self.interval_max - self.interval_min),
+            ..self
+        }
+    }
+
+    /// Random duration in `0..=max`.
+    fn random(rng: &mut Rng, max: Duration) -> Duration {
+        let max_ms = max.as_millis() as u32;
+        Duration::from_millis(rng.below(max_ms + 1) as u64)
+    }
+
+    /// Pick the intervals for this boot.
+    pub fn randomize(&self, rng: &mut Rng) -> AdvertisementParameters {
+        let shift = Self::random(rng, self.spread);
+        let mut params = AdvertisementParameters::default();
+        params.interval_min = self.interval_min + shift;
+        params.interval_max = self.interval_max + shift;
+        crate::log!(
+            Ble,
+            info,
+            "[adv] intervals {} ms to {} ms",
+            params.interval_min.as_millis(),
+            params.interval_max.as_millis()
+        );
+        params
+    }
+
+    /// Wait a random time before (re)starting advertising.
+    pub async fn restart_delay(&self, rng: &mut Rng) {
+        Timer::after(Self::random(rng, self.restart_jitter)).await
+    }
+}
+
+/// Longest high duty cycle directed advertising the specification allows.
+pub const HIGH_DUTY_TIMEOUT: Duration = Duration::from_millis(1280);
+
+/// Low duty cycle directed advertising following the high duty cycle one.
+pub const LOW_DUTY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether directed advertising can reach a central that connected from
+/// `address`: a public or random static address. A resolvable private
+/// address has changed by the next connection.
+pub fn is_identity(address: &Address) -> bool {
+    address.kind == AddrKind::PUBLIC || address.addr.raw()[5] >> 6 == 0b11
+}
+
+/// Advertise directed to `peer`, with high duty cycle for
+/// [`HIGH_DUTY_TIMEOUT`], then with the intervals of `params` for
+/// [`LOW_DUTY_TIMEOUT`].
+///
+/// Returns `None` if the central didn't connect, the caller falls back to
+/// undirected advertising then.
+pub async fn advertise_directed<'d, C: Controller, P: PacketPool>(
+    peripheral: &mut Peripheral<'d, C, P>,
+    params: &AdvertisementParameters,
+    peer: Address,
+) -> Result<Option<Connection<'d, P>>, BleHostError<C::Error>> {
+    let phases = [
+        (
+            Advertisement::ConnectableNonscannableDirectedHighDuty { peer },
+            HIGH_DUTY_TIMEOUT,
+        ),
+        (
+            Advertisement::ConnectableNonscannableDirected { peer },
+            LOW_DUTY_TIMEOUT,
+        ),
+    ];
+    for (advertisement, timeout) in phases {
+        let advertiser = peripheral.advertise(params, advertisement).await?;
+        if let Either::First(conn) = select(advertiser.accept(), Timer::after(timeout)).await {
+            return Ok(Some(conn?));
+        }
+    }
+    Ok(None)
+}
+
+/// Plan the payload of `ads`, the most important first, warning about what
+/// didn't fit.
+pub fn payload(ads: &[Ad]) -> Plan {
+    let plan = plan(ads);
+    if !plan.dropped.is_empty() {
+        crate::log!(
+            Ble,
+            warn,
+            "[adv] AD types {=[u8]:x} dropped",
+            plan.dropped.as_slice()
+        );
+    }
+    if plan.shortened {
+        crate::log!(Ble, warn, "[adv] name shortened");
+    }
+    plan
+}
@@ -0,0 +1,41 @@
+//! Callbacks for the lifecycle of BLE connections.
+//!
+//! The BLE tasks publish connection events on the [event bus](crate::events).
+//! Application code that reacts to them (e.g. powering a sensor while a
+//! central is connected) implements [`BleEvents`] and runs it with
+//! [`dispatch`], instead of adding to the GATT event loop. All callbacks
+//! default to doing nothing.
+
+use crate::events::{self, ConnParams, Event};
+
+/// Reactions to connection events, called in the order of the events.
+pub trait BleEvents {
+    /// A central connected.
+    fn connected(&mut self, _address: [u8; 6], _mtu: u16) {}
+
+    /// The central disconnected.
+    fn disconnected(&mut self) {}
+
+    /// The central changed the connection parameters.
+    fn params_updated(&mut self, _params: ConnParams) {}
+
+    /// The ATT MTU was exchanged.
+    fn mtu_updated(&mut self, _mtu: u16) {}
+}
+
+/// Call `hooks` for every connection event.
+pub async fn dispatch(hooks: &mut impl BleEvents) {
+    let Ok(mut events) = events::subscribe() else {
+        crate::log!(Ble, warn, "[ble] no event bus subscriber available");
+        return;
+    };
+    loop {
+        match events.next_message_pure().await {
+            Event::CentralConnected { address, mtu } => hooks.connected(address, mtu),
+            Event::CentralDisconnected => hooks.disconnected(),
+            Event::ConnParamsUpdated(params) => hooks.params_updated(params),
+            Event::MtuUpdated(mtu) => hooks.mtu_updated(mtu),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Link parameters for throughput: ATT MTU and LE Data Length Extension.
+//!
+//! Without them every notification carries at most 20 bytes in a 27 byte
+//! link layer packet. The ATT MTU is exchanged by the central (a GATT
+//! client), the host offers up to [`MAX_ATT_MTU`] for the packet pool.
+//! [`request_data_length`] asks the controller for link layer packets of
+//! [`MAX_TX_OCTETS`], so a notification of a full MTU takes one packet.
+//! [`LinkParams`] tracks what was negotiated.
+
+use nrf_sdc::SoftdeviceController;
+use trouble_host::prelude::*;
+
+use super::SoftdeviceError;
+
+/// Largest ATT MTU of the default packet pool.
+pub const MAX_ATT_MTU: u16 = 247;
+
+/// Largest link layer payload with Data Length Extension.
+pub const MAX_TX_OCTETS: u16 = 251;
+
+/// Air time of a [`MAX_TX_OCTETS`] packet on the 1M PHY in µs.
+pub const MAX_TX_TIME_US: u16 = 2120;
+
+/// ATT header of a notification (opcode and handle).
+const NOTIFY_HEADER_LEN: u16 = 3;
+
+/// Ask the controller to use the longest link layer packets on `conn`.
+///
+/// The peer may answer with shorter ones, the result is reported as a
+/// `DataLengthUpdated` connection event.
+pub async fn request_data_length<P: PacketPool>(
+    stack: &Stack<'_, SoftdeviceController<'_>, P>,
+    conn: &Connection<'_, P>,
+) -> Result<(), BleHostError<SoftdeviceError>> {
+    conn.update_data_length(stack, MAX_TX_OCTETS, MAX_TX_TIME_US)
+        .await
+}
+
+/// Negotiated link parameters of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct LinkParams {
+    pub att_mtu: u16,
+    /// Link layer payload sent and received, 27 until Data Length
+    /// Extension was negotiated.
+    pub tx_octets: u16,
+    pub rx_octets: u16,
+}
+
+impl LinkParams {
+    /// Parameters of a new connection.
+    pub fn new<P: PacketPool>(conn: &Connection<'_, P>) -> Self {
+        Self {
+            att_mtu: conn.att_mtu(),
+            tx_octets: 27,
+            rx_octets: 27,
+        }
+    }
+
+    /// Longest value of a single notification.
+    pub fn max_notify_len(&self) -> usize {
+        self.att_mtu.saturating_sub(NOTIFY_HEADER_LEN) as usize
+    }
+
+    /// Take the ATT MTU of `conn` after an exchange, returning whether it
+    /// changed.
+    pub fn update_mtu<P: PacketPool>(&mut self, conn: &Connection<'_, P>) -> bool {
+        let changed = conn.att_mtu() != self.att_mtu;
+        self.att_mtu = conn.att_mtu();
+        changed
+    }
+
+    /// Take the result of a data length update.
+    pub fn update_data_length(&mut self, tx_octets: u16, rx_octets: u16) {
+        self.tx_octets = tx_octets;
+        self.rx_octets = rx_octets;
+    }
+
+    /// Log the negotiated values.
+    pub fn log(&self) {
+        crate::log!(
+            Ble,
+            info,
+            "[link] ATT MTU {}, data length tx {} / rx {} bytes",
+            self.att_mtu,
+            self.tx_octets,
+            self.rx_octets
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Long characteristic values, longer than one ATT MTU.
+//!
+//! The host serves them: a central reads them with Read Blob requests at
+//! increasing offsets and writes them with Prepare Write requests and one
+//! Execute Write. Values refreshed on every read (e.g. the settings export)
+//! must only be refreshed by the first read of such a sequence,
+//! [`read_offset`] tells which one it is. [`PreparedWrites`] puts the
+//! prepared parts together and hands out the whole value once executed.
+
+use heapless::Vec;
+use trouble_host::prelude::*;
+
+/// Longest attribute value.
+pub const MAX_VALUE_LEN: usize = 512;
+
+/// Execute Write flag writing the queued values, any other cancels them.
+const EXECUTE_WRITE: u8 = 0x01;
+
+/// Offset of a Read Blob request, 0 for any other request.
+pub fn read_offset(request: &AttClient<'_>) -> usize {
+    match request {
+        AttClient::Request(AttReq::ReadBlob { offset, .. }) => *offset as usize,
+        _ => 0,
+    }
+}
+
+/// The value the prepared writes of a central put together, for one
+/// characteristic at a time with the parts in order, as GATT clients write
+/// long values.
+#[derive(Debug, Default)]
+pub struct PreparedWrites {
+    handle: Option<u16>,
+    value: Vec<u8, MAX_VALUE_LEN>,
+}
+
+impl PreparedWrites {
+    pub const fn new() -> Self {
+        Self {
+            handle: None,
+            value: Vec::new(),
+        }
+    }
+
+    /// Follow the requests of the central, the handle and the whole value
+    /// an Execute Write request writes. Parts out of order or beyond
+    /// [`MAX_VALUE_LEN`] drop the queued value.
+    pub fn executed(&mut self, request: &AttClient<'_>) -> Option<(u16, Vec<u8, MAX_VALUE_LEN>)> {
+        match request {
+            AttClient::Request(AttReq::PrepareWrite {
+                handle,
+                offset,
+                value,
+            }) => {
+                if *offset == 0 {
+                    self.cancel();
+                    self.handle = Some(*handle);
+                }
+                let next = self.handle == Some(*handle) && *offset as usize == self.value.len();
+                if !next || self.value.extend_from_slice(value).is_err() {
+                    self.cancel();
+                }
+                None
+            }
+            AttClient::Request(AttReq::ExecuteWrite { flags }) if *flags == EXECUTE_WRITE => {
+                let handle = self.handle.take()?;
+                Some((handle, core::mem::take(&mut self.value)))
+            }
+            AttClient::Request(AttReq::ExecuteWrite { .. }) => {
+                self.cancel();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.handle = None;
+        self.value.clear();
+    }
+}
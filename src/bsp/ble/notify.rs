@@ -0,0 +1,51 @@
+//! Notifications that wait for a free packet instead of being dropped.
+//!
+//! `Characteristic::notify` fails at once with `OutOfMemory` when the packet
+//! pool is exhausted, e.g. during a burst of NMEA chunks while the central
+//! is slow to acknowledge. [`send`] retries until a packet is free and gives
+//! up after [`NOTIFY_TIMEOUT`], counting the notifications given up in
+//! [`NOTIFICATIONS_DROPPED`](crate::metrics::NOTIFICATIONS_DROPPED).
+
+use embassy_time::{Duration, Timer, with_timeout};
+use trouble_host::prelude::*;
+
+use crate::metrics;
+
+/// Longest wait for a free packet.
+pub const NOTIFY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Wait between two attempts while the packet pool is exhausted.
+const RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Errors notifying a value.
+#[derive(Debug, defmt::Format)]
+pub enum NotifyError {
+    /// No packet became free within [`NOTIFY_TIMEOUT`].
+    Timeout,
+    /// The host rejected the notification, e.g. the connection closed.
+    Host(Error),
+}
+
+/// Notify `value`, returning once it is queued for sending.
+pub async fn send<T: FromGatt, P: PacketPool>(
+    characteristic: &Characteristic<T>,
+    conn: &GattConnection<'_, '_, P>,
+    value: &T,
+) -> Result<(), NotifyError> {
+    let queued = async {
+        loop {
+            match characteristic.notify(conn, value).await {
+                Err(Error::OutOfMemory) => Timer::after(RETRY_INTERVAL).await,
+                result => return result,
+            }
+        }
+    };
+    let result = match with_timeout(NOTIFY_TIMEOUT, queued).await {
+        Ok(result) => result.map_err(NotifyError::Host),
+        Err(_) => Err(NotifyError::Timeout),
+    };
+    if result.is_err() {
+        metrics::NOTIFICATIONS_DROPPED.increment();
+    }
+    result
+}
@@ -0,0 +1,165 @@
+//! Several logical beacons advertised by one device.
+//!
+//! An [`AdvRotation`] time-multiplexes the payloads of its [`Beacon`]s, e.g.
+//! an [`IBeacon`], an [`EddystoneUrl`] and a [`Tlm`] frame. With legacy
+//! advertising one advertiser sends each payload for the dwell time in turn
+//! ([`AdvRotation::run_legacy`]). With
+//! [extended advertising](super::SdcConfig::extended_adv) every beacon gets
+//! its own advertising set and all are sent at once
+//! ([`AdvRotation::run_extended`]), their data refreshed every dwell time.
+
+use embassy_time::{Duration, Timer};
+use nrf_sdc::SoftdeviceController;
+use nrf52_radio_core::beacon::{EDDYSTONE_URL_LEN, IBEACON_COMPANY_ID, eddystone_url, ibeacon};
+use trouble_host::prelude::*;
+
+use super::SoftdeviceError;
+use crate::crypto::eid::EDDYSTONE_UUID;
+use crate::metrics;
+
+/// Longest legacy advertising data.
+const ADV_DATA_LEN: usize = 31;
+
+/// A logical beacon.
+pub trait Beacon {
+    /// Encode the advertising data into `buf`, returning its length.
+    ///
+    /// Called whenever the beacon is advertised, so its content may change.
+    fn adv_data(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+}
+
+/// Apple iBeacon.
+pub struct IBeacon {
+    pub uuid: [u8; 16],
+    pub major: u16,
+    pub minor: u16,
+    /// RSSI at 1 m in dBm.
+    pub tx_power: i8,
+}
+
+impl Beacon for IBeacon {
+    fn adv_data(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        AdStructure::encode_slice(
+            &[
+                AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+                AdStructure::ManufacturerSpecificData {
+                    company_identifier: IBEACON_COMPANY_ID,
+                    payload: &ibeacon(&self.uuid, self.major, self.minor, self.tx_power),
+                },
+            ],
+            buf,
+        )
+    }
+}
+
+/// Eddystone-URL beacon.
+pub struct EddystoneUrl {
+    frame: heapless::Vec<u8, EDDYSTONE_URL_LEN>,
+}
+
+impl EddystoneUrl {
+    /// Beacon of `url`, `tx_power` is the RSSI at 0 m in dBm. `None` if the
+    /// URL can't be encoded, see [`eddystone_url`].
+    pub fn new(url: &str, tx_power: i8) -> Option<Self> {
+        Some(Self {
+            frame: eddystone_url(url, tx_power)?,
+        })
+    }
+}
+
+impl Beacon for EddystoneUrl {
+    fn adv_data(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        eddystone(&self.frame, buf)
+    }
+}
+
+/// Eddystone-TLM beacon with the current [`metrics`].
+pub struct Tlm;
+
+impl Beacon for Tlm {
+    fn adv_data(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        eddystone(&metrics::snapshot().tlm_frame(), buf)
+    }
+}
+
+/// Advertising data of an Eddystone frame.
+fn eddystone(frame: &[u8], buf: &mut [u8]) -> Result<usize, Error> {
+    AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::ServiceUuids16(&[EDDYSTONE_UUID]),
+            AdStructure::ServiceData16 {
+                uuid: EDDYSTONE_UUID,
+                data: frame,
+            },
+        ],
+        buf,
+    )
+}
+
+/// Advertises `N` beacons from one device.
+pub struct AdvRotation<'a, const N: usize> {
+    beacons: [&'a mut dyn Beacon; N],
+    dwell: Duration,
+}
+
+impl<'a, const N: usize> AdvRotation<'a, N> {
+    /// Rotation through `beacons`, each advertised for `dwell` in turn.
+    pub fn new(beacons: [&'a mut dyn Beacon; N], dwell: Duration) -> Self {
+        const { assert!(N > 0, "a rotation needs at least one beacon") };
+        Self { beacons, dwell }
+    }
+
+    /// Advertise the beacons in turn with one legacy advertiser.
+    ///
+    /// Only returns on errors.
+    pub async fn run_legacy<P: PacketPool>(
+        &mut self,
+        peripheral: &mut Peripheral<'_, SoftdeviceController<'_>, P>,
+        params: &AdvertisementParameters,
+    ) -> Result<(), BleHostError<SoftdeviceError>> {
+        loop {
+            for beacon in &mut self.beacons {
+                let mut adv_data = [0; ADV_DATA_LEN];
+                let len = beacon.adv_data(&mut adv_data)?;
+                let _advertiser = peripheral
+                    .advertise(
+                        params,
+                        Advertisement::NonconnectableNonscannableUndirected {
+                            adv_data: &adv_data[..len],
+                        },
+                    )
+                    .await?;
+                Timer::after(self.dwell).await;
+            }
+        }
+    }
+
+    /// Advertise all beacons at once in their own extended advertising
+    /// sets. The controller has to support `N` sets.
+    ///
+    /// Only returns on errors.
+    pub async fn run_extended<P: PacketPool>(
+        &mut self,
+        peripheral: &mut Peripheral<'_, SoftdeviceController<'_>, P>,
+        params: &AdvertisementParameters,
+    ) -> Result<(), BleHostError<SoftdeviceError>> {
+        loop {
+            let mut adv_data = [[0; ADV_DATA_LEN]; N];
+            let mut lens = [0; N];
+            for ((beacon, buf), len) in self.beacons.iter_mut().zip(&mut adv_data).zip(&mut lens) {
+                *len = beacon.adv_data(buf)?;
+            }
+            let sets: [AdvertisementSet; N] = core::array::from_fn(|i| AdvertisementSet {
+                params: *params,
+                data: Advertisement::ExtNonconnectableNonscannableUndirected {
+                    anonymous: false,
+                    adv_data: &adv_data[i][..lens[i]],
+                },
+            });
+            let mut handles = AdvertisementSet::handles(&sets);
+            let _advertiser = peripheral.advertise_ext(&sets, &mut handles).await?;
+            Timer::after(self.dwell).await;
+        }
+    }
+}
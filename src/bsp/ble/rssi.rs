@@ -0,0 +1,39 @@
+//! RSSI of a connection, read periodically from the controller.
+//!
+//! [`monitor`] publishes every reading as
+//! [`Event::RssiUpdated`](crate::events::Event::RssiUpdated) and notifies it
+//! to a central subscribed to the given characteristic. Consumers smooth the
+//! readings with a [`Proximity`], see [`nrf52_radio_core::rssi`].
+
+use embassy_time::{Duration, Timer};
+use nrf_sdc::SoftdeviceController;
+pub use nrf52_radio_core::rssi::{HYSTERESIS_DB, Proximity};
+use trouble_host::prelude::*;
+
+use super::notify;
+use crate::events::{self, Event};
+
+/// Interval in which the RSSI is read.
+pub const RSSI_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Read the RSSI of `conn` every [`RSSI_INTERVAL`], publish it and notify it
+/// over `characteristic`.
+pub async fn monitor<P: PacketPool>(
+    stack: &Stack<'_, SoftdeviceController<'_>, P>,
+    conn: &GattConnection<'_, '_, P>,
+    characteristic: &Characteristic<i8>,
+) -> ! {
+    loop {
+        Timer::after(RSSI_INTERVAL).await;
+        let rssi = match conn.raw().rssi(stack).await {
+            Ok(rssi) => rssi,
+            Err(e) => {
+                crate::log!(Ble, warn, "[rssi] couldn't read RSSI: {:?}", e);
+                continue;
+            }
+        };
+        crate::log!(Ble, debug, "[rssi] {} dBm", rssi);
+        events::publish(Event::RssiUpdated(rssi));
+        let _ = notify::send(characteristic, conn, &rssi).await;
+    }
+}
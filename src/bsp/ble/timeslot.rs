@@ -0,0 +1,311 @@
+//! Radio timeslots of the MPSL for proprietary protocols next to BLE.
+//!
+//! The Softdevice Controller owns the RADIO and TIMER0. A [`Timeslot`]
+//! session asks the MPSL for time in between BLE events, during which a
+//! [`TimeslotHandler`] has both peripherals to itself, e.g. for a custom
+//! protocol or ranging pings. The handler is called from the highest
+//! priority interrupt and must be quick: it sets up the RADIO on
+//! [`start`](TimeslotHandler::start), reacts to its interrupts and ends the
+//! slot before the granted length is over, otherwise the MPSL asserts.
+//!
+//! Slots the MPSL couldn't grant are reported as [`SessionEvent`]s to the
+//! async side, which requests new ones with [`Timeslot::request`].
+
+use core::cell::{Cell, UnsafeCell};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_sync::signal::Signal;
+use embassy_time::Duration;
+use nrf_sdc::mpsl::{MultiprotocolServiceLayer, raw};
+
+/// Signals of `mpsl_timeslot.h`.
+mod signal {
+    pub const START: u32 = 0;
+    pub const TIMER0: u32 = 1;
+    pub const RADIO: u32 = 2;
+    pub const EXTEND_FAILED: u32 = 3;
+    pub const EXTEND_SUCCEEDED: u32 = 4;
+    pub const BLOCKED: u32 = 5;
+    pub const CANCELLED: u32 = 6;
+    pub const SESSION_IDLE: u32 = 7;
+    pub const INVALID_RETURN: u32 = 8;
+    pub const OVERSTAYED: u32 = 10;
+}
+
+/// Return actions of `mpsl_timeslot.h`.
+mod action {
+    pub const NONE: u8 = 0;
+    pub const EXTEND: u8 = 1;
+    pub const END: u8 = 2;
+    pub const REQUEST: u8 = 3;
+}
+
+const REQ_TYPE_EARLIEST: u8 = 0;
+const REQ_TYPE_NORMAL: u8 = 1;
+const HFCLK_CFG_XTAL_GUARANTEED: u8 = 0;
+const PRIORITY_NORMAL: u8 = 1;
+
+/// Shortest slot the MPSL grants.
+pub const MIN_LENGTH: Duration = Duration::from_micros(100);
+
+/// Longest slot the MPSL grants.
+pub const MAX_LENGTH: Duration = Duration::from_micros(128_000_000);
+
+/// What to do after a signal was handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Action {
+    /// Keep the slot.
+    None,
+    /// Extend the slot by this long, see
+    /// [`extended`](TimeslotHandler::extended).
+    Extend(Duration),
+    /// End the slot, the RADIO and TIMER0 must be idle.
+    End,
+    /// End the slot and request the next one.
+    Request(Request),
+}
+
+/// A request for a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Request {
+    /// As early as possible, within `timeout`.
+    Earliest { length: Duration, timeout: Duration },
+    /// `distance` after the start of the previous slot.
+    Normal {
+        length: Duration,
+        distance: Duration,
+    },
+}
+
+impl Request {
+    fn to_raw(self) -> raw::mpsl_timeslot_request_t {
+        // SAFETY: all-zero is a valid request, the fields are set below.
+        let mut request: raw::mpsl_timeslot_request_t = unsafe { core::mem::zeroed() };
+        match self {
+            Request::Earliest { length, timeout } => {
+                request.request_type = REQ_TYPE_EARLIEST;
+                request.params.earliest = raw::mpsl_timeslot_request_earliest_t {
+                    hfclk: HFCLK_CFG_XTAL_GUARANTEED,
+                    priority: PRIORITY_NORMAL,
+                    length_us: length.as_micros() as u32,
+                    timeout_us: timeout.as_micros() as u32,
+                };
+            }
+            Request::Normal { length, distance } => {
+                request.request_type = REQ_TYPE_NORMAL;
+                request.params.normal = raw::mpsl_timeslot_request_normal_t {
+                    hfclk: HFCLK_CFG_XTAL_GUARANTEED,
+                    priority: PRIORITY_NORMAL,
+                    distance_us: distance.as_micros() as u32,
+                    length_us: length.as_micros() as u32,
+                };
+            }
+        }
+        request
+    }
+}
+
+/// Code of the radio protocol, called in interrupt context during a slot.
+pub trait TimeslotHandler: Sync {
+    /// The slot started, the RADIO and TIMER0 are reset and available.
+    /// TIMER0 counts from 0 in µs, e.g. to end the slot in time.
+    fn start(&self) -> Action;
+
+    /// The TIMER0 interrupt fired.
+    fn timer0(&self) -> Action {
+        Action::End
+    }
+
+    /// The RADIO interrupt fired.
+    fn radio(&self) -> Action {
+        Action::None
+    }
+
+    /// Result of [`Action::Extend`].
+    fn extended(&self, _succeeded: bool) -> Action {
+        Action::None
+    }
+}
+
+/// Events of a session outside of slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SessionEvent {
+    /// A request collided with BLE activity, request again.
+    Blocked,
+    /// A granted slot was cancelled, request again.
+    Cancelled,
+    /// No slot is requested anymore.
+    Idle,
+    /// The handler returned an invalid action or overstayed its slot.
+    Error,
+}
+
+/// Errors of the timeslot API, with the error code of the MPSL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum TimeslotError {
+    /// A session is already open, only one is supported.
+    Busy,
+    Mpsl(i32),
+}
+
+fn check(ret: i32) -> Result<(), TimeslotError> {
+    match ret {
+        0 => Ok(()),
+        e => Err(TimeslotError::Mpsl(e)),
+    }
+}
+
+/// Memory for the context of one session.
+struct Context<T>(UnsafeCell<T>);
+
+// SAFETY: only accessed by the MPSL, or by the callback in its interrupt.
+unsafe impl<T> Sync for Context<T> {}
+
+static SESSION_MEMORY: Context<[u32; raw::MPSL_TIMESLOT_CONTEXT_SIZE as usize / 4]> = Context(
+    UnsafeCell::new([0; raw::MPSL_TIMESLOT_CONTEXT_SIZE as usize / 4]),
+);
+
+/// Returned to the MPSL from the callback, read after it returns.
+static RETURN_PARAM: Context<raw::mpsl_timeslot_signal_return_param_t> =
+    // SAFETY: all-zero is a valid return parameter.
+    Context(UnsafeCell::new(unsafe { core::mem::zeroed() }));
+
+/// Next request of [`Action::Request`], read after the callback returns.
+static NEXT_REQUEST: Context<raw::mpsl_timeslot_request_t> =
+    // SAFETY: all-zero is a valid request.
+    Context(UnsafeCell::new(unsafe { core::mem::zeroed() }));
+
+static OPEN: AtomicBool = AtomicBool::new(false);
+
+static HANDLER: Mutex<CriticalSectionRawMutex, Cell<Option<&'static dyn TimeslotHandler>>> =
+    Mutex::new(Cell::new(None));
+
+static EVENTS: Signal<CriticalSectionRawMutex, SessionEvent> = Signal::new();
+
+unsafe extern "C" fn callback(
+    _session_id: u8,
+    signal: u32,
+) -> *mut raw::mpsl_timeslot_signal_return_param_t {
+    let Some(handler) = HANDLER.lock(|h| h.get()) else {
+        return core::ptr::null_mut();
+    };
+    let next = match signal {
+        signal::START => handler.start(),
+        signal::TIMER0 => handler.timer0(),
+        signal::RADIO => handler.radio(),
+        signal::EXTEND_FAILED => handler.extended(false),
+        signal::EXTEND_SUCCEEDED => handler.extended(true),
+        signal::BLOCKED | signal::CANCELLED | signal::SESSION_IDLE => {
+            EVENTS.signal(match signal {
+                signal::BLOCKED => SessionEvent::Blocked,
+                signal::CANCELLED => SessionEvent::Cancelled,
+                _ => SessionEvent::Idle,
+            });
+            return core::ptr::null_mut();
+        }
+        signal::INVALID_RETURN | signal::OVERSTAYED => {
+            EVENTS.signal(SessionEvent::Error);
+            return core::ptr::null_mut();
+        }
+        _ => return core::ptr::null_mut(),
+    };
+    let ret = RETURN_PARAM.0.get();
+    // SAFETY: the MPSL reads the return parameter (and the next request) only
+    // after the callback returned, and calls the callback non-reentrantly.
+    unsafe {
+        match next {
+            Action::None => (*ret).callback_action = action::NONE,
+            Action::End => (*ret).callback_action = action::END,
+            Action::Extend(length) => {
+                (*ret).callback_action = action::EXTEND;
+                (*ret).params.extend.length_us = length.as_micros() as u32;
+            }
+            Action::Request(request) => {
+                *NEXT_REQUEST.0.get() = request.to_raw();
+                (*ret).callback_action = action::REQUEST;
+                (*ret).params.request.p_next = NEXT_REQUEST.0.get();
+            }
+        }
+    }
+    ret
+}
+
+/// An open timeslot session, closed when dropped.
+pub struct Timeslot {
+    session_id: u8,
+}
+
+impl Timeslot {
+    /// Open the session with `handler`. The MPSL has to run already.
+    pub fn open(
+        _mpsl: &MultiprotocolServiceLayer<'_>,
+        handler: &'static dyn TimeslotHandler,
+    ) -> Result<Self, TimeslotError> {
+        if OPEN.swap(true, Ordering::AcqRel) {
+            return Err(TimeslotError::Busy);
+        }
+        HANDLER.lock(|h| h.set(Some(handler)));
+        let mut session_id = 0;
+        // SAFETY: the memory is static and used by this one session only,
+        // the callback has the signature the MPSL expects.
+        let result = unsafe {
+            check(raw::mpsl_timeslot_session_count_set(
+                SESSION_MEMORY.0.get().cast(),
+                1,
+            ))
+            .and_then(|()| {
+                check(raw::mpsl_timeslot_session_open(
+                    Some(callback),
+                    &mut session_id,
+                ))
+            })
+        };
+        if let Err(e) = result {
+            HANDLER.lock(|h| h.set(None));
+            OPEN.store(false, Ordering::Release);
+            return Err(e);
+        }
+        crate::log!(Ble, info, "[timeslot] session {} open", session_id);
+        Ok(Self { session_id })
+    }
+
+    /// Request the first slot of the session, or the next one after a
+    /// [`SessionEvent`].
+    pub fn request(&self, request: Request) -> Result<(), TimeslotError> {
+        let request = request.to_raw();
+        // SAFETY: the MPSL copies the request.
+        check(unsafe { raw::mpsl_timeslot_request(self.session_id, &request) })
+    }
+
+    /// Wait for the next event outside of slots.
+    pub async fn event(&self) -> SessionEvent {
+        EVENTS.wait().await
+    }
+
+    /// Request slots forever, starting with `first`.
+    ///
+    /// The MPSL drops requests that collide with BLE activity, `first` is
+    /// requested again after every [`SessionEvent`].
+    pub async fn keep_requesting(&self, first: Request) -> ! {
+        loop {
+            if let Err(e) = self.request(first) {
+                crate::log!(Ble, warn, "[timeslot] request failed: {:?}", e);
+            }
+            let event = self.event().await;
+            crate::log!(Ble, debug, "[timeslot] session {:?}", event);
+        }
+    }
+}
+
+impl Drop for Timeslot {
+    fn drop(&mut self) {
+        // SAFETY: the session is open, the handler stays valid until the
+        // MPSL confirmed closing it with the last callback.
+        if let Err(e) = check(unsafe { raw::mpsl_timeslot_session_close(self.session_id) }) {
+            crate::log!(Ble, warn, "[timeslot] couldn't close session: {:?}", e);
+        }
+        HANDLER.lock(|h| h.set(None));
+        OPEN.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,93 @@
+//! Brown-out protection of the flash.
+//!
+//! An erase or write cut short by a collapsing battery leaves a corrupted
+//! page behind. [`init`] enables the power-fail comparator (POF), which
+//! warns when the supply drops below [`THRESHOLD_MV`], well above the
+//! brown-out reset. [`watch`] polls the warning, the POWER interrupt belongs
+//! to the MPSL, and on a warning:
+//! - stops the recording [track log](crate::track) session, so its summary
+//!   is written while a short write still completes,
+//! - then marks the power as failing, from when on the
+//!   [`CountingFlash`](super::flash::CountingFlash) refuses erases and writes
+//!   with [`FlashError::PowerFailing`](super::flash::FlashError::PowerFailing).
+//!
+//! The power is good again once the battery measures [`RECOVER_MV`].
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use defmt::{info, warn};
+use embassy_futures::select::{Either, select};
+use embassy_nrf::pac::{self, power::vals::Threshold};
+use embassy_time::{Duration, Ticker};
+
+use crate::events::{self, Event};
+use crate::track::{self, TrackError};
+
+/// Supply voltage below which the POF warns.
+pub const THRESHOLD_MV: u16 = 2800;
+
+/// Battery voltage at which flash writes are allowed again.
+pub const RECOVER_MV: u16 = 3500;
+
+/// Interval in which the POF warning is polled, a page erase takes 85 ms.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Set from a POF warning until the battery recovered.
+static FAILING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the supply is too low for flash erases and writes.
+pub fn power_failing() -> bool {
+    FAILING.load(Ordering::Relaxed)
+}
+
+/// Enable the power-fail comparator at [`THRESHOLD_MV`].
+pub fn init() {
+    pac::POWER.events_pofwarn().write_value(0);
+    pac::POWER.pofcon().write(|w| {
+        w.set_pof(true);
+        w.set_threshold(Threshold::V28);
+    });
+}
+
+fn pof_warning() -> bool {
+    pac::POWER.events_pofwarn().read() != 0
+}
+
+/// Close the track log and block the flash on a POF warning, unblock it
+/// once the battery recovered.
+pub async fn watch() {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[brownout] no event bus subscriber available");
+        return;
+    };
+    let mut ticker = Ticker::every(POLL_INTERVAL);
+    loop {
+        match select(events.next_message_pure(), ticker.next()).await {
+            Either::First(Event::BatteryUpdated { millivolts, .. })
+                if power_failing() && millivolts >= RECOVER_MV =>
+            {
+                info!(
+                    "[brownout] battery at {} mV, flash writes allowed",
+                    millivolts
+                );
+                pac::POWER.events_pofwarn().write_value(0);
+                FAILING.store(false, Ordering::Relaxed);
+            }
+            Either::First(_) => {}
+            Either::Second(_) => {
+                if power_failing() || !pof_warning() {
+                    continue;
+                }
+                warn!(
+                    "[brownout] supply below {} mV, blocking flash writes",
+                    THRESHOLD_MV
+                );
+                match track::stop().await {
+                    Ok(_) | Err(TrackError::NotRecording | TrackError::Unavailable) => {}
+                    Err(e) => warn!("[brownout] couldn't stop the track log session: {:?}", e),
+                }
+                FAILING.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! User button.
+//!
+//! The [`button_task`] owns the GPIO, debounces it and publishes every press
+//! as [`Event::ButtonPressed`], classified by how long it was held. Short
+//! presses are published after [`DOUBLE_PRESS`], when it's clear they
+//! aren't a [`ButtonPress::Double`].
+
+use embassy_futures::select::{Either, select};
+use embassy_nrf::Peri;
+use embassy_nrf::gpio::{AnyPin, Input, Pull};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::events::{self, ButtonPress, Event};
+
+/// Presses shorter than this are ignored as bounces.
+const DEBOUNCE: Duration = Duration::from_millis(20);
+
+/// A second short press within this after a short press makes them a
+/// [`ButtonPress::Double`].
+pub const DOUBLE_PRESS: Duration = Duration::from_millis(300);
+
+/// Presses at least this long are [`ButtonPress::Long`].
+pub const LONG_PRESS: Duration = Duration::from_secs(1);
+
+/// Presses at least this long are [`ButtonPress::Hold`], published while
+/// the button is still pressed.
+pub const HOLD: Duration = Duration::from_secs(10);
+
+/// Wait up to [`DOUBLE_PRESS`] for another short press.
+async fn pressed_again(button: &mut Input<'_>) -> bool {
+    Timer::after(DEBOUNCE).await;
+    if let Either::Second(_) = select(button.wait_for_low(), Timer::after(DOUBLE_PRESS)).await {
+        return false;
+    }
+    Timer::after(DEBOUNCE).await;
+    button.wait_for_high().await;
+    true
+}
+
+/// Watch the button on `pin` (active low, internal pull-up).
+#[embassy_executor::task]
+pub async fn button_task(pin: Peri<'static, AnyPin>) {
+    let mut button = Input::new(pin, Pull::Up);
+    loop {
+        button.wait_for_low().await;
+        let pressed = Instant::now();
+        Timer::after(DEBOUNCE).await;
+        if button.is_high() {
+            continue;
+        }
+        let press = match select(button.wait_for_high(), Timer::after(HOLD - DEBOUNCE)).await {
+            Either::First(_) if pressed.elapsed() < LONG_PRESS => ButtonPress::Short,
+            Either::First(_) => ButtonPress::Long,
+            Either::Second(_) => ButtonPress::Hold,
+        };
+        let press = if press == ButtonPress::Short && pressed_again(&mut button).await {
+            ButtonPress::Double
+        } else {
+            press
+        };
+        events::publish(Event::ButtonPressed(press));
+        if press == ButtonPress::Hold {
+            button.wait_for_high().await;
+        }
+        Timer::after(DEBOUNCE).await;
+    }
+}
@@ -0,0 +1,147 @@
+//! Piezo buzzer playing alert melodies.
+//!
+//! The [`buzzer_task`] owns PWM1 and the buzzer pin. It plays a [`Melody`]
+//! requested with [`play`], which returns immediately, and the alert
+//! melodies of the events enabled in the
+//! [`buzzer_alerts`](crate::settings::Settings::buzzer_alerts) setting.
+//! A new melody interrupts the one playing.
+
+use defmt::warn;
+use embassy_futures::select::{Either, select};
+use embassy_nrf::Peri;
+use embassy_nrf::gpio::AnyPin;
+use embassy_nrf::peripherals::PWM1;
+use embassy_nrf::pwm::{DutyCycle, SimpleConfig, SimplePwm};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+
+use crate::events::{self, Event};
+use crate::settings;
+
+/// Bits of the [`buzzer_alerts`](crate::settings::Settings::buzzer_alerts)
+/// setting.
+pub mod alerts {
+    /// The position left the geofence.
+    pub const FENCE: u16 = 1 << 0;
+    /// The battery is low.
+    pub const BATTERY: u16 = 1 << 1;
+    /// The central disconnected.
+    pub const CONNECTION: u16 = 1 << 2;
+    /// The phone notified a new alert.
+    pub const NOTIFICATION: u16 = 1 << 3;
+    /// All alerts.
+    pub const ALL: u16 = FENCE | BATTERY | CONNECTION | NOTIFICATION;
+}
+
+/// Short tone sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Melody {
+    /// Single short beep, e.g. to acknowledge an input.
+    Beep,
+    /// Three rising tones, repeated: the position left the geofence.
+    FenceExited,
+    /// Two falling tones: the battery is low.
+    BatteryLow,
+    /// Two short low beeps: the central disconnected.
+    Disconnected,
+    /// Two beeps: a mild [alert](crate::alert).
+    AlertMild,
+    /// Loud alternating tones: a high [alert](crate::alert), repeated.
+    AlertHigh,
+    /// Two quick high tones: a phone notification.
+    Chirp,
+    /// Nothing, stops the melody playing.
+    Silence,
+}
+
+impl Melody {
+    /// Sequence of (frequency in Hz, duration in ms) notes, 0 Hz is a rest.
+    fn notes(&self) -> &'static [(u32, u64)] {
+        match self {
+            Melody::Beep => &[(2700, 60)],
+            Melody::FenceExited => &[
+                (2000, 120),
+                (2500, 120),
+                (3000, 120),
+                (0, 200),
+                (2000, 120),
+                (2500, 120),
+                (3000, 240),
+            ],
+            Melody::BatteryLow => &[(2200, 250), (0, 50), (1600, 400)],
+            Melody::Disconnected => &[(1200, 80), (0, 80), (1200, 80)],
+            Melody::AlertMild => &[(2700, 150), (0, 150), (2700, 150)],
+            Melody::AlertHigh => &[
+                (3000, 200),
+                (2300, 200),
+                (3000, 200),
+                (2300, 200),
+                (3000, 200),
+                (2300, 200),
+            ],
+            Melody::Chirp => &[(3200, 40), (0, 30), (3800, 60)],
+            Melody::Silence => &[],
+        }
+    }
+
+    /// Alert melody of `event`, if its alert is enabled in `alerts`.
+    fn for_event(event: &Event, enabled: u16) -> Option<Self> {
+        let (melody, alert) = match event {
+            Event::FenceExited => (Melody::FenceExited, alerts::FENCE),
+            Event::BatteryLow { .. } => (Melody::BatteryLow, alerts::BATTERY),
+            Event::CentralDisconnected => (Melody::Disconnected, alerts::CONNECTION),
+            Event::PhoneNotification(_) => (Melody::Chirp, alerts::NOTIFICATION),
+            _ => return None,
+        };
+        (enabled & alert != 0).then_some(melody)
+    }
+}
+
+static MELODY: Signal<CriticalSectionRawMutex, Melody> = Signal::new();
+
+/// Play `melody` on the [`buzzer_task`] without waiting for it.
+pub fn play(melody: Melody) {
+    MELODY.signal(melody);
+}
+
+/// Drive the piezo buzzer on `pin` with PWM1.
+#[embassy_executor::task]
+pub async fn buzzer_task(pwm: Peri<'static, PWM1>, pin: Peri<'static, AnyPin>) {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[buzzer] no event bus subscriber available");
+        return;
+    };
+    let mut pwm = SimplePwm::new_1ch(pwm, pin, &SimpleConfig::default());
+    pwm.disable();
+    let mut next = None;
+    loop {
+        let melody = match next.take() {
+            Some(melody) => melody,
+            None => match select(MELODY.wait(), events.next_message_pure()).await {
+                Either::First(melody) => melody,
+                Either::Second(event) => {
+                    match Melody::for_event(&event, settings::get().buzzer_alerts) {
+                        Some(melody) => melody,
+                        None => continue,
+                    }
+                }
+            },
+        };
+        for (hz, ms) in melody.notes() {
+            if *hz == 0 {
+                pwm.disable();
+            } else {
+                pwm.enable();
+                pwm.set_period(*hz);
+                pwm.set_duty(0, DutyCycle::normal(pwm.max_duty() / 2));
+            }
+            if let Either::Second(melody) =
+                select(Timer::after(Duration::from_millis(*ms)), MELODY.wait()).await
+            {
+                next = Some(melody);
+                break;
+            }
+        }
+        pwm.disable();
+    }
+}
@@ -0,0 +1,161 @@
+//! Clock and regulator configuration of the board.
+//!
+//! The low frequency clock is started by `embassy_nrf::init` for the time
+//! driver and later taken over by the MPSL, so both have to agree on its
+//! source. [`BoardConfig`] describes the clocks and regulators once and
+//! derives the `embassy-nrf` configuration and the MPSL clock configuration
+//! from it, after checking it against the requirements of the MPSL.
+
+#[cfg(not(feature = "chip-nrf52832"))]
+pub use embassy_nrf::config::Reg0Voltage;
+use embassy_nrf::config::{Config, DcdcConfig, HfclkSource, LfclkSource};
+use nrf_sdc::mpsl::raw as mpsl;
+
+/// Worst sleep clock accuracy allowed by the Bluetooth specification.
+const MAX_ACCURACY_PPM: u16 = 500;
+
+/// Source of the 32.768 kHz low frequency clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LfClock {
+    /// External crystal with the given accuracy in ppm.
+    Xtal { accuracy_ppm: u16 },
+    /// Internal RC oscillator, calibrated periodically by the MPSL.
+    /// Costs some current for the calibration and wider receive windows.
+    Rc,
+    /// Synthesized from the high frequency clock. Not supported together
+    /// with the MPSL, which stops the high frequency crystal when idle.
+    Synthesized,
+}
+
+/// Errors of an invalid [`BoardConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ConfigError {
+    /// The MPSL can't run from the synthesized low frequency clock.
+    SynthesizedLfClock,
+    /// The crystal accuracy is worse than the Bluetooth specification allows.
+    LfAccuracy(u16),
+}
+
+/// Clocks and regulators of the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardConfig {
+    pub lf_clock: LfClock,
+    /// Start the 64 MHz crystal at boot and keep it running, instead of
+    /// letting the MPSL start it only for radio activity. Needed for
+    /// accurate timers or high UARTE baud rates, costs about 250 µA.
+    pub hf_xtal: bool,
+    /// DC/DC converter of REG0, only used when supplied through VDDH.
+    #[cfg(not(feature = "chip-nrf52832"))]
+    pub dcdc_reg0: bool,
+    /// Output voltage of REG0 (VDD when supplied through VDDH), written to
+    /// UICR if different, which takes effect after a reset.
+    #[cfg(not(feature = "chip-nrf52832"))]
+    pub reg0_voltage: Option<Reg0Voltage>,
+    /// DC/DC converter of REG1, needs the external inductor.
+    pub dcdc_reg1: bool,
+}
+
+impl Default for BoardConfig {
+    /// Works on any board: RC oscillator, no DC/DC converter.
+    fn default() -> Self {
+        Self {
+            lf_clock: LfClock::Rc,
+            hf_xtal: false,
+            #[cfg(not(feature = "chip-nrf52832"))]
+            dcdc_reg0: false,
+            #[cfg(not(feature = "chip-nrf52832"))]
+            reg0_voltage: None,
+            dcdc_reg1: false,
+        }
+    }
+}
+
+impl BoardConfig {
+    /// Adafruit Feather nRF52840: 32 kHz crystal, DC/DC inductor on REG1,
+    /// supplied with 3.3 V on VDD.
+    pub const ADAFRUIT_FEATHER: Self = Self {
+        lf_clock: LfClock::Xtal { accuracy_ppm: 20 },
+        hf_xtal: false,
+        #[cfg(not(feature = "chip-nrf52832"))]
+        dcdc_reg0: false,
+        #[cfg(not(feature = "chip-nrf52832"))]
+        reg0_voltage: None,
+        dcdc_reg1: true,
+    };
+
+    /// Seeed Wio Tracker L1: 32 kHz crystal. The DC/DC converter of REG1
+    /// stays off until the inductor is confirmed on the schematic, without
+    /// it the chip browns out.
+    // TODO: Verify the crystal in the Wio Tracker L1 schematic.
+    pub const WIO_TRACKER_L1: Self = Self {
+        lf_clock: LfClock::Xtal { accuracy_ppm: 20 },
+        hf_xtal: false,
+        #[cfg(not(feature = "chip-nrf52832"))]
+        dcdc_reg0: false,
+        #[cfg(not(feature = "chip-nrf52832"))]
+        reg0_voltage: None,
+        dcdc_reg1: false,
+    };
+
+    /// Check the configuration against the requirements of the MPSL.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self.lf_clock {
+            LfClock::Synthesized => return Err(ConfigError::SynthesizedLfClock),
+            LfClock::Xtal { accuracy_ppm } if accuracy_ppm > MAX_ACCURACY_PPM => {
+                return Err(ConfigError::LfAccuracy(accuracy_ppm));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Configuration of `embassy_nrf::init`.
+    pub fn embassy_config(&self) -> Config {
+        let mut config = Config::default();
+        config.hfclk_source = if self.hf_xtal {
+            HfclkSource::ExternalXtal
+        } else {
+            HfclkSource::Internal
+        };
+        config.lfclk_source = match self.lf_clock {
+            LfClock::Xtal { .. } => LfclkSource::ExternalXtal,
+            LfClock::Rc => LfclkSource::InternalRC,
+            LfClock::Synthesized => LfclkSource::Synthesized,
+        };
+        config.dcdc = DcdcConfig {
+            #[cfg(not(feature = "chip-nrf52832"))]
+            reg0: self.dcdc_reg0,
+            #[cfg(not(feature = "chip-nrf52832"))]
+            reg0_voltage: self.reg0_voltage,
+            reg1: self.dcdc_reg1,
+        };
+        config.debug = super::approtect::debug();
+        config
+    }
+
+    /// Low frequency clock configuration of the MPSL.
+    pub fn lf_clock_config(&self) -> mpsl::mpsl_clock_lfclk_cfg_t {
+        let (source, rc_ctiv, rc_temp_ctiv, accuracy_ppm) = match self.lf_clock {
+            LfClock::Xtal { accuracy_ppm } => (mpsl::MPSL_CLOCK_LF_SRC_XTAL, 0, 0, accuracy_ppm),
+            LfClock::Rc => (
+                mpsl::MPSL_CLOCK_LF_SRC_RC,
+                mpsl::MPSL_RECOMMENDED_RC_CTIV as u8,
+                mpsl::MPSL_RECOMMENDED_RC_TEMP_CTIV as u8,
+                mpsl::MPSL_DEFAULT_CLOCK_ACCURACY_PPM as u16,
+            ),
+            LfClock::Synthesized => (
+                mpsl::MPSL_CLOCK_LF_SRC_SYNTH,
+                0,
+                0,
+                mpsl::MPSL_DEFAULT_CLOCK_ACCURACY_PPM as u16,
+            ),
+        };
+        mpsl::mpsl_clock_lfclk_cfg_t {
+            source: source as u8,
+            rc_ctiv,
+            rc_temp_ctiv,
+            accuracy_ppm,
+            skip_wait_lfclk_started: mpsl::MPSL_DEFAULT_SKIP_WAIT_LFCLK_STARTED != 0,
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Flash layout for application data and sharing of the NVMC.
+//!
+//! The data regions sit between the firmware and the bootloader (see
+//! `memory/*.x`), which starts at [`BOOTLOADER_START`]. Every user gets a
+//! [`Partition`] of the shared flash, with addresses relative to the start
+//! of its [`Region`]. The erases of the data pages are counted by the
+//! [`maintenance`](crate::maintenance) task.
+//! While the [supply fails](super::brownout), erases and writes are refused.
+//! The UICR is written word by word with [`write_uicr`].
+//!
+//! With the radio running, the flash is programmed through the MPSL
+//! ([`init`]), which schedules every erase and write in a timeslot between
+//! radio events instead of halting the CPU in the middle of one. The users
+//! share it behind an async mutex, a task waiting for the flash doesn't
+//! block the others. Firmware without the MPSL takes the NVMC with
+//! [`init_nvmc`].
+
+use embassy_embedded_hal::{adapter::BlockingAsync, flash::partition};
+use embassy_nrf::{
+    Peri,
+    nvmc::Nvmc,
+    pac::{self, nvmc::vals::Wen},
+    peripherals::NVMC,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use embedded_storage_async::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use nrf_mpsl::MultiprotocolServiceLayer;
+use static_cell::StaticCell;
+
+/// Size of a flash page of the nRF52840.
+pub const PAGE_SIZE: u32 = 4096;
+
+/// A range of whole flash pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Region {
+    /// Absolute address of the first page.
+    pub start: u32,
+    /// Number of pages.
+    pub pages: u32,
+}
+
+impl Region {
+    /// Size in bytes.
+    pub const fn size(&self) -> u32 {
+        self.pages * PAGE_SIZE
+    }
+
+    /// Address after the last page.
+    pub const fn end(&self) -> u32 {
+        self.start + self.size()
+    }
+
+    /// Access the region of the shared flash.
+    pub fn partition<F: NorFlash>(&self, flash: &'static SharedFlash<F>) -> Partition<F> {
+        partition::Partition::new(flash, self.start, self.size())
+    }
+}
+
+/// Start of the Adafruit bootloader.
+#[cfg(feature = "chip-nrf52840")]
+pub const BOOTLOADER_START: u32 = 0xF4000;
+/// Start of the Adafruit bootloader.
+#[cfg(any(feature = "chip-nrf52833", feature = "chip-nrf52832"))]
+pub const BOOTLOADER_START: u32 = 0x74000;
+
+/// Pages of the track log.
+#[cfg(feature = "chip-nrf52840")]
+const TRACK_LOG_PAGES: u32 = 62;
+/// Pages of the track log.
+#[cfg(any(feature = "chip-nrf52833", feature = "chip-nrf52832"))]
+const TRACK_LOG_PAGES: u32 = 14;
+
+/// Sessions of the track log, the first application data.
+pub const TRACK_LOG: Region = Region {
+    start: REPLAY.start - TRACK_LOG_PAGES * PAGE_SIZE,
+    pages: TRACK_LOG_PAGES,
+};
+
+/// Sequence number of the last signed update, see
+/// [`replay`](crate::replay). Kept by a factory reset.
+pub const REPLAY: Region = Region {
+    start: WEAR.start - PAGE_SIZE,
+    pages: 1,
+};
+
+/// Erase counters of the data pages.
+pub const WEAR: Region = Region {
+    start: CRASH_DUMP.start - PAGE_SIZE,
+    pages: 1,
+};
+
+/// Dump of the last crash, written by the HardFault handler.
+pub const CRASH_DUMP: Region = Region {
+    start: BOOTLOADER_START - 6 * PAGE_SIZE,
+    pages: 1,
+};
+
+/// Telemetry samples queued while no central is connected.
+pub const TELEMETRY: Region = Region {
+    start: BOOTLOADER_START - 5 * PAGE_SIZE,
+    pages: 4,
+};
+
+/// Device settings, the last page below the Adafruit bootloader.
+pub const SETTINGS: Region = Region {
+    start: TELEMETRY.end(),
+    pages: 1,
+};
+
+/// All data regions, from the track log up to the bootloader.
+pub const DATA: Region = Region {
+    start: TRACK_LOG.start,
+    pages: (BOOTLOADER_START - TRACK_LOG.start) / PAGE_SIZE,
+};
+
+/// Number of pages of [`DATA`].
+pub const DATA_PAGES: usize = DATA.pages as usize;
+
+/// Errors of the [`CountingFlash`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashError {
+    /// The flash refused the operation.
+    Flash(NorFlashErrorKind),
+    /// The supply is too low to complete an erase or write, see
+    /// [`brownout`](super::brownout).
+    PowerFailing,
+}
+
+impl NorFlashError for FlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::Flash(kind) => *kind,
+            Self::PowerFailing => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+impl defmt::Format for FlashError {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::Flash(kind) => defmt::write!(f, "Flash({})", defmt::Debug2Format(kind)),
+            Self::PowerFailing => defmt::write!(f, "PowerFailing"),
+        }
+    }
+}
+
+/// Keep the kind of an error of the underlying flash.
+fn flash_error<E: NorFlashError>(e: E) -> FlashError {
+    FlashError::Flash(e.kind())
+}
+
+/// Refuse erases and writes while the supply fails.
+fn check_power() -> Result<(), FlashError> {
+    if super::brownout::power_failing() {
+        return Err(FlashError::PowerFailing);
+    }
+    Ok(())
+}
+
+/// A flash counting the erases of the [`DATA`] pages.
+pub struct CountingFlash<F>(F);
+
+impl<F: NorFlash> ErrorType for CountingFlash<F> {
+    type Error = FlashError;
+}
+
+impl<F: NorFlash> ReadNorFlash for CountingFlash<F> {
+    const READ_SIZE: usize = F::READ_SIZE;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read(offset, bytes).await.map_err(flash_error)
+    }
+
+    fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+}
+
+impl<F: NorFlash> NorFlash for CountingFlash<F> {
+    const WRITE_SIZE: usize = F::WRITE_SIZE;
+    const ERASE_SIZE: usize = F::ERASE_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_power()?;
+        self.0.erase(from, to).await.map_err(flash_error)?;
+        for page in (from..to).step_by(PAGE_SIZE as usize) {
+            crate::maintenance::count_erase(page);
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_power()?;
+        self.0.write(offset, bytes).await.map_err(flash_error)
+    }
+}
+
+/// The flash programmed through the MPSL.
+pub type MpslFlash = nrf_mpsl::Flash<'static>;
+
+/// The NVMC, halting the CPU while erasing or writing.
+pub type NvmcFlash = BlockingAsync<Nvmc<'static>>;
+
+/// The flash shared by all flash users.
+pub type SharedFlash<F = MpslFlash> = Mutex<CriticalSectionRawMutex, CountingFlash<F>>;
+
+/// Part of the [`SharedFlash`].
+pub type Partition<F = MpslFlash> =
+    partition::Partition<'static, CriticalSectionRawMutex, CountingFlash<F>>;
+
+/// Take the flash for sharing between the flash users, programmed through
+/// `mpsl`. Erases and writes only complete while `mpsl` runs.
+pub fn init(
+    mpsl: &'static MultiprotocolServiceLayer<'static>,
+    nvmc: Peri<'static, NVMC>,
+) -> &'static SharedFlash {
+    static FLASH: StaticCell<SharedFlash> = StaticCell::new();
+    FLASH.init(Mutex::new(CountingFlash(nrf_mpsl::Flash::take(mpsl, nvmc))))
+}
+
+/// Take the NVMC for sharing between the flash users, for firmware without
+/// the MPSL, e.g. with USB taking the clock interrupt.
+pub fn init_nvmc(nvmc: Peri<'static, NVMC>) -> &'static SharedFlash<NvmcFlash> {
+    static FLASH: StaticCell<SharedFlash<NvmcFlash>> = StaticCell::new();
+    let nvmc = BlockingAsync::new(Nvmc::new(nvmc));
+    FLASH.init(Mutex::new(CountingFlash(nvmc)))
+}
+
+/// Write `bytes` (whole words) to erased UICR words from `addr` on. Bits can
+/// only be cleared, the UICR is erased only together with the whole flash.
+/// Configuration registers take effect at the next reset.
+///
+/// Panics if `bytes` isn't a whole number of words.
+pub fn write_uicr(addr: u32, bytes: &[u8]) {
+    assert!(bytes.len().is_multiple_of(4), "UICR writes are whole words");
+    let nvmc = pac::NVMC;
+    let wait_ready = || while !nvmc.ready().read().ready() {};
+    cortex_m::interrupt::free(|_| {
+        wait_ready();
+        nvmc.config().write(|w| w.set_wen(Wen::WEN));
+        for (i, word) in bytes.chunks_exact(4).enumerate() {
+            let addr = (addr + 4 * i as u32) as *mut u32;
+            let word = u32::from_le_bytes(word.try_into().unwrap());
+            // SAFETY: The UICR is always mapped and the callers own the words.
+            unsafe { core::ptr::write_volatile(addr, word) };
+            wait_ready();
+        }
+        nvmc.config().write(|w| w.set_wen(Wen::REN));
+    });
+}
@@ -0,0 +1,58 @@
+//! Sharing of the TWIM between the devices on the I2C bus.
+//!
+//! The display and the sensors each get an [`I2cDevice`] of the
+//! [`SharedI2c`], transfers are serialized by its mutex. The register
+//! helpers are shared by the sensor drivers.
+
+use core::cell::RefCell;
+
+use embassy_embedded_hal::shared_bus::blocking::i2c;
+use embassy_nrf::twim::Twim;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embedded_hal::i2c::I2c;
+use static_cell::StaticCell;
+
+/// The TWIM shared by all devices on the bus.
+pub type SharedI2c = Mutex<CriticalSectionRawMutex, RefCell<Twim<'static>>>;
+
+/// A device on the [`SharedI2c`].
+pub type I2cDevice = i2c::I2cDevice<'static, CriticalSectionRawMutex, Twim<'static>>;
+
+/// Take the TWIM for sharing between the devices.
+pub fn init(twim: Twim<'static>) -> &'static SharedI2c {
+    static I2C: StaticCell<SharedI2c> = StaticCell::new();
+    I2C.init(Mutex::new(RefCell::new(twim)))
+}
+
+/// Access the shared bus.
+pub fn device(bus: &'static SharedI2c) -> I2cDevice {
+    I2cDevice::new(bus)
+}
+
+/// Read consecutive registers of the device at `address`, starting at
+/// `register`.
+pub fn read_registers<I: I2c>(
+    i2c: &mut I,
+    address: u8,
+    register: u8,
+    buf: &mut [u8],
+) -> Result<(), I::Error> {
+    i2c.write_read(address, &[register], buf)
+}
+
+/// Read a single register.
+pub fn read_register<I: I2c>(i2c: &mut I, address: u8, register: u8) -> Result<u8, I::Error> {
+    let mut value = [0];
+    read_registers(i2c, address, register, &mut value)?;
+    Ok(value[0])
+}
+
+/// Write a single register.
+pub fn write_register<I: I2c>(
+    i2c: &mut I,
+    address: u8,
+    register: u8,
+    value: u8,
+) -> Result<(), I::Error> {
+    i2c.write(address, &[register, value])
+}
@@ -0,0 +1,80 @@
+//! Scanning the I2C bus and detecting the display.
+//!
+//! A device is present if it acknowledges its address for a one byte read.
+//! Depending on the module, the OLED controller answers at 0x3C or 0x3D
+//! (selected by a resistor), so the address is detected instead of hardcoded.
+
+use defmt::{Debug2Format, info, warn};
+use embedded_hal::i2c::{Error, ErrorKind, I2c};
+
+/// Possible I2C addresses of the SSD1306 and SH1106 OLED controllers.
+pub const OLED_ADDRESSES: [u8; 2] = [0x3C, 0x3D];
+
+/// Addresses that aren't reserved by the I2C specification.
+const ADDRESSES: core::ops::Range<u8> = 0x08..0x78;
+
+/// Errors of probing the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ProbeError {
+    /// No device acknowledged any of the probed addresses.
+    NotFound,
+    /// The bus failed, e.g. a stuck line or arbitration loss.
+    Bus,
+}
+
+/// Set of addresses acknowledged on the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Devices(u128);
+
+impl Devices {
+    pub fn contains(&self, address: u8) -> bool {
+        address < 128 && self.0 & (1 << address) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The acknowledged addresses in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u8> {
+        (0..128).filter(|&a| self.contains(a))
+    }
+}
+
+/// Whether a device acknowledges `address`.
+pub fn probe<I: I2c>(i2c: &mut I, address: u8) -> Result<bool, ProbeError> {
+    match i2c.read(address, &mut [0]) {
+        Ok(()) => Ok(true),
+        Err(e) if matches!(e.kind(), ErrorKind::NoAcknowledge(_)) => Ok(false),
+        Err(e) => {
+            warn!(
+                "[i2c] error probing {:#04x}: {:?}",
+                address,
+                Debug2Format(&e)
+            );
+            Err(ProbeError::Bus)
+        }
+    }
+}
+
+/// Probe all non-reserved addresses.
+pub fn scan<I: I2c>(i2c: &mut I) -> Result<Devices, ProbeError> {
+    let mut devices = Devices::default();
+    for address in ADDRESSES {
+        if probe(i2c, address)? {
+            info!("[i2c] device at {:#04x}", address);
+            devices.0 |= 1 << address;
+        }
+    }
+    Ok(devices)
+}
+
+/// Find the address of the OLED controller.
+pub fn find_oled<I: I2c>(i2c: &mut I) -> Result<u8, ProbeError> {
+    for address in OLED_ADDRESSES {
+        if probe(i2c, address)? {
+            return Ok(address);
+        }
+    }
+    Err(ProbeError::NotFound)
+}
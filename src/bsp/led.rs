@@ -0,0 +1,143 @@
+//! Status LED manager.
+//!
+//! Tasks set the current [`LedPattern`] via [`set_pattern`], the
+//! [`led_task`] owns the GPIO and plays the pattern in a loop. The
+//! [NeoPixel](crate::bsp::neopixel) follows the same pattern in the color
+//! of the device state. After a fatal error, when tasks don't run anymore,
+//! [`play_blocking`] plays a pattern by busy waiting.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use defmt::warn;
+use embassy_futures::select::{Either, select};
+use embassy_nrf::Peri;
+use embassy_nrf::gpio::{AnyPin, Level, Output, OutputDrive, Pin as _, Port};
+use embassy_nrf::pac;
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    watch::{Receiver, Watch},
+};
+use embassy_time::{Duration, Timer};
+
+/// Blink pattern of the status LED.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LedPattern {
+    /// LED off.
+    Off,
+    /// LED permanently on.
+    On,
+    /// Short flash every two seconds, everything is fine.
+    Heartbeat,
+    /// Fast blinking, e.g. while a subsystem recovers from an error.
+    Recovering,
+    /// Double flashes, while an [alert](crate::alert) is raised.
+    Alert,
+    /// Three short, three long, three short flashes: unrecoverable failure.
+    Failure,
+}
+
+impl LedPattern {
+    /// Sequence of (LED on, duration) steps of this pattern.
+    pub(crate) fn steps(&self) -> &'static [(bool, u64)] {
+        match self {
+            LedPattern::Off => &[(false, 1000)],
+            LedPattern::On => &[(true, 1000)],
+            LedPattern::Heartbeat => &[(true, 50), (false, 1950)],
+            LedPattern::Recovering => &[(true, 100), (false, 100)],
+            LedPattern::Alert => &[(true, 80), (false, 80), (true, 80), (false, 360)],
+            LedPattern::Failure => &[
+                (true, 150),
+                (false, 150),
+                (true, 150),
+                (false, 150),
+                (true, 150),
+                (false, 450),
+                (true, 450),
+                (false, 150),
+                (true, 450),
+                (false, 150),
+                (true, 450),
+                (false, 450),
+                (true, 150),
+                (false, 150),
+                (true, 150),
+                (false, 150),
+                (true, 150),
+                (false, 1500),
+            ],
+        }
+    }
+}
+
+/// Number of tasks following the pattern: the LED and the NeoPixel.
+const RECEIVERS: usize = 2;
+
+static PATTERN: Watch<CriticalSectionRawMutex, LedPattern, RECEIVERS> = Watch::new();
+
+/// Receiver of the current pattern.
+pub(crate) type PatternReceiver = Receiver<'static, CriticalSectionRawMutex, LedPattern, RECEIVERS>;
+
+/// Change the pattern played by the [`led_task`].
+pub fn set_pattern(pattern: LedPattern) {
+    PATTERN.sender().send(pattern);
+}
+
+/// The pattern currently played, [`LedPattern::Heartbeat`] before any was set.
+pub fn pattern() -> LedPattern {
+    PATTERN.try_get().unwrap_or(LedPattern::Heartbeat)
+}
+
+/// Follow the pattern, `None` if there are already [`RECEIVERS`].
+pub(crate) fn pattern_receiver() -> Option<PatternReceiver> {
+    PATTERN.receiver()
+}
+
+/// Port (bit 5) and pin number of the status LED, [`NO_PIN`] before the
+/// [`led_task`] started.
+static PIN: AtomicU8 = AtomicU8::new(NO_PIN);
+
+const NO_PIN: u8 = 0xFF;
+
+/// Core clock cycles per millisecond.
+const CYCLES_PER_MS: u32 = 64_000;
+
+/// Play `pattern` once on the status LED by busy waiting, or just wait if
+/// there is no LED.
+pub fn play_blocking(pattern: LedPattern) {
+    let pin = PIN.load(Ordering::Relaxed);
+    let port = if pin & 0x20 == 0 { pac::P0 } else { pac::P1 };
+    for (on, ms) in pattern.steps() {
+        if pin != NO_PIN {
+            port.out().modify(|w| w.set_pin((pin & 0x1F) as usize, *on));
+        }
+        cortex_m::asm::delay(*ms as u32 * CYCLES_PER_MS);
+    }
+}
+
+/// Drive the status LED on `pin` (active high).
+#[embassy_executor::task]
+pub async fn led_task(pin: Peri<'static, AnyPin>) {
+    let port = match pin.port() {
+        Port::Port0 => 0,
+        Port::Port1 => 0x20,
+    };
+    PIN.store(port | pin.pin(), Ordering::Relaxed);
+    let mut led = Output::new(pin, Level::Low, OutputDrive::Standard);
+    let Some(mut receiver) = pattern_receiver() else {
+        warn!("[led] no pattern receiver available");
+        return;
+    };
+    let mut pattern = receiver.try_get().unwrap_or(LedPattern::Heartbeat);
+    loop {
+        for (on, ms) in pattern.steps() {
+            led.set_level(Level::from(*on));
+            match select(Timer::after(Duration::from_millis(*ms)), receiver.changed()).await {
+                Either::First(_) => {}
+                Either::Second(new) => {
+                    pattern = new;
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Link quality reporting based on the Softdevice Controller's
+//! vendor specific QoS connection event reports.
+//!
+//! When enabled, the SDC emits one report per connection event, containing
+//! the data channel that was used and how many packets were received with
+//! CRC errors. [`LinkQuality`] accumulates these reports into per-channel
+//! packet error rates and can be plugged into the `trouble-host` runner as an
+//! [`EventHandler`].
+
+use core::cell::RefCell;
+
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use nrf_sdc::raw;
+use trouble_host::prelude::*;
+
+/// Number of BLE data channels.
+pub const DATA_CHANNELS: usize = 37;
+
+/// Vendor specific subevent code of the QoS connection event report.
+const QOS_CONN_EVENT_REPORT_SUBEVENT: u8 = 0x80;
+
+/// Minimum length of a QoS connection event report including the subevent code.
+const QOS_CONN_EVENT_REPORT_LEN: usize = 11;
+
+/// Number of packets after which the per-channel counters are halved,
+/// so the error rates follow changes of the RF environment.
+const DECAY_THRESHOLD: u16 = 1024;
+
+/// Error returned when the controller rejected a vendor specific command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct HciStatus(pub u8);
+
+/// A single QoS connection event report as emitted by the SDC.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct QosConnEventReport {
+    pub conn_handle: u16,
+    pub event_counter: u16,
+    pub channel_index: u8,
+    pub tx_packet_count: u8,
+    pub tx_ack_count: u8,
+    pub tx_retransmit_count: u8,
+    pub rx_packet_count: u8,
+    pub rx_crc_error_count: u8,
+}
+
+impl QosConnEventReport {
+    /// Parse the parameters of a vendor specific HCI event.
+    ///
+    /// Returns `None` for other vendor events or truncated reports.
+    pub fn parse(params: &[u8]) -> Option<Self> {
+        if params.len() < QOS_CONN_EVENT_REPORT_LEN || params[0] != QOS_CONN_EVENT_REPORT_SUBEVENT {
+            return None;
+        }
+        Some(Self {
+            conn_handle: u16::from_le_bytes([params[1], params[2]]),
+            event_counter: u16::from_le_bytes([params[3], params[4]]),
+            channel_index: params[5],
+            tx_packet_count: params[6],
+            tx_ack_count: params[7],
+            tx_retransmit_count: params[8],
+            rx_packet_count: params[9],
+            rx_crc_error_count: params[10],
+        })
+    }
+}
+
+/// Packet counters for a single data channel.
+#[derive(Debug, Clone, Copy, Default, defmt::Format)]
+pub struct ChannelStats {
+    /// Packets sent or received on this channel.
+    pub packets: u16,
+    /// Packets that were not acknowledged or received with a CRC error.
+    pub errors: u16,
+}
+
+impl ChannelStats {
+    const EMPTY: Self = Self {
+        packets: 0,
+        errors: 0,
+    };
+
+    /// Packet error rate in percent, `None` if no packets were seen yet.
+    pub fn packet_error_rate(&self) -> Option<u8> {
+        (self.packets > 0).then(|| (u32::from(self.errors) * 100 / u32::from(self.packets)) as u8)
+    }
+
+    fn record(&mut self, packets: u16, errors: u16) {
+        self.packets = self.packets.saturating_add(packets);
+        self.errors = self.errors.saturating_add(errors);
+        if self.packets >= DECAY_THRESHOLD {
+            self.packets /= 2;
+            self.errors /= 2;
+        }
+    }
+}
+
+/// Per-channel link quality of all active connections.
+pub struct LinkQuality {
+    channels: Mutex<CriticalSectionRawMutex, RefCell<[ChannelStats; DATA_CHANNELS]>>,
+}
+
+impl Default for LinkQuality {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LinkQuality {
+    pub const fn new() -> Self {
+        Self {
+            channels: Mutex::new(RefCell::new([ChannelStats::EMPTY; DATA_CHANNELS])),
+        }
+    }
+
+    /// Enable QoS connection event reports in the Softdevice Controller.
+    ///
+    /// Must be called after the controller was built, and again after a
+    /// reset of the controller.
+    pub fn enable_reports() -> Result<(), HciStatus> {
+        let params = raw::sdc_hci_cmd_vs_qos_conn_event_report_enable_t { enable: 1 };
+        // SAFETY: The command only reads `params`, which outlives the call.
+        match unsafe { raw::sdc_hci_cmd_vs_qos_conn_event_report_enable(&params) } {
+            0 => Ok(()),
+            status => Err(HciStatus(status)),
+        }
+    }
+
+    /// Account a single QoS report.
+    pub fn record(&self, report: &QosConnEventReport) {
+        let channel = usize::from(report.channel_index);
+        if channel >= DATA_CHANNELS {
+            warn!("[link_quality] invalid channel index {}", channel);
+            return;
+        }
+        let tx_lost = report.tx_packet_count.saturating_sub(report.tx_ack_count);
+        let packets = u16::from(report.tx_packet_count) + u16::from(report.rx_packet_count);
+        let errors = u16::from(tx_lost) + u16::from(report.rx_crc_error_count);
+        self.channels
+            .lock(|c| c.borrow_mut()[channel].record(packets, errors));
+    }
+
+    /// Current statistics of a single data channel.
+    pub fn channel(&self, channel: usize) -> ChannelStats {
+        self.channels
+            .lock(|c| c.borrow().get(channel).copied().unwrap_or_default())
+    }
+
+    /// Packet error rate per channel in percent, `0xFF` for channels without traffic.
+    ///
+    /// This is the value layout of the diagnostics characteristic.
+    pub fn error_rates(&self) -> [u8; DATA_CHANNELS] {
+        let mut rates = [0xFF; DATA_CHANNELS];
+        self.channels.lock(|c| {
+            for (rate, stats) in rates.iter_mut().zip(c.borrow().iter()) {
+                if let Some(per) = stats.packet_error_rate() {
+                    *rate = per;
+                }
+            }
+        });
+        rates
+    }
+
+    /// Reset all counters, e.g. after a disconnect.
+    pub fn reset(&self) {
+        self.channels
+            .lock(|c| *c.borrow_mut() = [ChannelStats::EMPTY; DATA_CHANNELS]);
+    }
+
+    /// Log the packet error rate of all used channels.
+    pub fn log_summary(&self) {
+        let rates = self.error_rates();
+        for (channel, per) in rates.iter().enumerate().filter(|(_, per)| **per != 0xFF) {
+            info!("[link_quality] channel {}: PER {}%", channel, per);
+        }
+    }
+}
+
+impl EventHandler for LinkQuality {
+    fn on_vendor(&self, vendor: &Vendor) {
+        if let Some(report) = QosConnEventReport::parse(vendor.params) {
+            self.record(&report);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! I2C magnetometers: ST LIS2MDL and QST QMC5883L.
+//!
+//! [`detect`] probes the bus for either of them and configures it for
+//! continuous measurements at 10 Hz. Readings are raw counts in the axes
+//! printed on the sensor, mount it with x pointing forward and z up (see
+//! [`compass`](crate::nav::compass)) or swap the axes in [`Magnetometer::read`].
+
+use defmt::info;
+use embedded_hal::i2c::I2c;
+
+use super::i2c::{read_register, read_registers, write_register};
+use super::i2c_probe::{self, ProbeError};
+
+/// I2C address of the LIS2MDL.
+const LIS2MDL_ADDRESS: u8 = 0x1E;
+const LIS2MDL_WHO_AM_I: u8 = 0x4F;
+const LIS2MDL_ID: u8 = 0x40;
+const LIS2MDL_CFG_REG_A: u8 = 0x60;
+const LIS2MDL_CFG_REG_C: u8 = 0x62;
+const LIS2MDL_OUTX_L: u8 = 0x68;
+
+/// I2C address of the QMC5883L.
+const QMC5883L_ADDRESS: u8 = 0x0D;
+const QMC5883L_CHIP_ID: u8 = 0x0D;
+const QMC5883L_ID: u8 = 0xFF;
+const QMC5883L_DATA: u8 = 0x00;
+const QMC5883L_CONTROL_1: u8 = 0x09;
+const QMC5883L_SET_RESET_PERIOD: u8 = 0x0B;
+
+/// Errors of the magnetometer drivers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum MagnetometerError {
+    /// No supported magnetometer answered.
+    NotFound,
+    /// A transfer failed.
+    Bus,
+}
+
+impl From<ProbeError> for MagnetometerError {
+    fn from(e: ProbeError) -> Self {
+        match e {
+            ProbeError::NotFound => Self::NotFound,
+            ProbeError::Bus => Self::Bus,
+        }
+    }
+}
+
+/// A 3 axis magnetometer.
+pub trait Magnetometer {
+    /// The latest measurement in raw counts.
+    fn read(&mut self) -> Result<[i16; 3], MagnetometerError>;
+}
+
+fn bus<E>(_: E) -> MagnetometerError {
+    MagnetometerError::Bus
+}
+
+fn read_axes<I: I2c>(
+    i2c: &mut I,
+    address: u8,
+    register: u8,
+) -> Result<[i16; 3], MagnetometerError> {
+    let mut data = [0; 6];
+    read_registers(i2c, address, register, &mut data).map_err(bus)?;
+    Ok(core::array::from_fn(|i| {
+        i16::from_le_bytes([data[2 * i], data[2 * i + 1]])
+    }))
+}
+
+/// ST LIS2MDL, 1.5 mG per count.
+pub struct Lis2mdl<I> {
+    i2c: I,
+}
+
+impl<I: I2c> Lis2mdl<I> {
+    /// Check the identity and start continuous mode with temperature
+    /// compensation at 10 Hz.
+    pub fn new(mut i2c: I) -> Result<Self, MagnetometerError> {
+        if read_register(&mut i2c, LIS2MDL_ADDRESS, LIS2MDL_WHO_AM_I).map_err(bus)? != LIS2MDL_ID {
+            return Err(MagnetometerError::NotFound);
+        }
+        write_register(&mut i2c, LIS2MDL_ADDRESS, LIS2MDL_CFG_REG_A, 0x80).map_err(bus)?;
+        // Block data update, so both bytes of an axis are from the same sample.
+        write_register(&mut i2c, LIS2MDL_ADDRESS, LIS2MDL_CFG_REG_C, 0x10).map_err(bus)?;
+        Ok(Self { i2c })
+    }
+}
+
+impl<I: I2c> Magnetometer for Lis2mdl<I> {
+    fn read(&mut self) -> Result<[i16; 3], MagnetometerError> {
+        read_axes(&mut self.i2c, LIS2MDL_ADDRESS, LIS2MDL_OUTX_L)
+    }
+}
+
+/// QST QMC5883L, 3000 counts per G in the 8 G range.
+pub struct Qmc5883l<I> {
+    i2c: I,
+}
+
+impl<I: I2c> Qmc5883l<I> {
+    /// Check the identity and start continuous mode at 10 Hz, 8 G range and
+    /// 512 times oversampling.
+    pub fn new(mut i2c: I) -> Result<Self, MagnetometerError> {
+        if read_register(&mut i2c, QMC5883L_ADDRESS, QMC5883L_CHIP_ID).map_err(bus)? != QMC5883L_ID
+        {
+            return Err(MagnetometerError::NotFound);
+        }
+        // Recommended by the datasheet.
+        write_register(&mut i2c, QMC5883L_ADDRESS, QMC5883L_SET_RESET_PERIOD, 0x01).map_err(bus)?;
+        write_register(&mut i2c, QMC5883L_ADDRESS, QMC5883L_CONTROL_1, 0x11).map_err(bus)?;
+        Ok(Self { i2c })
+    }
+}
+
+impl<I: I2c> Magnetometer for Qmc5883l<I> {
+    fn read(&mut self) -> Result<[i16; 3], MagnetometerError> {
+        read_axes(&mut self.i2c, QMC5883L_ADDRESS, QMC5883L_DATA)
+    }
+}
+
+/// One of the supported magnetometers.
+pub enum AnyMagnetometer<I> {
+    Lis2mdl(Lis2mdl<I>),
+    Qmc5883l(Qmc5883l<I>),
+}
+
+impl<I: I2c> Magnetometer for AnyMagnetometer<I> {
+    fn read(&mut self) -> Result<[i16; 3], MagnetometerError> {
+        match self {
+            Self::Lis2mdl(m) => m.read(),
+            Self::Qmc5883l(m) => m.read(),
+        }
+    }
+}
+
+/// Find and configure a supported magnetometer on the bus.
+pub fn detect<I: I2c>(mut i2c: I) -> Result<AnyMagnetometer<I>, MagnetometerError> {
+    if i2c_probe::probe(&mut i2c, LIS2MDL_ADDRESS)? {
+        info!("[mag] LIS2MDL at {:#04x}", LIS2MDL_ADDRESS);
+        return Lis2mdl::new(i2c).map(AnyMagnetometer::Lis2mdl);
+    }
+    if i2c_probe::probe(&mut i2c, QMC5883L_ADDRESS)? {
+        info!("[mag] QMC5883L at {:#04x}", QMC5883L_ADDRESS);
+        return Qmc5883l::new(i2c).map(AnyMagnetometer::Qmc5883l);
+    }
+    Err(MagnetometerError::NotFound)
+}
@@ -0,0 +1,164 @@
+//! WS2812 RGB status LED (NeoPixel on Adafruit Feather nRF52840).
+//!
+//! The WS2812 is driven with a PWM sequence: every bit is one PWM period of
+//! 1.25 µs, high for 0.4 µs (0) or 0.8 µs (1), followed by a low reset
+//! period that latches the color.
+//!
+//! The [`neopixel_task`] plays the [`LedPattern`] of the status LED in the
+//! color of the device state: red while recovering from an error or after
+//! a failure, green with a GNSS fix, blue while advertising.
+
+use defmt::warn;
+use embassy_futures::select::{Either3, select3};
+use embassy_nrf::Peri;
+use embassy_nrf::gpio::AnyPin;
+use embassy_nrf::peripherals::PWM0;
+use embassy_nrf::pwm::{
+    Config, Prescaler, SequenceConfig, SequenceLoad, SequencePwm, SingleSequenceMode,
+    SingleSequencer,
+};
+use embassy_time::{Duration, Timer};
+
+use crate::bsp::led::{self, LedPattern};
+use crate::events::{self, Event};
+
+/// PWM period of a bit in 16 MHz ticks (1.25 µs).
+const BIT_TICKS: u16 = 20;
+
+/// Duty of a 1 bit, the polarity bit makes the output high first.
+const T1H: u16 = 0x8000 | 13;
+
+/// Duty of a 0 bit.
+const T0H: u16 = 0x8000 | 7;
+
+/// Low output for the reset period.
+const RESET: u16 = 0x8000;
+
+/// Additional low periods after the last bit, at least 50 µs.
+const RESET_PERIODS: u32 = 40 * BIT_TICKS as u32 - 1;
+
+/// Color of the NeoPixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const OFF: Self = Self::new(0, 0, 0);
+    /// Error.
+    pub const RED: Self = Self::new(32, 0, 0);
+    /// GNSS fix.
+    pub const GREEN: Self = Self::new(0, 32, 0);
+    /// Advertising.
+    pub const BLUE: Self = Self::new(0, 0, 32);
+    /// Connected without fix.
+    pub const WHITE: Self = Self::new(16, 16, 16);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// PWM sequence of this color, in the GRB order of the WS2812.
+    fn sequence(&self) -> [u16; 25] {
+        let mut words = [RESET; 25];
+        let bits = (self.g as u32) << 16 | (self.r as u32) << 8 | self.b as u32;
+        for (i, word) in words[..24].iter_mut().enumerate() {
+            *word = if bits & (1 << (23 - i)) != 0 {
+                T1H
+            } else {
+                T0H
+            };
+        }
+        words
+    }
+}
+
+/// Single WS2812 LED on a PWM output.
+pub struct NeoPixel<'d> {
+    pwm: SequencePwm<'d>,
+}
+
+impl<'d> NeoPixel<'d> {
+    pub fn new(pwm: Peri<'d, PWM0>, pin: Peri<'d, AnyPin>) -> Self {
+        let mut config = Config::default();
+        config.sequence_load = SequenceLoad::Common;
+        config.prescaler = Prescaler::Div1;
+        config.max_duty = BIT_TICKS;
+        // Only fails for invalid configurations.
+        let pwm = SequencePwm::new_1ch(pwm, pin, config).unwrap();
+        Self { pwm }
+    }
+
+    /// Latch `color`.
+    pub async fn set(&mut self, color: Rgb) {
+        let words = color.sequence();
+        let mut config = SequenceConfig::default();
+        config.end_delay = RESET_PERIODS;
+        let sequencer = SingleSequencer::new(&mut self.pwm, &words, config);
+        if let Err(e) = sequencer.start(SingleSequenceMode::Times(1)) {
+            warn!("[neopixel] couldn't start sequence: {:?}", e);
+        }
+        // Bits and reset take 80 µs, the sequencer stops when dropped.
+        Timer::after(Duration::from_micros(100)).await;
+    }
+}
+
+/// Color of the device state.
+fn status_color(pattern: LedPattern, advertising: bool, fix: bool) -> Rgb {
+    match pattern {
+        LedPattern::Recovering | LedPattern::Failure => Rgb::RED,
+        _ if fix => Rgb::GREEN,
+        _ if advertising => Rgb::BLUE,
+        _ => Rgb::WHITE,
+    }
+}
+
+/// Play the status LED pattern on the NeoPixel on `pin` with PWM0.
+#[embassy_executor::task]
+pub async fn neopixel_task(pwm: Peri<'static, PWM0>, pin: Peri<'static, AnyPin>) {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[neopixel] no event bus subscriber available");
+        return;
+    };
+    let Some(mut patterns) = led::pattern_receiver() else {
+        warn!("[neopixel] no pattern receiver available");
+        return;
+    };
+    let mut pixel = NeoPixel::new(pwm, pin);
+    let mut pattern = patterns.try_get().unwrap_or(LedPattern::Heartbeat);
+    let mut advertising = false;
+    let mut fix = false;
+    loop {
+        let color = status_color(pattern, advertising, fix);
+        for (on, ms) in pattern.steps() {
+            pixel.set(if *on { color } else { Rgb::OFF }).await;
+            let mut step = Timer::after(Duration::from_millis(*ms));
+            let restart = loop {
+                match select3(&mut step, patterns.changed(), events.next_message_pure()).await {
+                    Either3::First(_) => break false,
+                    Either3::Second(new) => {
+                        pattern = new;
+                        break true;
+                    }
+                    Either3::Third(event) => {
+                        match event {
+                            Event::AdvertisingStarted => advertising = true,
+                            Event::CentralConnected { .. } => advertising = false,
+                            Event::FixAcquired(_) => fix = true,
+                            Event::FixLost | Event::GnssAbsent => fix = false,
+                            _ => {}
+                        }
+                        if status_color(pattern, advertising, fix) != color {
+                            break true;
+                        }
+                    }
+                }
+            };
+            if restart {
+                break;
+            }
+        }
+    }
+}
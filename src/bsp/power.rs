@@ -0,0 +1,89 @@
+//! System power states.
+//!
+//! [`ship_mode`] puts the device into System OFF for shipping and storage.
+//! Only a single GPIO ([`WakePin`]), e.g. the user button or a reed switch
+//! closed by a magnet, wakes it up again, which resets the chip.
+
+use defmt::info;
+use embassy_nrf::Peri;
+use embassy_nrf::gpio::{Pin, Port};
+use embassy_nrf::pac;
+use embassy_nrf::pac::gpio::vals;
+use embassy_time::{Duration, Timer};
+
+use crate::bsp::led::{self, LedPattern};
+use crate::events::{self, Event};
+
+/// Time for the subsystems to power down their devices after
+/// [`Event::ShuttingDown`].
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(200);
+
+/// GPIO waking the device from System OFF when pulled low.
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct WakePin {
+    port: u8,
+    pin: u8,
+}
+
+impl WakePin {
+    /// The pin may still be used (e.g. as button input) until [`ship_mode`].
+    pub fn new<P: Pin>(pin: &Peri<'_, P>) -> Self {
+        Self {
+            port: match pin.port() {
+                Port::Port0 => 0,
+                Port::Port1 => 1,
+            },
+            pin: pin.pin(),
+        }
+    }
+
+    fn configure(&self) {
+        let port = if self.port == 0 { pac::P0 } else { pac::P1 };
+        port.pin_cnf(self.pin as usize).write(|w| {
+            w.set_dir(vals::Dir::INPUT);
+            w.set_input(vals::Input::CONNECT);
+            w.set_pull(vals::Pull::PULLUP);
+            w.set_sense(vals::Sense::LOW);
+        });
+    }
+}
+
+/// Disable the peripherals that keep clocks or regulators running.
+fn disable_peripherals() {
+    pac::UARTE0
+        .enable()
+        .write(|w| w.set_enable(pac::uarte::vals::Enable::DISABLED));
+    #[cfg(not(feature = "chip-nrf52832"))]
+    pac::UARTE1
+        .enable()
+        .write(|w| w.set_enable(pac::uarte::vals::Enable::DISABLED));
+    pac::TWIM0
+        .enable()
+        .write(|w| w.set_enable(pac::twim::vals::Enable::DISABLED));
+    pac::SAADC.enable().write(|w| w.set_enable(false));
+    pac::RADIO.power().write(|w| w.set_power(false));
+}
+
+/// Enter System OFF (drawing less than 1 µA) until `wake` is pulled low.
+///
+/// Publishes [`Event::ShuttingDown`] first, so the display and the GNSS
+/// module can be powered down.
+pub async fn ship_mode(wake: WakePin) -> ! {
+    info!("[power] entering ship mode, wake on {:?}", wake);
+    events::publish(Event::ShuttingDown);
+    led::set_pattern(LedPattern::Off);
+    Timer::after(SHUTDOWN_GRACE).await;
+
+    cortex_m::interrupt::disable();
+    disable_peripherals();
+    wake.configure();
+    // Clear the reset reason, so the next boot can tell it woke from System OFF.
+    pac::POWER
+        .resetreas()
+        .write_value(pac::power::regs::Resetreas(0xFFFF_FFFF));
+    pac::POWER.systemoff().write(|w| w.set_systemoff(true));
+    // System OFF is emulated while a debugger is attached.
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
@@ -0,0 +1,98 @@
+//! Allocation of PPI channels.
+//!
+//! The SoftDevice Controller and the MPSL need a fixed set of PPI channels,
+//! which are handed out as a whole as [`BleChannels`]. All other
+//! configurable channels are handed out one by one by [`PpiAllocator::alloc`]
+//! to drivers needing one, e.g. the UARTE idle detection, so users don't
+//! have to agree on channel numbers.
+
+use defmt::info;
+use embassy_nrf::{
+    Peri,
+    peripherals::{
+        PPI_CH17, PPI_CH18, PPI_CH19, PPI_CH20, PPI_CH21, PPI_CH22, PPI_CH23, PPI_CH24, PPI_CH25,
+        PPI_CH26, PPI_CH27, PPI_CH28, PPI_CH29, PPI_CH30, PPI_CH31,
+    },
+    ppi::AnyConfigurableChannel,
+};
+
+/// Number of configurable channels not used by the BLE stack (0 to 16).
+pub const FREE_CHANNELS: usize = 17;
+
+/// A PPI channel handed out by the allocator.
+pub type Channel = Peri<'static, AnyConfigurableChannel>;
+
+/// Errors of the PPI allocator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum PpiError {
+    /// All free channels are allocated.
+    Exhausted,
+    /// The BLE channels were already taken.
+    BleTaken,
+}
+
+/// The channels reserved for the SoftDevice Controller and the MPSL.
+pub struct BleChannels {
+    pub ppi_ch17: Peri<'static, PPI_CH17>,
+    pub ppi_ch18: Peri<'static, PPI_CH18>,
+    pub ppi_ch19: Peri<'static, PPI_CH19>,
+    pub ppi_ch20: Peri<'static, PPI_CH20>,
+    pub ppi_ch21: Peri<'static, PPI_CH21>,
+    pub ppi_ch22: Peri<'static, PPI_CH22>,
+    pub ppi_ch23: Peri<'static, PPI_CH23>,
+    pub ppi_ch24: Peri<'static, PPI_CH24>,
+    pub ppi_ch25: Peri<'static, PPI_CH25>,
+    pub ppi_ch26: Peri<'static, PPI_CH26>,
+    pub ppi_ch27: Peri<'static, PPI_CH27>,
+    pub ppi_ch28: Peri<'static, PPI_CH28>,
+    pub ppi_ch29: Peri<'static, PPI_CH29>,
+    pub ppi_ch30: Peri<'static, PPI_CH30>,
+    pub ppi_ch31: Peri<'static, PPI_CH31>,
+}
+
+/// Hands out the PPI channels.
+pub struct PpiAllocator {
+    free: [Option<Channel>; FREE_CHANNELS],
+    ble: Option<BleChannels>,
+}
+
+impl PpiAllocator {
+    /// `free` are the channels 0 to 16 in order.
+    pub fn new(free: [Channel; FREE_CHANNELS], ble: BleChannels) -> Self {
+        Self {
+            free: free.map(Some),
+            ble: Some(ble),
+        }
+    }
+
+    /// Allocate the lowest free channel.
+    pub fn alloc(&mut self) -> Result<Channel, PpiError> {
+        let (number, channel) = self
+            .free
+            .iter_mut()
+            .enumerate()
+            .find_map(|(n, c)| c.take().map(|c| (n, c)))
+            .ok_or(PpiError::Exhausted)?;
+        info!("[ppi] allocated channel {}", number);
+        Ok(channel)
+    }
+
+    /// Allocate `N` channels, none if there aren't enough free ones.
+    pub fn alloc_n<const N: usize>(&mut self) -> Result<[Channel; N], PpiError> {
+        if self.available() < N {
+            return Err(PpiError::Exhausted);
+        }
+        // There are enough free channels, so every allocation succeeds.
+        Ok(core::array::from_fn(|_| self.alloc().unwrap()))
+    }
+
+    /// Number of free channels.
+    pub fn available(&self) -> usize {
+        self.free.iter().filter(|c| c.is_some()).count()
+    }
+
+    /// Take the channels reserved for the BLE stack.
+    pub fn take_ble(&mut self) -> Result<BleChannels, PpiError> {
+        self.ble.take().ok_or(PpiError::BleTaken)
+    }
+}
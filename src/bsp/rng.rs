@@ -0,0 +1,94 @@
+//! Shared true random number generator.
+//!
+//! The RNG peripheral is owned by this module, [`Rng`] is a copyable handle
+//! to it implementing the `rand_core` traits. One handle feeds the
+//! SoftDevice Controller, the application uses others for nonces, jitter and
+//! keys. The RNG runs with bias correction, so the output is suitable for
+//! cryptographic use.
+//!
+//! The RNG runs all the time. A reader waits for the next value with
+//! interrupts enabled and takes it in a critical section of a few
+//! instructions, so the SoftDevice Controller, which reads from its
+//! interrupt handlers, isn't delayed by an application read and can
+//! preempt it.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_nrf::{Peri, pac, peripherals::RNG};
+use rand_core::{CryptoRng, RngCore};
+
+/// Whether the RNG peripheral was taken.
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Next random byte.
+fn next_byte() -> u8 {
+    let rng = pac::RNG;
+    loop {
+        while rng.events_valrdy().read() == 0 {}
+        // A preempting reader may have taken the value since.
+        let byte = cortex_m::interrupt::free(|_| {
+            (rng.events_valrdy().read() != 0).then(|| {
+                rng.events_valrdy().write_value(0);
+                rng.value().read().value()
+            })
+        });
+        if let Some(byte) = byte {
+            return byte;
+        }
+    }
+}
+
+/// Handle to the shared RNG.
+#[derive(Clone, Copy)]
+pub struct Rng {
+    _private: (),
+}
+
+impl Rng {
+    /// Take the RNG peripheral. Panics when called twice.
+    pub fn new(_rng: Peri<'static, RNG>) -> Self {
+        assert!(!TAKEN.swap(true, Ordering::Relaxed), "RNG already taken");
+        let rng = pac::RNG;
+        rng.config().write(|w| w.set_dercen(true));
+        rng.events_valrdy().write_value(0);
+        rng.tasks_start().write_value(1);
+        Self { _private: () }
+    }
+
+    /// Random value in `0..bound`, `bound` must not be 0.
+    pub fn below(&mut self, bound: u32) -> u32 {
+        // Rejection sampling avoids a bias towards small values.
+        let zone = u32::MAX - u32::MAX % bound;
+        loop {
+            let value = self.next_u32();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+}
+
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        dest.fill_with(next_byte);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for Rng {}
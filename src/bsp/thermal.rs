@@ -0,0 +1,122 @@
+//! Thermal protection of the radio.
+//!
+//! [`run`] reads the die temperature through the MPSL, which owns the TEMP
+//! peripheral, and lets a [`Throttle`] step the TX power of the advertising
+//! set and of the connection down while the die is at or above
+//! [`Settings::thermal_limit_c`], e.g. in an enclosure in the sun. The
+//! power is raised again once it cooled down. A connection established
+//! while throttled gets the reduced power through [`connected`].
+//!
+//! [`Settings::thermal_limit_c`]: crate::settings::Settings::thermal_limit_c
+
+use core::sync::atomic::{AtomicI8, AtomicU16, Ordering};
+
+use defmt::{info, warn};
+use embassy_time::{Duration, Ticker};
+use nrf_sdc::{mpsl, raw};
+pub use nrf52_radio_core::thermal::{HYSTERESIS_C, TX_POWER_STEPS_DBM, Throttle};
+
+use crate::bsp::link_quality::HciStatus;
+use crate::settings;
+
+/// Interval in which the die temperature is read.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Handle of the legacy advertising set used by `trouble-host`.
+const ADV_HANDLE: u16 = 0;
+
+/// Handle types of the TX power vendor command.
+const HANDLE_TYPE_ADV: u8 = 0;
+const HANDLE_TYPE_CONN: u8 = 2;
+
+/// Marks that no central is connected.
+const NO_CONNECTION: u16 = u16::MAX;
+
+/// TX power currently applied, in dBm.
+static TX_POWER: AtomicI8 = AtomicI8::new(TX_POWER_STEPS_DBM[0]);
+
+/// Handle of the current connection, [`NO_CONNECTION`] if none.
+static CONNECTION: AtomicU16 = AtomicU16::new(NO_CONNECTION);
+
+/// Set the TX power of an advertising set or connection.
+fn write_tx_power(handle_type: u8, handle: u16, dbm: i8) -> Result<(), HciStatus> {
+    let params = raw::sdc_hci_cmd_vs_zephyr_write_tx_power_t {
+        handle_type,
+        handle,
+        tx_power_level: dbm,
+    };
+    let mut ret = raw::sdc_hci_cmd_vs_zephyr_write_tx_power_return_t {
+        handle_type: 0,
+        handle: 0,
+        selected_tx_power: 0,
+    };
+    match unsafe { raw::sdc_hci_cmd_vs_zephyr_write_tx_power(&params, &mut ret) } {
+        0 => Ok(()),
+        status => Err(HciStatus(status)),
+    }
+}
+
+/// Apply the TX power to the advertising set and the connection.
+fn apply(dbm: i8) {
+    TX_POWER.store(dbm, Ordering::Relaxed);
+    if let Err(e) = write_tx_power(HANDLE_TYPE_ADV, ADV_HANDLE, dbm) {
+        warn!("[thermal] couldn't set the advertising TX power: {:?}", e);
+    }
+    let conn = CONNECTION.load(Ordering::Relaxed);
+    if conn != NO_CONNECTION
+        && let Err(e) = write_tx_power(HANDLE_TYPE_CONN, conn, dbm)
+    {
+        warn!("[thermal] couldn't set the connection TX power: {:?}", e);
+    }
+}
+
+/// A central connected with the connection `handle` (`None`: it
+/// disconnected). Applies the current TX power to the connection.
+pub fn connected(handle: Option<u16>) {
+    CONNECTION.store(handle.unwrap_or(NO_CONNECTION), Ordering::Relaxed);
+    let dbm = TX_POWER.load(Ordering::Relaxed);
+    if let Some(handle) = handle
+        && dbm != TX_POWER_STEPS_DBM[0]
+        && let Err(e) = write_tx_power(HANDLE_TYPE_CONN, handle, dbm)
+    {
+        warn!("[thermal] couldn't set the connection TX power: {:?}", e);
+    }
+}
+
+/// TX power of the advertising set and the connection in dBm.
+pub fn tx_power() -> i8 {
+    TX_POWER.load(Ordering::Relaxed)
+}
+
+/// Die temperature in 0.25 °C.
+///
+/// The MPSL has to be initialized.
+pub fn die_temperature() -> i32 {
+    unsafe { mpsl::raw::mpsl_temperature_get() }
+}
+
+/// Throttle the TX power by the die temperature.
+///
+/// The MPSL has to be initialized.
+pub async fn run() {
+    let mut throttle = Throttle::new();
+    let mut ticker = Ticker::every(SAMPLE_INTERVAL);
+    loop {
+        let quarter_c = die_temperature();
+        let limit_c = settings::get().thermal_limit_c;
+        if let Some(dbm) = throttle.update(quarter_c, limit_c) {
+            if throttle.throttled() {
+                warn!(
+                    "[thermal] die at {} °C (limit {} °C), TX power {} dBm",
+                    quarter_c / 4,
+                    limit_c,
+                    dbm
+                );
+            } else {
+                info!("[thermal] die at {} °C, TX power restored", quarter_c / 4);
+            }
+            apply(dbm);
+        }
+        ticker.next().await;
+    }
+}
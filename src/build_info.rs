@@ -0,0 +1,37 @@
+//! Version and build of the firmware, embedded by the build script.
+//!
+//! Printed at boot with [`log`] and readable as [`text`] (e.g. from a GATT
+//! characteristic), so devices in the field can be inventoried.
+
+use core::fmt::Write;
+
+use defmt::info;
+use heapless::String;
+
+/// Version of the crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Abbreviated git commit, `-dirty` with uncommitted changes, `unknown`
+/// without git.
+pub const GIT_HASH: &str = env!("GIT_HASH");
+
+/// Unix time of the build in seconds (`SOURCE_DATE_EPOCH` if set).
+pub const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+
+/// Longest [`text`].
+pub const TEXT_LEN: usize = 48;
+
+/// `<version>+<git hash> <timestamp>`, e.g. `0.1.0+2d6e4ef1 1760659200`.
+pub fn text() -> String<TEXT_LEN> {
+    let mut text = String::new();
+    let _ = write!(text, "{}+{} {}", VERSION, GIT_HASH, BUILD_TIMESTAMP);
+    text
+}
+
+/// Log the build info.
+pub fn log() {
+    info!(
+        "[build] version {} ({}, built at {})",
+        VERSION, GIT_HASH, BUILD_TIMESTAMP
+    );
+}
@@ -0,0 +1,146 @@
+//! Compass subsystem.
+//!
+//! [`Compass`] reads a [`Magnetometer`] and an [`Accelerometer`], applies
+//! the calibration stored in the [settings](crate::settings) and publishes
+//! the tilt-compensated heading as [`Event::HeadingUpdated`]. The heading
+//! is magnetic, not corrected for the declination.
+//!
+//! The `calibrate` shell command (or [`start_calibration`]) captures the
+//! extremes of all axes while the device is turned in all directions for
+//! [`CALIBRATION_TIME`] and saves them.
+
+use core::fmt::Write;
+
+use defmt::{info, warn};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+pub use nrf52_radio_core::nav::compass::{Calibration, Vector};
+use nrf52_radio_core::nav::compass::{CalibrationCapture, heading};
+
+use crate::bsp::magnetometer::{Magnetometer, MagnetometerError};
+use crate::events::{self, Event};
+use crate::settings;
+use crate::shell::{Command, Output};
+use crate::supervisor::Subsystem;
+
+/// Interval of the measurements.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Change of the heading in degrees before it is published again.
+const HEADING_HYSTERESIS: f32 = 2.0;
+
+/// Duration of a calibration.
+pub const CALIBRATION_TIME: Duration = Duration::from_secs(20);
+
+/// Interval of the measurements during a calibration.
+const CALIBRATION_INTERVAL: Duration = Duration::from_millis(50);
+
+static CALIBRATE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Start a calibration of the running compass.
+pub fn start_calibration() {
+    CALIBRATE.signal(());
+}
+
+/// Source of the direction of gravity for the tilt compensation.
+pub trait Accelerometer {
+    /// Acceleration at rest, pointing up, in any unit.
+    fn read(&mut self) -> Result<Vector, MagnetometerError>;
+}
+
+/// Stand-in for boards without accelerometer, the device has to be held
+/// level for a correct heading.
+pub struct Level;
+
+impl Accelerometer for Level {
+    fn read(&mut self) -> Result<Vector, MagnetometerError> {
+        Ok([0.0, 0.0, 1.0])
+    }
+}
+
+/// Compass subsystem publishing the heading.
+pub struct Compass<M, A> {
+    magnetometer: M,
+    accelerometer: A,
+}
+
+impl<M: Magnetometer, A: Accelerometer> Compass<M, A> {
+    pub fn new(magnetometer: M, accelerometer: A) -> Self {
+        Self {
+            magnetometer,
+            accelerometer,
+        }
+    }
+
+    async fn calibrate(&mut self) -> Result<(), MagnetometerError> {
+        info!(
+            "[compass] calibrating, turn the device in all directions for {} s",
+            CALIBRATION_TIME.as_secs()
+        );
+        let mut capture = CalibrationCapture::new();
+        let end = Instant::now() + CALIBRATION_TIME;
+        while Instant::now() < end {
+            capture.add(self.magnetometer.read()?);
+            Timer::after(CALIBRATION_INTERVAL).await;
+        }
+        match capture.finish() {
+            Some(calibration) => {
+                info!("[compass] calibrated: {:?}", calibration);
+                settings::update(|s| {
+                    s.mag_min = calibration.min;
+                    s.mag_max = calibration.max;
+                });
+            }
+            None => warn!("[compass] calibration failed, not all axes were turned"),
+        }
+        Ok(())
+    }
+}
+
+impl<M: Magnetometer, A: Accelerometer> Subsystem for Compass<M, A> {
+    type Error = MagnetometerError;
+
+    const NAME: &'static str = "compass";
+
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        let mut published: Option<f32> = None;
+        loop {
+            if CALIBRATE.try_take().is_some() {
+                self.calibrate().await?;
+                published = None;
+            }
+            let settings = settings::get();
+            let calibration = Calibration {
+                min: settings.mag_min,
+                max: settings.mag_max,
+            };
+            let magnetic = calibration.apply(self.magnetometer.read()?);
+            let heading = heading(magnetic, self.accelerometer.read()?);
+            let changed = published.is_none_or(|p| {
+                let diff = (heading - p + 540.0) % 360.0 - 180.0;
+                diff.abs() >= HEADING_HYSTERESIS
+            });
+            if changed {
+                events::publish(Event::HeadingUpdated(heading));
+                published = Some(heading);
+            }
+            Timer::after(SAMPLE_INTERVAL).await;
+        }
+    }
+}
+
+fn calibrate_command(_args: &str, out: &mut Output) -> core::fmt::Result {
+    start_calibration();
+    write!(
+        out,
+        "turn the device in all directions for {} s\r\n",
+        CALIBRATION_TIME.as_secs()
+    )
+}
+
+/// Shell command to calibrate the compass.
+pub const COMMANDS: &[Command] = &[Command {
+    name: "calibrate",
+    help: "calibrate the compass",
+    handler: calibrate_command,
+}];
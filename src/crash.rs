@@ -0,0 +1,146 @@
+//! Crash dumps in the [`CRASH_DUMP`] flash page.
+//!
+//! On a HardFault, [`save`] writes the exception frame, the fault status
+//! registers and the top of the stack as a [`CrashDump`] and the device is
+//! reset. After the reboot the [`Store`] loads the dump, it is available
+//! from [`last`] (e.g. to expose it over GATT) until [`clear`] is called.
+//!
+//! The handler programs the NVMC directly: the shared flash may be locked
+//! by the faulting code, and the radio doesn't matter anymore.
+
+use core::cell::Cell;
+
+use defmt::{info, warn};
+use embassy_nrf::pac::{self, nvmc::vals::Wen};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_sync::signal::Signal;
+use embedded_storage_async::nor_flash::NorFlash;
+pub use nrf52_radio_core::crash::{CrashDump, STACK_WORDS, stack_pointer};
+
+use crate::bsp::flash::{CRASH_DUMP, PAGE_SIZE};
+use crate::supervisor::Subsystem;
+
+unsafe extern "C" {
+    /// Top of the stack, defined by the linker script of `cortex-m-rt`.
+    static _stack_start: u32;
+}
+
+/// Write a dump of `frame` to flash, `exc_return` is the EXC_RETURN value
+/// the HardFault handler was entered with.
+///
+/// # Safety
+///
+/// Only call from the HardFault handler, nothing else may use the NVMC.
+pub unsafe fn save(frame: &cortex_m_rt::ExceptionFrame, exc_return: u32) {
+    let sp = stack_pointer(frame as *const _ as u32, exc_return, frame.xpsr());
+    // SAFETY: Reading the fault status registers has no side effects.
+    let scb = unsafe { &*cortex_m::peripheral::SCB::PTR };
+    let mut dump = CrashDump::new(
+        [
+            frame.r0(),
+            frame.r1(),
+            frame.r2(),
+            frame.r3(),
+            frame.r12(),
+            frame.lr(),
+            frame.pc(),
+            frame.xpsr(),
+        ],
+        scb.cfsr.read(),
+        scb.hfsr.read(),
+        scb.mmfar.read(),
+        scb.bfar.read(),
+        sp,
+    );
+    let top = &raw const _stack_start as u32;
+    for (i, word) in dump.stack.iter_mut().enumerate() {
+        let addr = sp + 4 * i as u32;
+        if addr >= top {
+            break;
+        }
+        // SAFETY: The address is between the stack pointer and the top of the stack.
+        *word = unsafe { core::ptr::read_volatile(addr as *const u32) };
+    }
+    dump.seal();
+
+    let nvmc = pac::NVMC;
+    let wait_ready = || while !nvmc.ready().read().ready() {};
+    wait_ready();
+    nvmc.config().write(|w| w.set_wen(Wen::EEN));
+    nvmc.erasepage().write_value(CRASH_DUMP.start);
+    wait_ready();
+    nvmc.config().write(|w| w.set_wen(Wen::WEN));
+    let words: &[u32] = bytemuck::cast_slice(dump.as_bytes());
+    for (i, word) in words.iter().enumerate() {
+        let addr = (CRASH_DUMP.start + 4 * i as u32) as *mut u32;
+        // SAFETY: The crash dump page is reserved for this and was erased.
+        unsafe { core::ptr::write_volatile(addr, *word) };
+        wait_ready();
+    }
+    nvmc.config().write(|w| w.set_wen(Wen::REN));
+}
+
+static LAST: Mutex<CriticalSectionRawMutex, Cell<Option<CrashDump>>> = Mutex::new(Cell::new(None));
+
+/// Signalled when the dump has to be erased from flash.
+static CLEAR: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// The dump of the last crash, if there is one.
+pub fn last() -> Option<CrashDump> {
+    LAST.lock(|l| l.get())
+}
+
+/// Delete the dump of the last crash.
+pub fn clear() {
+    LAST.lock(|l| l.set(None));
+    CLEAR.signal(());
+}
+
+/// Flash backed crash dump storage.
+///
+/// `flash` is the [`CRASH_DUMP`] partition.
+pub struct Store<F> {
+    flash: F,
+}
+
+impl<F: NorFlash> Store<F> {
+    pub fn new(flash: F) -> Self {
+        Self { flash }
+    }
+
+    /// Load the dump of the last crash.
+    pub async fn load(&mut self) -> Result<(), F::Error> {
+        let mut bytes = [0; CrashDump::LEN];
+        self.flash.read(0, &mut bytes).await?;
+        let dump: CrashDump = bytemuck::pod_read_unaligned(&bytes);
+        if dump.is_valid() {
+            warn!(
+                "[crash] last crash at pc {=u32:#x} (lr {=u32:#x}, cfsr {=u32:#x})",
+                dump.pc(),
+                dump.lr(),
+                dump.cfsr
+            );
+            LAST.lock(|l| l.set(Some(dump)));
+        }
+        Ok(())
+    }
+}
+
+impl<F: NorFlash> Subsystem for Store<F> {
+    type Error = F::Error;
+
+    const NAME: &'static str = "crash";
+
+    /// Erase the dump whenever it was cleared.
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        loop {
+            CLEAR.wait().await;
+            if let Err(e) = self.flash.erase(0, PAGE_SIZE).await {
+                // Retry after the supervisor restarted us.
+                CLEAR.signal(());
+                return Err(e);
+            }
+            info!("[crash] dump cleared");
+        }
+    }
+}
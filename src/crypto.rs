@@ -0,0 +1,369 @@
+//! Application layer encryption.
+//!
+//! AES-CCM (RFC 3610) and AES-CMAC (RFC 4493) on top of a [`BlockCipher`]. [`SdcEcb`] uses the AES
+//! ECB peripheral through the SoftDevice Controller, which owns the
+//! peripheral while BLE is running. [`BeaconCipher`] encrypts and
+//! authenticates beacon payloads with a pre-shared key and a rolling
+//! counter, which is sent in the clear and becomes part of the nonce.
+//! Rotating identifiers for private beacons are generated by [`eid`], the
+//! advertisements of item-finder networks by `finder`. Updates written over
+//! BLE are checked against an Ed25519 signature by [`signed`].
+//!
+//! With the `cryptocell` feature, AES ([`Aes`]), [`sha256`], [`ecdh_p256`]
+//! and the signature check run on the CryptoCell of the nRF52840 ([`hw`]),
+//! without it on the ECB peripheral and in software.
+
+pub mod eid;
+#[cfg(feature = "item-finder")]
+pub mod finder;
+#[cfg(feature = "cryptocell")]
+pub mod hw;
+pub mod signed;
+
+/// Size of an AES block.
+pub const BLOCK_LEN: usize = 16;
+
+/// Size of a CCM nonce (length field of 2 bytes, so payloads up to 64 KiB).
+pub const NONCE_LEN: usize = 13;
+
+/// Errors of the crypto operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum CryptoError {
+    /// The ECB peripheral returned an error code.
+    Ecb(i32),
+    /// The authentication tag doesn't match, the data was modified or the key is wrong.
+    TagMismatch,
+    /// A buffer is too small or a length is out of range.
+    InvalidLength,
+    /// A key isn't on the curve or out of range.
+    InvalidKey,
+    /// The CryptoCell library returned an error code.
+    #[cfg(feature = "cryptocell")]
+    CryptoCell(u32),
+}
+
+/// AES-128 of the firmware, on the CryptoCell if enabled.
+#[cfg(feature = "cryptocell")]
+pub type Aes = hw::CcAes;
+/// AES-128 of the firmware, on the CryptoCell if enabled.
+#[cfg(not(feature = "cryptocell"))]
+pub type Aes = SdcEcb;
+
+/// SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    #[cfg(feature = "cryptocell")]
+    match hw::sha256(data) {
+        Ok(digest) => return digest,
+        Err(e) => defmt::warn!("[crypto] CryptoCell SHA-256 failed: {:?}", e),
+    }
+    nrf52_radio_core::sha256::sha256(data)
+}
+
+/// P-256 ECDH shared secret (the x coordinate) of our `secret` scalar and
+/// the `peer` public key (x and y, big endian).
+pub fn ecdh_p256(secret: &[u8; 32], peer: &[u8; 64]) -> Result<[u8; 32], CryptoError> {
+    #[cfg(feature = "cryptocell")]
+    return hw::ecdh_p256(secret, peer);
+    #[cfg(not(feature = "cryptocell"))]
+    {
+        let secret =
+            p256::SecretKey::from_bytes(secret.into()).map_err(|_| CryptoError::InvalidKey)?;
+        let mut point = [0; 65];
+        point[0] = 0x04;
+        point[1..].copy_from_slice(peer);
+        let peer = p256::PublicKey::from_sec1_bytes(&point).map_err(|_| CryptoError::InvalidKey)?;
+        let shared = p256::ecdh::diffie_hellman(secret.to_nonzero_scalar(), peer.as_affine());
+        Ok((*shared.raw_secret_bytes()).into())
+    }
+}
+
+/// AES-128 block encryption.
+pub trait BlockCipher {
+    /// Encrypt a block in place.
+    fn encrypt_block(&self, block: &mut [u8; BLOCK_LEN]) -> Result<(), CryptoError>;
+}
+
+/// AES-128 using the ECB peripheral through the SoftDevice Controller.
+pub struct SdcEcb {
+    /// Key, most significant byte first.
+    key: [u8; BLOCK_LEN],
+}
+
+impl SdcEcb {
+    pub fn new(key: [u8; BLOCK_LEN]) -> Self {
+        Self { key }
+    }
+}
+
+impl From<[u8; BLOCK_LEN]> for SdcEcb {
+    fn from(key: [u8; BLOCK_LEN]) -> Self {
+        Self::new(key)
+    }
+}
+
+impl BlockCipher for SdcEcb {
+    fn encrypt_block(&self, block: &mut [u8; BLOCK_LEN]) -> Result<(), CryptoError> {
+        let mut context = nrf_sdc::raw::sdc_soc_ecb_context_t {
+            key: self.key,
+            cleartext: *block,
+            ciphertext: [0; BLOCK_LEN],
+        };
+        // SAFETY: the context is valid for the duration of the call.
+        let ret = unsafe { nrf_sdc::raw::sdc_soc_ecb_block_encrypt(&mut context) };
+        if ret != 0 {
+            return Err(CryptoError::Ecb(ret));
+        }
+        *block = context.ciphertext;
+        Ok(())
+    }
+}
+
+fn xor(block: &mut [u8; BLOCK_LEN], data: &[u8]) {
+    block.iter_mut().zip(data).for_each(|(b, d)| *b ^= d);
+}
+
+/// AES-CCM with a `TAG_LEN` byte authentication tag (4 to 16, even).
+pub struct Ccm<C, const TAG_LEN: usize> {
+    cipher: C,
+}
+
+impl<C: BlockCipher, const TAG_LEN: usize> Ccm<C, TAG_LEN> {
+    pub fn new(cipher: C) -> Self {
+        const { assert!(TAG_LEN >= 4 && TAG_LEN <= 16 && TAG_LEN % 2 == 0) };
+        Self { cipher }
+    }
+
+    /// Counter block `i` for the key stream.
+    fn counter_block(nonce: &[u8; NONCE_LEN], i: u16) -> [u8; BLOCK_LEN] {
+        let mut block = [0; BLOCK_LEN];
+        block[0] = 1; // L - 1
+        block[1..14].copy_from_slice(nonce);
+        block[14..].copy_from_slice(&i.to_be_bytes());
+        block
+    }
+
+    /// CBC-MAC over the associated data and the plaintext.
+    fn mac(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<[u8; BLOCK_LEN], CryptoError> {
+        let len = u16::try_from(plaintext.len()).map_err(|_| CryptoError::InvalidLength)?;
+        if aad.len() >= 0xFF00 {
+            return Err(CryptoError::InvalidLength);
+        }
+        let mut x = [0; BLOCK_LEN];
+        x[0] = (if aad.is_empty() { 0 } else { 0x40 }) | ((TAG_LEN as u8 - 2) / 2) << 3 | 1;
+        x[1..14].copy_from_slice(nonce);
+        x[14..].copy_from_slice(&len.to_be_bytes());
+        self.cipher.encrypt_block(&mut x)?;
+
+        if !aad.is_empty() {
+            // The first block starts with the length of the associated data.
+            let first = aad.len().min(BLOCK_LEN - 2);
+            let mut block = [0; BLOCK_LEN];
+            block[..2].copy_from_slice(&(aad.len() as u16).to_be_bytes());
+            block[2..2 + first].copy_from_slice(&aad[..first]);
+            xor(&mut x, &block);
+            self.cipher.encrypt_block(&mut x)?;
+            for chunk in aad[first..].chunks(BLOCK_LEN) {
+                xor(&mut x, chunk);
+                self.cipher.encrypt_block(&mut x)?;
+            }
+        }
+        for chunk in plaintext.chunks(BLOCK_LEN) {
+            xor(&mut x, chunk);
+            self.cipher.encrypt_block(&mut x)?;
+        }
+        Ok(x)
+    }
+
+    /// XOR `data` with the key stream, starting at counter block 1.
+    fn apply_key_stream(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        data: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        for (i, chunk) in data.chunks_mut(BLOCK_LEN).enumerate() {
+            let mut stream = Self::counter_block(nonce, i as u16 + 1);
+            self.cipher.encrypt_block(&mut stream)?;
+            chunk.iter_mut().zip(stream).for_each(|(b, s)| *b ^= s);
+        }
+        Ok(())
+    }
+
+    /// Encrypt the tag with counter block 0.
+    fn encrypt_tag(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        mac: &[u8; BLOCK_LEN],
+    ) -> Result<[u8; TAG_LEN], CryptoError> {
+        let mut s0 = Self::counter_block(nonce, 0);
+        self.cipher.encrypt_block(&mut s0)?;
+        let mut tag = [0; TAG_LEN];
+        tag.iter_mut()
+            .zip(mac.iter().zip(s0))
+            .for_each(|(t, (m, s))| *t = m ^ s);
+        Ok(tag)
+    }
+
+    /// Encrypt `data` in place and return the authentication tag.
+    pub fn seal(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Result<[u8; TAG_LEN], CryptoError> {
+        let mac = self.mac(nonce, aad, data)?;
+        self.apply_key_stream(nonce, data)?;
+        self.encrypt_tag(nonce, &mac)
+    }
+
+    /// Decrypt `data` in place and verify the authentication tag.
+    ///
+    /// On error, `data` holds no usable plaintext.
+    pub fn open(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8; TAG_LEN],
+    ) -> Result<(), CryptoError> {
+        self.apply_key_stream(nonce, data)?;
+        let mac = self.mac(nonce, aad, data)?;
+        let expected = self.encrypt_tag(nonce, &mac)?;
+        // Constant time comparison
+        let diff = expected
+            .iter()
+            .zip(tag)
+            .fold(0, |acc, (a, b)| acc | (a ^ b));
+        if diff != 0 {
+            data.fill(0);
+            return Err(CryptoError::TagMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// Multiply by x in GF(2^128), for the CMAC subkeys.
+fn double(block: &[u8; BLOCK_LEN]) -> [u8; BLOCK_LEN] {
+    let mut out = [0; BLOCK_LEN];
+    for (i, b) in out.iter_mut().enumerate() {
+        *b = block[i] << 1 | block.get(i + 1).map_or(0, |next| next >> 7);
+    }
+    out[BLOCK_LEN - 1] ^= 0x87 * (block[0] >> 7);
+    out
+}
+
+/// AES-CMAC of `message`.
+pub fn cmac<C: BlockCipher>(cipher: &C, message: &[u8]) -> Result<[u8; BLOCK_LEN], CryptoError> {
+    let mut l = [0; BLOCK_LEN];
+    cipher.encrypt_block(&mut l)?;
+    let k1 = double(&l);
+    let k2 = double(&k1);
+
+    let blocks = message.len().div_ceil(BLOCK_LEN).max(1);
+    let (head, last) = message.split_at((blocks - 1) * BLOCK_LEN);
+    let mut x = [0; BLOCK_LEN];
+    for chunk in head.chunks(BLOCK_LEN) {
+        xor(&mut x, chunk);
+        cipher.encrypt_block(&mut x)?;
+    }
+    let mut block = [0; BLOCK_LEN];
+    block[..last.len()].copy_from_slice(last);
+    if last.len() == BLOCK_LEN {
+        xor(&mut block, &k1);
+    } else {
+        block[last.len()] = 0x80;
+        xor(&mut block, &k2);
+    }
+    xor(&mut x, &block);
+    cipher.encrypt_block(&mut x)?;
+    Ok(x)
+}
+
+/// Length of the authentication tag of beacon payloads.
+pub const BEACON_TAG_LEN: usize = 4;
+
+/// Length of the salt of beacon payloads, as much as fits a legacy
+/// advertisement next to an 8 byte payload and a short name.
+pub const BEACON_SALT_LEN: usize = 4;
+
+/// Bytes added to a beacon payload: salt, counter and tag.
+pub const BEACON_OVERHEAD: usize = BEACON_SALT_LEN + 4 + BEACON_TAG_LEN;
+
+/// Encrypts beacon payloads as
+/// `salt (4) | counter (4, little endian) | ciphertext | tag (4)`.
+///
+/// The nonce is `company id (2) | salt (4) | counter (4) | 0 (3)`, the
+/// company id is authenticated as associated data. A nonce must never repeat
+/// for a key, so the salt is drawn at random on every boot and the counter
+/// starts from a random value as well.
+pub struct BeaconCipher<C> {
+    ccm: Ccm<C, BEACON_TAG_LEN>,
+    company_id: u16,
+    salt: [u8; BEACON_SALT_LEN],
+    counter: u32,
+}
+
+impl<C: BlockCipher> BeaconCipher<C> {
+    /// Cipher encrypting with `salt` from `counter` on, receivers only
+    /// decrypt and take both from the payloads.
+    pub fn new(cipher: C, company_id: u16, salt: [u8; BEACON_SALT_LEN], counter: u32) -> Self {
+        Self {
+            ccm: Ccm::new(cipher),
+            company_id,
+            salt,
+            counter,
+        }
+    }
+
+    fn nonce(company_id: u16, salt: &[u8], counter: u32) -> [u8; NONCE_LEN] {
+        let mut nonce = [0; NONCE_LEN];
+        nonce[..2].copy_from_slice(&company_id.to_le_bytes());
+        nonce[2..2 + BEACON_SALT_LEN].copy_from_slice(salt);
+        nonce[2 + BEACON_SALT_LEN..6 + BEACON_SALT_LEN].copy_from_slice(&counter.to_le_bytes());
+        nonce
+    }
+
+    /// Encrypt `plaintext` into `out` with the next counter value.
+    /// Returns the length of the payload.
+    pub fn encrypt(&mut self, plaintext: &[u8], out: &mut [u8]) -> Result<usize, CryptoError> {
+        let len = plaintext.len() + BEACON_OVERHEAD;
+        if out.len() < len {
+            return Err(CryptoError::InvalidLength);
+        }
+        let counter = self.counter;
+        self.counter = self.counter.wrapping_add(1);
+        let nonce = Self::nonce(self.company_id, &self.salt, counter);
+        let (header, body) = out[..len].split_at_mut(BEACON_SALT_LEN + 4);
+        header[..BEACON_SALT_LEN].copy_from_slice(&self.salt);
+        header[BEACON_SALT_LEN..].copy_from_slice(&counter.to_le_bytes());
+        let (data, tag_out) = body.split_at_mut(plaintext.len());
+        data.copy_from_slice(plaintext);
+        let tag = self
+            .ccm
+            .seal(&nonce, &self.company_id.to_le_bytes(), data)?;
+        tag_out.copy_from_slice(&tag);
+        Ok(len)
+    }
+
+    /// Decrypt a payload produced by [`encrypt`](Self::encrypt) in place.
+    /// Returns the plaintext part of `payload`.
+    pub fn decrypt<'p>(&self, payload: &'p mut [u8]) -> Result<&'p [u8], CryptoError> {
+        if payload.len() < BEACON_OVERHEAD {
+            return Err(CryptoError::InvalidLength);
+        }
+        let (header, body) = payload.split_at_mut(BEACON_SALT_LEN + 4);
+        let (salt, counter) = header.split_at(BEACON_SALT_LEN);
+        let counter = u32::from_le_bytes(counter.try_into().unwrap());
+        let nonce = Self::nonce(self.company_id, salt, counter);
+        let (data, tag) = body.split_at_mut(body.len() - BEACON_TAG_LEN);
+        let tag: [u8; BEACON_TAG_LEN] =
+            (&*tag).try_into().map_err(|_| CryptoError::InvalidLength)?;
+        self.ccm
+            .open(&nonce, &self.company_id.to_le_bytes(), data, &tag)?;
+        Ok(data)
+    }
+}
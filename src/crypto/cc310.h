@@ -0,0 +1,9 @@
+/* Headers of the nrf_cc310 library, bindings generated by build.rs for
+ * src/crypto/hw.rs. */
+#include "sns_silib.h"
+#include "crys_hash.h"
+#include "ssi_aes.h"
+#include "crys_ecpki_build.h"
+#include "crys_ecpki_domain.h"
+#include "crys_ecpki_dh.h"
+#include "crys_ec_edw_api.h"
@@ -0,0 +1,137 @@
+//! Ephemeral identifiers (Eddystone-EID).
+//!
+//! The identifier is derived from a 128 bit identity key and a time counter
+//! with AES, and changes every 2^K seconds. A resolver holding the identity
+//! key and knowing the clock of the device can compute the same identifiers
+//! and recognize the device, everybody else sees unlinkable random values.
+//!
+//! The time counter is the number of seconds since [`EPOCH`]. It runs on the
+//! RTC based `embassy-time` clock and is set from the GNSS time by
+//! [`track_time`]; until then it counts from boot.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use defmt::{info, warn};
+use embassy_time::{Duration, Instant};
+
+use super::{BLOCK_LEN, BlockCipher, CryptoError};
+use crate::events::{self, Event};
+
+/// Start of the time counter, 2020-01-01T00:00:00Z as Unix time.
+pub const EPOCH: i64 = 1_577_836_800;
+
+/// 16 bit UUID of the Eddystone service, little endian.
+pub const EDDYSTONE_UUID: [u8; 2] = [0xAA, 0xFE];
+
+/// Eddystone frame type of an EID frame.
+const FRAME_TYPE_EID: u8 = 0x30;
+
+/// Length of an ephemeral identifier.
+pub const EID_LEN: usize = 8;
+
+/// Length of an Eddystone-EID frame (service data after the UUID).
+pub const FRAME_LEN: usize = 3 + EID_LEN;
+
+/// Largest supported rotation exponent.
+pub const MAX_EXPONENT: u8 = 15;
+
+/// Time counter minus the uptime in seconds.
+static TIME_OFFSET: AtomicU32 = AtomicU32::new(0);
+
+/// Current value of the time counter in seconds.
+pub fn time_counter() -> u32 {
+    TIME_OFFSET
+        .load(Ordering::Relaxed)
+        .wrapping_add(Instant::now().as_secs() as u32)
+}
+
+/// Set the time counter to `secs` seconds since [`EPOCH`].
+pub fn set_time_counter(secs: u32) {
+    TIME_OFFSET.store(
+        secs.wrapping_sub(Instant::now().as_secs() as u32),
+        Ordering::Relaxed,
+    );
+}
+
+/// Keep the time counter in sync with the time published by the GNSS module.
+pub async fn track_time() {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[eid] no event bus subscriber available");
+        return;
+    };
+    let mut synced = false;
+    loop {
+        if let Event::TimeUpdated(dt) = events.next_message_pure().await {
+            let Ok(secs) = u32::try_from(dt.and_utc().timestamp() - EPOCH) else {
+                continue;
+            };
+            set_time_counter(secs);
+            if !synced {
+                info!("[eid] time counter synced: {}", secs);
+                synced = true;
+            }
+        }
+    }
+}
+
+/// Generates the ephemeral identifiers of a device.
+pub struct EidGenerator<C> {
+    identity: C,
+    exponent: u8,
+}
+
+impl<C: BlockCipher + From<[u8; BLOCK_LEN]>> EidGenerator<C> {
+    /// `identity` is keyed with the identity key, the identifier rotates
+    /// every 2^`exponent` seconds (at most 2^[`MAX_EXPONENT`]).
+    pub fn new(identity: C, exponent: u8) -> Result<Self, CryptoError> {
+        if exponent > MAX_EXPONENT {
+            return Err(CryptoError::InvalidLength);
+        }
+        Ok(Self { identity, exponent })
+    }
+
+    /// Rotation period of the identifier.
+    pub fn period(&self) -> Duration {
+        Duration::from_secs(1 << self.exponent)
+    }
+
+    /// Time until the identifier changes.
+    pub fn until_rotation(&self, time_counter: u32) -> Duration {
+        let period = 1u32 << self.exponent;
+        Duration::from_secs((period - time_counter % period) as u64)
+    }
+
+    /// Identifier for the time counter `secs`.
+    pub fn eid(&self, secs: u32) -> Result<[u8; EID_LEN], CryptoError> {
+        let quantized = secs & !((1u32 << self.exponent) - 1);
+        let t = quantized.to_be_bytes();
+
+        // Temporary key, changes every 2^16 seconds.
+        let mut temporary = [0; BLOCK_LEN];
+        temporary[11] = 0xFF;
+        temporary[14..].copy_from_slice(&t[..2]);
+        self.identity.encrypt_block(&mut temporary)?;
+
+        let mut block = [0; BLOCK_LEN];
+        block[11] = self.exponent;
+        block[12..].copy_from_slice(&t);
+        C::from(temporary).encrypt_block(&mut block)?;
+
+        let mut eid = [0; EID_LEN];
+        eid.copy_from_slice(&block[..EID_LEN]);
+        Ok(eid)
+    }
+
+    /// Eddystone-EID frame for the time counter `secs`, for service data
+    /// with [`EDDYSTONE_UUID`].
+    ///
+    /// `tx_power` is the calibrated TX power at 0 m in dBm.
+    pub fn frame(&self, secs: u32, tx_power: i8) -> Result<[u8; FRAME_LEN], CryptoError> {
+        let mut frame = [0; FRAME_LEN];
+        frame[0] = FRAME_TYPE_EID;
+        frame[1] = tx_power as u8;
+        frame[2] = self.exponent;
+        frame[3..].copy_from_slice(&self.eid(secs)?);
+        Ok(frame)
+    }
+}
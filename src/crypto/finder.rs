@@ -0,0 +1,68 @@
+//! Item-finder advertisements (opt-in with the `item-finder` feature).
+//!
+//! [`OfflineFinding`] advertises a public key in the offline finding format,
+//! for locating the device through a community network of finders, see the
+//! `finder_tag` binary. [`ExposureNotification`] generates the rotating
+//! service data of the exposure notification format from configured keys.
+//!
+//! Both formats need the random address of the advertisements to match:
+//! the address of an offline finding key is derived from the key, and the
+//! address of exposure notifications has to be rotated together with the
+//! identifier, every [`EN_INTERVAL_SECS`].
+
+use embassy_time::Duration;
+pub use nrf52_radio_core::finder::{
+    AEM_LEN, APPLE_COMPANY_ID, EN_INTERVAL_SECS, EN_UUID, OFFLINE_FINDING_KEY_LEN, OfflineFinding,
+    RPI_LEN,
+};
+use nrf52_radio_core::finder::{en_interval, en_metadata, en_service_data, rpi_block};
+
+use super::{BlockCipher, CryptoError, eid};
+
+/// Length of the exposure notification service data after the UUID.
+pub const EN_SERVICE_DATA_LEN: usize = RPI_LEN + AEM_LEN;
+
+/// Current Unix time in seconds, from the GNSS synced [`eid`] time counter.
+pub fn unix_time() -> i64 {
+    eid::time_counter() as i64 + eid::EPOCH
+}
+
+/// Generates the exposure notification service data.
+///
+/// The keys are the rolling proximity identifier key and the associated
+/// encrypted metadata key, both derived from a temporary exposure key with
+/// HKDF by whoever provisions the device.
+pub struct ExposureNotification<C> {
+    rpi_key: C,
+    aem_key: C,
+    tx_power: i8,
+}
+
+impl<C: BlockCipher> ExposureNotification<C> {
+    /// `tx_power` is the advertised TX power in dBm.
+    pub fn new(rpi_key: C, aem_key: C, tx_power: i8) -> Self {
+        Self {
+            rpi_key,
+            aem_key,
+            tx_power,
+        }
+    }
+
+    /// Service data for the Unix time `secs`.
+    pub fn service_data(&self, secs: i64) -> Result<[u8; EN_SERVICE_DATA_LEN], CryptoError> {
+        let mut rpi = rpi_block(en_interval(secs));
+        self.rpi_key.encrypt_block(&mut rpi)?;
+
+        // AES-CTR with the identifier as initial counter block.
+        let mut keystream = rpi;
+        self.aem_key.encrypt_block(&mut keystream)?;
+        let mut aem = en_metadata(self.tx_power);
+        aem.iter_mut().zip(keystream).for_each(|(m, k)| *m ^= k);
+        Ok(en_service_data(&rpi, &aem))
+    }
+
+    /// Time until the identifier changes.
+    pub fn until_rotation(&self, secs: i64) -> Duration {
+        Duration::from_secs((EN_INTERVAL_SECS - secs.rem_euclid(EN_INTERVAL_SECS)) as u64)
+    }
+}
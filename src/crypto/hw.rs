@@ -0,0 +1,221 @@
+//! CryptoCell (CC310) backend of the nRF52840.
+//!
+//! AES, SHA-256, P-256 ECDH and Ed25519 verification run on the CC310
+//! through the `nrf_cc310` runtime library of the nRF5 SDK, which
+//! `build.rs` links from `NRF_CC310_LIB_DIR` with the `cryptocell` feature.
+//! [`init`] enables the peripheral, the parent module dispatches to this
+//! backend instead of the software (and SoftDevice Controller)
+//! implementations.
+//!
+//! The library isn't reentrant, its calls are serialized by a thread mode
+//! lock: they take milliseconds for ECDH and mustn't block the radio
+//! interrupts. The declarations and the sizes of the library types are
+//! generated by `build.rs` from the headers in `NRF_CC310_INCLUDE_DIR`.
+
+use core::cell::RefCell;
+
+use defmt::{info, warn};
+use embassy_nrf::pac;
+use embassy_sync::blocking_mutex::{Mutex, raw::ThreadModeRawMutex};
+
+use self::ffi::*;
+use super::{BLOCK_LEN, BlockCipher, CryptoError};
+
+/// Bindings of the `nrf_cc310` library.
+#[allow(
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    dead_code,
+    clippy::all
+)]
+mod ffi {
+    include!(concat!(env!("OUT_DIR"), "/cc310.rs"));
+}
+
+/// Prefix of an uncompressed SEC1 point.
+const POINT_UNCOMPRESSED: u8 = 0x04;
+
+/// Scratch memory of the key import, ECDH and the Ed25519 verification,
+/// used one at a time.
+#[repr(C)]
+union Temp {
+    build: CRYS_ECPKI_BUILD_TempData_t,
+    ecdh: CRYS_ECDH_TempData_t,
+    edw: CRYS_ECEDW_TempBuff_t,
+}
+
+/// Buffers of the library, too big for the task stacks.
+struct Buffers {
+    aes: SaSiAesUserContext_t,
+    public_key: CRYS_ECPKI_UserPublKey_t,
+    private_key: CRYS_ECPKI_UserPrivKey_t,
+    temp: Temp,
+}
+
+impl Buffers {
+    const fn new() -> Self {
+        // SAFETY: The library types are plain C structures, all zeros is
+        // their state before the library initializes them.
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+static BUFFERS: Mutex<ThreadModeRawMutex, RefCell<Buffers>> =
+    Mutex::new(RefCell::new(Buffers::new()));
+
+/// Run `f` with exclusive access to the library.
+fn with_buffers<R>(f: impl FnOnce(&mut Buffers) -> R) -> R {
+    BUFFERS.lock(|buffers| f(&mut buffers.borrow_mut()))
+}
+
+fn check(status: u32) -> Result<(), CryptoError> {
+    match status {
+        0 => Ok(()),
+        status => Err(CryptoError::CryptoCell(status)),
+    }
+}
+
+/// Power up the CryptoCell and initialize the library. Call before any
+/// other crypto operation.
+pub fn init() {
+    pac::CRYPTOCELL.enable().write(|w| w.set_enable(true));
+    match check(unsafe { SaSi_LibInit() }) {
+        Ok(()) => info!("[crypto] CryptoCell ready"),
+        Err(e) => warn!("[crypto] couldn't initialize the CryptoCell: {:?}", e),
+    }
+}
+
+/// AES-128 on the CryptoCell.
+pub struct CcAes {
+    key: [u8; BLOCK_LEN],
+}
+
+impl CcAes {
+    pub fn new(key: [u8; BLOCK_LEN]) -> Self {
+        Self { key }
+    }
+}
+
+impl From<[u8; BLOCK_LEN]> for CcAes {
+    fn from(key: [u8; BLOCK_LEN]) -> Self {
+        Self::new(key)
+    }
+}
+
+impl BlockCipher for CcAes {
+    fn encrypt_block(&self, block: &mut [u8; BLOCK_LEN]) -> Result<(), CryptoError> {
+        with_buffers(|buffers| {
+            let context = &mut buffers.aes as *mut _;
+            let mut key_bytes = self.key;
+            let mut key = SaSiAesUserKeyData_t {
+                pKey: key_bytes.as_mut_ptr(),
+                keySize: BLOCK_LEN,
+            };
+            let mut input = *block;
+            let mut out_len = BLOCK_LEN;
+            let result = unsafe {
+                check(SaSi_AesInit(
+                    context,
+                    SASI_AES_ENCRYPT,
+                    SASI_AES_MODE_ECB,
+                    SASI_AES_PADDING_NONE,
+                ))
+                .and_then(|()| {
+                    check(SaSi_AesSetKey(
+                        context,
+                        SASI_AES_USER_KEY,
+                        (&mut key as *mut SaSiAesUserKeyData_t).cast(),
+                        size_of::<SaSiAesUserKeyData_t>(),
+                    ))
+                })
+                .and_then(|()| {
+                    check(SaSi_AesFinish(
+                        context,
+                        BLOCK_LEN,
+                        input.as_mut_ptr(),
+                        BLOCK_LEN,
+                        block.as_mut_ptr(),
+                        &mut out_len,
+                    ))
+                })
+            };
+            unsafe { SaSi_AesFree(context) };
+            result
+        })
+    }
+}
+
+/// SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> Result<[u8; 32], CryptoError> {
+    let mut result: CRYS_HASH_Result_t = [0; 16];
+    with_buffers(|_| {
+        check(unsafe {
+            CRYS_HASH(
+                CRYS_HASH_SHA256_mode,
+                data.as_ptr().cast_mut(),
+                data.len(),
+                result.as_mut_ptr(),
+            )
+        })
+    })?;
+    // The result words hold the digest bytes in order.
+    let mut digest = [0; 32];
+    digest.copy_from_slice(&bytemuck::cast_slice::<u32, u8>(&result)[..32]);
+    Ok(digest)
+}
+
+/// P-256 ECDH shared secret (the x coordinate) of our `secret` scalar and
+/// the `peer` public key (x and y, big endian). A peer key that isn't a
+/// point of P-256 is rejected, it would leak bits of `secret`.
+pub fn ecdh_p256(secret: &[u8; 32], peer: &[u8; 64]) -> Result<[u8; 32], CryptoError> {
+    let mut point = [0; 65];
+    point[0] = POINT_UNCOMPRESSED;
+    point[1..].copy_from_slice(peer);
+    with_buffers(|buffers| unsafe {
+        let domain = CRYS_ECPKI_GetEcDomain(CRYS_ECPKI_DomainID_secp256r1);
+        // `CRYS_ECPKI_BuildPublKeyFullCheck` is a macro for this.
+        check(_DX_ECPKI_BuildPublKey(
+            domain,
+            point.as_mut_ptr(),
+            point.len() as u32,
+            ECpublKeyFullCheck,
+            &mut buffers.public_key,
+            &mut buffers.temp.build,
+        ))
+        .map_err(|_| CryptoError::InvalidKey)?;
+        check(CRYS_ECPKI_BuildPrivKey(
+            domain,
+            secret.as_ptr(),
+            secret.len() as u32,
+            &mut buffers.private_key,
+        ))?;
+        let mut shared = [0; 32];
+        let mut len = shared.len() as u32;
+        let result = check(CRYS_ECDH_SVDP_DH(
+            &mut buffers.public_key,
+            &mut buffers.private_key,
+            shared.as_mut_ptr(),
+            &mut len,
+            &mut buffers.temp.ecdh,
+        ));
+        // Don't leave the private key behind.
+        buffers.private_key = core::mem::zeroed();
+        result.map(|()| shared)
+    })
+}
+
+/// Whether `signature` is a valid Ed25519 signature of `message` by `key`.
+pub fn ed25519_verify(key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    with_buffers(|buffers| unsafe {
+        CRYS_ECEDW_Verify(
+            signature.as_ptr(),
+            signature.len(),
+            key.as_ptr(),
+            key.len(),
+            message.as_ptr().cast_mut(),
+            message.len(),
+            &mut buffers.temp.edw,
+        ) == 0
+    })
+}
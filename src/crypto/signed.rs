@@ -0,0 +1,61 @@
+//! Verification of signed updates written over BLE.
+//!
+//! The Ed25519 public key is baked in at build time from the
+//! `CONFIG_SIGNING_KEY` environment variable (64 hex digits, checked by
+//! `build.rs`). Firmware built with a key accepts only updates in the
+//! envelope of [`nrf52_radio_core::signed`] with a valid signature, firmware
+//! built without one takes unsigned updates as before, see [`required`].
+//! [`replay`](crate::replay) keeps the [sequence number](Signed::sequence)
+//! of the last update taken, older ones are refused.
+//! The signature is checked on the CryptoCell with the `cryptocell`
+//! feature.
+
+use defmt::warn;
+#[cfg(not(feature = "cryptocell"))]
+use ed25519_dalek::{Signature, VerifyingKey};
+use nrf52_radio_core::signed::decode_key;
+pub use nrf52_radio_core::signed::{
+    HEADER_LEN, KEY_LEN, Kind, SIGNATURE_LEN, Signed, SignedError, signed_len,
+};
+
+/// Public key as hex digits, `None` if the firmware was built without one.
+const SIGNING_KEY: Option<&str> = option_env!("CONFIG_SIGNING_KEY");
+
+/// Whether updates have to be signed.
+pub fn required() -> bool {
+    SIGNING_KEY.is_some()
+}
+
+/// Verify a blob signed for `kind`, returning the verified envelope.
+pub fn verify(blob: &[u8], kind: Kind) -> Result<Signed<'_>, SignedError> {
+    let signed = Signed::parse(blob, kind)?;
+    let key = SIGNING_KEY.and_then(decode_key).ok_or(SignedError::NoKey)?;
+    if !check(&key, signed.message, signed.signature)? {
+        warn!("[crypto] rejected {:?} update with a bad signature", kind);
+        return Err(SignedError::Signature);
+    }
+    Ok(signed)
+}
+
+/// Whether `signature` of `message` verifies with `key`.
+#[cfg(not(feature = "cryptocell"))]
+fn check(
+    key: &[u8; KEY_LEN],
+    message: &[u8],
+    signature: &[u8; SIGNATURE_LEN],
+) -> Result<bool, SignedError> {
+    let key = VerifyingKey::from_bytes(key).map_err(|_| SignedError::NoKey)?;
+    Ok(key
+        .verify_strict(message, &Signature::from_bytes(signature))
+        .is_ok())
+}
+
+/// Whether `signature` of `message` verifies with `key`.
+#[cfg(feature = "cryptocell")]
+fn check(
+    key: &[u8; KEY_LEN],
+    message: &[u8],
+    signature: &[u8; SIGNATURE_LEN],
+) -> Result<bool, SignedError> {
+    Ok(super::hw::ed25519_verify(key, message, signature))
+}
@@ -0,0 +1,115 @@
+//! Time-synchronized beaconing in the slots of a shared epoch.
+//!
+//! [`advertise_slots`] advertises a [`SlotBeacon`] in the slot of one unit
+//! of a [`Schedule`], timed by [`utc_us_at`]: UTC from the GNSS pulse per
+//! second (see [`gnss::pps`](crate::gnss::pps)) where it is wired, else
+//! from the slot beacons of a unit that has it, fed in with [`observe`].
+//! Units without either stay silent. A receiver tracks the units of every
+//! epoch with a [`Roster`]. The slot logic lives in
+//! [`nrf52_radio_core::epoch`].
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Instant, Timer};
+pub use nrf52_radio_core::epoch::{
+    BleSync, MAX_UNITS, Roster, SLOT_BEACON_LEN, Schedule, SlotBeacon, Source, write_missing,
+};
+use trouble_host::prelude::*;
+
+use crate::gateway::manufacturer_data;
+use crate::gnss::pps;
+
+/// Advertising interval in the slot, the shortest the specification allows.
+const ADV_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Wait before checking again for a time source.
+const UNSYNCED_RETRY: Duration = Duration::from_secs(1);
+
+static BLE_SYNC: Mutex<CriticalSectionRawMutex, RefCell<BleSync>> =
+    Mutex::new(RefCell::new(BleSync::new()));
+
+/// UTC in µs at `instant` and where it comes from, `None` without a time
+/// source.
+pub fn utc_us_at(instant: Instant) -> Option<(i64, Source)> {
+    if let Some(utc_us) = pps::utc_us_at(instant) {
+        return Some((utc_us, Source::Gnss));
+    }
+    let utc_us = BLE_SYNC.lock(|sync| sync.borrow().utc_us(instant.as_micros()))?;
+    Some((utc_us, Source::Ble))
+}
+
+/// Decode the slot beacon in advertising `data` received at `at`, and
+/// learn the time from it.
+pub fn observe(
+    schedule: &Schedule,
+    company_id: u16,
+    data: &[u8],
+    at: Instant,
+) -> Option<SlotBeacon> {
+    let beacon = SlotBeacon::decode(manufacturer_data(data, company_id)?)?;
+    if BLE_SYNC.lock(|sync| sync.borrow_mut().observe(schedule, &beacon, at.as_micros())) {
+        crate::log!(Ble, trace, "[epoch] sync from unit {}", beacon.unit);
+    }
+    Some(beacon)
+}
+
+/// Advertise the slot beacon of `unit` in its slot of every epoch.
+pub async fn advertise_slots<'d, C: Controller, P: PacketPool>(
+    peripheral: &mut Peripheral<'d, C, P>,
+    schedule: &Schedule,
+    unit: u8,
+    company_id: u16,
+) -> Result<(), BleHostError<C::Error>> {
+    let mut params = AdvertisementParameters::default();
+    params.interval_min = ADV_INTERVAL;
+    params.interval_max = ADV_INTERVAL;
+    let mut synced = false;
+    loop {
+        let now = Instant::now();
+        let Some((utc_us, _)) = utc_us_at(now) else {
+            if synced {
+                defmt::warn!("[epoch] lost the time, not advertising");
+                synced = false;
+            }
+            Timer::after(UNSYNCED_RETRY).await;
+            continue;
+        };
+        if !synced {
+            defmt::info!("[epoch] synced, advertising as unit {}", unit);
+            synced = true;
+        }
+        let (epoch, start) = schedule.next_slot(utc_us, unit);
+        Timer::at(now + Duration::from_micros((start - utc_us) as u64)).await;
+        // The source may have changed while waiting.
+        let Some((_, source)) = utc_us_at(Instant::now()) else {
+            continue;
+        };
+        let beacon = SlotBeacon {
+            unit,
+            epoch,
+            source,
+        }
+        .encode();
+        let mut adv_data = [0; 31];
+        let len = AdStructure::encode_slice(
+            &[
+                AdStructure::Flags(BR_EDR_NOT_SUPPORTED),
+                AdStructure::ManufacturerSpecificData {
+                    company_identifier: company_id,
+                    payload: &beacon,
+                },
+            ],
+            &mut adv_data[..],
+        )?;
+        let _advertiser = peripheral
+            .advertise(
+                &params,
+                Advertisement::NonconnectableNonscannableUndirected {
+                    adv_data: &adv_data[..len],
+                },
+            )
+            .await?;
+        Timer::after(Duration::from_micros(schedule.active_us() as u64)).await;
+    }
+}
@@ -0,0 +1,161 @@
+//! Application event bus.
+//!
+//! The GNSS, BLE, UI and power tasks don't call each other directly, they
+//! publish typed [`Event`]s on a shared `embassy-sync` pub/sub channel and
+//! subscribe to the events they are interested in.
+
+use chrono::NaiveDateTime;
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    pubsub::{self, PubSubChannel, Subscriber},
+};
+
+use crate::ans::NewAlert;
+use crate::battery::ChargeState;
+use crate::gnss::Fix;
+use crate::nav::MotionState;
+use crate::policy::PowerLevel;
+use crate::sensors::Sample;
+use crate::waypoint::Waypoint;
+
+/// Number of events buffered per subscriber before the oldest ones are lost.
+const CAPACITY: usize = 8;
+
+/// Maximum number of concurrent subscribers: the 17 tasks of the firmware
+/// subscribing and room for more. A task subscribing beyond it gives up on
+/// the events.
+const SUBSCRIBERS: usize = 24;
+
+/// Maximum number of concurrent (non-immediate) publishers.
+const PUBLISHERS: usize = 4;
+
+/// Kind of a button press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ButtonPress {
+    Short,
+    /// Two short presses within
+    /// [`DOUBLE_PRESS`](crate::bsp::button::DOUBLE_PRESS).
+    Double,
+    Long,
+    /// Held for [`HOLD`](crate::bsp::button::HOLD), published before release.
+    Hold,
+}
+
+/// Physical layer of a BLE connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Phy {
+    Le1M,
+    Le2M,
+    LeCoded,
+}
+
+/// Parameters of a BLE connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct ConnParams {
+    /// Connection interval in µs.
+    pub interval_us: u32,
+    /// Connection events the peripheral may skip.
+    pub latency: u16,
+    /// Supervision timeout in ms.
+    pub timeout_ms: u32,
+}
+
+/// Events exchanged between the subsystems.
+#[derive(Debug, Clone, PartialEq, defmt::Format)]
+pub enum Event {
+    /// The GNSS module reported a valid position.
+    FixAcquired(Fix),
+    /// The GNSS module reported that it has no position fix.
+    FixLost,
+    /// The GNSS module sent nothing for a while although it should be
+    /// tracking, it's missing or not powered. Reading is retried.
+    GnssAbsent,
+    /// The GNSS module reported speed and course over ground, both
+    /// low-pass filtered (see [`MotionFilter`](crate::nav::MotionFilter)).
+    MotionUpdated {
+        /// Speed over ground, 0 while stationary.
+        speed_knots: f32,
+        /// Course over ground in degrees from true north, `None` while stationary.
+        course: Option<f32>,
+        state: MotionState,
+    },
+    /// A [sensor](crate::sensors) took a measurement.
+    SensorSampled(Sample),
+    /// Barometric altitude fused with the GNSS altitude, in m above mean
+    /// sea level.
+    AltitudeUpdated(f32),
+    /// Tilt-compensated compass heading in degrees from magnetic north.
+    HeadingUpdated(f32),
+    /// The IMU detected motion (`true`) or rest (`false`).
+    MotionDetected(bool),
+    /// The GNSS module reported the current UTC time.
+    TimeUpdated(#[defmt(Debug2Format)] NaiveDateTime),
+    /// Advertising started, no central is connected.
+    AdvertisingStarted,
+    /// A central connected to the GATT server.
+    CentralConnected {
+        /// Address of the central, least significant byte first.
+        address: [u8; 6],
+        /// Negotiated ATT MTU.
+        mtu: u16,
+    },
+    /// The connected central disconnected.
+    CentralDisconnected,
+    /// The PHY of the connection changed.
+    PhyUpdated { tx: Phy, rx: Phy },
+    /// The ATT MTU of the connection changed.
+    MtuUpdated(u16),
+    /// The connection parameters changed.
+    ConnParamsUpdated(ConnParams),
+    /// Link layer payload lengths changed with Data Length Extension.
+    DataLengthUpdated { tx_octets: u16, rx_octets: u16 },
+    /// RSSI of the connection in dBm.
+    RssiUpdated(i8),
+    /// The connected phone notified a new alert, see [`ans`](crate::ans).
+    PhoneNotification(NewAlert),
+    /// The user button was pressed.
+    ButtonPressed(ButtonPress),
+    /// The estimated battery state of charge changed.
+    BatteryUpdated { percent: u8, millivolts: u16 },
+    /// The charger started or stopped charging, or USB power was (dis)connected.
+    ChargeStateChanged(ChargeState),
+    /// The battery state of charge dropped below the low battery threshold.
+    BatteryLow { millivolts: u16 },
+    /// The [battery policy](crate::policy) changed how much power to save.
+    PowerLevelChanged(PowerLevel),
+    /// The navigation target was set or cleared.
+    WaypointChanged(Option<Waypoint>),
+    /// The current position left the configured geofence.
+    FenceExited,
+    /// The [settings](crate::settings) were changed.
+    SettingsChanged,
+    /// Ship mode was requested, e.g. over BLE.
+    ShipModeRequested,
+    /// The device enters System OFF shortly, devices should be powered down.
+    ShuttingDown,
+}
+
+/// Event bus type.
+pub type EventBus =
+    PubSubChannel<CriticalSectionRawMutex, Event, CAPACITY, SUBSCRIBERS, PUBLISHERS>;
+
+/// Subscriber of the [`EVENTS`] bus.
+pub type EventSubscriber =
+    Subscriber<'static, CriticalSectionRawMutex, Event, CAPACITY, SUBSCRIBERS, PUBLISHERS>;
+
+/// The application event bus.
+pub static EVENTS: EventBus = PubSubChannel::new();
+
+/// Publish an event to all subscribers without waiting.
+///
+/// If a subscriber lags behind, its oldest event is dropped.
+pub fn publish(event: Event) {
+    EVENTS.immediate_publisher().publish_immediate(event);
+}
+
+/// Subscribe to the event bus.
+///
+/// Fails if there are already [`SUBSCRIBERS`] subscribers.
+pub fn subscribe() -> Result<EventSubscriber, pubsub::Error> {
+    EVENTS.subscriber()
+}
@@ -0,0 +1,128 @@
+//! Factory reset with a staged confirmation.
+//!
+//! A reset takes two [`request`]s within [`CONFIRM_WINDOW`]: the first one
+//! arms it, the second one confirms it, so a single stray command or button
+//! press doesn't wipe the device. The [`FactoryReset`] subsystem then erases
+//! the [`ERASED`] regions, the track log, the crash dump, the telemetry
+//! queue and the settings, which hold the accept list as there are no bonds
+//! stored yet, and reboots into the defaults. The
+//! [replay protection](crate::replay) is kept. It keeps the shared flash
+//! locked from the first erase to the reboot, so the other flash users can't
+//! write their state back, and drops the [retained state](crate::retained).
+//! The `factory_reset` shell [`COMMANDS`] request it as well.
+
+use core::cell::Cell;
+use core::fmt::Write;
+
+use defmt::warn;
+use embassy_futures::yield_now;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant};
+use embedded_storage_async::nor_flash::NorFlash;
+
+use crate::bsp::flash::{
+    CRASH_DUMP, FlashError, PAGE_SIZE, Region, SETTINGS, SharedFlash, TELEMETRY, TRACK_LOG,
+};
+use crate::retained;
+use crate::shell::{Command, Output};
+use crate::supervisor::Subsystem;
+
+/// Time to confirm an armed reset.
+pub const CONFIRM_WINDOW: Duration = Duration::from_secs(10);
+
+/// Regions erased by a reset.
+pub const ERASED: [Region; 4] = [TRACK_LOG, CRASH_DUMP, TELEMETRY, SETTINGS];
+
+/// Result of a [`request`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Stage {
+    /// Request again within [`CONFIRM_WINDOW`] to reset.
+    Armed,
+    /// The device is reset.
+    Confirmed,
+}
+
+/// When the reset was armed.
+static ARMED: Mutex<CriticalSectionRawMutex, Cell<Option<Instant>>> = Mutex::new(Cell::new(None));
+
+/// Signalled when the reset was confirmed.
+static CONFIRMED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Arm the reset, or confirm it if it was armed within [`CONFIRM_WINDOW`].
+pub fn request() -> Stage {
+    let now = Instant::now();
+    let confirmed = ARMED.lock(|armed| match armed.take() {
+        Some(at) if now.duration_since(at) <= CONFIRM_WINDOW => true,
+        _ => {
+            armed.set(Some(now));
+            false
+        }
+    });
+    if confirmed {
+        warn!("[factory_reset] confirmed");
+        CONFIRMED.signal(());
+        Stage::Confirmed
+    } else {
+        warn!(
+            "[factory_reset] armed, request again within {} s to confirm",
+            CONFIRM_WINDOW.as_secs()
+        );
+        Stage::Armed
+    }
+}
+
+fn factory_reset_command(_args: &str, out: &mut Output) -> core::fmt::Result {
+    match request() {
+        Stage::Armed => write!(
+            out,
+            "repeat within {} s to erase all data\r\n",
+            CONFIRM_WINDOW.as_secs()
+        ),
+        Stage::Confirmed => write!(out, "erasing, rebooting\r\n"),
+    }
+}
+
+/// Shell command to request the reset.
+pub const COMMANDS: &[Command] = &[Command {
+    name: "factory_reset",
+    help: "erase settings and logs, twice within 10 s",
+    handler: factory_reset_command,
+}];
+
+/// Erases the data regions once the reset was confirmed.
+pub struct FactoryReset {
+    flash: &'static SharedFlash,
+}
+
+impl FactoryReset {
+    pub fn new(flash: &'static SharedFlash) -> Self {
+        Self { flash }
+    }
+}
+
+impl Subsystem for FactoryReset {
+    type Error = FlashError;
+
+    const NAME: &'static str = "factory_reset";
+
+    /// Erase page by page and reboot once confirmed.
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        CONFIRMED.wait().await;
+        // Held until the reboot, the other users wait for it.
+        let mut flash = self.flash.lock().await;
+        for region in ERASED {
+            for page in (region.start..region.end()).step_by(PAGE_SIZE as usize) {
+                if let Err(e) = flash.erase(page, page + PAGE_SIZE).await {
+                    // Retry after the supervisor restarted us.
+                    CONFIRMED.signal(());
+                    return Err(e);
+                }
+                yield_now().await;
+            }
+        }
+        retained::invalidate();
+        warn!("[factory_reset] erased, rebooting");
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+}
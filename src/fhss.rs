@@ -0,0 +1,401 @@
+//! Frequency hopping point-to-point link on the raw radio.
+//!
+//! An alternative to BLE for telemetry in congested environments: the
+//! [`Transmitter`] sends every attempt on the next channel of the
+//! [`HopTable`] in its own short [timeslot](crate::bsp::ble::timeslot) and
+//! waits for the acknowledgement on the same channel. The [`Receiver`]
+//! listens in long slots on the channel of the hop it expects next. After a
+//! lost frame it stays there until the transmitter comes around again, at
+//! worst one round through the [`CHANNELS`].
+//!
+//! Payloads go in with [`send`] and come out with [`receive`], the ARQ and
+//! the hop table live in [`nrf52_radio_core::fhss`].
+
+use core::cell::{RefCell, UnsafeCell};
+
+use embassy_nrf::pac::{self, radio::vals};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::Duration;
+use heapless::Vec;
+pub use nrf52_radio_core::fhss::{
+    CHANNELS, Frame, HopTable, Kind, MAX_ATTEMPTS, MAX_FRAME_LEN, MAX_PAYLOAD, Outcome,
+};
+use nrf52_radio_core::fhss::{Receiver as ArqReceiver, Sender};
+
+use crate::bsp::ble::timeslot::{Action, Request, TimeslotHandler};
+
+/// Shared key of the hop table, change it for every pair of boards.
+pub const LINK_KEY: [u8; 16] = *b"nrf52-radio-fhss";
+
+/// Base address and prefix of the link packets.
+const BASE_ADDRESS: u32 = 0x4648_5353;
+const PREFIX: u8 = 0x5a;
+
+/// Slot of one attempt of the transmitter.
+const ATTEMPT_SLOT: Duration = Duration::from_micros(2000);
+
+/// Distance between the attempts of the transmitter.
+pub const ATTEMPT_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Wait for the acknowledgement after starting to send, in µs of TIMER0.
+const ACK_TIMEOUT_US: u32 = 1500;
+
+/// Slot of the receiver, listening most of the time.
+const LISTEN_SLOT: Duration = Duration::from_millis(50);
+
+/// The receiver ends its slot this long before the granted length runs out.
+const SLOT_MARGIN: Duration = Duration::from_micros(500);
+
+static TX_QUEUE: Channel<CriticalSectionRawMutex, Vec<u8, MAX_PAYLOAD>, 1> = Channel::new();
+static OUTCOME: Signal<CriticalSectionRawMutex, Outcome> = Signal::new();
+static RX_QUEUE: Channel<CriticalSectionRawMutex, Vec<u8, MAX_PAYLOAD>, 4> = Channel::new();
+
+/// Send `payload` (cut to [`MAX_PAYLOAD`]) through the [`Transmitter`],
+/// returning once it was acknowledged or dropped.
+pub async fn send(payload: &[u8]) -> Outcome {
+    let payload = &payload[..payload.len().min(MAX_PAYLOAD)];
+    OUTCOME.reset();
+    TX_QUEUE.send(Vec::from_slice(payload).unwrap()).await;
+    OUTCOME.wait().await
+}
+
+/// Wait for the next new payload of the [`Receiver`].
+pub async fn receive() -> Vec<u8, MAX_PAYLOAD> {
+    RX_QUEUE.receive().await
+}
+
+/// Length field and frame of a packet.
+struct PacketBuffer(UnsafeCell<[u8; 1 + MAX_FRAME_LEN]>);
+
+// SAFETY: only accessed from the timeslot callback, which isn't reentrant.
+unsafe impl Sync for PacketBuffer {}
+
+static PACKET: PacketBuffer = PacketBuffer(UnsafeCell::new([0; 1 + MAX_FRAME_LEN]));
+
+/// Write an encoded frame to the packet buffer.
+///
+/// # Safety
+///
+/// The RADIO must be disabled.
+unsafe fn write_frame(frame: &[u8]) {
+    // SAFETY: the caller guarantees the RADIO doesn't access the buffer.
+    let packet = unsafe { &mut *PACKET.0.get() };
+    packet[0] = frame.len() as u8;
+    packet[1..1 + frame.len()].copy_from_slice(frame);
+}
+
+/// Decode the received packet, `None` for a CRC error or invalid frame.
+///
+/// # Safety
+///
+/// The RADIO must be disabled.
+unsafe fn read_frame<'a>() -> Option<Frame<'a>> {
+    if pac::RADIO.crcstatus().read().crcstatus() != vals::Crcstatus::CRCOK {
+        return None;
+    }
+    // SAFETY: the caller guarantees the RADIO doesn't access the buffer.
+    let packet = unsafe { &*PACKET.0.get() };
+    let len = (packet[0] as usize).min(MAX_FRAME_LEN);
+    Frame::decode(&packet[1..1 + len])
+}
+
+/// Configure the RADIO for the link on `frequency` and arm the slot guard
+/// at `guard_us` of TIMER0 (1 MHz from the start of the slot).
+fn setup(frequency: u8, guard_us: u32) {
+    let radio = pac::RADIO;
+    radio.mode().write(|w| w.set_mode(vals::Mode::NRF_1MBIT));
+    radio
+        .txpower()
+        .write(|w| w.set_txpower(vals::Txpower::POS4_DBM));
+    radio.pcnf0().write(|w| w.set_lflen(8));
+    radio.pcnf1().write(|w| {
+        w.set_maxlen(MAX_FRAME_LEN as u8);
+        w.set_balen(4);
+        w.set_whiteen(true);
+    });
+    radio.base0().write_value(BASE_ADDRESS);
+    radio.prefix0().write(|w| w.set_ap(0, PREFIX));
+    radio.txaddress().write(|w| w.set_txaddress(0));
+    radio.rxaddresses().write(|w| w.set_addr(0, true));
+    radio.crccnf().write(|w| w.set_len(vals::Len::THREE));
+    radio.crcinit().write(|w| w.set_crcinit(0x55_5555));
+    radio.crcpoly().write(|w| w.set_crcpoly(0x00_065b));
+    radio.packetptr().write_value(PACKET.0.get() as u32);
+    tune(frequency);
+    radio.events_disabled().write_value(0);
+    radio.intenset().write(|w| w.set_disabled(true));
+
+    let timer = pac::TIMER0;
+    timer.cc(0).write_value(guard_us);
+    timer.events_compare(0).write_value(0);
+    timer.intenset().write(|w| w.set_compare(0, true));
+}
+
+/// Switch to `frequency` in MHz above 2400, the RADIO must be disabled.
+fn tune(frequency: u8) {
+    let radio = pac::RADIO;
+    radio.frequency().write(|w| w.set_frequency(frequency));
+    radio
+        .datawhiteiv()
+        .write(|w| w.set_datawhiteiv(frequency & 0x3f));
+}
+
+/// Disable the RADIO and the slot guard, so the slot can end.
+fn stop() {
+    let radio = pac::RADIO;
+    radio.shorts().write(|_| {});
+    radio.intenclr().write(|w| w.set_disabled(true));
+    if radio.state().read().state() != vals::State::DISABLED {
+        radio.events_disabled().write_value(0);
+        radio.tasks_disable().write_value(1);
+        while radio.events_disabled().read() == 0 {}
+    }
+    radio.events_disabled().write_value(0);
+    let timer = pac::TIMER0;
+    timer.intenclr().write(|w| w.set_compare(0, true));
+    timer.events_compare(0).write_value(0);
+}
+
+/// Whether the DISABLED event of the RADIO is pending, clearing it.
+fn take_disabled() -> bool {
+    let radio = pac::RADIO;
+    let disabled = radio.events_disabled().read() != 0;
+    radio.events_disabled().write_value(0);
+    disabled
+}
+
+struct TxState {
+    table: HopTable,
+    hop: u16,
+    sender: Sender,
+    /// Whether the frame is out and the RADIO waits for the ack.
+    awaiting_ack: bool,
+}
+
+/// Sending end, one attempt per slot.
+pub struct Transmitter {
+    state: Mutex<CriticalSectionRawMutex, RefCell<Option<TxState>>>,
+}
+
+impl Transmitter {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Derive the hop table from `key` and return the first request of the
+    /// session.
+    pub fn first_request(&self, key: &[u8; 16]) -> Request {
+        let state = TxState {
+            table: HopTable::new(key),
+            hop: 0,
+            sender: Sender::new(),
+            awaiting_ack: false,
+        };
+        self.state.lock(|s| s.replace(Some(state)));
+        Request::Earliest {
+            length: ATTEMPT_SLOT,
+            timeout: ATTEMPT_INTERVAL,
+        }
+    }
+
+    /// End the attempt with `ack`, hop on and request the next slot.
+    fn finish(state: &mut TxState, ack: Option<&Frame>) -> Action {
+        stop();
+        state.awaiting_ack = false;
+        if let Some(outcome) = state.sender.answer(ack) {
+            OUTCOME.signal(outcome);
+        }
+        state.hop = state.hop.wrapping_add(1);
+        Action::Request(Request::Normal {
+            length: ATTEMPT_SLOT,
+            distance: ATTEMPT_INTERVAL,
+        })
+    }
+
+    fn with_state(&self, f: impl FnOnce(&mut TxState) -> Action) -> Action {
+        self.state.lock(|s| match s.borrow_mut().as_mut() {
+            Some(state) => f(state),
+            None => Action::End,
+        })
+    }
+}
+
+impl Default for Transmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeslotHandler for Transmitter {
+    fn start(&self) -> Action {
+        self.with_state(|state| {
+            if state.sender.is_idle()
+                && let Ok(payload) = TX_QUEUE.try_receive()
+            {
+                state.sender.load(&payload);
+            }
+            let mut frame = [0; MAX_FRAME_LEN];
+            let Some(len) = state.sender.transmit(state.hop, &mut frame) else {
+                // Nothing to send, keep the slots going.
+                return Action::Request(Request::Normal {
+                    length: ATTEMPT_SLOT,
+                    distance: ATTEMPT_INTERVAL,
+                });
+            };
+            setup(state.table.frequency(state.hop), ACK_TIMEOUT_US);
+            // SAFETY: the RADIO is disabled at the start of the slot.
+            unsafe { write_frame(&frame[..len]) };
+            pac::RADIO.shorts().write(|w| {
+                w.set_ready_start(true);
+                w.set_end_disable(true);
+                w.set_disabled_rxen(true);
+            });
+            pac::RADIO.tasks_txen().write_value(1);
+            Action::None
+        })
+    }
+
+    fn timer0(&self) -> Action {
+        self.with_state(|state| Self::finish(state, None))
+    }
+
+    fn radio(&self) -> Action {
+        if !take_disabled() {
+            return Action::None;
+        }
+        self.with_state(|state| {
+            if !state.awaiting_ack {
+                // Sent, the shortcut started receiving the ack.
+                state.awaiting_ack = true;
+                pac::RADIO.shorts().write(|w| {
+                    w.set_ready_start(true);
+                    w.set_end_disable(true);
+                });
+                return Action::None;
+            }
+            // SAFETY: the RADIO is disabled.
+            let ack = unsafe { read_frame() };
+            Self::finish(state, ack.as_ref())
+        })
+    }
+}
+
+struct RxState {
+    table: HopTable,
+    /// Hop the next frame is expected on.
+    hop: u16,
+    receiver: ArqReceiver,
+    /// Whether the RADIO is sending an ack.
+    acking: bool,
+}
+
+/// Receiving end, listening in long slots.
+pub struct Receiver {
+    state: Mutex<CriticalSectionRawMutex, RefCell<Option<RxState>>>,
+}
+
+impl Receiver {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Derive the hop table from `key` and return the first request of the
+    /// session.
+    pub fn first_request(&self, key: &[u8; 16]) -> Request {
+        let state = RxState {
+            table: HopTable::new(key),
+            hop: 0,
+            receiver: ArqReceiver::new(),
+            acking: false,
+        };
+        self.state.lock(|s| s.replace(Some(state)));
+        Self::request()
+    }
+
+    fn request() -> Request {
+        Request::Earliest {
+            length: LISTEN_SLOT,
+            timeout: LISTEN_SLOT,
+        }
+    }
+
+    fn listen(state: &mut RxState) {
+        state.acking = false;
+        tune(state.table.frequency(state.hop));
+        pac::RADIO.shorts().write(|w| {
+            w.set_ready_start(true);
+            w.set_end_disable(true);
+        });
+        pac::RADIO.tasks_rxen().write_value(1);
+    }
+
+    fn with_state(&self, f: impl FnOnce(&mut RxState) -> Action) -> Action {
+        self.state.lock(|s| match s.borrow_mut().as_mut() {
+            Some(state) => f(state),
+            None => Action::End,
+        })
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeslotHandler for Receiver {
+    fn start(&self) -> Action {
+        self.with_state(|state| {
+            let guard = (LISTEN_SLOT - SLOT_MARGIN).as_micros() as u32;
+            setup(state.table.frequency(state.hop), guard);
+            Self::listen(state);
+            Action::None
+        })
+    }
+
+    fn timer0(&self) -> Action {
+        stop();
+        Action::Request(Self::request())
+    }
+
+    fn radio(&self) -> Action {
+        if !take_disabled() {
+            return Action::None;
+        }
+        self.with_state(|state| {
+            if state.acking {
+                // Follow the transmitter to its next hop.
+                Self::listen(state);
+                return Action::None;
+            }
+            // SAFETY: the RADIO is disabled.
+            let Some(frame) = (unsafe { read_frame() }).filter(|f| f.kind == Kind::Data) else {
+                Self::listen(state);
+                return Action::None;
+            };
+            if state.receiver.receive(&frame) {
+                let _ = RX_QUEUE.try_send(Vec::from_slice(frame.payload).unwrap());
+            }
+            let mut ack = [0; MAX_FRAME_LEN];
+            let len = Frame {
+                kind: Kind::Ack,
+                seq: frame.seq,
+                hop: frame.hop,
+                payload: &[],
+            }
+            .encode(&mut ack);
+            state.hop = frame.hop.wrapping_add(1);
+            state.acking = true;
+            // SAFETY: the RADIO is disabled, the received frame was copied.
+            unsafe { write_frame(&ack[..len]) };
+            pac::RADIO.tasks_txen().write_value(1);
+            Action::None
+        })
+    }
+}
@@ -0,0 +1,7 @@
+//! Fixed-size text for the OLED and read-only GATT characteristics.
+//!
+//! The functions live in [`nrf52_radio_core::fmt`] to be tested on the host.
+
+pub use nrf52_radio_core::fmt::{
+    COORDINATES_LEN, MAC_LEN, VERSION_LEN, coordinates, mac, parse_mac, version,
+};
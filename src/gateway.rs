@@ -0,0 +1,8 @@
+//! Decoding of received beacons for the `ble_gateway` binary.
+//!
+//! The decoders live in [`nrf52_radio_core::gateway`] to be tested on the
+//! host.
+
+pub use nrf52_radio_core::gateway::{
+    BEACON_PLAINTEXT_LEN, Record, ad_structures, eddystone_frame, manufacturer_data,
+};
@@ -0,0 +1,43 @@
+//! GATT services shared by the binaries.
+//!
+//! `#[gatt_server]` needs all services of a binary in one struct, but the
+//! services themselves are plain `#[gatt_service]` structs. They are defined
+//! here once, and a binary picks the ones it needs as fields of its server,
+//! next to its own services:
+//!
+//! ```ignore
+//! use nrf52_radio_rs::gatt::{BatteryService, DeviceInfoService, NusService};
+//!
+//! #[gatt_server]
+//! struct Server {
+//!     battery_service: BatteryService,
+//!     nus_service: NusService,
+//!     device_info_service: DeviceInfoService,
+//!     my_service: MyService,
+//! }
+//! ```
+//!
+//! The characteristics are public, so the binary reads, writes and notifies
+//! them through the server as before, e.g. `server.nus_service.tx`. Value
+//! encoders that go with a service live in its module.
+
+pub mod battery;
+pub mod device_info;
+pub mod generic_attribute;
+pub mod heart_rate;
+pub mod location;
+pub mod nus;
+pub mod presentation;
+pub mod proximity;
+pub mod ranging;
+pub mod smp;
+
+pub use battery::BatteryService;
+pub use device_info::DeviceInfoService;
+pub use generic_attribute::GenericAttributeService;
+pub use heart_rate::HeartRateService;
+pub use location::LocationService;
+pub use nus::NusService;
+pub use proximity::{ImmediateAlertService, LinkLossService, TxPowerService};
+pub use ranging::RangingService;
+pub use smp::SmpService;
@@ -0,0 +1,21 @@
+//! Battery Service with the level and the power state.
+
+use trouble_host::prelude::*;
+
+use super::presentation;
+
+/// Battery service
+#[gatt_service(uuid = service::BATTERY)]
+pub struct BatteryService {
+    /// Battery Level
+    #[descriptor(uuid = descriptors::VALID_RANGE, read, value = [0, 100])]
+    #[descriptor(uuid = descriptors::MEASUREMENT_DESCRIPTION, name = "hello", read, value = "Battery Level")]
+    #[descriptor(uuid = descriptors::CHARACTERISTIC_PRESENTATION_FORMAT, read, value = presentation::PERCENT.to_bytes())]
+    #[characteristic(uuid = characteristic::BATTERY_LEVEL, read, notify, value = 10)]
+    pub level: u8,
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001100000", write, read, notify)]
+    pub status: bool,
+    /// Battery Power State, see [`ChargeState::power_state`](crate::battery::ChargeState::power_state)
+    #[characteristic(uuid = "00002a1a-0000-1000-8000-00805f9b34fb", read, notify)]
+    pub power_state: u8,
+}
@@ -0,0 +1,13 @@
+//! Device Information Service with the firmware revision.
+
+use trouble_host::prelude::*;
+
+use crate::build_info;
+
+/// Device information service
+#[gatt_service(uuid = "0000180a-0000-1000-8000-00805f9b34fb")]
+pub struct DeviceInfoService {
+    /// Firmware revision string, see [`build_info::text`]
+    #[characteristic(uuid = "00002a26-0000-1000-8000-00805f9b34fb", read)]
+    pub firmware_revision: heapless::Vec<u8, { build_info::TEXT_LEN }>,
+}
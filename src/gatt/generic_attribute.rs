@@ -0,0 +1,97 @@
+//! Generic Attribute Service with Service Changed and the Database Hash.
+//!
+//! The layout of the services is identified by a revision, see
+//! [`nrf52_radio_core::gatt_cache`]. [`announce_changes`] tells the first
+//! central after an update of the layout to discover the services again.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, with_timeout};
+pub use nrf52_radio_core::gatt_cache::{
+    ALL_HANDLES, DATABASE_HASH_LEN, database_hash, service_changed,
+};
+use trouble_host::prelude::*;
+
+use crate::settings;
+
+/// Time the central gets to subscribe to Service Changed after connecting.
+const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Indications bit of a Client Characteristic Configuration value.
+const CCCD_INDICATE: u16 = 0x0002;
+
+/// Whether the central enabled the Service Changed indications, set by
+/// [`written`].
+static SUBSCRIBED: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+
+/// Generic attribute service
+#[gatt_service(uuid = "00001801-0000-1000-8000-00805f9b34fb")]
+pub struct GenericAttributeService {
+    /// Service Changed, the handle range of the changed services
+    #[characteristic(uuid = "00002a05-0000-1000-8000-00805f9b34fb", indicate)]
+    pub service_changed: [u8; 4],
+    /// Database Hash, see [`database_hash`]
+    #[characteristic(uuid = "00002b2a-0000-1000-8000-00805f9b34fb", read)]
+    pub database_hash: [u8; DATABASE_HASH_LEN],
+}
+
+/// Track the subscription to Service Changed, call for every write of the
+/// central to `handle`.
+pub fn written(service: &GenericAttributeService, handle: u16, data: &[u8]) {
+    if Some(handle) != service.service_changed.cccd_handle {
+        return;
+    }
+    let config = match data {
+        [low, high] => u16::from_le_bytes([*low, *high]),
+        _ => 0,
+    };
+    SUBSCRIBED.signal(config & CCCD_INDICATE != 0);
+}
+
+/// Indicate Service Changed for all handles if the layout `revision`
+/// differs from the one announced last, which is stored in the settings.
+///
+/// The indication is sent once the central subscribed, see [`written`], and
+/// the revision is stored once the central confirmed it. Without bonding the
+/// subscription isn't kept across connections, a central that doesn't
+/// subscribe within [`SUBSCRIBE_TIMEOUT`] or disconnects before confirming
+/// misses the indication and the next connection tries again.
+pub async fn announce_changes<P: PacketPool>(
+    service: &GenericAttributeService,
+    conn: &GattConnection<'_, '_, P>,
+    revision: u16,
+) {
+    SUBSCRIBED.reset();
+    let announced = settings::get().gatt_revision;
+    if announced == revision {
+        return;
+    }
+    let subscribed = async { while !SUBSCRIBED.wait().await {} };
+    if with_timeout(SUBSCRIBE_TIMEOUT, subscribed).await.is_err() {
+        crate::log!(
+            Ble,
+            info,
+            "[gatt] central didn't subscribe to service changed"
+        );
+        return;
+    }
+    // Returns once the central confirmed the indication.
+    match service.service_changed.indicate(conn, &ALL_HANDLES).await {
+        Ok(()) => {
+            crate::log!(
+                Ble,
+                info,
+                "[gatt] service changed confirmed, layout {} -> {}",
+                announced,
+                revision
+            );
+            settings::update(|s| s.gatt_revision = revision);
+        }
+        Err(e) => crate::log!(
+            Ble,
+            warn,
+            "[gatt] couldn't indicate service changed: {:?}",
+            e
+        ),
+    }
+}
@@ -0,0 +1,15 @@
+//! Heart Rate Service, e.g. of the [`hrs_sim`](crate::hrs_sim) simulator.
+
+pub use nrf52_radio_core::hrs::{LOCATION_CHEST, MEASUREMENT_LEN, measurement};
+use trouble_host::prelude::*;
+
+/// Heart rate service
+#[gatt_service(uuid = service::HEART_RATE)]
+pub struct HeartRateService {
+    /// Heart Rate Measurement, see [`measurement`]
+    #[characteristic(uuid = characteristic::HEART_RATE_MEASUREMENT, notify)]
+    pub measurement: heapless::Vec<u8, MEASUREMENT_LEN>,
+    /// Body Sensor Location
+    #[characteristic(uuid = characteristic::BODY_SENSOR_LOCATION, read, value = LOCATION_CHEST)]
+    pub location: u8,
+}
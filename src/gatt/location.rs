@@ -0,0 +1,84 @@
+//! Location and Navigation Service with the current time and position.
+
+use bytemuck::{Pod, Zeroable, checked::try_cast};
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use trouble_host::prelude::*;
+
+use crate::gnss::Fix;
+
+/// Current Time characteristic of BLE
+/// see GATT specification supplement (2023-12-23)
+/// section 3.71
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct CurrentTime {
+    year: u16,
+    month: u8,
+    day: u8,
+    hours: u8,
+    minutes: u8,
+    seconds: u8,
+    day_of_week: u8,
+    fractions_256: u8,
+    adj_reason: u8,
+}
+
+impl CurrentTime {
+    pub fn from(date_time: &NaiveDateTime) -> Self {
+        let date = date_time.date();
+        let time = date_time.time();
+        CurrentTime {
+            year: date.year() as u16,
+            month: date.month() as u8,
+            day: date.day() as u8,
+            hours: time.hour() as u8,
+            minutes: time.minute() as u8,
+            seconds: time.second() as u8,
+            day_of_week: 0u8,
+            fractions_256: 0u8,
+            adj_reason: 0u8,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 10] {
+        try_cast(self).expect("CurrentTime should always have a representation of size 10 bytes")
+    }
+}
+
+/// Flags of the Location and Speed characteristic: location and elevation
+/// present, position ok.
+const LOCATION_FLAGS: u16 = 1 << 2 | 1 << 3 | 1 << 7;
+
+/// Elevation source flag of the Location and Speed characteristic: barometric.
+const ELEVATION_BAROMETRIC: u16 = 1 << 10;
+
+/// Length of the Location and Speed value with location and elevation.
+pub const LOCATION_LEN: usize = 13;
+
+/// Location and Speed characteristic of the LNS (GATT specification
+/// supplement, section 3.138) with location and elevation. The barometric
+/// altitude is used if there is a barometer, the GNSS altitude otherwise.
+pub fn location_and_speed(fix: &Fix, barometric: Option<f32>) -> Option<[u8; LOCATION_LEN]> {
+    let (elevation, flags) = match (barometric, fix.altitude) {
+        (Some(altitude), _) => (altitude, LOCATION_FLAGS | ELEVATION_BAROMETRIC),
+        (None, Some(altitude)) => (altitude, LOCATION_FLAGS),
+        (None, None) => return None,
+    };
+    let mut value = [0; LOCATION_LEN];
+    value[..2].copy_from_slice(&flags.to_le_bytes());
+    value[2..6].copy_from_slice(&((fix.latitude * 1e7) as i32).to_le_bytes());
+    value[6..10].copy_from_slice(&((fix.longitude * 1e7) as i32).to_le_bytes());
+    // sint24 in 1/100 m
+    value[10..].copy_from_slice(&((elevation * 100.0) as i32).to_le_bytes()[..3]);
+    Some(value)
+}
+
+/// GNSS service
+#[gatt_service(uuid = service::LOCATION_AND_NAVIGATION)]
+pub struct LocationService {
+    #[characteristic(uuid = characteristic::CURRENT_TIME, read, notify)]
+    pub time: [u8; 10],
+    /// Location and elevation, see [`location_and_speed`]
+    #[characteristic(uuid = "00002a67-0000-1000-8000-00805f9b34fb", read, notify)]
+    pub location: [u8; LOCATION_LEN],
+}
@@ -0,0 +1,21 @@
+//! Nordic UART Service, a byte stream in both directions.
+
+use trouble_host::prelude::*;
+
+/// Length of the values, fits into an ATT MTU of 247.
+pub const CHUNK_LEN: usize = 244;
+
+/// Nordic UART Service, e.g. to pass the raw NMEA stream of the GNSS module through
+#[gatt_service(uuid = "6e400001-b5a3-f393-e0a9-e50e24dcca9e")]
+pub struct NusService {
+    /// Bytes written by the central
+    #[characteristic(
+        uuid = "6e400002-b5a3-f393-e0a9-e50e24dcca9e",
+        write,
+        write_without_response
+    )]
+    pub rx: heapless::Vec<u8, CHUNK_LEN>,
+    /// Bytes notified to the central
+    #[characteristic(uuid = "6e400003-b5a3-f393-e0a9-e50e24dcca9e", notify)]
+    pub tx: heapless::Vec<u8, CHUNK_LEN>,
+}
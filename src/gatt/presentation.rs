@@ -0,0 +1,27 @@
+//! Descriptors telling generic BLE browsers how to show a characteristic.
+//!
+//! A characteristic declares a human-readable Characteristic User
+//! Description and, for a value with a format and unit, a Characteristic
+//! Presentation Format from the [`Format`]s declared once in
+//! [`nrf52_radio_core::presentation`]:
+//!
+//! ```ignore
+//! /// Waypoint latitude in 1e-7 degrees
+//! #[descriptor(uuid = descriptors::CHARACTERISTIC_USER_DESCRIPTION, read, value = "Waypoint latitude")]
+//! #[descriptor(uuid = descriptors::CHARACTERISTIC_PRESENTATION_FORMAT, read, value = presentation::DEGREES_E7.to_bytes())]
+//! #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001500002", read)]
+//! latitude: i32,
+//! ```
+//!
+//! A format describes a single value, a characteristic of several values
+//! declares [`OPAQUE`] and describes them in the documentation.
+//!
+//! Descriptors take attribute handles, adding one changes the layout of the
+//! server.
+
+pub use nrf52_radio_core::presentation::{
+    DBM, DEGREES_E7, FORMAT_BOOLEAN, FORMAT_SINT8, FORMAT_SINT16, FORMAT_SINT32, FORMAT_STRUCT,
+    FORMAT_UINT8, FORMAT_UINT16, FORMAT_UINT32, FORMAT_UTF8, Format, LEN, OPAQUE, PERCENT, TEXT,
+    UNIT_BEATS_PER_MINUTE, UNIT_DECIBEL, UNIT_DEGREE, UNIT_DEGREE_CELSIUS, UNIT_METRE, UNIT_PASCAL,
+    UNIT_PERCENTAGE, UNIT_SECOND, UNIT_UNITLESS, UNIT_VOLT,
+};
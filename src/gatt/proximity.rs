@@ -0,0 +1,37 @@
+//! Services of the Proximity profile: Immediate Alert, Link Loss and TX
+//! Power, see [`alert`](crate::alert).
+
+pub use nrf52_radio_core::alert::AlertLevel;
+use trouble_host::prelude::*;
+
+/// Alert level of an Alert Level value, `None` if it's invalid.
+pub fn alert_level(value: &[u8]) -> Option<AlertLevel> {
+    match value {
+        [level] => AlertLevel::try_from(*level).ok(),
+        _ => None,
+    }
+}
+
+/// Immediate alert service, a phone makes the device alert to find it
+#[gatt_service(uuid = service::IMMEDIATE_ALERT)]
+pub struct ImmediateAlertService {
+    /// Alert Level, see [`AlertLevel`]
+    #[characteristic(uuid = characteristic::ALERT_LEVEL, write_without_response)]
+    pub alert_level: u8,
+}
+
+/// Link loss service
+#[gatt_service(uuid = service::LINK_LOSS)]
+pub struct LinkLossService {
+    /// Alert Level raised when the link is lost, see [`AlertLevel`]
+    #[characteristic(uuid = characteristic::ALERT_LEVEL, read, write)]
+    pub alert_level: u8,
+}
+
+/// TX power service
+#[gatt_service(uuid = service::TX_POWER)]
+pub struct TxPowerService {
+    /// TX Power Level of the connection in dBm
+    #[characteristic(uuid = characteristic::TX_POWER_LEVEL, read)]
+    pub level: i8,
+}
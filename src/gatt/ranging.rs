@@ -0,0 +1,17 @@
+//! Ranging service with the estimated distance to the responder.
+
+use trouble_host::prelude::*;
+
+/// Distance estimated by [`ranging`](crate::ranging)
+#[gatt_service(uuid = "408813df-5dd4-1f87-ec11-cdb001600000")]
+pub struct RangingService {
+    /// Distance in cm, see [`Estimate::distance_cm`](crate::ranging::Estimate::distance_cm)
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001600001", read, notify)]
+    pub distance: i32,
+    /// Round trip times of the last estimate
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001600002", read)]
+    pub samples: u16,
+    /// Known distance in cm to calibrate the next estimate at
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001600003", write)]
+    pub calibrate: u16,
+}
@@ -0,0 +1,22 @@
+//! SMP service of mcumgr, the transport of [`crate::smp`].
+//!
+//! The same service carries firmware updates with a DFU capable
+//! bootloader, the [server](crate::smp::Server) has no image upload yet.
+
+use trouble_host::prelude::*;
+
+/// Length of the values, fits into an ATT MTU of 247.
+pub const CHUNK_LEN: usize = 244;
+
+/// SMP service of mcumgr
+#[gatt_service(uuid = "8d53dc1d-1db7-4cd3-868b-8a527460aa84")]
+pub struct SmpService {
+    /// Requests written in one or more chunks, responses notified
+    #[characteristic(
+        uuid = "da2e7828-fbce-4e01-ae9e-261174997c48",
+        write,
+        write_without_response,
+        notify
+    )]
+    pub smp: heapless::Vec<u8, CHUNK_LEN>,
+}
@@ -0,0 +1,311 @@
+//! GNSS subsystem for the L76K module of the Wio Tracker L1.
+//!
+//! Reads NMEA sentences from the GNSS UART and publishes the parsed
+//! results on the application [event bus](crate::events).
+//! [`Gnss`] is a [`Subsystem`] meant to be run by the
+//! [supervisor](crate::supervisor), duty cycling is done by a [`Policy`].
+//! A module that stays silent outside standby is reported as
+//! [`Event::GnssAbsent`] and retried with the supervisor's backoff.
+//! Assistance data from a phone is injected through [`assist`], the
+//! pulse per second gives sub-millisecond UTC through [`pps`].
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::NaiveDateTime;
+use defmt::{info, warn};
+use embassy_futures::select::{Either3, select3};
+use embassy_nrf::uarte::{self, UarteRxWithIdle, UarteTx};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer, with_timeout};
+use nmea::ParseResult::{self, GGA, RMC, ZDA};
+pub use nrf52_radio_core::gnss::Fix;
+
+use crate::events::{self, Event};
+use crate::metrics;
+use crate::nav::{self, MotionFilter};
+use crate::settings;
+use crate::supervisor::Subsystem;
+
+pub mod assist;
+pub mod config;
+pub mod line_reader;
+pub mod passthrough;
+pub mod policy;
+pub mod pps;
+
+pub use config::GnssConfig;
+pub use line_reader::{LineError, LineReader};
+pub use policy::{Policy, run_policy};
+
+use config::{Command, ConfigError, INITIAL_BAUD, uarte_baudrate};
+
+/// PCAS message (proprietary NMEA message) to configure
+/// the receiver to start searching for satellites (GPS and BeiDou).
+pub const ENABLE_GNSS_MODULE: &[u8; 14] = b"$PCAS04,3*1A\r\n";
+
+/// NMEA 0183 messages have a max length of 82 chars.
+pub const NMEA_MAX_LEN: usize = 82;
+
+/// Update rate requested by the [`Policy`], applied after the next sentence.
+static UPDATE_RATE: Signal<CriticalSectionRawMutex, u8> = Signal::new();
+
+/// Change the NMEA update rate of the running GNSS subsystem.
+pub fn request_update_rate(update_rate_hz: u8) {
+    UPDATE_RATE.signal(update_rate_hz);
+}
+
+/// Set while the [`Policy`] keeps the module in standby, so it's silent.
+static STANDBY: AtomicBool = AtomicBool::new(false);
+
+/// Tell the GNSS subsystem whether the module was put into standby.
+pub fn set_standby(standby: bool) {
+    STANDBY.store(standby, Ordering::Relaxed);
+}
+
+/// Publish the relevant content of a parsed NMEA sentence, filtering
+/// speed and course through `motion`.
+fn publish_nmea(parse_result: ParseResult, motion: &mut MotionFilter) {
+    match parse_result {
+        GGA(gga) => {
+            let (Some(latitude), Some(longitude)) = (gga.latitude, gga.longitude) else {
+                events::publish(Event::FixLost);
+                return;
+            };
+            let fix = Fix {
+                latitude,
+                longitude,
+                altitude: gga.altitude,
+                satellites: gga.fix_satellites.unwrap_or(0) as u8,
+                hdop: gga.hdop,
+            };
+            crate::log!(Gnss, info, "[gnss] position fix: {}", fix);
+            metrics::FIXES.increment();
+            events::publish(Event::FixAcquired(fix));
+        }
+        RMC(rmc) => {
+            if let Some(speed_knots) = rmc.speed_over_ground {
+                motion.set_stationary_knots(nav::stationary_knots(&settings::get()));
+                let previous = motion.state();
+                let state = motion.update(speed_knots, rmc.true_course);
+                if state != previous {
+                    info!("[gnss] {:?}", state);
+                }
+                events::publish(Event::MotionUpdated {
+                    speed_knots: motion.speed_knots().unwrap_or(0.0),
+                    course: motion.course(),
+                    state,
+                });
+            }
+        }
+        ZDA(zda) => {
+            let maybe_utc_dt: Option<NaiveDateTime> = zda.utc_date_time();
+            crate::log!(
+                Gnss,
+                info,
+                "[gnss] current UTC time: {}",
+                defmt::Debug2Format(&maybe_utc_dt)
+            );
+            if let Some(dt) = maybe_utc_dt {
+                pps::utc_second(&dt);
+                events::publish(Event::TimeUpdated(dt));
+            }
+        }
+        _ => {
+            warn!("[gnss] unexpected NMEA sentence received")
+        }
+    }
+}
+
+/// Errors of the GNSS subsystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum GnssError {
+    /// Sending a command to the module failed.
+    Command(uarte::Error),
+    /// Receiving from the module failed repeatedly.
+    Receive(uarte::Error),
+    /// The module sent nothing for [`SILENCE_TIMEOUT`] while not in standby.
+    Silent,
+    /// The [`GnssConfig`] is not supported.
+    Config(ConfigError),
+}
+
+/// Time without a sentence after which the module is considered absent.
+const SILENCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of consecutive receive errors after which the subsystem fails.
+const MAX_CONSECUTIVE_RX_ERRORS: u8 = 5;
+
+/// Time for the last byte of a command to leave the UARTE
+/// before its baud rate is changed.
+const BAUD_SWITCH_DELAY: Duration = Duration::from_millis(20);
+
+/// GNSS subsystem reading NMEA sentences from the module's UART.
+pub struct Gnss<'a, 'd> {
+    rx: &'a mut UarteRxWithIdle<'d>,
+    tx: &'a mut UarteTx<'d>,
+    config: GnssConfig,
+    /// Baud rate the UARTE is currently configured for.
+    baud: u32,
+    motion: MotionFilter,
+}
+
+impl<'a, 'd> Gnss<'a, 'd> {
+    /// The UARTE has to be configured for [`INITIAL_BAUD`].
+    pub fn new(
+        rx: &'a mut UarteRxWithIdle<'d>,
+        tx: &'a mut UarteTx<'d>,
+        config: GnssConfig,
+    ) -> Self {
+        Self {
+            rx,
+            tx,
+            config,
+            baud: INITIAL_BAUD,
+            motion: MotionFilter::default(),
+        }
+    }
+
+    fn set_uarte_baud(&mut self, baud: u32) {
+        if let Some(baudrate) = uarte_baudrate(baud) {
+            self.tx.set_baudrate(baudrate);
+            self.baud = baud;
+        }
+    }
+
+    /// Switch the module and the UARTE to the configured baud rate
+    /// and set the update rate.
+    async fn negotiate(&mut self) -> Result<(), GnssError> {
+        self.config.validate().map_err(GnssError::Config)?;
+        let target = self.config.target_baud;
+        if self.baud != target
+            && let Some(cmd) = Command::set_baud(target)
+        {
+            info!("[gnss] switching from {} to {} baud", self.baud, target);
+            self.tx
+                .write(cmd.as_bytes())
+                .await
+                .map_err(GnssError::Command)?;
+            Timer::after(BAUD_SWITCH_DELAY).await;
+            self.set_uarte_baud(target);
+        }
+        if self.config.changes_update_rate() {
+            let cmd = Command::set_update_rate(self.config.update_rate_hz);
+            self.tx
+                .write(cmd.as_bytes())
+                .await
+                .map_err(GnssError::Command)?;
+        }
+        Ok(())
+    }
+
+    /// After a failure the module and the UARTE may disagree on the baud rate,
+    /// e.g. when the module was power cycled. Alternate between the initial and
+    /// the target baud rate, so the next run can talk to the module again.
+    fn fall_back(&mut self) {
+        let other = if self.baud == INITIAL_BAUD {
+            self.config.target_baud
+        } else {
+            INITIAL_BAUD
+        };
+        warn!("[gnss] falling back to {} baud", other);
+        self.set_uarte_baud(other);
+    }
+
+    /// Enable the GNSS module and publish its NMEA sentences until
+    /// receiving fails repeatedly or the module stays silent.
+    async fn read_sentences(&mut self) -> Result<(), GnssError> {
+        // TODO: Necessary to send ENABLE_GNSS_MODULE?
+        self.tx
+            .write(ENABLE_GNSS_MODULE)
+            .await
+            .map_err(GnssError::Command)?;
+        self.negotiate().await?;
+
+        let mut reader: LineReader<'_, '_> = LineReader::new(self.rx);
+        let mut nmea_buf = [0u8; NMEA_MAX_LEN];
+        let mut rx_errors = 0u8;
+        loop {
+            // Writing pass-through or assistance data interrupts reading, a
+            // sentence being received at that moment may be lost.
+            let read = with_timeout(SILENCE_TIMEOUT, reader.read_line(&mut nmea_buf));
+            let result = match select3(read, passthrough::next_write(), assist::next_frame()).await
+            {
+                Either3::First(Ok(result)) => result,
+                Either3::First(Err(_)) => {
+                    if STANDBY.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    warn!("[gnss] no sentence for {} s", SILENCE_TIMEOUT.as_secs());
+                    events::publish(Event::GnssAbsent);
+                    return Err(GnssError::Silent);
+                }
+                Either3::Second(data) => {
+                    self.tx.write(&data).await.map_err(GnssError::Command)?;
+                    continue;
+                }
+                Either3::Third(frame) => {
+                    self.tx.write(&frame).await.map_err(GnssError::Command)?;
+                    continue;
+                }
+            };
+            match result {
+                Ok(len) => {
+                    rx_errors = 0;
+                    crate::log!(
+                        Gnss,
+                        info,
+                        "[gnss] received NMEA sentence: {}",
+                        str::from_utf8(&nmea_buf[..len]).unwrap_or("UTF8 error"),
+                    );
+                    passthrough::forward(&nmea_buf[..len]);
+                    if let Ok(valid_nmea) = nmea::parse_bytes(&nmea_buf[..len]) {
+                        publish_nmea(valid_nmea, &mut self.motion);
+                    }
+                    if let Some(hz) = UPDATE_RATE.try_take()
+                        && hz != self.config.update_rate_hz
+                    {
+                        let config = GnssConfig {
+                            update_rate_hz: hz,
+                            ..self.config
+                        };
+                        if let Err(e) = config.validate() {
+                            warn!("[gnss] ignoring update rate: {:?}", e);
+                            continue;
+                        }
+                        info!("[gnss] update rate {} Hz", hz);
+                        self.config = config;
+                        let cmd = Command::set_update_rate(hz);
+                        self.tx
+                            .write(cmd.as_bytes())
+                            .await
+                            .map_err(GnssError::Command)?;
+                    }
+                }
+                Err(LineError::TooLong) => {
+                    warn!("[gnss] discarded overlong NMEA sentence");
+                }
+                Err(LineError::Uart(e)) => {
+                    warn!("[gnss] error receiving bytes: {:?} error", e);
+                    rx_errors += 1;
+                    if rx_errors >= MAX_CONSECUTIVE_RX_ERRORS {
+                        return Err(GnssError::Receive(e));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Subsystem for Gnss<'_, '_> {
+    type Error = GnssError;
+
+    const NAME: &'static str = "gnss";
+
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        let result = self.read_sentences().await;
+        if let Err(GnssError::Receive(_)) = result {
+            self.fall_back();
+        }
+        result
+    }
+}
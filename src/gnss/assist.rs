@@ -0,0 +1,82 @@
+//! Injection of assistance data (AGNSS) into the GNSS module.
+//!
+//! A phone downloads ephemerides, almanacs and its coarse position and time
+//! and writes them as CASIC binary messages, in chunks of any size, to an
+//! [`Injector`]. Complete frames with a valid checksum are queued and
+//! written to the module by the [`Gnss`](super::Gnss) subsystem, which cuts
+//! the time to first fix from minutes to seconds.
+
+use defmt::warn;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Duration, with_timeout};
+use heapless::Vec;
+use nrf52_radio_core::casic::FrameAssembler;
+
+/// Maximum length of a frame, fits the ephemeris messages.
+pub const MAX_FRAME_LEN: usize = 128;
+
+/// A complete frame, ready to be written to the module.
+pub type Frame = Vec<u8, MAX_FRAME_LEN>;
+
+/// Frames buffered for the module.
+const FRAME_QUEUE_LEN: usize = 4;
+
+/// Time to wait for space in the queue before dropping a frame, so a
+/// stopped [`Gnss`](super::Gnss) subsystem doesn't block the caller.
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+static FRAMES: Channel<CriticalSectionRawMutex, Frame, FRAME_QUEUE_LEN> = Channel::new();
+
+/// Reassembles the assistance data written by one client.
+#[derive(Default)]
+pub struct Injector {
+    assembler: FrameAssembler<MAX_FRAME_LEN>,
+}
+
+impl Injector {
+    pub const fn new() -> Self {
+        Self {
+            assembler: FrameAssembler::new(),
+        }
+    }
+
+    /// Queue the frames completed by `data` for the module.
+    ///
+    /// Waits while the queue is full, so a client writing with response is
+    /// slowed down to the pace of the UART. Returns `false` if a frame was
+    /// dropped.
+    pub async fn write(&mut self, data: &[u8]) -> bool {
+        let mut complete = true;
+        for &byte in data {
+            let frame = match self.assembler.push(byte) {
+                Some(Ok(frame)) => Frame::from_slice(frame).unwrap(),
+                Some(Err(e)) => {
+                    warn!("[agnss] invalid frame: {:?}", e);
+                    complete = false;
+                    continue;
+                }
+                None => continue,
+            };
+            crate::log!(
+                Gnss,
+                debug,
+                "[agnss] frame {:02x}/{:02x}",
+                frame[4],
+                frame[5]
+            );
+            if with_timeout(QUEUE_TIMEOUT, FRAMES.send(frame))
+                .await
+                .is_err()
+            {
+                warn!("[agnss] GNSS subsystem not ready, frame dropped");
+                complete = false;
+            }
+        }
+        complete
+    }
+}
+
+/// Wait for the next frame to write to the module.
+pub(super) async fn next_frame() -> Frame {
+    FRAMES.receive().await
+}
@@ -0,0 +1,84 @@
+//! Configuration of the L76K GNSS module via PCAS (proprietary NMEA) commands.
+//!
+//! The module starts at 9600 baud, which is too slow for NMEA update rates
+//! above 1 Hz. [`GnssConfig`] describes the desired baud rate and update
+//! rate, which are negotiated by [`Gnss`](super::Gnss) at startup: the baud
+//! rate command is sent at the initial rate, then the UARTE is switched over.
+//! By default the module is switched to [`DEFAULT_BAUD`], which carries the
+//! NMEA output of every update rate.
+
+use embassy_nrf::uarte::Baudrate;
+pub use nrf52_radio_core::gnss::{Command, nmea_checksum};
+use nrf52_radio_core::gnss::{baud_code, fits_link};
+
+/// Baud rate of the module after power on.
+pub const INITIAL_BAUD: u32 = 9600;
+
+/// Baud rate the module is switched to by default.
+pub const DEFAULT_BAUD: u32 = 115200;
+
+/// Desired operating parameters of the GNSS module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct GnssConfig {
+    /// Baud rate to switch the module and the UARTE to.
+    /// One of 9600, 19200, 38400, 57600 or 115200.
+    pub target_baud: u32,
+    /// NMEA update rate, one of 1, 2, 4, 5 or 10 Hz.
+    pub update_rate_hz: u8,
+}
+
+impl Default for GnssConfig {
+    fn default() -> Self {
+        Self {
+            target_baud: DEFAULT_BAUD,
+            update_rate_hz: 1,
+        }
+    }
+}
+
+/// Invalid [`GnssConfig`] values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ConfigError {
+    UnsupportedBaud(u32),
+    UnsupportedUpdateRate(u8),
+    /// The NMEA output at the update rate doesn't fit the baud rate.
+    LinkBudget {
+        baud: u32,
+        update_rate_hz: u8,
+    },
+}
+
+impl GnssConfig {
+    /// Check that the module and the UARTE support this configuration and
+    /// that the baud rate carries the NMEA output at the update rate.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        baud_code(self.target_baud).ok_or(ConfigError::UnsupportedBaud(self.target_baud))?;
+        if !matches!(self.update_rate_hz, 1 | 2 | 4 | 5 | 10) {
+            return Err(ConfigError::UnsupportedUpdateRate(self.update_rate_hz));
+        }
+        if !fits_link(self.target_baud, self.update_rate_hz) {
+            return Err(ConfigError::LinkBudget {
+                baud: self.target_baud,
+                update_rate_hz: self.update_rate_hz,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the NMEA update rate differs from the module's default of 1 Hz.
+    pub fn changes_update_rate(&self) -> bool {
+        self.update_rate_hz != 1
+    }
+}
+
+/// UARTE setting of a baud rate supported by the module.
+pub fn uarte_baudrate(baud: u32) -> Option<Baudrate> {
+    match baud {
+        9600 => Some(Baudrate::BAUD9600),
+        19200 => Some(Baudrate::BAUD19200),
+        38400 => Some(Baudrate::BAUD38400),
+        57600 => Some(Baudrate::BAUD57600),
+        115200 => Some(Baudrate::BAUD115200),
+        _ => None,
+    }
+}
@@ -0,0 +1,67 @@
+//! Line oriented reader for the GNSS UART.
+//!
+//! Instead of reading one byte per DMA transfer, [`LineReader`] lets EasyDMA
+//! fill the largest contiguous free region of an internal ring buffer and
+//! relies on the TIMER/PPI idle detection set up by
+//! [`Uarte::split_with_idle`](embassy_nrf::uarte::Uarte::split_with_idle) to
+//! end the transfer once the module stops sending. Complete lines are then
+//! taken out of the ring buffer by [`LineReader::read_line`].
+
+use embassy_nrf::uarte::{self, UarteRxWithIdle};
+pub use nrf52_radio_core::gnss::{RingBuffer, TooLong};
+
+/// Default size of the ring buffer, enough for a burst of several NMEA sentences.
+pub const DEFAULT_BUFFER_LEN: usize = 512;
+
+/// Longest UARTE EasyDMA transfer, MAXCNT has 8 bits on the nRF52832.
+#[cfg(feature = "chip-nrf52832")]
+const EASYDMA_MAX_LEN: usize = 0xFF;
+#[cfg(not(feature = "chip-nrf52832"))]
+const EASYDMA_MAX_LEN: usize = 0xFFFF;
+
+/// Errors of [`LineReader::read_line`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum LineError {
+    /// The UART reported an error, buffered data is kept.
+    Uart(uarte::Error),
+    /// The line didn't fit into the provided buffer and was discarded.
+    TooLong,
+}
+
+/// Reads lines from a UART using large DMA transfers ended by idle detection.
+pub struct LineReader<'a, 'd, const N: usize = DEFAULT_BUFFER_LEN> {
+    rx: &'a mut UarteRxWithIdle<'d>,
+    ring: RingBuffer<N>,
+}
+
+impl<'a, 'd, const N: usize> LineReader<'a, 'd, N> {
+    pub fn new(rx: &'a mut UarteRxWithIdle<'d>) -> Self {
+        Self {
+            rx,
+            ring: RingBuffer::new(),
+        }
+    }
+
+    /// Wait for the next complete line and copy it (without line ending)
+    /// into `line`, returning its length.
+    pub async fn read_line(&mut self, line: &mut [u8]) -> Result<usize, LineError> {
+        loop {
+            if let Some(result) = self.ring.pop_line(line) {
+                return result.map_err(|TooLong| LineError::TooLong);
+            }
+            if self.ring.len() == N {
+                // Full without a line ending, the data can't be a valid line.
+                self.ring.clear();
+                return Err(LineError::TooLong);
+            }
+            let writable = self.ring.writable();
+            let len = writable.len().min(EASYDMA_MAX_LEN);
+            let n = self
+                .rx
+                .read_until_idle(&mut writable[..len])
+                .await
+                .map_err(LineError::Uart)?;
+            self.ring.commit(n);
+        }
+    }
+}
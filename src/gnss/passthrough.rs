@@ -0,0 +1,89 @@
+//! Pass-through of the raw NMEA stream, e.g. over the Nordic UART Service.
+//!
+//! While [enabled](set_enabled), the [`Gnss`](super::Gnss) subsystem copies
+//! every received sentence verbatim (with its `\r\n`) into a queue read by
+//! [`next_sentence`], and writes the bytes queued by [`send_to_module`] to
+//! the module. This way standard GNSS configuration tools on a phone can
+//! talk to the module through the nRF52. Sentences are still parsed and
+//! published on the event bus, they are dropped from the pass-through while
+//! its queue is full.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use heapless::Vec;
+
+use super::NMEA_MAX_LEN;
+
+/// Maximum length of a sentence including the line ending.
+pub const SENTENCE_LEN: usize = NMEA_MAX_LEN + 2;
+
+/// Maximum length of a queued write to the module.
+pub const WRITE_LEN: usize = 64;
+
+/// A sentence as received from the module.
+pub type Sentence = Vec<u8, SENTENCE_LEN>;
+
+/// Sentences buffered for the central, about one update at 1 Hz.
+const SENTENCE_QUEUE_LEN: usize = 8;
+
+/// Writes buffered for the module.
+const WRITE_QUEUE_LEN: usize = 4;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static SENTENCES: Channel<CriticalSectionRawMutex, Sentence, SENTENCE_QUEUE_LEN> = Channel::new();
+
+static WRITES: Channel<CriticalSectionRawMutex, Vec<u8, WRITE_LEN>, WRITE_QUEUE_LEN> =
+    Channel::new();
+
+/// Start or stop passing the NMEA stream through, e.g. on (dis)connection.
+///
+/// Stopping drops everything queued in both directions.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        SENTENCES.clear();
+        WRITES.clear();
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Queue a received sentence (without line ending) if enabled.
+pub(super) fn forward(line: &[u8]) {
+    if !is_enabled() {
+        return;
+    }
+    let mut sentence = Sentence::new();
+    if sentence.extend_from_slice(line).is_ok() && sentence.extend_from_slice(b"\r\n").is_ok() {
+        let _ = SENTENCES.try_send(sentence);
+    }
+}
+
+/// Wait for the next sentence from the module.
+pub async fn next_sentence() -> Sentence {
+    SENTENCES.receive().await
+}
+
+/// Queue bytes to be written to the module verbatim.
+///
+/// Returns `false` if pass-through is disabled or (some of) the bytes were
+/// dropped because the queue is full.
+pub fn send_to_module(data: &[u8]) -> bool {
+    if !is_enabled() {
+        return false;
+    }
+    data.chunks(WRITE_LEN).all(|chunk| {
+        // Chunks are at most WRITE_LEN long, so they always fit.
+        let chunk = Vec::from_slice(chunk).unwrap();
+        WRITES.try_send(chunk).is_ok()
+    })
+}
+
+/// Wait for the next bytes to write to the module.
+pub(super) async fn next_write() -> Vec<u8, WRITE_LEN> {
+    WRITES.receive().await
+}
@@ -0,0 +1,235 @@
+//! Duty cycling of the GNSS module.
+//!
+//! A [`Policy`] trades fix freshness against battery life. The
+//! [`PolicyEngine`] turns the policy and the events on the bus into the
+//! [`Mode`] the module should be in, [`run_policy`] applies it: standby via
+//! the module's wakeup pin, the update rate via [`request_update_rate`].
+//!
+//! [`Policy::Adaptive`] only puts the module into standby when an IMU
+//! publishes [`Event::MotionDetected`], as a module in standby can't tell
+//! that the device moves again. Before that, the GNSS itself has to
+//! consider the device [stationary](MotionState::Stationary).
+//!
+//! The [battery policy](crate::policy) overrides the policy: at
+//! [`PowerLevel::Reduced`] the module is additionally duty cycled by
+//! [`REDUCED_ON`] and [`REDUCED_OFF`], from [`PowerLevel::Critical`] on it
+//! stays in standby.
+
+use defmt::info;
+use embassy_futures::select::{Either, select};
+use embassy_nrf::gpio::{Level, Output};
+use embassy_time::{Duration, Instant, Timer};
+
+use super::{request_update_rate, set_standby};
+use crate::events::{self, Event};
+use crate::nav::MotionState;
+use crate::policy::PowerLevel;
+
+/// Time the module tracks per duty cycle at [`PowerLevel::Reduced`], enough
+/// for a hot start.
+pub const REDUCED_ON: Duration = Duration::from_secs(30);
+
+/// Time the module is in standby per duty cycle at [`PowerLevel::Reduced`].
+pub const REDUCED_OFF: Duration = Duration::from_secs(90);
+
+/// How the GNSS module is duty cycled.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Policy {
+    /// Always on at the configured update rate.
+    AlwaysOn,
+    /// On for `on`, then in standby for `off`, repeatedly.
+    Periodic { on: Duration, off: Duration },
+    /// Update rate by speed, standby while the IMU reports rest.
+    Adaptive {
+        /// At and above this speed the fast update rate is used.
+        fast_speed_knots: f32,
+        /// Update rate while moving slowly.
+        slow_rate_hz: u8,
+        /// Update rate while moving fast. Needs a baud rate high enough for
+        /// the NMEA output, see [`GnssConfig`](super::GnssConfig).
+        fast_rate_hz: u8,
+        /// Standby after the IMU reported rest for this long.
+        standby_after: Duration,
+    },
+}
+
+/// Operating mode of the GNSS module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Mode {
+    /// Tracking at the given update rate in Hz, or the configured one.
+    On(Option<u8>),
+    Standby,
+}
+
+/// State machine deciding the [`Mode`] of the module.
+pub struct PolicyEngine {
+    policy: Policy,
+    started: Instant,
+    speed_knots: f32,
+    motion: MotionState,
+    /// Time since which the IMU reports rest, `None` while moving or without IMU.
+    resting_since: Option<Instant>,
+    power: PowerLevel,
+    /// Start of the duty cycle at [`PowerLevel::Reduced`].
+    reduced_since: Instant,
+}
+
+impl PolicyEngine {
+    pub fn new(policy: Policy, now: Instant) -> Self {
+        Self {
+            policy,
+            started: now,
+            speed_knots: 0.0,
+            motion: MotionState::Stationary,
+            resting_since: None,
+            power: PowerLevel::Normal,
+            reduced_since: now,
+        }
+    }
+
+    /// Track speed, motion and the power level.
+    pub fn update(&mut self, event: &Event, now: Instant) {
+        match event {
+            Event::MotionUpdated {
+                speed_knots, state, ..
+            } => {
+                self.speed_knots = *speed_knots;
+                self.motion = *state;
+            }
+            Event::MotionDetected(true) => self.resting_since = None,
+            Event::MotionDetected(false) => {
+                if self.resting_since.is_none() && self.motion == MotionState::Stationary {
+                    self.resting_since = Some(now);
+                }
+            }
+            Event::PowerLevelChanged(level) => {
+                if *level == PowerLevel::Reduced && self.power != PowerLevel::Reduced {
+                    self.reduced_since = now;
+                }
+                self.power = *level;
+            }
+            _ => {}
+        }
+    }
+
+    /// Mode the module should be in at `now`.
+    pub fn mode(&self, now: Instant) -> Mode {
+        match self.power {
+            PowerLevel::Normal => self.policy_mode(now),
+            PowerLevel::Reduced if Self::duty_on(self.reduced_since, now) => self.policy_mode(now),
+            _ => Mode::Standby,
+        }
+    }
+
+    /// Next time the mode may change without an event.
+    pub fn next_change(&self, now: Instant) -> Option<Instant> {
+        match self.power {
+            PowerLevel::Normal => self.policy_next_change(now),
+            PowerLevel::Reduced => {
+                let duty = Self::duty_change(self.reduced_since, now);
+                Some(self.policy_next_change(now).map_or(duty, |at| at.min(duty)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the [`PowerLevel::Reduced`] duty cycle started at `since`
+    /// is in its on phase.
+    fn duty_on(since: Instant, now: Instant) -> bool {
+        let period = (REDUCED_ON + REDUCED_OFF).as_ticks();
+        (now - since).as_ticks() % period < REDUCED_ON.as_ticks()
+    }
+
+    /// End of the current phase of the [`PowerLevel::Reduced`] duty cycle.
+    fn duty_change(since: Instant, now: Instant) -> Instant {
+        let period = (REDUCED_ON + REDUCED_OFF).as_ticks();
+        let phase = (now - since).as_ticks() % period;
+        let remaining = if phase < REDUCED_ON.as_ticks() {
+            REDUCED_ON.as_ticks() - phase
+        } else {
+            period - phase
+        };
+        now + Duration::from_ticks(remaining)
+    }
+
+    /// Mode by the [`Policy`] alone.
+    fn policy_mode(&self, now: Instant) -> Mode {
+        match self.policy {
+            Policy::AlwaysOn => Mode::On(None),
+            Policy::Periodic { on, off } => {
+                let period = (on + off).as_ticks().max(1);
+                let phase = (now - self.started).as_ticks() % period;
+                if phase < on.as_ticks() {
+                    Mode::On(None)
+                } else {
+                    Mode::Standby
+                }
+            }
+            Policy::Adaptive {
+                fast_speed_knots,
+                slow_rate_hz,
+                fast_rate_hz,
+                standby_after,
+            } => match self.resting_since {
+                Some(since) if now - since >= standby_after => Mode::Standby,
+                _ if self.speed_knots >= fast_speed_knots => Mode::On(Some(fast_rate_hz)),
+                _ => Mode::On(Some(slow_rate_hz)),
+            },
+        }
+    }
+
+    /// Next change of the mode by the [`Policy`] alone.
+    fn policy_next_change(&self, now: Instant) -> Option<Instant> {
+        match self.policy {
+            Policy::AlwaysOn => None,
+            Policy::Periodic { on, off } => {
+                let period = (on + off).as_ticks().max(1);
+                let phase = (now - self.started).as_ticks() % period;
+                let remaining = if phase < on.as_ticks() {
+                    on.as_ticks() - phase
+                } else {
+                    period - phase
+                };
+                Some(now + Duration::from_ticks(remaining))
+            }
+            Policy::Adaptive { standby_after, .. } => self
+                .resting_since
+                .map(|since| since + standby_after)
+                .filter(|at| *at > now),
+        }
+    }
+}
+
+/// Duty cycle the GNSS module according to `policy`.
+///
+/// `wakeup` is the module's wakeup pin (high: tracking, low: standby).
+pub async fn run_policy(policy: Policy, wakeup: &mut Output<'_>) {
+    let Ok(mut events) = events::subscribe() else {
+        defmt::warn!("[gnss] no event bus subscriber available");
+        return;
+    };
+    let mut engine = PolicyEngine::new(policy, Instant::now());
+    let mut current = None;
+    loop {
+        let now = Instant::now();
+        let mode = engine.mode(now);
+        if current != Some(mode) {
+            info!("[gnss] mode {:?}", mode);
+            set_standby(mode == Mode::Standby);
+            match mode {
+                Mode::Standby => wakeup.set_level(Level::Low),
+                Mode::On(rate) => {
+                    wakeup.set_level(Level::High);
+                    if let Some(hz) = rate {
+                        request_update_rate(hz);
+                    }
+                }
+            }
+            current = Some(mode);
+        }
+        let next = engine.next_change(now).unwrap_or(Instant::MAX);
+        if let Either::First(event) = select(events.next_message_pure(), Timer::at(next)).await {
+            engine.update(&event, Instant::now());
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Pulse per second of the GNSS module for sub-millisecond UTC.
+//!
+//! Where the PPS line of the module is wired, [`PpsCapture`] captures its
+//! rising edge with TIMER2 through GPIOTE and PPI, so the local time of the
+//! pulse doesn't depend on how fast the executor gets to it. The pulses
+//! and the UTC seconds of the ZDA sentences discipline a [`PpsClock`] that
+//! maps [`Instant`]s, which run from the RTC, to UTC, e.g. to timestamp
+//! log records or radio packets with [`utc_at`].
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+use chrono::{DateTime, NaiveDateTime};
+use defmt::info;
+use embassy_nrf::{
+    Peri,
+    gpio::{GpioPin, Input, Pull},
+    gpiote::{self, InputChannel, InputChannelPolarity},
+    pac,
+    peripherals::TIMER2,
+    ppi::{AnyConfigurableChannel, Ppi, Task},
+};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::Instant;
+pub use nrf52_radio_core::pps::{Anchor, HOLDOVER_US, PpsClock};
+
+use crate::supervisor::Subsystem;
+
+static CLOCK: Mutex<CriticalSectionRawMutex, RefCell<PpsClock>> =
+    Mutex::new(RefCell::new(PpsClock::new()));
+
+/// Take the UTC time of a ZDA sentence, which names the second of the
+/// pulse before it.
+pub fn utc_second(dt: &NaiveDateTime) {
+    let local_us = Instant::now().as_micros();
+    let secs = dt.and_utc().timestamp();
+    let matched = CLOCK.lock(|clock| clock.borrow_mut().utc_second(secs, local_us));
+    if matched {
+        crate::log!(Gnss, debug, "[pps] second {} matched", secs);
+    }
+}
+
+/// UTC in µs since the Unix epoch at `instant`, `None` without recent
+/// pulses.
+pub fn utc_us_at(instant: Instant) -> Option<i64> {
+    CLOCK.lock(|clock| clock.borrow().utc_us(instant.as_micros()))
+}
+
+/// UTC at `instant`, `None` without recent pulses.
+pub fn utc_at(instant: Instant) -> Option<NaiveDateTime> {
+    DateTime::from_timestamp_micros(utc_us_at(instant)?).map(|dt| dt.naive_utc())
+}
+
+/// Captures the pulses, see the [module](self) documentation.
+pub struct PpsCapture<'d> {
+    input: InputChannel<'d>,
+    _ppi: Ppi<'d, AnyConfigurableChannel, 1, 1>,
+    _timer: Peri<'d, TIMER2>,
+    pulses: u32,
+}
+
+impl<'d> PpsCapture<'d> {
+    /// Capture the rising edges of `pin` into CC[0] of `timer`, which runs
+    /// at 1 MHz from now on.
+    pub fn new(
+        timer: Peri<'d, TIMER2>,
+        channel: Peri<'d, impl gpiote::Channel>,
+        pin: Peri<'d, impl GpioPin>,
+        ppi: Peri<'d, AnyConfigurableChannel>,
+    ) -> Self {
+        let t = pac::TIMER2;
+        t.bitmode()
+            .write(|w| w.set_bitmode(pac::timer::vals::Bitmode::_32BIT));
+        // 16 MHz / 2^4
+        t.prescaler().write(|w| w.set_prescaler(4));
+        t.tasks_clear().write_value(1);
+        t.tasks_start().write_value(1);
+        let input = InputChannel::new(
+            channel,
+            Input::new(pin, Pull::None),
+            InputChannelPolarity::LoToHi,
+        );
+        let mut ppi =
+            Ppi::new_one_to_one(ppi, input.event_in(), Task::from_reg(t.tasks_capture(0)));
+        ppi.enable();
+        Self {
+            input,
+            _ppi: ppi,
+            _timer: timer,
+            pulses: 0,
+        }
+    }
+}
+
+impl Subsystem for PpsCapture<'_> {
+    type Error = Infallible;
+
+    const NAME: &'static str = "pps";
+
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        let t = pac::TIMER2;
+        loop {
+            self.input.wait().await;
+            // The time since the pulse, both captures are in µs.
+            t.tasks_capture(1).write_value(1);
+            let now = Instant::now().as_micros();
+            let latency = t.cc(1).read().wrapping_sub(t.cc(0).read());
+            CLOCK.lock(|clock| clock.borrow_mut().pulse(now - latency as u64));
+            self.pulses += 1;
+            if self.pulses == 1 {
+                info!("[pps] first pulse");
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Heart rate monitor simulator for testing phone apps.
+//!
+//! [`run`] beats like a [`Waveform`] and hands a Heart Rate Measurement to
+//! [`next_measurement`] every [`MEASUREMENT_INTERVAL`], carrying the RR
+//! intervals of the beats since the previous one. The `hrs_sim` binary
+//! notifies them over the [`HeartRateService`](crate::gatt::HeartRateService).
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::Vec;
+pub use nrf52_radio_core::hrs::{Beat, MAX_RR, MEASUREMENT_LEN, Waveform, measurement};
+
+/// Interval of the measurements, as of a typical chest strap.
+pub const MEASUREMENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Latest measurement, waiting for [`next_measurement`].
+static MEASUREMENT: Signal<CriticalSectionRawMutex, Vec<u8, MEASUREMENT_LEN>> = Signal::new();
+
+/// Wait for the next measurement. Measurements not taken in time are
+/// replaced, with their RR intervals.
+pub async fn next_measurement() -> Vec<u8, MEASUREMENT_LEN> {
+    MEASUREMENT.wait().await
+}
+
+/// Beat with the jitter seeded by `seed` forever.
+pub async fn run(seed: u32) -> ! {
+    let mut waveform = Waveform::new(seed);
+    let mut rr = Vec::<u16, MAX_RR>::new();
+    let mut next = Instant::now() + MEASUREMENT_INTERVAL;
+    loop {
+        let beat = waveform.next_beat();
+        Timer::after_millis(beat.rr_ms()).await;
+        // More beats than fit only at a rate above 240 bpm.
+        let _ = rr.push(beat.rr);
+        if Instant::now() >= next {
+            MEASUREMENT.signal(measurement(beat.bpm, &rr));
+            rr.clear();
+            next += MEASUREMENT_INTERVAL;
+        }
+    }
+}
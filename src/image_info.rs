@@ -0,0 +1,179 @@
+//! State of the running image as seen by the bootloader.
+//!
+//! The firmware is flashed with the Adafruit bootloader, or signed and
+//! swapped in by MCUboot. [`info`] tells which one it runs under:
+//! - MCUboot leaves an image header of [`HEADER_SIZE`] bytes in front of the
+//!   vector table, with the image version. A test image swapped in by an
+//!   update is [`ImageStatus::Pending`] until [`mark_image_ok`] writes the
+//!   image OK flag of the slot trailer, otherwise MCUboot reverts it on the
+//!   next reset. The primary slot is assumed to end where the application
+//!   data begins ([`DATA`]).
+//! - The Adafruit bootloader has no revert, its settings page only tells
+//!   whether the application bank is valid.
+//!
+//! [`reboot_to_bootloader`] resets into the update mode of the bootloader,
+//! so the firmware is reflashed over USB without a double tap on reset:
+//! the UF2 drive of the Adafruit bootloader, or the serial recovery of
+//! MCUboot, which has to be built with `CONFIG_BOOT_SERIAL_BOOT_MODE` on a
+//! GPREGRET retention area without prefix and checksum.
+//!
+//! [`confirm`] runs the self-test: an image which ran [`SELF_TEST_TIME`]
+//! without a subsystem restart or a failing BLE stack is marked OK. The
+//! version and the status are reported over SMP, where `mcumgr image
+//! confirm` marks the image OK as well.
+
+use core::cell::Cell;
+use core::sync::atomic::Ordering;
+
+use defmt::{info, warn};
+use embassy_nrf::pac;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Timer};
+use embedded_storage_async::nor_flash::NorFlash;
+pub use nrf52_radio_core::image::{AdafruitBank, ImageHeader, ImageStatus, ImageVersion, Trailer};
+use nrf52_radio_core::image::{FLAG_SET, IMAGE_OK_OFFSET, TRAILER_LEN};
+
+use crate::bsp::flash::{DATA, FlashError, SharedFlash};
+use crate::recovery::DIAGNOSTICS;
+use crate::supervisor::RESTARTS;
+
+/// Bytes of the MCUboot image header in front of the vector table.
+pub const HEADER_SIZE: u32 = 0x200;
+
+/// Settings page of the Adafruit bootloader.
+#[cfg(feature = "chip-nrf52840")]
+const ADAFRUIT_SETTINGS: u32 = 0xFF000;
+/// Settings page of the Adafruit bootloader.
+#[cfg(any(feature = "chip-nrf52833", feature = "chip-nrf52832"))]
+const ADAFRUIT_SETTINGS: u32 = 0x7F000;
+
+/// GPREGRET value making the Adafruit bootloader start the UF2 drive.
+pub const UF2_MAGIC: u8 = 0x57;
+
+/// GPREGRET value making MCUboot enter serial recovery (boot mode
+/// bootloader).
+pub const MCUBOOT_RECOVERY_MAGIC: u8 = 0x01;
+
+/// End of the MCUboot primary slot.
+const SLOT_END: u32 = DATA.start;
+
+/// Uptime after which a healthy image is marked OK.
+pub const SELF_TEST_TIME: Duration = Duration::from_secs(60);
+
+/// The bootloader the firmware runs under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Bootloader {
+    Mcuboot,
+    Adafruit,
+}
+
+/// The running image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct ImageInfo {
+    pub bootloader: Bootloader,
+    /// Version from the image header, `None` without one.
+    pub version: Option<ImageVersion>,
+    pub status: ImageStatus,
+}
+
+/// Errors of [`mark_image_ok`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ImageError {
+    /// [`init`] wasn't called.
+    Unavailable,
+    Flash(FlashError),
+}
+
+static FLASH: Mutex<CriticalSectionRawMutex, Cell<Option<&'static SharedFlash>>> =
+    Mutex::new(Cell::new(None));
+
+/// Read `N` bytes of the memory mapped flash.
+fn read<const N: usize>(addr: u32) -> [u8; N] {
+    // SAFETY: Only called with addresses in the flash, which is always mapped.
+    unsafe { core::ptr::read_volatile(addr as *const [u8; N]) }
+}
+
+/// The MCUboot image header, if the image has one.
+fn header() -> Option<ImageHeader> {
+    // SAFETY: Reading VTOR has no side effects.
+    let vtor = unsafe { (*cortex_m::peripheral::SCB::PTR).vtor.read() };
+    let addr = vtor.checked_sub(HEADER_SIZE)?;
+    ImageHeader::parse(&read(addr)).filter(|header| u32::from(header.hdr_size) == HEADER_SIZE)
+}
+
+/// The running image.
+pub fn info() -> ImageInfo {
+    match header() {
+        Some(header) => ImageInfo {
+            bootloader: Bootloader::Mcuboot,
+            version: Some(header.version),
+            status: Trailer::parse(&read(SLOT_END - TRAILER_LEN as u32)).status(),
+        },
+        None => ImageInfo {
+            bootloader: Bootloader::Adafruit,
+            version: None,
+            status: AdafruitBank::parse(read::<1>(ADAFRUIT_SETTINGS)[0]).status(),
+        },
+    }
+}
+
+/// Log the running image and keep the flash for [`mark_image_ok`].
+pub fn init(flash: &'static SharedFlash) {
+    FLASH.lock(|f| f.set(Some(flash)));
+    let image = info();
+    info!(
+        "[image] {} image {}, {}",
+        image.bootloader, image.version, image.status
+    );
+}
+
+/// Confirm a test image swapped in by MCUboot, so it isn't reverted on the
+/// next reset. Nothing to do under the Adafruit bootloader or for an image
+/// confirmed already.
+pub async fn mark_image_ok() -> Result<(), ImageError> {
+    let image = info();
+    if image.bootloader != Bootloader::Mcuboot || image.status != ImageStatus::Pending {
+        return Ok(());
+    }
+    let flash = FLASH.lock(|f| f.get()).ok_or(ImageError::Unavailable)?;
+    let addr = SLOT_END - TRAILER_LEN as u32 + IMAGE_OK_OFFSET as u32;
+    flash
+        .lock()
+        .await
+        .write(addr, &[FLAG_SET, 0xFF, 0xFF, 0xFF])
+        .await
+        .map_err(ImageError::Flash)?;
+    info!("[image] image confirmed");
+    Ok(())
+}
+
+/// Reset into the update mode of the bootloader.
+pub fn reboot_to_bootloader() -> ! {
+    let magic = match info().bootloader {
+        Bootloader::Mcuboot => MCUBOOT_RECOVERY_MAGIC,
+        Bootloader::Adafruit => UF2_MAGIC,
+    };
+    warn!("[image] rebooting into the bootloader");
+    pac::POWER.gpregret().write(|w| w.set_gpregret(magic));
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Mark the image OK once it ran [`SELF_TEST_TIME`] without trouble.
+pub async fn confirm() {
+    if info().status != ImageStatus::Pending {
+        return;
+    }
+    Timer::after(SELF_TEST_TIME).await;
+    let restarts = RESTARTS.load(Ordering::Relaxed);
+    let failures = DIAGNOSTICS.consecutive_failures.load(Ordering::Relaxed);
+    if restarts > 0 || failures > 0 {
+        warn!(
+            "[image] self-test failed ({} restarts, {} BLE failures), the image is reverted on reset",
+            restarts, failures
+        );
+        return;
+    }
+    if let Err(e) = mark_image_ok().await {
+        warn!("[image] couldn't confirm the image: {:?}", e);
+    }
+}
@@ -0,0 +1,158 @@
+//! Device keys in the UICR customer registers.
+//!
+//! Keys are written once per device with [`provision`], e.g. by the
+//! provisioning firmware, into a slot per [`KeyId`] (see
+//! [`nrf52_radio_core::keystore`]). At runtime only [`KeyHandle`]s are
+//! handed out, which run the crypto operations without giving the key
+//! away: there is no way to read secret key material back, the `keys` shell
+//! [`COMMANDS`] only list whether a slot is provisioned. Only the public
+//! key of the item-finder tag is read, with [`finder_public_key`]. A
+//! debugger can still read the UICR until the
+//! [access port is protected](crate::bsp::approtect).
+//!
+//! Erasing the UICR would also clear the pin and regulator configuration,
+//! so a provisioned slot can't be replaced. [`lock_provisioning`] marks
+//! the end of the [provisioning](crate::provision) the same way.
+
+use core::fmt::Write;
+
+use defmt::{info, warn};
+use nrf52_radio_core::finder::OFFLINE_FINDING_KEY_LEN;
+pub use nrf52_radio_core::keystore::{CUSTOMER_LEN, KeyId, SlotState};
+use nrf52_radio_core::keystore::{
+    LOCK_MAGIC, LOCK_OFFSET, encode_slot, is_locked, key, slot_state,
+};
+
+use crate::bsp::flash;
+use crate::crypto::{self, Aes, BLOCK_LEN, CryptoError};
+use crate::shell::{Command, Output};
+
+/// Address of the UICR customer registers.
+const CUSTOMER: u32 = 0x1000_1080;
+
+/// Errors of the keystore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum KeyError {
+    /// The slot wasn't provisioned.
+    Missing,
+    /// The slot holds a key already.
+    Provisioned,
+    /// The slot was written, but doesn't verify.
+    Corrupt,
+    /// The key has the wrong length for the slot or operation.
+    Length,
+    Crypto(CryptoError),
+}
+
+/// Read the customer registers.
+fn customer() -> [u8; CUSTOMER_LEN] {
+    // SAFETY: The UICR is always mapped and reading it has no side effects.
+    unsafe { core::ptr::read_volatile(CUSTOMER as *const [u8; CUSTOMER_LEN]) }
+}
+
+/// State of the slot of `id`.
+pub fn state(id: KeyId) -> SlotState {
+    slot_state(&customer(), id)
+}
+
+/// Write `key` to the empty slot of `id`. Takes effect right away, the
+/// UICR configuration registers only at the next reset.
+pub fn provision(id: KeyId, key: &[u8]) -> Result<(), KeyError> {
+    match state(id) {
+        SlotState::Empty => {}
+        SlotState::Valid => return Err(KeyError::Provisioned),
+        SlotState::Corrupt => return Err(KeyError::Corrupt),
+    }
+    let slot = encode_slot(id, key).ok_or(KeyError::Length)?;
+    flash::write_uicr(CUSTOMER + id.offset() as u32, &slot);
+    match state(id) {
+        SlotState::Valid => {
+            info!("[keystore] {} key provisioned", id.name());
+            Ok(())
+        }
+        _ => Err(KeyError::Corrupt),
+    }
+}
+
+/// Whether provisioning was locked.
+pub fn provisioning_locked() -> bool {
+    is_locked(&customer())
+}
+
+/// Lock provisioning for good.
+pub fn lock_provisioning() {
+    if !provisioning_locked() {
+        flash::write_uicr(CUSTOMER + LOCK_OFFSET as u32, &LOCK_MAGIC.to_le_bytes());
+        warn!("[keystore] provisioning locked");
+    }
+}
+
+/// Reference to a provisioned key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct KeyHandle(KeyId);
+
+impl KeyHandle {
+    pub fn id(&self) -> KeyId {
+        self.0
+    }
+
+    /// Run `f` with the key, which isn't kept beyond it.
+    fn with_key<R>(&self, f: impl FnOnce(&[u8]) -> R) -> Result<R, KeyError> {
+        let mut customer = customer();
+        let result = key(&customer, self.0).map(f).ok_or(KeyError::Corrupt);
+        customer.fill(0);
+        result
+    }
+
+    /// AES-128 with the key.
+    pub fn aes(&self) -> Result<Aes, KeyError> {
+        self.with_key(|key| {
+            let key: [u8; BLOCK_LEN] = key.try_into().map_err(|_| KeyError::Length)?;
+            Ok(Aes::new(key))
+        })?
+    }
+
+    /// P-256 ECDH of the key with the `peer` public key.
+    pub fn ecdh_p256(&self, peer: &[u8; 64]) -> Result<[u8; 32], KeyError> {
+        self.with_key(|key| {
+            let secret: &[u8; 32] = key.try_into().map_err(|_| KeyError::Length)?;
+            crypto::ecdh_p256(secret, peer).map_err(KeyError::Crypto)
+        })?
+    }
+}
+
+/// The handle of the key of `id`.
+pub fn handle(id: KeyId) -> Result<KeyHandle, KeyError> {
+    match state(id) {
+        SlotState::Valid => Ok(KeyHandle(id)),
+        SlotState::Empty => Err(KeyError::Missing),
+        SlotState::Corrupt => {
+            warn!("[keystore] {} key corrupt", id.name());
+            Err(KeyError::Corrupt)
+        }
+    }
+}
+
+/// The public key of the item-finder tag, it isn't secret.
+pub fn finder_public_key() -> Result<[u8; OFFLINE_FINDING_KEY_LEN], KeyError> {
+    handle(KeyId::Finder)?.with_key(|key| key.try_into().map_err(|_| KeyError::Length))?
+}
+
+fn keys_command(_args: &str, out: &mut Output) -> core::fmt::Result {
+    for id in KeyId::ALL {
+        let state = match state(id) {
+            SlotState::Empty => "empty",
+            SlotState::Valid => "provisioned",
+            SlotState::Corrupt => "corrupt",
+        };
+        write!(out, "{}: {}\r\n", id.name(), state)?;
+    }
+    Ok(())
+}
+
+/// Shell commands of the keystore.
+pub const COMMANDS: &[Command] = &[Command {
+    name: "keys",
+    help: "list the provisioned keys",
+    handler: keys_command,
+}];
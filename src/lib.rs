@@ -1,17 +1,176 @@
 #![no_main]
 #![no_std]
 
+#[cfg(not(any(
+    all(
+        feature = "chip-nrf52840",
+        not(feature = "chip-nrf52833"),
+        not(feature = "chip-nrf52832")
+    ),
+    all(
+        feature = "chip-nrf52833",
+        not(feature = "chip-nrf52840"),
+        not(feature = "chip-nrf52832")
+    ),
+    all(
+        feature = "chip-nrf52832",
+        not(feature = "chip-nrf52840"),
+        not(feature = "chip-nrf52833")
+    ),
+)))]
+compile_error!("Enable exactly one of the chip-nrf52840, chip-nrf52833 and chip-nrf52832 features");
+
 use defmt_rtt as _;
+#[cfg(feature = "chip-nrf52840")]
+use embassy_nrf::peripherals::{
+    P0_19, P0_20, P0_21, P0_22, P0_23, P1_10, P1_11, P1_12, P1_13, P1_14, QSPI,
+};
+#[cfg(not(feature = "chip-nrf52832"))]
+use embassy_nrf::peripherals::{
+    P1_00, P1_01, P1_03, P1_04, P1_05, P1_06, P1_07, P1_08, P1_09, PWM3, SPI3, UARTE1, USBD,
+};
 use embassy_nrf::{
     Peri,
+    gpio::AnyPin,
     peripherals::{
-        P0_05, P0_06, P0_26, P0_27, P1_09, PPI_CH0, PPI_CH1, RNG, TIMER0, TIMER1, TWISPI0, UARTE0,
+        GPIOTE_CH0, GPIOTE_CH1, GPIOTE_CH2, GPIOTE_CH3, GPIOTE_CH4, GPIOTE_CH5, GPIOTE_CH6,
+        GPIOTE_CH7, NFCT, NVMC, P0_05, P0_06, P0_17, P0_26, P0_27, PWM0, PWM1, PWM2, SAADC, TIMER0,
+        TIMER1, TIMER2, TWISPI0, UARTE0,
     },
+    saadc::ChannelConfig,
 };
+#[cfg(not(feature = "panic-reset"))]
 use panic_probe as _;
 
+pub mod alert;
+pub mod altimeter;
+pub mod ans;
+pub mod ant;
+pub mod battery;
 pub mod bsp {
+    pub mod approtect;
+    pub mod barometer;
     pub mod ble;
+    pub mod brownout;
+    pub mod button;
+    pub mod buzzer;
+    pub mod config;
+    pub mod flash;
+    pub mod i2c;
+    pub mod i2c_probe;
+    pub mod led;
+    pub mod link_quality;
+    pub mod magnetometer;
+    pub mod neopixel;
+    pub mod power;
+    pub mod ppi;
+    pub mod rng;
+    pub mod thermal;
+}
+pub mod build_info;
+pub mod compass;
+pub mod crash;
+pub mod crypto;
+pub mod epoch;
+pub mod events;
+pub mod factory_reset;
+pub mod fhss;
+pub mod fmt;
+pub mod gateway;
+pub mod gatt;
+pub mod gnss;
+pub mod hrs_sim;
+pub mod image_info;
+pub mod keystore;
+pub mod log;
+pub mod maintenance;
+pub mod mesh;
+pub mod metrics;
+pub mod nav;
+pub mod nfc;
+pub mod policy;
+pub mod provision;
+pub mod range;
+pub mod ranging;
+pub mod recovery;
+pub mod replay;
+pub mod retained;
+pub mod sensors;
+pub mod settings;
+pub mod shell;
+pub mod smp;
+pub mod sniffer;
+pub mod supervisor;
+pub mod telemetry;
+pub mod throughput;
+pub mod track;
+pub mod ui;
+#[cfg(feature = "usb-hid")]
+pub mod usb_hid;
+pub mod waypoint;
+
+/// GPIO port 1 pins without a fixed function on the supported boards.
+///
+/// The nRF52833 only has P1.00 to P1.09, the nRF52832 has no port 1.
+#[cfg(not(feature = "chip-nrf52832"))]
+pub struct Port1 {
+    pub p1_00: Peri<'static, P1_00>,
+    pub p1_01: Peri<'static, P1_01>,
+    pub p1_03: Peri<'static, P1_03>,
+    pub p1_04: Peri<'static, P1_04>,
+    pub p1_05: Peri<'static, P1_05>,
+    pub p1_06: Peri<'static, P1_06>,
+    pub p1_07: Peri<'static, P1_07>,
+    pub p1_08: Peri<'static, P1_08>,
+    #[cfg(feature = "chip-nrf52840")]
+    pub p1_10: Peri<'static, P1_10>,
+    #[cfg(feature = "chip-nrf52840")]
+    pub p1_11: Peri<'static, P1_11>,
+    #[cfg(feature = "chip-nrf52840")]
+    pub p1_12: Peri<'static, P1_12>,
+    #[cfg(feature = "chip-nrf52840")]
+    pub p1_13: Peri<'static, P1_13>,
+    #[cfg(feature = "chip-nrf52840")]
+    pub p1_14: Peri<'static, P1_14>,
+}
+
+/// Pulse width modulation peripherals.
+pub struct Pwm {
+    pub pwm0: Peri<'static, PWM0>,
+    pub pwm1: Peri<'static, PWM1>,
+    pub pwm2: Peri<'static, PWM2>,
+    #[cfg(not(feature = "chip-nrf52832"))]
+    pub pwm3: Peri<'static, PWM3>,
+}
+
+/// GPIO tasks and events channels, e.g. for pin change interrupts.
+pub struct Gpiote {
+    pub ch0: Peri<'static, GPIOTE_CH0>,
+    pub ch1: Peri<'static, GPIOTE_CH1>,
+    pub ch2: Peri<'static, GPIOTE_CH2>,
+    pub ch3: Peri<'static, GPIOTE_CH3>,
+    pub ch4: Peri<'static, GPIOTE_CH4>,
+    pub ch5: Peri<'static, GPIOTE_CH5>,
+    pub ch6: Peri<'static, GPIOTE_CH6>,
+    pub ch7: Peri<'static, GPIOTE_CH7>,
+}
+
+/// QSPI peripheral and the pins of the external flash on Adafruit Feather nRF52840.
+///
+/// IO0 is GPIO 0.17, see [`Board::p0_17`].
+#[cfg(feature = "chip-nrf52840")]
+pub struct Qspi {
+    pub qspi: Peri<'static, QSPI>,
+    /// GPIO 0.19 (QSPI SCK)
+    pub sck: Peri<'static, P0_19>,
+    /// GPIO 0.20 (QSPI CS)
+    pub csn: Peri<'static, P0_20>,
+    /// GPIO 0.22 (QSPI IO1)
+    pub io1: Peri<'static, P0_22>,
+    /// GPIO 0.23 (QSPI IO2)
+    pub io2: Peri<'static, P0_23>,
+    /// GPIO 0.21 (QSPI IO3)
+    pub io3: Peri<'static, P0_21>,
 }
 
 // TODO: Move Board into bsp module?:
@@ -21,59 +180,230 @@ pub struct Board {
     pub p0_05: Peri<'static, P0_05>,
     /// GPIO 0.06 (OLED I2C SDA on Wio Tracker L1)
     pub p0_06: Peri<'static, P0_06>,
+    /// NeoPixel data: GPIO 0.16 on Adafruit Feather nRF52840, unset on the
+    /// Wio Tracker L1.
+    pub neopixel: Option<Peri<'static, AnyPin>>,
+    /// GPIO 0.17 (QSPI IO0 on Adafruit Feather nRF52840)
+    pub p0_17: Peri<'static, P0_17>,
+    /// Charger status input, active low. Unset until the pin is confirmed
+    /// on the Wio Tracker L1 schematic.
+    pub charger_status: Option<Peri<'static, AnyPin>>,
+    /// Console UART (RX, TX) for [`uarte1`](Self::uarte1): GPIO 0.24 and
+    /// 0.25 on Adafruit Feather nRF52840, unset on the Wio Tracker L1.
+    pub console: Option<(Peri<'static, AnyPin>, Peri<'static, AnyPin>)>,
     /// GPIO 0.26 (GNSS RX on Wio Tracker L1)
     pub p0_26: Peri<'static, P0_26>,
     /// GPIO 0.27 (GNSS TX on Wio Tracker L1)
     pub p0_27: Peri<'static, P0_27>,
+    /// SAADC input of the battery divider: AIN5 (GPIO 0.29) on Adafruit
+    /// Feather nRF52840, see [`battery::BatteryModel`]. Unset on the Wio
+    /// Tracker L1 until the pin is confirmed on the schematic.
+    pub vbat: Option<ChannelConfig<'static>>,
+    /// User switch, active low: GPIO 1.02 on Adafruit Feather nRF52840,
+    /// unset on the Wio Tracker L1.
+    pub user_switch: Option<Peri<'static, AnyPin>>,
     /// GPIO 1.09 (GNSS wakeup on Wio Tracker L1)
+    #[cfg(not(feature = "chip-nrf52832"))]
     pub p1_09: Peri<'static, P1_09>,
+    /// Status LED, active high: GPIO 1.15 (red) on Adafruit Feather
+    /// nRF52840, unset on the Wio Tracker L1.
+    pub led: Option<Peri<'static, AnyPin>>,
+    /// Remaining GPIO port 1 pins
+    #[cfg(not(feature = "chip-nrf52832"))]
+    pub p1: Port1,
     /// TIMER0 peripheral
     pub timer0: Peri<'static, TIMER0>,
     /// TIMER1 peripheral
     pub timer1: Peri<'static, TIMER1>,
-    /// Random number generator
-    pub rng: Peri<'static, RNG>,
+    /// TIMER2 peripheral (GNSS pulse capture, see [`gnss::pps`])
+    pub timer2: Peri<'static, TIMER2>,
+    /// Random number generator, shared with the BLE controller
+    pub rng: bsp::rng::Rng,
+    /// Successive approximation ADC (battery voltage, see [`battery`])
+    pub saadc: Peri<'static, SAADC>,
+    /// NFC tag peripheral (see [`nfc`])
+    pub nfct: Peri<'static, NFCT>,
+    /// Non-volatile memory controller (flash, see [`bsp::flash`])
+    pub nvmc: Peri<'static, NVMC>,
     /// Bluetooth Low Energy
     pub ble: bsp::ble::BleControllerBuilder<'static>,
     /// Two-Wire & Serial Peripheral Interface 0 (shared)
     pub twispi0: Peri<'static, TWISPI0>,
-    // TODO: documentation.
+    /// UARTE0 (GNSS on Wio Tracker L1)
     pub uarte0: Peri<'static, UARTE0>,
-    pub ppi_ch0: Peri<'static, PPI_CH0>,
-    pub ppi_ch1: Peri<'static, PPI_CH1>,
+    /// UARTE1 (debug console, see [`shell`])
+    #[cfg(not(feature = "chip-nrf52832"))]
+    pub uarte1: Peri<'static, UARTE1>,
+    /// PPI channels not used by the BLE stack
+    pub ppi: bsp::ppi::PpiAllocator,
+    /// PWM0 to PWM3
+    pub pwm: Pwm,
+    /// GPIOTE channels
+    pub gpiote: Gpiote,
+    /// QSPI with the external flash pins
+    #[cfg(feature = "chip-nrf52840")]
+    pub qspi: Qspi,
+    /// SPI master 3, the only one up to 32 MHz
+    #[cfg(not(feature = "chip-nrf52832"))]
+    pub spim3: Peri<'static, SPI3>,
+    /// USB device (keyboard, see `usb_hid`)
+    #[cfg(not(feature = "chip-nrf52832"))]
+    pub usbd: Peri<'static, USBD>,
 }
 
 impl Default for Board {
     fn default() -> Self {
-        Self::new(Default::default())
+        Self::new(Default::default()).unwrap()
     }
 }
 
 impl Board {
-    pub fn new(config: embassy_nrf::config::Config) -> Self {
-        let p = embassy_nrf::init(config);
-        Self {
+    /// Initialize the peripherals with the clocks and regulators of `config`.
+    pub fn new(config: bsp::config::BoardConfig) -> Result<Self, bsp::config::ConfigError> {
+        config.validate()?;
+        let p = embassy_nrf::init(config.embassy_config());
+        let mut ppi = bsp::ppi::PpiAllocator::new(
+            [
+                p.PPI_CH0.into(),
+                p.PPI_CH1.into(),
+                p.PPI_CH2.into(),
+                p.PPI_CH3.into(),
+                p.PPI_CH4.into(),
+                p.PPI_CH5.into(),
+                p.PPI_CH6.into(),
+                p.PPI_CH7.into(),
+                p.PPI_CH8.into(),
+                p.PPI_CH9.into(),
+                p.PPI_CH10.into(),
+                p.PPI_CH11.into(),
+                p.PPI_CH12.into(),
+                p.PPI_CH13.into(),
+                p.PPI_CH14.into(),
+                p.PPI_CH15.into(),
+                p.PPI_CH16.into(),
+            ],
+            bsp::ppi::BleChannels {
+                ppi_ch17: p.PPI_CH17,
+                ppi_ch18: p.PPI_CH18,
+                ppi_ch19: p.PPI_CH19,
+                ppi_ch20: p.PPI_CH20,
+                ppi_ch21: p.PPI_CH21,
+                ppi_ch22: p.PPI_CH22,
+                ppi_ch23: p.PPI_CH23,
+                ppi_ch24: p.PPI_CH24,
+                ppi_ch25: p.PPI_CH25,
+                ppi_ch26: p.PPI_CH26,
+                ppi_ch27: p.PPI_CH27,
+                ppi_ch28: p.PPI_CH28,
+                ppi_ch29: p.PPI_CH29,
+                ppi_ch30: p.PPI_CH30,
+                ppi_ch31: p.PPI_CH31,
+            },
+        );
+        let ble_channels = ppi.take_ble().unwrap();
+        Ok(Self {
             ble: bsp::ble::BleControllerBuilder::new(
-                p.RTC0, p.TEMP, p.PPI_CH17, p.PPI_CH18, p.PPI_CH19, p.PPI_CH20, p.PPI_CH21,
-                p.PPI_CH22, p.PPI_CH23, p.PPI_CH24, p.PPI_CH25, p.PPI_CH26, p.PPI_CH27, p.PPI_CH28,
-                p.PPI_CH29, p.PPI_CH30, p.PPI_CH31,
+                p.RTC0,
+                p.TEMP,
+                ble_channels,
+                config.lf_clock_config(),
             ),
             p0_05: p.P0_05,
             p0_06: p.P0_06,
+            #[cfg(feature = "board-feather")]
+            neopixel: Some(p.P0_16.into()),
+            #[cfg(not(feature = "board-feather"))]
+            neopixel: None,
+            p0_17: p.P0_17,
+            charger_status: None,
+            #[cfg(feature = "board-feather")]
+            console: Some((p.P0_24.into(), p.P0_25.into())),
+            #[cfg(not(feature = "board-feather"))]
+            console: None,
             p0_26: p.P0_26,
             p0_27: p.P0_27,
+            #[cfg(feature = "board-feather")]
+            vbat: Some(ChannelConfig::single_ended(p.P0_29)),
+            #[cfg(not(feature = "board-feather"))]
+            vbat: None,
+            #[cfg(feature = "board-feather")]
+            user_switch: Some(p.P1_02.into()),
+            #[cfg(not(feature = "board-feather"))]
+            user_switch: None,
+            #[cfg(not(feature = "chip-nrf52832"))]
             p1_09: p.P1_09,
-            rng: p.RNG,
+            #[cfg(feature = "board-feather")]
+            led: Some(p.P1_15.into()),
+            #[cfg(not(feature = "board-feather"))]
+            led: None,
+            #[cfg(not(feature = "chip-nrf52832"))]
+            p1: Port1 {
+                p1_00: p.P1_00,
+                p1_01: p.P1_01,
+                p1_03: p.P1_03,
+                p1_04: p.P1_04,
+                p1_05: p.P1_05,
+                p1_06: p.P1_06,
+                p1_07: p.P1_07,
+                p1_08: p.P1_08,
+                #[cfg(feature = "chip-nrf52840")]
+                p1_10: p.P1_10,
+                #[cfg(feature = "chip-nrf52840")]
+                p1_11: p.P1_11,
+                #[cfg(feature = "chip-nrf52840")]
+                p1_12: p.P1_12,
+                #[cfg(feature = "chip-nrf52840")]
+                p1_13: p.P1_13,
+                #[cfg(feature = "chip-nrf52840")]
+                p1_14: p.P1_14,
+            },
+            rng: bsp::rng::Rng::new(p.RNG),
+            nfct: p.NFCT,
+            nvmc: p.NVMC,
+            saadc: p.SAADC,
             timer0: p.TIMER0,
             timer1: p.TIMER1,
+            timer2: p.TIMER2,
             twispi0: p.TWISPI0,
             uarte0: p.UARTE0,
-            ppi_ch0: p.PPI_CH0,
-            ppi_ch1: p.PPI_CH1,
-        }
+            #[cfg(not(feature = "chip-nrf52832"))]
+            uarte1: p.UARTE1,
+            ppi,
+            pwm: Pwm {
+                pwm0: p.PWM0,
+                pwm1: p.PWM1,
+                pwm2: p.PWM2,
+                #[cfg(not(feature = "chip-nrf52832"))]
+                pwm3: p.PWM3,
+            },
+            gpiote: Gpiote {
+                ch0: p.GPIOTE_CH0,
+                ch1: p.GPIOTE_CH1,
+                ch2: p.GPIOTE_CH2,
+                ch3: p.GPIOTE_CH3,
+                ch4: p.GPIOTE_CH4,
+                ch5: p.GPIOTE_CH5,
+                ch6: p.GPIOTE_CH6,
+                ch7: p.GPIOTE_CH7,
+            },
+            #[cfg(feature = "chip-nrf52840")]
+            qspi: Qspi {
+                qspi: p.QSPI,
+                sck: p.P0_19,
+                csn: p.P0_20,
+                io1: p.P0_22,
+                io2: p.P0_23,
+                io3: p.P0_21,
+            },
+            #[cfg(not(feature = "chip-nrf52832"))]
+            spim3: p.SPI3,
+            #[cfg(not(feature = "chip-nrf52832"))]
+            usbd: p.USBD,
+        })
     }
 }
 
+#[cfg(not(feature = "panic-reset"))]
 #[defmt::panic_handler]
 fn panic() -> ! {
     // same panicking *behavior* as `panic-probe` but doesn't print a panic message
@@ -81,6 +411,27 @@ fn panic() -> ! {
     cortex_m::asm::udf()
 }
 
+#[cfg(feature = "panic-reset")]
+#[defmt::panic_handler]
+fn panic() -> ! {
+    // `defmt::panic!` already logged the message.
+    fatal_reset()
+}
+
+#[cfg(feature = "panic-reset")]
+#[panic_handler]
+fn rust_panic(info: &core::panic::PanicInfo) -> ! {
+    defmt::error!("{}", defmt::Display2Format(info));
+    fatal_reset()
+}
+
+/// Play the failure pattern on the status LED, then reset the device.
+#[cfg(feature = "panic-reset")]
+fn fatal_reset() -> ! {
+    bsp::led::play_blocking(bsp::led::LedPattern::Failure);
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
 /// Terminates the application and makes a semihosting-capable debug tool exit
 /// with status code 0.
 pub fn exit() -> ! {
@@ -89,12 +440,37 @@ pub fn exit() -> ! {
 
 /// Hardfault handler.
 ///
-/// Terminates the application and makes a semihosting-capable debug tool exit
-/// with an error. This seems better than the default, which is to spin in a
-/// loop.
+/// Writes a [crash dump](crash) to flash. With the `panic-reset` feature, the
+/// fault is logged and the device reset after playing the failure pattern.
+/// Otherwise, with a debugger attached, the application terminates and a
+/// semihosting-capable debug tool exits with an error, without one the device
+/// is reset (semihosting would hang).
 #[cortex_m_rt::exception]
-unsafe fn HardFault(_frame: &cortex_m_rt::ExceptionFrame) -> ! {
-    semihosting::process::exit(1);
+unsafe fn HardFault(frame: &cortex_m_rt::ExceptionFrame) -> ! {
+    let exc_return: u32;
+    // SAFETY: Only reads LR, which still holds EXC_RETURN: the trampoline of
+    // `cortex-m-rt` branches here without linking and nothing was called yet.
+    unsafe {
+        core::arch::asm!(
+            "mov {}, lr",
+            out(reg) exc_return,
+            options(nomem, nostack, preserves_flags)
+        )
+    };
+    // SAFETY: Called from the HardFault handler.
+    unsafe { crash::save(frame, exc_return) };
+    #[cfg(feature = "panic-reset")]
+    {
+        defmt::error!("hard fault at pc {=u32:#x}", frame.pc());
+        fatal_reset()
+    }
+    #[cfg(not(feature = "panic-reset"))]
+    {
+        if cortex_m::peripheral::DCB::is_debugger_attached() {
+            semihosting::process::exit(1);
+        }
+        cortex_m::peripheral::SCB::sys_reset()
+    }
 }
 
 // defmt-test 0.3.0 has the limitation that this `#[tests]` attribute can only be used
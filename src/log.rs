@@ -0,0 +1,108 @@
+//! Runtime log levels per module.
+//!
+//! `DEFMT_LOG` selects at compile time which messages are built into the
+//! firmware at all. Of those, [`log!`](crate::log!) only prints the ones at
+//! or above the level set with [`set_level`] for their [`Module`], e.g. to
+//! mute the NMEA sentences of the GNSS module in the field. All modules
+//! start at [`Level::Trace`], passing everything `DEFMT_LOG` lets through.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Verbosity, from the most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+#[repr(u8)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    /// Nothing is printed.
+    Off,
+}
+
+impl Level {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Level::Trace,
+            1 => Level::Debug,
+            2 => Level::Info,
+            3 => Level::Warn,
+            4 => Level::Error,
+            5 => Level::Off,
+            _ => return None,
+        })
+    }
+}
+
+/// Modules with their own level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum Module {
+    /// GNSS module, NMEA sentences and assistance data.
+    Gnss,
+    /// Advertising, connections and GATT.
+    Ble,
+    /// NFC tag emulation.
+    Nfc,
+    /// OLED pages.
+    Ui,
+}
+
+/// Number of [`Module`]s.
+pub const MODULES: usize = 4;
+
+static LEVELS: [AtomicU8; MODULES] = [const { AtomicU8::new(Level::Trace as u8) }; MODULES];
+
+/// Print messages of `module` at `level` and above.
+pub fn set_level(module: Module, level: Level) {
+    defmt::info!("[log] {:?} level {:?}", module, level);
+    LEVELS[module as usize].store(level as u8, Ordering::Relaxed);
+}
+
+/// Current levels, indexed by [`Module`].
+pub fn levels() -> [u8; MODULES] {
+    core::array::from_fn(|i| LEVELS[i].load(Ordering::Relaxed))
+}
+
+/// Set the levels, indexed by [`Module`]. Invalid levels keep the current
+/// one, e.g. `0xFF`.
+pub fn set_levels(levels: &[u8; MODULES]) {
+    let modules = [Module::Gnss, Module::Ble, Module::Nfc, Module::Ui];
+    for (module, level) in modules.into_iter().zip(levels) {
+        if let Some(level) = Level::from_u8(*level) {
+            set_level(module, level);
+        }
+    }
+}
+
+/// Whether a message of `module` at `level` is printed.
+pub fn enabled(module: Module, level: Level) -> bool {
+    level as u8 >= LEVELS[module as usize].load(Ordering::Relaxed)
+}
+
+/// Log with a `defmt` macro if the runtime level of the module allows it,
+/// e.g. `log!(Gnss, info, "[gnss] fix: {}", fix)`.
+#[macro_export]
+macro_rules! log {
+    ($module:ident, trace, $($arg:tt)+) => {
+        $crate::log!(@ $module, Trace, trace, $($arg)+)
+    };
+    ($module:ident, debug, $($arg:tt)+) => {
+        $crate::log!(@ $module, Debug, debug, $($arg)+)
+    };
+    ($module:ident, info, $($arg:tt)+) => {
+        $crate::log!(@ $module, Info, info, $($arg)+)
+    };
+    ($module:ident, warn, $($arg:tt)+) => {
+        $crate::log!(@ $module, Warn, warn, $($arg)+)
+    };
+    ($module:ident, error, $($arg:tt)+) => {
+        $crate::log!(@ $module, Error, error, $($arg)+)
+    };
+    (@ $module:ident, $level:ident, $macro:ident, $($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Module::$module, $crate::log::Level::$level) {
+            ::defmt::$macro!($($arg)+);
+        }
+    };
+}
@@ -0,0 +1,220 @@
+//! Flash maintenance while power allows.
+//!
+//! Every erase of a [data page](crate::bsp::flash::DATA) is counted by the
+//! shared flash. The [`Maintenance`] subsystem persists the counters in the
+//! [wear page](crate::bsp::flash::WEAR), compacting it when full, and
+//! erases the pages of deleted track log sessions ahead of their reuse, so
+//! recording doesn't wait for an erase. It only works on USB power or above
+//! [`MIN_BATTERY_PERCENT`]. The counters are read with [`erases`] and the
+//! `wear` shell [`COMMANDS`].
+
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use defmt::{info, warn};
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Timer};
+use embedded_storage_async::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
+use heapless::Vec;
+pub use nrf52_radio_core::wear::{MAX_ERASES, Record};
+use nrf52_radio_core::wear::{RECORD_LEN, WearTable};
+
+use crate::battery::ChargeState;
+use crate::bsp::flash::{DATA, DATA_PAGES, PAGE_SIZE, Partition, SharedFlash, WEAR};
+use crate::events::{self, Event};
+use crate::shell::{Command, Output};
+use crate::supervisor::Subsystem;
+use crate::track::{self, TrackError};
+
+/// State of charge above which maintenance runs on battery.
+pub const MIN_BATTERY_PERCENT: u8 = 50;
+
+/// Interval in which new work is looked for.
+const INTERVAL: Duration = Duration::from_secs(60);
+
+/// Pause between two pieces of work, an erase takes the flash for about
+/// 85 ms.
+const STEP_PAUSE: Duration = Duration::from_millis(200);
+
+/// Records in the wear page.
+const LOG_CAPACITY: u32 = PAGE_SIZE / RECORD_LEN;
+
+/// Records read at once while loading.
+const LOAD_CHUNK: u32 = 64;
+
+/// Erase counts of the data pages.
+static COUNTERS: Mutex<CriticalSectionRawMutex, RefCell<WearTable<DATA_PAGES>>> =
+    Mutex::new(RefCell::new(WearTable::new()));
+
+/// Count an erase of the page at absolute `address`.
+pub fn count_erase(address: u32) {
+    if let Some(offset) = address.checked_sub(DATA.start) {
+        COUNTERS.lock(|c| c.borrow_mut().count((offset / PAGE_SIZE) as usize));
+    }
+}
+
+/// Erase count per data page since the device was first flashed.
+pub fn erases() -> [u32; DATA_PAGES] {
+    COUNTERS.lock(|c| *c.borrow().erases())
+}
+
+/// Persists the erase counters and erases reclaimed pages.
+pub struct Maintenance {
+    flash: Partition,
+    /// Records in the wear page.
+    log_len: u32,
+    charge: Option<ChargeState>,
+    percent: Option<u8>,
+}
+
+impl Maintenance {
+    /// Load the erase counters from the wear page.
+    ///
+    /// Create it before the other flash users, erases counted before are
+    /// overwritten by the loaded counts.
+    pub async fn new(flash: &'static SharedFlash) -> Self {
+        let mut partition = WEAR.partition(flash);
+        let mut log_len = 0;
+        let mut chunk = [0; (LOAD_CHUNK * RECORD_LEN) as usize];
+        while log_len < LOG_CAPACITY {
+            if partition
+                .read(log_len * RECORD_LEN, &mut chunk)
+                .await
+                .is_err()
+            {
+                break;
+            }
+            let words = chunk
+                .chunks_exact(RECORD_LEN as usize)
+                .map(|word| u32::from_le_bytes(word.try_into().unwrap()));
+            let loaded = COUNTERS.lock(|c| c.borrow_mut().load(words));
+            log_len += loaded;
+            if loaded < LOAD_CHUNK {
+                break;
+            }
+        }
+        info!("[maintenance] {} wear records", log_len);
+        Self {
+            flash: partition,
+            log_len,
+            charge: None,
+            percent: None,
+        }
+    }
+
+    /// Whether there is USB power or enough charge.
+    fn allowed(&self) -> bool {
+        matches!(
+            self.charge,
+            Some(ChargeState::Charging | ChargeState::Charged)
+        ) || self.percent.is_some_and(|p| p > MIN_BATTERY_PERCENT)
+    }
+
+    fn observe(&mut self, event: Event) {
+        match event {
+            Event::BatteryUpdated { percent, .. } => self.percent = Some(percent),
+            Event::ChargeStateChanged(state) => self.charge = Some(state),
+            _ => {}
+        }
+    }
+
+    /// Persist the counters or erase a reclaimed page, returns whether there
+    /// was anything to do.
+    async fn step(&mut self) -> Result<bool, <Partition as ErrorType>::Error> {
+        if COUNTERS.lock(|c| c.borrow().is_dirty()) {
+            self.persist().await?;
+            return Ok(true);
+        }
+        match track::reclaim().await {
+            Ok(reclaimed) => Ok(reclaimed),
+            Err(TrackError::Unavailable) => Ok(false),
+            Err(e) => {
+                warn!("[maintenance] couldn't reclaim: {:?}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Append the counts changed since, or compact the wear page if they
+    /// don't fit.
+    async fn persist(&mut self) -> Result<(), <Partition as ErrorType>::Error> {
+        // Not borrowed while erasing, the erase is counted.
+        let dirty: Vec<Record, DATA_PAGES> =
+            COUNTERS.lock(|c| c.borrow_mut().take_dirty().collect());
+        if self.log_len + dirty.len() as u32 <= LOG_CAPACITY {
+            for record in &dirty {
+                self.append(record).await?;
+            }
+            return Ok(());
+        }
+        self.flash.erase(0, PAGE_SIZE).await?;
+        self.log_len = 0;
+        let counters = COUNTERS.lock(|c| c.borrow().clone());
+        for record in counters.records() {
+            self.append(&record).await?;
+        }
+        info!("[maintenance] compacted {} wear records", self.log_len);
+        Ok(())
+    }
+
+    async fn append(&mut self, record: &Record) -> Result<(), <Partition as ErrorType>::Error> {
+        self.flash
+            .write(self.log_len * RECORD_LEN, &record.encode().to_le_bytes())
+            .await?;
+        self.log_len += 1;
+        Ok(())
+    }
+}
+
+impl Subsystem for Maintenance {
+    type Error = <Partition as ErrorType>::Error;
+
+    const NAME: &'static str = "maintenance";
+
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        let Ok(mut events) = events::subscribe() else {
+            warn!("[maintenance] no event bus subscriber available");
+            return Ok(());
+        };
+        loop {
+            while let Some(event) = events.try_next_message_pure() {
+                self.observe(event);
+            }
+            if self.allowed() && self.step().await? {
+                Timer::after(STEP_PAUSE).await;
+                continue;
+            }
+            // Power changes come as events, new work is looked for every INTERVAL.
+            if let Either::First(event) =
+                select(events.next_message_pure(), Timer::after(INTERVAL)).await
+            {
+                self.observe(event);
+            }
+        }
+    }
+}
+
+fn wear_command(_args: &str, out: &mut Output) -> core::fmt::Result {
+    let (total, most_worn) = COUNTERS.lock(|c| {
+        let counters = c.borrow();
+        (counters.total(), counters.most_worn())
+    });
+    write!(out, "{} erases of {} pages", total, DATA_PAGES)?;
+    if let Some((page, erases)) = most_worn {
+        write!(
+            out,
+            ", most at 0x{:05x}: {}",
+            DATA.start + page as u32 * PAGE_SIZE,
+            erases
+        )?;
+    }
+    out.write_str("\r\n")
+}
+
+/// Shell command to show the erase counters.
+pub const COMMANDS: &[Command] = &[Command {
+    name: "wear",
+    help: "erase counters of the data pages",
+    handler: wear_command,
+}];
@@ -0,0 +1,452 @@
+//! Bluetooth Mesh advertising bearer.
+//!
+//! A [`Node`] sends access messages into an existing mesh network, and
+//! decrypts the network PDUs it receives, given the network key, the IV
+//! index and an application key from a provisioner. Segmented messages are
+//! reassembled, acknowledgements and relaying are left to the caller.
+//!
+//! The node has no replay protection for its own messages across reboots:
+//! the sequence number passed to [`Node::new`] has to be higher than any
+//! used before, or the other nodes drop the messages. `mesh_node` keeps
+//! them in the settings and publishes sensor readings with a node.
+
+use core::marker::PhantomData;
+
+use heapless::Vec;
+pub use nrf52_radio_core::mesh::{
+    KEY_LEN, MAX_NETWORK_PDU_LEN, MESH_MESSAGE, PRESENT_DEVICE_OPERATING_TEMPERATURE, PduError,
+    Reassembler, SENSOR_STATUS, SegmentHeader, decode_key, sensor_status,
+};
+use nrf52_radio_core::mesh::{
+    MAX_UNSEGMENTED_LEN, SEGMENT_LEN, Segments, application_nonce, network_nonce,
+    unsegmented_header,
+};
+
+use crate::crypto::{BLOCK_LEN, BlockCipher, Ccm, CryptoError, cmac};
+
+/// Network PDU as sent in an advertisement.
+pub type NetworkPdu = Vec<u8, MAX_NETWORK_PDU_LEN>;
+
+/// Length of the TransMIC of unsegmented and short segmented messages.
+const TRANS_MIC_LEN: usize = 4;
+
+/// Length of the NetMIC of access messages.
+const NET_MIC_LEN: usize = 4;
+
+/// Length of the NetMIC of control messages.
+const CONTROL_NET_MIC_LEN: usize = 8;
+
+/// Bytes of the network header before the encrypted DST.
+const HEADER_LEN: usize = 7;
+
+/// Longest access payload: 32 segments minus the TransMIC.
+pub const MAX_ACCESS_LEN: usize = 380;
+
+/// Errors of the mesh layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum MeshError {
+    Crypto(CryptoError),
+    Pdu(PduError),
+    /// The PDU belongs to another network (NID or IV index).
+    OtherNetwork,
+    /// The PDU isn't encrypted with the application key.
+    OtherKey,
+}
+
+impl From<CryptoError> for MeshError {
+    fn from(e: CryptoError) -> Self {
+        MeshError::Crypto(e)
+    }
+}
+
+impl From<PduError> for MeshError {
+    fn from(e: PduError) -> Self {
+        MeshError::Pdu(e)
+    }
+}
+
+/// AES-CMAC with a zero key of `message`.
+fn s1<C: BlockCipher + From<[u8; BLOCK_LEN]>>(message: &[u8]) -> Result<[u8; 16], CryptoError> {
+    cmac(&C::from([0; BLOCK_LEN]), message)
+}
+
+/// Keys derived from a network key.
+struct NetworkKeys {
+    nid: u8,
+    encryption: [u8; BLOCK_LEN],
+    privacy: [u8; BLOCK_LEN],
+}
+
+impl NetworkKeys {
+    /// Master credentials of `net_key` (k2 with P = 0x00).
+    fn derive<C: BlockCipher + From<[u8; BLOCK_LEN]>>(
+        net_key: [u8; BLOCK_LEN],
+    ) -> Result<Self, CryptoError> {
+        let t = C::from(cmac(&C::from(s1::<C>(b"smk2")?), &net_key)?);
+        let t1 = cmac(&t, &[0x00, 0x01])?;
+        let mut message = [0; BLOCK_LEN + 2];
+        message[..BLOCK_LEN].copy_from_slice(&t1);
+        message[BLOCK_LEN..].copy_from_slice(&[0x00, 0x02]);
+        let t2 = cmac(&t, &message)?;
+        message[..BLOCK_LEN].copy_from_slice(&t2);
+        message[BLOCK_LEN..].copy_from_slice(&[0x00, 0x03]);
+        let t3 = cmac(&t, &message)?;
+        Ok(Self {
+            nid: t1[BLOCK_LEN - 1] & 0x7F,
+            encryption: t2,
+            privacy: t3,
+        })
+    }
+}
+
+/// An application key and its identifier.
+pub struct AppKey {
+    key: [u8; BLOCK_LEN],
+    aid: u8,
+}
+
+impl AppKey {
+    /// Derive the identifier of `key` (k4).
+    pub fn new<C: BlockCipher + From<[u8; BLOCK_LEN]>>(
+        key: [u8; BLOCK_LEN],
+    ) -> Result<Self, CryptoError> {
+        let t = C::from(cmac(&C::from(s1::<C>(b"smk4")?), &key)?);
+        let aid = cmac(&t, b"id6\x01")?[BLOCK_LEN - 1] & 0x3F;
+        Ok(Self { key, aid })
+    }
+
+    /// Identifier of the key in the lower transport header.
+    pub fn aid(&self) -> u8 {
+        self.aid
+    }
+}
+
+/// Network header of a PDU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct NetworkHeader {
+    /// Control message (the transport PDU isn't an access message).
+    pub ctl: bool,
+    pub ttl: u8,
+    pub seq: u32,
+    pub src: u16,
+    pub dst: u16,
+}
+
+/// Network layer encryption with a network key.
+pub struct Network<C> {
+    keys: NetworkKeys,
+    iv_index: u32,
+    cipher: PhantomData<C>,
+}
+
+impl<C: BlockCipher + From<[u8; BLOCK_LEN]>> Network<C> {
+    pub fn new(net_key: [u8; BLOCK_LEN], iv_index: u32) -> Result<Self, CryptoError> {
+        Ok(Self {
+            keys: NetworkKeys::derive::<C>(net_key)?,
+            iv_index,
+            cipher: PhantomData,
+        })
+    }
+
+    /// Network identifier of the key in the first byte of every PDU.
+    pub fn nid(&self) -> u8 {
+        self.keys.nid
+    }
+
+    /// Key stream of the header obfuscation.
+    fn pecb(&self, iv_index: u32, privacy_random: &[u8]) -> Result<[u8; BLOCK_LEN], CryptoError> {
+        let mut block = [0; BLOCK_LEN];
+        block[5..9].copy_from_slice(&iv_index.to_be_bytes());
+        block[9..].copy_from_slice(&privacy_random[..7]);
+        C::from(self.keys.privacy).encrypt_block(&mut block)?;
+        Ok(block)
+    }
+
+    fn ccm_open<const MIC: usize>(
+        &self,
+        nonce: &[u8; 13],
+        data: &mut [u8],
+        mic: &[u8],
+    ) -> Result<(), CryptoError> {
+        let mic: [u8; MIC] = mic.try_into().map_err(|_| CryptoError::InvalidLength)?;
+        Ccm::<C, MIC>::new(C::from(self.keys.encryption)).open(nonce, &[], data, &mic)
+    }
+
+    /// Decrypt the network PDU `pdu` in place, returning its header and the
+    /// lower transport PDU.
+    pub fn decrypt<'p>(&self, pdu: &'p mut [u8]) -> Result<(NetworkHeader, &'p [u8]), MeshError> {
+        let (header, _, lower) = self.open(pdu)?;
+        Ok((header, lower))
+    }
+
+    /// Decrypt `pdu` like [`decrypt`](Self::decrypt), also returning the IV
+    /// index it was sent with, the previous one during an IV update.
+    fn open<'p>(&self, pdu: &'p mut [u8]) -> Result<(NetworkHeader, u32, &'p [u8]), MeshError> {
+        if pdu.len() < HEADER_LEN + 2 + 1 + NET_MIC_LEN || pdu.len() > MAX_NETWORK_PDU_LEN {
+            return Err(PduError::Malformed.into());
+        }
+        if pdu[0] & 0x7F != self.keys.nid {
+            return Err(MeshError::OtherNetwork);
+        }
+        // The IVI bit tells whether the previous IV index is still in use.
+        let iv_index = if (pdu[0] >> 7) as u32 == self.iv_index & 1 {
+            self.iv_index
+        } else {
+            self.iv_index.wrapping_sub(1)
+        };
+        let pecb = self.pecb(iv_index, &pdu[HEADER_LEN..])?;
+        pdu[1..HEADER_LEN]
+            .iter_mut()
+            .zip(pecb)
+            .for_each(|(b, k)| *b ^= k);
+        let ctl_ttl = pdu[1];
+        let seq = u32::from_be_bytes([0, pdu[2], pdu[3], pdu[4]]);
+        let src = u16::from_be_bytes([pdu[5], pdu[6]]);
+        let ctl = ctl_ttl & 0x80 != 0;
+        let mic_len = if ctl {
+            CONTROL_NET_MIC_LEN
+        } else {
+            NET_MIC_LEN
+        };
+        let nonce = network_nonce(ctl_ttl, seq, src, iv_index);
+        let (data, mic) = pdu[HEADER_LEN..].split_at_mut(pdu.len() - HEADER_LEN - mic_len);
+        if data.len() < 3 {
+            return Err(PduError::Malformed.into());
+        }
+        if ctl {
+            self.ccm_open::<CONTROL_NET_MIC_LEN>(&nonce, data, mic)
+        } else {
+            self.ccm_open::<NET_MIC_LEN>(&nonce, data, mic)
+        }
+        .map_err(|_| MeshError::OtherNetwork)?;
+        let header = NetworkHeader {
+            ctl,
+            ttl: ctl_ttl & 0x7F,
+            seq,
+            src,
+            dst: u16::from_be_bytes([data[0], data[1]]),
+        };
+        Ok((header, iv_index, &data[2..]))
+    }
+
+    /// Encrypt the access message `transport` (lower transport PDU) into a
+    /// network PDU.
+    pub fn encrypt(
+        &self,
+        header: &NetworkHeader,
+        transport: &[u8],
+    ) -> Result<NetworkPdu, MeshError> {
+        if header.ctl {
+            // Control messages aren't sent by the node.
+            return Err(PduError::Malformed.into());
+        }
+        let len = HEADER_LEN + 2 + transport.len() + NET_MIC_LEN;
+        if len > MAX_NETWORK_PDU_LEN {
+            return Err(PduError::TooLong.into());
+        }
+        let mut pdu = [0; MAX_NETWORK_PDU_LEN];
+        let ctl_ttl = header.ttl & 0x7F;
+        pdu[0] = ((self.iv_index & 1) as u8) << 7 | self.keys.nid;
+        pdu[1] = ctl_ttl;
+        pdu[2..5].copy_from_slice(&header.seq.to_be_bytes()[1..]);
+        pdu[5..7].copy_from_slice(&header.src.to_be_bytes());
+        pdu[7..9].copy_from_slice(&header.dst.to_be_bytes());
+        pdu[9..len - NET_MIC_LEN].copy_from_slice(transport);
+
+        let nonce = network_nonce(ctl_ttl, header.seq, header.src, self.iv_index);
+        let (data, mic) = pdu[HEADER_LEN..len].split_at_mut(len - HEADER_LEN - NET_MIC_LEN);
+        let tag =
+            Ccm::<C, NET_MIC_LEN>::new(C::from(self.keys.encryption)).seal(&nonce, &[], data)?;
+        mic.copy_from_slice(&tag);
+
+        let pecb = self.pecb(self.iv_index, &pdu[HEADER_LEN..])?;
+        pdu[1..HEADER_LEN]
+            .iter_mut()
+            .zip(pecb)
+            .for_each(|(b, k)| *b ^= k);
+        Ok(Vec::from_slice(&pdu[..len]).unwrap())
+    }
+}
+
+/// Access message received by a [`Node`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Received<'a> {
+    pub header: NetworkHeader,
+    /// Decrypted access payload: opcode and parameters.
+    pub access: &'a [u8],
+}
+
+/// A node of a mesh network sending and receiving access messages with one
+/// application key.
+pub struct Node<C> {
+    network: Network<C>,
+    app_key: AppKey,
+    address: u16,
+    seq: u32,
+    ttl: u8,
+    reassembler: Reassembler<{ MAX_ACCESS_LEN + TRANS_MIC_LEN }>,
+    access: [u8; MAX_ACCESS_LEN + TRANS_MIC_LEN],
+}
+
+impl<C: BlockCipher + From<[u8; BLOCK_LEN]>> Node<C> {
+    /// Node with the unicast `address`, sending with the TTL `ttl` and
+    /// sequence numbers from `seq`.
+    pub fn new(network: Network<C>, app_key: AppKey, address: u16, seq: u32, ttl: u8) -> Self {
+        Self {
+            network,
+            app_key,
+            address,
+            seq,
+            ttl,
+            reassembler: Reassembler::new(),
+            access: [0; MAX_ACCESS_LEN + TRANS_MIC_LEN],
+        }
+    }
+
+    /// Next sequence number to be used.
+    pub fn seq(&self) -> u32 {
+        self.seq
+    }
+
+    fn next_seq(&mut self) -> u32 {
+        let seq = self.seq;
+        self.seq = (self.seq + 1) & 0xFF_FFFF;
+        seq
+    }
+
+    /// Encrypt the access payload `access` (opcode and parameters) for
+    /// `dst` into up to `N` network PDUs, segmented if needed.
+    pub fn send<const N: usize>(
+        &mut self,
+        dst: u16,
+        access: &[u8],
+    ) -> Result<Vec<NetworkPdu, N>, MeshError> {
+        let len = access.len() + TRANS_MIC_LEN;
+        if access.len() > MAX_ACCESS_LEN {
+            return Err(PduError::TooLong.into());
+        }
+        let seq_auth = self.seq;
+        let mut upper = [0; MAX_ACCESS_LEN + TRANS_MIC_LEN];
+        upper[..access.len()].copy_from_slice(access);
+        let nonce = application_nonce(false, seq_auth, self.address, dst, self.network.iv_index);
+        let (data, mic) = upper[..len].split_at_mut(access.len());
+        let tag =
+            Ccm::<C, TRANS_MIC_LEN>::new(C::from(self.app_key.key)).seal(&nonce, &[], data)?;
+        mic.copy_from_slice(&tag);
+        let upper = &upper[..len];
+
+        let mut pdus = Vec::new();
+        let mut lower = [0; MAX_UNSEGMENTED_LEN + 1];
+        if len <= MAX_UNSEGMENTED_LEN {
+            lower[0] = unsegmented_header(true, self.app_key.aid);
+            lower[1..=len].copy_from_slice(upper);
+            let header = self.header(dst);
+            let pdu = self.network.encrypt(&header, &lower[..=len])?;
+            pdus.push(pdu).map_err(|_| PduError::TooLong)?;
+        } else {
+            if len.div_ceil(SEGMENT_LEN) > N {
+                return Err(PduError::TooLong.into());
+            }
+            let segments = Segments::new(upper, true, self.app_key.aid, false, seq_auth)?;
+            for (segment_header, segment) in segments {
+                let segment_len = SegmentHeader::LEN + segment.len();
+                lower[..SegmentHeader::LEN].copy_from_slice(&segment_header);
+                lower[SegmentHeader::LEN..segment_len].copy_from_slice(segment);
+                let header = self.header(dst);
+                let pdu = self.network.encrypt(&header, &lower[..segment_len])?;
+                pdus.push(pdu).map_err(|_| PduError::TooLong)?;
+            }
+        }
+        Ok(pdus)
+    }
+
+    fn header(&mut self, dst: u16) -> NetworkHeader {
+        NetworkHeader {
+            ctl: false,
+            ttl: self.ttl,
+            seq: self.next_seq(),
+            src: self.address,
+            dst,
+        }
+    }
+
+    /// Decrypt a network PDU, returning the access message once it's
+    /// complete.
+    pub fn receive(&mut self, pdu: &mut [u8]) -> Result<Option<Received<'_>>, MeshError> {
+        // The application nonce has the IV index of the network layer.
+        let (header, iv_index, lower) = self.network.open(pdu)?;
+        if header.ctl || lower.is_empty() {
+            return Ok(None);
+        }
+        let (akf_aid, seq_auth, upper) = match SegmentHeader::parse(lower) {
+            Some((segment_header, segment)) => {
+                if segment_header.szmic {
+                    // 8 byte TransMIC, not supported.
+                    return Err(PduError::Malformed.into());
+                }
+                let Some(upper) = self.reassembler.push(header.src, segment_header, segment)?
+                else {
+                    return Ok(None);
+                };
+                // SeqAuth: the sequence number with the lowest 13 bits
+                // replaced by SeqZero, assuming it's from the same block.
+                let mut seq_auth = header.seq & !0x1FFF | segment_header.seq_zero as u32;
+                if seq_auth > header.seq {
+                    seq_auth = seq_auth.wrapping_sub(0x2000);
+                }
+                let len = upper.len();
+                self.access[..len].copy_from_slice(upper);
+                (lower[0], seq_auth, len)
+            }
+            None => {
+                let len = lower.len() - 1;
+                self.access[..len].copy_from_slice(&lower[1..]);
+                (lower[0], header.seq, len)
+            }
+        };
+        if akf_aid & 0x40 == 0 || akf_aid & 0x3F != self.app_key.aid || upper < TRANS_MIC_LEN {
+            return Err(MeshError::OtherKey);
+        }
+        let nonce = application_nonce(false, seq_auth, header.src, header.dst, iv_index);
+        let (data, mic) = self.access[..upper].split_at_mut(upper - TRANS_MIC_LEN);
+        let mic: [u8; TRANS_MIC_LEN] = (&*mic).try_into().unwrap();
+        Ccm::<C, TRANS_MIC_LEN>::new(C::from(self.app_key.key))
+            .open(&nonce, &[], data, &mic)
+            .map_err(|_| MeshError::OtherKey)?;
+        Ok(Some(Received {
+            header,
+            access: data,
+        }))
+    }
+}
+
+/// Advertising data carrying `pdu`, returns its length.
+pub fn adv_data(pdu: &[u8], out: &mut [u8]) -> Result<usize, PduError> {
+    let len = pdu.len() + 2;
+    if pdu.len() > MAX_NETWORK_PDU_LEN || out.len() < len {
+        return Err(PduError::TooLong);
+    }
+    out[0] = pdu.len() as u8 + 1;
+    out[1] = MESH_MESSAGE;
+    out[2..len].copy_from_slice(pdu);
+    Ok(len)
+}
+
+/// Network PDUs in the advertising data `data`.
+pub fn network_pdus(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = data;
+    core::iter::from_fn(move || {
+        loop {
+            let (&len, tail) = rest.split_first()?;
+            let len = len as usize;
+            if len == 0 || tail.len() < len {
+                return None;
+            }
+            let (structure, next) = tail.split_at(len);
+            rest = next;
+            if structure[0] == MESH_MESSAGE {
+                return Some(&structure[1..]);
+            }
+        }
+    })
+}
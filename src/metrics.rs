@@ -0,0 +1,82 @@
+//! Counters of the firmware since the last cold boot, they continue across
+//! resets through the [retained state](crate::retained).
+//!
+//! Subsystems increment the counters directly, [`snapshot`] reads them all
+//! at once (e.g. for a GATT characteristic or an Eddystone-TLM frame) and
+//! [`log_summary`] prints them periodically.
+
+use core::sync::atomic::{AtomicU16, AtomicU32, Ordering};
+
+use defmt::info;
+use embassy_time::{Duration, Instant, Timer};
+pub use nrf52_radio_core::metrics::{Snapshot, TLM_LEN};
+
+/// A counter since the last cold boot.
+pub struct Counter(AtomicU32);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    pub fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Continue from the value of the previous run.
+    pub fn restore(&self, value: u32) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Advertising sets started.
+pub static ADVERTISEMENTS: Counter = Counter::new();
+
+/// Connections established.
+pub static CONNECTIONS: Counter = Counter::new();
+
+/// Position fixes received from the GNSS module.
+pub static FIXES: Counter = Counter::new();
+
+/// Notifications given up because no packet became free.
+pub static NOTIFICATIONS_DROPPED: Counter = Counter::new();
+
+static BATTERY_MV: AtomicU16 = AtomicU16::new(0);
+
+/// Record the last battery voltage.
+pub fn set_battery_millivolts(millivolts: u16) {
+    BATTERY_MV.store(millivolts, Ordering::Relaxed);
+}
+
+/// Current values of all counters.
+pub fn snapshot() -> Snapshot {
+    Snapshot {
+        uptime_secs: Instant::now().as_secs() as u32,
+        advertisements: ADVERTISEMENTS.get(),
+        connections: CONNECTIONS.get(),
+        fixes: FIXES.get(),
+        battery_mv: BATTERY_MV.load(Ordering::Relaxed),
+        reserved: 0,
+    }
+}
+
+/// Log the counters every `interval`.
+pub async fn log_summary(interval: Duration) -> ! {
+    loop {
+        Timer::after(interval).await;
+        let s = snapshot();
+        info!(
+            "[metrics] uptime {} s, {} advertisements, {} connections, {} fixes, battery {} mV, {} notifications dropped",
+            s.uptime_secs,
+            s.advertisements,
+            s.connections,
+            s.fixes,
+            s.battery_mv,
+            NOTIFICATIONS_DROPPED.get()
+        );
+    }
+}
@@ -0,0 +1,16 @@
+//! Navigation helpers shared by the GNSS subsystem and the UI.
+//!
+//! The filters and [`format`] live in [`nrf52_radio_core::nav`] to be
+//! tested on the host.
+
+pub use nrf52_radio_core::nav::{
+    DEFAULT_STATIONARY_KNOTS, MotionFilter, MotionState, format, relative_bearing,
+};
+use nrf52_radio_core::telemetry::KNOT_CM_S;
+
+use crate::settings::Settings;
+
+/// Stationary threshold configured in the settings, in knots.
+pub fn stationary_knots(settings: &Settings) -> f32 {
+    settings.stationary_speed_cm_s as f32 / KNOT_CM_S
+}
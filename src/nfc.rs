@@ -0,0 +1,103 @@
+//! NFC tag emulation for out-of-band pairing.
+//!
+//! The NFCT peripheral emulates an NFC Forum Type 2 Tag holding a Bluetooth
+//! LE OOB record with the device name and its BLE address. Tapping a phone
+//! on the antenna (NFC1/NFC2 pins) offers to pair with the device. The LE
+//! Secure Connections OOB values are left out until the pairing of the BLE
+//! host takes them, a phone would fail to pair with values of another key.
+//!
+//! The hardware handles the anticollision, [`run_tag`] answers the READ
+//! commands of the reader from the tag memory.
+
+use defmt::info;
+use embassy_nrf::nfct::{Config, NfcId, NfcT, SddPat, SelResProtocol};
+use nrf52_radio_core::ndef::{BLOCK_LEN, le_oob_record};
+pub use nrf52_radio_core::ndef::{NdefError, OobData, Type2Tag};
+
+/// Bytes of the emulated tag memory, enough for a name of 20 characters
+/// with OOB values.
+pub const TAG_LEN: usize = 144;
+
+/// Tag memory holding the device information.
+pub type DeviceTag = Type2Tag<TAG_LEN>;
+
+/// Nordic Semiconductor as manufacturer in the first byte of a UID.
+const MANUFACTURER_NORDIC: u8 = 0x5F;
+
+/// Type 2 Tag READ command: page number, returns 4 pages.
+const CMD_READ: u8 = 0x30;
+
+/// Type 2 Tag HALT command.
+const CMD_HALT: u8 = 0x50;
+
+/// Longest command frame of the reader.
+const MAX_COMMAND_LEN: usize = 16;
+
+/// UID of the tag, derived from the BLE address (least significant byte
+/// first) so it's stable per device.
+pub fn uid(address: [u8; 6]) -> [u8; 7] {
+    let mut uid = [MANUFACTURER_NORDIC, 0, 0, 0, 0, 0, 0];
+    uid[1..].copy_from_slice(&address);
+    uid
+}
+
+/// Tag with the LE OOB record of the device.
+pub fn device_tag(
+    address: [u8; 6],
+    random_address: bool,
+    name: &str,
+    oob: Option<&OobData>,
+) -> Result<DeviceTag, NdefError> {
+    let mut message = [0; TAG_LEN];
+    let len = le_oob_record(&mut message, address, random_address, name, oob)?;
+    Type2Tag::new(uid(address), &message[..len])
+}
+
+/// Configuration of the NFCT peripheral for the [`device_tag`] with `address`.
+pub fn config(address: [u8; 6]) -> Config {
+    Config {
+        nfcid1: NfcId::DoubleSize(uid(address)),
+        sdd_pat: SddPat::SDD00100,
+        plat_conf: 0,
+        protocol: SelResProtocol::Type2,
+    }
+}
+
+/// Present `tag` to readers in the field, forever.
+pub async fn run_tag(nfct: &mut NfcT<'_>, tag: &DeviceTag) -> ! {
+    let mut command = [0; MAX_COMMAND_LEN];
+    loop {
+        nfct.activate().await;
+        info!("[nfc] selected by a reader");
+        loop {
+            let len = match nfct.recv_frame(&mut command).await {
+                Ok(len) => len,
+                Err(e) => {
+                    // The phone was taken away.
+                    crate::log!(Nfc, debug, "[nfc] receive error: {:?}", e);
+                    break;
+                }
+            };
+            match command[..len] {
+                [CMD_READ, page] => {
+                    let block: [u8; BLOCK_LEN] = tag.read(page);
+                    if let Err(e) = nfct.tx_frame(&block, 8).await {
+                        crate::log!(Nfc, debug, "[nfc] transmit error: {:?}", e);
+                        break;
+                    }
+                }
+                [CMD_HALT, 0] => {
+                    nfct.sleep();
+                    break;
+                }
+                // Not supported, the reader times out.
+                _ => crate::log!(
+                    Nfc,
+                    debug,
+                    "[nfc] ignoring command {=[u8]:x}",
+                    &command[..len]
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Battery-aware behavior of the subsystems.
+//!
+//! [`run`] follows the battery and charger events through a
+//! [`BatteryPolicy`] and publishes [`Event::PowerLevelChanged`], the
+//! subsystems adapt themselves:
+//! - [`PowerLevel::Reduced`]: the [GNSS policy](crate::gnss::policy) duty
+//!   cycles the module, advertising restarts at an interval of at least
+//!   [`REDUCED_ADV_INTERVAL_MS`], see [`adv_interval_ms`].
+//! - [`PowerLevel::Critical`]: the GNSS module is in standby and the
+//!   [display](crate::ui::PowerSave) stays off.
+//! - [`PowerLevel::Empty`]: [`run`] waits for pending settings to be written
+//!   and requests ship mode, whose [`Event::ShuttingDown`] stops the track
+//!   log session and flushes the telemetry.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use defmt::{info, warn};
+use embassy_time::{Duration, Instant, Timer};
+pub use nrf52_radio_core::policy::{
+    BatteryPolicy, CRITICAL_PERCENT, EMPTY_PERCENT, PowerLevel, REDUCED_PERCENT,
+};
+
+use crate::battery::ChargeState;
+use crate::events::{self, Event};
+use crate::settings;
+
+/// Shortest advertising interval at [`PowerLevel::Reduced`] and below.
+pub const REDUCED_ADV_INTERVAL_MS: u16 = 1000;
+
+/// Longest wait for the settings to be written before shutting down.
+const PERSIST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Interval in which the settings are checked while waiting.
+const PERSIST_POLL: Duration = Duration::from_millis(10);
+
+/// Current level as `PowerLevel as u8`.
+static LEVEL: AtomicU8 = AtomicU8::new(PowerLevel::Normal as u8);
+
+/// The current power level.
+pub fn level() -> PowerLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => PowerLevel::Normal,
+        1 => PowerLevel::Reduced,
+        2 => PowerLevel::Critical,
+        _ => PowerLevel::Empty,
+    }
+}
+
+/// Advertising interval to use instead of the `configured` one.
+pub fn adv_interval_ms(configured: u16) -> u16 {
+    match level() {
+        PowerLevel::Normal => configured,
+        _ => configured.max(REDUCED_ADV_INTERVAL_MS),
+    }
+}
+
+/// Publish the power level from the battery state of charge, and shut down
+/// once the battery is empty.
+pub async fn run() {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[policy] no event bus subscriber available");
+        return;
+    };
+    let mut policy = BatteryPolicy::new();
+    loop {
+        let changed = match events.next_message_pure().await {
+            Event::BatteryUpdated { percent, .. } => policy.battery(percent),
+            Event::ChargeStateChanged(state) => {
+                policy.external_power(state != ChargeState::Discharging)
+            }
+            _ => None,
+        };
+        let Some(level) = changed else {
+            continue;
+        };
+        info!("[policy] power level {:?}", level);
+        LEVEL.store(level as u8, Ordering::Relaxed);
+        events::publish(Event::PowerLevelChanged(level));
+        if level == PowerLevel::Empty {
+            persist().await;
+            events::publish(Event::ShipModeRequested);
+        }
+    }
+}
+
+/// Wait for changed settings to reach the flash.
+async fn persist() {
+    let deadline = Instant::now() + PERSIST_TIMEOUT;
+    while settings::save_pending() && Instant::now() < deadline {
+        Timer::after(PERSIST_POLL).await;
+    }
+}
@@ -0,0 +1,129 @@
+//! Factory provisioning.
+//!
+//! A new device is set up with the line protocol of
+//! [`nrf52_radio_core::provision`]: the device name, the keys of the
+//! [keystore], the compass calibration and other settings. The `provision`
+//! binary serves it over USB CDC, any other line transport (e.g. the NUS)
+//! can pass its lines to [`handle`]. Requests are refused once the `lock`
+//! request [locked](keystore::lock_provisioning) provisioning, which
+//! survives a factory reset. `debug-lock` enables the
+//! [access port protection](approtect) of production units.
+
+use core::fmt::Write;
+
+use defmt::{info, warn};
+use embassy_time::{Duration, Instant, Timer};
+pub use nrf52_radio_core::provision::{ParseError, Request, parse};
+
+use crate::bsp::approtect::{self, Protection};
+use crate::keystore::{self, KeyError, KeyId, SlotState};
+use crate::settings;
+
+/// Time the settings get to reach the flash before provisioning is locked.
+const SAVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether the device still takes provisioning requests.
+pub fn open() -> bool {
+    !keystore::provisioning_locked()
+}
+
+fn key_error(e: KeyError) -> &'static str {
+    match e {
+        KeyError::Provisioned => "key already provisioned",
+        KeyError::Corrupt => "key slot corrupt",
+        KeyError::Length => "wrong key length",
+        KeyError::Missing | KeyError::Crypto(_) => "key error",
+    }
+}
+
+fn status(out: &mut impl Write) -> core::fmt::Result {
+    let s = settings::get();
+    write!(out, "name: {}\r\n", s.device_name_str())?;
+    for id in KeyId::ALL {
+        let state = match keystore::state(id) {
+            SlotState::Empty => "empty",
+            SlotState::Valid => "provisioned",
+            SlotState::Corrupt => "corrupt",
+        };
+        write!(out, "key {}: {}\r\n", id.name(), state)?;
+    }
+    write!(out, "cal: {:?} {:?}\r\n", s.mag_min, s.mag_max)?;
+    write!(out, "locked: {}\r\n", !open())?;
+    let debug = match approtect::protection() {
+        Protection::Enabled => "locked",
+        Protection::Disabled | Protection::Unconfigured => "open",
+    };
+    write!(out, "debug: {}\r\n", debug)
+}
+
+/// Wait until the changed settings were written to flash.
+async fn flush_settings() {
+    let deadline = Instant::now() + SAVE_TIMEOUT;
+    while settings::save_pending() && Instant::now() < deadline {
+        Timer::after_millis(10).await;
+    }
+}
+
+/// Execute the request on `line` and write the response to `out`.
+pub async fn handle(line: &str, out: &mut impl Write) -> core::fmt::Result {
+    if !open() {
+        return write!(out, "ERR locked\r\n");
+    }
+    let request = match parse(line) {
+        Ok(request) => request,
+        Err(e) => return write!(out, "ERR {}\r\n", e.reason()),
+    };
+    let result = match request {
+        Request::Name(name) => {
+            let mut fits = false;
+            settings::update(|s| fits = s.set_device_name(name));
+            if fits { Ok(()) } else { Err("name too long") }
+        }
+        Request::Key(id, key) => keystore::provision(id, &key).map_err(key_error),
+        Request::Calibration { min, max } => {
+            if min.iter().zip(max).all(|(min, max)| *min < max) {
+                settings::update(|s| {
+                    s.mag_min = min;
+                    s.mag_max = max;
+                });
+                Ok(())
+            } else {
+                Err("minimum not below maximum")
+            }
+        }
+        Request::Set(name, value) => match settings::field(name) {
+            Some(field) => {
+                let mut valid = false;
+                settings::update(|s| valid = (field.set)(s, value));
+                if valid { Ok(()) } else { Err("out of range") }
+            }
+            None => Err("unknown setting"),
+        },
+        Request::Status => status(out).map_err(|_| "output full"),
+        Request::Lock => {
+            flush_settings().await;
+            keystore::lock_provisioning();
+            Ok(())
+        }
+        Request::DebugLock => {
+            approtect::enable();
+            write!(
+                out,
+                "WARNING debugger locked out from the next reset on\r\n"
+            )
+            .map_err(|_| "output full")
+        }
+    };
+    // Only the command is logged, never the key.
+    let command = line.split_whitespace().next().unwrap_or("");
+    match result {
+        Ok(()) => {
+            info!("[provision] {} done", command);
+            write!(out, "OK\r\n")
+        }
+        Err(reason) => {
+            warn!("[provision] {} failed: {}", command, reason);
+            write!(out, "ERR {}\r\n", reason)
+        }
+    }
+}
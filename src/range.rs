@@ -0,0 +1,25 @@
+//! Two-board range test (`range_tx` and `range_rx` binaries).
+//!
+//! `range_tx` advertises a numbered [`Frame`] every [`FRAME_INTERVAL`] at
+//! [`TX_POWER_DBM`] from [`TX_ADDRESS`]. `range_rx` scans for it, counts the
+//! frames with a [`PerCounter`] and shows the packet error rate and RSSI of
+//! every [`WINDOW`] on the OLED ([`RangePage`](crate::ui::RangePage)). The
+//! counting lives in [`nrf52_radio_core::range`] to be tested on the host.
+
+use embassy_time::Duration;
+pub use nrf52_radio_core::range::{FRAME_LEN, Frame, PerCounter, Window};
+
+/// Static random address of the transmitting board.
+pub const TX_ADDRESS: [u8; 6] = [0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xfb];
+
+/// Company identifier of the manufacturer specific data carrying the frames.
+pub const COMPANY_ID: u16 = 0xFFFF;
+
+/// TX power of the transmitter.
+pub const TX_POWER_DBM: i8 = 0;
+
+/// Interval in which the transmitter advertises the next frame.
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Measurement window of the receiver.
+pub const WINDOW: Duration = Duration::from_secs(2);
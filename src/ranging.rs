@@ -0,0 +1,304 @@
+//! Experimental distance estimation between two boards by round trip time.
+//!
+//! Both boards use the raw RADIO in [timeslots](crate::bsp::ble::timeslot)
+//! between their BLE events. The [`Initiator`] sends polls and TIMER0
+//! captures the address events of the poll and of its echo through the
+//! pre-programmed PPI channel 26. The [`Responder`] echoes every packet it
+//! receives with the fixed turnaround of the RADIO shortcuts. The round trip
+//! times arrive through [`next_rtt`] and are turned into distances by an
+//! [`Estimator`], see [`nrf52_radio_core::ranging`].
+//!
+//! Both sides run in independent timeslots, polls the responder doesn't
+//! hear are lost and cost the rest of the initiator's slot.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, Ordering};
+
+use embassy_nrf::pac::{self, radio::vals};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::Duration;
+pub use nrf52_radio_core::ranging::{Estimate, Estimator, POLL_LEN, Poll, TICK_HZ};
+
+use crate::bsp::ble::timeslot::{Action, Request, TimeslotHandler};
+
+/// RF channel, 2480 MHz, above the BLE advertising channel 39.
+const FREQUENCY: u8 = 80;
+
+/// Base address and prefix of the ranging packets.
+const BASE_ADDRESS: u32 = 0x5241_4e47;
+const PREFIX: u8 = 0xa5;
+
+/// Turnaround of the responder in µs.
+const TIFS_US: u16 = 150;
+
+/// Pre-programmed PPI channel from RADIO ADDRESS to TIMER0 CAPTURE[1].
+const PPI_ADDRESS_CAPTURE: usize = 26;
+
+/// Length of the slots of the initiator, a few exchanges each.
+pub const INITIATOR_SLOT: Duration = Duration::from_millis(5);
+
+/// Distance between the slots of the initiator.
+pub const INITIATOR_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Length of the slots of the responder, listening most of the time.
+pub const RESPONDER_SLOT: Duration = Duration::from_millis(100);
+
+/// The slot is ended this long before the granted length runs out.
+const SLOT_MARGIN: Duration = Duration::from_micros(500);
+
+/// Round trip times in ticks of [`TICK_HZ`], waiting for [`next_rtt`].
+static RTTS: Channel<CriticalSectionRawMutex, u32, 32> = Channel::new();
+
+/// Wait for the next round trip time of the [`Initiator`].
+pub async fn next_rtt() -> u32 {
+    RTTS.receive().await
+}
+
+/// Buffer the RADIO sends from and receives into.
+struct PacketBuffer(UnsafeCell<[u8; POLL_LEN]>);
+
+// SAFETY: only accessed from the timeslot callback, which isn't reentrant.
+unsafe impl Sync for PacketBuffer {}
+
+static PACKET: PacketBuffer = PacketBuffer(UnsafeCell::new([0; POLL_LEN]));
+
+/// Configure RADIO and TIMER0 at the start of a slot.
+///
+/// TIMER0 is switched to [`TICK_HZ`] and ends the slot with COMPARE[0]
+/// [`SLOT_MARGIN`] before `length`.
+fn setup(length: Duration) {
+    let radio = pac::RADIO;
+    radio.mode().write(|w| w.set_mode(vals::Mode::NRF_1MBIT));
+    radio.frequency().write(|w| w.set_frequency(FREQUENCY));
+    radio
+        .txpower()
+        .write(|w| w.set_txpower(vals::Txpower::_0_DBM));
+    // Static length packets without S0, LENGTH and S1.
+    radio.pcnf0().write(|_| {});
+    radio.pcnf1().write(|w| {
+        w.set_maxlen(POLL_LEN as u8);
+        w.set_statlen(POLL_LEN as u8);
+        w.set_balen(4);
+        w.set_whiteen(true);
+    });
+    radio.base0().write_value(BASE_ADDRESS);
+    radio.prefix0().write(|w| w.set_ap(0, PREFIX));
+    radio.txaddress().write(|w| w.set_txaddress(0));
+    radio.rxaddresses().write(|w| w.set_addr(0, true));
+    radio.crccnf().write(|w| w.set_len(vals::Len::TWO));
+    radio.crcinit().write(|w| w.set_crcinit(0xffff));
+    radio.crcpoly().write(|w| w.set_crcpoly(0x1_1021));
+    radio
+        .datawhiteiv()
+        .write(|w| w.set_datawhiteiv(FREQUENCY & 0x3f));
+    radio.tifs().write(|w| w.set_tifs(TIFS_US));
+    radio.packetptr().write_value(PACKET.0.get() as u32);
+    radio.events_disabled().write_value(0);
+    radio.intenset().write(|w| w.set_disabled(true));
+
+    let timer = pac::TIMER0;
+    timer.tasks_stop().write_value(1);
+    timer.tasks_clear().write_value(1);
+    timer.prescaler().write(|w| w.set_prescaler(0));
+    timer
+        .bitmode()
+        .write(|w| w.set_bitmode(pac::timer::vals::Bitmode::_32BIT));
+    let end = length.as_micros() - SLOT_MARGIN.as_micros();
+    timer
+        .cc(0)
+        .write_value((end * TICK_HZ as u64 / 1_000_000) as u32);
+    timer.events_compare(0).write_value(0);
+    timer.intenset().write(|w| w.set_compare(0, true));
+    timer.tasks_start().write_value(1);
+}
+
+/// Stop the RADIO and TIMER0, so the slot can end.
+fn teardown() {
+    pac::PPI
+        .chenclr()
+        .write(|w| w.set_ch(PPI_ADDRESS_CAPTURE, true));
+    let radio = pac::RADIO;
+    radio.shorts().write(|_| {});
+    radio.intenclr().write(|w| w.set_disabled(true));
+    if radio.state().read().state() != vals::State::DISABLED {
+        radio.events_disabled().write_value(0);
+        radio.tasks_disable().write_value(1);
+        while radio.events_disabled().read() == 0 {}
+    }
+    radio.events_disabled().write_value(0);
+    let timer = pac::TIMER0;
+    timer.intenclr().write(|w| w.set_compare(0, true));
+    timer.events_compare(0).write_value(0);
+    timer.tasks_stop().write_value(1);
+}
+
+/// Whether the last received packet had a valid CRC.
+fn crc_ok() -> bool {
+    pac::RADIO.crcstatus().read().crcstatus() == vals::Crcstatus::CRCOK
+}
+
+/// Sends polls and measures the round trip times of their echoes.
+pub struct Initiator {
+    seq: AtomicU16,
+    /// TIMER0 at the address event of the poll in flight.
+    tx_ticks: AtomicU32,
+    /// Whether the RADIO is receiving the echo.
+    receiving: AtomicBool,
+}
+
+impl Initiator {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU16::new(0),
+            tx_ticks: AtomicU32::new(0),
+            receiving: AtomicBool::new(false),
+        }
+    }
+
+    /// First request of the session.
+    pub fn first_request() -> Request {
+        Request::Earliest {
+            length: INITIATOR_SLOT,
+            timeout: INITIATOR_INTERVAL,
+        }
+    }
+
+    /// Send the next poll, the RADIO switches to receiving afterwards.
+    fn poll(&self) {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+        // SAFETY: the RADIO is disabled, it doesn't access the buffer.
+        unsafe { *PACKET.0.get() = Poll { seq }.encode() };
+        self.receiving.store(false, Ordering::Relaxed);
+        let radio = pac::RADIO;
+        radio.shorts().write(|w| {
+            w.set_ready_start(true);
+            w.set_end_disable(true);
+            w.set_disabled_rxen(true);
+        });
+        radio.tasks_txen().write_value(1);
+    }
+}
+
+impl Default for Initiator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeslotHandler for Initiator {
+    fn start(&self) -> Action {
+        setup(INITIATOR_SLOT);
+        pac::PPI
+            .chenset()
+            .write(|w| w.set_ch(PPI_ADDRESS_CAPTURE, true));
+        self.poll();
+        Action::None
+    }
+
+    fn timer0(&self) -> Action {
+        teardown();
+        Action::Request(Request::Normal {
+            length: INITIATOR_SLOT,
+            distance: INITIATOR_INTERVAL,
+        })
+    }
+
+    fn radio(&self) -> Action {
+        let radio = pac::RADIO;
+        if radio.events_disabled().read() == 0 {
+            return Action::None;
+        }
+        radio.events_disabled().write_value(0);
+        let captured = pac::TIMER0.cc(1).read();
+        if !self.receiving.swap(true, Ordering::Relaxed) {
+            // The poll is out, the shortcut started receiving the echo.
+            self.tx_ticks.store(captured, Ordering::Relaxed);
+            radio.shorts().write(|w| {
+                w.set_ready_start(true);
+                w.set_end_disable(true);
+            });
+            return Action::None;
+        }
+        // SAFETY: the RADIO is disabled, it doesn't access the buffer.
+        let echo = Poll::decode(unsafe { &*PACKET.0.get() });
+        if crc_ok() && echo.map(|p| p.seq) == Some(self.seq.load(Ordering::Relaxed)) {
+            let rtt = captured.wrapping_sub(self.tx_ticks.load(Ordering::Relaxed));
+            let _ = RTTS.try_send(rtt);
+        }
+        self.poll();
+        Action::None
+    }
+}
+
+/// Echoes every packet after the fixed turnaround.
+pub struct Responder {
+    /// Whether the RADIO is sending the echo.
+    sending: AtomicBool,
+}
+
+impl Responder {
+    pub const fn new() -> Self {
+        Self {
+            sending: AtomicBool::new(false),
+        }
+    }
+
+    /// First request of the session.
+    pub fn first_request() -> Request {
+        Request::Earliest {
+            length: RESPONDER_SLOT,
+            timeout: RESPONDER_SLOT,
+        }
+    }
+
+    /// Receive, then echo from the same buffer through the shortcuts.
+    fn listen(&self) {
+        self.sending.store(false, Ordering::Relaxed);
+        pac::RADIO.shorts().write(|w| {
+            w.set_ready_start(true);
+            w.set_end_disable(true);
+            w.set_disabled_txen(true);
+        });
+    }
+}
+
+impl Default for Responder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeslotHandler for Responder {
+    fn start(&self) -> Action {
+        setup(RESPONDER_SLOT);
+        self.listen();
+        pac::RADIO.tasks_rxen().write_value(1);
+        Action::None
+    }
+
+    fn timer0(&self) -> Action {
+        teardown();
+        Action::Request(Self::first_request())
+    }
+
+    fn radio(&self) -> Action {
+        let radio = pac::RADIO;
+        if radio.events_disabled().read() == 0 {
+            return Action::None;
+        }
+        radio.events_disabled().write_value(0);
+        if self.sending.swap(true, Ordering::Relaxed) {
+            // The echo is out, the shortcut started receiving again.
+            self.listen();
+        } else {
+            // Received, the shortcut started sending the echo.
+            radio.shorts().write(|w| {
+                w.set_ready_start(true);
+                w.set_end_disable(true);
+                w.set_disabled_rxen(true);
+            });
+        }
+        Action::None
+    }
+}
@@ -0,0 +1,200 @@
+//! Error recovery for the BLE stack.
+//!
+//! Instead of panicking on the first error of the `trouble-host` runner or
+//! the advertising loop, errors are classified with [`classify`]. Transient
+//! errors are retried after an exponential [`Backoff`], controller faults
+//! additionally reset the controller, and only fatal errors (or too many
+//! consecutive failures) put the firmware into a failure state, signalled by
+//! the status LED. All of this is counted in [`DIAGNOSTICS`].
+//!
+//! The consecutive failures are counted by the [`Backoff`] of each
+//! subsystem, one failing subsystem doesn't use up the budget of another.
+//! A subsystem restarted by the [supervisor](crate::supervisor) starts with
+//! a new backoff and no failures.
+//!
+//! The controller is reset by restarting the `trouble-host` runner, which
+//! resets it and applies the configuration of the host again (random
+//! address, event masks). The runner waits for [`reset_requested`] next to
+//! running, vendor specific configuration is up to the users of the
+//! controller, e.g. per connection.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use defmt::{error, warn};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use trouble_host::prelude::*;
+
+use crate::bsp::led::{self, LedPattern};
+
+/// Number of consecutive failures after which recovery is given up.
+pub const MAX_CONSECUTIVE_FAILURES: u32 = 8;
+
+/// How an error should be handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ErrorClass {
+    /// Retry after a backoff, e.g. disconnects, timeouts or exhausted buffers.
+    Transient,
+    /// The controller misbehaved and has to be reset before retrying.
+    Controller,
+    /// Retrying won't help, e.g. invalid configuration.
+    Fatal,
+}
+
+/// Classify an error returned by the BLE host.
+pub fn classify<E>(err: &BleHostError<E>) -> ErrorClass {
+    match err {
+        BleHostError::Controller(_) => ErrorClass::Controller,
+        BleHostError::BleHost(e) => match e {
+            Error::Disconnected
+            | Error::Timeout
+            | Error::Busy
+            | Error::OutOfMemory
+            | Error::NoChannelAvailable
+            | Error::ConnectionLimitReached => ErrorClass::Transient,
+            Error::Hci(_) => ErrorClass::Controller,
+            _ => ErrorClass::Fatal,
+        },
+    }
+}
+
+/// Counters describing the health of the BLE stack.
+pub struct Diagnostics {
+    /// Errors returned by the BLE stack since boot.
+    pub errors: AtomicU32,
+    /// Controller resets performed since boot.
+    pub controller_resets: AtomicU32,
+    /// Consecutive failures of all subsystems, without a success of the
+    /// failing one in between.
+    pub consecutive_failures: AtomicU32,
+}
+
+impl Diagnostics {
+    const fn new() -> Self {
+        Self {
+            errors: AtomicU32::new(0),
+            controller_resets: AtomicU32::new(0),
+            consecutive_failures: AtomicU32::new(0),
+        }
+    }
+}
+
+/// Diagnostics of the BLE stack.
+pub static DIAGNOSTICS: Diagnostics = Diagnostics::new();
+
+/// A controller reset requested by [`recover`].
+static RESET: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Wait until the controller has to be reset, the runner of the host
+/// restarts then.
+pub async fn reset_requested() {
+    RESET.wait().await
+}
+
+/// Exponential backoff between retries, counting the consecutive failures
+/// of a subsystem until [`reset`](Self::reset) or drop.
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+    failures: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(30))
+    }
+}
+
+impl Backoff {
+    pub const fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            current: initial,
+            failures: 0,
+        }
+    }
+
+    /// Count a failure, returning the consecutive failures.
+    pub fn fail(&mut self) -> u32 {
+        self.failures += 1;
+        DIAGNOSTICS
+            .consecutive_failures
+            .fetch_add(1, Ordering::Relaxed);
+        self.failures
+    }
+
+    /// Wait for the current delay and double it for the next time.
+    pub async fn wait(&mut self) {
+        Timer::after(self.current).await;
+        self.current = (self.current * 2).min(self.max);
+    }
+
+    /// Start again with the initial delay and no failures, after a
+    /// successful operation.
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+        let failures = core::mem::take(&mut self.failures);
+        if failures > 0
+            && DIAGNOSTICS
+                .consecutive_failures
+                .fetch_sub(failures, Ordering::Relaxed)
+                == failures
+        {
+            led::set_pattern(LedPattern::Heartbeat);
+        }
+    }
+}
+
+impl Drop for Backoff {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
+
+/// Outcome of [`recover`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Recovery {
+    /// The operation should be retried.
+    Retry,
+    /// Recovery failed, the subsystem should stay down.
+    GiveUp,
+}
+
+/// Handle an error of the BLE stack: count it, request a controller reset
+/// if necessary and wait for the backoff.
+///
+/// `tag` is used as log prefix.
+pub async fn recover<E: core::fmt::Debug>(
+    tag: &str,
+    err: &BleHostError<E>,
+    backoff: &mut Backoff,
+) -> Recovery {
+    let class = classify(err);
+    DIAGNOSTICS.errors.fetch_add(1, Ordering::Relaxed);
+    let failures = backoff.fail();
+    warn!(
+        "[{}] error ({:?}, failure {}): {:?}",
+        tag,
+        class,
+        failures,
+        defmt::Debug2Format(err)
+    );
+    if class == ErrorClass::Fatal || failures >= MAX_CONSECUTIVE_FAILURES {
+        error!("[{}] giving up", tag);
+        led::set_pattern(LedPattern::Failure);
+        return Recovery::GiveUp;
+    }
+    led::set_pattern(LedPattern::Recovering);
+    if class == ErrorClass::Controller {
+        DIAGNOSTICS
+            .controller_resets
+            .fetch_add(1, Ordering::Relaxed);
+        warn!("[{}] resetting the controller", tag);
+        RESET.signal(());
+    }
+    backoff.wait().await;
+    Recovery::Retry
+}
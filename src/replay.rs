@@ -0,0 +1,124 @@
+//! Replay protection of signed updates.
+//!
+//! Every [signed](crate::crypto::signed) update carries a sequence number,
+//! only updates newer than the last one taken are [accepted](accept). The
+//! last sequence number is kept in the [`REPLAY`] page, apart from the
+//! settings: neither a [factory reset](crate::factory_reset) nor the
+//! defaults after a settings migration lower it.
+//!
+//! The page is an append-only log of the sequence numbers as little endian
+//! words, the last one written counts. The [`Store`] appends every new one
+//! and erases the page only when it is full, writing the current number
+//! back right after.
+//!
+//! [`REPLAY`]: crate::bsp::flash::REPLAY
+
+use core::cell::Cell;
+
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_sync::signal::Signal;
+use embedded_storage_async::nor_flash::NorFlash;
+
+use crate::bsp::flash::PAGE_SIZE;
+use crate::crypto::signed::{Signed, SignedError};
+use crate::supervisor::Subsystem;
+
+/// Bytes of an entry of the log.
+const ENTRY_LEN: u32 = 4;
+
+/// Entries read at once while loading.
+const LOAD_CHUNK: usize = 64;
+
+/// Sequence number of the last signed update taken.
+static LAST: Mutex<CriticalSectionRawMutex, Cell<u32>> = Mutex::new(Cell::new(0));
+
+/// Signalled when the sequence number has to be written to flash.
+static SAVE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Sequence number of the last signed update taken.
+pub fn last() -> u32 {
+    LAST.lock(|l| l.get())
+}
+
+/// Take the update `signed` if it is newer than the last one, its sequence
+/// number is written to flash.
+pub fn accept(signed: &Signed<'_>) -> Result<(), SignedError> {
+    LAST.lock(|l| {
+        signed.check_sequence(l.get())?;
+        l.set(signed.sequence);
+        Ok(())
+    })?;
+    SAVE.signal(());
+    Ok(())
+}
+
+/// Flash backed storage of the sequence number.
+///
+/// `flash` is the [`REPLAY`](crate::bsp::flash::REPLAY) partition.
+pub struct Store<F> {
+    flash: F,
+    /// Entries in the page.
+    len: u32,
+}
+
+impl<F: NorFlash> Store<F> {
+    pub fn new(flash: F) -> Self {
+        Self { flash, len: 0 }
+    }
+
+    /// Load the last sequence number, before any update is taken.
+    pub async fn load(&mut self) -> Result<(), F::Error> {
+        let mut chunk = [0; LOAD_CHUNK * ENTRY_LEN as usize];
+        let mut last = 0;
+        self.len = 0;
+        'entries: while self.len < PAGE_SIZE / ENTRY_LEN {
+            self.flash.read(self.len * ENTRY_LEN, &mut chunk).await?;
+            for entry in chunk.chunks_exact(ENTRY_LEN as usize) {
+                let entry = u32::from_le_bytes(entry.try_into().unwrap());
+                if entry == u32::MAX {
+                    break 'entries;
+                }
+                last = entry;
+                self.len += 1;
+            }
+        }
+        // Only ever raised, an update taken before loading stays refused.
+        LAST.lock(|l| l.set(l.get().max(last)));
+        info!("[replay] last signed update {}", last);
+        Ok(())
+    }
+
+    /// Append the current sequence number, compacting the page if it's full.
+    pub async fn save(&mut self) -> Result<(), F::Error> {
+        let last = last();
+        if self.len >= PAGE_SIZE / ENTRY_LEN {
+            self.flash.erase(0, PAGE_SIZE).await?;
+            self.len = 0;
+        }
+        self.flash
+            .write(self.len * ENTRY_LEN, &last.to_le_bytes())
+            .await?;
+        self.len += 1;
+        Ok(())
+    }
+}
+
+impl<F: NorFlash> Subsystem for Store<F> {
+    type Error = F::Error;
+
+    const NAME: &'static str = "replay";
+
+    /// Write the sequence number to flash whenever an update was taken.
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        loop {
+            SAVE.wait().await;
+            if let Err(e) = self.save().await {
+                warn!("[replay] couldn't save the sequence number");
+                // Retry after the supervisor restarted us.
+                SAVE.signal(());
+                return Err(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! State preserved across resets in retained RAM.
+//!
+//! A [`RetainedState`] sits in the `.retained` section at the top of RAM
+//! (see `memory/*.x`), which the startup code leaves alone. [`keep`] stores
+//! the clock, the last fix and the [counters](crate::metrics) in it. After a
+//! watchdog or soft reset, or a wake from System OFF, [`init`] finds a
+//! valid record and resumes right away: the counters continue and, unless
+//! the device was off for an unknown time, the [track log clock](crate::track)
+//! runs on without waiting for the GNSS time. The RAM section holding the
+//! record is retained in System OFF.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+
+use bytemuck::Zeroable;
+use defmt::info;
+use embassy_futures::select::{Either, select};
+use embassy_nrf::pac;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Instant, Ticker};
+pub use nrf52_radio_core::retained::{CLOCK_VALID, FIX_VALID, RetainedState};
+
+use crate::events::{self, Event};
+use crate::metrics;
+use crate::track;
+
+/// RAM block and section holding the `.retained` section, for System OFF
+/// retention.
+#[cfg(feature = "chip-nrf52840")]
+const RAM_SECTION: (usize, usize) = (8, 5);
+#[cfg(feature = "chip-nrf52833")]
+const RAM_SECTION: (usize, usize) = (8, 1);
+#[cfg(feature = "chip-nrf52832")]
+const RAM_SECTION: (usize, usize) = (7, 1);
+
+/// Interval in which the uptime and the counters are stored.
+const KEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Not initialized, the startup code skips the section.
+#[unsafe(link_section = ".retained")]
+static RETAINED: Mutex<CriticalSectionRawMutex, UnsafeCell<MaybeUninit<RetainedState>>> =
+    Mutex::new(UnsafeCell::new(MaybeUninit::uninit()));
+
+/// Read, change and seal the record.
+fn update<R>(f: impl FnOnce(&mut RetainedState) -> R) -> R {
+    RETAINED.lock(|cell| {
+        // SAFETY: Any bit pattern is a `RetainedState` (it's `Pod`), and the
+        // lock makes the access exclusive.
+        let state = unsafe { (*cell.get()).assume_init_mut() };
+        let result = f(state);
+        state.seal();
+        result
+    })
+}
+
+/// Take the record of the previous run, if there is one, and restore its
+/// state. Call first thing in `main`.
+///
+/// Returns the previous record.
+pub fn init() -> Option<RetainedState> {
+    let (block, section) = RAM_SECTION;
+    pac::POWER
+        .ram(block)
+        .powerset()
+        .write(|w| w.set_s_retention(section, true));
+    let reason = pac::POWER.resetreas().read();
+    pac::POWER
+        .resetreas()
+        .write_value(pac::power::regs::Resetreas(0xFFFF_FFFF));
+    // Only these resets keep the clock running, the others take an unknown
+    // time.
+    let clock_kept = reason.dog() || reason.sreq() || reason.lockup();
+
+    let previous = update(|state| {
+        let previous = state.is_valid().then_some(*state);
+        *state = match previous {
+            Some(previous) => previous.resume(clock_kept),
+            None => RetainedState::new(),
+        };
+        previous
+    });
+    let Some(previous) = previous else {
+        info!("[retained] cold boot");
+        return None;
+    };
+    let resumed = update(|state| *state);
+    info!(
+        "[retained] warm boot {}, clock {}",
+        resumed.warm_boots,
+        if resumed.utc_at_reset().is_some() {
+            "kept"
+        } else {
+            "lost"
+        }
+    );
+    if let Some(utc) = resumed.utc_at_reset() {
+        track::set_clock(utc, Instant::from_ticks(0));
+    }
+    metrics::ADVERTISEMENTS.restore(resumed.advertisements);
+    metrics::CONNECTIONS.restore(resumed.connections);
+    metrics::FIXES.restore(resumed.fixes);
+    Some(previous)
+}
+
+/// Drop the record, the next boot is a cold one, e.g. after a
+/// [factory reset](crate::factory_reset). [`keep`] seals it again, reset
+/// right away.
+pub fn invalidate() {
+    RETAINED.lock(|cell| {
+        // SAFETY: The lock makes the access exclusive.
+        unsafe { (*cell.get()).write(RetainedState::zeroed()) };
+    });
+}
+
+/// The last fix as latitude and longitude in degrees, also of a previous run.
+pub fn last_fix() -> Option<(f64, f64)> {
+    update(|state| state.last_fix())
+}
+
+/// Keep the record up to date with the events on the bus.
+pub async fn keep() {
+    let Ok(mut events) = events::subscribe() else {
+        defmt::warn!("[retained] no event bus subscriber available");
+        return;
+    };
+    let mut ticker = Ticker::every(KEEP_INTERVAL);
+    loop {
+        match select(events.next_message_pure(), ticker.next()).await {
+            Either::First(Event::FixAcquired(fix)) => {
+                update(|state| state.set_fix(fix.latitude, fix.longitude))
+            }
+            Either::First(Event::TimeUpdated(dt)) => update(|state| {
+                state.set_clock(dt.and_utc().timestamp(), Instant::now().as_secs() as u32)
+            }),
+            Either::First(_) => {}
+            Either::Second(_) => update(|state| {
+                state.uptime_secs = Instant::now().as_secs() as u32;
+                state.advertisements = metrics::ADVERTISEMENTS.get();
+                state.connections = metrics::CONNECTIONS.get();
+                state.fixes = metrics::FIXES.get();
+            }),
+        }
+    }
+}
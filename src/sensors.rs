@@ -0,0 +1,138 @@
+//! Polling of the environmental sensors.
+//!
+//! Drivers implement [`Sensor`] and are registered with a [`Scheduler`] as
+//! a tuple of [`Scheduled`] sensors, each with its own interval. The
+//! scheduler samples every sensor when it's due, one after another as they
+//! usually share the I2C bus, and publishes the results as
+//! [`Event::SensorSampled`] for the fusion, BLE and logging consumers.
+
+use defmt::warn;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::i2c::I2c;
+
+use crate::bsp::barometer::{AnyBarometer, Barometer, BarometerError, Pressure};
+use crate::events::{self, Event};
+use crate::supervisor::Subsystem;
+
+/// A measurement of a [`Sensor`].
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Sample {
+    Pressure(Pressure),
+    /// Temperature in °C.
+    Temperature(f32),
+    /// Relative humidity in percent.
+    Humidity(f32),
+}
+
+/// Errors of a [`Sensor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SensorError {
+    /// The sensor has no new measurement yet.
+    NotReady,
+    /// A transfer failed.
+    Bus,
+}
+
+/// A sensor polled by the [`Scheduler`].
+pub trait Sensor {
+    /// Name used in log messages.
+    const NAME: &'static str;
+
+    /// Take a measurement.
+    fn sample(&mut self) -> impl Future<Output = Result<Sample, SensorError>>;
+}
+
+impl<I: I2c> Sensor for AnyBarometer<I> {
+    const NAME: &'static str = "barometer";
+
+    async fn sample(&mut self) -> Result<Sample, SensorError> {
+        self.read().map(Sample::Pressure).map_err(|e| match e {
+            BarometerError::NotReady => SensorError::NotReady,
+            BarometerError::NotFound | BarometerError::Bus => SensorError::Bus,
+        })
+    }
+}
+
+/// A sensor with its polling interval.
+pub struct Scheduled<S> {
+    sensor: S,
+    interval: Duration,
+    next: Instant,
+}
+
+impl<S: Sensor> Scheduled<S> {
+    /// Sample `sensor` every `interval`, starting right away.
+    pub fn new(sensor: S, interval: Duration) -> Self {
+        Self {
+            sensor,
+            interval,
+            next: Instant::now(),
+        }
+    }
+
+    async fn sample_if_due(&mut self, now: Instant) {
+        if now < self.next {
+            return;
+        }
+        match self.sensor.sample().await {
+            Ok(sample) => events::publish(Event::SensorSampled(sample)),
+            Err(e) => warn!("[sensors] {=str}: {:?}", S::NAME, e),
+        }
+        // Skip missed samples instead of catching up.
+        self.next = (self.next + self.interval).max(now);
+    }
+}
+
+/// A tuple of [`Scheduled`] sensors.
+pub trait SensorList {
+    /// Time the next sensor is due.
+    fn next_due(&self) -> Instant;
+
+    /// Sample all sensors due at `now`.
+    fn sample_due(&mut self, now: Instant) -> impl Future<Output = ()>;
+}
+
+macro_rules! impl_sensor_list {
+    ($($sensor:ident $index:tt),+) => {
+        impl<$($sensor: Sensor),+> SensorList for ($(Scheduled<$sensor>,)+) {
+            fn next_due(&self) -> Instant {
+                let mut next = Instant::MAX;
+                $(next = next.min(self.$index.next);)+
+                next
+            }
+
+            async fn sample_due(&mut self, now: Instant) {
+                $(self.$index.sample_if_due(now).await;)+
+            }
+        }
+    };
+}
+
+impl_sensor_list!(A 0);
+impl_sensor_list!(A 0, B 1);
+impl_sensor_list!(A 0, B 1, C 2);
+impl_sensor_list!(A 0, B 1, C 2, D 3);
+
+/// Polls the registered sensors, never fails.
+pub struct Scheduler<L> {
+    sensors: L,
+}
+
+impl<L: SensorList> Scheduler<L> {
+    pub fn new(sensors: L) -> Self {
+        Self { sensors }
+    }
+}
+
+impl<L: SensorList> Subsystem for Scheduler<L> {
+    type Error = core::convert::Infallible;
+
+    const NAME: &'static str = "sensors";
+
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        loop {
+            Timer::at(self.sensors.next_due()).await;
+            self.sensors.sample_due(Instant::now()).await;
+        }
+    }
+}
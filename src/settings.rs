@@ -0,0 +1,632 @@
+//! Persistent device settings.
+//!
+//! The current [`Settings`] are kept in RAM and read with [`get`]. Changes
+//! made with [`update`] are published as [`Event::SettingsChanged`] and
+//! written to a dedicated flash page ([`SETTINGS`](crate::bsp::flash::SETTINGS))
+//! by the [`Store`] subsystem. A record with a wrong magic, version or
+//! checksum (e.g. erased flash) is ignored and the defaults are used.
+//!
+//! Settings are accessible by name through [`FIELDS`], which also backs the
+//! `get` and `set` shell [`COMMANDS`]. The `accept` command edits the
+//! [accept list](Settings::accept_list).
+//!
+//! The whole settings are copied between devices as one blob with
+//! [`export`] and [`import`], the flash record including its header and
+//! checksum. Firmware built with a signing key imports only blobs
+//! [signed](crate::crypto::signed) for [`Kind::Settings`], newer than the
+//! [last one](crate::replay).
+
+use core::cell::Cell;
+use core::fmt::Write;
+
+use bytemuck::{Pod, Zeroable};
+use defmt::{info, warn};
+use embassy_sync::signal::Signal;
+use embassy_sync::{blocking_mutex::Mutex, blocking_mutex::raw::CriticalSectionRawMutex};
+use embedded_storage_async::nor_flash::NorFlash;
+pub use nrf52_radio_core::crc::crc32;
+use nrf52_radio_core::telemetry::KNOT_CM_S;
+
+use crate::bsp::buzzer::alerts;
+use crate::bsp::flash::PAGE_SIZE;
+use crate::compass::Calibration;
+use crate::crypto::signed::{self, Kind, SignedError};
+use crate::epoch::MAX_UNITS;
+use crate::events::{self, Event};
+use crate::fmt;
+use crate::nav::DEFAULT_STATIONARY_KNOTS;
+use crate::replay;
+use crate::shell::{Command, Output};
+use crate::supervisor::Subsystem;
+use crate::ui::oled::{Controller, PanelSize, Rotation};
+
+/// Marks a settings record in flash.
+const MAGIC: u32 = 0x5345_5454; // "SETT"
+
+/// Layout version of [`Settings`]. Bump when fields are changed or removed.
+const VERSION: u16 = 15;
+
+/// Number of centrals on the accept list.
+pub const ACCEPT_LIST_LEN: usize = 4;
+
+/// Length of the [keyboard text](Settings::keyboard_text).
+pub const KEYBOARD_TEXT_LEN: usize = 32;
+
+/// Length of the [device name](Settings::device_name), it fits the
+/// advertising data next to the flags and a 16 bit service UUID.
+pub const DEVICE_NAME_LEN: usize = 20;
+
+/// Shortest advertising interval allowed by the specification, in ms.
+pub const ADV_INTERVAL_MIN_MS: u16 = 20;
+
+/// Longest advertising interval allowed by the specification, in ms.
+pub const ADV_INTERVAL_MAX_MS: u16 = 10_240;
+
+/// Highest die temperature limit, the maximum operating temperature.
+pub const THERMAL_LIMIT_MAX_C: u16 = 85;
+
+/// Weakest RSSI to wake up the display at, in -dBm.
+pub const PROXIMITY_WAKE_MAX_DBM: u16 = 127;
+
+/// Highest unicast address of a mesh node.
+pub const MESH_ADDRESS_MAX: u32 = 0x7FFF;
+
+/// Device settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable, defmt::Format)]
+#[repr(C)]
+pub struct Settings {
+    /// Seconds without input after which the display is dimmed (0: never).
+    pub display_dim_secs: u16,
+    /// Seconds without input after which the display is turned off (0: never).
+    pub display_off_secs: u16,
+    /// OLED controller, index of [`Controller::ALL`]: 0 SSD1306, 1 SH1106.
+    pub display_controller: u8,
+    /// OLED panel, index of [`PanelSize::ALL`]: 0 128x64, 1 128x32, 2 64x48,
+    /// 3 64x32.
+    pub display_size: u8,
+    /// Clockwise rotation of the display in degrees, a multiple of 90.
+    pub display_rotation: u16,
+    /// Index of the page of the [`Menu`](crate::ui::Menu) viewed last.
+    pub ui_page: u16,
+    /// Seconds without input after which the menu returns to its home page
+    /// (0: never).
+    pub ui_home_secs: u16,
+    /// Speed below which the device is considered stationary, in cm/s.
+    pub stationary_speed_cm_s: u16,
+    /// Minimum interval of connectable advertising in ms, from
+    /// [`ADV_INTERVAL_MIN_MS`] to [`ADV_INTERVAL_MAX_MS`].
+    pub adv_interval_ms: u16,
+    /// Distance from the waypoint beyond which the position left the
+    /// geofence, in m (0: no geofence), see [`waypoint`](crate::waypoint).
+    pub geofence_radius_m: u16,
+    /// Die temperature in °C from which the TX power is reduced, up to
+    /// [`THERMAL_LIMIT_MAX_C`] (0: never), see
+    /// [`thermal`](crate::bsp::thermal).
+    pub thermal_limit_c: u16,
+    /// Events that sound an alert on the buzzer, see
+    /// [`alerts`](crate::bsp::buzzer::alerts).
+    pub buzzer_alerts: u16,
+    /// GAP appearance, e.g. 0x0340 for a heart rate sensor. The firmware
+    /// chooses the appearance while it's 0 (unknown).
+    pub appearance: u16,
+    /// Revision of the GATT layout the centrals were told about last, see
+    /// [`announce_changes`](crate::gatt::generic_attribute::announce_changes).
+    pub gatt_revision: u16,
+    /// RSSI from which a connected central wakes up the display, in -dBm,
+    /// e.g. 55 for -55 dBm (0: never), see
+    /// [`Proximity`](crate::bsp::ble::rssi::Proximity).
+    pub proximity_wake_dbm: u16,
+    /// Minimum raw magnetometer reading per axis, see [`Calibration`].
+    pub mag_min: [i16; 3],
+    /// Maximum raw magnetometer reading per axis, see [`Calibration`].
+    pub mag_max: [i16; 3],
+    /// Addresses of the centrals allowed to connect, least significant
+    /// byte first, unused entries zero. Anyone may connect while the list
+    /// is empty.
+    pub accept_list: [[u8; 6]; ACCEPT_LIST_LEN],
+    /// UTF-8 text typed by the USB keyboard instead of the coordinates,
+    /// padded with zeros, see `usb_hid`.
+    pub keyboard_text: [u8; KEYBOARD_TEXT_LEN],
+    /// UTF-8 name in the advertisements, padded with zeros. The firmware
+    /// chooses the name while it's empty.
+    pub device_name: [u8; DEVICE_NAME_LEN],
+    /// Slot of the unit in the group of `slotted_beacon`, below
+    /// [`MAX_UNITS`], see [`epoch`](crate::epoch).
+    pub slot_unit: u32,
+    /// Unicast address of the node in the mesh network of `mesh_node`,
+    /// 0x0001 to [`MESH_ADDRESS_MAX`] (0: not provisioned), see
+    /// [`mesh`](crate::mesh).
+    pub mesh_address: u32,
+    /// Current IV index of the mesh network.
+    pub mesh_iv_index: u32,
+    /// Sequence number from which the mesh node sends, the ones below may
+    /// have been used before.
+    pub mesh_seq: u32,
+}
+
+impl Settings {
+    pub const fn new() -> Self {
+        Self {
+            display_dim_secs: 30,
+            display_off_secs: 120,
+            display_controller: 0,
+            display_size: 0,
+            display_rotation: 0,
+            ui_page: 0,
+            ui_home_secs: 60,
+            stationary_speed_cm_s: (DEFAULT_STATIONARY_KNOTS * KNOT_CM_S) as u16,
+            adv_interval_ms: 100,
+            geofence_radius_m: 0,
+            thermal_limit_c: 70,
+            buzzer_alerts: alerts::ALL,
+            appearance: 0,
+            gatt_revision: 0,
+            proximity_wake_dbm: 0,
+            mag_min: Calibration::UNCALIBRATED.min,
+            mag_max: Calibration::UNCALIBRATED.max,
+            accept_list: [[0; 6]; ACCEPT_LIST_LEN],
+            keyboard_text: [0; KEYBOARD_TEXT_LEN],
+            device_name: [0; DEVICE_NAME_LEN],
+            slot_unit: 0,
+            mesh_address: 0,
+            mesh_iv_index: 0,
+            mesh_seq: 0,
+        }
+    }
+
+    /// The used entries of the accept list.
+    pub fn accepted(&self) -> impl Iterator<Item = &[u8; 6]> {
+        self.accept_list.iter().filter(|a| **a != [0; 6])
+    }
+
+    /// The configured display, the defaults for values out of range.
+    pub fn display(&self) -> (Controller, PanelSize, Rotation) {
+        (
+            Controller::ALL
+                .get(self.display_controller as usize)
+                .copied()
+                .unwrap_or(Controller::Ssd1306),
+            PanelSize::ALL
+                .get(self.display_size as usize)
+                .copied()
+                .unwrap_or(PanelSize::W128H64),
+            Rotation::from_degrees(self.display_rotation).unwrap_or_default(),
+        )
+    }
+
+    /// The keyboard text up to the first zero, empty if it isn't UTF-8.
+    pub fn keyboard_str(&self) -> &str {
+        padded_str(&self.keyboard_text)
+    }
+
+    /// The device name up to the first zero, empty if it isn't UTF-8.
+    pub fn device_name_str(&self) -> &str {
+        padded_str(&self.device_name)
+    }
+
+    /// Set the device name, `false` if it's too long.
+    pub fn set_device_name(&mut self, name: &str) -> bool {
+        let Some(padded) = self.device_name.get_mut(..name.len()) else {
+            return false;
+        };
+        padded.copy_from_slice(name.as_bytes());
+        self.device_name[name.len()..].fill(0);
+        true
+    }
+}
+
+/// A text field up to the first zero, empty if it isn't UTF-8.
+fn padded_str(bytes: &[u8]) -> &str {
+    let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    core::str::from_utf8(&bytes[..len]).unwrap_or("")
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Record stored in flash.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct Record {
+    magic: u32,
+    version: u16,
+    len: u16,
+    settings: Settings,
+    crc: u32,
+}
+
+impl Record {
+    fn new(settings: Settings) -> Self {
+        let mut record = Self {
+            magic: MAGIC,
+            version: VERSION,
+            len: size_of::<Settings>() as u16,
+            settings,
+            crc: 0,
+        };
+        record.crc = record.checksum();
+        record
+    }
+
+    fn checksum(&self) -> u32 {
+        let bytes = bytemuck::bytes_of(self);
+        crc32(&bytes[..bytes.len() - size_of::<u32>()])
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC
+            && self.version == VERSION
+            && self.len as usize == size_of::<Settings>()
+            && self.crc == self.checksum()
+    }
+}
+
+/// Length of the blob of [`export`] and [`import`].
+pub const BLOB_LEN: usize = size_of::<Record>();
+
+/// Length of a blob of [`export`] in a signed envelope.
+pub const SIGNED_BLOB_LEN: usize = signed::signed_len(BLOB_LEN);
+
+/// Errors importing settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ImportError {
+    /// The blob isn't [`BLOB_LEN`] bytes long.
+    Length,
+    /// Wrong magic, version or checksum, e.g. from another firmware.
+    Invalid,
+    /// Not signed, the signature doesn't verify or the blob is old.
+    Signature(SignedError),
+}
+
+static SETTINGS: Mutex<CriticalSectionRawMutex, Cell<Settings>> =
+    Mutex::new(Cell::new(Settings::new()));
+
+/// Signalled when the settings have to be written to flash.
+static SAVE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// The current settings.
+pub fn get() -> Settings {
+    SETTINGS.lock(|s| s.get())
+}
+
+/// Change the settings and schedule writing them to flash.
+pub fn update(f: impl FnOnce(&mut Settings)) {
+    let settings = SETTINGS.lock(|s| {
+        let mut settings = s.get();
+        f(&mut settings);
+        s.set(settings);
+        settings
+    });
+    events::publish(Event::SettingsChanged);
+    SAVE.signal(());
+    info!("[settings] updated: {:?}", settings);
+}
+
+/// Whether changed settings still have to be written to flash.
+pub fn save_pending() -> bool {
+    SAVE.signaled()
+}
+
+/// The current settings as a blob for [`import`] on another device.
+pub fn export() -> [u8; BLOB_LEN] {
+    bytemuck::cast(Record::new(get()))
+}
+
+/// Replace all settings by a blob of [`export`], signed if
+/// [required](signed::required) with a sequence number above the
+/// [last one](crate::replay).
+pub fn import(blob: &[u8]) -> Result<(), ImportError> {
+    let signed = if signed::required() {
+        Some(signed::verify(blob, Kind::Settings).map_err(ImportError::Signature)?)
+    } else {
+        None
+    };
+    let payload = signed.as_ref().map_or(blob, |s| s.payload);
+    let record: Record =
+        bytemuck::try_pod_read_unaligned(payload).map_err(|_| ImportError::Length)?;
+    if !record.is_valid() {
+        return Err(ImportError::Invalid);
+    }
+    if let Some(signed) = &signed {
+        replay::accept(signed).map_err(ImportError::Signature)?;
+    }
+    update(|s| *s = record.settings);
+    Ok(())
+}
+
+/// A setting accessible by name.
+pub struct Field {
+    pub name: &'static str,
+    pub get: fn(&Settings) -> u32,
+    /// Set the value, returns `false` if it is out of range.
+    pub set: fn(&mut Settings, u32) -> bool,
+}
+
+/// All settings accessible by name.
+pub const FIELDS: &[Field] = &[
+    Field {
+        name: "display_dim_secs",
+        get: |s| s.display_dim_secs.into(),
+        set: |s, v| u16::try_from(v).map(|v| s.display_dim_secs = v).is_ok(),
+    },
+    Field {
+        name: "display_off_secs",
+        get: |s| s.display_off_secs.into(),
+        set: |s, v| u16::try_from(v).map(|v| s.display_off_secs = v).is_ok(),
+    },
+    Field {
+        name: "display_controller",
+        get: |s| s.display_controller.into(),
+        set: |s, v| {
+            u8::try_from(v)
+                .ok()
+                .filter(|v| (*v as usize) < Controller::ALL.len())
+                .map(|v| s.display_controller = v)
+                .is_some()
+        },
+    },
+    Field {
+        name: "display_size",
+        get: |s| s.display_size.into(),
+        set: |s, v| {
+            u8::try_from(v)
+                .ok()
+                .filter(|v| (*v as usize) < PanelSize::ALL.len())
+                .map(|v| s.display_size = v)
+                .is_some()
+        },
+    },
+    Field {
+        name: "display_rotation",
+        get: |s| s.display_rotation.into(),
+        set: |s, v| {
+            u16::try_from(v)
+                .ok()
+                .filter(|v| Rotation::from_degrees(*v).is_some())
+                .map(|v| s.display_rotation = v)
+                .is_some()
+        },
+    },
+    Field {
+        name: "ui_home_secs",
+        get: |s| s.ui_home_secs.into(),
+        set: |s, v| u16::try_from(v).map(|v| s.ui_home_secs = v).is_ok(),
+    },
+    Field {
+        name: "stationary_speed_cm_s",
+        get: |s| s.stationary_speed_cm_s.into(),
+        set: |s, v| {
+            u16::try_from(v)
+                .map(|v| s.stationary_speed_cm_s = v)
+                .is_ok()
+        },
+    },
+    Field {
+        name: "adv_interval_ms",
+        get: |s| s.adv_interval_ms.into(),
+        set: |s, v| {
+            u16::try_from(v)
+                .ok()
+                .filter(|v| (ADV_INTERVAL_MIN_MS..=ADV_INTERVAL_MAX_MS).contains(v))
+                .map(|v| s.adv_interval_ms = v)
+                .is_some()
+        },
+    },
+    Field {
+        name: "geofence_radius_m",
+        get: |s| s.geofence_radius_m.into(),
+        set: |s, v| u16::try_from(v).map(|v| s.geofence_radius_m = v).is_ok(),
+    },
+    Field {
+        name: "thermal_limit_c",
+        get: |s| s.thermal_limit_c.into(),
+        set: |s, v| {
+            u16::try_from(v)
+                .ok()
+                .filter(|v| *v <= THERMAL_LIMIT_MAX_C)
+                .map(|v| s.thermal_limit_c = v)
+                .is_some()
+        },
+    },
+    Field {
+        name: "buzzer_alerts",
+        get: |s| s.buzzer_alerts.into(),
+        set: |s, v| {
+            u16::try_from(v)
+                .ok()
+                .filter(|v| v & !alerts::ALL == 0)
+                .map(|v| s.buzzer_alerts = v)
+                .is_some()
+        },
+    },
+    Field {
+        name: "appearance",
+        get: |s| s.appearance.into(),
+        set: |s, v| u16::try_from(v).map(|v| s.appearance = v).is_ok(),
+    },
+    Field {
+        name: "proximity_wake_dbm",
+        get: |s| s.proximity_wake_dbm.into(),
+        set: |s, v| {
+            u16::try_from(v)
+                .ok()
+                .filter(|v| *v <= PROXIMITY_WAKE_MAX_DBM)
+                .map(|v| s.proximity_wake_dbm = v)
+                .is_some()
+        },
+    },
+    Field {
+        name: "slot_unit",
+        get: |s| s.slot_unit,
+        set: |s, v| {
+            u8::try_from(v)
+                .ok()
+                .filter(|v| *v < MAX_UNITS)
+                .map(|v| s.slot_unit = v.into())
+                .is_some()
+        },
+    },
+    Field {
+        name: "mesh_address",
+        get: |s| s.mesh_address,
+        set: |s, v| {
+            Some(v)
+                .filter(|v| *v <= MESH_ADDRESS_MAX)
+                .map(|v| s.mesh_address = v)
+                .is_some()
+        },
+    },
+    Field {
+        name: "mesh_iv_index",
+        get: |s| s.mesh_iv_index,
+        set: |s, v| {
+            s.mesh_iv_index = v;
+            true
+        },
+    },
+];
+
+/// Find a setting by name.
+pub fn field(name: &str) -> Option<&'static Field> {
+    FIELDS.iter().find(|f| f.name == name)
+}
+
+fn get_command(args: &str, out: &mut Output) -> core::fmt::Result {
+    let settings = get();
+    for f in FIELDS.iter().filter(|f| args.is_empty() || f.name == args) {
+        write!(out, "{} = {}\r\n", f.name, (f.get)(&settings))?;
+    }
+    Ok(())
+}
+
+fn set_command(args: &str, out: &mut Output) -> core::fmt::Result {
+    let Some((name, value)) = args.split_once(' ') else {
+        return write!(out, "usage: set <name> <value>\r\n");
+    };
+    let Some(f) = field(name) else {
+        return write!(out, "unknown setting '{}'\r\n", name);
+    };
+    let Ok(value) = value.trim().parse() else {
+        return write!(out, "invalid value '{}'\r\n", value.trim());
+    };
+    let mut valid = false;
+    update(|s| valid = (f.set)(s, value));
+    if !valid {
+        write!(out, "value out of range\r\n")?;
+    }
+    Ok(())
+}
+
+fn accept_command(args: &str, out: &mut Output) -> core::fmt::Result {
+    let (action, mac) = args.split_once(' ').unwrap_or((args, ""));
+    let address = fmt::parse_mac(mac.trim());
+    match (action, address) {
+        ("", _) => {
+            for address in get().accepted() {
+                write!(out, "{}\r\n", fmt::mac(address))?;
+            }
+        }
+        ("clear", _) => update(|s| s.accept_list = [[0; 6]; ACCEPT_LIST_LEN]),
+        ("add", Some(address)) => {
+            let mut added = false;
+            update(|s| {
+                if s.accepted().all(|a| *a != address)
+                    && let Some(free) = s.accept_list.iter_mut().find(|a| **a == [0; 6])
+                {
+                    *free = address;
+                }
+                added = s.accepted().any(|a| *a == address);
+            });
+            if !added {
+                write!(out, "accept list full\r\n")?;
+            }
+        }
+        ("remove", Some(address)) => update(|s| {
+            s.accept_list
+                .iter_mut()
+                .filter(|a| **a == address)
+                .for_each(|a| *a = [0; 6])
+        }),
+        _ => write!(out, "usage: accept [add|remove <mac>|clear]\r\n")?,
+    }
+    Ok(())
+}
+
+/// Shell commands to show and change the settings.
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "get",
+        help: "show settings: get [name]",
+        handler: get_command,
+    },
+    Command {
+        name: "set",
+        help: "change a setting: set <name> <value>",
+        handler: set_command,
+    },
+    Command {
+        name: "accept",
+        help: "centrals allowed to connect: accept [add|remove <mac>|clear]",
+        handler: accept_command,
+    },
+];
+
+/// Flash backed settings storage.
+///
+/// `flash` is the [`SETTINGS`](crate::bsp::flash::SETTINGS) partition.
+pub struct Store<F> {
+    flash: F,
+}
+
+impl<F: NorFlash> Store<F> {
+    pub fn new(flash: F) -> Self {
+        Self { flash }
+    }
+
+    /// Load the settings from flash, keeping the defaults if there are none.
+    ///
+    /// Returns whether settings were stored, `false` e.g. on the first boot.
+    pub async fn load(&mut self) -> Result<bool, F::Error> {
+        let mut record = Record::zeroed();
+        self.flash
+            .read(0, bytemuck::bytes_of_mut(&mut record))
+            .await?;
+        if record.is_valid() {
+            SETTINGS.lock(|s| s.set(record.settings));
+            info!("[settings] loaded: {:?}", record.settings);
+        } else {
+            info!("[settings] no valid settings stored, using defaults");
+        }
+        Ok(record.is_valid())
+    }
+
+    /// Write the current settings to flash.
+    pub async fn save(&mut self) -> Result<(), F::Error> {
+        let record = Record::new(get());
+        self.flash.erase(0, PAGE_SIZE).await?;
+        self.flash.write(0, bytemuck::bytes_of(&record)).await
+    }
+}
+
+impl<F: NorFlash> Subsystem for Store<F> {
+    type Error = F::Error;
+
+    const NAME: &'static str = "settings";
+
+    /// Write the settings to flash whenever they were changed.
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        loop {
+            SAVE.wait().await;
+            if let Err(e) = self.save().await {
+                warn!("[settings] couldn't save settings");
+                // Retry after the supervisor restarted us.
+                SAVE.signal(());
+                return Err(e);
+            }
+            info!("[settings] saved");
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Line based command shell on a dedicated UARTE (UARTE1 by default).
+//!
+//! Input is echoed back, backspace is supported and every completed line is
+//! split into a command name and its arguments. Commands are plain functions
+//! registered in a [`Command`] table; [`BUILTIN_COMMANDS`] provides the
+//! generic ones.
+
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+
+use embassy_nrf::uarte::{self, UarteRx, UarteTx};
+use embassy_time::Instant;
+
+use crate::recovery::DIAGNOSTICS;
+use crate::supervisor::{RESTARTS, Subsystem};
+
+/// Maximum length of an input line.
+pub const LINE_MAX_LEN: usize = 80;
+
+/// Maximum length of the output of a single command.
+pub const OUTPUT_MAX_LEN: usize = 256;
+
+const PROMPT: &[u8] = b"> ";
+
+/// Output buffer of a command. Output exceeding [`OUTPUT_MAX_LEN`] is truncated.
+pub struct Output {
+    buf: [u8; OUTPUT_MAX_LEN],
+    len: usize,
+}
+
+impl Output {
+    const fn new() -> Self {
+        Self {
+            buf: [0; OUTPUT_MAX_LEN],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Write for Output {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let n = s.len().min(OUTPUT_MAX_LEN - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        if n < s.len() {
+            Err(core::fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A shell command.
+pub struct Command {
+    /// Name typed by the user.
+    pub name: &'static str,
+    /// One line description shown by `help`.
+    pub help: &'static str,
+    /// Handler getting the (trimmed) arguments after the name.
+    pub handler: fn(args: &str, out: &mut Output) -> core::fmt::Result,
+}
+
+fn uptime(_args: &str, out: &mut Output) -> core::fmt::Result {
+    write!(out, "{} s\r\n", Instant::now().as_secs())
+}
+
+fn diag(_args: &str, out: &mut Output) -> core::fmt::Result {
+    write!(
+        out,
+        "ble errors: {}\r\ncontroller resets: {}\r\nsubsystem restarts: {}\r\n",
+        DIAGNOSTICS.errors.load(Ordering::Relaxed),
+        DIAGNOSTICS.controller_resets.load(Ordering::Relaxed),
+        RESTARTS.load(Ordering::Relaxed),
+    )
+}
+
+fn reset(_args: &str, _out: &mut Output) -> core::fmt::Result {
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Commands useful in every application.
+pub const BUILTIN_COMMANDS: &[Command] = &[
+    Command {
+        name: "uptime",
+        help: "time since boot",
+        handler: uptime,
+    },
+    Command {
+        name: "diag",
+        help: "error and restart counters",
+        handler: diag,
+    },
+    Command {
+        name: "reset",
+        help: "reboot the device",
+        handler: reset,
+    },
+];
+
+/// Command shell subsystem.
+pub struct Shell<'a, 'd> {
+    rx: &'a mut UarteRx<'d>,
+    tx: &'a mut UarteTx<'d>,
+    /// Command tables, searched in order.
+    commands: &'a [&'a [Command]],
+}
+
+impl<'a, 'd> Shell<'a, 'd> {
+    pub fn new(
+        rx: &'a mut UarteRx<'d>,
+        tx: &'a mut UarteTx<'d>,
+        commands: &'a [&'a [Command]],
+    ) -> Self {
+        Self { rx, tx, commands }
+    }
+
+    fn find(&self, name: &str) -> Option<&Command> {
+        self.commands
+            .iter()
+            .flat_map(|table| table.iter())
+            .find(|cmd| cmd.name == name)
+    }
+
+    /// Run a single command line and return its output.
+    fn execute(&self, line: &str, out: &mut Output) {
+        let line = line.trim();
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        if name.is_empty() {
+            return;
+        }
+        let _ = if name == "help" {
+            self.commands
+                .iter()
+                .flat_map(|table| table.iter())
+                .try_for_each(|cmd| write!(out, "{:<10} {}\r\n", cmd.name, cmd.help))
+        } else if let Some(cmd) = self.find(name) {
+            (cmd.handler)(args.trim(), out)
+        } else {
+            write!(out, "unknown command '{}', try 'help'\r\n", name)
+        };
+    }
+
+    /// Read a line, echoing the input. Returns the line length.
+    async fn read_line(&mut self, line: &mut [u8; LINE_MAX_LEN]) -> Result<usize, uarte::Error> {
+        let mut len = 0;
+        let mut byte = [0u8; 1];
+        loop {
+            self.rx.read(&mut byte).await?;
+            match byte[0] {
+                b'\r' | b'\n' => {
+                    self.tx.write(b"\r\n").await?;
+                    return Ok(len);
+                }
+                // Backspace and delete
+                0x08 | 0x7F if len > 0 => {
+                    len -= 1;
+                    self.tx.write(b"\x08 \x08").await?;
+                }
+                b if b.is_ascii_graphic() || b == b' ' => {
+                    if len < LINE_MAX_LEN {
+                        line[len] = b;
+                        len += 1;
+                        self.tx.write(&byte).await?;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Subsystem for Shell<'_, '_> {
+    type Error = uarte::Error;
+
+    const NAME: &'static str = "shell";
+
+    async fn run(&mut self) -> Result<(), Self::Error> {
+        let mut line = [0u8; LINE_MAX_LEN];
+        loop {
+            self.tx.write(PROMPT).await?;
+            let len = self.read_line(&mut line).await?;
+            let mut out = Output::new();
+            // Only ASCII is accepted by `read_line`.
+            self.execute(str::from_utf8(&line[..len]).unwrap_or(""), &mut out);
+            if !out.as_bytes().is_empty() {
+                self.tx.write(out.as_bytes()).await?;
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Remote management with mcumgr over the SMP GATT service.
+//!
+//! Standard tools (the mcumgr CLI, nRF Connect Device Manager) talk to the
+//! device with the Simple Management Protocol. Supported are the OS group
+//! (echo, reset, buffer parameters), the stats group with the counters of
+//! [`DIAGNOSTICS`] and the supervisor, and reading the image state. There
+//! is no DFU: the image list shows the running image only, without hash,
+//! with the version and the confirm status of [`image_info`]. Confirming it
+//! marks a test image swapped in by MCUboot OK. Firmware built with a
+//! signing key also wants a `manifest` [signed](signed) for
+//! [`Kind::DfuManifest`] naming the running version.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use defmt::{info, warn};
+use nrf52_radio_core::smp::cbor::{Encoder, Item, map_get};
+use nrf52_radio_core::smp::{Assembler, HEADER_LEN, encode_rc, group, image, op, os, stats};
+pub use nrf52_radio_core::smp::{Header, Rc};
+
+use crate::build_info;
+use crate::crypto::signed::{self, Kind};
+use crate::image_info::{self, ImageStatus, ImageVersion};
+use crate::recovery::DIAGNOSTICS;
+use crate::supervisor::RESTARTS;
+
+/// Longest request accepted.
+pub const MAX_REQUEST_LEN: usize = 256;
+
+/// Longest response, header included.
+pub const MAX_RESPONSE_LEN: usize = 384;
+
+/// A named group of counters, read with `mcumgr stat <name>`.
+struct StatGroup {
+    name: &'static str,
+    fields: &'static [(&'static str, &'static AtomicU32)],
+}
+
+static STAT_GROUPS: &[StatGroup] = &[
+    StatGroup {
+        name: "ble",
+        fields: &[
+            ("errors", &DIAGNOSTICS.errors),
+            ("controller_resets", &DIAGNOSTICS.controller_resets),
+            ("consecutive_failures", &DIAGNOSTICS.consecutive_failures),
+        ],
+    },
+    StatGroup {
+        name: "supervisor",
+        fields: &[("restarts", &RESTARTS)],
+    },
+];
+
+/// A response to notify, header included.
+pub struct Response {
+    buf: [u8; MAX_RESPONSE_LEN],
+    len: usize,
+    /// The device has to be reset once the response is sent.
+    pub reset: bool,
+}
+
+impl Response {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// SMP server of a connection.
+#[derive(Default)]
+pub struct Server {
+    assembler: Assembler<MAX_REQUEST_LEN>,
+}
+
+impl Server {
+    pub const fn new() -> Self {
+        Self {
+            assembler: Assembler::new(),
+        }
+    }
+
+    /// Handle a write to the SMP characteristic, returning the response once
+    /// a request is complete.
+    pub async fn write(&mut self, data: &[u8]) -> Option<Response> {
+        match self.assembler.push(data) {
+            Ok(Some((header, payload))) => Some(handle(&header, payload).await),
+            Ok(None) => None,
+            Err(rc) => {
+                warn!("[smp] request dropped: {:?}", rc);
+                None
+            }
+        }
+    }
+}
+
+/// Execute a request.
+pub async fn handle(header: &Header, payload: &[u8]) -> Response {
+    let mut response = Response {
+        buf: [0; MAX_RESPONSE_LEN],
+        len: 0,
+        reset: false,
+    };
+    let out = &mut response.buf[HEADER_LEN..];
+    let result = dispatch(header, payload, &mut Encoder::new(out)).await;
+    let len = match result {
+        Ok(len) => len,
+        Err(rc) => {
+            warn!(
+                "[smp] group {} command {} failed: {:?}",
+                header.group, header.id, rc
+            );
+            // An error response always fits.
+            encode_rc(out, header, rc).unwrap_or(0)
+        }
+    };
+    response.reset = result.is_ok() && header.group == group::OS && header.id == os::RESET;
+    response.buf[..HEADER_LEN].copy_from_slice(&header.response(len as u16).to_bytes());
+    response.len = HEADER_LEN + len;
+    response
+}
+
+/// Check that the `manifest` of an image confirmation is signed for the
+/// running image.
+fn check_manifest(payload: &[u8]) -> Result<(), Rc> {
+    let Some(Item::Bytes(blob)) = map_get(payload, "manifest")? else {
+        warn!("[smp] no manifest to confirm the image");
+        return Err(Rc::Invalid);
+    };
+    let manifest = signed::verify(blob, Kind::DfuManifest).map_err(|e| {
+        warn!("[smp] invalid manifest: {:?}", e);
+        Rc::Invalid
+    })?;
+    let version: ImageVersion =
+        bytemuck::try_pod_read_unaligned(manifest.payload).map_err(|_| Rc::Invalid)?;
+    if image_info::info().version != Some(version) {
+        warn!("[smp] manifest is for {}, not the running image", version);
+        return Err(Rc::Invalid);
+    }
+    Ok(())
+}
+
+/// Encode the response payload of a request, returning its length.
+async fn dispatch(header: &Header, payload: &[u8], out: &mut Encoder) -> Result<usize, Rc> {
+    match (header.group, header.id) {
+        (group::OS, os::ECHO) => {
+            let Some(Item::Text(text)) = map_get(payload, "d")? else {
+                return Err(Rc::Invalid);
+            };
+            out.map(1)?.text("r")?.text(text)?;
+        }
+        (group::OS, os::RESET) => {
+            info!("[smp] reset requested");
+            out.map(0)?;
+        }
+        (group::OS, os::MCUMGR_PARAMS) => {
+            out.map(2)?
+                .text("buf_size")?
+                .uint(MAX_REQUEST_LEN as u64)?
+                .text("buf_count")?
+                .uint(1)?;
+        }
+        (group::IMAGE, image::STATE) => {
+            if header.op == op::WRITE {
+                // Testing another image needs a DFU.
+                let Some(Item::Bool(true)) = map_get(payload, "confirm")? else {
+                    return Err(Rc::NotSupported);
+                };
+                if signed::required() {
+                    check_manifest(payload)?;
+                }
+                image_info::mark_image_ok().await.map_err(|e| {
+                    warn!("[smp] couldn't confirm the image: {:?}", e);
+                    Rc::Unknown
+                })?;
+            }
+            let running = image_info::info();
+            let mut version = heapless::String::<24>::new();
+            match running.version {
+                Some(v) => write!(version, "{}", v).map_err(|_| Rc::NoMemory)?,
+                None => version
+                    .push_str(build_info::VERSION)
+                    .map_err(|_| Rc::NoMemory)?,
+            }
+            out.map(2)?.text("images")?.array(1)?.map(7)?;
+            out.text("slot")?.uint(0)?;
+            out.text("version")?.text(&version)?;
+            out.text("bootable")?
+                .bool(running.status != ImageStatus::Unknown)?;
+            out.text("pending")?.bool(false)?;
+            out.text("confirmed")?
+                .bool(running.status == ImageStatus::Confirmed)?;
+            out.text("active")?.bool(true)?;
+            out.text("permanent")?.bool(false)?;
+            out.text("splitStatus")?.uint(0)?;
+        }
+        (group::STATS, stats::READ) => {
+            let Some(Item::Text(name)) = map_get(payload, "name")? else {
+                return Err(Rc::Invalid);
+            };
+            let stat = STAT_GROUPS
+                .iter()
+                .find(|g| g.name == name)
+                .ok_or(Rc::NoEntry)?;
+            out.map(2)?.text("name")?.text(stat.name)?;
+            out.text("fields")?.map(stat.fields.len())?;
+            for (field, counter) in stat.fields {
+                out.text(field)?
+                    .uint(counter.load(Ordering::Relaxed) as u64)?;
+            }
+        }
+        (group::STATS, stats::LIST) => {
+            out.map(1)?.text("stat_list")?.array(STAT_GROUPS.len())?;
+            for stat in STAT_GROUPS {
+                out.text(stat.name)?;
+            }
+        }
+        _ => return Err(Rc::NotSupported),
+    }
+    Ok(out.len())
+}
@@ -0,0 +1,149 @@
+//! Receiver of raw BLE advertising channel packets (`sniffer` binary).
+//!
+//! The RADIO listens on one advertising channel for any packet with the
+//! advertising access address, without a BLE stack. TIMER1 counts µs and
+//! captures the address event of every packet through a PPI channel, so
+//! the timestamps don't depend on how fast the packets are picked up.
+//! Packets come out as PCAP [`Record`]s, see [`nrf52_radio_core::pcap`].
+//!
+//! The HFXO has to run, the RADIO doesn't start it without the MPSL.
+
+use embassy_futures::yield_now;
+use embassy_nrf::{
+    Peri,
+    pac::{self, radio::vals},
+    peripherals::TIMER1,
+    ppi::{AnyConfigurableChannel, Event, Ppi, Task},
+};
+pub use nrf52_radio_core::pcap::{
+    ADV_ACCESS_ADDRESS, ADV_CRC_INIT, MAX_PDU_LEN, MAX_RECORD_LEN, Record, global_header,
+};
+
+/// Frequency of advertising channel `channel` in MHz above 2400.
+fn frequency(channel: u8) -> u8 {
+    match channel {
+        37 => 2,
+        38 => 26,
+        _ => 80,
+    }
+}
+
+/// Captures the packets of one advertising channel.
+pub struct Sniffer<'d> {
+    _timer: Peri<'d, TIMER1>,
+    _ppi: Ppi<'d, AnyConfigurableChannel, 1, 1>,
+    channel: u8,
+    /// S0 (PDU header), length and payload, the PDU.
+    buf: [u8; MAX_PDU_LEN],
+    /// Timestamp extended beyond the 32 bit timer.
+    last_capture: u32,
+    elapsed_us: u64,
+}
+
+impl<'d> Sniffer<'d> {
+    /// Listen on advertising `channel` (37 to 39).
+    pub fn new(
+        timer: Peri<'d, TIMER1>,
+        ppi: Peri<'d, AnyConfigurableChannel>,
+        channel: u8,
+    ) -> Self {
+        let t = pac::TIMER1;
+        t.bitmode()
+            .write(|w| w.set_bitmode(pac::timer::vals::Bitmode::_32BIT));
+        // 16 MHz / 2^4
+        t.prescaler().write(|w| w.set_prescaler(4));
+        t.tasks_clear().write_value(1);
+        t.tasks_start().write_value(1);
+        let mut ppi = Ppi::new_one_to_one(
+            ppi,
+            Event::from_reg(pac::RADIO.events_address()),
+            Task::from_reg(t.tasks_capture(0)),
+        );
+        ppi.enable();
+
+        let radio = pac::RADIO;
+        radio.mode().write(|w| w.set_mode(vals::Mode::BLE_1MBIT));
+        radio.pcnf0().write(|w| {
+            w.set_s0len(true);
+            w.set_lflen(8);
+        });
+        radio.pcnf1().write(|w| {
+            w.set_maxlen((MAX_PDU_LEN - 2) as u8);
+            w.set_balen(3);
+            w.set_whiteen(true);
+        });
+        radio.base0().write_value(ADV_ACCESS_ADDRESS << 8);
+        radio
+            .prefix0()
+            .write(|w| w.set_ap(0, (ADV_ACCESS_ADDRESS >> 24) as u8));
+        radio.rxaddresses().write(|w| w.set_addr(0, true));
+        radio.crccnf().write(|w| {
+            w.set_len(vals::Len::THREE);
+            w.set_skipaddr(vals::Skipaddr::SKIP);
+        });
+        radio.crcpoly().write(|w| w.set_crcpoly(0x00_065b));
+        radio.crcinit().write(|w| w.set_crcinit(ADV_CRC_INIT));
+        radio.shorts().write(|w| {
+            w.set_ready_start(true);
+            w.set_end_disable(true);
+            w.set_address_rssistart(true);
+            w.set_disabled_rssistop(true);
+        });
+        Self {
+            _timer: timer,
+            _ppi: ppi,
+            channel,
+            buf: [0; MAX_PDU_LEN],
+            last_capture: 0,
+            elapsed_us: 0,
+        }
+    }
+
+    /// Switch to advertising `channel` for the next packets.
+    pub fn set_channel(&mut self, channel: u8) {
+        self.channel = channel;
+    }
+
+    /// Receive the next packet, including those with a CRC error.
+    ///
+    /// Polls the RADIO, the sniffer is the only thing the board does. When
+    /// the future is dropped before it completes, the RADIO keeps receiving
+    /// into the sniffer until the next call.
+    pub async fn receive(&mut self) -> Record<'_> {
+        let radio = pac::RADIO;
+        radio
+            .frequency()
+            .write(|w| w.set_frequency(frequency(self.channel)));
+        radio
+            .datawhiteiv()
+            .write(|w| w.set_datawhiteiv(self.channel));
+        radio.packetptr().write_value(self.buf.as_mut_ptr() as u32);
+        radio.events_disabled().write_value(0);
+        radio.tasks_rxen().write_value(1);
+        while radio.events_disabled().read() == 0 {
+            yield_now().await;
+        }
+        radio.events_disabled().write_value(0);
+
+        let capture = pac::TIMER1.cc(0).read();
+        self.elapsed_us += capture.wrapping_sub(self.last_capture) as u64;
+        self.last_capture = capture;
+        let len = 2 + (self.buf[1] as usize).min(MAX_PDU_LEN - 2);
+        Record {
+            timestamp_us: self.elapsed_us,
+            channel: self.channel,
+            rssi: -(radio.rssisample().read().rssisample() as i8),
+            crc_ok: radio.crcstatus().read().crcstatus() == vals::Crcstatus::CRCOK,
+            pdu: &self.buf[..len],
+        }
+    }
+}
+
+impl Drop for Sniffer<'_> {
+    fn drop(&mut self) {
+        let radio = pac::RADIO;
+        radio.shorts().write(|_| {});
+        radio.tasks_disable().write_value(1);
+        pac::TIMER1.tasks_stop().write_value(1);
+    }
+}
@@ -0,0 +1,58 @@
+//! Lightweight supervisor for long running subsystems.
+//!
+//! A subsystem (GNSS reader, BLE runner, advertising loop, ...) implements
+//! [`Subsystem`] and is driven by [`supervise`], which restarts it whenever it
+//! returns, waiting with an exponential backoff in between. This way a single
+//! failing subsystem doesn't take down the whole firmware.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use defmt::{info, warn};
+use embassy_time::{Duration, Instant};
+
+use crate::recovery::Backoff;
+
+/// A subsystem that runs until it fails (or finishes) and can be restarted.
+pub trait Subsystem {
+    /// Error returned when the subsystem fails.
+    type Error: core::fmt::Debug;
+
+    /// Name used in log messages.
+    const NAME: &'static str;
+
+    /// Run the subsystem. Called again after every return.
+    fn run(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/// A subsystem that ran at least this long is considered stable and the
+/// backoff starts from the initial delay again.
+const STABLE_RUNTIME: Duration = Duration::from_secs(60);
+
+/// Total number of subsystem restarts since boot.
+pub static RESTARTS: AtomicU32 = AtomicU32::new(0);
+
+/// Run `subsystem` forever, restarting it with exponential backoff.
+pub async fn supervise<S: Subsystem>(subsystem: &mut S) -> ! {
+    let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(60));
+    loop {
+        let started = Instant::now();
+        match subsystem.run().await {
+            Ok(()) => info!("[supervisor] {} finished", S::NAME),
+            Err(e) => warn!(
+                "[supervisor] {} failed: {:?}",
+                S::NAME,
+                defmt::Debug2Format(&e)
+            ),
+        }
+        if started.elapsed() >= STABLE_RUNTIME {
+            backoff.reset();
+        }
+        let restarts = RESTARTS.fetch_add(1, Ordering::Relaxed) + 1;
+        backoff.wait().await;
+        info!(
+            "[supervisor] restarting {} ({} restarts in total)",
+            S::NAME,
+            restarts
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Store-and-forward telemetry.
+//!
+//! Telemetry [`Sample`]s are taken periodically by [`collect`], whether a
+//! central is connected or not. They are buffered in RAM and spilled to the
+//! [telemetry flash region](crate::bsp::flash::TELEMETRY) when the RAM buffer
+//! is full, so no data is lost during BLE outages or reboots. The connection
+//! drains the queue oldest-first with [`peek`] and [`ack`]; the sequence
+//! numbers let the central detect gaps.
+//!
+//! The flash region is used as a ring of records, a record is marked as sent
+//! by clearing its `sent` word (without erasing). When the region is full,
+//! the oldest page is erased and its samples are lost.
+
+use bytemuck::Zeroable;
+use defmt::{Debug2Format, info, warn};
+use embassy_futures::select::{Either, select};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::{Duration, Instant, Ticker};
+use embedded_storage_async::nor_flash::NorFlash;
+use heapless::Deque;
+pub use nrf52_radio_core::telemetry::Sample;
+use nrf52_radio_core::telemetry::{Record, UNSENT};
+
+use crate::bsp::flash::{FlashError, PAGE_SIZE, Partition};
+use crate::events::{self, Event};
+
+/// Samples buffered in RAM before they are written to flash.
+const RAM_CAPACITY: usize = 32;
+
+/// Length of a [`Record`] in flash.
+const RECORD_LEN: u32 = size_of::<Record>() as u32;
+
+/// Queue of samples in RAM, backed by a flash ring.
+pub struct TelemetryQueue<F> {
+    flash: F,
+    ram: Deque<Sample, RAM_CAPACITY>,
+    /// Number of record slots in the flash ring.
+    slots: u32,
+    /// Slot of the oldest unsent record in flash.
+    read: u32,
+    /// Slot the next record is written to.
+    write: u32,
+    next_seq: u32,
+}
+
+impl<F: NorFlash> TelemetryQueue<F> {
+    const SLOTS_PER_PAGE: u32 = PAGE_SIZE / RECORD_LEN;
+
+    /// Recover the queue from flash.
+    pub async fn new(flash: F) -> Result<Self, F::Error> {
+        let slots = flash.capacity() as u32 / PAGE_SIZE * Self::SLOTS_PER_PAGE;
+        let mut queue = Self {
+            flash,
+            ram: Deque::new(),
+            slots,
+            read: 0,
+            write: 0,
+            next_seq: 0,
+        };
+        queue.scan().await?;
+        Ok(queue)
+    }
+
+    fn address(slot: u32) -> u32 {
+        slot / Self::SLOTS_PER_PAGE * PAGE_SIZE + slot % Self::SLOTS_PER_PAGE * RECORD_LEN
+    }
+
+    async fn read_record(&mut self, slot: u32) -> Result<Record, F::Error> {
+        let mut record = Record::zeroed();
+        self.flash
+            .read(Self::address(slot), bytemuck::bytes_of_mut(&mut record))
+            .await?;
+        Ok(record)
+    }
+
+    /// Find the newest record (to continue writing after it) and the oldest
+    /// unsent one.
+    async fn scan(&mut self) -> Result<(), F::Error> {
+        let mut newest: Option<(u32, u32)> = None;
+        let mut oldest_unsent: Option<(u32, u32)> = None;
+        for slot in 0..self.slots {
+            let record = self.read_record(slot).await?;
+            if !record.is_valid() {
+                continue;
+            }
+            let seq = record.sample.seq;
+            if newest.is_none_or(|(s, _)| seq > s) {
+                newest = Some((seq, slot));
+            }
+            if record.sent == UNSENT && oldest_unsent.is_none_or(|(s, _)| seq < s) {
+                oldest_unsent = Some((seq, slot));
+            }
+        }
+        if let Some((seq, slot)) = newest {
+            self.next_seq = seq.wrapping_add(1);
+            self.write = (slot + 1) % self.slots;
+        }
+        self.read = oldest_unsent.map_or(self.write, |(_, slot)| slot);
+        info!(
+            "[telemetry] {} unsent samples in flash, next seq {}",
+            self.flash_len(),
+            self.next_seq
+        );
+        Ok(())
+    }
+
+    /// Number of unsent samples in flash.
+    fn flash_len(&self) -> u32 {
+        (self.write + self.slots - self.read) % self.slots
+    }
+
+    /// Number of unsent samples.
+    pub fn len(&self) -> usize {
+        self.flash_len() as usize + self.ram.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append a sample, assigning its sequence number.
+    pub async fn push(&mut self, mut sample: Sample) -> Result<(), F::Error> {
+        sample.seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        if self.ram.is_full() {
+            self.spill().await?;
+        }
+        // The RAM buffer has room after spilling.
+        let _ = self.ram.push_back(sample);
+        Ok(())
+    }
+
+    /// Move the RAM buffer to flash.
+    async fn spill(&mut self) -> Result<(), F::Error> {
+        while let Some(sample) = self.ram.pop_front() {
+            if self.write % Self::SLOTS_PER_PAGE == 0 {
+                self.erase_page(self.write / Self::SLOTS_PER_PAGE).await?;
+            }
+            let record = Record::new(sample);
+            self.flash
+                .write(Self::address(self.write), bytemuck::bytes_of(&record))
+                .await?;
+            self.write = (self.write + 1) % self.slots;
+        }
+        Ok(())
+    }
+
+    /// Erase a page of the ring, dropping the unsent samples on it.
+    async fn erase_page(&mut self, page: u32) -> Result<(), F::Error> {
+        let first = page * Self::SLOTS_PER_PAGE;
+        let last = first + Self::SLOTS_PER_PAGE;
+        if self.flash_len() > 0 && (first..last).contains(&self.read) {
+            let lost = last - self.read;
+            warn!("[telemetry] flash full, dropping {} samples", lost);
+            self.read = last % self.slots;
+        }
+        self.flash
+            .erase(page * PAGE_SIZE, (page + 1) * PAGE_SIZE)
+            .await
+    }
+
+    /// The oldest unsent sample.
+    pub async fn peek(&mut self) -> Result<Option<Sample>, F::Error> {
+        if self.flash_len() > 0 {
+            return Ok(Some(self.read_record(self.read).await?.sample));
+        }
+        Ok(self.ram.front().copied())
+    }
+
+    /// Mark the oldest sample as sent.
+    pub async fn ack(&mut self) -> Result<(), F::Error> {
+        if self.flash_len() > 0 {
+            let sent_offset = Self::address(self.read) + RECORD_LEN - size_of::<u32>() as u32;
+            self.flash.write(sent_offset, &0u32.to_le_bytes()).await?;
+            self.read = (self.read + 1) % self.slots;
+        } else {
+            self.ram.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Write the RAM buffer to flash, e.g. before powering off.
+    pub async fn flush(&mut self) -> Result<(), F::Error> {
+        self.spill().await
+    }
+}
+
+static QUEUE: Mutex<CriticalSectionRawMutex, Option<TelemetryQueue<Partition>>> = Mutex::new(None);
+
+/// Signalled when a sample was pushed.
+static PUSHED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Log a flash error, `None` on one.
+fn log_err<R>(result: Result<R, FlashError>) -> Option<R> {
+    result
+        .inspect_err(|e| warn!("[telemetry] flash error: {:?}", e))
+        .ok()
+}
+
+/// Recover the queue from the telemetry flash partition.
+pub async fn init(flash: Partition) {
+    match TelemetryQueue::new(flash).await {
+        Ok(queue) => *QUEUE.lock().await = Some(queue),
+        Err(e) => warn!("[telemetry] couldn't read queue: {:?}", Debug2Format(&e)),
+    }
+}
+
+/// Queue a sample.
+pub async fn push(sample: Sample) {
+    if let Some(queue) = QUEUE.lock().await.as_mut() {
+        log_err(queue.push(sample).await);
+    }
+    PUSHED.signal(());
+}
+
+/// The oldest unsent sample.
+pub async fn peek() -> Option<Sample> {
+    log_err(QUEUE.lock().await.as_mut()?.peek().await).flatten()
+}
+
+/// Mark the sample returned by [`peek`] as sent.
+pub async fn ack() {
+    if let Some(queue) = QUEUE.lock().await.as_mut() {
+        log_err(queue.ack().await);
+    }
+}
+
+/// Write the RAM buffer to flash.
+pub async fn flush() {
+    if let Some(queue) = QUEUE.lock().await.as_mut() {
+        log_err(queue.flush().await);
+    }
+}
+
+/// Wait until a sample was pushed.
+pub async fn wait_pushed() {
+    PUSHED.wait().await
+}
+
+/// Take a sample every `interval` from the events on the bus.
+///
+/// Flushes the queue to flash on [`Event::ShuttingDown`].
+pub async fn collect(interval: Duration) {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[telemetry] no event bus subscriber available");
+        return;
+    };
+    let mut sample = Sample::new();
+    let mut ticker = Ticker::every(interval);
+    loop {
+        match select(events.next_message_pure(), ticker.next()).await {
+            Either::First(Event::FixAcquired(fix)) => sample.set_fix(Some(&fix)),
+            Either::First(Event::FixLost) => sample.set_fix(None),
+            Either::First(Event::MotionUpdated { speed_knots, .. }) => {
+                sample.set_speed_knots(speed_knots)
+            }
+            Either::First(Event::BatteryUpdated { percent, .. }) => {
+                sample.battery_percent = percent;
+            }
+            Either::First(Event::ShuttingDown) => flush().await,
+            Either::First(_) => {}
+            Either::Second(_) => {
+                sample.uptime_secs = Instant::now().as_secs() as u32;
+                push(sample).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Framing protocol and statistics for the L2CAP throughput test
+//! (`l2cap_tx` and `l2cap_rx` binaries).
+//!
+//! Every SDU sent over the channel starts with a 9 byte header:
+//!
+//! | offset | size | content                                     |
+//! |--------|------|---------------------------------------------|
+//! | 0      | 1    | [`FrameKind`]                               |
+//! | 1      | 4    | sequence number (little endian)             |
+//! | 5      | 4    | sender timestamp in µs (little endian)      |
+//!
+//! followed by filler bytes up to the configured SDU size.
+//! The receiver answers every [`FrameKind::EchoRequest`] with an
+//! [`FrameKind::Echo`] frame carrying the same sequence number and timestamp,
+//! which lets the sender measure the round trip latency without
+//! synchronized clocks.
+
+use defmt::info;
+use embassy_time::{Duration, Instant};
+
+/// Protocol/Service Multiplexer used by the throughput test.
+pub const PSM: u16 = 0x2349;
+
+/// Static random address of the receiving board, so the sender can
+/// connect to it without scanning.
+pub const RX_ADDRESS: [u8; 6] = [0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xfe];
+
+/// Size of the frame header in bytes.
+pub const HEADER_LEN: usize = 9;
+
+/// Kind of a throughput test frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum FrameKind {
+    /// Payload only.
+    Data = 0x01,
+    /// Payload, receiver has to reply with [`FrameKind::Echo`].
+    EchoRequest = 0x02,
+    /// Reply to an [`FrameKind::EchoRequest`].
+    Echo = 0x03,
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = FrameError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::Data),
+            0x02 => Ok(Self::EchoRequest),
+            0x03 => Ok(Self::Echo),
+            _ => Err(FrameError::UnknownKind(value)),
+        }
+    }
+}
+
+/// Error decoding a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum FrameError {
+    /// The buffer is shorter than the frame header.
+    TooShort,
+    /// The first byte is not a valid [`FrameKind`].
+    UnknownKind(u8),
+}
+
+/// Header of a throughput test frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct FrameHeader {
+    pub kind: FrameKind,
+    pub seq: u32,
+    pub timestamp_us: u32,
+}
+
+impl FrameHeader {
+    /// Write the header followed by filler bytes into `buf`.
+    ///
+    /// Returns the number of bytes used, which is `buf.len()`
+    /// (or [`HEADER_LEN`] for [`FrameKind::Echo`] frames).
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, FrameError> {
+        if buf.len() < HEADER_LEN {
+            return Err(FrameError::TooShort);
+        }
+        buf[0] = self.kind as u8;
+        buf[1..5].copy_from_slice(&self.seq.to_le_bytes());
+        buf[5..9].copy_from_slice(&self.timestamp_us.to_le_bytes());
+        if self.kind == FrameKind::Echo {
+            return Ok(HEADER_LEN);
+        }
+        for (i, b) in buf[HEADER_LEN..].iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        Ok(buf.len())
+    }
+
+    /// Read the header from the start of `buf`.
+    pub fn decode(buf: &[u8]) -> Result<Self, FrameError> {
+        if buf.len() < HEADER_LEN {
+            return Err(FrameError::TooShort);
+        }
+        Ok(Self {
+            kind: FrameKind::try_from(buf[0])?,
+            seq: u32::from_le_bytes([buf[1], buf[2], buf[3], buf[4]]),
+            timestamp_us: u32::from_le_bytes([buf[5], buf[6], buf[7], buf[8]]),
+        })
+    }
+}
+
+/// Current time as wrapping µs timestamp, as carried in the frame header.
+pub fn timestamp_us() -> u32 {
+    Instant::now().as_micros() as u32
+}
+
+/// Accumulates received bytes, lost frames and latencies and
+/// periodically reports them via defmt.
+pub struct ThroughputMeter {
+    window_start: Instant,
+    report_interval: Duration,
+    bytes: u32,
+    frames: u32,
+    lost: u32,
+    next_seq: Option<u32>,
+    latency_sum_us: u64,
+    latency_max_us: u32,
+    latency_count: u32,
+}
+
+impl ThroughputMeter {
+    pub fn new(report_interval: Duration) -> Self {
+        Self {
+            window_start: Instant::now(),
+            report_interval,
+            bytes: 0,
+            frames: 0,
+            lost: 0,
+            next_seq: None,
+            latency_sum_us: 0,
+            latency_max_us: 0,
+            latency_count: 0,
+        }
+    }
+
+    /// Account a frame of `len` bytes and detect gaps in the sequence numbers.
+    /// A sequence number going backwards means the sender restarted, the
+    /// count continues from there without any loss.
+    pub fn record_frame(&mut self, header: &FrameHeader, len: usize) {
+        if let Some(expected) = self.next_seq {
+            let gap = header.seq.wrapping_sub(expected);
+            if gap <= i32::MAX as u32 {
+                self.lost = self.lost.saturating_add(gap);
+            }
+        }
+        self.next_seq = Some(header.seq.wrapping_add(1));
+        self.bytes += len as u32;
+        self.frames += 1;
+    }
+
+    /// Account the round trip latency of an [`FrameKind::Echo`] frame.
+    pub fn record_echo(&mut self, header: &FrameHeader) {
+        let rtt = timestamp_us().wrapping_sub(header.timestamp_us);
+        self.latency_sum_us += u64::from(rtt);
+        self.latency_max_us = self.latency_max_us.max(rtt);
+        self.latency_count += 1;
+    }
+
+    /// Log and reset the statistics if the report interval elapsed.
+    pub fn report_if_due(&mut self) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < self.report_interval {
+            return;
+        }
+        let kbit_per_s = u64::from(self.bytes) * 8 / elapsed.as_millis().max(1);
+        info!(
+            "[throughput] {} kbit/s, {} frames, {} lost",
+            kbit_per_s, self.frames, self.lost
+        );
+        if self.latency_count > 0 {
+            info!(
+                "[throughput] round trip avg {} us, max {} us",
+                self.latency_sum_us / u64::from(self.latency_count),
+                self.latency_max_us
+            );
+        }
+        *self = Self {
+            next_seq: self.next_seq,
+            ..Self::new(self.report_interval)
+        };
+    }
+}
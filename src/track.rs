@@ -0,0 +1,811 @@
+//! Track log of GNSS fixes in named sessions.
+//!
+//! A session is started and stopped with [`start`] and [`stop`] (or
+//! [`toggle`]), from the button, over BLE or with the `track` shell
+//! [`COMMANDS`]. While one is recording, [`collect`] appends a point per
+//! [`POINT_INTERVAL`] to the
+//! [track log flash region](crate::bsp::flash::TRACK_LOG). The start time,
+//! fix count and distance of every session are listed by [`sessions`]. The
+//! log fills up to [`QUOTA_PERCENT`] of its pages, beyond that the oldest
+//! sessions are deleted, their pages are erased by [`reclaim`] from the
+//! [`maintenance`](crate::maintenance) task or when taken again. A session
+//! interrupted by a reboot is stopped with the metadata recovered from its
+//! points. The flash layout lives in [`nrf52_radio_core::track`], the points
+//! are compressed by its [`codec`]. The GPX of a session is read with
+//! [`export`].
+//!
+//! The log is behind an async mutex, its users wait for the flash. The
+//! sessions are also kept in RAM for [`sessions`], and the shell commands
+//! [`request`] starting and stopping from the [`collect`] task.
+
+use core::cell::{Cell, RefCell};
+use core::fmt::{Debug, Write};
+
+use defmt::{Debug2Format, info, warn};
+use embassy_futures::select::{Either, select};
+use embassy_sync::{
+    blocking_mutex::{self, raw::CriticalSectionRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant};
+use embedded_storage_async::nor_flash::NorFlash;
+use heapless::Vec;
+pub use nrf52_radio_core::gpx;
+pub use nrf52_radio_core::track::{
+    NAME_LEN, Point, QUOTA_PERCENT, SessionInfo, Stats, codec, name_str,
+};
+use nrf52_radio_core::track::{
+    PageHeader, SESSION_HEADER_OFFSET, SUMMARY_OFFSET, SessionHeader, Summary,
+    codec::{Decoder, Encoder, PADDING},
+    points_offset, quota_pages,
+};
+
+use crate::bsp::flash::{PAGE_SIZE, Partition};
+use crate::events::{self, Event};
+use crate::shell::{Command, Output};
+
+/// Sessions kept at most, the oldest is deleted for a new one beyond.
+pub const MAX_SESSIONS: usize = 32;
+
+/// Shortest time between two points of a session.
+pub const POINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Write granularity of the flash, records are written in whole words.
+const WORD: usize = 4;
+
+/// Errors of the track log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum TrackError {
+    /// The log couldn't be read at boot.
+    Unavailable,
+    /// Reading or writing the flash failed.
+    Flash,
+    /// A session is already recording.
+    Recording,
+    /// No session is recording.
+    NotRecording,
+    /// The recording session alone fills the log.
+    Full,
+    /// No session has the ID.
+    UnknownSession,
+}
+
+/// Log a flash error and map it to [`TrackError::Flash`].
+fn map_err<T, E: Debug>(result: Result<T, E>) -> Result<T, TrackError> {
+    result
+        .inspect_err(|e| warn!("[track] flash error: {:?}", Debug2Format(e)))
+        .map_err(|_| TrackError::Flash)
+}
+
+/// Bytes of a flash range, read ahead in chunks by [`fill`](Self::fill).
+struct FlashBytes<'a, F: NorFlash> {
+    flash: &'a mut F,
+    /// Address of the next byte read from flash.
+    address: u32,
+    end: u32,
+    chunk: [u8; 32],
+    /// Position of the next byte in `chunk`.
+    pos: usize,
+    len: usize,
+}
+
+impl<'a, F: NorFlash> FlashBytes<'a, F> {
+    fn new(flash: &'a mut F, address: u32, end: u32) -> Self {
+        Self {
+            flash,
+            address,
+            end,
+            chunk: [0; 32],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Address of the next byte.
+    fn position(&self) -> u32 {
+        self.address - (self.len - self.pos) as u32
+    }
+
+    /// Read ahead up to a whole chunk, which holds the next record with the
+    /// padding in front of it.
+    async fn fill(&mut self) -> Result<(), F::Error> {
+        self.chunk.copy_within(self.pos..self.len, 0);
+        self.len -= self.pos;
+        self.pos = 0;
+        let n = (self.chunk.len() - self.len).min((self.end - self.address) as usize);
+        if n > 0 {
+            self.flash
+                .read(self.address, &mut self.chunk[self.len..self.len + n])
+                .await?;
+            self.address += n as u32;
+            self.len += n;
+        }
+        Ok(())
+    }
+}
+
+impl<F: NorFlash> Iterator for FlashBytes<'_, F> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = *self.chunk[..self.len].get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+}
+
+/// Where the recording session writes.
+struct Recording {
+    /// Page of the ring written to.
+    page: u32,
+    /// Offset of the next word in the page.
+    offset: u32,
+    /// Bytes of the last record not written yet, short of a word.
+    pending: Vec<u8, WORD>,
+    encoder: Encoder,
+    stats: Stats,
+}
+
+/// Progress of reading the points of a session, see [`export`].
+#[derive(Debug, Clone, Copy)]
+pub struct Export {
+    id: u32,
+    /// Page within the session.
+    index: u32,
+    /// Offset of the next record in the page.
+    offset: u32,
+    decoder: Decoder,
+}
+
+impl Export {
+    /// Read session `id` from its start.
+    pub const fn new(id: u32) -> Self {
+        Self {
+            id,
+            index: 0,
+            offset: points_offset(0),
+            decoder: Decoder::new(),
+        }
+    }
+}
+
+/// The sessions in a ring of flash pages.
+pub struct TrackLog<F> {
+    flash: F,
+    /// Number of pages in the ring.
+    pages: u32,
+    /// Oldest first, the recording one last.
+    sessions: Vec<SessionInfo, MAX_SESSIONS>,
+    next_id: u32,
+    recording: Option<Recording>,
+    /// Pages of deleted sessions, one bit each, erased by [`reclaim`](Self::reclaim).
+    reclaimed: u64,
+    /// Pages erased by [`reclaim`](Self::reclaim) and not taken since.
+    clean: u64,
+}
+
+impl<F: NorFlash> TrackLog<F> {
+    /// Recover the sessions from flash.
+    pub async fn new(flash: F) -> Result<Self, F::Error> {
+        let pages = flash.capacity() as u32 / PAGE_SIZE;
+        assert!(pages <= u64::BITS);
+        let mut log = Self {
+            flash,
+            pages,
+            sessions: Vec::new(),
+            next_id: 0,
+            recording: None,
+            reclaimed: 0,
+            clean: 0,
+        };
+        // First pages, then the others, which may come before their first
+        // page in the ring.
+        for page in 0..pages {
+            let header: PageHeader = log.read(page * PAGE_SIZE).await?;
+            if !header.is_valid() || header.index != 0 {
+                continue;
+            }
+            let session: SessionHeader = log.read(page * PAGE_SIZE + SESSION_HEADER_OFFSET).await?;
+            if !session.is_valid() {
+                continue;
+            }
+            let info = SessionInfo {
+                id: header.session,
+                name: session.name,
+                start_utc: session.start_utc,
+                end_utc: 0,
+                fixes: 0,
+                distance_m: 0,
+                first_page: page,
+                pages: 1,
+                recording: false,
+            };
+            if log.sessions.push(info).is_err() {
+                warn!("[track] too many sessions, ignoring {}", header.session);
+            }
+        }
+        for page in 0..pages {
+            let header: PageHeader = log.read(page * PAGE_SIZE).await?;
+            if !header.is_valid() {
+                continue;
+            }
+            // Pages of deleted sessions keep their ID from being reused.
+            log.next_id = log.next_id.max(header.session + 1);
+            if header.index == 0 {
+                continue;
+            }
+            if let Some(session) = log.sessions.iter_mut().find(|s| s.id == header.session) {
+                session.pages = session.pages.max(header.index + 1);
+            }
+        }
+        log.sessions.sort_unstable_by_key(|s| s.id);
+        for page in 0..pages {
+            let header: u32 = log.read(page * PAGE_SIZE).await?;
+            if !log.in_use(page) && header != u32::MAX {
+                log.reclaimed |= 1 << page;
+            }
+        }
+        for i in 0..log.sessions.len() {
+            log.summarize(i).await?;
+        }
+        info!(
+            "[track] {} sessions, {} of {} pages used",
+            log.sessions.len(),
+            log.used_pages(),
+            pages
+        );
+        Ok(log)
+    }
+
+    async fn read<T: bytemuck::Pod>(&mut self, offset: u32) -> Result<T, F::Error> {
+        let mut value = T::zeroed();
+        self.flash
+            .read(offset, bytemuck::bytes_of_mut(&mut value))
+            .await?;
+        Ok(value)
+    }
+
+    /// Address of page `page` of the ring, wrapping around at its end.
+    fn address(&self, page: u32) -> u32 {
+        page % self.pages * PAGE_SIZE
+    }
+
+    /// Take the metadata of session `i` from its summary, or recover it
+    /// from its points and write the summary.
+    async fn summarize(&mut self, i: usize) -> Result<(), F::Error> {
+        let session = self.sessions[i];
+        let first = self.address(session.first_page);
+        let summary: Summary = self.read(first + SUMMARY_OFFSET).await?;
+        let summary = if summary.is_valid() {
+            summary
+        } else {
+            let erased = bytemuck::bytes_of(&summary).iter().all(|b| *b == 0xFF);
+            let mut stats = Stats::default();
+            let mut end_utc = session.start_utc;
+            for index in 0..session.pages {
+                let page = self.address(session.first_page + index);
+                let mut bytes = FlashBytes::new(
+                    &mut self.flash,
+                    page + points_offset(index),
+                    page + PAGE_SIZE,
+                );
+                let mut decoder = Decoder::new();
+                loop {
+                    bytes.fill().await?;
+                    let Some(point) = decoder.next_point(&mut bytes) else {
+                        break;
+                    };
+                    stats.add(&point);
+                    end_utc = point.utc;
+                }
+            }
+            let summary = Summary::new(&stats, end_utc);
+            // A torn summary can't be written over.
+            if erased {
+                self.flash
+                    .write(first + SUMMARY_OFFSET, bytemuck::bytes_of(&summary))
+                    .await?;
+            }
+            info!("[track] recovered session {}", session.id);
+            summary
+        };
+        let session = &mut self.sessions[i];
+        session.fixes = summary.fixes;
+        session.distance_m = summary.distance_m;
+        session.end_utc = summary.end_utc;
+        Ok(())
+    }
+
+    /// Pages taken by the sessions.
+    pub fn used_pages(&self) -> u32 {
+        self.sessions.iter().map(|s| s.pages).sum()
+    }
+
+    /// The sessions, oldest first.
+    pub fn sessions(&self) -> &[SessionInfo] {
+        &self.sessions
+    }
+
+    /// Whether a session is recording.
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Whether `page` belongs to a session.
+    fn in_use(&self, page: u32) -> bool {
+        self.sessions
+            .iter()
+            .any(|s| (page + self.pages - s.first_page) % self.pages < s.pages)
+    }
+
+    /// Delete the oldest session by clearing the header of its first page,
+    /// its pages are erased by [`reclaim`](Self::reclaim) or when taken again.
+    async fn delete_oldest(&mut self) -> Result<(), F::Error> {
+        let oldest = self.sessions.remove(0);
+        let first = self.address(oldest.first_page);
+        self.flash
+            .write(first, &[0; size_of::<PageHeader>()])
+            .await?;
+        for index in 0..oldest.pages {
+            self.reclaimed |= 1 << ((oldest.first_page + index) % self.pages);
+        }
+        info!(
+            "[track] deleted session {} ({} pages)",
+            oldest.id, oldest.pages
+        );
+        Ok(())
+    }
+
+    /// Number of pages of deleted sessions not erased yet.
+    pub fn reclaimable(&self) -> u32 {
+        self.reclaimed.count_ones()
+    }
+
+    /// Erase a page of a deleted session ahead of being taken again, returns
+    /// whether there was one.
+    pub async fn reclaim(&mut self) -> Result<bool, F::Error> {
+        if self.reclaimed == 0 {
+            return Ok(false);
+        }
+        let page = self.reclaimed.trailing_zeros();
+        let address = self.address(page);
+        self.flash.erase(address, address + PAGE_SIZE).await?;
+        self.reclaimed &= !(1 << page);
+        self.clean |= 1 << page;
+        Ok(true)
+    }
+
+    /// Erase the page after the newest session for it, deleting the oldest
+    /// sessions beyond the quota. `None` if only the recording session is
+    /// left.
+    async fn allocate(&mut self) -> Result<Option<u32>, F::Error> {
+        while self.used_pages() + 1 > quota_pages(self.pages) {
+            if self.sessions.len() == 1 && self.recording.is_some() || self.sessions.is_empty() {
+                return Ok(None);
+            }
+            self.delete_oldest().await?;
+        }
+        let page = self
+            .sessions
+            .last()
+            .map_or(0, |s| (s.first_page + s.pages) % self.pages);
+        if self.clean & 1 << page == 0 {
+            let address = self.address(page);
+            self.flash.erase(address, address + PAGE_SIZE).await?;
+        }
+        self.reclaimed &= !(1 << page);
+        self.clean &= !(1 << page);
+        Ok(Some(page))
+    }
+
+    /// Start a session named `name` at `start_utc`, returns its ID.
+    pub async fn start(&mut self, name: &str, start_utc: u32) -> Result<u32, TrackError> {
+        if self.recording.is_some() {
+            return Err(TrackError::Recording);
+        }
+        while self.sessions.is_full() {
+            map_err(self.delete_oldest().await)?;
+        }
+        let page = map_err(self.allocate().await)?.ok_or(TrackError::Full)?;
+        let id = self.next_id;
+        let header = SessionHeader::new(name, start_utc);
+        let address = self.address(page);
+        map_err(
+            self.flash
+                .write(address, bytemuck::bytes_of(&PageHeader::new(id, 0)))
+                .await,
+        )?;
+        map_err(
+            self.flash
+                .write(address + SESSION_HEADER_OFFSET, bytemuck::bytes_of(&header))
+                .await,
+        )?;
+        // There is room, the oldest sessions were deleted for it.
+        let _ = self.sessions.push(SessionInfo {
+            id,
+            name: header.name,
+            start_utc,
+            end_utc: 0,
+            fixes: 0,
+            distance_m: 0,
+            first_page: page,
+            pages: 1,
+            recording: true,
+        });
+        self.next_id += 1;
+        self.recording = Some(Recording {
+            page,
+            offset: points_offset(0),
+            pending: Vec::new(),
+            encoder: Encoder::new(),
+            stats: Stats::default(),
+        });
+        info!(
+            "[track] started session {} '{}'",
+            id,
+            name_str(&header.name)
+        );
+        Ok(id)
+    }
+
+    /// Write the whole words of the pending bytes and `bytes` to the page
+    /// of the recording session, keep the rest pending.
+    async fn append(&mut self, bytes: &[u8]) -> Result<(), F::Error> {
+        // Only called while recording.
+        let recording = self.recording.as_mut().unwrap();
+        let mut buf = [0; WORD + codec::MAX_RECORD_LEN];
+        let pending = recording.pending.len();
+        let len = pending + bytes.len();
+        buf[..pending].copy_from_slice(&recording.pending);
+        buf[pending..len].copy_from_slice(bytes);
+        let whole = len / WORD * WORD;
+        let address = recording.page % self.pages * PAGE_SIZE + recording.offset;
+        self.flash.write(address, &buf[..whole]).await?;
+        recording.offset += whole as u32;
+        recording.pending = Vec::from_slice(&buf[whole..len]).unwrap();
+        Ok(())
+    }
+
+    /// Pad the pending bytes to a word and write them.
+    async fn flush(&mut self) -> Result<(), F::Error> {
+        // Only called while recording.
+        let pending = self.recording.as_ref().unwrap().pending.len();
+        if pending > 0 {
+            self.append(&[PADDING; WORD][pending..]).await?;
+        }
+        Ok(())
+    }
+
+    /// Append `point` to the recording session.
+    ///
+    /// Up to a word of it stays in RAM until the next point or [`stop`](Self::stop).
+    pub async fn record(&mut self, point: &Point) -> Result<(), TrackError> {
+        let recording = self.recording.as_mut().ok_or(TrackError::NotRecording)?;
+        let mut record = recording.encoder.encode(point);
+        let used = recording.offset as usize + recording.pending.len();
+        if used + record.len() > PAGE_SIZE as usize {
+            map_err(self.flush().await)?;
+            let Some(page) = map_err(self.allocate().await)? else {
+                warn!("[track] log full");
+                self.stop(point.utc).await?;
+                return Err(TrackError::Full);
+            };
+            // The recording session is never deleted, it is the last one.
+            let session = self.sessions.last_mut().unwrap();
+            let header = PageHeader::new(session.id, session.pages);
+            session.pages += 1;
+            let address = self.address(page);
+            map_err(self.flash.write(address, bytemuck::bytes_of(&header)).await)?;
+            let recording = self.recording.as_mut().unwrap();
+            recording.page = page;
+            recording.offset = points_offset(header.index);
+            // Every page starts with a keyframe.
+            recording.encoder.keyframe();
+            record = recording.encoder.encode(point);
+        }
+        map_err(self.append(&record).await)?;
+        let recording = self.recording.as_mut().unwrap();
+        recording.stats.add(point);
+        let session = self.sessions.last_mut().unwrap();
+        session.fixes = recording.stats.fixes;
+        session.distance_m = recording.stats.distance_m();
+        Ok(())
+    }
+
+    /// Stop the recording session at `end_utc` and write its summary.
+    pub async fn stop(&mut self, end_utc: u32) -> Result<SessionInfo, TrackError> {
+        if self.recording.is_none() {
+            return Err(TrackError::NotRecording);
+        }
+        map_err(self.flush().await)?;
+        let recording = self.recording.take().unwrap();
+        let session = self.sessions.last_mut().unwrap();
+        session.recording = false;
+        session.end_utc = end_utc;
+        let session = *session;
+        let summary = Summary::new(&recording.stats, end_utc);
+        let address = self.address(session.first_page) + SUMMARY_OFFSET;
+        map_err(
+            self.flash
+                .write(address, bytemuck::bytes_of(&summary))
+                .await,
+        )?;
+        info!(
+            "[track] stopped session {}: {} fixes, {} m",
+            session.id, session.fixes, session.distance_m
+        );
+        Ok(session)
+    }
+
+    /// Decode the next points of the session of `export` into `points`,
+    /// until it is full or the session ends.
+    pub async fn export<const N: usize>(
+        &mut self,
+        export: &mut Export,
+        points: &mut Vec<Point, N>,
+    ) -> Result<(), TrackError> {
+        let session = *self
+            .sessions
+            .iter()
+            .find(|s| s.id == export.id)
+            .ok_or(TrackError::UnknownSession)?;
+        while !points.is_full() && export.index < session.pages {
+            let page = self.address(session.first_page + export.index);
+            let mut bytes =
+                FlashBytes::new(&mut self.flash, page + export.offset, page + PAGE_SIZE);
+            while !points.is_full() {
+                map_err(bytes.fill().await)?;
+                let Some(point) = export.decoder.next_point(&mut bytes) else {
+                    export.index += 1;
+                    export.offset = points_offset(export.index);
+                    export.decoder = Decoder::new();
+                    break;
+                };
+                let _ = points.push(point);
+                export.offset = bytes.position() - page;
+            }
+        }
+        Ok(())
+    }
+}
+
+static LOG: Mutex<CriticalSectionRawMutex, Option<TrackLog<Partition>>> = Mutex::new(None);
+
+/// The sessions of the log, see [`sessions`].
+static SESSIONS: blocking_mutex::Mutex<
+    CriticalSectionRawMutex,
+    RefCell<Vec<SessionInfo, MAX_SESSIONS>>,
+> = blocking_mutex::Mutex::new(RefCell::new(Vec::new()));
+
+/// UTC seconds since the Unix epoch at an instant, see [`set_clock`].
+static CLOCK: blocking_mutex::Mutex<CriticalSectionRawMutex, Cell<Option<(i64, Instant)>>> =
+    blocking_mutex::Mutex::new(Cell::new(None));
+
+/// A change of the recording session, see [`request`].
+#[derive(Debug, Clone, PartialEq, Eq, defmt::Format)]
+pub enum Request {
+    /// Start a session with the name, or one named after the UTC time.
+    Start(Option<heapless::String<NAME_LEN>>),
+    Stop,
+}
+
+/// The last request not handled yet.
+static REQUEST: Signal<CriticalSectionRawMutex, Request> = Signal::new();
+
+/// Keep the sessions of `log` for [`sessions`].
+fn publish(log: &TrackLog<Partition>) {
+    SESSIONS.lock(|s| *s.borrow_mut() = Vec::from_slice(log.sessions()).unwrap());
+}
+
+/// Recover the log from the track log flash partition.
+pub async fn init(flash: Partition) {
+    match TrackLog::new(flash).await {
+        Ok(log) => {
+            publish(&log);
+            *LOG.lock().await = Some(log);
+        }
+        Err(e) => warn!("[track] couldn't read the log: {:?}", Debug2Format(&e)),
+    }
+}
+
+/// The UTC time was `utc_secs` since the Unix epoch at `at`, e.g. from the
+/// GNSS module or the [retained state](crate::retained) of the last run.
+pub fn set_clock(utc_secs: i64, at: Instant) {
+    CLOCK.lock(|c| c.set(Some((utc_secs, at))));
+}
+
+/// UTC seconds since the Unix epoch, 0 before the time is known.
+pub fn utc_now() -> u32 {
+    CLOCK.lock(|c| c.get()).map_or(0, |(secs, at)| {
+        (secs + at.elapsed().as_secs() as i64) as u32
+    })
+}
+
+/// Start a session named `name`, returns its ID.
+pub async fn start(name: &str) -> Result<u32, TrackError> {
+    let utc = utc_now();
+    let mut log = LOG.lock().await;
+    let log = log.as_mut().ok_or(TrackError::Unavailable)?;
+    let result = log.start(name, utc).await;
+    publish(log);
+    result
+}
+
+/// Stop the recording session.
+pub async fn stop() -> Result<SessionInfo, TrackError> {
+    let utc = utc_now();
+    let mut log = LOG.lock().await;
+    let log = log.as_mut().ok_or(TrackError::Unavailable)?;
+    let result = log.stop(utc).await;
+    publish(log);
+    result
+}
+
+/// Append `point` to the recording session.
+async fn record(point: &Point) -> Result<(), TrackError> {
+    let mut log = LOG.lock().await;
+    let log = log.as_mut().ok_or(TrackError::Unavailable)?;
+    let result = log.record(point).await;
+    publish(log);
+    result
+}
+
+/// Name of a session started now: the UTC time, or `track` without it.
+fn default_name() -> heapless::String<NAME_LEN> {
+    let mut name = heapless::String::new();
+    match chrono::DateTime::from_timestamp(utc_now() as i64, 0).filter(|_| utc_now() != 0) {
+        Some(dt) => {
+            let _ = write!(name, "{}", dt.format("%Y%m%d-%H%M%S"));
+        }
+        None => {
+            let _ = name.push_str("track");
+        }
+    }
+    name
+}
+
+/// Stop the recording session, or start one named after the UTC time.
+pub async fn toggle() -> Result<(), TrackError> {
+    let recording = LOG
+        .lock()
+        .await
+        .as_ref()
+        .ok_or(TrackError::Unavailable)?
+        .is_recording();
+    if recording {
+        stop().await.map(|_| ())
+    } else {
+        start(&default_name()).await.map(|_| ())
+    }
+}
+
+/// Have the [`collect`] task start or stop a session, for callers that
+/// can't wait for the flash.
+pub fn request(request: Request) {
+    REQUEST.signal(request);
+}
+
+/// The sessions, oldest first, empty if the log couldn't be read.
+pub fn sessions() -> Vec<SessionInfo, MAX_SESSIONS> {
+    SESSIONS.lock(|s| s.borrow().clone())
+}
+
+/// Erase a page of a deleted session, returns whether there was one.
+pub async fn reclaim() -> Result<bool, TrackError> {
+    let mut log = LOG.lock().await;
+    let log = log.as_mut().ok_or(TrackError::Unavailable)?;
+    map_err(log.reclaim().await)
+}
+
+/// The session `id`.
+pub fn session(id: u32) -> Option<SessionInfo> {
+    sessions().into_iter().find(|s| s.id == id)
+}
+
+/// Decode the next points of a session into `points`, fewer than fit once
+/// it ends.
+pub async fn export<const N: usize>(
+    export: &mut Export,
+    points: &mut Vec<Point, N>,
+) -> Result<(), TrackError> {
+    let mut log = LOG.lock().await;
+    let log = log.as_mut().ok_or(TrackError::Unavailable)?;
+    log.export(export, points).await
+}
+
+/// Start or stop a session on a [`Request`].
+async fn handle(request: Request) {
+    let result = match request {
+        Request::Start(Some(name)) => start(&name).await.map(|_| ()),
+        Request::Start(None) => start(&default_name()).await.map(|_| ()),
+        Request::Stop => stop().await.map(|_| ()),
+    };
+    if let Err(e) = result {
+        warn!("[track] request failed: {:?}", e);
+    }
+}
+
+/// Record the fixes on the bus into the recording session, and handle the
+/// [`request`]s.
+///
+/// Stops the session on [`Event::ShuttingDown`].
+pub async fn collect() {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[track] no event bus subscriber available");
+        return;
+    };
+    let mut last_point: Option<Instant> = None;
+    loop {
+        let event = match select(events.next_message_pure(), REQUEST.wait()).await {
+            Either::First(event) => event,
+            Either::Second(request) => {
+                handle(request).await;
+                continue;
+            }
+        };
+        match event {
+            Event::TimeUpdated(dt) => {
+                set_clock(dt.and_utc().timestamp(), Instant::now());
+            }
+            Event::FixAcquired(fix) => {
+                if last_point.is_some_and(|at| at.elapsed() < POINT_INTERVAL) {
+                    continue;
+                }
+                let point = Point::new(&fix, utc_now());
+                match record(&point).await {
+                    Ok(()) => last_point = Some(Instant::now()),
+                    Err(TrackError::NotRecording | TrackError::Unavailable) => {}
+                    Err(e) => warn!("[track] point not recorded: {:?}", e),
+                }
+            }
+            Event::ShuttingDown => {
+                let _ = stop().await;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn track_command(args: &str, out: &mut Output) -> core::fmt::Result {
+    let (action, name) = args.split_once(' ').unwrap_or((args, ""));
+    let request = match action {
+        "" | "list" => {
+            for s in sessions() {
+                write!(
+                    out,
+                    "{} {} start {} fixes {} {} m {} pages{}\r\n",
+                    s.id,
+                    name_str(&s.name),
+                    s.start_utc,
+                    s.fixes,
+                    s.distance_m,
+                    s.pages,
+                    if s.recording { " (recording)" } else { "" }
+                )?;
+            }
+            return Ok(());
+        }
+        "start" if name.trim().is_empty() => Request::Start(None),
+        "start" => {
+            // Cut short like the session header does.
+            let name = name.trim();
+            let mut len = name.len().min(NAME_LEN);
+            while !name.is_char_boundary(len) {
+                len -= 1;
+            }
+            Request::Start(Some(heapless::String::try_from(&name[..len]).unwrap()))
+        }
+        "stop" => Request::Stop,
+        _ => return write!(out, "usage: track [list|start [name]|stop]\r\n"),
+    };
+    self::request(request);
+    Ok(())
+}
+
+/// Shell command to record and list sessions.
+pub const COMMANDS: &[Command] = &[Command {
+    name: "track",
+    help: "track log sessions: track [list|start [name]|stop]",
+    handler: track_command,
+}];
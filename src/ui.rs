@@ -0,0 +1,380 @@
+//! User interface on the monochrome OLED.
+//!
+//! A [`Page`] keeps the state it renders, updated from the application
+//! [event bus](crate::events), and draws itself onto any [`Display`].
+//! [`run_page`] drives a single page: it redraws whenever an event changed
+//! the page or its refresh interval elapsed. [`Carousel`] combines two pages
+//! and switches between them on a short button press, nested carousels
+//! cycle through all their pages. A [`Menu`] over the carousels remembers the
+//! page viewed last and returns to the home page. Without user input the
+//! display is dimmed and turned off by [`PowerSave`], and kept off while the
+//! [battery policy](crate::policy) is at [`PowerLevel::Critical`]. A
+//! connected central coming near wakes it up like a button press, see
+//! [`proximity_wake_dbm`](settings::Settings::proximity_wake_dbm). The
+//! [`SettingsPage`] edits settings with the button, other tasks check
+//! [`input_captured`] before acting on long and double presses. A [`QrPage`]
+//! shows e.g. the BLE address for a phone to scan. [`Notifications`] shows
+//! the notifications of a phone over another page.
+//!
+//! The display is an SSD1306 or SH1106 [`Oled`] of one of the
+//! [`PanelSize`]s, chosen in the [settings](settings::Settings::display_controller).
+//! A change of the rotation applies right away, of the controller or size
+//! on the next [`connect`]. With the `epaper` feature, an SSD1681 [`Epaper`]
+//! on SPI can be used instead, [`run_page`] then keeps to its
+//! [minimum refresh interval](Display::min_refresh_interval) and coalesces
+//! the redraws in between.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use display_interface::DisplayError;
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use embedded_hal::i2c::I2c;
+
+use crate::battery;
+use crate::bsp::ble::rssi::Proximity;
+use crate::bsp::i2c_probe::{self, ProbeError};
+use crate::events::{self, ButtonPress, Event};
+use crate::policy::PowerLevel;
+use crate::settings;
+
+pub mod assets;
+pub mod ble_page;
+pub mod debug_page;
+#[cfg(feature = "epaper")]
+pub mod epaper;
+pub mod gnss_page;
+pub mod menu;
+pub mod notification_page;
+pub mod oled;
+pub mod power;
+pub mod qr_page;
+pub mod range_page;
+pub mod settings_page;
+pub mod status_page;
+pub mod waypoint_page;
+
+pub use ble_page::BlePage;
+pub use debug_page::DebugPage;
+#[cfg(feature = "epaper")]
+pub use epaper::Epaper;
+pub use gnss_page::GnssPage;
+pub use menu::Menu;
+pub use notification_page::Notifications;
+pub use oled::{Controller, Oled, PanelSize, Rotation};
+pub use power::{Power, PowerSave};
+pub use qr_page::QrPage;
+pub use range_page::RangePage;
+pub use settings_page::SettingsPage;
+pub use status_page::StatusPage;
+pub use waypoint_page::WaypointPage;
+
+/// Contrast of the display when it's dimmed.
+const DIMMED_CONTRAST: u8 = 0x01;
+
+/// Contrast of the display at full brightness.
+const FULL_CONTRAST: u8 = 0xCF;
+
+/// Whether the shown page captures the button, see [`input_captured`].
+static INPUT_CAPTURED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the page shown by [`run_page`] uses long and double presses
+/// itself, so other tasks have to ignore them.
+pub fn input_captured() -> bool {
+    INPUT_CAPTURED.load(Ordering::Relaxed)
+}
+
+/// A buffered monochrome display.
+pub trait Display: DrawTarget<Color = BinaryColor, Error = DisplayError> {
+    /// Clear the frame buffer (without flushing it).
+    fn clear_buffer(&mut self);
+
+    /// Send the frame buffer to the display.
+    fn flush_buffer(&mut self) -> Result<(), DisplayError>;
+
+    /// Turn the display on or off, keeping its contents.
+    fn set_power(&mut self, on: bool) -> Result<(), DisplayError>;
+
+    /// Reduce the brightness of the display.
+    fn set_dimmed(&mut self, dimmed: bool) -> Result<(), DisplayError>;
+
+    /// Rotate what is drawn from now on.
+    fn set_rotation(&mut self, rotation: Rotation);
+
+    /// Shortest time between two flushes, for slow displays.
+    fn min_refresh_interval(&self) -> Duration {
+        Duration::from_ticks(0)
+    }
+}
+
+/// Errors of [`connect`].
+#[derive(Debug, defmt::Format)]
+pub enum ConnectError {
+    /// No display answered on the bus.
+    Probe(ProbeError),
+    /// The display was found but couldn't be initialized.
+    Display(DisplayError),
+}
+
+/// Detect the OLED on the bus and initialize it as configured in the
+/// settings.
+///
+/// `i2c` is the TWIM itself or a device on the shared bus.
+pub fn connect<I: I2c>(mut i2c: I) -> Result<impl Display, ConnectError> {
+    let address = i2c_probe::find_oled(&mut i2c).map_err(ConnectError::Probe)?;
+    let (controller, size, rotation) = settings::get().display();
+    defmt::info!("[ui] {:?} {:?} at {:#04x}", controller, size, address);
+    let mut display = Oled::new(i2c, address, controller, size, rotation);
+    display.init().map_err(ConnectError::Display)?;
+    Ok(display)
+}
+
+/// A full screen page of the UI.
+pub trait Page {
+    /// Update the page state. Returns `true` if the page has to be redrawn.
+    fn update(&mut self, event: &Event) -> bool;
+
+    /// Draw the page into the (cleared) frame buffer.
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError>;
+
+    /// Interval in which the page is redrawn without events,
+    /// e.g. to show that data became stale.
+    fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Show the next sub-page, if any. Returns `false` if the page has no
+    /// (further) sub-pages and wrapped around to the first one.
+    fn advance(&mut self) -> bool {
+        false
+    }
+
+    /// Whether the page uses long and double presses itself, e.g. to edit
+    /// a setting, see [`input_captured`].
+    fn captures_input(&self) -> bool {
+        false
+    }
+
+    /// Number of pages a [`Menu`] can show one by one, more than one for
+    /// carousels.
+    fn page_count(&self) -> usize {
+        1
+    }
+
+    /// Index of the shown page, below [`page_count`](Self::page_count).
+    fn page_index(&self) -> usize {
+        0
+    }
+
+    /// Show the page at `index`, the first one if it's out of range.
+    fn show_page(&mut self, _index: usize) {}
+
+    /// Called before every redraw, for changes that depend on the time
+    /// rather than on events.
+    fn tick(&mut self) {}
+}
+
+/// Two pages sharing the display, switched by a short button press.
+///
+/// Both pages receive all events but the button presses, so the hidden page
+/// is up to date when it's shown. Carousels can be nested to show more than
+/// two pages.
+pub struct Carousel<A, B> {
+    first: A,
+    second: B,
+    show_second: bool,
+}
+
+impl<A: Page, B: Page> Carousel<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Self {
+            first,
+            second,
+            show_second: false,
+        }
+    }
+}
+
+impl<A: Page, B: Page> Page for Carousel<A, B> {
+    fn update(&mut self, event: &Event) -> bool {
+        if *event == Event::ButtonPressed(ButtonPress::Short) {
+            self.advance();
+            return true;
+        }
+        if let Event::ButtonPressed(_) = event {
+            return if self.show_second {
+                self.second.update(event)
+            } else {
+                self.first.update(event)
+            };
+        }
+        let first = self.first.update(event);
+        let second = self.second.update(event);
+        if self.show_second { second } else { first }
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        if self.show_second {
+            self.second.draw(display)
+        } else {
+            self.first.draw(display)
+        }
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        if self.show_second {
+            self.second.refresh_interval()
+        } else {
+            self.first.refresh_interval()
+        }
+    }
+
+    fn advance(&mut self) -> bool {
+        if self.show_second {
+            self.show_second = self.second.advance();
+            self.show_second
+        } else {
+            self.show_second = !self.first.advance();
+            true
+        }
+    }
+
+    fn captures_input(&self) -> bool {
+        if self.show_second {
+            self.second.captures_input()
+        } else {
+            self.first.captures_input()
+        }
+    }
+
+    fn page_count(&self) -> usize {
+        self.first.page_count() + self.second.page_count()
+    }
+
+    fn page_index(&self) -> usize {
+        if self.show_second {
+            self.first.page_count() + self.second.page_index()
+        } else {
+            self.first.page_index()
+        }
+    }
+
+    fn show_page(&mut self, index: usize) {
+        let first = self.first.page_count();
+        self.show_second = (first..self.page_count()).contains(&index);
+        if self.show_second {
+            self.second.show_page(index - first);
+        } else {
+            self.first.show_page(index);
+        }
+    }
+
+    fn tick(&mut self) {
+        self.first.tick();
+        self.second.tick();
+    }
+}
+
+/// Clear, draw and flush a page.
+pub fn render<D: Display, P: Page>(display: &mut D, page: &P) -> Result<(), DisplayError> {
+    display.clear_buffer();
+    page.draw(display)?;
+    display.flush_buffer()
+}
+
+/// Show `page` on `display` and keep it up to date.
+///
+/// A button press while the display is off only wakes it up, it isn't passed
+/// on to the page. Redraws within the
+/// [minimum refresh interval](Display::min_refresh_interval) of the display
+/// are postponed to its end and combined into one.
+pub async fn run_page<D: Display, P: Page>(
+    display: &mut D,
+    page: &mut P,
+) -> Result<(), DisplayError> {
+    let Ok(mut events) = events::subscribe() else {
+        defmt::warn!("[ui] no event bus subscriber available");
+        return Ok(());
+    };
+    let mut power = PowerSave::new();
+    let mut proximity = Proximity::new();
+    let (_, _, mut rotation) = settings::get().display();
+    page.tick();
+    render(display, page)?;
+    INPUT_CAPTURED.store(page.captures_input(), Ordering::Relaxed);
+    let mut last_render = Instant::now();
+    let mut pending = false;
+    loop {
+        let settings = settings::get();
+        let next_render = last_render + display.min_refresh_interval();
+        let refresh = match power.power() {
+            Power::Off => None,
+            _ if pending => Some(next_render),
+            _ => Some(next_render.max(Instant::now() + page.refresh_interval())),
+        };
+        let wake = match (refresh, power.deadline(&settings)) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b).unwrap_or(Instant::MAX),
+        };
+        match select(events.next_message_pure(), Timer::at(wake)).await {
+            Either::First(Event::ButtonPressed(press)) => {
+                if power.input(display)? == Power::Off || page.update(&Event::ButtonPressed(press))
+                {
+                    pending = true;
+                }
+            }
+            Either::First(event) => {
+                match event {
+                    Event::ShuttingDown => return display.set_power(false),
+                    Event::BatteryLow { .. } => power.set_low_battery(true),
+                    Event::BatteryUpdated { percent, .. } if percent >= battery::LOW_PERCENT => {
+                        power.set_low_battery(false)
+                    }
+                    Event::PowerLevelChanged(level) => {
+                        power.set_suspended(level >= PowerLevel::Critical);
+                        power.poll(display, &settings)?;
+                    }
+                    Event::RssiUpdated(rssi) if settings.proximity_wake_dbm != 0 => {
+                        let threshold = -(settings.proximity_wake_dbm as i16) as i8;
+                        if proximity.update(rssi, threshold) {
+                            crate::log!(Ui, debug, "[ui] central near, waking up");
+                            if power.input(display)? == Power::Off {
+                                pending = true;
+                            }
+                        }
+                    }
+                    Event::CentralDisconnected => proximity.reset(),
+                    // Shown by the page, like a button press wakes it up.
+                    Event::PhoneNotification(_) => {
+                        power.input(display)?;
+                    }
+                    _ => {}
+                }
+                let (_, _, configured) = settings::get().display();
+                let rotated = configured != rotation;
+                if rotated {
+                    rotation = configured;
+                    display.set_rotation(rotation);
+                }
+                if page.update(&event) || rotated {
+                    pending = true;
+                }
+            }
+            Either::Second(_) => {
+                if power.poll(display, &settings)? != Power::Off {
+                    pending = true;
+                }
+            }
+        }
+        if power.power() == Power::Off {
+            pending = false;
+        } else if pending && Instant::now() >= next_render {
+            page.tick();
+            render(display, page)?;
+            last_render = Instant::now();
+            pending = false;
+        }
+        INPUT_CAPTURED.store(page.captures_input(), Ordering::Relaxed);
+    }
+}
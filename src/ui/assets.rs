@@ -0,0 +1,254 @@
+//! Fonts, icons and the status bar shared by all pages.
+//!
+//! Icons are compiled-in 1 bit per pixel bitmaps, rows from top to bottom,
+//! most significant bit left.
+
+use core::fmt::Write;
+
+use display_interface::DisplayError;
+use embedded_graphics::{
+    image::{Image, ImageRaw},
+    mono_font::{
+        MonoFont, MonoTextStyle,
+        ascii::{FONT_6X10, FONT_10X20},
+    },
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle},
+    text::{Baseline, Text},
+};
+use heapless::String;
+
+use super::Display;
+use crate::battery::{self, ChargeState};
+use crate::events::Event;
+
+/// Font for regular text.
+pub const SMALL_FONT: MonoFont<'static> = FONT_6X10;
+
+/// Font for prominent numbers like speed or coordinates.
+pub const LARGE_FONT: MonoFont<'static> = FONT_10X20;
+
+/// Height of a line of [`SMALL_FONT`] text including spacing.
+pub const LINE_HEIGHT: i32 = 10;
+
+/// Height of the status bar including its separator line.
+pub const STATUS_BAR_HEIGHT: i32 = 10;
+
+/// First row below the status bar available to a page.
+pub const CONTENT_TOP: i32 = STATUS_BAR_HEIGHT + 2;
+
+/// An 8x8 icon.
+pub type Icon = ImageRaw<'static, BinaryColor>;
+
+pub const SATELLITE: Icon = ImageRaw::new(
+    &[
+        0b01000000, 0b11100000, 0b01010000, 0b00011100, 0b00001110, 0b00000101, 0b00000011,
+        0b00001110,
+    ],
+    8,
+);
+
+pub const BLUETOOTH: Icon = ImageRaw::new(
+    &[
+        0b00010000, 0b00011000, 0b01010100, 0b00111000, 0b00111000, 0b01010100, 0b00011000,
+        0b00010000,
+    ],
+    8,
+);
+
+pub const WARNING: Icon = ImageRaw::new(
+    &[
+        0b00011000, 0b00011000, 0b00100100, 0b00100100, 0b01011010, 0b01000010, 0b10011001,
+        0b11111111,
+    ],
+    8,
+);
+
+pub const CHARGING: Icon = ImageRaw::new(
+    &[
+        0b00001100, 0b00011000, 0b00110000, 0b01111110, 0b00001100, 0b00011000, 0b00110000,
+        0b00100000,
+    ],
+    8,
+);
+
+/// Battery gauges (16x8) from empty to full.
+pub const BATTERY: [Icon; 5] = [
+    ImageRaw::new(
+        &[
+            0b11111111, 0b11111100, 0b10000000, 0b00000100, 0b10000000, 0b00000110, 0b10000000,
+            0b00000110, 0b10000000, 0b00000110, 0b10000000, 0b00000110, 0b10000000, 0b00000100,
+            0b11111111, 0b11111100,
+        ],
+        16,
+    ),
+    ImageRaw::new(
+        &[
+            0b11111111, 0b11111100, 0b10000000, 0b00000100, 0b10110000, 0b00000110, 0b10110000,
+            0b00000110, 0b10110000, 0b00000110, 0b10110000, 0b00000110, 0b10000000, 0b00000100,
+            0b11111111, 0b11111100,
+        ],
+        16,
+    ),
+    ImageRaw::new(
+        &[
+            0b11111111, 0b11111100, 0b10000000, 0b00000100, 0b10110110, 0b00000110, 0b10110110,
+            0b00000110, 0b10110110, 0b00000110, 0b10110110, 0b00000110, 0b10000000, 0b00000100,
+            0b11111111, 0b11111100,
+        ],
+        16,
+    ),
+    ImageRaw::new(
+        &[
+            0b11111111, 0b11111100, 0b10000000, 0b00000100, 0b10110110, 0b11000110, 0b10110110,
+            0b11000110, 0b10110110, 0b11000110, 0b10110110, 0b11000110, 0b10000000, 0b00000100,
+            0b11111111, 0b11111100,
+        ],
+        16,
+    ),
+    ImageRaw::new(
+        &[
+            0b11111111, 0b11111100, 0b10000000, 0b00000100, 0b10110110, 0b11011110, 0b10110110,
+            0b11011110, 0b10110110, 0b11011110, 0b10110110, 0b11011110, 0b10000000, 0b00000100,
+            0b11111111, 0b11111100,
+        ],
+        16,
+    ),
+];
+
+/// Battery gauge for a state of charge in percent.
+pub fn battery_icon(percent: u8) -> &'static Icon {
+    let level = (percent.min(100) as usize * (BATTERY.len() - 1) + 50) / 100;
+    &BATTERY[level]
+}
+
+/// Draw an icon with its top left corner at `at`.
+pub fn draw_icon<D: Display>(display: &mut D, icon: &Icon, at: Point) -> Result<(), DisplayError> {
+    Image::new(icon, at).draw(display).map(|_| ())
+}
+
+/// Draw a line of [`SMALL_FONT`] text with its top left corner at `at`.
+pub fn draw_text<D: Display>(display: &mut D, text: &str, at: Point) -> Result<(), DisplayError> {
+    let style = MonoTextStyle::new(&SMALL_FONT, BinaryColor::On);
+    Text::with_baseline(text, at, style, Baseline::Top)
+        .draw(display)
+        .map(|_| ())
+}
+
+/// Draw [`LARGE_FONT`] text with its top left corner at `at`.
+pub fn draw_large_text<D: Display>(
+    display: &mut D,
+    text: &str,
+    at: Point,
+) -> Result<(), DisplayError> {
+    let style = MonoTextStyle::new(&LARGE_FONT, BinaryColor::On);
+    Text::with_baseline(text, at, style, Baseline::Top)
+        .draw(display)
+        .map(|_| ())
+}
+
+/// Status bar at the top of every page: satellites in use, BLE connection,
+/// battery, charging and a warning indicator (low battery or GNSS module
+/// absent).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StatusBar {
+    satellites: Option<u8>,
+    connected: bool,
+    battery_percent: Option<u8>,
+    charging: bool,
+    warning: bool,
+    gnss_absent: bool,
+}
+
+impl StatusBar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update the indicators. Returns `true` if the status bar changed.
+    pub fn update(&mut self, event: &Event) -> bool {
+        let before = (
+            self.satellites,
+            self.connected,
+            self.battery_percent,
+            self.charging,
+            self.warning,
+            self.gnss_absent,
+        );
+        match event {
+            Event::FixAcquired(fix) => {
+                self.satellites = Some(fix.satellites);
+                self.gnss_absent = false;
+            }
+            Event::FixLost => {
+                self.satellites = None;
+                self.gnss_absent = false;
+            }
+            Event::GnssAbsent => {
+                self.satellites = None;
+                self.gnss_absent = true;
+            }
+            Event::CentralConnected { .. } => self.connected = true,
+            Event::CentralDisconnected => self.connected = false,
+            Event::BatteryUpdated { percent, .. } => {
+                self.battery_percent = Some(*percent);
+                self.warning = *percent < battery::LOW_PERCENT;
+            }
+            Event::BatteryLow { .. } => self.warning = true,
+            Event::ChargeStateChanged(state) => self.charging = *state == ChargeState::Charging,
+            _ => {}
+        }
+        before
+            != (
+                self.satellites,
+                self.connected,
+                self.battery_percent,
+                self.charging,
+                self.warning,
+                self.gnss_absent,
+            )
+    }
+
+    /// Whether the GNSS module was reported absent.
+    pub fn gnss_absent(&self) -> bool {
+        self.gnss_absent
+    }
+
+    /// Draw the status bar at the top of the display, the power icons
+    /// aligned to its right edge.
+    pub fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        let width = display.bounding_box().size.width as i32;
+        draw_icon(display, &SATELLITE, Point::zero())?;
+        let mut satellites: String<4> = String::new();
+        match self.satellites {
+            Some(n) => {
+                let _ = write!(satellites, "{}", n);
+            }
+            None => {
+                let _ = satellites.push('-');
+            }
+        }
+        draw_text(display, &satellites, Point::new(10, -1))?;
+
+        if self.connected {
+            draw_icon(display, &BLUETOOTH, Point::new(40, 0))?;
+        }
+        if self.warning || self.gnss_absent {
+            draw_icon(display, &WARNING, Point::new(width - 40, 0))?;
+        }
+        if self.charging {
+            draw_icon(display, &CHARGING, Point::new(width - 27, 0))?;
+        }
+        if let Some(percent) = self.battery_percent {
+            draw_icon(display, battery_icon(percent), Point::new(width - 16, 0))?;
+        }
+
+        Line::new(
+            Point::new(0, STATUS_BAR_HEIGHT - 1),
+            Point::new(width - 1, STATUS_BAR_HEIGHT - 1),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+        .draw(display)
+    }
+}
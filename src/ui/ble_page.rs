@@ -0,0 +1,113 @@
+//! Page showing the BLE connection state.
+
+use core::fmt::Write;
+
+use display_interface::DisplayError;
+use embedded_graphics::prelude::*;
+use heapless::String;
+
+use super::assets::{self, CONTENT_TOP, LINE_HEIGHT, StatusBar};
+use super::{Display, Page};
+use crate::events::{Event, Phy};
+use crate::fmt;
+
+/// State of the BLE peripheral as seen by the page.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum LinkState {
+    #[default]
+    Idle,
+    Advertising,
+    Connected {
+        address: [u8; 6],
+        mtu: u16,
+        phy: Option<Phy>,
+        rssi: Option<i8>,
+    },
+}
+
+/// Advertising state, connected central, MTU, PHY and RSSI.
+#[derive(Default)]
+pub struct BlePage {
+    status_bar: StatusBar,
+    state: LinkState,
+}
+
+impl BlePage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn phy_name(phy: Option<Phy>) -> &'static str {
+    match phy {
+        None => "?",
+        Some(Phy::Le1M) => "1M",
+        Some(Phy::Le2M) => "2M",
+        Some(Phy::LeCoded) => "Coded",
+    }
+}
+
+impl Page for BlePage {
+    fn update(&mut self, event: &Event) -> bool {
+        let status_changed = self.status_bar.update(event);
+        match (event, &mut self.state) {
+            (Event::AdvertisingStarted, _) => self.state = LinkState::Advertising,
+            (Event::CentralConnected { address, mtu }, _) => {
+                self.state = LinkState::Connected {
+                    address: *address,
+                    mtu: *mtu,
+                    phy: None,
+                    rssi: None,
+                }
+            }
+            (Event::CentralDisconnected, _) => self.state = LinkState::Idle,
+            (Event::MtuUpdated(new), LinkState::Connected { mtu, .. }) => *mtu = *new,
+            (Event::PhyUpdated { rx, .. }, LinkState::Connected { phy, .. }) => *phy = Some(*rx),
+            (Event::RssiUpdated(new), LinkState::Connected { rssi, .. }) => *rssi = Some(*new),
+            _ => return status_changed,
+        }
+        true
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        self.status_bar.draw(display)?;
+
+        let mut lines: [String<24>; 3] = Default::default();
+        let mut rssi = None;
+        match self.state {
+            LinkState::Idle => {
+                let _ = write!(lines[0], "BLE idle");
+            }
+            LinkState::Advertising => {
+                let _ = write!(lines[0], "BLE advertising");
+            }
+            LinkState::Connected {
+                address: a,
+                mtu,
+                phy,
+                rssi: r,
+            } => {
+                let _ = write!(lines[0], "BLE connected");
+                let _ = lines[1].push_str(&fmt::mac(&a));
+                let _ = write!(lines[2], "MTU {}  PHY {}", mtu, phy_name(phy));
+                rssi = r;
+            }
+        }
+        for (i, line) in lines.iter().enumerate().filter(|(_, l)| !l.is_empty()) {
+            assets::draw_text(
+                display,
+                line,
+                Point::new(0, CONTENT_TOP + i as i32 * LINE_HEIGHT),
+            )?;
+        }
+
+        if let Some(rssi) = rssi {
+            let top = CONTENT_TOP + 3 * LINE_HEIGHT;
+            let mut value: String<8> = String::new();
+            let _ = write!(value, "{:4}", rssi);
+            assets::draw_large_text(display, &value, Point::new(0, top))?;
+            assets::draw_text(display, "dBm", Point::new(46, top + 8))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+//! Page with the firmware build and the [metrics](crate::metrics).
+
+use core::fmt::Write;
+
+use display_interface::DisplayError;
+use embedded_graphics::prelude::*;
+use heapless::String;
+
+use super::assets::{self, LINE_HEIGHT};
+use super::{Display, Page};
+use crate::build_info::{GIT_HASH, VERSION};
+use crate::events::Event;
+use crate::metrics::{self, NOTIFICATIONS_DROPPED};
+
+/// Version, git hash and the counters, read on every redraw.
+#[derive(Default)]
+pub struct DebugPage;
+
+impl DebugPage {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Page for DebugPage {
+    /// The counters aren't events, the page is redrawn periodically.
+    fn update(&mut self, _event: &Event) -> bool {
+        false
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        let s = metrics::snapshot();
+        let mut lines: [String<24>; 6] = Default::default();
+        let _ = write!(lines[0], "v{} {}", VERSION, GIT_HASH);
+        let _ = write!(lines[1], "ADV {}", s.advertisements);
+        let _ = write!(lines[2], "CONN {}", s.connections);
+        let _ = write!(lines[3], "FIX {}", s.fixes);
+        let _ = write!(lines[4], "DROP {}", NOTIFICATIONS_DROPPED.get());
+        let _ = write!(lines[5], "UP {} s", s.uptime_secs);
+        for (i, line) in lines.iter().enumerate() {
+            assets::draw_text(display, line, Point::new(0, i as i32 * LINE_HEIGHT))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+//! SSD1681 e-paper displays on SPI, e.g. the 1.54" 200x200 modules.
+//!
+//! The image stays on the panel without power, so the display is put into
+//! deep sleep instead of being dimmed or turned off, and woken up by a
+//! hardware reset. A refresh takes up to 2 s, [`flush_buffer`] only starts
+//! it and the next command waits for BUSY to be released. Together with
+//! [`Display::min_refresh_interval`], [`run_page`](super::run_page) doesn't
+//! block the executor on the panel.
+//!
+//! [`flush_buffer`]: Display::flush_buffer
+
+use display_interface::DisplayError;
+use embassy_time::Duration;
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::spi::SpiDevice;
+pub use nrf52_radio_core::epaper::RefreshPolicy;
+use nrf52_radio_core::epaper::{
+    DEEP_SLEEP, FrameBuffer, INIT_COMMANDS, MASTER_ACTIVATION, Refresh, SET_RAM_X_COUNTER,
+    SET_RAM_Y_COUNTER, SIZE, SW_RESET, UPDATE_CONTROL_2, WRITE_BLACK_RAM, WRITE_RED_RAM,
+};
+
+use super::{Display, Rotation};
+
+/// Shortest time between two refreshes, longer than a full refresh.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Longest wait for BUSY, a full refresh takes about 2 s.
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+/// Buffered e-paper display on SPI.
+///
+/// `busy` is high while the controller is busy, `dc` selects data (high) or
+/// commands (low), `reset` is active low.
+pub struct Epaper<SPI, DC, RST, BUSY, D> {
+    spi: SPI,
+    dc: DC,
+    reset: RST,
+    busy: BUSY,
+    delay: D,
+    frame: FrameBuffer,
+    policy: RefreshPolicy,
+    asleep: bool,
+}
+
+impl<SPI, DC, RST, BUSY, D> Epaper<SPI, DC, RST, BUSY, D>
+where
+    SPI: SpiDevice,
+    DC: OutputPin,
+    RST: OutputPin,
+    BUSY: InputPin,
+    D: DelayNs,
+{
+    pub fn new(
+        spi: SPI,
+        dc: DC,
+        reset: RST,
+        busy: BUSY,
+        delay: D,
+        rotation: Rotation,
+        policy: RefreshPolicy,
+    ) -> Self {
+        Self {
+            spi,
+            dc,
+            reset,
+            busy,
+            delay,
+            frame: FrameBuffer::new(rotation),
+            policy,
+            asleep: true,
+        }
+    }
+
+    /// Reset and initialize the controller and clear the display.
+    pub fn init(&mut self) -> Result<(), DisplayError> {
+        self.wake()?;
+        self.frame.clear();
+        self.flush_buffer()
+    }
+
+    /// Hardware reset, leaves deep sleep, then initialize the controller.
+    fn wake(&mut self) -> Result<(), DisplayError> {
+        self.reset.set_low().map_err(|_| DisplayError::RSError)?;
+        self.delay.delay_ms(10);
+        self.reset.set_high().map_err(|_| DisplayError::RSError)?;
+        self.delay.delay_ms(10);
+        self.wait_busy()?;
+        self.command(SW_RESET, &[])?;
+        self.wait_busy()?;
+        for (command, data) in INIT_COMMANDS {
+            self.command(command, data)?;
+        }
+        self.wait_busy()?;
+        self.asleep = false;
+        // The RAM with the previous image was lost.
+        self.policy.reset();
+        Ok(())
+    }
+
+    fn wait_busy(&mut self) -> Result<(), DisplayError> {
+        for _ in 0..BUSY_TIMEOUT_MS {
+            if !self
+                .busy
+                .is_high()
+                .map_err(|_| DisplayError::BusWriteError)?
+            {
+                return Ok(());
+            }
+            self.delay.delay_ms(1);
+        }
+        defmt::warn!("[ui] e-paper busy for more than {=u32} ms", BUSY_TIMEOUT_MS);
+        Err(DisplayError::BusWriteError)
+    }
+
+    fn command(&mut self, command: u8, data: &[u8]) -> Result<(), DisplayError> {
+        self.dc.set_low().map_err(|_| DisplayError::DCError)?;
+        self.spi
+            .write(&[command])
+            .map_err(|_| DisplayError::BusWriteError)?;
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.dc.set_high().map_err(|_| DisplayError::DCError)?;
+        self.spi
+            .write(data)
+            .map_err(|_| DisplayError::BusWriteError)
+    }
+
+    /// Write the frame to `ram` from the top left.
+    fn write_ram(&mut self, ram: u8) -> Result<(), DisplayError> {
+        self.command(SET_RAM_X_COUNTER, &[0x00])?;
+        self.command(SET_RAM_Y_COUNTER, &[0x00, 0x00])?;
+        self.dc.set_low().map_err(|_| DisplayError::DCError)?;
+        self.spi
+            .write(&[ram])
+            .map_err(|_| DisplayError::BusWriteError)?;
+        self.dc.set_high().map_err(|_| DisplayError::DCError)?;
+        self.spi
+            .write(self.frame.bytes())
+            .map_err(|_| DisplayError::BusWriteError)
+    }
+}
+
+impl<SPI, DC, RST, BUSY, D> OriginDimensions for Epaper<SPI, DC, RST, BUSY, D> {
+    fn size(&self) -> Size {
+        Size::new(SIZE as u32, SIZE as u32)
+    }
+}
+
+impl<SPI, DC, RST, BUSY, D> DrawTarget for Epaper<SPI, DC, RST, BUSY, D> {
+    type Color = BinaryColor;
+    type Error = DisplayError;
+
+    fn draw_iter<P>(&mut self, pixels: P) -> Result<(), Self::Error>
+    where
+        P: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let (Ok(x), Ok(y)) = (usize::try_from(point.x), usize::try_from(point.y)) {
+                self.frame.set_pixel(x, y, color.is_on());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<SPI, DC, RST, BUSY, D> Display for Epaper<SPI, DC, RST, BUSY, D>
+where
+    SPI: SpiDevice,
+    DC: OutputPin,
+    RST: OutputPin,
+    BUSY: InputPin,
+    D: DelayNs,
+{
+    fn clear_buffer(&mut self) {
+        self.frame.clear();
+    }
+
+    fn flush_buffer(&mut self) -> Result<(), DisplayError> {
+        if self.asleep {
+            self.wake()?;
+        }
+        // The previous refresh may still be running.
+        self.wait_busy()?;
+        let refresh = self.policy.choose();
+        self.write_ram(WRITE_BLACK_RAM)?;
+        if refresh == Refresh::Full {
+            self.write_ram(WRITE_RED_RAM)?;
+        }
+        self.command(UPDATE_CONTROL_2, &[refresh.update_sequence()])?;
+        self.command(MASTER_ACTIVATION, &[])
+    }
+
+    fn set_power(&mut self, on: bool) -> Result<(), DisplayError> {
+        match (on, self.asleep) {
+            (true, true) => self.wake(),
+            (false, false) => {
+                self.wait_busy()?;
+                self.asleep = true;
+                self.command(DEEP_SLEEP, &[0x01])
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn set_dimmed(&mut self, _dimmed: bool) -> Result<(), DisplayError> {
+        // Reflective, nothing to dim.
+        Ok(())
+    }
+
+    fn set_rotation(&mut self, rotation: Rotation) {
+        self.frame.set_rotation(rotation);
+    }
+
+    fn min_refresh_interval(&self) -> Duration {
+        MIN_REFRESH_INTERVAL
+    }
+}
@@ -0,0 +1,94 @@
+//! Page showing the live GNSS status.
+
+use core::fmt::Write;
+
+use display_interface::DisplayError;
+use embassy_time::{Duration, Instant};
+use embedded_graphics::prelude::*;
+use heapless::String;
+
+use super::assets::{self, CONTENT_TOP, LINE_HEIGHT, StatusBar};
+use super::{Display, Page};
+use crate::events::Event;
+use crate::gnss::Fix;
+use crate::nav::{MotionState, format};
+
+/// A fix older than this is shown as stale.
+const FIX_MAX_AGE: Duration = Duration::from_secs(5);
+
+/// Fix state, HDOP, coordinates (decimal degrees and MGRS) and speed.
+#[derive(Default)]
+pub struct GnssPage {
+    status_bar: StatusBar,
+    fix: Option<(Fix, Instant)>,
+    motion: Option<(f32, MotionState)>,
+}
+
+impl GnssPage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn status(&self) -> &'static str {
+        if self.status_bar.gnss_absent() {
+            return "ABSENT";
+        }
+        match self.fix {
+            None => "NO FIX",
+            Some((_, at)) if at.elapsed() > FIX_MAX_AGE => "STALE",
+            Some(_) => "FIX",
+        }
+    }
+}
+
+impl Page for GnssPage {
+    fn update(&mut self, event: &Event) -> bool {
+        let status_changed = self.status_bar.update(event);
+        match event {
+            Event::FixAcquired(fix) => self.fix = Some((*fix, Instant::now())),
+            Event::FixLost | Event::GnssAbsent => self.fix = None,
+            Event::MotionUpdated {
+                speed_knots, state, ..
+            } => self.motion = Some((*speed_knots, *state)),
+            _ => return status_changed,
+        }
+        true
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        self.status_bar.draw(display)?;
+
+        let mut lines: [String<24>; 3] = Default::default();
+        let _ = write!(lines[0], "GNSS {}", self.status());
+        if let Some((fix, _)) = &self.fix {
+            if let Some(hdop) = fix.hdop {
+                let _ = write!(lines[0], "  HDOP {:.1}", hdop);
+            }
+            let _ = format::decimal_degrees(&mut lines[1], fix.latitude, fix.longitude);
+            let _ = format::mgrs(&mut lines[2], fix.latitude, fix.longitude, 5);
+        }
+        for (i, line) in lines.iter().enumerate().filter(|(_, l)| !l.is_empty()) {
+            assets::draw_text(
+                display,
+                line,
+                Point::new(0, CONTENT_TOP + i as i32 * LINE_HEIGHT),
+            )?;
+        }
+
+        let top = CONTENT_TOP + 3 * LINE_HEIGHT;
+        match self.motion {
+            Some((speed, MotionState::Moving)) => {
+                let mut value: String<8> = String::new();
+                // 1 knot = 1.852 km/h
+                let _ = write!(value, "{:5.1}", speed * 1.852);
+                assets::draw_large_text(display, &value, Point::new(0, top))?;
+                assets::draw_text(display, "km/h", Point::new(56, top + 8))?;
+            }
+            Some((_, MotionState::Stationary)) => {
+                assets::draw_large_text(display, "STILL", Point::new(0, top))?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Menu over a tree of pages.
+//!
+//! The tree is built from nested [`Carousel`](super::Carousel)s, e.g. one
+//! per section (Status, GNSS, BLE, Settings, Debug) with its pages, and
+//! every page has an index in it, see [`Page::page_count`]. [`Menu`] shows
+//! the page viewed last before a reboot, kept in
+//! [`Settings::ui_page`](crate::settings::Settings::ui_page), and returns to
+//! the first (home) page after [`Settings::ui_home_secs`] without input.
+//!
+//! [`Settings::ui_home_secs`]: crate::settings::Settings::ui_home_secs
+
+use display_interface::DisplayError;
+use embassy_time::{Duration, Instant};
+
+use super::{Display, Page};
+use crate::events::Event;
+use crate::settings;
+
+/// Time a page has to be shown before it's remembered, so paging through
+/// the menu doesn't write the settings on every press.
+const REMEMBER_AFTER: Duration = Duration::from_secs(5);
+
+/// Remembers the last page and returns to the home page.
+pub struct Menu<P> {
+    root: P,
+    last_input: Instant,
+    /// Index of the page the user switched to.
+    viewed: usize,
+    /// Index stored in the settings.
+    remembered: usize,
+}
+
+impl<P: Page> Menu<P> {
+    /// Show the page of `root` viewed last.
+    pub fn new(mut root: P) -> Self {
+        let remembered = settings::get().ui_page as usize;
+        root.show_page(remembered);
+        let viewed = root.page_index();
+        Self {
+            root,
+            last_input: Instant::now(),
+            viewed,
+            remembered,
+        }
+    }
+
+    /// Time without input after which the home page is shown, if enabled.
+    fn home_timeout(&self) -> Option<Duration> {
+        match settings::get().ui_home_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs.into())),
+        }
+    }
+}
+
+impl<P: Page> Page for Menu<P> {
+    fn update(&mut self, event: &Event) -> bool {
+        let changed = self.root.update(event);
+        if let Event::ButtonPressed(_) = event {
+            self.last_input = Instant::now();
+            self.viewed = self.root.page_index();
+        }
+        changed
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        self.root.draw(display)
+    }
+
+    /// The refresh interval of the shown page, shorter if the home page is
+    /// due earlier.
+    fn refresh_interval(&self) -> Duration {
+        let interval = self.root.refresh_interval();
+        match self.home_timeout() {
+            Some(timeout) if self.root.page_index() != 0 && !self.root.captures_input() => {
+                let home_in = (self.last_input + timeout).saturating_duration_since(Instant::now());
+                interval.min(home_in.max(Duration::from_millis(1)))
+            }
+            _ => interval,
+        }
+    }
+
+    fn advance(&mut self) -> bool {
+        self.root.advance()
+    }
+
+    fn captures_input(&self) -> bool {
+        self.root.captures_input()
+    }
+
+    fn page_count(&self) -> usize {
+        self.root.page_count()
+    }
+
+    fn page_index(&self) -> usize {
+        self.root.page_index()
+    }
+
+    fn show_page(&mut self, index: usize) {
+        self.root.show_page(index)
+    }
+
+    /// Remember the viewed page and return to the home page. A page that
+    /// [captures the input](Page::captures_input), e.g. while editing, is
+    /// kept.
+    fn tick(&mut self) {
+        self.root.tick();
+        let idle = self.last_input.elapsed();
+        if self.viewed != self.remembered && idle >= REMEMBER_AFTER {
+            self.remembered = self.viewed;
+            let page = self.viewed as u16;
+            settings::update(|s| s.ui_page = page);
+        }
+        if let Some(timeout) = self.home_timeout()
+            && idle >= timeout
+            && self.root.page_index() != 0
+            && !self.root.captures_input()
+        {
+            self.root.show_page(0);
+        }
+    }
+}
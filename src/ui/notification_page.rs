@@ -0,0 +1,113 @@
+//! Phone notifications shown over another page.
+
+use core::fmt::Write;
+
+use display_interface::DisplayError;
+use embassy_time::{Duration, Instant};
+use embedded_graphics::prelude::*;
+use heapless::String;
+
+use super::assets::{self, CONTENT_TOP, LINE_HEIGHT, StatusBar};
+use super::{Display, Page};
+use crate::ans::NewAlert;
+use crate::events::Event;
+
+/// How long a notification is shown unless dismissed.
+const SHOW_DURATION: Duration = Duration::from_secs(10);
+
+/// Shows the last [phone notification](crate::ans) over `page` for
+/// [`SHOW_DURATION`], a button press dismisses it.
+///
+/// The page keeps receiving all events but the dismissing button press.
+pub struct Notifications<P> {
+    page: P,
+    status_bar: StatusBar,
+    shown: Option<(NewAlert, Instant)>,
+}
+
+impl<P: Page> Notifications<P> {
+    pub fn new(page: P) -> Self {
+        Self {
+            page,
+            status_bar: StatusBar::new(),
+            shown: None,
+        }
+    }
+}
+
+impl<P: Page> Page for Notifications<P> {
+    fn update(&mut self, event: &Event) -> bool {
+        let status_changed = self.status_bar.update(event);
+        match event {
+            Event::PhoneNotification(alert) => {
+                self.shown = Some((*alert, Instant::now()));
+                true
+            }
+            Event::ButtonPressed(_) if self.shown.is_some() => {
+                self.shown = None;
+                true
+            }
+            _ => self.page.update(event) || (self.shown.is_some() && status_changed),
+        }
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        let Some((alert, _)) = &self.shown else {
+            return self.page.draw(display);
+        };
+        self.status_bar.draw(display)?;
+        let mut title: String<24> = String::new();
+        let _ = write!(title, "{}", alert.category.label());
+        if alert.count > 1 {
+            let _ = write!(title, " ({})", alert.count);
+        }
+        assets::draw_text(display, &title, Point::new(0, CONTENT_TOP))?;
+        assets::draw_text(
+            display,
+            alert.text(),
+            Point::new(0, CONTENT_TOP + 2 * LINE_HEIGHT),
+        )
+    }
+
+    /// The refresh interval of the page, shorter if the notification is
+    /// due to be hidden earlier.
+    fn refresh_interval(&self) -> Duration {
+        let interval = self.page.refresh_interval();
+        match self.shown {
+            Some((_, since)) => {
+                let hide_in = (since + SHOW_DURATION).saturating_duration_since(Instant::now());
+                interval.min(hide_in.max(Duration::from_millis(1)))
+            }
+            None => interval,
+        }
+    }
+
+    fn advance(&mut self) -> bool {
+        self.page.advance()
+    }
+
+    fn captures_input(&self) -> bool {
+        self.shown.is_some() || self.page.captures_input()
+    }
+
+    fn page_count(&self) -> usize {
+        self.page.page_count()
+    }
+
+    fn page_index(&self) -> usize {
+        self.page.page_index()
+    }
+
+    fn show_page(&mut self, index: usize) {
+        self.page.show_page(index)
+    }
+
+    fn tick(&mut self) {
+        self.page.tick();
+        if let Some((_, since)) = self.shown
+            && since.elapsed() >= SHOW_DURATION
+        {
+            self.shown = None;
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! SSD1306 and SH1106 OLEDs on I2C.
+//!
+//! [`Oled`] keeps a [`FrameBuffer`] and writes it page by page, the same
+//! way for both controllers. The controller, panel size and rotation are
+//! taken from the [settings](crate::settings::Settings::display_controller)
+//! by [`connect`](super::connect).
+
+use display_interface::DisplayError;
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use embedded_hal::i2c::I2c;
+pub use nrf52_radio_core::oled::{Controller, PanelSize, Rotation};
+use nrf52_radio_core::oled::{
+    FrameBuffer, INIT_LEN, contrast_commands, init_commands, page_address, power_command,
+};
+
+use super::{DIMMED_CONTRAST, Display, FULL_CONTRAST};
+
+/// Control byte before commands.
+const COMMAND: u8 = 0x00;
+
+/// Control byte before display RAM data.
+const DATA: u8 = 0x40;
+
+/// Longest page, of a 128 wide panel.
+const PAGE_LEN: usize = 128;
+
+/// Buffered OLED on I2C.
+pub struct Oled<I> {
+    i2c: I,
+    address: u8,
+    controller: Controller,
+    frame: FrameBuffer,
+}
+
+impl<I: I2c> Oled<I> {
+    pub fn new(
+        i2c: I,
+        address: u8,
+        controller: Controller,
+        size: PanelSize,
+        rotation: Rotation,
+    ) -> Self {
+        Self {
+            i2c,
+            address,
+            controller,
+            frame: FrameBuffer::new(size, rotation),
+        }
+    }
+
+    /// Initialize the controller, clear the display and turn it on.
+    pub fn init(&mut self) -> Result<(), DisplayError> {
+        self.command(&init_commands(self.controller, self.frame.size()))?;
+        self.frame.clear();
+        self.flush_buffer()?;
+        self.command(&[power_command(true)])
+    }
+
+    fn command(&mut self, commands: &[u8]) -> Result<(), DisplayError> {
+        let mut buf = [COMMAND; 1 + INIT_LEN];
+        buf[1..=commands.len()].copy_from_slice(commands);
+        self.i2c
+            .write(self.address, &buf[..=commands.len()])
+            .map_err(|_| DisplayError::BusWriteError)
+    }
+}
+
+impl<I> OriginDimensions for Oled<I> {
+    fn size(&self) -> Size {
+        let (width, height) = self.frame.dimensions();
+        Size::new(width as u32, height as u32)
+    }
+}
+
+impl<I> DrawTarget for Oled<I> {
+    type Color = BinaryColor;
+    type Error = DisplayError;
+
+    fn draw_iter<P>(&mut self, pixels: P) -> Result<(), Self::Error>
+    where
+        P: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let (Ok(x), Ok(y)) = (usize::try_from(point.x), usize::try_from(point.y)) {
+                self.frame.set_pixel(x, y, color.is_on());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I: I2c> Display for Oled<I> {
+    fn clear_buffer(&mut self) {
+        self.frame.clear();
+    }
+
+    fn flush_buffer(&mut self) -> Result<(), DisplayError> {
+        let size = self.frame.size();
+        for page in 0..size.pages() {
+            self.command(&page_address(self.controller, size, page as u8))?;
+            let bytes = self.frame.page(page);
+            let mut buf = [DATA; 1 + PAGE_LEN];
+            buf[1..=bytes.len()].copy_from_slice(bytes);
+            self.i2c
+                .write(self.address, &buf[..=bytes.len()])
+                .map_err(|_| DisplayError::BusWriteError)?;
+        }
+        Ok(())
+    }
+
+    fn set_power(&mut self, on: bool) -> Result<(), DisplayError> {
+        self.command(&[power_command(on)])
+    }
+
+    fn set_dimmed(&mut self, dimmed: bool) -> Result<(), DisplayError> {
+        self.command(&contrast_commands(if dimmed {
+            DIMMED_CONTRAST
+        } else {
+            FULL_CONTRAST
+        }))
+    }
+
+    fn set_rotation(&mut self, rotation: Rotation) {
+        self.frame.set_rotation(rotation);
+    }
+}
@@ -0,0 +1,131 @@
+//! Display power saving.
+//!
+//! The display is dimmed and then turned off when there was no user input
+//! for the time configured in the [settings](crate::settings). A button
+//! press wakes it up again. While the battery is low, the display is turned
+//! off already after the dim timeout. While it's
+//! [suspended](PowerSave::set_suspended), e.g. at
+//! [`PowerLevel::Critical`](crate::policy::PowerLevel::Critical), it stays
+//! off regardless of input.
+
+use display_interface::DisplayError;
+use embassy_time::{Duration, Instant};
+
+use super::Display;
+use crate::settings::Settings;
+
+/// Power state of the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Power {
+    On,
+    Dimmed,
+    Off,
+}
+
+/// Tracks user input and dims or turns off the display.
+pub struct PowerSave {
+    last_input: Instant,
+    power: Power,
+    low_battery: bool,
+    suspended: bool,
+}
+
+impl Default for PowerSave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PowerSave {
+    pub fn new() -> Self {
+        Self {
+            last_input: Instant::now(),
+            power: Power::On,
+            low_battery: false,
+            suspended: false,
+        }
+    }
+
+    pub fn power(&self) -> Power {
+        self.power
+    }
+
+    /// Save more power while the battery is low.
+    pub fn set_low_battery(&mut self, low: bool) {
+        self.low_battery = low;
+    }
+
+    /// Keep the display off, even on input, until it's resumed. Applied by
+    /// the next [`poll`](Self::poll).
+    pub fn set_suspended(&mut self, suspended: bool) {
+        self.suspended = suspended;
+    }
+
+    /// Seconds without input after which the display is turned off (0: never).
+    fn off_secs(&self, settings: &Settings) -> u16 {
+        if self.low_battery && settings.display_dim_secs != 0 {
+            settings.display_dim_secs
+        } else {
+            settings.display_off_secs
+        }
+    }
+
+    /// Power state the display should be in after `idle` without input.
+    fn target(&self, settings: &Settings, idle: Duration) -> Power {
+        let after = |secs: u16| secs != 0 && idle >= Duration::from_secs(secs.into());
+        if self.suspended || after(self.off_secs(settings)) {
+            Power::Off
+        } else if after(settings.display_dim_secs) {
+            Power::Dimmed
+        } else {
+            Power::On
+        }
+    }
+
+    /// Time of the next power state change, if any.
+    pub fn deadline(&self, settings: &Settings) -> Option<Instant> {
+        let timeout = match self.power {
+            Power::On => settings.display_dim_secs,
+            Power::Dimmed => self.off_secs(settings),
+            Power::Off => 0,
+        };
+        (timeout != 0).then(|| self.last_input + Duration::from_secs(timeout.into()))
+    }
+
+    /// Register user input and wake up the display.
+    ///
+    /// Returns the power state before the input.
+    pub fn input<D: Display>(&mut self, display: &mut D) -> Result<Power, DisplayError> {
+        self.last_input = Instant::now();
+        let before = self.power;
+        let power = if self.suspended {
+            Power::Off
+        } else {
+            Power::On
+        };
+        self.set(display, power)?;
+        Ok(before)
+    }
+
+    /// Dim or turn off the display if there was no input for long enough.
+    pub fn poll<D: Display>(
+        &mut self,
+        display: &mut D,
+        settings: &Settings,
+    ) -> Result<Power, DisplayError> {
+        let target = self.target(settings, self.last_input.elapsed());
+        self.set(display, target)?;
+        Ok(self.power)
+    }
+
+    fn set<D: Display>(&mut self, display: &mut D, power: Power) -> Result<(), DisplayError> {
+        if power == self.power {
+            return Ok(());
+        }
+        crate::log!(Ui, debug, "[ui] display {:?}", power);
+        display.set_power(power != Power::Off)?;
+        display.set_dimmed(power == Power::Dimmed)?;
+        self.power = power;
+        Ok(())
+    }
+}
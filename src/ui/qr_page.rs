@@ -0,0 +1,102 @@
+//! Page showing a QR code of the device, e.g. its BLE address.
+//!
+//! Scanning it with a phone connects to exactly this unit in a pile of
+//! identical boards. The code is drawn as dark modules on a lit square, with
+//! a quiet zone as wide as the display allows, and scaled to whole pixels.
+
+use display_interface::DisplayError;
+use embassy_time::Duration;
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+use heapless::String;
+pub use nrf52_radio_core::qr::{Ecc, QrCode, QrError};
+
+use super::assets::{self, LINE_HEIGHT};
+use super::{Display, Page};
+use crate::events::Event;
+use crate::fmt;
+
+/// Widest quiet zone around the code, in modules.
+const QUIET_ZONE: u32 = 4;
+
+/// Gap between the code and the caption.
+const CAPTION_GAP: i32 = 4;
+
+/// A QR code with a caption next to it.
+pub struct QrPage {
+    code: Result<QrCode, QrError>,
+    caption: [&'static str; 2],
+}
+
+impl QrPage {
+    /// Code of `text`, at level M if it fits, otherwise L.
+    pub fn new(text: &str, caption: [&'static str; 2]) -> Self {
+        let code = QrCode::encode(text.as_bytes(), Ecc::Medium)
+            .or_else(|_| QrCode::encode(text.as_bytes(), Ecc::Low));
+        if let Err(e) = code {
+            defmt::warn!("[ui] no QR code of {=str}: {:?}", text, e);
+        }
+        Self { code, caption }
+    }
+
+    /// Code of the BLE address, as shown by scanner apps.
+    pub fn address(address: &[u8; 6]) -> Self {
+        let text: String<{ fmt::MAC_LEN }> = fmt::mac(address);
+        Self::new(&text, ["Scan to", "connect"])
+    }
+}
+
+impl Page for QrPage {
+    fn update(&mut self, _event: &Event) -> bool {
+        false
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        let Ok(code) = &self.code else {
+            return assets::draw_text(display, "QR: too long", Point::zero());
+        };
+        let bounds = display.bounding_box().size;
+        let side = bounds.width.min(bounds.height);
+        let size = code.size() as u32;
+        // At least one module of quiet zone, then as large as possible.
+        let scale = (side / (size + 2)).max(1);
+        let quiet = ((side / scale).saturating_sub(size) / 2).min(QUIET_ZONE);
+        let total = (size + 2 * quiet) * scale;
+        let top_left = Point::new(0, (bounds.height.saturating_sub(total) / 2) as i32);
+
+        Rectangle::new(top_left, Size::new_equal(total))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(display)?;
+        let origin = top_left + Point::new_equal((quiet * scale) as i32);
+        let module = PrimitiveStyle::with_fill(BinaryColor::Off);
+        for y in 0..code.size() {
+            for x in (0..code.size()).filter(|&x| code.get(x, y)) {
+                let at = Point::new((x as u32 * scale) as i32, (y as u32 * scale) as i32);
+                Rectangle::new(origin + at, Size::new_equal(scale))
+                    .into_styled(module)
+                    .draw(display)?;
+            }
+        }
+
+        let left = total as i32 + CAPTION_GAP;
+        if left < bounds.width as i32 {
+            let top = bounds.height as i32 / 2 - LINE_HEIGHT;
+            for (i, line) in self.caption.iter().enumerate() {
+                assets::draw_text(
+                    display,
+                    line,
+                    Point::new(left, top + i as i32 * LINE_HEIGHT),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Nothing changes, the code stays as drawn.
+    fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+}
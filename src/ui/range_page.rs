@@ -0,0 +1,74 @@
+//! Page showing the results of the range test.
+
+use core::fmt::Write;
+
+use display_interface::DisplayError;
+use embedded_graphics::prelude::*;
+use heapless::String;
+
+use super::assets::{self, CONTENT_TOP, LINE_HEIGHT};
+use super::{Display, Page};
+use crate::events::Event;
+use crate::range::Window;
+
+/// Frames received, packet error rate and RSSI of the last window.
+#[derive(Default)]
+pub struct RangePage {
+    window: Option<Window>,
+}
+
+impl RangePage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show the statistics of a finished window.
+    pub fn set_window(&mut self, window: Window) {
+        self.window = Some(window);
+    }
+}
+
+impl Page for RangePage {
+    /// The page is updated by [`set_window`](Self::set_window) only.
+    fn update(&mut self, _event: &Event) -> bool {
+        false
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        let mut lines: [String<24>; 3] = Default::default();
+        let _ = write!(lines[0], "Range test");
+        let mut mean = None;
+        match self.window {
+            None => {
+                let _ = write!(lines[1], "waiting...");
+            }
+            Some(window) => {
+                let _ = write!(lines[1], "RX {}/{}", window.received, window.expected);
+                match window.per_permille() {
+                    Some(per) => {
+                        let _ = write!(lines[2], "PER {}.{}%", per / 10, per % 10);
+                    }
+                    None => {
+                        let _ = write!(lines[2], "PER -");
+                    }
+                }
+                if let Some((rssi, min, max)) = window.rssi {
+                    let _ = write!(lines[1], "  {}..{}", min, max);
+                    mean = Some(rssi);
+                }
+            }
+        }
+        for (i, line) in lines.iter().enumerate().filter(|(_, l)| !l.is_empty()) {
+            assets::draw_text(display, line, Point::new(0, i as i32 * LINE_HEIGHT))?;
+        }
+
+        if let Some(rssi) = mean {
+            let top = CONTENT_TOP + 3 * LINE_HEIGHT;
+            let mut value: String<8> = String::new();
+            let _ = write!(value, "{:4}", rssi);
+            assets::draw_large_text(display, &value, Point::new(0, top))?;
+            assets::draw_text(display, "dBm", Point::new(46, top + 8))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+//! Page editing settings on the device, without a phone.
+//!
+//! A short press selects the next setting, after the last one a
+//! [`Carousel`](super::Carousel) shows its next page. A long press starts
+//! editing the setting with a [`NumberEditor`] or [`TextEditor`]: short
+//! presses step through the digits or characters, long presses select, a
+//! double press cancels. The value is written with [`settings::update`] once
+//! it's confirmed. While the page is shown it
+//! [captures](Page::captures_input) long and double presses.
+
+use core::fmt::Write;
+
+use display_interface::DisplayError;
+use embedded_graphics::prelude::*;
+use heapless::String;
+pub use nrf52_radio_core::editor::{Choice, Input, NumberEditor, Outcome, TextEditor};
+
+use super::assets::{self, LINE_HEIGHT};
+use super::{Display, Page};
+use crate::events::{ButtonPress, Event};
+use crate::settings::{self, ADV_INTERVAL_MAX_MS, ADV_INTERVAL_MIN_MS, DEVICE_NAME_LEN};
+
+/// Width of a [`SMALL_FONT`](assets::SMALL_FONT) character.
+const CHAR_WIDTH: i32 = 6;
+
+/// A setting editable on the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Item {
+    DeviceName,
+    AdvInterval,
+    GeofenceRadius,
+}
+
+impl Item {
+    pub const ALL: [Self; 3] = [Self::DeviceName, Self::AdvInterval, Self::GeofenceRadius];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::DeviceName => "Name",
+            Self::AdvInterval => "Adv ms",
+            Self::GeofenceRadius => "Fence m",
+        }
+    }
+
+    /// The current value as text.
+    fn value(self, out: &mut String<24>) {
+        let settings = settings::get();
+        let _ = match self {
+            Self::DeviceName => match settings.device_name_str() {
+                "" => write!(out, "(default)"),
+                name => write!(out, "{}", name),
+            },
+            Self::AdvInterval => write!(out, "{}", settings.adv_interval_ms),
+            Self::GeofenceRadius => match settings.geofence_radius_m {
+                0 => write!(out, "off"),
+                radius => write!(out, "{}", radius),
+            },
+        };
+    }
+
+    fn editor(self) -> Editor {
+        let settings = settings::get();
+        match self {
+            Self::DeviceName => Editor::Text(TextEditor::new(settings.device_name_str())),
+            Self::AdvInterval => Editor::Number(NumberEditor::new(
+                settings.adv_interval_ms.into(),
+                ADV_INTERVAL_MIN_MS.into(),
+                ADV_INTERVAL_MAX_MS.into(),
+            )),
+            Self::GeofenceRadius => Editor::Number(NumberEditor::new(
+                settings.geofence_radius_m.into(),
+                0,
+                u16::MAX.into(),
+            )),
+        }
+    }
+
+    /// Store the confirmed value.
+    fn save(self, editor: &Editor) {
+        match (self, editor) {
+            (Self::DeviceName, Editor::Text(text)) => {
+                settings::update(|s| {
+                    s.set_device_name(text.text());
+                });
+            }
+            (Self::AdvInterval, Editor::Number(number)) => {
+                settings::update(|s| s.adv_interval_ms = number.value() as u16)
+            }
+            (Self::GeofenceRadius, Editor::Number(number)) => {
+                settings::update(|s| s.geofence_radius_m = number.value() as u16)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Editor of the selected setting.
+enum Editor {
+    Number(NumberEditor),
+    Text(TextEditor<DEVICE_NAME_LEN>),
+}
+
+impl Editor {
+    fn input(&mut self, input: Input) -> Outcome {
+        match self {
+            Self::Number(number) => number.input(input),
+            Self::Text(text) => text.input(input),
+        }
+    }
+
+    /// Draw the value at `top`, the current digit or choice marked below.
+    fn draw<D: Display>(&self, display: &mut D, top: i32) -> Result<(), DisplayError> {
+        let mut line: String<24> = String::new();
+        let (marker, at) = match self {
+            Self::Number(number) => {
+                for digit in number.digits() {
+                    let _ = write!(line, "{}", digit);
+                }
+                ("^", number.cursor())
+            }
+            Self::Text(text) => {
+                let _ = write!(line, "{}", text.text());
+                let marker = match text.choice() {
+                    Choice::Char(c) => {
+                        let _ = line.push(c);
+                        "^"
+                    }
+                    Choice::Delete => "^ DEL",
+                    Choice::Done => "^ OK",
+                };
+                (marker, text.text().len())
+            }
+        };
+        assets::draw_text(display, &line, Point::new(0, top))?;
+        assets::draw_text(
+            display,
+            marker,
+            Point::new(at as i32 * CHAR_WIDTH, top + LINE_HEIGHT),
+        )
+    }
+}
+
+/// List of the editable settings, one of them possibly being edited.
+pub struct SettingsPage {
+    selected: usize,
+    editor: Option<Editor>,
+}
+
+impl Default for SettingsPage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SettingsPage {
+    pub fn new() -> Self {
+        Self {
+            selected: 0,
+            editor: None,
+        }
+    }
+
+    fn item(&self) -> Item {
+        Item::ALL[self.selected]
+    }
+
+    fn input(&mut self, input: Input) {
+        let item = self.item();
+        let Some(editor) = &mut self.editor else {
+            match input {
+                Input::Next => self.selected = (self.selected + 1) % Item::ALL.len(),
+                Input::Select => self.editor = Some(item.editor()),
+                Input::Cancel => {}
+            }
+            return;
+        };
+        match editor.input(input) {
+            Outcome::Editing => {}
+            Outcome::Done => {
+                item.save(editor);
+                self.editor = None;
+            }
+            Outcome::Cancelled => self.editor = None,
+        }
+    }
+}
+
+impl Page for SettingsPage {
+    fn update(&mut self, event: &Event) -> bool {
+        let input = match event {
+            Event::ButtonPressed(ButtonPress::Short) => {
+                self.advance();
+                return true;
+            }
+            Event::ButtonPressed(ButtonPress::Long) => Input::Select,
+            Event::ButtonPressed(ButtonPress::Double) => Input::Cancel,
+            Event::SettingsChanged => return self.editor.is_none(),
+            _ => return false,
+        };
+        self.input(input);
+        true
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        if let Some(editor) = &self.editor {
+            assets::draw_text(display, self.item().label(), Point::zero())?;
+            return editor.draw(display, LINE_HEIGHT + 2);
+        }
+        for (i, item) in Item::ALL.iter().enumerate() {
+            let mut line: String<24> = String::new();
+            let marker = if i == self.selected { '>' } else { ' ' };
+            let _ = write!(line, "{}{:<8}", marker, item.label());
+            item.value(&mut line);
+            assets::draw_text(display, &line, Point::new(0, i as i32 * LINE_HEIGHT))?;
+        }
+        Ok(())
+    }
+
+    /// Select the next setting, or step the editor.
+    fn advance(&mut self) -> bool {
+        if self.editor.is_none() && self.selected + 1 == Item::ALL.len() {
+            self.selected = 0;
+            return false;
+        }
+        self.input(Input::Next);
+        true
+    }
+
+    fn captures_input(&self) -> bool {
+        true
+    }
+}
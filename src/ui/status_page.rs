@@ -0,0 +1,80 @@
+//! Home page with the device state at a glance.
+
+use core::fmt::Write;
+
+use display_interface::DisplayError;
+use embassy_time::Instant;
+use embedded_graphics::prelude::*;
+use heapless::String;
+
+use super::assets::{self, CONTENT_TOP, LINE_HEIGHT, StatusBar};
+use super::{Display, Page};
+use crate::battery::ChargeState;
+use crate::events::Event;
+use crate::settings;
+
+/// Device name, battery and uptime.
+#[derive(Default)]
+pub struct StatusPage {
+    status_bar: StatusBar,
+    battery: Option<(u8, u16)>,
+    charge: Option<ChargeState>,
+}
+
+impl StatusPage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Page for StatusPage {
+    fn update(&mut self, event: &Event) -> bool {
+        let status_changed = self.status_bar.update(event);
+        match event {
+            Event::BatteryUpdated {
+                percent,
+                millivolts,
+            } => self.battery = Some((*percent, *millivolts)),
+            Event::ChargeStateChanged(state) => self.charge = Some(*state),
+            Event::SettingsChanged => {}
+            _ => return status_changed,
+        }
+        true
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        self.status_bar.draw(display)?;
+
+        let mut lines: [String<24>; 3] = Default::default();
+        let settings = settings::get();
+        let _ = match settings.device_name_str() {
+            "" => write!(lines[0], "Status"),
+            name => write!(lines[0], "{}", name),
+        };
+        let _ = match self.battery {
+            Some((percent, millivolts)) => write!(lines[1], "BAT {}% {} mV", percent, millivolts),
+            None => write!(lines[1], "BAT -"),
+        };
+        let _ = match self.charge {
+            Some(ChargeState::Charging) => write!(lines[1], " CHG"),
+            Some(ChargeState::Charged) => write!(lines[1], " FULL"),
+            _ => Ok(()),
+        };
+        let uptime = Instant::now().as_secs();
+        let _ = write!(
+            lines[2],
+            "UP {}d {:02}:{:02}",
+            uptime / 86_400,
+            uptime / 3600 % 24,
+            uptime / 60 % 60
+        );
+        for (i, line) in lines.iter().enumerate() {
+            assets::draw_text(
+                display,
+                line,
+                Point::new(0, CONTENT_TOP + i as i32 * LINE_HEIGHT),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+//! Page pointing to the [waypoint](crate::waypoint).
+
+use core::fmt::Write;
+
+use display_interface::DisplayError;
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Circle, PrimitiveStyle, Triangle},
+};
+use heapless::String;
+use libm::{cosf, sinf};
+
+use super::assets::{self, CONTENT_TOP, LINE_HEIGHT, StatusBar};
+use super::{Display, Page};
+use crate::events::Event;
+use crate::gnss::Fix;
+use crate::nav::relative_bearing;
+use crate::waypoint::{self, Waypoint};
+
+/// Distance below which the waypoint counts as reached, in m.
+const ARRIVAL_RADIUS_M: f64 = 10.0;
+
+/// Radius of the compass rose around the arrow.
+const ROSE_RADIUS: i32 = 24;
+
+/// Center of the compass rose.
+const ROSE_CENTER: Point = Point::new(ROSE_RADIUS, CONTENT_TOP + ROSE_RADIUS);
+
+/// Left edge of the text next to the compass rose.
+const TEXT_LEFT: i32 = 2 * ROSE_RADIUS + 6;
+
+/// Arrow to the waypoint and the distance to it.
+///
+/// With a [compass](crate::compass) or while moving the arrow is relative
+/// to the heading or the course over ground, i.e. up is straight ahead,
+/// otherwise up is north.
+pub struct WaypointPage {
+    status_bar: StatusBar,
+    waypoint: Option<Waypoint>,
+    fix: Option<Fix>,
+    course: Option<f32>,
+    heading: Option<f32>,
+}
+
+impl Default for WaypointPage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaypointPage {
+    pub fn new() -> Self {
+        Self {
+            status_bar: StatusBar::new(),
+            waypoint: waypoint::get(),
+            fix: None,
+            course: None,
+            heading: None,
+        }
+    }
+}
+
+/// Draw a filled arrow pointing `degrees` clockwise from up.
+fn draw_arrow<D: Display>(display: &mut D, degrees: f32) -> Result<(), DisplayError> {
+    let point = |degrees: f32, radius: f32| {
+        let rad = degrees.to_radians();
+        ROSE_CENTER + Point::new((sinf(rad) * radius) as i32, (-cosf(rad) * radius) as i32)
+    };
+    let radius = (ROSE_RADIUS - 4) as f32;
+    Circle::with_center(ROSE_CENTER, 2 * ROSE_RADIUS as u32)
+        .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+        .draw(display)?;
+    Triangle::new(
+        point(degrees, radius),
+        point(degrees + 150.0, radius * 0.7),
+        point(degrees - 150.0, radius * 0.7),
+    )
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+    .draw(display)
+}
+
+impl Page for WaypointPage {
+    fn update(&mut self, event: &Event) -> bool {
+        let status_changed = self.status_bar.update(event);
+        match event {
+            Event::WaypointChanged(waypoint) => self.waypoint = *waypoint,
+            Event::FixAcquired(fix) => self.fix = Some(*fix),
+            Event::FixLost | Event::GnssAbsent => self.fix = None,
+            Event::MotionUpdated { course, .. } => self.course = *course,
+            Event::HeadingUpdated(heading) => self.heading = Some(*heading),
+            _ => return status_changed,
+        }
+        true
+    }
+
+    fn draw<D: Display>(&self, display: &mut D) -> Result<(), DisplayError> {
+        self.status_bar.draw(display)?;
+
+        let (Some(waypoint), Some(fix)) = (self.waypoint, self.fix) else {
+            let text = if self.waypoint.is_none() {
+                "NO WAYPOINT"
+            } else {
+                "WPT  NO FIX"
+            };
+            return assets::draw_text(display, text, Point::new(0, CONTENT_TOP));
+        };
+        let (distance, bearing) = waypoint.course_from(fix.latitude, fix.longitude);
+        if distance < ARRIVAL_RADIUS_M {
+            return assets::draw_large_text(display, "ARRIVED", Point::new(0, CONTENT_TOP));
+        }
+
+        let (heading, mode) = match (self.heading, self.course) {
+            (Some(heading), _) => (heading, "MAG UP"),
+            (None, Some(course)) => (course, "COG UP"),
+            (None, None) => (0.0, "N UP"),
+        };
+        draw_arrow(display, relative_bearing(bearing as f32, heading))?;
+
+        let mut value: String<8> = String::new();
+        let _ = match distance {
+            d if d < 1000.0 => write!(value, "{:.0}m", d),
+            d if d < 10_000.0 => write!(value, "{:.2}km", d / 1000.0),
+            d => write!(value, "{:.1}km", d / 1000.0),
+        };
+        assets::draw_large_text(display, &value, Point::new(TEXT_LEFT, CONTENT_TOP))?;
+
+        let mut line: String<16> = String::new();
+        let _ = write!(line, "BRG {:03.0}", bearing);
+        assets::draw_text(display, &line, Point::new(TEXT_LEFT, CONTENT_TOP + 24))?;
+        assets::draw_text(
+            display,
+            mode,
+            Point::new(TEXT_LEFT, CONTENT_TOP + 24 + LINE_HEIGHT),
+        )
+    }
+}
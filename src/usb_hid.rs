@@ -0,0 +1,190 @@
+//! USB keyboard typing the current position into a computer.
+//!
+//! With the `usb-hid` feature the device enumerates as a keyboard while
+//! connected to a computer. A short [button press](ButtonPress::Short)
+//! types the last fix as [coordinates](crate::fmt::coordinates), or the
+//! [keyboard text](crate::settings::Settings::keyboard_text) if one is set
+//! with the `keyboard` shell [`COMMANDS`]. Characters not on a US keyboard
+//! are skipped.
+//!
+//! The POWER interrupt belongs to the MPSL, so the USB driver doesn't
+//! detect VBUS itself, it is taken from [`Event::ChargeStateChanged`].
+
+use core::fmt::Write;
+
+use defmt::{info, warn};
+use embassy_futures::join::join;
+use embassy_nrf::peripherals::{self, USBD};
+use embassy_nrf::usb::vbus_detect::SoftwareVbusDetect;
+use embassy_nrf::usb::{self, Driver};
+use embassy_nrf::{Peri, bind_interrupts};
+use embassy_time::{Duration, with_timeout};
+use embassy_usb::Builder;
+use embassy_usb::class::hid::{self, HidBootProtocol, HidSubclass, HidWriter, State};
+use embassy_usb::driver::EndpointError;
+use heapless::String;
+pub use nrf52_radio_core::hid::key;
+use static_cell::StaticCell;
+use usbd_hid::descriptor::{KeyboardReport, SerializedDescriptor};
+
+use crate::battery::ChargeState;
+use crate::events::{self, ButtonPress, Event};
+use crate::fmt;
+use crate::gnss::Fix;
+use crate::settings::{self, KEYBOARD_TEXT_LEN};
+use crate::shell::{Command, Output};
+
+bind_interrupts!(struct Irqs {
+    USBD => usb::InterruptHandler<peripherals::USBD>;
+});
+
+/// Vendor ID of pid.codes.
+const VID: u16 = 0x1209;
+
+/// Product ID, a pid.codes test PID.
+const PID: u16 = 0x0001;
+
+/// Time the computer has to configure the keyboard before typing is given
+/// up, e.g. while only charging.
+const CONFIGURE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Length of a boot keyboard report.
+const REPORT_LEN: usize = 8;
+
+static VBUS: StaticCell<SoftwareVbusDetect> = StaticCell::new();
+
+/// Run the keyboard on `usbd`, never returns.
+pub async fn run(usbd: Peri<'static, USBD>) {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[usb_hid] no event bus subscriber available");
+        return;
+    };
+    let vbus = &*VBUS.init(SoftwareVbusDetect::new(false, false));
+    let mut config = embassy_usb::Config::new(VID, PID);
+    config.manufacturer = Some("nrf52-radio-rs");
+    config.product = Some("GNSS keyboard");
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    let mut config_descriptor = [0; 128];
+    let mut bos_descriptor = [0; 16];
+    let mut control_buf = [0; 64];
+    let mut state = State::new();
+    let mut builder = Builder::new(
+        Driver::new(usbd, Irqs, vbus),
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [],
+        &mut control_buf,
+    );
+    let mut keyboard = HidWriter::<_, REPORT_LEN>::new(
+        &mut builder,
+        &mut state,
+        hid::Config {
+            report_descriptor: KeyboardReport::desc(),
+            request_handler: None,
+            poll_ms: 10,
+            max_packet_size: REPORT_LEN as u16,
+            hid_subclass: HidSubclass::Boot,
+            hid_boot_protocol: HidBootProtocol::Keyboard,
+        },
+    );
+    let mut device = builder.build();
+
+    let mut fix = None;
+    let typing = async {
+        loop {
+            match events.next_message_pure().await {
+                Event::FixAcquired(f) => fix = Some(f),
+                Event::ChargeStateChanged(state) => {
+                    let powered = matches!(state, ChargeState::Charging | ChargeState::Charged);
+                    vbus.detected(powered);
+                    if powered {
+                        vbus.ready();
+                    }
+                }
+                Event::ButtonPressed(ButtonPress::Short) => {
+                    if with_timeout(CONFIGURE_TIMEOUT, keyboard.ready())
+                        .await
+                        .is_err()
+                    {
+                        info!("[usb_hid] not connected to a computer");
+                        continue;
+                    }
+                    let text = text(fix.as_ref());
+                    match type_text(&mut keyboard, &text).await {
+                        Ok(()) => info!("[usb_hid] typed '{}'", text.as_str()),
+                        Err(e) => warn!("[usb_hid] couldn't type: {:?}", e),
+                    }
+                }
+                _ => {}
+            }
+        }
+    };
+    join(device.run(), typing).await;
+}
+
+/// The configured keyboard text, else the coordinates of `fix`.
+fn text(fix: Option<&Fix>) -> String<KEYBOARD_TEXT_LEN> {
+    let settings = settings::get();
+    let mut text = String::new();
+    // Both fit KEYBOARD_TEXT_LEN.
+    match (settings.keyboard_str(), fix) {
+        ("", Some(fix)) => {
+            let _ = text.push_str(&fmt::coordinates(fix.latitude, fix.longitude));
+        }
+        (configured, _) => {
+            let _ = text.push_str(configured);
+        }
+    }
+    text
+}
+
+/// Press and release the key of every character of `text`.
+async fn type_text<'d, D: embassy_usb::driver::Driver<'d>>(
+    keyboard: &mut HidWriter<'d, D, REPORT_LEN>,
+    text: &str,
+) -> Result<(), EndpointError> {
+    for (modifier, keycode) in text.chars().filter_map(key) {
+        let mut report = KeyboardReport {
+            modifier,
+            reserved: 0,
+            leds: 0,
+            keycodes: [keycode, 0, 0, 0, 0, 0],
+        };
+        keyboard.write_serialize(&report).await?;
+        // Released after every key, so repeated characters are typed.
+        report.modifier = 0;
+        report.keycodes = [0; 6];
+        keyboard.write_serialize(&report).await?;
+    }
+    Ok(())
+}
+
+fn keyboard_command(args: &str, out: &mut Output) -> core::fmt::Result {
+    let args = args.trim();
+    if args.is_empty() {
+        let settings = settings::get();
+        return match settings.keyboard_str() {
+            "" => write!(out, "typing the coordinates\r\n"),
+            text => write!(out, "typing '{}'\r\n", text),
+        };
+    }
+    let text = if args == "clear" { "" } else { args };
+    if text.len() > KEYBOARD_TEXT_LEN {
+        return write!(out, "at most {} bytes\r\n", KEYBOARD_TEXT_LEN);
+    }
+    settings::update(|s| {
+        s.keyboard_text = [0; KEYBOARD_TEXT_LEN];
+        s.keyboard_text[..text.len()].copy_from_slice(text.as_bytes());
+    });
+    Ok(())
+}
+
+/// Shell command to set the text typed instead of the coordinates.
+pub const COMMANDS: &[Command] = &[Command {
+    name: "keyboard",
+    help: "text typed by the USB keyboard: keyboard [<text>|clear]",
+    handler: keyboard_command,
+}];
@@ -0,0 +1,67 @@
+//! Course-to-waypoint navigation.
+//!
+//! A target set with [`set`], e.g. over BLE, is published as
+//! [`Event::WaypointChanged`], the [`WaypointPage`](crate::ui::WaypointPage)
+//! shows the bearing and distance to it from the current fix. The target is
+//! kept in RAM only.
+//!
+//! With a [geofence radius](crate::settings::Settings::geofence_radius_m)
+//! configured, [`watch_geofence`] publishes [`Event::FenceExited`] when a fix
+//! is farther than that from the target, once until it's back inside.
+
+use core::cell::Cell;
+
+use defmt::{info, warn};
+use embassy_sync::{blocking_mutex::Mutex, blocking_mutex::raw::CriticalSectionRawMutex};
+pub use nrf52_radio_core::nav::Waypoint;
+
+use crate::events::{self, Event};
+use crate::settings;
+
+static WAYPOINT: Mutex<CriticalSectionRawMutex, Cell<Option<Waypoint>>> =
+    Mutex::new(Cell::new(None));
+
+/// The current target.
+pub fn get() -> Option<Waypoint> {
+    WAYPOINT.lock(|w| w.get())
+}
+
+/// Set or clear the target.
+pub fn set(waypoint: Option<Waypoint>) {
+    WAYPOINT.lock(|w| w.set(waypoint));
+    info!("[waypoint] target {:?}", waypoint);
+    events::publish(Event::WaypointChanged(waypoint));
+}
+
+/// Publish [`Event::FenceExited`] when a fix leaves the geofence around the
+/// target.
+pub async fn watch_geofence() {
+    let Ok(mut events) = events::subscribe() else {
+        warn!("[waypoint] no event bus subscriber available");
+        return;
+    };
+    let mut outside = false;
+    loop {
+        match events.next_message_pure().await {
+            Event::FixAcquired(fix) => {
+                let radius = settings::get().geofence_radius_m;
+                let Some(waypoint) = get().filter(|_| radius != 0) else {
+                    outside = false;
+                    continue;
+                };
+                let (distance, _) = waypoint.course_from(fix.latitude, fix.longitude);
+                let exited = distance > f64::from(radius);
+                if exited && !outside {
+                    info!(
+                        "[waypoint] left the geofence, {} m from the target",
+                        distance
+                    );
+                    events::publish(Event::FenceExited);
+                }
+                outside = exited;
+            }
+            Event::WaypointChanged(_) => outside = false,
+            _ => {}
+        }
+    }
+}
@@ -7,10 +7,922 @@ use nrf52_radio_rs as _; // memory layout + panic handler
 // feature)
 #[defmt_test::tests]
 mod tests {
-    use defmt::assert;
+    use bytemuck::Zeroable;
+    #[cfg(feature = "cryptocell")]
+    use core::sync::atomic::AtomicBool;
+    use core::sync::atomic::{Ordering, compiler_fence};
+    use defmt::{assert, assert_eq, assert_ne};
+    use embassy_embedded_hal::adapter::BlockingAsync;
+    use embassy_futures::block_on;
+    use embassy_nrf::pac;
+    use embedded_storage::nor_flash::{
+        ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash, check_erase, check_read, check_write,
+    };
+    use nrf52_radio_rs::{
+        bsp::{
+            ble::{
+                adv::{self, Ad},
+                long,
+            },
+            flash::REPLAY,
+        },
+        crypto::{
+            BLOCK_LEN, BeaconCipher, BlockCipher, Ccm, CryptoError,
+            eid::{EDDYSTONE_UUID, EidGenerator},
+            signed::{SIGNATURE_LEN, Signed, SignedError},
+        },
+        factory_reset,
+        gnss::{config::Command, line_reader::RingBuffer},
+        mesh::{self, AppKey, Network, Node},
+        recovery::{Backoff, DIAGNOSTICS, MAX_CONSECUTIVE_FAILURES},
+        replay,
+        settings::{self, BLOB_LEN, ImportError, Settings, Store},
+        telemetry::{Sample, TelemetryQueue},
+        track::{Export, Point, TrackLog, name_str},
+    };
+    use trouble_host::prelude::*;
+    #[cfg(feature = "cryptocell")]
+    use {
+        ed25519_dalek::{Signature, VerifyingKey},
+        nrf52_radio_core::sha256,
+        nrf52_radio_rs::crypto::hw,
+        p256::elliptic_curve::sec1::ToEncodedPoint,
+    };
+
+    /// NOR flash in RAM: erasing sets all bits, writing can only clear bits.
+    struct RamFlash<const N: usize> {
+        data: [u8; N],
+    }
+
+    impl<const N: usize> RamFlash<N> {
+        fn new() -> Self {
+            Self { data: [0xFF; N] }
+        }
+    }
+
+    impl<const N: usize> ErrorType for RamFlash<N> {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl<const N: usize> ReadNorFlash for RamFlash<N> {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            check_read(self, offset, bytes.len())?;
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            N
+        }
+    }
+
+    impl<const N: usize> NorFlash for RamFlash<N> {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 4096;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            check_erase(self, from, to)?;
+            self.data[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            check_write(self, offset, bytes.len())?;
+            let offset = offset as usize;
+            for (d, b) in self.data[offset..offset + bytes.len()]
+                .iter_mut()
+                .zip(bytes)
+            {
+                *d &= b;
+            }
+            Ok(())
+        }
+    }
+
+    /// Not AES, but a deterministic keyed block function, enough to test the
+    /// CCM and EID constructions without the Softdevice Controller.
+    struct TestCipher([u8; BLOCK_LEN]);
+
+    impl From<[u8; BLOCK_LEN]> for TestCipher {
+        fn from(key: [u8; BLOCK_LEN]) -> Self {
+            Self(key)
+        }
+    }
+
+    impl BlockCipher for TestCipher {
+        fn encrypt_block(&self, block: &mut [u8; BLOCK_LEN]) -> Result<(), CryptoError> {
+            for round in 0..4 {
+                let prev = *block;
+                for i in 0..BLOCK_LEN {
+                    block[i] =
+                        prev[i].rotate_left(3) ^ prev[(i + 1) % BLOCK_LEN] ^ self.0[i] ^ round;
+                }
+            }
+            Ok(())
+        }
+    }
 
     #[test]
-    fn it_works() {
-        assert!(true)
+    fn ad_structures_are_encoded() {
+        let flags = Ad::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED);
+        let plan = adv::payload(&[
+            flags,
+            Ad::ServiceUuids16(&[[0x0F, 0x18]]),
+            Ad::Manufacturer {
+                company_identifier: 0xFFFF,
+                payload: &[1, 2, 3, 4],
+            },
+            Ad::Name {
+                name: "TB",
+                min_len: 2,
+            },
+        ]);
+        assert!(plan.is_complete());
+        assert_eq!(
+            plan.payload,
+            [
+                2, 0x01, 0x06, // flags
+                3, 0x03, 0x0F, 0x18, // service UUIDs
+                7, 0xFF, 0xFF, 0xFF, 1, 2, 3, 4, // manufacturer data
+                3, 0x09, b'T', b'B', // complete name
+            ]
+        );
+
+        // The name gets the 4 bytes left.
+        let plan = adv::payload(&[
+            flags,
+            Ad::Manufacturer {
+                company_identifier: 0xFFFF,
+                payload: &[0xA5; 18],
+            },
+            Ad::Name {
+                name: "Tracker",
+                min_len: 2,
+            },
+        ]);
+        assert!(plan.dropped.is_empty() && plan.shortened);
+        assert_eq!(plan.payload.len(), adv::ADV_LEN);
+        assert_eq!(plan.payload[..7], [2, 0x01, 0x06, 21, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(plan.payload[7..25], [0xA5; 18]);
+        assert_eq!(plan.payload[25..], [5, 0x08, b'T', b'r', b'a', b'c']);
+    }
+
+    #[test]
+    fn eid_frame_is_encoded_as_service_data() {
+        let eid = EidGenerator::new(TestCipher([7; BLOCK_LEN]), 10).unwrap();
+        let frame = eid.frame(5000, -18).unwrap();
+        assert_eq!(frame[..3], [0x30, -18i8 as u8, 10]);
+
+        let mut buf = [0; 31];
+        let len = AdStructure::encode_slice(
+            &[AdStructure::ServiceData16 {
+                uuid: EDDYSTONE_UUID,
+                data: &frame,
+            }],
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(len, 2 + 2 + frame.len());
+        assert_eq!(buf[..4], [3 + frame.len() as u8, 0x16, 0xAA, 0xFE]);
+    }
+
+    #[test]
+    fn eid_rotates_with_the_period() {
+        let eid = EidGenerator::new(TestCipher([7; BLOCK_LEN]), 10).unwrap();
+        assert_eq!(eid.eid(1024).unwrap(), eid.eid(2047).unwrap());
+        assert_ne!(eid.eid(1024).unwrap(), eid.eid(2048).unwrap());
+        assert_eq!(eid.until_rotation(2000).as_secs(), 48);
+        assert!(EidGenerator::new(TestCipher([7; BLOCK_LEN]), 16).is_err());
+    }
+
+    /// AES-128 on the ECB peripheral, which the tests own as the
+    /// SoftDevice Controller doesn't run.
+    struct EcbCipher([u8; BLOCK_LEN]);
+
+    impl From<[u8; BLOCK_LEN]> for EcbCipher {
+        fn from(key: [u8; BLOCK_LEN]) -> Self {
+            Self(key)
+        }
+    }
+
+    impl BlockCipher for EcbCipher {
+        fn encrypt_block(&self, block: &mut [u8; BLOCK_LEN]) -> Result<(), CryptoError> {
+            // Key, cleartext and ciphertext, read and written by the peripheral.
+            let mut data = [0; 3 * BLOCK_LEN];
+            data[..BLOCK_LEN].copy_from_slice(&self.0);
+            data[BLOCK_LEN..2 * BLOCK_LEN].copy_from_slice(block);
+            let ecb = pac::ECB;
+            ecb.events_endecb().write_value(0);
+            ecb.ecbdataptr().write_value(data.as_mut_ptr() as u32);
+            ecb.tasks_startecb().write_value(1);
+            while ecb.events_endecb().read() == 0 {}
+            compiler_fence(Ordering::SeqCst);
+            ecb.events_endecb().write_value(0);
+            block.copy_from_slice(&data[2 * BLOCK_LEN..]);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ecb_known_answer() {
+        // FIPS-197, appendix C.1.
+        let aes = EcbCipher(hex("000102030405060708090a0b0c0d0e0f"));
+        let mut block = hex("00112233445566778899aabbccddeeff");
+        aes.encrypt_block(&mut block).unwrap();
+        assert_eq!(block, hex::<16>("69c4e0d86a7b0430d8cdb78070b4c55a"));
+    }
+
+    #[test]
+    fn eid_known_answers() {
+        // Computed with the construction of the Eddystone-EID specification:
+        // temporary key AES(ik, 0 (11) | 0xFF | 0 (2) | t[31:16]), EID the
+        // first 8 bytes of AES(tk, 0 (11) | K | t with the low K bits cleared).
+        let identity = || EcbCipher(hex("e2b3c4d5f60718293a4b5c6d7e8f9001"));
+        let eid = EidGenerator::new(identity(), 10).unwrap();
+        assert_eq!(eid.eid(0x1234_5678).unwrap(), hex::<8>("f9c18c80ab053440"));
+        assert_eq!(eid.eid(0x1234_57FF).unwrap(), hex::<8>("f9c18c80ab053440"));
+        assert_eq!(eid.eid(0x1234_5800).unwrap(), hex::<8>("476b325207eb4a8d"));
+        let eid = EidGenerator::new(identity(), 0).unwrap();
+        assert_eq!(eid.eid(0x0001_0000).unwrap(), hex::<8>("ca2cbb32116633ad"));
+        let eid = EidGenerator::new(identity(), 15).unwrap();
+        assert_eq!(eid.eid(1_000_000).unwrap(), hex::<8>("b39143d5b4b84392"));
+    }
+
+    #[test]
+    fn ccm_rfc3610_packet_vectors() {
+        let ccm = Ccm::<_, 8>::new(EcbCipher(hex("c0c1c2c3c4c5c6c7c8c9cacbcccdcecf")));
+        // Packet vector 1.
+        let nonce = hex("00000003020100a0a1a2a3a4a5");
+        let packet: [u8; 31] = core::array::from_fn(|i| i as u8);
+        let mut data: [u8; 23] = packet[8..].try_into().unwrap();
+        let tag = ccm.seal(&nonce, &packet[..8], &mut data).unwrap();
+        assert_eq!(
+            data,
+            hex::<23>("588c979a61c663d2f066d0c2c0f989806d5f6b61dac384")
+        );
+        assert_eq!(tag, hex::<8>("17e8d12cfdf926e0"));
+        ccm.open(&nonce, &packet[..8], &mut data, &tag).unwrap();
+        assert_eq!(data, packet[8..]);
+
+        // Packet vector 2.
+        let nonce = hex("00000004030201a0a1a2a3a4a5");
+        let packet: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let mut data: [u8; 24] = packet[8..].try_into().unwrap();
+        let tag = ccm.seal(&nonce, &packet[..8], &mut data).unwrap();
+        assert_eq!(
+            data,
+            hex::<24>("72c91a36e135f8cf291ca894085c87e3cc15c439c9e43a3b")
+        );
+        assert_eq!(tag, hex::<8>("a091d56e10400916"));
+    }
+
+    #[test]
+    fn ccm_round_trip_and_tamper_detection() {
+        let ccm = Ccm::<_, 8>::new(TestCipher([1; BLOCK_LEN]));
+        let nonce = [3; 13];
+        let plaintext = *b"twenty byte payload!";
+        let mut data = plaintext;
+        let tag = ccm.seal(&nonce, b"aad", &mut data).unwrap();
+        assert_ne!(data, plaintext);
+
+        let mut opened = data;
+        ccm.open(&nonce, b"aad", &mut opened, &tag).unwrap();
+        assert_eq!(opened, plaintext);
+
+        let mut tampered = data;
+        tampered[0] ^= 1;
+        assert_eq!(
+            ccm.open(&nonce, b"aad", &mut tampered, &tag),
+            Err(CryptoError::TagMismatch)
+        );
+        let mut wrong_aad = data;
+        assert_eq!(
+            ccm.open(&nonce, b"bad", &mut wrong_aad, &tag),
+            Err(CryptoError::TagMismatch)
+        );
+    }
+
+    /// Bytes of hex digits.
+    fn hex<const N: usize>(digits: &str) -> [u8; N] {
+        let mut bytes = [0; N];
+        for (byte, pair) in bytes.iter_mut().zip(digits.as_bytes().chunks_exact(2)) {
+            *byte = u8::from_str_radix(core::str::from_utf8(pair).unwrap(), 16).unwrap();
+        }
+        bytes
+    }
+
+    /// Enable the CryptoCell for the first test using it.
+    #[cfg(feature = "cryptocell")]
+    fn cryptocell() {
+        static READY: AtomicBool = AtomicBool::new(false);
+        if !READY.swap(true, Ordering::Relaxed) {
+            hw::init();
+        }
+    }
+
+    #[cfg(feature = "cryptocell")]
+    #[test]
+    fn cryptocell_aes_known_answer() {
+        cryptocell();
+        // FIPS-197, appendix C.1.
+        let aes = hw::CcAes::new(hex("000102030405060708090a0b0c0d0e0f"));
+        let mut block = hex("00112233445566778899aabbccddeeff");
+        aes.encrypt_block(&mut block).unwrap();
+        assert_eq!(block, hex::<16>("69c4e0d86a7b0430d8cdb78070b4c55a"));
+    }
+
+    #[cfg(feature = "cryptocell")]
+    #[test]
+    fn cryptocell_sha256_matches_software() {
+        cryptocell();
+        let digest = hw::sha256(b"abc").unwrap();
+        assert_eq!(
+            digest,
+            hex::<32>("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        let data = [0x5A; 300];
+        assert_eq!(hw::sha256(&data).unwrap(), sha256::sha256(&data));
+    }
+
+    #[cfg(feature = "cryptocell")]
+    #[test]
+    fn cryptocell_ecdh_matches_software() {
+        cryptocell();
+        let ours = [0x11; 32];
+        let theirs = p256::SecretKey::from_bytes(&[0x22; 32].into()).unwrap();
+        let point = theirs.public_key().to_encoded_point(false);
+        let peer: [u8; 64] = point.as_bytes()[1..].try_into().unwrap();
+        let expected = p256::ecdh::diffie_hellman(
+            theirs.to_nonzero_scalar(),
+            p256::SecretKey::from_bytes(&ours.into())
+                .unwrap()
+                .public_key()
+                .as_affine(),
+        );
+        let shared = hw::ecdh_p256(&ours, &peer).unwrap();
+        assert_eq!(&shared[..], &expected.raw_secret_bytes()[..]);
+    }
+
+    #[cfg(feature = "cryptocell")]
+    #[test]
+    fn cryptocell_ecdh_rejects_points_off_the_curve() {
+        cryptocell();
+        let theirs = p256::SecretKey::from_bytes(&[0x22; 32].into()).unwrap();
+        let point = theirs.public_key().to_encoded_point(false);
+        let mut peer: [u8; 64] = point.as_bytes()[1..].try_into().unwrap();
+        peer[63] ^= 1;
+        assert_eq!(
+            hw::ecdh_p256(&[0x11; 32], &peer),
+            Err(CryptoError::InvalidKey)
+        );
+    }
+
+    #[cfg(feature = "cryptocell")]
+    #[test]
+    fn cryptocell_ed25519_matches_software() {
+        cryptocell();
+        // RFC 8032, section 7.1, test 2.
+        let key = hex("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+        let message = [0x72];
+        let mut signature = hex(
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+             085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        );
+        let software = VerifyingKey::from_bytes(&key).unwrap();
+        assert!(hw::ed25519_verify(&key, &message, &signature));
+        assert!(
+            software
+                .verify_strict(&message, &Signature::from_bytes(&signature))
+                .is_ok()
+        );
+        signature[0] ^= 1;
+        assert!(!hw::ed25519_verify(&key, &message, &signature));
+        assert!(
+            software
+                .verify_strict(&message, &Signature::from_bytes(&signature))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn beacon_payload_round_trip() {
+        let salt = [5, 6, 7, 8];
+        let mut sender = BeaconCipher::new(TestCipher([9; BLOCK_LEN]), 0xFFFF, salt, 41);
+        let receiver = BeaconCipher::new(TestCipher([9; BLOCK_LEN]), 0xFFFF, [0; 4], 0);
+        let mut payload = [0; 20];
+        let len = sender
+            .encrypt(&[1, 2, 3, 4, 5, 6, 7, 8], &mut payload)
+            .unwrap();
+        assert_eq!(len, 20);
+        assert_eq!(payload[..4], salt);
+        assert_eq!(payload[4..8], 41u32.to_le_bytes());
+        let mut copy = payload;
+        assert_eq!(
+            receiver.decrypt(&mut copy).unwrap(),
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        );
+
+        // The salt is authenticated with the nonce.
+        payload[0] ^= 1;
+        assert_eq!(
+            receiver.decrypt(&mut payload),
+            Err(CryptoError::TagMismatch)
+        );
+
+        // The counter advances, so the same plaintext encrypts differently,
+        // as it does after a reboot with another salt.
+        let mut first = [0; 20];
+        let mut second = [0; 20];
+        let mut rebooted = [0; 20];
+        sender.encrypt(&[0; 8], &mut first).unwrap();
+        sender.encrypt(&[0; 8], &mut second).unwrap();
+        assert_ne!(first, second);
+        BeaconCipher::new(TestCipher([9; BLOCK_LEN]), 0xFFFF, [1, 2, 3, 4], 42)
+            .encrypt(&[0; 8], &mut rebooted)
+            .unwrap();
+        assert_ne!(first[4..], rebooted[4..]);
+    }
+
+    /// Keys of the sample data of the Mesh Profile specification, 8.2.
+    const MESH_NET_KEY: &str = "7dd7364cd842ad18c17c2b820c84c3d6";
+    const MESH_APP_KEY: &str = "63964771734fbd76e3b40519d1d94a48";
+
+    #[test]
+    fn mesh_sample_data() {
+        // k2 (8.1.4) and k4 (8.1.7)
+        let network = Network::<EcbCipher>::new(hex("f7a2a44f8e8a8029064f173ddc1e2b00"), 0);
+        assert_eq!(network.unwrap().nid(), 0x7F);
+        let app_key = AppKey::new::<EcbCipher>(hex("3216d1509884b533248541792b877f98"));
+        assert_eq!(app_key.unwrap().aid(), 0x38);
+        // 8.2.1 and 8.2.2
+        let network = Network::<EcbCipher>::new(hex(MESH_NET_KEY), 0x1234_5678).unwrap();
+        assert_eq!(network.nid(), 0x68);
+        let app_key = AppKey::new::<EcbCipher>(hex(MESH_APP_KEY)).unwrap();
+        assert_eq!(app_key.aid(), 0x26);
+
+        // Message #1, a control message.
+        let mut pdu = hex::<28>("68eca487516765b5e5bfdacbaf6cb7fb6bff871f035444ce83a670df");
+        let (header, lower) = network.decrypt(&mut pdu).unwrap();
+        assert_eq!(
+            header,
+            mesh::NetworkHeader {
+                ctl: true,
+                ttl: 0,
+                seq: 1,
+                src: 0x1201,
+                dst: 0xFFFD,
+            }
+        );
+        assert_eq!(lower, hex::<11>("034b50057e400000010000"));
+
+        // Message #18, an unsegmented access message.
+        let mut node = Node::new(network, app_key, 0x0003, 0, 5);
+        let mut pdu = hex::<23>("6848cba437860e5673728a627fb938535508e21a6baf57");
+        let message = node.receive(&mut pdu).unwrap().unwrap();
+        assert_eq!((message.header.ttl, message.header.seq), (3, 7));
+        assert_eq!((message.header.src, message.header.dst), (0x1201, 0xFFFF));
+        assert_eq!(message.access, hex::<5>("0400000000"));
+    }
+
+    #[test]
+    fn mesh_access_message_round_trip() {
+        let node = |iv_index, address, seq| {
+            Node::new(
+                Network::<EcbCipher>::new(hex(MESH_NET_KEY), iv_index).unwrap(),
+                AppKey::new::<EcbCipher>(hex(MESH_APP_KEY)).unwrap(),
+                address,
+                seq,
+                5,
+            )
+        };
+        let mut sender = node(0x1234_5678, 0x0100, 0x1FFE);
+        let mut receiver = node(0x1234_5678, 0x0200, 0);
+
+        // Unsegmented
+        let pdus = sender.send::<4>(0xC000, &[0x52, 0x4F, 0x00, 42]).unwrap();
+        assert_eq!(pdus.len(), 1);
+        let mut adv = [0; 31];
+        let len = mesh::adv_data(&pdus[0], &mut adv).unwrap();
+        let mut pdu = [0; mesh::MAX_NETWORK_PDU_LEN];
+        let received = mesh::network_pdus(&adv[..len]).next().unwrap();
+        pdu[..received.len()].copy_from_slice(received);
+        let message = receiver
+            .receive(&mut pdu[..received.len()])
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.header.src, 0x0100);
+        assert_eq!(message.header.dst, 0xC000);
+        assert_eq!(message.header.ttl, 5);
+        assert_eq!(message.access, [0x52, 0x4F, 0x00, 42]);
+
+        // Segmented, the sequence numbers cross a SeqZero block.
+        let access = [0xA5; 20];
+        let mut pdus = sender.send::<4>(0xC000, &access).unwrap();
+        assert_eq!(pdus.len(), 2);
+        assert_eq!(sender.seq(), 0x2001);
+        assert_eq!(receiver.receive(&mut pdus[0]).unwrap(), None);
+        let message = receiver.receive(&mut pdus[1]).unwrap().unwrap();
+        assert_eq!(message.access, access);
+
+        // A node still on the previous IV index during an IV update.
+        let mut behind = node(0x1234_5677, 0x0300, 0);
+        let mut pdus = behind.send::<4>(0xC000, &access).unwrap();
+        assert_eq!(receiver.receive(&mut pdus[0]).unwrap(), None);
+        let message = receiver.receive(&mut pdus[1]).unwrap().unwrap();
+        assert_eq!(message.access, access);
+
+        // Another network key
+        let mut pdus = sender.send::<4>(0xC000, &access).unwrap();
+        let mut stranger = Node::new(
+            Network::<EcbCipher>::new([0x11; BLOCK_LEN], 0x1234_5678).unwrap(),
+            AppKey::new::<EcbCipher>(hex(MESH_APP_KEY)).unwrap(),
+            0x0300,
+            0,
+            5,
+        );
+        assert!(stranger.receive(&mut pdus[0]).is_err());
+    }
+
+    #[test]
+    fn failures_are_counted_per_subsystem_run() {
+        let total = || DIAGNOSTICS.consecutive_failures.load(Ordering::Relaxed);
+        let mut runner = Backoff::default();
+        let mut advertising = Backoff::default();
+        for _ in 1..MAX_CONSECUTIVE_FAILURES {
+            runner.fail();
+        }
+        // A failing subsystem doesn't use up the budget of another one.
+        assert_eq!(advertising.fail(), 1);
+        assert_eq!(runner.fail(), MAX_CONSECUTIVE_FAILURES);
+
+        // The supervisor restarts the runner, which gives up only after
+        // failing again as often.
+        drop(runner);
+        assert_eq!(total(), 1);
+        let mut runner = Backoff::default();
+        assert_eq!(runner.fail(), 1);
+        assert_eq!(total(), 2);
+
+        advertising.reset();
+        assert_eq!(advertising.fail(), 1);
+        drop(advertising);
+        drop(runner);
+        assert_eq!(total(), 0);
+    }
+
+    #[test]
+    fn telemetry_sample_round_trip() {
+        let sample = Sample {
+            seq: 7,
+            uptime_secs: 3600,
+            latitude_e7: 533_613_366,
+            longitude_e7: -65_056_200,
+            speed_cm_s: 150,
+            battery_percent: 80,
+            reserved: 0,
+        };
+        let bytes: [u8; 20] = bytemuck::cast(sample);
+        assert_eq!(bytes[..4], 7u32.to_le_bytes());
+        assert_eq!(bytemuck::cast::<_, Sample>(bytes), sample);
+    }
+
+    #[test]
+    fn telemetry_queue_spills_and_recovers() {
+        let mut flash = RamFlash::<{ 2 * 4096 }>::new();
+        block_on(async {
+            let mut queue = TelemetryQueue::new(BlockingAsync::new(&mut flash))
+                .await
+                .unwrap();
+            for _ in 0..40 {
+                queue.push(Sample::zeroed()).await.unwrap();
+            }
+            assert_eq!(queue.len(), 40);
+            assert_eq!(queue.peek().await.unwrap().map(|s| s.seq), Some(0));
+            queue.ack().await.unwrap();
+            queue.flush().await.unwrap();
+        });
+        block_on(async {
+            let mut queue = TelemetryQueue::new(BlockingAsync::new(&mut flash))
+                .await
+                .unwrap();
+            assert_eq!(queue.len(), 39);
+            assert_eq!(queue.peek().await.unwrap().map(|s| s.seq), Some(1));
+            queue.push(Sample::zeroed()).await.unwrap();
+            assert_eq!(queue.len(), 40);
+        });
+    }
+
+    #[test]
+    fn track_log_rotates_and_recovers() {
+        let mut flash = RamFlash::<{ 3 * 4096 }>::new();
+        let point = |utc| Point {
+            utc,
+            latitude_e7: 470_000_000 + utc as i32,
+            longitude_e7: 80_000_000,
+            altitude_m: 420,
+            satellites: 7,
+            reserved: 0,
+        };
+        block_on(async {
+            let mut log = TrackLog::new(BlockingAsync::new(&mut flash)).await.unwrap();
+            assert_eq!(log.start("first", 100).await.unwrap(), 0);
+            // 5 bytes per delta, a page holds about 800.
+            for utc in 0..1000 {
+                log.record(&point(utc)).await.unwrap();
+            }
+            let first = log.stop(1000).await.unwrap();
+            assert_eq!((first.fixes, first.pages), (1000, 2));
+            let mut export = Export::new(0);
+            let mut points = heapless::Vec::<Point, 64>::new();
+            let mut utc = 0;
+            loop {
+                log.export(&mut export, &mut points).await.unwrap();
+                for p in &points {
+                    assert!(*p == point(utc));
+                    utc += 1;
+                }
+                if !points.is_full() {
+                    break;
+                }
+                points.clear();
+            }
+            assert_eq!(utc, 1000);
+            // The quota of 2 pages is reached, the first session goes.
+            assert_eq!(log.start("second", 500).await.unwrap(), 1);
+            assert_eq!(log.sessions().len(), 1);
+            for utc in 501..=503 {
+                log.record(&point(utc)).await.unwrap();
+            }
+        });
+        // Interrupted while recording.
+        block_on(async {
+            let mut log = TrackLog::new(BlockingAsync::new(&mut flash)).await.unwrap();
+            let second = log.sessions()[0];
+            assert_eq!(name_str(&second.name), "second");
+            // 24 bytes were written, the last one of the third point not yet.
+            assert_eq!((second.fixes, second.end_utc), (2, 502));
+            assert!(!second.recording);
+            // The second page of the first session is left to erase.
+            assert_eq!(log.reclaimable(), 1);
+            assert!(log.reclaim().await.unwrap());
+            assert_eq!(log.reclaimable(), 0);
+        });
+    }
+
+    #[test]
+    fn nmea_sentences_are_parsed() {
+        let gga = nmea::parse_str(
+            "$GPGGA,092750.000,5321.6802,N,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,*76",
+        )
+        .unwrap();
+        let nmea::ParseResult::GGA(gga) = gga else {
+            defmt::panic!("expected GGA");
+        };
+        assert!((gga.latitude.unwrap() - 53.361337).abs() < 1e-5);
+        assert!((gga.longitude.unwrap() + 6.505620).abs() < 1e-5);
+        assert_eq!(gga.fix_satellites, Some(8));
+
+        let rmc = nmea::parse_str(
+            "$GNRMC,092751.000,A,5321.6802,N,00630.3371,W,0.06,31.66,280511,,,A*5B",
+        )
+        .unwrap();
+        let nmea::ParseResult::RMC(rmc) = rmc else {
+            defmt::panic!("expected RMC");
+        };
+        assert_eq!(rmc.speed_over_ground, Some(0.06));
+
+        let zda = nmea::parse_str("$GPZDA,160012.71,11,03,2004,-1,00*7D").unwrap();
+        assert!(matches!(zda, nmea::ParseResult::ZDA(_)));
+
+        assert!(nmea::parse_str("$GPZDA,160012.71,11,03,2004,-1,00*7E").is_err());
+    }
+
+    #[test]
+    fn pcas_commands_have_checksums() {
+        assert_eq!(
+            Command::set_update_rate(1).as_bytes(),
+            b"$PCAS02,1000*2E\r\n"
+        );
+    }
+
+    #[test]
+    fn nmea_lines_are_split() {
+        let mut ring = RingBuffer::<32>::new();
+        ring.extend(b"$A*00\r\n$B");
+        let mut line = [0; 16];
+        assert_eq!(ring.pop_line(&mut line), Some(Ok(5)));
+        assert_eq!(line[..5], *b"$A*00");
+        assert_eq!(ring.pop_line(&mut line), None);
+        ring.extend(b"*00\n");
+        assert_eq!(ring.pop_line(&mut line), Some(Ok(5)));
+        assert_eq!(line[..5], *b"$B*00");
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn settings_store_round_trip() {
+        let mut flash = RamFlash::<4096>::new();
+        settings::update(|s| s.display_dim_secs = 5);
+        block_on(Store::new(BlockingAsync::new(&mut flash)).save()).unwrap();
+
+        settings::update(|s| s.display_dim_secs = 30);
+        block_on(Store::new(BlockingAsync::new(&mut flash)).load()).unwrap();
+        assert_eq!(settings::get().display_dim_secs, 5);
+
+        // A corrupted record is ignored.
+        flash.data[8] ^= 0xFF;
+        settings::update(|s| s.display_dim_secs = 30);
+        block_on(Store::new(BlockingAsync::new(&mut flash)).load()).unwrap();
+        assert_eq!(settings::get().display_dim_secs, 30);
+    }
+
+    #[test]
+    fn settings_export_import() {
+        settings::update(|s| s.display_dim_secs = 5);
+        let mut blob = settings::export();
+        settings::update(|s| s.display_dim_secs = 30);
+        assert_eq!(settings::import(&blob[1..]), Err(ImportError::Length));
+        blob[BLOB_LEN - 1] ^= 0xFF;
+        assert_eq!(settings::import(&blob), Err(ImportError::Invalid));
+        blob[BLOB_LEN - 1] ^= 0xFF;
+        settings::import(&blob).unwrap();
+        assert_eq!(settings::get().display_dim_secs, 5);
+        settings::update(|s| s.display_dim_secs = 30);
+    }
+
+    #[test]
+    fn reset_keeps_the_replay_protection() {
+        for region in factory_reset::ERASED {
+            assert!(region.end() <= REPLAY.start || region.start >= REPLAY.end());
+        }
+        let signature = [0; SIGNATURE_LEN];
+        let update = |sequence| Signed {
+            message: &[],
+            payload: &[],
+            signature: &signature,
+            sequence,
+        };
+        let mut flash = RamFlash::<4096>::new();
+        let mut store = replay::Store::new(BlockingAsync::new(&mut flash));
+        block_on(store.load()).unwrap();
+        let first = replay::last() + 1;
+        replay::accept(&update(first)).unwrap();
+        assert_eq!(replay::accept(&update(first)), Err(SignedError::Replayed));
+        block_on(store.save()).unwrap();
+
+        // The defaults of a reset or a migration leave the page alone.
+        settings::update(|s| *s = Settings::new());
+        let mut store = replay::Store::new(BlockingAsync::new(&mut flash));
+        block_on(store.load()).unwrap();
+        assert_eq!(replay::last(), first);
+        assert_eq!(replay::accept(&update(first)), Err(SignedError::Replayed));
+
+        // A full page is erased and continues with the last number.
+        for sequence in first + 1..=first + 1024 {
+            replay::accept(&update(sequence)).unwrap();
+            block_on(store.save()).unwrap();
+        }
+        drop(store);
+        let last = first + 1024;
+        assert_eq!(flash.data[..4], last.to_le_bytes());
+        assert_eq!(flash.data[4..8], [0xFF; 4]);
+        block_on(replay::Store::new(BlockingAsync::new(&mut flash)).load()).unwrap();
+        assert_eq!(replay::last(), last);
+    }
+
+    /// Service with long characteristics like the settings of the
+    /// diagnostics service.
+    #[gatt_service(uuid = "408813df-5dd4-1f87-ec11-cdb001200000")]
+    struct LongService {
+        #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200006", read, write)]
+        settings: heapless::Vec<u8, { settings::SIGNED_BLOB_LEN }>,
+        #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200007", read, write)]
+        value: heapless::Vec<u8, { long::MAX_VALUE_LEN }>,
+    }
+
+    #[gatt_server]
+    struct LongServer {
+        long_service: LongService,
+    }
+
+    fn long_server() -> LongServer<'static> {
+        LongServer::new_with_config(GapConfig::Peripheral(PeripheralConfig {
+            name: "test",
+            appearance: &appearance::power_device::GENERIC_POWER_DEVICE,
+        }))
+        .unwrap()
+    }
+
+    /// Prepare Write requests of `value` to `handle` with the default ATT
+    /// MTU, 18 bytes each.
+    fn prepare(
+        prepared: &mut long::PreparedWrites,
+        handle: u16,
+        value: &[u8],
+    ) -> Option<(u16, heapless::Vec<u8, { long::MAX_VALUE_LEN }>)> {
+        for (i, part) in value.chunks(18).enumerate() {
+            let request = AttClient::Request(AttReq::PrepareWrite {
+                handle,
+                offset: (18 * i) as u16,
+                value: part,
+            });
+            assert!(prepared.executed(&request).is_none());
+        }
+        prepared.executed(&AttClient::Request(AttReq::ExecuteWrite { flags: 0x01 }))
+    }
+
+    #[test]
+    fn long_values_are_read_in_parts() {
+        let server = long_server();
+        let characteristic = server.long_service.value;
+        let value: heapless::Vec<u8, { long::MAX_VALUE_LEN }> =
+            (0..long::MAX_VALUE_LEN).map(|i| i as u8).collect();
+        server.set(&characteristic, &value).unwrap();
+
+        let read = AttClient::Request(AttReq::Read {
+            handle: characteristic.handle,
+        });
+        assert_eq!(long::read_offset(&read), 0);
+        // Read Blob parts with the default ATT MTU, 22 bytes each.
+        let held = server.get(&characteristic).unwrap();
+        assert_eq!(held.len(), long::MAX_VALUE_LEN);
+        for offset in [0, 22, 510] {
+            let request = AttClient::Request(AttReq::ReadBlob {
+                handle: characteristic.handle,
+                offset: offset as u16,
+            });
+            assert_eq!(long::read_offset(&request), offset);
+            let end = (offset + 22).min(held.len());
+            assert_eq!(held[offset..end], value[offset..end]);
+        }
+    }
+
+    #[test]
+    fn prepared_writes_put_long_values_together() {
+        let server = long_server();
+        let handle = server.long_service.value.handle;
+        let value: [u8; long::MAX_VALUE_LEN] = core::array::from_fn(|i| i as u8);
+        let mut prepared = long::PreparedWrites::new();
+
+        let (written, whole) = prepare(&mut prepared, handle, &value).unwrap();
+        assert_eq!(written, handle);
+        assert_eq!(whole[..], value[..]);
+        assert!(
+            prepared
+                .executed(&AttClient::Request(AttReq::ExecuteWrite { flags: 0x01 }))
+                .is_none()
+        );
+
+        // Cancelled writes are dropped.
+        let request = AttClient::Request(AttReq::PrepareWrite {
+            handle,
+            offset: 0,
+            value: &value[..18],
+        });
+        assert!(prepared.executed(&request).is_none());
+        let cancel = AttClient::Request(AttReq::ExecuteWrite { flags: 0x00 });
+        assert!(prepared.executed(&cancel).is_none());
+        assert!(
+            prepared
+                .executed(&AttClient::Request(AttReq::ExecuteWrite { flags: 0x01 }))
+                .is_none()
+        );
+
+        // So are parts with a gap and values too long.
+        assert!(prepared.executed(&request).is_none());
+        let gap = AttClient::Request(AttReq::PrepareWrite {
+            handle,
+            offset: 36,
+            value: &value[36..54],
+        });
+        assert!(prepared.executed(&gap).is_none());
+        assert!(
+            prepared
+                .executed(&AttClient::Request(AttReq::ExecuteWrite { flags: 0x01 }))
+                .is_none()
+        );
+        let mut too_long = [0; long::MAX_VALUE_LEN + 1];
+        too_long[..long::MAX_VALUE_LEN].copy_from_slice(&value);
+        assert!(prepare(&mut prepared, handle, &too_long).is_none());
+    }
+
+    #[test]
+    fn settings_fit_a_long_characteristic() {
+        assert!(settings::SIGNED_BLOB_LEN <= long::MAX_VALUE_LEN);
+        let server = long_server();
+        let characteristic = server.long_service.settings;
+        let mut prepared = long::PreparedWrites::new();
+
+        settings::update(|s| s.display_dim_secs = 5);
+        let value = settings::export();
+        settings::update(|s| s.display_dim_secs = 30);
+        let (handle, written) = prepare(&mut prepared, characteristic.handle, &value).unwrap();
+        assert_eq!(handle, characteristic.handle);
+        settings::import(&written).unwrap();
+        assert_eq!(settings::get().display_dim_secs, 5);
+        settings::update(|s| s.display_dim_secs = 30);
     }
 }